@@ -0,0 +1,80 @@
+# Imports a pose library exported by `bvh_to_gav blender` into the open Blender scene: an
+# armature with one action per clip, single frame clips marked as pose assets. Open this file
+# in the Text Editor and press Run Script. It was generated together with the bundle it reads,
+# which already holds Blender bone space rotations, so it only creates datablocks.
+import json
+
+import bpy
+from mathutils import Matrix, Quaternion, Vector
+
+BUNDLE_PATH = __BUNDLE_PATH__
+BUNDLE_VERSION = __BUNDLE_VERSION__
+
+
+def build_armature(bundle):
+    data = bpy.data.armatures.new(bundle["name"])
+    armature = bpy.data.objects.new(bundle["name"], data)
+    bpy.context.collection.objects.link(armature)
+    bpy.context.view_layer.objects.active = armature
+    bpy.ops.object.mode_set(mode="EDIT")
+    for bone in bundle["bones"]:
+        edit_bone = data.edit_bones.new(bone["name"])
+        edit_bone.head = Vector(bone["head"])
+        edit_bone.tail = Vector(bone["tail"])
+        # Sets the roll, so the bone rests in the orientation its rotations are relative to.
+        rest = Quaternion(bone["rest"]).to_matrix().to_4x4()
+        edit_bone.matrix = Matrix.Translation(edit_bone.head) @ rest
+        if bone["parent"] is not None:
+            edit_bone.parent = data.edit_bones[bone["parent"]]
+    bpy.ops.object.mode_set(mode="OBJECT")
+    for pose_bone in armature.pose.bones:
+        pose_bone.rotation_mode = "QUATERNION"
+    return armature
+
+
+def add_curves(action, data_path, group, values):
+    for index in range(len(values[0])):
+        curve = action.fcurves.new(data_path, index=index, action_group=group)
+        curve.keyframe_points.add(len(values))
+        points = [c for frame, value in enumerate(values) for c in (frame + 1, value[index])]
+        curve.keyframe_points.foreach_set("co", points)
+        curve.update()
+
+
+def build_action(clip):
+    action = bpy.data.actions.new(clip["name"])
+    action.use_fake_user = True
+    for track in clip["tracks"]:
+        bone = 'pose.bones["{}"]'.format(track["bone"])
+        if track.get("locations"):
+            add_curves(action, bone + ".location", track["bone"], track["locations"])
+        add_curves(action, bone + ".rotation_quaternion", track["bone"], track["rotations"])
+    for event in clip.get("events", []):
+        marker = action.pose_markers.new(event["name"])
+        marker.frame = event["frame"] + 1
+    if clip["frame_count"] == 1:
+        action.asset_mark()
+    return action
+
+
+def main():
+    with open(BUNDLE_PATH) as file:
+        bundle = json.load(file)
+    if bundle["version"] != BUNDLE_VERSION:
+        raise RuntimeError(
+            "{} is version {}, this script reads version {}".format(
+                BUNDLE_PATH, bundle["version"], BUNDLE_VERSION
+            )
+        )
+    scene = bpy.context.scene
+    scene.render.fps = round(bundle["fps"])
+    armature = build_armature(bundle)
+    actions = [build_action(clip) for clip in bundle["clips"]]
+    if actions:
+        armature.animation_data_create().action = actions[0]
+        scene.frame_start = 1
+        scene.frame_end = bundle["clips"][0]["frame_count"]
+    print("Imported {} clips onto {}".format(len(actions), armature.name))
+
+
+main()
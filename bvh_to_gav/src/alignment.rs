@@ -0,0 +1,156 @@
+//! Canonical start of a clip: the clip is moved so the root starts above the origin with the
+//! lowest joint of the first frame at y = 0, and turned about +Y so the root starts facing +Z.
+//! Models then don't have to learn where in the capture volume a take started. The removed
+//! transform is recorded in the metadata sidecar of the tensor, see [`RootAlignment::apply_to`],
+//! and `decode` puts the clip back where it was captured.
+use anyhow::{Context, Result};
+use bevy_math::{Quat, Vec3};
+
+use crate::{
+    Animation, kinematics::global_transforms, metadata::ClipMetadata, root_motion::yaw,
+    skeleton::Skeleton,
+};
+
+const TRANSLATION_KEY: &str = "alignment_translation";
+const YAW_KEY: &str = "alignment_yaw";
+
+/// Transform removed from a clip: the aligned clip turned by `yaw` about +Y and then moved by
+/// `translation` is the clip as captured.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RootAlignment {
+    pub translation: Vec3,
+    /// Radians about +Y.
+    pub yaw: f32,
+}
+
+impl RootAlignment {
+    /// The transform that aligns `animation`, from its first frame.
+    pub fn fit(skeleton: &Skeleton, animation: &Animation) -> Self {
+        let (Some(&root), Some(rotations)) = (
+            animation.root_positions.first(),
+            animation.joint_rotations.first(),
+        ) else {
+            return RootAlignment::default();
+        };
+        let floor = global_transforms(skeleton, animation, 0)
+            .iter()
+            .map(|(position, _)| position.y)
+            .fold(root.y, f32::min);
+        RootAlignment {
+            translation: Vec3::new(root.x, floor, root.z),
+            yaw: yaw(rotations[0]),
+        }
+    }
+
+    fn rotation(&self) -> Quat {
+        Quat::from_rotation_y(self.yaw)
+    }
+
+    /// Aligns `animation`, removing this transform from its root.
+    pub fn remove(&self, animation: &mut Animation) {
+        let inverse = self.rotation().inverse();
+        for position in &mut animation.root_positions {
+            *position = inverse * (*position - self.translation);
+        }
+        if let Some(rotations) = animation.joint_rotations.first_mut() {
+            for rotation in rotations {
+                *rotation = inverse * *rotation;
+            }
+        }
+    }
+
+    /// Undoes [`RootAlignment::remove`].
+    pub fn restore(&self, animation: &mut Animation) {
+        let rotation = self.rotation();
+        for position in &mut animation.root_positions {
+            *position = rotation * *position + self.translation;
+        }
+        if let Some(rotations) = animation.joint_rotations.first_mut() {
+            for root_rotation in rotations {
+                *root_rotation = rotation * *root_rotation;
+            }
+        }
+    }
+
+    pub fn apply_to(&self, metadata: &mut ClipMetadata) {
+        let [x, y, z] = self.translation.to_array();
+        metadata.set(TRANSLATION_KEY, &format!("{},{},{}", x, y, z));
+        metadata.set(YAW_KEY, &self.yaw.to_string());
+    }
+
+    /// Removes a recorded alignment from `metadata`, for clips converted again without one.
+    pub fn clear(metadata: &mut ClipMetadata) {
+        metadata.set(TRANSLATION_KEY, "");
+        metadata.set(YAW_KEY, "");
+    }
+
+    /// The alignment recorded in `metadata`, `None` for clips that weren't aligned.
+    pub fn from_metadata(metadata: &ClipMetadata) -> Result<Option<Self>> {
+        let (Some(translation), Some(yaw)) = (metadata.get(TRANSLATION_KEY), metadata.get(YAW_KEY))
+        else {
+            return Ok(None);
+        };
+        let translation = translation
+            .split(',')
+            .map(|value| value.trim().parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()
+            .ok()
+            .and_then(|values| <[f32; 3]>::try_from(values).ok())
+            .with_context(|| format!("Invalid {} {}", TRANSLATION_KEY, translation))?;
+        Ok(Some(RootAlignment {
+            translation: Vec3::from_array(translation),
+            yaw: yaw
+                .parse()
+                .with_context(|| format!("Invalid {} {}", YAW_KEY, yaw))?,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::skeleton::SkeletonJoint;
+
+    #[test]
+    fn test_alignment_starts_at_the_origin_facing_forward() {
+        let joint = |name: &str, parent, offset| SkeletonJoint {
+            name: name.to_string(),
+            parent,
+            offset,
+            end_site: None,
+        };
+        let skeleton = Skeleton {
+            joints: vec![
+                joint("Hips", None, Vec3::ZERO),
+                joint("Foot", Some(0), Vec3::NEG_Y * 90.0),
+            ],
+        };
+        let facing = Quat::from_rotation_y(1.2);
+        let animation = Animation {
+            root_positions: vec![Vec3::new(300.0, 95.0, -40.0), Vec3::new(310.0, 96.0, -35.0)],
+            joint_rotations: vec![vec![facing; 2], vec![Quat::IDENTITY; 2]],
+            events: Vec::new(),
+        };
+
+        let alignment = RootAlignment::fit(&skeleton, &animation);
+        let mut aligned = animation.clone();
+        alignment.remove(&mut aligned);
+        assert!(aligned.root_positions[0].distance(Vec3::new(0.0, 90.0, 0.0)) < 1e-3);
+        assert!(aligned.joint_rotations[0][0].angle_between(Quat::IDENTITY) < 1e-4);
+        // The foot of the first frame is on the ground.
+        let foot = global_transforms(&skeleton, &aligned, 0)[1].0;
+        assert!(foot.y.abs() < 1e-3);
+
+        let mut metadata = ClipMetadata::default();
+        assert_eq!(RootAlignment::from_metadata(&metadata).unwrap(), None);
+        alignment.apply_to(&mut metadata);
+        let read = RootAlignment::from_metadata(&metadata).unwrap().unwrap();
+        read.restore(&mut aligned);
+        RootAlignment::clear(&mut metadata);
+        assert_eq!(RootAlignment::from_metadata(&metadata).unwrap(), None);
+        for (restored, original) in aligned.root_positions.iter().zip(&animation.root_positions) {
+            assert!(restored.distance(*original) < 1e-3);
+        }
+        assert!(aligned.joint_rotations[0][1].angle_between(facing) < 1e-4);
+    }
+}
@@ -0,0 +1,148 @@
+//! Clips read straight out of `.zip`, `.tar` and `.tar.gz` archives, the way most public motion
+//! capture datasets are distributed, without extracting them first. Entries are streamed one at
+//! a time, so only the entry being read is held in memory.
+use std::{
+    io::{Read, Seek},
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::Result;
+use flate2::read::GzDecoder;
+use zip::ZipArchive;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl ArchiveFormat {
+    /// Format of the archive at `path` by its extension, `None` when it isn't an archive.
+    pub fn of(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveFormat::Tar)
+        } else {
+            None
+        }
+    }
+}
+
+/// Folder the clips of the archive at `path` are converted into when no output folder is
+/// given: next to the archive, named after it without its extension.
+pub fn extraction_folder(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let lowercase = name.to_ascii_lowercase();
+    let stem = [".tar.gz", ".tgz", ".tar", ".zip"]
+        .iter()
+        .find(|extension| lowercase.ends_with(*extension))
+        .map_or(&*name, |extension| &name[..name.len() - extension.len()]);
+    path.with_file_name(stem)
+}
+
+/// Whether `path` stays inside the archive, so it can't be written outside the output folder.
+fn is_enclosed(path: &Path) -> bool {
+    path.components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// Calls `f` with the path and content of every file of the archive, in archive order.
+pub fn for_each_file<R: Read + Seek>(
+    format: ArchiveFormat,
+    reader: R,
+    mut f: impl FnMut(&Path, &mut dyn Read) -> Result<()>,
+) -> Result<()> {
+    match format {
+        ArchiveFormat::Zip => {
+            let mut archive = ZipArchive::new(reader)?;
+            for index in 0..archive.len() {
+                let mut file = archive.by_index(index)?;
+                if let Some(path) = file.enclosed_name().filter(|_| file.is_file()) {
+                    f(&path, &mut file)?;
+                }
+            }
+        }
+        ArchiveFormat::Tar => for_each_tar_file(reader, f)?,
+        ArchiveFormat::TarGz => for_each_tar_file(GzDecoder::new(reader), f)?,
+    }
+    Ok(())
+}
+
+fn for_each_tar_file<R: Read>(
+    reader: R,
+    mut f: impl FnMut(&Path, &mut dyn Read) -> Result<()>,
+) -> Result<()> {
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        if entry.header().entry_type().is_file() && is_enclosed(&path) {
+            f(&path, &mut entry)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use super::*;
+
+    fn file_names<R: Read + Seek>(format: ArchiveFormat, reader: R) -> Vec<(String, String)> {
+        let mut files = Vec::new();
+        for_each_file(format, reader, |path, content| {
+            let mut text = String::new();
+            content.read_to_string(&mut text)?;
+            files.push((path.to_string_lossy().into_owned(), text));
+            Ok(())
+        })
+        .unwrap();
+        files
+    }
+
+    #[test]
+    fn test_archives_are_read_without_extracting() {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        zip.add_directory("cmu/", options).unwrap();
+        zip.start_file("cmu/walk.bvh", options).unwrap();
+        zip.write_all(b"HIERARCHY").unwrap();
+        let zip = zip.finish().unwrap();
+        assert_eq!(
+            file_names(ArchiveFormat::Zip, zip),
+            vec![("cmu/walk.bvh".to_string(), "HIERARCHY".to_string())]
+        );
+
+        let mut tar = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(6);
+        header.set_entry_type(tar::EntryType::Regular);
+        tar.append_data(&mut header.clone(), "cmu/run.bvh", &b"MOTION"[..])
+            .unwrap();
+        // Written by hand, as the builder refuses paths leaving the archive.
+        header.as_gnu_mut().unwrap().name[..10].copy_from_slice(b"../run.bvh");
+        header.set_cksum();
+        tar.append(&header, &b"MOTION"[..]).unwrap();
+        let tar = tar.into_inner().unwrap();
+        assert_eq!(
+            file_names(ArchiveFormat::Tar, Cursor::new(tar)),
+            vec![("cmu/run.bvh".to_string(), "MOTION".to_string())]
+        );
+
+        assert_eq!(
+            ArchiveFormat::of(Path::new("CMU.TAR.GZ")),
+            Some(ArchiveFormat::TarGz)
+        );
+        assert_eq!(ArchiveFormat::of(Path::new("walk.bvh")), None);
+        assert_eq!(
+            extraction_folder(Path::new("data/cmu.tar.gz")),
+            Path::new("data/cmu")
+        );
+    }
+}
@@ -0,0 +1,187 @@
+//! Random perturbations of clips for data augmentation. Transforms are written as
+//! `name:parameters` and applied in the order given:
+//!
+//! - `jitter:DEGREES` adds Gaussian noise of this standard deviation to every joint rotation.
+//! - `drift:CM` lets the root wander off its path in a random walk on the ground plane, this
+//!   many centimeters per second on average.
+//! - `crop:FRAMES` keeps a random window of this many frames.
+//! - `speed:MIN:MAX` plays the clip at a random speed factor between the two.
+//!
+//! All randomness comes from one [`StdRng`], so the same seed always gives the same variants.
+use std::str::FromStr;
+
+use anyhow::{Context, Error, Result, bail};
+use bevy_math::{Quat, Vec3};
+use rand::{Rng, rngs::StdRng};
+use rand_distr::{Distribution, Normal};
+
+use crate::{Animation, frame_rate::resample_frame_time};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Augmentation {
+    /// Standard deviation of the rotation noise, in degrees.
+    Jitter(f32),
+    /// Standard deviation of the root offset after one second, in centimeters.
+    Drift(f32),
+    /// Length of the kept window, in frames.
+    Crop(usize),
+    /// Range of the speed factor.
+    Speed(f32, f32),
+}
+
+impl FromStr for Augmentation {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.split(':');
+        let name = parts.next().unwrap_or_default();
+        let values: Vec<f32> = parts
+            .map(|value| {
+                value
+                    .parse()
+                    .with_context(|| format!("Invalid value {} in {}", value, s))
+            })
+            .collect::<Result<_>>()?;
+        let augmentation = match (name, values.as_slice()) {
+            ("jitter", &[degrees]) if degrees >= 0.0 => Augmentation::Jitter(degrees),
+            ("drift", &[distance]) if distance >= 0.0 => Augmentation::Drift(distance),
+            ("crop", &[frames]) if frames >= 1.0 => Augmentation::Crop(frames as usize),
+            ("speed", &[min, max]) if 0.0 < min && min <= max => Augmentation::Speed(min, max),
+            _ => bail!(
+                "Invalid augmentation {}, expected jitter:DEGREES, drift:CM, crop:FRAMES or speed:MIN:MAX",
+                s
+            ),
+        };
+        Ok(augmentation)
+    }
+}
+
+/// Gaussian noise of `deviation`, or none when it is zero.
+fn normal(deviation: f32) -> Normal<f32> {
+    Normal::new(0.0, deviation).unwrap_or_else(|_| Normal::new(0.0, 0.0).unwrap())
+}
+
+impl Augmentation {
+    /// Applies the transform to `animation`, returning the new animation and its frame time.
+    pub fn apply(
+        &self,
+        animation: &Animation,
+        frame_time: f32,
+        rng: &mut StdRng,
+    ) -> (Animation, f32) {
+        match *self {
+            Augmentation::Jitter(degrees) => {
+                let noise = normal(degrees.to_radians());
+                let mut jittered = animation.clone();
+                for rotation in jittered.joint_rotations.iter_mut().flatten() {
+                    let axis = Vec3::new(noise.sample(rng), noise.sample(rng), noise.sample(rng));
+                    *rotation = (Quat::from_scaled_axis(axis) * *rotation).normalize();
+                }
+                (jittered, frame_time)
+            }
+            Augmentation::Drift(distance) => {
+                // Steps of a random walk add up in variance, so each frame gets the share of
+                // its duration.
+                let step = normal(distance * frame_time.sqrt());
+                let mut offset = Vec3::ZERO;
+                let mut drifted = animation.clone();
+                for position in &mut drifted.root_positions {
+                    *position += offset;
+                    offset += Vec3::new(step.sample(rng), 0.0, step.sample(rng));
+                }
+                (drifted, frame_time)
+            }
+            Augmentation::Crop(frames) => {
+                let frame_count = animation.frame_count();
+                if frame_count <= frames {
+                    return (animation.clone(), frame_time);
+                }
+                let start = rng.gen_range(0..=frame_count - frames);
+                let mut cropped = animation.resample((start..start + frames).map(|f| f as f32));
+                cropped.events = animation
+                    .events
+                    .iter()
+                    .filter(|event| (start..start + frames).contains(&event.frame))
+                    .map(|event| {
+                        let mut event = event.clone();
+                        event.frame -= start;
+                        event
+                    })
+                    .collect();
+                (cropped, frame_time)
+            }
+            Augmentation::Speed(min, max) => {
+                let speed = if min < max {
+                    rng.gen_range(min..=max)
+                } else {
+                    min
+                };
+                // Sampling the clip further apart and playing it at the same rate speeds it up.
+                (
+                    resample_frame_time(animation, frame_time, frame_time * speed),
+                    frame_time,
+                )
+            }
+        }
+    }
+}
+
+/// Applies `augmentations` in order.
+pub fn augment(
+    animation: &Animation,
+    frame_time: f32,
+    augmentations: &[Augmentation],
+    rng: &mut StdRng,
+) -> (Animation, f32) {
+    augmentations.iter().fold(
+        (animation.clone(), frame_time),
+        |(animation, frame_time), augmentation| augmentation.apply(&animation, frame_time, rng),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::events::AnimationEvent;
+
+    #[test]
+    fn test_augmentations_are_seeded() {
+        let animation = Animation {
+            root_positions: (0..61).map(|f| Vec3::X * f as f32).collect(),
+            joint_rotations: vec![vec![Quat::IDENTITY; 61]; 2],
+            events: vec![AnimationEvent {
+                frame: 40,
+                name: "step".to_string(),
+                payload: None,
+            }],
+        };
+        let augmentations: Vec<Augmentation> = ["jitter:2", "drift:5", "crop:30", "speed:2:2"]
+            .iter()
+            .map(|a| a.parse().unwrap())
+            .collect();
+        let run = |seed| {
+            augment(
+                &animation,
+                1.0 / 30.0,
+                &augmentations,
+                &mut StdRng::seed_from_u64(seed),
+            )
+        };
+
+        let (a, frame_time) = run(7);
+        let (b, _) = run(7);
+        let (c, _) = run(8);
+        assert_eq!(frame_time, 1.0 / 30.0);
+        assert_eq!(a.root_positions, b.root_positions);
+        assert_eq!(a.joint_rotations, b.joint_rotations);
+        assert_ne!(a.root_positions, c.root_positions);
+        // 30 frames cropped, then played twice as fast.
+        assert_eq!(a.frame_count(), 16);
+        assert!(a.joint_rotations[1][3] != Quat::IDENTITY);
+
+        assert!("speed:2:1".parse::<Augmentation>().is_err());
+        assert!("blur:3".parse::<Augmentation>().is_err());
+    }
+}
@@ -0,0 +1,175 @@
+//! Beat alignment for dance datasets: clips are time warped so the beats found by an audio
+//! analysis land on a fixed tempo grid, and the beat phase can be stored as an extra channel.
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use bevy_math::Vec3;
+use ndarray::Array3;
+use serde::Deserialize;
+
+use crate::{Animation, append_curve};
+
+/// Beat timestamps in seconds, either a bare array or the `beats` field of an analysis result.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BeatFile {
+    Beats(Vec<f32>),
+    Analysis { beats: Vec<f32> },
+}
+
+/// Reads beat timestamps in seconds from an audio analysis JSON file, sorted.
+pub fn read_beats(path: &Path) -> Result<Vec<f32>> {
+    let file =
+        std::fs::File::open(path).with_context(|| format!("Could not open {}", path.display()))?;
+    let (BeatFile::Beats(mut beats) | BeatFile::Analysis { mut beats }) =
+        serde_json::from_reader(file)
+            .with_context(|| format!("{} holds no beat timestamps", path.display()))?;
+    beats.sort_by(f32::total_cmp);
+    if beats.len() < 2 {
+        bail!(
+            "At least two beats are needed, {} has {}",
+            path.display(),
+            beats.len()
+        );
+    }
+    Ok(beats)
+}
+
+/// Maps a time through the piecewise linear function through `from` → `to`. Outside the
+/// knots the time moves at the speed of the nearest interval.
+fn piecewise_linear(time: f32, from: &[f32], to: &[f32]) -> f32 {
+    let last = from.len() - 1;
+    let interval = from[1..last]
+        .partition_point(|&knot| knot <= time)
+        .min(last - 1);
+    let (f0, f1, t0, t1) = (
+        from[interval],
+        from[interval + 1],
+        to[interval],
+        to[interval + 1],
+    );
+    if f1 > f0 {
+        t0 + (time - f0) * (t1 - t0) / (f1 - f0)
+    } else {
+        t0
+    }
+}
+
+/// Maps a time from the original clip to the warped one (or back, with the knots swapped).
+/// Before the first and after the last beat the clip plays at its original speed.
+fn warp_time(time: f32, from: &[f32], to: &[f32]) -> f32 {
+    let last = from.len() - 1;
+    if time < from[0] {
+        to[0] + time - from[0]
+    } else if time > from[last] {
+        to[last] + time - from[last]
+    } else {
+        piecewise_linear(time, from, to)
+    }
+}
+
+/// Beat grid with a fixed `bpm`, starting at the first beat so the intro is kept.
+pub fn beat_grid(beats: &[f32], bpm: f32) -> Vec<f32> {
+    let period = 60.0 / bpm;
+    (0..beats.len())
+        .map(|i| beats[0] + i as f32 * period)
+        .collect()
+}
+
+/// Time warps `animation` so the `beats` (in seconds) land on a grid of `bpm` beats per minute.
+/// Events are moved along with the frames they mark.
+pub fn warp_to_beat_grid(
+    animation: &Animation,
+    frame_time: f32,
+    beats: &[f32],
+    bpm: f32,
+) -> Result<Animation> {
+    if beats.len() < 2 || bpm <= 0.0 {
+        bail!("Warping needs at least two beats and a positive tempo");
+    }
+    let grid = beat_grid(beats, bpm);
+    let duration = animation.frame_count().saturating_sub(1) as f32 * frame_time;
+    let warped_duration = warp_time(duration, beats, &grid);
+    let frame_count = (warped_duration / frame_time).round() as usize + 1;
+
+    let mut warped = animation.resample(
+        (0..frame_count)
+            .map(|frame| warp_time(frame as f32 * frame_time, &grid, beats) / frame_time),
+    );
+    warped.events = animation
+        .events
+        .iter()
+        .map(|event| {
+            let time = warp_time(event.frame as f32 * frame_time, beats, &grid);
+            let mut event = event.clone();
+            event.frame = ((time / frame_time).round().max(0.0) as usize).min(frame_count - 1);
+            event
+        })
+        .collect();
+    Ok(warped)
+}
+
+/// Position within the current beat of every frame, from 0 at a beat to 1 at the next one.
+pub fn beat_phase(frame_count: usize, frame_time: f32, beats: &[f32]) -> Vec<f32> {
+    let indices: Vec<f32> = (0..beats.len()).map(|i| i as f32).collect();
+    (0..frame_count)
+        .map(|frame| piecewise_linear(frame as f32 * frame_time, beats, &indices).rem_euclid(1.0))
+        .collect()
+}
+
+/// Appends the beat phase to a GAV tensor as one more curve holding `sin`, `cos` and the raw
+/// phase, so the wrap from 1 to 0 stays continuous for a network.
+pub fn append_beat_phase(gav_data: &Array3<f32>, phase: &[f32]) -> Result<Array3<f32>> {
+    let curve: Vec<Vec3> = phase
+        .iter()
+        .map(|&p| {
+            let angle = p * std::f32::consts::TAU;
+            Vec3::new(angle.sin(), angle.cos(), p)
+        })
+        .collect();
+    append_curve(gav_data, &curve)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::AnimationEvent;
+    use bevy_math::Quat;
+
+    #[test]
+    fn test_warp_moves_beats_onto_grid() {
+        // The root moves one unit per frame at 10 fps, beats are irregular.
+        let animation = Animation {
+            root_positions: (0..41).map(|i| Vec3::new(i as f32, 0.0, 0.0)).collect(),
+            joint_rotations: vec![vec![Quat::IDENTITY; 41]],
+            events: vec![AnimationEvent {
+                frame: 25,
+                name: "step".to_string(),
+                payload: None,
+            }],
+        };
+        let beats = [1.0, 1.5, 2.5, 3.0];
+        let warped = warp_to_beat_grid(&animation, 0.1, &beats, 60.0).unwrap();
+
+        // Grid beats at 1, 2, 3 and 4 seconds show the poses of the original beats.
+        for (beat, grid) in beats.iter().zip([1.0, 2.0, 3.0, 4.0]) {
+            let frame = (grid / 0.1f32).round() as usize;
+            let expected = beat / 0.1;
+            assert!((warped.root_positions[frame].x - expected).abs() < 1e-3);
+        }
+        // The event on the third beat moves along with it.
+        assert_eq!(warped.events[0].frame, 30);
+        assert_eq!(warped.frame_count(), 51);
+    }
+
+    #[test]
+    fn test_beat_phase_wraps_at_beats() {
+        let phase = beat_phase(6, 0.25, &[0.0, 0.5, 1.0]);
+        assert_eq!(phase, vec![0.0, 0.5, 0.0, 0.5, 0.0, 0.5]);
+
+        let gav = Array3::<f32>::zeros((2, 6, 3));
+        let gav = append_beat_phase(&gav, &phase).unwrap();
+        assert_eq!(gav.dim(), (3, 6, 3));
+        assert_eq!(gav[[2, 1, 2]], 0.5);
+    }
+}
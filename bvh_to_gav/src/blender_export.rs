@@ -0,0 +1,287 @@
+//! Export of clips to Blender as a pose library: a JSON bundle holding an armature of the
+//! skeleton and one action per clip, and a Python script, generated next to it, that imports
+//! the bundle into the open scene when run from Blender's Text Editor.
+//!
+//! The script only creates datablocks, every conversion happens here. Positions are converted
+//! to Blender's Z-up meters. Every bone points from its joint to its first child, or its end
+//! site, and its rest orientation is the rotation of +Y onto that direction. Rotations are
+//! exported as pose bone rotations, relative to the rest orientation of their bone, and root
+//! positions as the location of the root bones. Single frame clips become pose assets.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, bail};
+use bevy_math::{Quat, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Animation,
+    convention::{CoordinateConvention, CoordinateTransform, Handedness, LengthUnit, UpAxis},
+    events::AnimationEvent,
+    kinematics::rest_positions,
+    skeleton::Skeleton,
+};
+
+/// Version of the bundle format, checked by the importer script.
+pub const BLENDER_BUNDLE_VERSION: u32 = 1;
+pub const BLENDER_BUNDLE_FILE: &str = "pose_library.json";
+pub const BLENDER_IMPORTER_FILE: &str = "import_pose_library.py";
+const IMPORTER_TEMPLATE: &str = include_str!("../assets/blender_import.py");
+/// Bones shorter than this, in skeleton units, point along their parent instead.
+const MIN_BONE_LENGTH: f32 = 1e-3;
+/// Length of bones without a child or end site to point to, in skeleton units.
+const DEFAULT_BONE_LENGTH: f32 = 5.0;
+
+/// Transform from the clip convention to Blender's.
+fn to_blender() -> CoordinateTransform {
+    CoordinateConvention::default().to(&CoordinateConvention {
+        up: UpAxis::Z,
+        unit: LengthUnit::Meters,
+        handedness: Handedness::Right,
+    })
+}
+
+/// A quaternion in Blender's component order, `[w, x, y, z]`.
+fn wxyz(rotation: Quat) -> [f32; 4] {
+    [rotation.w, rotation.x, rotation.y, rotation.z]
+}
+
+/// Vector from `joint` to the tail of its bone, in skeleton units.
+fn bone_vector(skeleton: &Skeleton, joint: usize) -> Vec3 {
+    skeleton
+        .children(joint)
+        .map(|child| skeleton.joints[child].offset)
+        .chain(skeleton.joints[joint].end_site)
+        .find(|offset| offset.length() > MIN_BONE_LENGTH)
+        .unwrap_or_else(|| {
+            let direction = skeleton.joints[joint]
+                .parent
+                .map_or(Vec3::Y, |parent| bone_vector(skeleton, parent).normalize());
+            direction * DEFAULT_BONE_LENGTH
+        })
+}
+
+/// A bone of the armature, in armature space.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BlenderBone {
+    pub name: String,
+    pub parent: Option<String>,
+    pub head: [f32; 3],
+    pub tail: [f32; 3],
+    /// Rest orientation, its +Y pointing from the head to the tail.
+    pub rest: [f32; 4],
+}
+
+/// The curves of a bone in a clip.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BlenderTrack {
+    pub bone: String,
+    /// Pose bone location of every frame, for root bones only.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub locations: Vec<[f32; 3]>,
+    /// Pose bone rotation of every frame, without sign flips between frames so Blender
+    /// interpolates them the short way.
+    pub rotations: Vec<[f32; 4]>,
+}
+
+/// A clip, imported as an action. Events become pose markers.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BlenderClip {
+    pub name: String,
+    pub frame_count: usize,
+    pub tracks: Vec<BlenderTrack>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<AnimationEvent>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BlenderBundle {
+    pub version: u32,
+    /// Name of the armature.
+    pub name: String,
+    pub fps: f32,
+    /// Bones in joint order, parents before their children.
+    pub bones: Vec<BlenderBone>,
+    pub clips: Vec<BlenderClip>,
+}
+
+impl BlenderBundle {
+    /// An empty library of an armature of `skeleton`, named `name`.
+    pub fn new(name: &str, skeleton: &Skeleton, frame_time: f32) -> Self {
+        let transform = to_blender();
+        let heads = rest_positions(skeleton);
+        let bones = skeleton
+            .joints
+            .iter()
+            .enumerate()
+            .map(|(joint, bone)| {
+                let head = heads[joint];
+                let tail = head + bone_vector(skeleton, joint);
+                let direction = transform.point(tail - head).normalize();
+                BlenderBone {
+                    name: bone.name.clone(),
+                    parent: bone
+                        .parent
+                        .map(|parent| skeleton.joints[parent].name.clone()),
+                    head: transform.point(head).to_array(),
+                    tail: transform.point(tail).to_array(),
+                    rest: wxyz(Quat::from_rotation_arc(Vec3::Y, direction)),
+                }
+            })
+            .collect();
+        BlenderBundle {
+            version: BLENDER_BUNDLE_VERSION,
+            name: name.to_string(),
+            fps: 1.0 / frame_time,
+            bones,
+            clips: Vec::new(),
+        }
+    }
+
+    fn rest(&self, bone: usize) -> Quat {
+        let [w, x, y, z] = self.bones[bone].rest;
+        Quat::from_xyzw(x, y, z, w)
+    }
+
+    /// Adds `animation` of the skeleton of the library as the clip `name`.
+    pub fn add_clip(&mut self, name: &str, animation: &Animation) -> Result<()> {
+        if animation.joint_count() != self.bones.len() {
+            bail!(
+                "{} has {} joints but the armature {}",
+                name,
+                animation.joint_count(),
+                self.bones.len()
+            );
+        }
+        let transform = to_blender();
+        let tracks = self
+            .bones
+            .iter()
+            .enumerate()
+            .map(|(joint, bone)| {
+                let rest = self.rest(joint);
+                let mut previous = Quat::IDENTITY;
+                let rotations = animation.joint_rotations[joint]
+                    .iter()
+                    .map(|&rotation| {
+                        let mut pose = rest.inverse() * transform.rotation(rotation) * rest;
+                        if pose.dot(previous) < 0.0 {
+                            pose = -pose;
+                        }
+                        previous = pose;
+                        wxyz(pose)
+                    })
+                    .collect();
+                let locations = if bone.parent.is_none() {
+                    let head = Vec3::from_array(bone.head);
+                    animation
+                        .root_positions
+                        .iter()
+                        .map(|&position| {
+                            (rest.inverse() * (transform.point(position) - head)).to_array()
+                        })
+                        .collect()
+                } else {
+                    Vec::new()
+                };
+                BlenderTrack {
+                    bone: bone.name.clone(),
+                    locations,
+                    rotations,
+                }
+            })
+            .collect();
+        self.clips.push(BlenderClip {
+            name: name.to_string(),
+            frame_count: animation.frame_count(),
+            tracks,
+            events: animation.events.clone(),
+        });
+        Ok(())
+    }
+
+    /// Python script importing the bundle at `bundle_path`.
+    pub fn importer_script(bundle_path: &Path) -> Result<String> {
+        Ok(IMPORTER_TEMPLATE
+            .replace(
+                "__BUNDLE_PATH__",
+                &serde_json::to_string(&bundle_path.to_string_lossy())?,
+            )
+            .replace("__BUNDLE_VERSION__", &BLENDER_BUNDLE_VERSION.to_string()))
+    }
+
+    /// Writes the bundle and its importer script into `folder`, returning the script path.
+    pub fn save(&self, folder: &Path) -> Result<PathBuf> {
+        fs::create_dir_all(folder)?;
+        let bundle_path = folder.join(BLENDER_BUNDLE_FILE);
+        serde_json::to_writer(fs::File::create(&bundle_path)?, self)?;
+        // Blender runs the script from its own working directory.
+        let absolute = fs::canonicalize(&bundle_path)?;
+        let script_path = folder.join(BLENDER_IMPORTER_FILE);
+        fs::write(&script_path, Self::importer_script(&absolute)?)?;
+        Ok(script_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+    use crate::skeleton::SkeletonJoint;
+
+    #[test]
+    fn test_pose_bones_reproduce_the_clip() {
+        let skeleton = Skeleton {
+            joints: vec![
+                SkeletonJoint {
+                    name: "Hips".to_string(),
+                    parent: None,
+                    offset: Vec3::new(0.0, 90.0, 0.0),
+                    end_site: None,
+                },
+                SkeletonJoint {
+                    name: "Head".to_string(),
+                    parent: Some(0),
+                    offset: Vec3::new(0.0, 0.0, 60.0),
+                    end_site: None,
+                },
+            ],
+        };
+        let turn = Quat::from_rotation_y(FRAC_PI_2);
+        let animation = Animation {
+            root_positions: vec![Vec3::new(0.0, 100.0, 0.0)],
+            joint_rotations: vec![vec![turn], vec![turn]],
+            events: Vec::new(),
+        };
+        let mut bundle = BlenderBundle::new("walker", &skeleton, 1.0 / 30.0);
+        bundle.add_clip("turn", &animation).unwrap();
+
+        // Y up centimeters to Z up meters, with +Z forward becoming -Y.
+        assert_eq!(bundle.bones[0].head, [0.0, 0.0, 0.9]);
+        let tail = Vec3::from_array(bundle.bones[0].tail);
+        assert!(tail.distance(Vec3::new(0.0, -0.6, 0.9)) < 1e-5);
+        // The head has no child, so it continues along its parent.
+        let head_tail = Vec3::from_array(bundle.bones[1].tail);
+        assert!(head_tail.distance(Vec3::new(0.0, -0.65, 0.9)) < 1e-5);
+
+        // Rest orientation times pose rotation is the turn about Blender's up axis.
+        let clip = &bundle.clips[0];
+        for (bone, track) in clip.tracks.iter().enumerate() {
+            let [w, x, y, z] = track.rotations[0];
+            let pose = Quat::from_xyzw(x, y, z, w);
+            let world = bundle.rest(bone) * pose * bundle.rest(bone).inverse();
+            assert!(world.angle_between(Quat::from_rotation_z(FRAC_PI_2)) < 1e-3);
+        }
+        let location = Vec3::from_array(clip.tracks[0].locations[0]);
+        let root = Vec3::from_array(bundle.bones[0].head) + bundle.rest(0) * location;
+        assert!(root.distance(Vec3::new(0.0, 0.0, 1.0)) < 1e-5);
+        assert!(clip.tracks[1].locations.is_empty());
+
+        let script = BlenderBundle::importer_script(Path::new("/tmp/pose_library.json")).unwrap();
+        assert!(script.contains("BUNDLE_PATH = \"/tmp/pose_library.json\""));
+        assert!(!script.contains("__BUNDLE"));
+    }
+}
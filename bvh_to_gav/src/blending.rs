@@ -0,0 +1,173 @@
+//! Blending of animations of the same skeleton, and crossfades for stitching clips, such as
+//! generated clips, into one. Root positions are interpolated linearly and joint rotations
+//! spherically, like [`Animation::sample`].
+use anyhow::{Result, bail};
+use bevy_math::Vec3;
+
+use crate::{Animation, alignment::RootAlignment, events::AnimationEvent, root_motion::yaw};
+
+impl Animation {
+    /// This animation blended frame by frame with `other`, from this one at `weight` 0 to
+    /// `other` at 1. The blend is as long as the shorter of the two and keeps the events of
+    /// this one that fall in it.
+    pub fn blend(&self, other: &Animation, weight: f32) -> Result<Animation> {
+        if self.joint_count() != other.joint_count() {
+            bail!(
+                "Can't blend an animation of {} joints with one of {}",
+                self.joint_count(),
+                other.joint_count()
+            );
+        }
+        let frame_count = self.frame_count().min(other.frame_count());
+        Ok(Animation {
+            root_positions: self
+                .root_positions
+                .iter()
+                .zip(&other.root_positions)
+                .map(|(a, b)| a.lerp(*b, weight))
+                .collect(),
+            joint_rotations: self
+                .joint_rotations
+                .iter()
+                .zip(&other.joint_rotations)
+                .map(|(a, b)| a.iter().zip(b).map(|(a, b)| a.slerp(*b, weight)).collect())
+                .collect(),
+            events: self
+                .events
+                .iter()
+                .filter(|event| event.frame < frame_count)
+                .cloned()
+                .collect(),
+        })
+    }
+}
+
+/// Transform of the root of `animation` at `frame` about +Y, ignoring its height.
+fn heading(animation: &Animation, frame: usize) -> RootAlignment {
+    let root = animation.root_positions[frame];
+    RootAlignment {
+        translation: Vec3::new(root.x, 0.0, root.z),
+        yaw: animation
+            .joint_rotations
+            .first()
+            .map_or(0.0, |rotations| yaw(rotations[frame])),
+    }
+}
+
+/// `first` followed by `second`, with the last `transition` frames of `first` blended into the
+/// first `transition` frames of `second`, with weights rising linearly. `second` is moved and
+/// turned about +Y first, so its root starts the transition where and facing where the root of
+/// `first` is. Heights are kept. The result has `transition` frames fewer than both together,
+/// and the events of both.
+pub fn crossfade(first: &Animation, second: &Animation, transition: usize) -> Result<Animation> {
+    if transition > first.frame_count() || transition > second.frame_count() {
+        bail!(
+            "A transition of {} frames is longer than a clip of {} or {} frames",
+            transition,
+            first.frame_count(),
+            second.frame_count()
+        );
+    }
+    if first.joint_count() != second.joint_count() {
+        bail!(
+            "Can't crossfade an animation of {} joints into one of {}",
+            first.joint_count(),
+            second.joint_count()
+        );
+    }
+    let start = first.frame_count() - transition;
+    let mut aligned = second.clone();
+    if second.frame_count() > 0 && first.frame_count() > 0 {
+        heading(second, 0).remove(&mut aligned);
+        heading(first, start.min(first.frame_count() - 1)).restore(&mut aligned);
+    }
+
+    let mut animation = first.resample((0..start).map(|frame| frame as f32));
+    let weights = (0..transition).map(|frame| (frame + 1) as f32 / (transition + 1) as f32);
+    for (frame, weight) in weights.enumerate() {
+        let (Some(from), Some(to)) = (first.pose(start + frame), aligned.pose(frame)) else {
+            unreachable!("The transition fits in both clips");
+        };
+        animation
+            .root_positions
+            .push(from.root_position.lerp(to.root_position, weight));
+        for (track, (a, b)) in animation
+            .joint_rotations
+            .iter_mut()
+            .zip(from.joint_rotations.iter().zip(&to.joint_rotations))
+        {
+            track.push(a.slerp(*b, weight));
+        }
+    }
+    let rest = aligned.resample((transition..aligned.frame_count()).map(|frame| frame as f32));
+    animation.root_positions.extend(rest.root_positions);
+    for (track, rest) in animation
+        .joint_rotations
+        .iter_mut()
+        .zip(rest.joint_rotations)
+    {
+        track.extend(rest);
+    }
+
+    animation.events = first
+        .events
+        .iter()
+        .cloned()
+        .chain(second.events.iter().map(|event| AnimationEvent {
+            frame: event.frame + start,
+            ..event.clone()
+        }))
+        .collect();
+    animation.events.sort_by_key(|event| event.frame);
+    Ok(animation)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use bevy_math::Quat;
+
+    use super::*;
+
+    fn walk(frame_count: usize, direction: Vec3, rotation: Quat) -> Animation {
+        Animation {
+            root_positions: (0..frame_count)
+                .map(|frame| Vec3::new(0.0, 90.0, 0.0) + direction * frame as f32)
+                .collect(),
+            joint_rotations: vec![vec![rotation; frame_count]; 2],
+            events: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_crossfade_continues_the_root_path() {
+        let forward = walk(10, Vec3::Z, Quat::IDENTITY);
+        let turned = Quat::from_rotation_y(FRAC_PI_2);
+        let half = forward.blend(&walk(8, Vec3::X, turned), 0.5).unwrap();
+        assert_eq!(half.frame_count(), 8);
+        assert!(half.root_positions[2].distance(Vec3::new(1.0, 90.0, 1.0)) < 1e-5);
+        assert!(
+            half.joint_rotations[1][0].angle_between(Quat::from_rotation_y(FRAC_PI_2 / 2.0)) < 1e-5
+        );
+
+        // Walks along +X facing +X, which the crossfade turns to continue along +Z.
+        let mut second = walk(6, Vec3::X, turned);
+        second.events.push(AnimationEvent {
+            frame: 1,
+            name: "step".to_string(),
+            payload: None,
+        });
+        let stitched = crossfade(&forward, &second, 3).unwrap();
+        assert_eq!(stitched.frame_count(), 13);
+        assert_eq!(stitched.events[0].frame, 8);
+        for frame in 0..13 {
+            let expected = Vec3::new(0.0, 90.0, frame as f32);
+            assert!(stitched.root_positions[frame].distance(expected) < 1e-4);
+            assert!(stitched.joint_rotations[0][frame].angle_between(Quat::IDENTITY) < 1e-3);
+        }
+        // Only the root is turned.
+        assert!(stitched.joint_rotations[1][12].angle_between(turned) < 1e-3);
+        assert!(crossfade(&forward, &second, 7).is_err());
+    }
+}
@@ -0,0 +1,128 @@
+//! Length buckets of the sequences of a [`crate::manifest::Manifest`], to pick sequence length
+//! settings that don't spend most of a batch on padding. A sequence is a clip, or with a maximum
+//! length a chunk of at most that many frames, the last chunk of a clip holding the rest.
+//!
+//! Sequences go to the first bucket whose length fits them, and are padded to it. Batches are
+//! drawn from one bucket and padded to the batch size, so the padding of a configuration is
+//! every frame of its batches that isn't a frame of a sequence. The bucket lengths of `count`
+//! buckets are quantiles of the sequence lengths, so each bucket holds about as many sequences.
+use serde::{Deserialize, Serialize};
+
+/// Start and length of the sequences of a clip of `frame_count` frames.
+pub fn clip_sequences(frame_count: usize, max_length: Option<usize>) -> Vec<(usize, usize)> {
+    let length = max_length.unwrap_or(frame_count).max(1);
+    (0..frame_count)
+        .step_by(length)
+        .map(|start| (start, length.min(frame_count - start)))
+        .collect()
+}
+
+/// Lengths of `count` buckets for sequences of `lengths`, the longest bucket fitting the longest
+/// sequence. Fewer when quantiles coincide.
+pub fn bucket_lengths(lengths: &[usize], count: usize) -> Vec<usize> {
+    let mut sorted = lengths.to_vec();
+    sorted.sort_unstable();
+    let mut buckets: Vec<usize> = (1..=count.max(1))
+        .filter_map(|bucket| {
+            let rank = (bucket * sorted.len()).div_ceil(count.max(1));
+            sorted.get(rank.checked_sub(1)?).copied()
+        })
+        .collect();
+    buckets.dedup();
+    buckets
+}
+
+/// Index of the bucket a sequence of `length` frames goes to, the last one when none fits.
+pub fn bucket_of(buckets: &[usize], length: usize) -> usize {
+    buckets
+        .iter()
+        .position(|bucket| length <= *bucket)
+        .unwrap_or(buckets.len().saturating_sub(1))
+}
+
+/// Padding of a bucket and batch size configuration.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PaddingReport {
+    pub batch_size: usize,
+    pub buckets: Vec<usize>,
+    pub batches: usize,
+    /// Frames of the sequences.
+    pub frames: usize,
+    /// Frames of the batches, padding included.
+    pub padded_frames: usize,
+}
+
+impl PaddingReport {
+    pub fn new(lengths: &[usize], buckets: &[usize], batch_size: usize) -> Self {
+        let mut sequence_counts = vec![0usize; buckets.len()];
+        for &length in lengths {
+            sequence_counts[bucket_of(buckets, length)] += 1;
+        }
+        let batch_counts: Vec<usize> = sequence_counts
+            .iter()
+            .map(|count| count.div_ceil(batch_size.max(1)))
+            .collect();
+        PaddingReport {
+            batch_size,
+            buckets: buckets.to_vec(),
+            batches: batch_counts.iter().sum(),
+            frames: lengths.iter().sum(),
+            padded_frames: batch_counts
+                .iter()
+                .zip(buckets)
+                .map(|(batches, bucket)| batches * batch_size * bucket)
+                .sum(),
+        }
+    }
+
+    /// Fraction of the frames of the batches that are padding.
+    pub fn waste(&self) -> f32 {
+        if self.padded_frames == 0 {
+            return 0.0;
+        }
+        1.0 - self.frames as f32 / self.padded_frames as f32
+    }
+}
+
+/// The bucket of a sequence.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BucketAssignment {
+    pub clip: String,
+    pub start: usize,
+    pub frames: usize,
+    pub bucket: usize,
+    /// Length the sequence is padded to.
+    pub bucket_length: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buckets_cut_padding() {
+        assert_eq!(
+            clip_sequences(250, Some(100)),
+            [(0, 100), (100, 100), (200, 50)]
+        );
+        assert_eq!(clip_sequences(40, None), [(0, 40)]);
+
+        let lengths = [10, 10, 10, 20, 20, 100];
+        assert_eq!(bucket_lengths(&lengths, 1), [100]);
+        assert_eq!(bucket_lengths(&lengths, 3), [10, 20, 100]);
+        assert_eq!(bucket_lengths(&lengths, 6), [10, 20, 100]);
+        assert_eq!(bucket_of(&[10, 20, 100], 15), 1);
+        assert_eq!(bucket_of(&[10, 20], 30), 1);
+
+        let single = PaddingReport::new(&lengths, &[100], 2);
+        assert_eq!(single.batches, 3);
+        assert_eq!(single.padded_frames, 600);
+        assert_eq!(single.frames, 170);
+        assert!((single.waste() - (1.0 - 170.0 / 600.0)).abs() < 1e-6);
+        let bucketed = PaddingReport::new(&lengths, &[10, 20, 100], 2);
+        // 10 10 | 10 pad | 20 20 | 100 pad
+        assert_eq!(bucketed.batches, 4);
+        assert_eq!(bucketed.padded_frames, 20 + 20 + 40 + 200);
+        assert!(bucketed.waste() < single.waste());
+    }
+}
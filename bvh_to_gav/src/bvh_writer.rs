@@ -0,0 +1,209 @@
+use std::io::{self, Write};
+
+use anyhow::{Result, bail};
+use bevy_math::{EulerRot, Vec3};
+use ndarray::Array3;
+
+use crate::{Animation, gav_to_animation, skeleton::Skeleton};
+
+/// Writes `animation` as a BVH file. Root joints get position and rotation channels, all other
+/// joints rotation channels only. Rotations are written in ZXY order.
+pub fn write_bvh<W: Write>(
+    writer: &mut W,
+    skeleton: &Skeleton,
+    animation: &Animation,
+    frame_time: f32,
+) -> io::Result<()> {
+    if skeleton.joint_count() != animation.joint_count() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Skeleton has {} joints but the animation has {} rotation tracks",
+                skeleton.joint_count(),
+                animation.joint_count()
+            ),
+        ));
+    }
+
+    writeln!(writer, "HIERARCHY")?;
+    for root in skeleton.roots() {
+        write_joint(writer, skeleton, root, 0)?;
+    }
+
+    let order = skeleton.depth_first_order();
+    writeln!(writer, "MOTION")?;
+    writeln!(writer, "Frames: {}", animation.frame_count())?;
+    writeln!(writer, "Frame Time: {:.6}", frame_time)?;
+    for frame in 0..animation.frame_count() {
+        let mut values = Vec::with_capacity(3 + order.len() * 3);
+        for &joint_index in &order {
+            if skeleton.joints[joint_index].parent.is_none() {
+                let position = animation.root_positions[frame];
+                values.extend([position.x, position.y, position.z]);
+            }
+            let (z, x, y) = animation.joint_rotations[joint_index][frame].to_euler(EulerRot::ZXY);
+            values.extend([z.to_degrees(), x.to_degrees(), y.to_degrees()]);
+        }
+        let line: Vec<String> = values.iter().map(|v| format!("{:.6}", v)).collect();
+        writeln!(writer, "{}", line.join(" "))?;
+    }
+    Ok(())
+}
+
+/// Writes a GAV tensor, for example one generated by a model, as a BVH file for `skeleton`.
+/// The curves have to follow [`Skeleton::joint_order`], extra curves after the joints are
+/// ignored.
+pub fn gav_to_bvh<W: Write>(
+    writer: &mut W,
+    gav_data: Array3<f32>,
+    skeleton: &Skeleton,
+    frame_time: f32,
+) -> Result<()> {
+    let mut animation = gav_to_animation(gav_data)?;
+    if animation.joint_count() < skeleton.joint_count() {
+        bail!(
+            "Skeleton has {} joints but the tensor only has {} joint curves",
+            skeleton.joint_count(),
+            animation.joint_count()
+        );
+    }
+    animation.joint_rotations.truncate(skeleton.joint_count());
+    write_bvh(writer, skeleton, &animation, frame_time)?;
+    Ok(())
+}
+
+fn write_joint<W: Write>(
+    writer: &mut W,
+    skeleton: &Skeleton,
+    index: usize,
+    depth: usize,
+) -> io::Result<()> {
+    let indent = "\t".repeat(depth);
+    let joint = &skeleton.joints[index];
+    if joint.parent.is_none() {
+        writeln!(writer, "{}ROOT {}", indent, joint.name)?;
+    } else {
+        writeln!(writer, "{}JOINT {}", indent, joint.name)?;
+    }
+    writeln!(writer, "{}{{", indent)?;
+    write_offset(writer, &indent, joint.offset)?;
+    if joint.parent.is_none() {
+        writeln!(
+            writer,
+            "{}\tCHANNELS 6 Xposition Yposition Zposition Zrotation Xrotation Yrotation",
+            indent
+        )?;
+    } else {
+        writeln!(
+            writer,
+            "{}\tCHANNELS 3 Zrotation Xrotation Yrotation",
+            indent
+        )?;
+    }
+
+    let mut has_children = false;
+    for child in skeleton.children(index) {
+        has_children = true;
+        write_joint(writer, skeleton, child, depth + 1)?;
+    }
+    // BVH requires every leaf joint to terminate in an end site.
+    if !has_children {
+        writeln!(writer, "{}\tEnd Site", indent)?;
+        writeln!(writer, "{}\t{{", indent)?;
+        write_offset(
+            writer,
+            &format!("{}\t", indent),
+            joint.end_site.unwrap_or(Vec3::ZERO),
+        )?;
+        writeln!(writer, "{}\t}}", indent)?;
+    }
+    writeln!(writer, "{}}}", indent)
+}
+
+fn write_offset<W: Write>(writer: &mut W, indent: &str, offset: Vec3) -> io::Result<()> {
+    writeln!(
+        writer,
+        "{}\tOFFSET {:.6} {:.6} {:.6}",
+        indent, offset.x, offset.y, offset.z
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::skeleton::SkeletonJoint;
+    use bevy_math::Quat;
+
+    fn skeleton() -> Skeleton {
+        Skeleton {
+            joints: vec![
+                SkeletonJoint {
+                    name: "Hips".to_string(),
+                    parent: None,
+                    offset: Vec3::ZERO,
+                    end_site: None,
+                },
+                SkeletonJoint {
+                    name: "Head".to_string(),
+                    parent: Some(0),
+                    offset: Vec3::Y,
+                    end_site: Some(Vec3::Y),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_write_bvh_single_frame() {
+        let skeleton = skeleton();
+        let animation = Animation {
+            root_positions: vec![Vec3::new(1.0, 2.0, 3.0)],
+            joint_rotations: vec![
+                vec![Quat::IDENTITY],
+                vec![Quat::from_rotation_y(90f32.to_radians())],
+            ],
+            events: vec![],
+        };
+
+        let mut output = Vec::new();
+        write_bvh(&mut output, &skeleton, &animation, 1.0 / 30.0).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.starts_with("HIERARCHY\nROOT Hips\n"));
+        assert!(output.contains("\tJOINT Head\n"));
+        assert_eq!(output.matches("End Site").count(), 1);
+        assert!(output.contains("Frames: 1\n"));
+        let last_line = output.lines().last().unwrap();
+        let values: Vec<f32> = last_line.split(' ').map(|v| v.parse().unwrap()).collect();
+        assert_eq!(values.len(), 9);
+        assert_eq!(&values[..3], &[1.0, 2.0, 3.0]);
+        assert!((values[8] - 90.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_gav_to_bvh_round_trip() {
+        let rotation = Quat::from_rotation_x(30f32.to_radians());
+        let animation = Animation {
+            root_positions: vec![Vec3::ZERO, Vec3::X],
+            joint_rotations: vec![vec![Quat::IDENTITY; 2], vec![rotation, rotation.inverse()]],
+            events: vec![],
+        };
+        let gav_data = crate::animation_to_gav(&animation).unwrap();
+
+        let mut output = Vec::new();
+        gav_to_bvh(&mut output, gav_data, &skeleton(), 1.0 / 30.0).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains("Frames: 2\n"));
+        let frames: Vec<Vec<f32>> = output
+            .lines()
+            .rev()
+            .take(2)
+            .map(|line| line.split(' ').map(|v| v.parse().unwrap()).collect())
+            .collect();
+        // Last frame first: root at X, head rotated back by 30 degrees about X.
+        assert_eq!(&frames[0][..3], &[1.0, 0.0, 0.0]);
+        assert!((frames[0][7] + 30.0).abs() < 1e-3);
+        assert!((frames[1][7] - 30.0).abs() < 1e-3);
+    }
+}
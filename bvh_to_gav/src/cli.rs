@@ -0,0 +1,705 @@
+//! Command line of `bvh_to_gav`. Every command is a subcommand, and the exit code tells a
+//! script what happened: 0 when the command succeeded, 1 when it failed, 2 when its arguments
+//! were invalid and 3 when a check such as `validate` or `verify` found problems.
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use bvh_to_gav::{
+    augment::Augmentation,
+    contacts::{DEFAULT_CONTACT_HEIGHT, DEFAULT_CONTACT_SPEED},
+    convention::CoordinateConvention,
+    conversion_settings::ConversionSettings,
+    derivatives::Differencing,
+    dtype::Dtype,
+    folds::FoldGrouping,
+    joint_map::JointMap,
+    joint_sets::JointSet,
+    manifest::MetadataFilter,
+    masking::MaskStrategy,
+    mirror::MirrorMap,
+    model_tag::ModelTag,
+    noise_sweep::NoiseSchedule,
+    normalization::NormalizationMode,
+    reference_motion::ReferenceFormat,
+    round_trip::DEFAULT_TOLERANCE,
+    smoothing::{JointSmoothing, Smoothing},
+    storage,
+    storage::WriteOptions,
+    windows::PositionalEncoding,
+    windows::Windows,
+};
+use clap::{Args, Parser, Subcommand};
+
+pub const EXIT_FAILURE: u8 = 1;
+pub const EXIT_CHECK_FAILED: u8 = 3;
+
+/// Converts BVH motion capture to GAV tensors and prepares datasets of them for training.
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+/// Where a command writes files derived from its inputs.
+#[derive(Args)]
+pub struct OutputArgs {
+    /// Folder to write the outputs to instead of next to the inputs, keeping the folder
+    /// structure below the source. Can be an s3:// or gs:// prefix too.
+    #[arg(long)]
+    pub out_dir: Option<PathBuf>,
+    /// Replaces outputs that already exist instead of skipping their inputs.
+    #[arg(long)]
+    pub overwrite: bool,
+}
+
+impl OutputArgs {
+    /// Output of `input`, found below `root`, with its extension replaced by `extension`.
+    pub fn output_path(&self, root: &Path, input: &Path, extension: &str) -> PathBuf {
+        let output = match &self.out_dir {
+            Some(out_dir) => match input.strip_prefix(root) {
+                Ok(relative) if !relative.as_os_str().is_empty() => out_dir.join(relative),
+                _ => out_dir.join(input.file_name().unwrap_or_default()),
+            },
+            None => input.to_path_buf(),
+        };
+        output.with_extension(extension)
+    }
+}
+
+fn load_joint_map(path: &str) -> Result<JointMap> {
+    JointMap::load(Path::new(path))
+}
+
+fn load_mirror_map(path: &str) -> Result<MirrorMap> {
+    MirrorMap::load(Path::new(path))
+}
+
+fn size(value: &str) -> Result<usize> {
+    storage::parse_size(value)
+}
+
+fn positive(value: &str) -> Result<f32> {
+    match value.parse::<f32>() {
+        Ok(value) if value > 0.0 => Ok(value),
+        _ => anyhow::bail!("Expected a positive number, got {}", value),
+    }
+}
+
+/// How converted files are written to local storage, for network filesystems where writing
+/// is the bottleneck.
+#[derive(Args)]
+pub struct WriteArgs {
+    /// Bytes buffered per file, like 64K or 8M.
+    #[arg(long, value_parser = size, default_value = "8K")]
+    pub write_buffer: usize,
+    /// Writes every file in chunks of this size, --write-threads of them at a time.
+    #[arg(long, value_parser = size, default_value = "8M")]
+    pub write_chunk: usize,
+    #[arg(long, default_value_t = 1)]
+    pub write_threads: usize,
+    /// Bypasses the page cache, on Linux. Needs a chunk size that's a multiple of 4K.
+    #[arg(long)]
+    pub direct_io: bool,
+}
+
+impl WriteArgs {
+    pub fn options(&self) -> WriteOptions {
+        WriteOptions {
+            buffer_size: self.write_buffer,
+            chunk_size: self.write_chunk,
+            threads: self.write_threads,
+            direct_io: self.direct_io,
+        }
+    }
+}
+
+/// Model a clip was generated by, added to the file name and metadata of the decoded clip.
+#[derive(Args)]
+pub struct TagArgs {
+    #[arg(long)]
+    pub model: Option<String>,
+    #[arg(long, requires = "model")]
+    pub checkpoint: Option<String>,
+    /// Training step of the checkpoint.
+    #[arg(long, requires = "model")]
+    pub step: Option<u64>,
+}
+
+impl TagArgs {
+    pub fn tag(&self) -> Option<ModelTag> {
+        Some(ModelTag {
+            model: self.model.clone()?,
+            checkpoint: self.checkpoint.clone(),
+            step: self.step,
+        })
+    }
+}
+
+/// Options of the folder conversion.
+#[derive(Args)]
+pub struct ConvertArgs {
+    /// Folder of BVH clips, or an s3:// or gs:// prefix when built with object-store support.
+    /// Can be a .zip, .tar or .tar.gz archive too, whose clips are all converted into a folder
+    /// named after it unless --out-dir is given.
+    pub source_folder: PathBuf,
+    /// Converts the clips of the subfolders too.
+    #[arg(long)]
+    pub recursive: bool,
+    #[command(flatten)]
+    pub output: OutputArgs,
+    #[command(flatten)]
+    pub write: WriteArgs,
+    /// Frame rate to resample clips at another rate to, interpolating root positions linearly
+    /// and joint rotations spherically.
+    #[arg(long, value_parser = positive)]
+    pub fps: Option<f32>,
+    /// Smooths clips before encoding them, with lowpass:CUTOFF_HZ or savgol:WINDOW[:ORDER].
+    /// Rotations are filtered as rotations, see `smoothing`.
+    #[arg(long)]
+    pub smooth: Option<Smoothing>,
+    /// Filter of a joint instead of the --smooth one, as JOINT=FILTER. Can be repeated.
+    #[arg(
+        long = "smooth-joint",
+        value_name = "JOINT=FILTER",
+        requires = "smooth"
+    )]
+    pub smooth_joints: Vec<JointSmoothing>,
+    /// Appends velocity and acceleration channels, by central, forward or backward differences.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "central")]
+    pub derivatives: Option<Differencing>,
+    /// Joints to encode, renamed and in a fixed order, from a joint map TOML file.
+    #[arg(long = "joints", value_name = "MAP", value_parser = load_joint_map)]
+    pub joint_map: Option<JointMap>,
+    /// Standard joint set to convert every rig to, smpl22, mixamo or cmu31, so all tensors have
+    /// the same joints. Joints are found by their usual names, see `joint_sets`.
+    #[arg(long, conflicts_with = "joint_map")]
+    pub joint_set: Option<JointSet>,
+    /// Convention of the source clips, converted to the Y-up centimeter default.
+    #[arg(long, default_value_t)]
+    pub convention: CoordinateConvention,
+    /// Training windows to write the start frames and rotation hemispheres of, as
+    /// LENGTH[:STRIDE].
+    #[arg(long)]
+    pub windows: Option<Windows>,
+    /// Positional encoding to write for every window, index or sinusoidal[:DIMENSIONS].
+    #[arg(long, requires = "windows")]
+    pub positional_encoding: Option<PositionalEncoding>,
+    /// Also writes every window as a tensor of its own, `<clip>_w<index>.npy`, listed with the
+    /// clip and frames it came from in `window_manifest.json`.
+    #[arg(long, requires = "windows", conflicts_with = "npz")]
+    pub split_windows: bool,
+    /// Encodes the pose in place, with the root trajectory as an extra curve.
+    #[arg(long)]
+    pub in_place: bool,
+    /// Moves every clip to start at the origin facing +Z with its lowest joint at y = 0,
+    /// recording the removed transform in the metadata of the tensor for decode to restore,
+    /// see `alignment`.
+    #[arg(long)]
+    pub canonicalize: bool,
+    /// Also writes every clip mirrored left to right, as `<clip>_mirrored.npy`.
+    #[arg(long)]
+    pub mirror: bool,
+    /// Left/right pairs to mirror, instead of the ones found by name. Implies --mirror.
+    #[arg(long, value_name = "PAIRS", value_parser = load_mirror_map)]
+    pub mirror_map: Option<MirrorMap>,
+    /// Normalizes the tensors once every clip is converted, standard or minmax. The statistics
+    /// are computed per output folder, so this doesn't combine with --recursive.
+    #[arg(
+        long,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "standard",
+        conflicts_with = "recursive"
+    )]
+    pub normalize: Option<NormalizationMode>,
+    /// Element type of the tensors, f16, f32 or f64, or i16 or i8 quantized per channel with the
+    /// scales in a `.quant.json` sidecar, see `quantization`.
+    #[arg(long, default_value_t)]
+    pub dtype: Dtype,
+    /// Writes every clip as a .npz archive of named f32 arrays instead of a GAV tensor, see
+    /// `npz`. Extra curves and normalization only exist for tensors.
+    #[arg(long, conflicts_with_all = ["dtype", "derivatives", "in_place", "normalize"])]
+    pub npz: bool,
+}
+
+impl ConvertArgs {
+    /// Extension of the converted clips.
+    pub fn extension(&self) -> &'static str {
+        if self.npz { "npz" } else { "npy" }
+    }
+
+    /// Settings to record for the tensor converted from `source`, none when the conversion
+    /// can't be repeated from them.
+    pub fn settings(&self, source: &Path) -> Option<ConversionSettings> {
+        if self.npz
+            || self.joint_map.is_some()
+            || self.joint_set.is_some()
+            || self.smooth.is_some()
+            || storage::is_remote(source)
+        {
+            return None;
+        }
+        Some(ConversionSettings {
+            source: std::path::absolute(source).ok()?,
+            convention: self.convention,
+            dtype: self.dtype,
+            in_place: self.in_place,
+            canonicalize: self.canonicalize,
+            derivatives: self.derivatives,
+            fps: self.fps,
+        })
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Converts every BVH clip of a folder to a GAV tensor with a skeleton sidecar.
+    Convert(ConvertArgs),
+    /// Converts the BVH clips whose paths are read from stdin, one per line, with the options
+    /// of convert, answering each with a line of JSON on stdout. Runs until stdin is closed,
+    /// keeping what every clip would load again in memory.
+    Serve(ConvertArgs),
+    /// Writes a GAV tensor as a BVH clip. With --model, the model tag is appended to the file
+    /// name of the clip and recorded in its metadata.
+    Decode {
+        input: PathBuf,
+        /// Output clip, by default the input with a .bvh extension.
+        output: Option<PathBuf>,
+        /// BVH clip, exported pose or skeleton sidecar of another tensor to take the skeleton
+        /// and frame time from, instead of the skeleton sidecar of the tensor.
+        #[arg(long)]
+        skeleton: Option<PathBuf>,
+        /// Clamps rotations that are too long to be part of a unit quaternion, as generated
+        /// tensors often have, and prints how much was corrected.
+        #[arg(long)]
+        project: bool,
+        /// Low-passes the curves of generated output where they oscillate faster than this
+        /// many Hz, keeping sharp motion, and prints the jitter before and after.
+        #[arg(long, value_name = "HZ")]
+        deflicker: Option<f32>,
+        #[command(flatten)]
+        options: OutputArgs,
+        #[command(flatten)]
+        tag: TagArgs,
+    },
+    /// Prints the skeleton, length, tensor shape, channel ranges and NaN counts of a BVH clip
+    /// or GAV tensor.
+    Inspect { clip: PathBuf },
+    /// Prints the number of clips, frames, frame rates and skeletons of a dataset.
+    Stats {
+        dataset_folder: PathBuf,
+        #[arg(long)]
+        recursive: bool,
+    },
+    /// Checks every clip of a dataset, or one clip, for invalid values and inconsistent
+    /// skeletons.
+    Validate {
+        path: PathBuf,
+        #[arg(long)]
+        recursive: bool,
+    },
+    /// Checks that clips survive the GAV encoding, or that dataset files match their hashes.
+    #[command(subcommand)]
+    Verify(VerifyCommand),
+    /// Compares a generated clip to a reference clip of the same skeleton, with the mean per
+    /// joint position error, bone length violation, foot skate and angular jerk.
+    Metrics {
+        reference: PathBuf,
+        candidate: PathBuf,
+    },
+    /// Runs an evaluation protocol, a TOML file of clips, windows, alignment, metrics and
+    /// seeds, and writes the versioned results as JSON, see `eval_protocol`.
+    EvalProtocol { protocol: PathBuf, output: PathBuf },
+    /// Extracts a single frame of a clip as a BVH, a JSON pose or a rendered PNG.
+    Pose {
+        clip: PathBuf,
+        frame: usize,
+        output: PathBuf,
+    },
+    /// Renders an animated GIF thumbnail of every clip.
+    Thumbs {
+        source_folder: PathBuf,
+        output_folder: PathBuf,
+    },
+    /// Exports clips of one skeleton as a Blender pose library, with a script importing it.
+    Blender {
+        output_folder: PathBuf,
+        #[arg(required = true)]
+        clips: Vec<PathBuf>,
+        /// Name of the armature.
+        #[arg(long, default_value = "animgen")]
+        name: String,
+    },
+    /// Exports a browsable HTML gallery of a dataset.
+    Gallery {
+        dataset_folder: PathBuf,
+        output_folder: PathBuf,
+        thumbnail_folder: Option<PathBuf>,
+    },
+    /// Finds the frames of a dataset closest to a pose.
+    Search {
+        dataset_folder: PathBuf,
+        query: PathBuf,
+        #[arg(default_value_t = 10)]
+        count: usize,
+    },
+    /// Finds the clips of a dataset whose root path is closest to a query path.
+    Trajectory {
+        dataset_folder: PathBuf,
+        query: PathBuf,
+        #[arg(default_value_t = 10)]
+        count: usize,
+    },
+    /// Warps a clip onto a beat grid and appends the beat phase.
+    Beats {
+        clip: PathBuf,
+        beats: PathBuf,
+        bpm: f32,
+        output: PathBuf,
+    },
+    /// Appends the gaze direction of the head to a clip.
+    Gaze {
+        clip: PathBuf,
+        output: PathBuf,
+        head_joint: Option<String>,
+    },
+    /// Labels the frames where feet touch the ground.
+    Contacts {
+        clip: PathBuf,
+        /// Height above the ground below which a joint can be in contact.
+        #[arg(long, default_value_t = DEFAULT_CONTACT_HEIGHT)]
+        max_height: f32,
+        /// Speed below which a joint can be in contact, per second.
+        #[arg(long, default_value_t = DEFAULT_CONTACT_SPEED)]
+        max_speed: f32,
+        /// Contact joints, the toes and heels found by name when none are given.
+        joints: Vec<String>,
+    },
+    /// Writes the foot contacts, pelvis height and hand targets of every frame of a clip as
+    /// constraints for physics-based trackers, next to the clip.
+    Constraints {
+        clip: PathBuf,
+        /// Height above the ground below which a joint can be in contact.
+        #[arg(long, default_value_t = DEFAULT_CONTACT_HEIGHT)]
+        max_height: f32,
+        /// Speed below which a joint can be in contact, per second.
+        #[arg(long, default_value_t = DEFAULT_CONTACT_SPEED)]
+        max_speed: f32,
+        /// Contact joints, the toes and heels found by name when none are given.
+        joints: Vec<String>,
+    },
+    /// Writes a clip, or every clip of a dataset, as reference motion for physics-based
+    /// controllers: DeepMimic motion JSON for its humanoid3d character, or the npz arrays of
+    /// LaFAN1.
+    Reference {
+        path: PathBuf,
+        output_folder: PathBuf,
+        #[arg(long, default_value_t)]
+        format: ReferenceFormat,
+        /// Marks DeepMimic motion as looping.
+        #[arg(long = "loop")]
+        looping: bool,
+    },
+    /// Writes the 263 HumanML3D features of every frame of a clip, or of every clip of a
+    /// dataset, at 20 fps.
+    Humanml3d {
+        path: PathBuf,
+        output_folder: PathBuf,
+    },
+    /// Writes a clip, or every clip of a dataset, as a temporal pyramid of its tensor at the
+    /// frame rate and every halving of it, `<clip>.pyramid.npz` with a skeleton sidecar, see
+    /// `pyramid`.
+    Pyramid {
+        path: PathBuf,
+        output_folder: PathBuf,
+        /// Number of levels, the clip itself included.
+        #[arg(long, default_value_t = 3)]
+        levels: usize,
+        /// Frame rate of the first level, by default that of the clip.
+        #[arg(long, value_parser = positive)]
+        fps: Option<f32>,
+    },
+    /// Writes the channels of a BVH clip or GAV tensor as CSV, a row per frame with the x, y and
+    /// z of every curve, for plotting in a spreadsheet while debugging a conversion.
+    Csv {
+        clip: PathBuf,
+        /// CSV file to write, by default the clip with a .csv extension.
+        output: Option<PathBuf>,
+        /// Joint whose curve to write, `root` for the root positions. Can be repeated, all
+        /// curves by default.
+        #[arg(long = "joint", value_name = "JOINT")]
+        joints: Vec<String>,
+    },
+    /// Compresses a clip to the keyframes that interpolate every frame within the tolerances,
+    /// as `<clip>.keys.npz` with a skeleton sidecar.
+    Compress {
+        clip: PathBuf,
+        /// Archive to write, by default next to the clip.
+        output: Option<PathBuf>,
+        /// Largest rotation error of a joint in degrees.
+        #[arg(long, default_value_t = 0.5)]
+        angle_tolerance: f32,
+        /// Largest root position error in centimeters.
+        #[arg(long, default_value_t = 0.1)]
+        position_tolerance: f32,
+    },
+    /// Resamples keyframes written by compress to a GAV tensor of every frame.
+    Decompress {
+        input: PathBuf,
+        /// Tensor to write, by default the input with a .npy extension.
+        output: Option<PathBuf>,
+    },
+    /// Builds the kinematic embedding index of a dataset.
+    Embed { dataset_folder: PathBuf },
+    /// Lists the clips of a dataset most similar to one of them.
+    Similar {
+        dataset_folder: PathBuf,
+        clip: String,
+        #[arg(default_value_t = 10)]
+        count: usize,
+    },
+    /// Reads or edits the metadata of a clip.
+    #[command(subcommand)]
+    Meta(MetaCommand),
+    /// Writes a training manifest of the clips whose metadata passes every filter, given as
+    /// key=value, key!=value, key~text or key!~text.
+    Assemble {
+        dataset_folder: PathBuf,
+        manifest: PathBuf,
+        filters: Vec<MetadataFilter>,
+    },
+    /// Writes class balanced sampling weights into a manifest.
+    Balance {
+        manifest: PathBuf,
+        #[arg(default_value = "labels")]
+        key: String,
+        /// Weights every window of LENGTH[:STRIDE] frames instead of every clip.
+        #[arg(long)]
+        windows: Option<Windows>,
+        /// Weights every motion primitive of the manifest instead of every clip, or with
+        /// --windows every window within a primitive.
+        #[arg(long)]
+        segments: bool,
+    },
+    /// Cuts the clips of a manifest into motion primitives at rests and sharp turns, and
+    /// records them in the manifest.
+    Segment {
+        manifest: PathBuf,
+        /// Frames of the shortest primitive.
+        #[arg(long, default_value_t = 15)]
+        min_frames: usize,
+        /// Cuts where the speed drops below this fraction of the mean speed of the clip.
+        #[arg(long, default_value_t = 0.5)]
+        valley: f32,
+        /// Cuts where the root turns by this many degrees.
+        #[arg(long, default_value_t = 60.0)]
+        turn: f32,
+    },
+    /// Assigns the clips of a manifest to cross-validation folds.
+    Folds {
+        manifest: PathBuf,
+        k: usize,
+        #[arg(default_value = "clip")]
+        grouping: FoldGrouping,
+    },
+    /// Scores the difficulty of every clip of a manifest from its speed variance, rotation
+    /// energy and foot contact changes, for curriculum learning from simple to complex clips.
+    Difficulty { manifest: PathBuf },
+    /// Reports how much of the batches of a manifest is padding for every batch size and from
+    /// one up to --buckets length buckets, see `bucketing`.
+    Buckets {
+        manifest: PathBuf,
+        /// Batch size to report on. Can be repeated.
+        #[arg(long = "batch-size", default_value = "32")]
+        batch_sizes: Vec<usize>,
+        #[arg(long, default_value_t = 4)]
+        buckets: usize,
+        /// Splits clips into sequences of at most this many frames.
+        #[arg(long)]
+        max_length: Option<usize>,
+        /// Records the bucket of every sequence in the manifest, with --buckets buckets.
+        #[arg(long)]
+        record: bool,
+    },
+    /// Records the content hash of every clip of a manifest and of its sidecars.
+    Hash { manifest: PathBuf },
+    /// Merges converted datasets into one, with clip ids prefixed by their source.
+    Merge {
+        output_folder: PathBuf,
+        #[arg(required = true)]
+        dataset_folders: Vec<PathBuf>,
+        #[arg(long, value_parser = positive)]
+        fps: Option<f32>,
+    },
+    /// Packs the converted clips of a dataset into one HDF5 file, with a group per clip. Needs
+    /// the hdf5 feature.
+    Hdf5 {
+        dataset_folder: PathBuf,
+        output: PathBuf,
+    },
+    /// Writes the frames of the converted clips of a dataset as rows of one Parquet table, for
+    /// queries with DuckDB or Polars. Needs the parquet feature.
+    Parquet {
+        dataset_folder: PathBuf,
+        output: PathBuf,
+    },
+    /// Computes per-channel normalization statistics of a converted dataset.
+    Normalize {
+        dataset_folder: PathBuf,
+        #[arg(default_value = "standard")]
+        mode: NormalizationMode,
+        /// Normalizes the tensors with the statistics.
+        #[arg(long)]
+        apply: bool,
+    },
+    /// Writes randomly augmented variants of every clip, with jitter:DEGREES, drift:CM,
+    /// crop:FRAMES or speed:MIN:MAX augmentations applied in order.
+    Augment {
+        dataset_folder: PathBuf,
+        output_folder: PathBuf,
+        count: usize,
+        #[arg(required = true)]
+        augmentations: Vec<Augmentation>,
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+    },
+    /// Writes the windows of the tensors of a dataset paired with noised copies at every level
+    /// of a schedule, a comma separated list of sigmas or geometric:MIN:MAX:COUNT, for denoising
+    /// diffusion. The noise is seeded, see `noise_sweep`.
+    Noise {
+        dataset_folder: PathBuf,
+        output_folder: PathBuf,
+        /// Windows as LENGTH[:STRIDE].
+        windows: Windows,
+        schedule: NoiseSchedule,
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+    },
+    /// Writes the windows of the tensors of a dataset paired with masked copies, one for every
+    /// strategy, spans:COUNT:MAX_LENGTH, keyframes:INTERVAL or limbs:PROBABILITY, for
+    /// in-betweening and completion models. The masks are seeded, see `masking`.
+    Mask {
+        dataset_folder: PathBuf,
+        output_folder: PathBuf,
+        /// Windows as LENGTH[:STRIDE].
+        windows: Windows,
+        #[arg(required = true)]
+        strategies: Vec<MaskStrategy>,
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+    },
+    /// Checks that the left/right joint pairs of a dataset mirror consistently.
+    Mirror { dataset_folder: PathBuf },
+    /// Measures the rotational coverage of every joint of a dataset.
+    Coverage { dataset_folder: PathBuf },
+    /// Fits the principal components of the rotations of every joint of a dataset, for the
+    /// joint PCA view of the preview.
+    JointPca { dataset_folder: PathBuf },
+    /// Fits a pose prior to a dataset, or scores clips against it.
+    #[command(subcommand)]
+    Prior(PriorCommand),
+    /// Reports clips at another frame rate than most, and resamples them into a folder.
+    Fps {
+        dataset_folder: PathBuf,
+        output_folder: Option<PathBuf>,
+    },
+    /// Packs a BVH clip, or a tensor with its skeleton sidecar, into a .gav container.
+    Pack { input: PathBuf, output: PathBuf },
+    /// Encodes finger joints compactly, or decodes them again.
+    #[command(subcommand)]
+    Fingers(FingersCommand),
+    /// Retargets a clip to another skeleton.
+    Retarget {
+        clip: PathBuf,
+        target: PathBuf,
+        output: PathBuf,
+        mapping: Option<PathBuf>,
+    },
+    /// Encodes a clip as dual quaternions, or decodes them again.
+    #[command(subcommand)]
+    Dualquat(CodecCommand),
+    /// Encodes a clip in place with its root trajectory, or decodes it again.
+    #[command(subcommand)]
+    Rootmotion(CodecCommand),
+    /// Encodes a clip as an absolute first frame and per-frame deltas, or decodes it again.
+    /// Delta tensors end in `.delta.npy`.
+    #[command(subcommand)]
+    Delta(CodecCommand),
+}
+
+#[derive(Subcommand)]
+pub enum VerifyCommand {
+    /// Encodes every BVH clip of a dataset, or one clip, as a GAV tensor, decodes it again and
+    /// reports how far each joint moved. Fails when a joint moved more than the tolerance, in
+    /// the units of the skeleton.
+    Roundtrip {
+        path: PathBuf,
+        #[arg(long)]
+        recursive: bool,
+        #[arg(long, default_value_t = DEFAULT_TOLERANCE)]
+        tolerance: f32,
+    },
+    /// Checks the files of a manifest against their hashes recorded by `hash`.
+    Integrity { manifest: PathBuf },
+}
+
+#[derive(Subcommand)]
+pub enum MetaCommand {
+    Get {
+        clip: PathBuf,
+        key: Option<String>,
+    },
+    Set {
+        clip: PathBuf,
+        key: String,
+        value: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum FingersCommand {
+    /// Fits the finger axes once to clips of one skeleton, for encoding each of them.
+    Fit {
+        output: PathBuf,
+        #[arg(required = true)]
+        clips: Vec<PathBuf>,
+    },
+    Encode {
+        clip: PathBuf,
+        output: PathBuf,
+        /// Finger axes written by `fingers fit`, fitted to the clip alone when not given.
+        #[arg(long)]
+        fingers: Option<PathBuf>,
+    },
+    Decode {
+        input: PathBuf,
+        reference: PathBuf,
+        output: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum PriorCommand {
+    /// Writes the prior next to the clips of the dataset.
+    Fit {
+        dataset_folder: PathBuf,
+        /// Gaussians per joint, 1 for a plain per-joint Gaussian.
+        #[arg(long, default_value_t = 1)]
+        components: usize,
+    },
+    /// Prints the log likelihood of a clip, or of every clip in a folder.
+    Score {
+        prior: PathBuf,
+        path: PathBuf,
+        /// Flags clips with a frame below this log likelihood.
+        #[arg(long)]
+        threshold: Option<f32>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum CodecCommand {
+    Encode { clip: PathBuf, output: PathBuf },
+    Decode { input: PathBuf, output: PathBuf },
+}
@@ -0,0 +1,84 @@
+//! Projection of decoded model output back onto valid skeletal motion, for
+//! `bvh_to_gav decode --project`. Only the rotations of a GAV tensor can be invalid: bone
+//! lengths come from the skeleton the tensor is decoded with, not from the tensor.
+use bevy_math::Vec3;
+use ndarray::{Array3, Axis};
+
+/// Magnitude of the corrections applied by a projection step.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Correction {
+    /// Number of values that were changed.
+    pub count: usize,
+    pub max: f32,
+    pub mean: f32,
+}
+
+impl Correction {
+    fn from_magnitudes(magnitudes: impl Iterator<Item = f32>) -> Self {
+        let mut count = 0;
+        let mut max: f32 = 0.0;
+        let mut sum = 0.0;
+        for magnitude in magnitudes.filter(|m| *m > 0.0) {
+            count += 1;
+            max = max.max(magnitude);
+            sum += magnitude;
+        }
+        Correction {
+            count,
+            max,
+            mean: if count > 0 { sum / count as f32 } else { 0.0 },
+        }
+    }
+}
+
+impl std::fmt::Display for Correction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} corrected, max {:.6}, mean {:.6}",
+            self.count, self.max, self.mean
+        )
+    }
+}
+
+/// Clamps the rotation bivectors of a GAV tensor to the unit ball. A bivector longer than one
+/// can't be the vector part of a unit quaternion, which generated tensors frequently produce.
+pub fn project_rotations(gav_data: &mut Array3<f32>) -> Correction {
+    let mut magnitudes = Vec::new();
+    for mut curve in gav_data.axis_iter_mut(Axis(0)).skip(1) {
+        for mut value in curve.axis_iter_mut(Axis(0)) {
+            let v = Vec3::new(value[0], value[1], value[2]);
+            let length = v.length();
+            if length > 1.0 {
+                let v = v / length;
+                value[0] = v.x;
+                value[1] = v.y;
+                value[2] = v.z;
+            }
+            magnitudes.push((length - 1.0).max(0.0));
+        }
+    }
+    Correction::from_magnitudes(magnitudes.into_iter())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project_rotations() {
+        let mut gav = Array3::from_shape_vec(
+            (2, 2, 3),
+            vec![5.0, 5.0, 5.0, 5.0, 5.0, 5.0, 0.0, 0.5, 0.0, 0.0, 2.0, 0.0],
+        )
+        .unwrap();
+        let correction = project_rotations(&mut gav);
+
+        // The root positions are left alone.
+        assert_eq!(gav[[0, 0, 0]], 5.0);
+        assert_eq!(gav[[1, 0, 1]], 0.5);
+        assert_eq!(gav[[1, 1, 1]], 1.0);
+        assert_eq!(correction.count, 1);
+        assert_eq!(correction.max, 1.0);
+    }
+}
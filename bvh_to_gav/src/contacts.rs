@@ -0,0 +1,170 @@
+//! Foot contact labels. A contact joint (usually a toe or heel) touches the ground in a frame
+//! when it is close to the ground and barely moving, measured on the global positions from
+//! [`crate::kinematics`]. The ground is the lowest height any contact joint reaches in the clip,
+//! with Y up.
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, bail};
+use bevy_math::Vec3;
+use ndarray::Array2;
+
+use crate::{
+    Animation,
+    derivatives::{Differencing, linear_velocities},
+    kinematics::global_positions,
+    skeleton::Skeleton,
+};
+
+pub const CONTACTS_EXTENSION: &str = "contacts.npy";
+/// Height above the ground below which a joint can be in contact, in skeleton units (usually cm).
+pub const DEFAULT_CONTACT_HEIGHT: f32 = 5.0;
+/// Speed below which a joint can be in contact, in skeleton units per second.
+pub const DEFAULT_CONTACT_SPEED: f32 = 50.0;
+
+/// Path of the contact labels written next to a tensor.
+pub fn contacts_path(tensor: &Path) -> PathBuf {
+    tensor.with_extension(CONTACTS_EXTENSION)
+}
+
+/// Toe and heel joints found by name. Skeletons without them fall back to the feet and ankles.
+/// End sites such as `LeftToe_End` are skipped.
+pub fn default_contact_joints(skeleton: &Skeleton) -> Vec<usize> {
+    let find = |parts: &[&str]| -> Vec<usize> {
+        skeleton
+            .joint_order()
+            .enumerate()
+            .filter(|(_, name)| {
+                let name = name.to_lowercase();
+                !name.ends_with("end") && parts.iter().any(|part| name.contains(part))
+            })
+            .map(|(index, _)| index)
+            .collect()
+    };
+    let joints = find(&["toe", "heel"]);
+    if joints.is_empty() {
+        find(&["foot", "ankle"])
+    } else {
+        joints
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContactConfig {
+    /// Joint indices, one contact channel each in this order.
+    pub joints: Vec<usize>,
+    pub max_height: f32,
+    pub max_speed: f32,
+}
+
+impl ContactConfig {
+    /// Default thresholds on the joints named in `names`, or on
+    /// [`default_contact_joints`] when `names` is empty.
+    pub fn new(skeleton: &Skeleton, names: &[&str]) -> Result<Self> {
+        let joints = if names.is_empty() {
+            default_contact_joints(skeleton)
+        } else {
+            names
+                .iter()
+                .map(|name| match skeleton.find(name) {
+                    Some(index) => Ok(index),
+                    None => bail!("No joint called {}", name),
+                })
+                .collect::<Result<_>>()?
+        };
+        if joints.is_empty() {
+            bail!("No toe, heel or foot joints found, name the contact joints");
+        }
+        Ok(ContactConfig {
+            joints,
+            max_height: DEFAULT_CONTACT_HEIGHT,
+            max_speed: DEFAULT_CONTACT_SPEED,
+        })
+    }
+}
+
+/// Contact labels as a `(frames, joints)` array of 0 and 1, one column per joint of `config`.
+pub fn detect_contacts(
+    skeleton: &Skeleton,
+    animation: &Animation,
+    frame_time: f32,
+    config: &ContactConfig,
+) -> Result<Array2<f32>> {
+    let positions = global_positions(skeleton, animation)?;
+    let trajectories: Vec<Vec<Vec3>> = config
+        .joints
+        .iter()
+        .map(|&joint| {
+            (0..animation.frame_count())
+                .map(|frame| {
+                    Vec3::new(
+                        positions[[joint, frame, 0]],
+                        positions[[joint, frame, 1]],
+                        positions[[joint, frame, 2]],
+                    )
+                })
+                .collect()
+        })
+        .collect();
+    let ground = trajectories
+        .iter()
+        .flatten()
+        .map(|position| position.y)
+        .fold(f32::INFINITY, f32::min);
+
+    let mut contacts = Array2::zeros((animation.frame_count(), config.joints.len()));
+    for (column, trajectory) in trajectories.iter().enumerate() {
+        let velocities = linear_velocities(trajectory, frame_time, Differencing::Central);
+        for (frame, (position, velocity)) in trajectory.iter().zip(&velocities).enumerate() {
+            let touching =
+                position.y - ground <= config.max_height && velocity.length() <= config.max_speed;
+            contacts[[frame, column]] = if touching { 1.0 } else { 0.0 };
+        }
+    }
+    Ok(contacts)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_math::Quat;
+
+    use super::*;
+    use crate::skeleton::SkeletonJoint;
+
+    #[test]
+    fn test_planted_toe_is_in_contact() {
+        let joint = |name: &str, parent, offset| SkeletonJoint {
+            name: name.to_string(),
+            parent,
+            offset,
+            end_site: None,
+        };
+        let skeleton = Skeleton {
+            joints: vec![
+                joint("Hips", None, Vec3::ZERO),
+                joint("LeftToeBase", Some(0), Vec3::new(10.0, -90.0, 0.0)),
+                joint("RightToeBase", Some(0), Vec3::new(-10.0, -90.0, 0.0)),
+                joint("RightToe_End", Some(2), Vec3::Z * 5.0),
+            ],
+        };
+        assert_eq!(default_contact_joints(&skeleton), vec![1, 2]);
+
+        // The hips stand still for three frames, then rise and move quickly, lifting both toes.
+        let root_positions = vec![
+            Vec3::Y * 90.0,
+            Vec3::Y * 90.0,
+            Vec3::Y * 90.0,
+            Vec3::new(0.0, 120.0, 20.0),
+            Vec3::new(0.0, 150.0, 40.0),
+        ];
+        let animation = Animation {
+            joint_rotations: vec![vec![Quat::IDENTITY; root_positions.len()]; 4],
+            root_positions,
+            events: vec![],
+        };
+        let config = ContactConfig::new(&skeleton, &["LeftToeBase"]).unwrap();
+        let contacts = detect_contacts(&skeleton, &animation, 1.0 / 30.0, &config).unwrap();
+        assert_eq!(contacts.dim(), (5, 1));
+        assert_eq!(contacts.column(0).to_vec(), vec![1.0, 1.0, 0.0, 0.0, 0.0]);
+        assert!(ContactConfig::new(&skeleton, &["Tail"]).is_err());
+    }
+}
@@ -0,0 +1,225 @@
+//! Self-describing `.gav` container: the GAV tensor together with the frame time and the
+//! skeleton it was encoded from, so downstream tools don't need to guess the layout.
+//!
+//! All values are little endian:
+//!
+//! | field | type |
+//! |---|---|
+//! | magic | `b"GAV\0"` |
+//! | version | `u32` |
+//! | frame time in seconds | `f32` |
+//! | joint count | `u32` |
+//! | per joint: name length, name | `u32`, UTF-8 bytes |
+//! | per joint: parent, `u32::MAX` for roots | `u32` |
+//! | per joint: offset | 3 × `f32` |
+//! | per joint: has end site, end site | `u8`, 3 × `f32` |
+//! | curve, frame and channel count | 3 × `u32` |
+//! | tensor in row major order | `f32` |
+use std::io::{Read, Write};
+
+use anyhow::{Context, Result, bail};
+use bevy_math::Vec3;
+use ndarray::Array3;
+
+use crate::skeleton::{Skeleton, SkeletonJoint};
+
+pub const GAV_MAGIC: [u8; 4] = *b"GAV\0";
+pub const GAV_VERSION: u32 = 1;
+const NO_PARENT: u32 = u32::MAX;
+
+/// Contents of a `.gav` file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GavFile {
+    pub frame_time: f32,
+    pub skeleton: Skeleton,
+    /// Curves as written by [`crate::bvh_to_gav`], joints in [`Skeleton::joint_order`].
+    pub data: Array3<f32>,
+}
+
+fn write_u32<W: Write>(writer: &mut W, value: u32) -> Result<()> {
+    writer.write_all(&value.to_le_bytes())?;
+    Ok(())
+}
+
+fn write_vec3<W: Write>(writer: &mut W, value: Vec3) -> Result<()> {
+    for v in value.to_array() {
+        writer.write_all(&v.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_f32<R: Read>(reader: &mut R) -> Result<f32> {
+    Ok(f32::from_bits(read_u32(reader)?))
+}
+
+/// Reads `len` bytes, a size taken from the header. The buffer grows with the bytes actually
+/// read, so a corrupt size fails on the end of the file instead of allocating it up front.
+fn read_bytes<R: Read>(reader: &mut R, len: usize) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        bail!(
+            "Truncated GAV file, expected {} more bytes",
+            len - bytes.len()
+        );
+    }
+    Ok(bytes)
+}
+
+fn read_vec3<R: Read>(reader: &mut R) -> Result<Vec3> {
+    Ok(Vec3::new(
+        read_f32(reader)?,
+        read_f32(reader)?,
+        read_f32(reader)?,
+    ))
+}
+
+pub fn write_gav<W: Write>(writer: &mut W, gav: &GavFile) -> Result<()> {
+    let (curve_count, frame_count, channel_count) = gav.data.dim();
+    if curve_count < gav.skeleton.joint_count() + 1 {
+        bail!(
+            "{} curves can't hold the root and {} joints",
+            curve_count,
+            gav.skeleton.joint_count()
+        );
+    }
+
+    writer.write_all(&GAV_MAGIC)?;
+    write_u32(writer, GAV_VERSION)?;
+    writer.write_all(&gav.frame_time.to_le_bytes())?;
+    write_u32(writer, gav.skeleton.joint_count() as u32)?;
+    for joint in &gav.skeleton.joints {
+        write_u32(writer, joint.name.len() as u32)?;
+        writer.write_all(joint.name.as_bytes())?;
+        write_u32(writer, joint.parent.map_or(NO_PARENT, |p| p as u32))?;
+        write_vec3(writer, joint.offset)?;
+        writer.write_all(&[joint.end_site.is_some() as u8])?;
+        write_vec3(writer, joint.end_site.unwrap_or(Vec3::ZERO))?;
+    }
+    for count in [curve_count, frame_count, channel_count] {
+        write_u32(writer, count as u32)?;
+    }
+    for value in gav.data.iter() {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+pub fn read_gav<R: Read>(reader: &mut R) -> Result<GavFile> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != GAV_MAGIC {
+        bail!("Not a GAV file");
+    }
+    let version = read_u32(reader)?;
+    if version != GAV_VERSION {
+        bail!(
+            "Unsupported GAV version {}, expected {}",
+            version,
+            GAV_VERSION
+        );
+    }
+    let frame_time = read_f32(reader)?;
+
+    let joint_count = read_u32(reader)? as usize;
+    let mut joints = Vec::new();
+    for _ in 0..joint_count {
+        let name_length = read_u32(reader)? as usize;
+        let name = read_bytes(reader, name_length)?;
+        let parent = read_u32(reader)?;
+        let offset = read_vec3(reader)?;
+        let mut has_end_site = [0];
+        reader.read_exact(&mut has_end_site)?;
+        let end_site = read_vec3(reader)?;
+        joints.push(SkeletonJoint {
+            name: String::from_utf8(name)?,
+            parent: (parent != NO_PARENT).then_some(parent as usize),
+            offset,
+            end_site: (has_end_site[0] != 0).then_some(end_site),
+        });
+    }
+    // Parents come before their children, as forward kinematics walks the joints in order.
+    if let Some((_, joint)) = joints
+        .iter()
+        .enumerate()
+        .find(|(index, joint)| joint.parent.is_some_and(|parent| parent >= *index))
+    {
+        bail!("Joint {} has an invalid parent", joint.name);
+    }
+
+    let shape = (
+        read_u32(reader)? as usize,
+        read_u32(reader)? as usize,
+        read_u32(reader)? as usize,
+    );
+    let len = shape
+        .0
+        .checked_mul(shape.1)
+        .and_then(|len| len.checked_mul(shape.2))
+        .and_then(|len| len.checked_mul(4))
+        .with_context(|| format!("Invalid tensor shape {:?}", shape))?;
+    let bytes = read_bytes(reader, len)?;
+    let values = bytes
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+        .collect();
+    Ok(GavFile {
+        frame_time,
+        skeleton: Skeleton { joints },
+        data: Array3::from_shape_vec(shape, values)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gav_container_round_trip() {
+        let gav = GavFile {
+            frame_time: 1.0 / 30.0,
+            skeleton: Skeleton {
+                joints: vec![
+                    SkeletonJoint {
+                        name: "Hips".to_string(),
+                        parent: None,
+                        offset: Vec3::ZERO,
+                        end_site: None,
+                    },
+                    SkeletonJoint {
+                        name: "Spine".to_string(),
+                        parent: Some(0),
+                        offset: Vec3::Y * 10.0,
+                        end_site: Some(Vec3::Y),
+                    },
+                ],
+            },
+            data: Array3::from_shape_fn((3, 4, 3), |(c, f, v)| (c * 100 + f * 10 + v) as f32),
+        };
+        let mut bytes = Vec::new();
+        write_gav(&mut bytes, &gav).unwrap();
+        assert_eq!(&bytes[..4], b"GAV\0");
+        assert_eq!(read_gav(&mut bytes.as_slice()).unwrap(), gav);
+
+        // A corrupt tensor shape fails on the end of the file.
+        let shape = bytes.len() - 3 * 4 * 3 * 4 - 3 * 4;
+        bytes[shape..shape + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(read_gav(&mut bytes.as_slice()).is_err());
+
+        bytes[4] = 2;
+        assert!(read_gav(&mut bytes.as_slice()).is_err());
+
+        let mut looped = gav.clone();
+        looped.skeleton.joints[1].parent = Some(1);
+        let mut bytes = Vec::new();
+        write_gav(&mut bytes, &looped).unwrap();
+        let error = read_gav(&mut bytes.as_slice()).unwrap_err();
+        assert!(error.to_string().contains("invalid parent"));
+    }
+}
@@ -0,0 +1,221 @@
+//! Coordinate conventions of motion capture files. Clips are stored Y-up, right-handed and in
+//! centimeters; files exported Z-up, in meters or left-handed are converted to that when they
+//! are read. A convention is written as comma separated parts, in any order and
+//! each optional: `z-up,m,left`.
+use std::{fmt, str::FromStr};
+
+use anyhow::{Error, Result, bail};
+use bevy_math::{Mat3, Quat, Vec3};
+use bvh_anim_parser::types::{BvhData, BvhMetadata};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UpAxis {
+    #[default]
+    Y,
+    /// Z up with Y pointing forward.
+    Z,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LengthUnit {
+    Meters,
+    #[default]
+    Centimeters,
+}
+
+impl LengthUnit {
+    pub fn meters(self) -> f32 {
+        match self {
+            LengthUnit::Meters => 1.0,
+            LengthUnit::Centimeters => 0.01,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Handedness {
+    #[default]
+    Right,
+    /// Mirrored along the forward axis, Z for Y-up and Y for Z-up.
+    Left,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct CoordinateConvention {
+    pub up: UpAxis,
+    pub unit: LengthUnit,
+    pub handedness: Handedness,
+}
+
+impl CoordinateConvention {
+    /// Maps directions of this convention to Y-up, right-handed ones.
+    fn basis(&self) -> Mat3 {
+        let mirror = match (self.handedness, self.up) {
+            (Handedness::Right, _) => Mat3::IDENTITY,
+            (Handedness::Left, UpAxis::Y) => Mat3::from_diagonal(Vec3::new(1.0, 1.0, -1.0)),
+            (Handedness::Left, UpAxis::Z) => Mat3::from_diagonal(Vec3::new(1.0, -1.0, 1.0)),
+        };
+        let up = match self.up {
+            UpAxis::Y => Mat3::IDENTITY,
+            // (x, y, z) to (x, z, -y)
+            UpAxis::Z => Mat3::from_cols(Vec3::X, -Vec3::Z, Vec3::Y),
+        };
+        up * mirror
+    }
+
+    /// Transform from this convention to `target`.
+    pub fn to(&self, target: &CoordinateConvention) -> CoordinateTransform {
+        CoordinateTransform {
+            basis: target.basis().transpose() * self.basis(),
+            scale: self.unit.meters() / target.unit.meters(),
+        }
+    }
+}
+
+impl FromStr for CoordinateConvention {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut convention = CoordinateConvention::default();
+        for part in s.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+            match part.to_ascii_lowercase().as_str() {
+                "y-up" => convention.up = UpAxis::Y,
+                "z-up" => convention.up = UpAxis::Z,
+                "m" | "meters" => convention.unit = LengthUnit::Meters,
+                "cm" | "centimeters" => convention.unit = LengthUnit::Centimeters,
+                "right" | "right-handed" => convention.handedness = Handedness::Right,
+                "left" | "left-handed" => convention.handedness = Handedness::Left,
+                _ => bail!(
+                    "Unknown coordinate convention {}, expected y-up|z-up, m|cm and right|left",
+                    part
+                ),
+            }
+        }
+        Ok(convention)
+    }
+}
+
+impl fmt::Display for CoordinateConvention {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let up = match self.up {
+            UpAxis::Y => "y-up",
+            UpAxis::Z => "z-up",
+        };
+        let unit = match self.unit {
+            LengthUnit::Meters => "m",
+            LengthUnit::Centimeters => "cm",
+        };
+        let handedness = match self.handedness {
+            Handedness::Right => "right",
+            Handedness::Left => "left",
+        };
+        write!(f, "{},{},{}", up, unit, handedness)
+    }
+}
+
+/// Change of basis and scale between two conventions, see [`CoordinateConvention::to`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CoordinateTransform {
+    /// Orthonormal, a reflection when the handedness changes.
+    pub basis: Mat3,
+    pub scale: f32,
+}
+
+impl CoordinateTransform {
+    pub fn is_identity(&self) -> bool {
+        self.basis == Mat3::IDENTITY && self.scale == 1.0
+    }
+
+    pub fn point(&self, point: Vec3) -> Vec3 {
+        self.basis * point * self.scale
+    }
+
+    /// The same rotation expressed in the new basis.
+    pub fn rotation(&self, rotation: Quat) -> Quat {
+        Quat::from_mat3(&(self.basis * Mat3::from_quat(rotation) * self.basis.transpose()))
+    }
+
+    /// Converts parsed BVH data in place, so everything built from it is converted.
+    pub fn apply_to_bvh(&self, bvh_meta: &mut BvhMetadata, bvh_data: &mut BvhData) {
+        if self.is_identity() {
+            return;
+        }
+        let point = |x: f64, y: f64, z: f64| self.point(Vec3::new(x as f32, y as f32, z as f32));
+        for joint in &mut bvh_meta.joints {
+            if let Some(end_site) = &mut joint.endsite {
+                let p = point(end_site.offset.x, end_site.offset.y, end_site.offset.z);
+                (end_site.offset.x, end_site.offset.y, end_site.offset.z) =
+                    (p.x as f64, p.y as f64, p.z as f64);
+            }
+        }
+        let positions = bvh_data
+            .rest_local_positions
+            .iter_mut()
+            .chain(bvh_data.pose_local_positions.iter_mut().flatten());
+        for v in positions {
+            let p = point(v.x, v.y, v.z);
+            (v.x, v.y, v.z) = (p.x as f64, p.y as f64, p.z as f64);
+        }
+        for q in bvh_data.pose_local_rotations.iter_mut().flatten() {
+            let r = self.rotation(Quat::from_xyzw(
+                q.v.x as f32,
+                q.v.y as f32,
+                q.v.z as f32,
+                q.s as f32,
+            ));
+            (q.v.x, q.v.y, q.v.z, q.s) = (r.x as f64, r.y as f64, r.z as f64, r.w as f64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+
+    #[test]
+    fn test_z_up_meters_to_default() {
+        let source: CoordinateConvention = "z-up,m".parse().unwrap();
+        assert_eq!(source.to_string(), "z-up,m,right");
+        let transform = source.to(&CoordinateConvention::default());
+        assert_eq!(transform.scale, 100.0);
+        // Up and forward in Z-up become up and forward in Y-up.
+        assert!(transform.point(Vec3::Z).distance(Vec3::Y * 100.0) < 1e-4);
+        assert!(transform.point(Vec3::Y).distance(-Vec3::Z * 100.0) < 1e-4);
+        // A turn about the up axis stays one.
+        let turn = transform.rotation(Quat::from_rotation_z(FRAC_PI_2));
+        assert!((turn * Vec3::X).distance(-Vec3::Z) < 1e-5);
+        assert!(
+            CoordinateConvention::default()
+                .to(&CoordinateConvention::default())
+                .is_identity()
+        );
+        assert!("x-up".parse::<CoordinateConvention>().is_err());
+    }
+
+    #[test]
+    fn test_handedness_mirrors_rotations() {
+        let left: CoordinateConvention = "left".parse().unwrap();
+        let transform = left.to(&CoordinateConvention::default());
+        assert_eq!(
+            transform.point(Vec3::new(1.0, 2.0, 3.0)),
+            Vec3::new(1.0, 2.0, -3.0)
+        );
+        // Mirroring Z turns rotations about Y the other way.
+        let rotation = Quat::from_rotation_y(0.5);
+        let mirrored = transform.rotation(rotation);
+        let point = Vec3::new(1.0, 0.0, 0.0);
+        assert!(
+            transform
+                .point(rotation * point)
+                .distance(mirrored * transform.point(point))
+                < 1e-5
+        );
+    }
+}
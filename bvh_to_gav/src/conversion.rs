@@ -0,0 +1,118 @@
+//! Outcome of converting a folder of clips, so one broken clip doesn't abort a batch of
+//! thousands. Every clip is converted on its own, failures are collected with their reason, and
+//! the report is written next to the converted tensors for scripts to pick the failures up.
+use std::{
+    any::Any,
+    cell::Cell,
+    panic::{self, UnwindSafe},
+    path::Path,
+    sync::Once,
+};
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+use crate::storage::{self, WriteStats};
+
+/// File name of the report written next to the converted tensors.
+pub const CONVERSION_REPORT_FILE: &str = "conversion.json";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ConversionFailure {
+    pub clip: String,
+    pub reason: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ConversionReport {
+    pub converted: usize,
+    /// Clips whose tensor already existed.
+    pub skipped: usize,
+    pub failed: Vec<ConversionFailure>,
+    /// Files written by the conversion, and how fast.
+    #[serde(default)]
+    pub writes: WriteStats,
+}
+
+impl ConversionReport {
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(serde_json::from_reader(storage::open(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        storage::write_with(path, |writer| {
+            Ok(serde_json::to_writer_pretty(writer, self)?)
+        })
+    }
+
+    /// Records the outcome of converting `clip`.
+    pub fn record(&mut self, clip: &Path, result: Result<()>) {
+        match result {
+            Ok(()) => self.converted += 1,
+            Err(e) => self.failed.push(ConversionFailure {
+                clip: clip.to_string_lossy().into_owned(),
+                reason: format!("{:#}", e),
+            }),
+        }
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
+
+thread_local! {
+    /// Whether the thread is running [`catch_panic`], whose panics are errors, not crashes.
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Installs a panic hook that doesn't print the panics [`catch_panic`] turns into errors, and
+/// prints the others like the hook it replaces. The hook is global, so binaries install it once
+/// at startup, before they start threads.
+pub fn install_panic_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if !CATCHING.with(Cell::get) {
+                hook(info);
+            }
+        }));
+    });
+}
+
+/// Runs `f`, turning a panic into an error. The BVH parser panics on malformed files, and
+/// without this a single one takes down the whole batch. The panic is still printed, unless
+/// [`install_panic_hook`] was called, its message is the error.
+pub fn catch_panic<T>(f: impl FnOnce() -> T + UnwindSafe) -> Result<T> {
+    let catching = CATCHING.replace(true);
+    let result = panic::catch_unwind(f);
+    CATCHING.set(catching);
+    result.map_err(|payload| anyhow!("{}", panic_message(payload.as_ref())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failures_are_recorded_with_their_reason() {
+        install_panic_hook();
+        let mut report = ConversionReport::default();
+        report.record(Path::new("walk.bvh"), catch_panic(|| ()));
+        let parsed: Result<usize> = catch_panic(|| "MOTION".parse::<usize>().unwrap());
+        report.record(Path::new("run.bvh"), parsed.map(|_| ()));
+        let panicked: Result<()> = catch_panic(|| panic!("Unexpected token {}", "OFSET"));
+        report.record(Path::new("jump.bvh"), panicked);
+        assert_eq!(report.converted, 1);
+        assert_eq!(report.failed[0].clip, "run.bvh");
+        assert!(report.failed[0].reason.contains("InvalidDigit"));
+        assert_eq!(report.failed[1].reason, "Unexpected token OFSET");
+    }
+}
@@ -0,0 +1,205 @@
+//! Settings a tensor was converted with, kept in its metadata sidecar, see [`crate::metadata`],
+//! with the BVH clip it came from. The preview shows them for the loaded tensor and converts
+//! the clip again with changed ones, to compare preprocessing choices without a batch run.
+//!
+//! Only conversions that can be repeated from these settings alone record them, so not those
+//! with a joint map or smoothing, and not mirrored variants.
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    alignment::RootAlignment,
+    animation_to_gav, append_curve, bvh_to_animation, check_frame_counts,
+    convention::CoordinateConvention,
+    derivatives::{Differencing, append_motion_channels},
+    dtype::Dtype,
+    frame_rate::{frame_rate, resample_frame_time},
+    load_bvh,
+    metadata::{ClipMetadata, read_metadata, write_metadata},
+    normalization::NormalizationStats,
+    quantization::write_tensor_file,
+    root_motion::extract_root_motion,
+    skeleton::{Skeleton, skeleton_path, write_skeleton_json},
+    storage,
+};
+
+const SOURCE_KEY: &str = "conversion_source";
+const CONVENTION_KEY: &str = "conversion_convention";
+const DTYPE_KEY: &str = "conversion_dtype";
+const IN_PLACE_KEY: &str = "conversion_in_place";
+const CANONICALIZE_KEY: &str = "conversion_canonicalize";
+const DERIVATIVES_KEY: &str = "conversion_derivatives";
+const FPS_KEY: &str = "conversion_fps";
+const KEYS: [&str; 7] = [
+    SOURCE_KEY,
+    CONVENTION_KEY,
+    DTYPE_KEY,
+    IN_PLACE_KEY,
+    CANONICALIZE_KEY,
+    DERIVATIVES_KEY,
+    FPS_KEY,
+];
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConversionSettings {
+    /// The BVH clip.
+    pub source: PathBuf,
+    /// Convention of the clip, which also scales it to centimeters.
+    pub convention: CoordinateConvention,
+    pub dtype: Dtype,
+    /// Root trajectory as an extra curve, with the pose in place.
+    pub in_place: bool,
+    /// Start at the origin facing +Z, see [`crate::alignment`].
+    pub canonicalize: bool,
+    /// Velocity and acceleration curves.
+    pub derivatives: Option<Differencing>,
+    /// Frame rate the clip is resampled to.
+    pub fps: Option<f32>,
+}
+
+impl ConversionSettings {
+    pub fn apply_to(&self, metadata: &mut ClipMetadata) {
+        metadata.set(SOURCE_KEY, &self.source.to_string_lossy());
+        metadata.set(CONVENTION_KEY, &self.convention.to_string());
+        metadata.set(DTYPE_KEY, &self.dtype.to_string());
+        metadata.set(IN_PLACE_KEY, &self.in_place.to_string());
+        metadata.set(CANONICALIZE_KEY, &self.canonicalize.to_string());
+        metadata.set(
+            DERIVATIVES_KEY,
+            &self
+                .derivatives
+                .map(|method| method.to_string())
+                .unwrap_or_default(),
+        );
+        metadata.set(
+            FPS_KEY,
+            &self.fps.map(|fps| fps.to_string()).unwrap_or_default(),
+        );
+    }
+
+    /// Removes recorded settings from `metadata`, for tensors they no longer describe.
+    pub fn clear(metadata: &mut ClipMetadata) {
+        for key in KEYS {
+            metadata.set(key, "");
+        }
+    }
+
+    /// The settings in `metadata`, `None` for tensors that didn't record them.
+    pub fn from_metadata(metadata: &ClipMetadata) -> Result<Option<Self>> {
+        let Some(source) = metadata.get(SOURCE_KEY) else {
+            return Ok(None);
+        };
+        let parse_error = |key: &str| format!("Invalid {} in the conversion settings", key);
+        Ok(Some(ConversionSettings {
+            source: PathBuf::from(source),
+            convention: metadata
+                .get(CONVENTION_KEY)
+                .unwrap_or_default()
+                .parse()
+                .with_context(|| parse_error(CONVENTION_KEY))?,
+            dtype: match metadata.get(DTYPE_KEY) {
+                Some(dtype) => dtype.parse().with_context(|| parse_error(DTYPE_KEY))?,
+                None => Dtype::default(),
+            },
+            in_place: metadata.get(IN_PLACE_KEY).as_deref() == Some("true"),
+            canonicalize: metadata.get(CANONICALIZE_KEY).as_deref() == Some("true"),
+            derivatives: metadata
+                .get(DERIVATIVES_KEY)
+                .map(|method| method.parse())
+                .transpose()
+                .with_context(|| parse_error(DERIVATIVES_KEY))?,
+            fps: metadata
+                .get(FPS_KEY)
+                .map(|fps| fps.parse())
+                .transpose()
+                .with_context(|| parse_error(FPS_KEY))?,
+        }))
+    }
+
+    /// Converts the source clip again into `tensor`, with its skeleton sidecar, normalized
+    /// when the tensor it replaces was, and records the settings in its metadata.
+    pub fn convert(&self, tensor: &Path) -> Result<()> {
+        let (mut bvh_meta, mut bvh_data) = load_bvh(&self.source)?;
+        check_frame_counts(&bvh_meta, &bvh_data)?;
+        self.convention
+            .to(&CoordinateConvention::default())
+            .apply_to_bvh(&mut bvh_meta, &mut bvh_data);
+        let mut frame_time = bvh_meta.frame_time as f32;
+        let skeleton = Skeleton::from_bvh(&bvh_meta, &bvh_data);
+        let mut animation = bvh_to_animation(&bvh_data, bvh_meta.num_frames);
+        match self.fps {
+            Some(fps) if frame_rate(frame_time) != fps => {
+                animation = resample_frame_time(&animation, frame_time, 1.0 / fps);
+                frame_time = 1.0 / fps;
+            }
+            _ => {}
+        }
+        let alignment = self
+            .canonicalize
+            .then(|| RootAlignment::fit(&skeleton, &animation));
+        if let Some(alignment) = &alignment {
+            alignment.remove(&mut animation);
+        }
+        let mut gav_tensor = if self.in_place {
+            let trajectory;
+            (animation, trajectory) = extract_root_motion(&animation);
+            append_curve(&animation_to_gav(&animation)?, &trajectory)?
+        } else {
+            animation_to_gav(&animation)?
+        };
+        if let Some(method) = self.derivatives {
+            gav_tensor = append_motion_channels(&gav_tensor, &animation, frame_time, method)?;
+        }
+        if let Some(stats) = NormalizationStats::applied_to(tensor)? {
+            stats.normalize(&mut gav_tensor)?;
+        }
+
+        write_tensor_file(tensor, &gav_tensor, self.dtype)?;
+        storage::write_with(&skeleton_path(tensor), |writer| {
+            write_skeleton_json(writer, &skeleton, frame_time)
+        })?;
+        let mut metadata = read_metadata(tensor)?;
+        self.apply_to(&mut metadata);
+        match &alignment {
+            Some(alignment) => alignment.apply_to(&mut metadata),
+            None => RootAlignment::clear(&mut metadata),
+        }
+        write_metadata(tensor, &metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings_round_trip_through_metadata() {
+        let settings = ConversionSettings {
+            source: PathBuf::from("clips/walk.bvh"),
+            convention: "z-up,m".parse().unwrap(),
+            dtype: Dtype::F16,
+            in_place: true,
+            canonicalize: true,
+            derivatives: Some(Differencing::Forward),
+            fps: Some(30.0),
+        };
+        let mut metadata = ClipMetadata::default();
+        assert_eq!(ConversionSettings::from_metadata(&metadata).unwrap(), None);
+        settings.apply_to(&mut metadata);
+        assert_eq!(
+            ConversionSettings::from_metadata(&metadata).unwrap(),
+            Some(settings.clone())
+        );
+
+        let defaults = ConversionSettings {
+            source: settings.source,
+            ..Default::default()
+        };
+        defaults.apply_to(&mut metadata);
+        assert_eq!(
+            ConversionSettings::from_metadata(&metadata).unwrap(),
+            Some(defaults)
+        );
+    }
+}
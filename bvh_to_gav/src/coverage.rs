@@ -0,0 +1,206 @@
+//! Rotational coverage of the joints of a dataset, to spot joints it barely exercises before
+//! training on it. The local rotations of a joint over every frame of every clip are measured
+//! relative to their mean, as scaled axis vectors, and the range of each axis is taken between
+//! the 2nd and 98th percentile so a few glitched frames don't count as coverage.
+use std::{fs::File, io::BufWriter, path::Path};
+
+use anyhow::{Context, Result, bail};
+use bevy_math::{Quat, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::{Animation, skeleton::Skeleton};
+
+/// File name of the report written next to the clips of a dataset.
+pub const COVERAGE_REPORT_FILE: &str = "coverage.json";
+/// Joints covering less than this fraction of the best covered joint are reported as low.
+pub const LOW_COVERAGE: f32 = 0.1;
+const PERCENTILE: f32 = 0.02;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct JointCoverage {
+    pub joint: String,
+    /// Range of the rotation about the local X, Y and Z axis, in degrees.
+    pub axis_ranges: [f32; 3],
+    /// Largest rotation away from the mean, in degrees.
+    pub max_angle: f32,
+}
+
+impl JointCoverage {
+    /// Sum of the axis ranges, in degrees.
+    pub fn coverage(&self) -> f32 {
+        self.axis_ranges.iter().sum()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CoverageReport {
+    pub clip_count: usize,
+    pub frame_count: usize,
+    /// Coverage of every joint of the dataset skeleton, in joint order.
+    pub joints: Vec<JointCoverage>,
+}
+
+/// Value at `fraction` of the sorted `values`.
+fn percentile(sorted: &[f32], fraction: f32) -> f32 {
+    let index = (fraction * (sorted.len() - 1) as f32).round() as usize;
+    sorted[index]
+}
+
+/// Average of rotations, all flipped into the hemisphere of the first.
+pub(crate) fn mean_rotation(rotations: &[Quat]) -> Quat {
+    let first = rotations.first().copied().unwrap_or(Quat::IDENTITY);
+    let sum = rotations
+        .iter()
+        .fold(Quat::from_xyzw(0.0, 0.0, 0.0, 0.0), |sum, &q| {
+            sum + if q.dot(first) < 0.0 { -q } else { q }
+        });
+    if sum.length_squared() > f32::EPSILON {
+        sum.normalize()
+    } else {
+        first
+    }
+}
+
+impl CoverageReport {
+    /// Measures the coverage of `animations`, which all have to be of `skeleton`.
+    pub fn new(skeleton: &Skeleton, animations: &[Animation]) -> Result<Self> {
+        if let Some(animation) = animations
+            .iter()
+            .find(|animation| animation.joint_count() != skeleton.joint_count())
+        {
+            bail!(
+                "An animation has {} joints, the skeleton {}",
+                animation.joint_count(),
+                skeleton.joint_count()
+            );
+        }
+        let frame_count: usize = animations.iter().map(Animation::frame_count).sum();
+        if frame_count == 0 {
+            bail!("No frames to measure");
+        }
+
+        let joints = skeleton
+            .joint_order()
+            .enumerate()
+            .map(|(joint, name)| {
+                let rotations: Vec<Quat> = animations
+                    .iter()
+                    .flat_map(|animation| animation.joint_rotations[joint].iter().copied())
+                    .collect();
+                let mean = mean_rotation(&rotations);
+                let offsets: Vec<Vec3> = rotations
+                    .iter()
+                    .map(|&q| {
+                        let relative = mean.inverse() * q;
+                        let relative = if relative.w < 0.0 {
+                            -relative
+                        } else {
+                            relative
+                        };
+                        relative.to_scaled_axis()
+                    })
+                    .collect();
+
+                let axis_ranges = [0, 1, 2].map(|axis| {
+                    let mut values: Vec<f32> = offsets.iter().map(|o| o[axis]).collect();
+                    values.sort_by(f32::total_cmp);
+                    (percentile(&values, 1.0 - PERCENTILE) - percentile(&values, PERCENTILE))
+                        .to_degrees()
+                });
+                let max_angle = offsets
+                    .iter()
+                    .map(|o| o.length())
+                    .fold(0.0, f32::max)
+                    .to_degrees();
+                JointCoverage {
+                    joint: name.to_string(),
+                    axis_ranges,
+                    max_angle,
+                }
+            })
+            .collect();
+        Ok(CoverageReport {
+            clip_count: animations.len(),
+            frame_count,
+            joints,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("Could not open {}", path.display()))?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), self)?;
+        Ok(())
+    }
+
+    pub fn find(&self, joint: &str) -> Option<&JointCoverage> {
+        self.joints.iter().find(|coverage| coverage.joint == joint)
+    }
+
+    /// Coverage of `joint` as a fraction of the best covered joint.
+    pub fn normalized(&self, joint: &JointCoverage) -> f32 {
+        let best = self
+            .joints
+            .iter()
+            .map(JointCoverage::coverage)
+            .fold(0.0, f32::max);
+        if best > 0.0 {
+            joint.coverage() / best
+        } else {
+            0.0
+        }
+    }
+
+    /// Joints sorted from the least to the most covered.
+    pub fn ranked(&self) -> Vec<&JointCoverage> {
+        let mut joints: Vec<&JointCoverage> = self.joints.iter().collect();
+        joints.sort_by(|a, b| a.coverage().total_cmp(&b.coverage()));
+        joints
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+    use crate::skeleton::SkeletonJoint;
+
+    #[test]
+    fn test_coverage_of_swinging_joint() {
+        let joint = |name: &str, parent| SkeletonJoint {
+            name: name.to_string(),
+            parent,
+            offset: Vec3::Y,
+            end_site: None,
+        };
+        let skeleton = Skeleton {
+            joints: vec![joint("Hips", None), joint("Knee", Some(0))],
+        };
+        // The knee bends back and forth over 90 degrees about X, the hips hold still.
+        let frames = 101;
+        let animation = Animation {
+            root_positions: vec![Vec3::ZERO; frames],
+            joint_rotations: vec![
+                vec![Quat::IDENTITY; frames],
+                (0..frames)
+                    .map(|f| Quat::from_rotation_x(FRAC_PI_2 * f as f32 / (frames - 1) as f32))
+                    .collect(),
+            ],
+            events: vec![],
+        };
+        let report = CoverageReport::new(&skeleton, &[animation]).unwrap();
+        assert_eq!(report.frame_count, frames);
+        let knee = report.find("Knee").unwrap();
+        assert!((knee.axis_ranges[0] - 86.4).abs() < 0.5, "{:?}", knee);
+        assert!(knee.axis_ranges[1].abs() < 1e-3);
+        assert!((knee.max_angle - 45.0).abs() < 0.5);
+        assert_eq!(report.normalized(knee), 1.0);
+        assert_eq!(report.ranked()[0].joint, "Hips");
+        assert!(CoverageReport::new(&skeleton, &[]).is_err());
+    }
+}
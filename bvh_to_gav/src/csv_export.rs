@@ -0,0 +1,125 @@
+//! CSV dump of the channels of a GAV tensor, for plotting curves in a spreadsheet while
+//! debugging a conversion. Every row is a frame, with its index and time followed by the x, y
+//! and z channels of every selected curve:
+//!
+//! ```text
+//! frame,time,root_x,root_y,root_z,Hips_x,Hips_y,Hips_z
+//! 0,0,0,90,0,0,0.0872,0
+//! ```
+//!
+//! Curves are named after their joint, the root positions `root` and the curves appended after
+//! the joints `curve_<index>`.
+use std::io::Write;
+
+use anyhow::{Result, bail};
+use ndarray::{Array3, Axis};
+
+use crate::skeleton::Skeleton;
+
+pub const ROOT_CURVE: &str = "root";
+const AXES: [&str; 3] = ["x", "y", "z"];
+
+/// Names of the `curve_count` curves of a tensor of `skeleton`.
+pub fn curve_names(skeleton: &Skeleton, curve_count: usize) -> Vec<String> {
+    let mut names = vec![ROOT_CURVE.to_string()];
+    names.extend(skeleton.joint_order().map(String::from));
+    names.truncate(curve_count);
+    names.extend((names.len()..curve_count).map(|index| format!("curve_{}", index)));
+    names
+}
+
+/// Indices of the curves called `selected`, in that order, or of every curve when none are.
+pub fn select_curves(names: &[String], selected: &[String]) -> Result<Vec<usize>> {
+    if selected.is_empty() {
+        return Ok((0..names.len()).collect());
+    }
+    selected
+        .iter()
+        .map(|name| match names.iter().position(|curve| curve == name) {
+            Some(index) => Ok(index),
+            None => bail!("No curve called {}", name),
+        })
+        .collect()
+}
+
+/// Quotes `field` when it holds a separator, quote or line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Writes the `curves` of `data`, named `names`, as CSV.
+pub fn write_channels_csv<W: Write>(
+    mut writer: W,
+    data: &Array3<f32>,
+    names: &[String],
+    curves: &[usize],
+    frame_time: f32,
+) -> Result<()> {
+    let (curve_count, frame_count, channel_count) = data.dim();
+    if names.len() != curve_count {
+        bail!("{} curve names for {} curves", names.len(), curve_count);
+    }
+    if channel_count != AXES.len() {
+        bail!("Expected {} channels, got {}", AXES.len(), channel_count);
+    }
+    let mut header = vec!["frame".to_string(), "time".to_string()];
+    for &curve in curves {
+        header.extend(AXES.map(|axis| csv_field(&format!("{}_{}", names[curve], axis))));
+    }
+    writeln!(writer, "{}", header.join(","))?;
+    for frame in 0..frame_count {
+        let values = data.index_axis(Axis(1), frame);
+        write!(writer, "{},{}", frame, frame as f32 * frame_time)?;
+        for &curve in curves {
+            for channel in 0..channel_count {
+                write!(writer, ",{}", values[[curve, channel]])?;
+            }
+        }
+        writeln!(writer)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy_math::Vec3;
+
+    use crate::skeleton::SkeletonJoint;
+
+    #[test]
+    fn test_selected_curves_are_written_per_frame() {
+        let joint = |name: &str, parent| SkeletonJoint {
+            name: name.to_string(),
+            parent,
+            offset: Vec3::ZERO,
+            end_site: None,
+        };
+        let skeleton = Skeleton {
+            joints: vec![joint("Hips", None), joint("Left, Foot", Some(0))],
+        };
+        let data = Array3::from_shape_fn((4, 2, 3), |(curve, frame, channel)| {
+            (curve * 100 + frame * 10 + channel) as f32
+        });
+        let names = curve_names(&skeleton, 4);
+        assert_eq!(names, ["root", "Hips", "Left, Foot", "curve_3"]);
+        let curves =
+            select_curves(&names, &["Left, Foot".to_string(), "root".to_string()]).unwrap();
+        assert_eq!(curves, [2, 0]);
+        assert!(select_curves(&names, &["Head".to_string()]).is_err());
+
+        let mut csv = Vec::new();
+        write_channels_csv(&mut csv, &data, &names, &curves, 0.5).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "frame,time,\"Left, Foot_x\",\"Left, Foot_y\",\"Left, Foot_z\",root_x,root_y,root_z\n\
+             0,0,200,201,202,0,1,2\n\
+             1,0.5,210,211,212,10,11,12\n"
+        );
+    }
+}
@@ -0,0 +1,270 @@
+//! Temporal cleanup of decoded model output.
+//!
+//! Generated clips often flicker: a curve jumps back and forth between two values on
+//! consecutive frames. Low-passing the whole clip hides that, but also rounds off sharp
+//! intentional motion like impacts, so the filter is only blended in around frames where the
+//! curve keeps oscillating faster than the cutoff frequency. Two direction reversals `k` frames
+//! apart are half a period of an oscillation at `fps / (2 k)` Hz, so only reversals that close
+//! to another one count.
+use bevy_math::{Quat, Vec4};
+
+use crate::Animation;
+
+#[derive(Clone, Debug)]
+pub struct DeflickerSettings {
+    /// Frame rate of the animation.
+    pub fps: f32,
+    /// Cutoff frequency in Hz, of the low-pass filter and above which oscillation is flicker.
+    pub cutoff_hz: f32,
+    /// Width in frames of the window in which direction reversals are counted.
+    pub window: usize,
+    /// A frame flickers when its window contains at least this many reversals.
+    pub min_reversals: usize,
+    /// Frame to frame changes smaller than this are never counted as reversals.
+    pub threshold: f32,
+}
+
+impl Default for DeflickerSettings {
+    fn default() -> Self {
+        DeflickerSettings {
+            fps: 30.0,
+            cutoff_hz: 6.0,
+            window: 6,
+            min_reversals: 3,
+            threshold: 1e-4,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeflickerReport {
+    /// Number of curves in which flicker was detected.
+    pub curves_filtered: usize,
+    /// Total number of flickering frames over all curves.
+    pub flicker_frames: usize,
+    /// Mean magnitude of the second difference before and after filtering.
+    pub jitter_before: f32,
+    pub jitter_after: f32,
+}
+
+/// Low-passes every curve of `animation` where flicker is detected. Root positions and joint
+/// rotations are handled independently.
+pub fn deflicker(animation: &mut Animation, settings: &DeflickerSettings) -> DeflickerReport {
+    let mut report = DeflickerReport::default();
+    let mut jitter_before = Vec::new();
+    let mut jitter_after = Vec::new();
+
+    let mut curves: Vec<Vec<Vec4>> = Vec::with_capacity(animation.joint_count() + 1);
+    curves.push(
+        animation
+            .root_positions
+            .iter()
+            .map(|p| p.extend(0.0))
+            .collect(),
+    );
+    for rotations in &animation.joint_rotations {
+        curves.push(continuous_hemisphere(rotations));
+    }
+
+    for curve in curves.iter_mut() {
+        jitter_before.push(jitter(curve));
+        let weights = flicker_weights(curve, settings);
+        let flicker_frames = weights.iter().filter(|w| **w >= 1.0).count();
+        if flicker_frames > 0 {
+            let filtered = filtfilt(curve, settings.cutoff_hz, settings.fps);
+            for ((value, filtered), weight) in curve.iter_mut().zip(filtered).zip(weights) {
+                *value = value.lerp(filtered, weight);
+            }
+            report.curves_filtered += 1;
+            report.flicker_frames += flicker_frames;
+        }
+        jitter_after.push(jitter(curve));
+    }
+
+    let mut curves = curves.into_iter();
+    if let Some(positions) = curves.next() {
+        animation.root_positions = positions.into_iter().map(|p| p.truncate()).collect();
+    }
+    for (rotations, curve) in animation.joint_rotations.iter_mut().zip(curves) {
+        *rotations = curve
+            .into_iter()
+            .map(|q| Quat::from_vec4(q).normalize())
+            .collect();
+    }
+
+    report.jitter_before = mean(&jitter_before);
+    report.jitter_after = mean(&jitter_after);
+    report
+}
+
+fn continuous_hemisphere(rotations: &[Quat]) -> Vec<Vec4> {
+    let mut previous = Vec4::ZERO;
+    rotations
+        .iter()
+        .map(|q| {
+            let mut q = Vec4::from(*q);
+            if q.dot(previous) < 0.0 {
+                q = -q;
+            }
+            previous = q;
+            q
+        })
+        .collect()
+}
+
+fn mean(values: &[f32]) -> f32 {
+    if values.is_empty() {
+        0.0
+    } else {
+        values.iter().sum::<f32>() / values.len() as f32
+    }
+}
+
+/// Mean magnitude of the second difference of a curve.
+fn jitter(curve: &[Vec4]) -> f32 {
+    let magnitudes: Vec<f32> = curve
+        .windows(3)
+        .map(|w| (w[0] - 2.0 * w[1] + w[2]).length())
+        .collect();
+    mean(&magnitudes)
+}
+
+/// Blend weight of the filtered curve per frame: one on flickering frames, ramping down to zero
+/// over half a window on either side.
+fn flicker_weights(curve: &[Vec4], settings: &DeflickerSettings) -> Vec<f32> {
+    let frame_count = curve.len();
+    let mut reversals = vec![false; frame_count];
+    for t in 1..frame_count.saturating_sub(1) {
+        let before = curve[t] - curve[t - 1];
+        let after = curve[t + 1] - curve[t];
+        reversals[t] = before.dot(after) < 0.0
+            && before.length() > settings.threshold
+            && after.length() > settings.threshold;
+    }
+    // Reversals closer than half a period at the cutoff.
+    let half_period = settings.fps / (2.0 * settings.cutoff_hz.max(f32::EPSILON));
+    let reversal_frames: Vec<usize> = (0..frame_count).filter(|&t| reversals[t]).collect();
+    let mut fast = vec![false; frame_count];
+    for pair in reversal_frames.windows(2) {
+        if ((pair[1] - pair[0]) as f32) < half_period {
+            fast[pair[0]] = true;
+            fast[pair[1]] = true;
+        }
+    }
+    let reversals = fast;
+
+    let half_window = settings.window / 2;
+    let mut weights = vec![0.0f32; frame_count];
+    for t in 0..frame_count {
+        let start = t.saturating_sub(half_window);
+        let end = (t + half_window + 1).min(frame_count);
+        let count = reversals[start..end].iter().filter(|r| **r).count();
+        if count < settings.min_reversals.max(1) {
+            continue;
+        }
+        let ramp = half_window + 1;
+        let start = t.saturating_sub(ramp);
+        let end = (t + ramp).min(frame_count - 1);
+        for (i, weight) in weights.iter_mut().enumerate().take(end + 1).skip(start) {
+            let falloff = 1.0 - i.abs_diff(t) as f32 / ramp as f32;
+            *weight = weight.max(falloff);
+        }
+    }
+    weights
+}
+
+/// Zero-phase second order Butterworth low-pass: the filter is run forward and then backward so
+/// the phase shifts cancel out.
+fn filtfilt(curve: &[Vec4], cutoff_hz: f32, fps: f32) -> Vec<Vec4> {
+    let nyquist = fps / 2.0;
+    let cutoff = cutoff_hz.clamp(f32::EPSILON, nyquist * 0.99);
+    let k = (std::f32::consts::PI * cutoff / fps).tan();
+    let sqrt2 = std::f32::consts::SQRT_2;
+    let norm = 1.0 / (1.0 + sqrt2 * k + k * k);
+    let b0 = k * k * norm;
+    let b1 = 2.0 * b0;
+    let b2 = b0;
+    let a1 = 2.0 * (k * k - 1.0) * norm;
+    let a2 = (1.0 - sqrt2 * k + k * k) * norm;
+
+    let filter = |input: &mut dyn Iterator<Item = Vec4>| -> Vec<Vec4> {
+        let mut output = Vec::with_capacity(curve.len());
+        let mut input = input.peekable();
+        // Start in the steady state of the first sample to avoid a transient at the edges.
+        let first = input.peek().copied().unwrap_or(Vec4::ZERO);
+        let (mut x1, mut x2, mut y1, mut y2) = (first, first, first, first);
+        for x in input {
+            let y = b0 * x + b1 * x1 + b2 * x2 - a1 * y1 - a2 * y2;
+            x2 = x1;
+            x1 = x;
+            y2 = y1;
+            y1 = y;
+            output.push(y);
+        }
+        output
+    };
+
+    let forward = filter(&mut curve.iter().copied());
+    let mut backward = filter(&mut forward.into_iter().rev());
+    backward.reverse();
+    backward
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy_math::Vec3;
+
+    fn animation_from_x(values: impl Iterator<Item = f32>) -> Animation {
+        Animation {
+            root_positions: values.map(|x| Vec3::new(x, 0.0, 0.0)).collect(),
+            joint_rotations: vec![],
+            events: vec![],
+        }
+    }
+
+    #[test]
+    fn test_deflicker_removes_oscillation() {
+        // A slow ramp with an alternating +-0.5 flicker in the middle third.
+        let mut animation = animation_from_x((0..90).map(|i| {
+            let flicker = if (30..60).contains(&i) { 0.5 } else { 0.0 };
+            i as f32 * 0.1 + if i % 2 == 0 { flicker } else { -flicker }
+        }));
+        let report = deflicker(&mut animation, &DeflickerSettings::default());
+
+        assert_eq!(report.curves_filtered, 1);
+        assert!(report.jitter_after < report.jitter_before * 0.5);
+        // Frames far away from the flicker are untouched.
+        assert_eq!(animation.root_positions[5].x, 0.5);
+        assert_eq!(animation.root_positions[85].x, 8.5);
+    }
+
+    #[test]
+    fn test_deflicker_keeps_oscillation_below_the_cutoff() {
+        // 5 Hz at 30 fps reverses every 3 frames, below a 6 Hz cutoff and above a 4 Hz one.
+        let wave = || (0..90).map(|i| (i as f32 * std::f32::consts::PI / 3.0 + 0.3).sin());
+        let mut animation = animation_from_x(wave());
+        let report = deflicker(&mut animation, &DeflickerSettings::default());
+        assert_eq!(report.curves_filtered, 0);
+
+        let settings = DeflickerSettings {
+            cutoff_hz: 4.0,
+            ..Default::default()
+        };
+        let report = deflicker(&mut animation, &settings);
+        assert_eq!(report.curves_filtered, 1);
+        assert!(report.jitter_after < report.jitter_before);
+    }
+
+    #[test]
+    fn test_deflicker_preserves_sharp_motion() {
+        // A single step is an intentional motion, not flicker.
+        let values: Vec<f32> = (0..60).map(|i| if i < 30 { 0.0 } else { 1.0 }).collect();
+        let mut animation = animation_from_x(values.clone().into_iter());
+        let report = deflicker(&mut animation, &DeflickerSettings::default());
+
+        assert_eq!(report.curves_filtered, 0);
+        let positions: Vec<f32> = animation.root_positions.iter().map(|p| p.x).collect();
+        assert_eq!(positions, values);
+    }
+}
@@ -0,0 +1,135 @@
+//! Delta encoding, a GAV layout for autoregressive models that predict the next frame from the
+//! previous one. Frame 0 holds the absolute pose like a plain GAV tensor, every later frame the
+//! change from the frame before it: the root displacement, and per joint the incremental
+//! rotation `delta` with `rotation = previous * delta`, stored like any GAV rotation.
+//!
+//! The deltas are taken from the frames as the decoder rebuilds them rather than from the
+//! original frames, so the rounding of each stored delta is corrected by the next one instead of
+//! adding up over the clip, and decoding reproduces the encoder's own reconstruction exactly.
+//!
+//! Delta tensors are written as `<clip>.delta.npy`, so they are never read as poses:
+//! [`crate::load_gav`] decodes them.
+use std::path::Path;
+
+use anyhow::{Result, bail};
+use bevy_math::{Quat, Vec3};
+use ndarray::Array3;
+
+use crate::{
+    Animation, animation_to_gav, canonical_rotation, gav_to_animation, rotation_from_vector,
+};
+
+/// Extension of delta tensors.
+pub const DELTA_EXTENSION: &str = "delta.npy";
+
+/// Whether `path` is a delta tensor, by its [`DELTA_EXTENSION`].
+pub fn is_delta_path(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.strip_suffix(DELTA_EXTENSION))
+        .is_some_and(|stem| stem.ends_with('.'))
+}
+
+/// The rotation the decoder reads back for a stored `rotation`.
+fn stored(rotation: Quat) -> Quat {
+    rotation_from_vector(canonical_rotation(rotation).xyz())
+}
+
+/// Encodes `animation` as a GAV tensor of deltas.
+pub fn animation_to_delta_gav(animation: &Animation) -> Result<Array3<f32>> {
+    let mut deltas = Animation {
+        root_positions: Vec::with_capacity(animation.frame_count()),
+        joint_rotations: vec![Vec::with_capacity(animation.frame_count()); animation.joint_count()],
+        events: Vec::new(),
+    };
+    let mut root = Vec3::ZERO;
+    for &position in &animation.root_positions {
+        let delta = position - root;
+        deltas.root_positions.push(delta);
+        root += delta;
+    }
+    for (rotations, joint_deltas) in animation
+        .joint_rotations
+        .iter()
+        .zip(&mut deltas.joint_rotations)
+    {
+        let mut previous = Quat::IDENTITY;
+        for &rotation in rotations {
+            let delta = previous.inverse() * rotation;
+            joint_deltas.push(delta);
+            previous = (previous * stored(delta)).normalize();
+        }
+    }
+    Ok(animation_to_gav(&deltas)?)
+}
+
+/// Rebuilds the animation of a tensor written by [`animation_to_delta_gav`].
+pub fn delta_gav_to_animation(gav_data: Array3<f32>) -> Result<Animation> {
+    if gav_data.dim().0 == 0 {
+        bail!("The delta tensor has no root curve");
+    }
+    let mut animation = gav_to_animation(gav_data)?;
+    let mut root = Vec3::ZERO;
+    for position in &mut animation.root_positions {
+        root += *position;
+        *position = root;
+    }
+    for rotations in &mut animation.joint_rotations {
+        let mut previous = Quat::IDENTITY;
+        for rotation in rotations {
+            previous = (previous * *rotation).normalize();
+            *rotation = previous;
+        }
+    }
+    Ok(animation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deltas_decode_without_drift() {
+        let frame_count = 500;
+        let animation = Animation {
+            root_positions: (0..frame_count)
+                .map(|frame| Vec3::new(frame as f32 * 1.3, 90.0 + (frame as f32).sin(), 0.1))
+                .collect(),
+            joint_rotations: vec![
+                // Keeps turning past half a turn, where stored rotations change hemisphere.
+                (0..frame_count)
+                    .map(|frame| Quat::from_rotation_y(frame as f32 * 0.05))
+                    .collect(),
+                (0..frame_count)
+                    .map(|frame| {
+                        Quat::from_euler(bevy_math::EulerRot::ZXY, 0.3, frame as f32 * 0.01, -0.2)
+                    })
+                    .collect(),
+            ],
+            events: Vec::new(),
+        };
+        let gav_data = animation_to_delta_gav(&animation).unwrap();
+        assert_eq!(gav_data.dim(), (3, frame_count, 3));
+        // Frame 0 is absolute, later frames are small steps.
+        assert_eq!(gav_data[[0, 0, 1]], 90.0);
+        assert!((gav_data[[0, 10, 0]] - 1.3).abs() < 1e-4);
+
+        assert!(is_delta_path(Path::new("data/walk.delta.npy")));
+        assert!(!is_delta_path(Path::new("data/walk.npy")));
+        assert!(!is_delta_path(Path::new("data/walkdelta.npy")));
+
+        let decoded = delta_gav_to_animation(gav_data).unwrap();
+        for (decoded, original) in decoded.root_positions.iter().zip(&animation.root_positions) {
+            assert!(decoded.distance(*original) < 1e-3);
+        }
+        for (decoded, original) in decoded
+            .joint_rotations
+            .iter()
+            .zip(&animation.joint_rotations)
+        {
+            for (decoded, original) in decoded.iter().zip(original) {
+                assert!(decoded.angle_between(*original) < 1e-3);
+            }
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! Velocity and acceleration channels computed with finite differences, appended to a GAV
+//! tensor as extra curves so motion models get them without a Python preprocessing step.
+use std::{fmt, str::FromStr};
+
+use anyhow::{Result, bail};
+use bevy_math::{Quat, Vec3};
+use ndarray::Array3;
+
+use crate::{Animation, append_curve};
+
+/// Finite difference scheme. Frames without a neighbor on the needed side fall back to the
+/// one-sided difference towards the other side.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Differencing {
+    /// `(x[t + 1] - x[t]) / dt`
+    Forward,
+    /// `(x[t] - x[t - 1]) / dt`
+    Backward,
+    /// `(x[t + 1] - x[t - 1]) / 2dt`
+    #[default]
+    Central,
+}
+
+impl FromStr for Differencing {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "forward" => Ok(Differencing::Forward),
+            "backward" => Ok(Differencing::Backward),
+            "central" => Ok(Differencing::Central),
+            _ => bail!(
+                "Unknown differencing {}, expected forward, backward or central",
+                name
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Differencing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Differencing::Forward => "forward",
+            Differencing::Backward => "backward",
+            Differencing::Central => "central",
+        })
+    }
+}
+
+impl Differencing {
+    /// Frames to difference at `frame`, as earlier and later frame.
+    fn neighbors(self, frame: usize, frame_count: usize) -> (usize, usize) {
+        let last = frame_count - 1;
+        match self {
+            Differencing::Forward if frame < last => (frame, frame + 1),
+            Differencing::Backward if frame > 0 => (frame - 1, frame),
+            Differencing::Central => (frame.saturating_sub(1), (frame + 1).min(last)),
+            // The end a one-sided difference can't reach.
+            Differencing::Forward => (frame.saturating_sub(1), frame),
+            Differencing::Backward => (frame, (frame + 1).min(last)),
+        }
+    }
+
+    /// Derivative of `values` in every frame, with `delta` the change from an earlier to a later
+    /// value.
+    fn apply<T: Copy>(
+        self,
+        values: &[T],
+        frame_time: f32,
+        delta: impl Fn(T, T) -> Vec3,
+    ) -> Vec<Vec3> {
+        (0..values.len())
+            .map(|frame| {
+                let (earlier, later) = self.neighbors(frame, values.len());
+                if later == earlier {
+                    return Vec3::ZERO;
+                }
+                delta(values[earlier], values[later]) / ((later - earlier) as f32 * frame_time)
+            })
+            .collect()
+    }
+}
+
+/// Linear velocity of `positions`, in units per second.
+pub fn linear_velocities(positions: &[Vec3], frame_time: f32, method: Differencing) -> Vec<Vec3> {
+    method.apply(positions, frame_time, |earlier, later| later - earlier)
+}
+
+/// Angular velocity of `rotations` as a scaled axis in radians per second, in the space the
+/// rotations are expressed in (the parent joint for local rotations).
+pub fn angular_velocities(rotations: &[Quat], frame_time: f32, method: Differencing) -> Vec<Vec3> {
+    method.apply(rotations, frame_time, |earlier, later| {
+        let delta = later * earlier.inverse();
+        // The shorter way round, so a sign flip between frames isn't a full turn.
+        let delta = if delta.w < 0.0 { -delta } else { delta };
+        delta.to_scaled_axis()
+    })
+}
+
+/// Appends the angular velocity of every joint, then the root velocity and the root
+/// acceleration, as one curve each. The acceleration is the difference of the velocity with the
+/// same scheme.
+pub fn append_motion_channels(
+    gav_data: &Array3<f32>,
+    animation: &Animation,
+    frame_time: f32,
+    method: Differencing,
+) -> Result<Array3<f32>> {
+    let mut gav_data = gav_data.clone();
+    for rotations in &animation.joint_rotations {
+        gav_data = append_curve(
+            &gav_data,
+            &angular_velocities(rotations, frame_time, method),
+        )?;
+    }
+    let velocities = linear_velocities(&animation.root_positions, frame_time, method);
+    let accelerations = linear_velocities(&velocities, frame_time, method);
+    gav_data = append_curve(&gav_data, &velocities)?;
+    append_curve(&gav_data, &accelerations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation_to_gav;
+
+    #[test]
+    fn test_motion_channels_of_steady_motion() {
+        let frame_time = 0.1;
+        // Constant acceleration of 2 along X, constant turn of 1 radian per second about Y.
+        let frames = 6;
+        let animation = Animation {
+            root_positions: (0..frames)
+                .map(|f| Vec3::X * (f as f32 * frame_time).powi(2))
+                .collect(),
+            joint_rotations: vec![
+                (0..frames)
+                    .map(|f| Quat::from_rotation_y(f as f32 * frame_time))
+                    .collect(),
+            ],
+            events: vec![],
+        };
+
+        let angular = angular_velocities(
+            &animation.joint_rotations[0],
+            frame_time,
+            Differencing::Forward,
+        );
+        for velocity in &angular {
+            assert!(velocity.distance(Vec3::Y) < 1e-3);
+        }
+        let velocities =
+            linear_velocities(&animation.root_positions, frame_time, Differencing::Central);
+        assert!((velocities[2].x - 0.4).abs() < 1e-4);
+
+        let gav_data = animation_to_gav(&animation).unwrap();
+        let extended =
+            append_motion_channels(&gav_data, &animation, frame_time, Differencing::Central)
+                .unwrap();
+        // Root and joint, then angular velocity, root velocity and root acceleration.
+        assert_eq!(extended.dim(), (5, frames, 3));
+        assert!((extended[[4, 2, 0]] - 2.0).abs() < 1e-3);
+        assert!("sideways".parse::<Differencing>().is_err());
+    }
+}
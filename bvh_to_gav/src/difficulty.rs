@@ -0,0 +1,180 @@
+//! Difficulty scores of the clips of a [`crate::manifest::Manifest`], for curriculum learning
+//! loaders that start with simple motion. A clip is measured on three features:
+//!
+//! - speed variance: the variance of the root speed, in squared units per second squared,
+//!   high for clips that start, stop and change pace
+//! - rotation energy: the mean over the frames of the squared angular speeds of all joints
+//!   summed, in squared radians per second squared
+//! - contact switch rate: how often the foot contacts, see [`crate::contacts`], change per
+//!   second, high for stepping, jumping and dancing. Zero for skeletons without feet.
+//!
+//! The features have different units, so the score of a clip is the mean of its rank in each
+//! of them over the clips of the manifest, from 0 for the simplest clip to 1 for the hardest.
+//! Scores are only comparable within a manifest.
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Animation,
+    contacts::{ContactConfig, default_contact_joints, detect_contacts},
+    derivatives::{Differencing, angular_velocities, linear_velocities},
+    skeleton::Skeleton,
+};
+
+/// What a clip is scored on.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct DifficultyFeatures {
+    pub speed_variance: f32,
+    pub rotation_energy: f32,
+    pub contact_switch_rate: f32,
+}
+
+impl DifficultyFeatures {
+    pub fn measure(skeleton: &Skeleton, animation: &Animation, frame_time: f32) -> Result<Self> {
+        let frame_count = animation.frame_count();
+        if frame_count < 2 {
+            return Ok(DifficultyFeatures::default());
+        }
+        let speeds: Vec<f32> =
+            linear_velocities(&animation.root_positions, frame_time, Differencing::Central)
+                .iter()
+                .map(|velocity| velocity.length())
+                .collect();
+        let mean_speed = speeds.iter().sum::<f32>() / frame_count as f32;
+        let speed_variance = speeds
+            .iter()
+            .map(|speed| (speed - mean_speed).powi(2))
+            .sum::<f32>()
+            / frame_count as f32;
+
+        let rotation_energy = animation
+            .joint_rotations
+            .iter()
+            .flat_map(|rotations| angular_velocities(rotations, frame_time, Differencing::Central))
+            .map(|velocity| velocity.length_squared())
+            .sum::<f32>()
+            / frame_count as f32;
+
+        let contact_switch_rate = if default_contact_joints(skeleton).is_empty() {
+            0.0
+        } else {
+            let contacts = detect_contacts(
+                skeleton,
+                animation,
+                frame_time,
+                &ContactConfig::new(skeleton, &[])?,
+            )?;
+            let switches = contacts
+                .rows()
+                .into_iter()
+                .zip(contacts.rows().into_iter().skip(1))
+                .map(|(previous, next)| {
+                    previous
+                        .iter()
+                        .zip(next.iter())
+                        .filter(|(a, b)| a != b)
+                        .count()
+                })
+                .sum::<usize>();
+            switches as f32 / ((frame_count - 1) as f32 * frame_time)
+        };
+
+        Ok(DifficultyFeatures {
+            speed_variance,
+            rotation_energy,
+            contact_switch_rate,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ClipDifficulty {
+    pub clip: String,
+    /// Mean rank of the features among the clips, from 0 to 1.
+    pub score: f32,
+    #[serde(flatten)]
+    pub features: DifficultyFeatures,
+}
+
+/// Fraction of `values` below each of them, so the smallest gets 0 and the largest 1.
+fn ranks(values: &[f32]) -> Vec<f32> {
+    let scale = values.len().saturating_sub(1).max(1) as f32;
+    values
+        .iter()
+        .map(|value| values.iter().filter(|other| *other < value).count() as f32 / scale)
+        .collect()
+}
+
+/// Scores `clips`, as `(clip, features)`, against each other, in their order.
+pub fn score_difficulty(clips: Vec<(String, DifficultyFeatures)>) -> Vec<ClipDifficulty> {
+    let feature = |f: fn(&DifficultyFeatures) -> f32| -> Vec<f32> {
+        ranks(
+            &clips
+                .iter()
+                .map(|(_, features)| f(features))
+                .collect::<Vec<_>>(),
+        )
+    };
+    let speed = feature(|features| features.speed_variance);
+    let rotation = feature(|features| features.rotation_energy);
+    let contacts = feature(|features| features.contact_switch_rate);
+    clips
+        .into_iter()
+        .enumerate()
+        .map(|(index, (clip, features))| ClipDifficulty {
+            clip,
+            score: (speed[index] + rotation[index] + contacts[index]) / 3.0,
+            features,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_math::{Quat, Vec3};
+
+    use super::*;
+    use crate::skeleton::SkeletonJoint;
+
+    #[test]
+    fn test_busier_clips_score_higher() {
+        let skeleton = Skeleton {
+            joints: vec![SkeletonJoint {
+                name: "Hips".to_string(),
+                parent: None,
+                offset: Vec3::ZERO,
+                end_site: None,
+            }],
+        };
+        let frame_time = 0.1;
+        let still = Animation {
+            root_positions: vec![Vec3::ZERO; 10],
+            joint_rotations: vec![vec![Quat::IDENTITY; 10]],
+            events: Vec::new(),
+        };
+        let busy = Animation {
+            // Speeds up, so the speed varies.
+            root_positions: (0..10).map(|i| Vec3::X * (i * i) as f32).collect(),
+            joint_rotations: vec![
+                (0..10)
+                    .map(|i| Quat::from_rotation_y(i as f32 * 0.2))
+                    .collect(),
+            ],
+            events: Vec::new(),
+        };
+        let still_features = DifficultyFeatures::measure(&skeleton, &still, frame_time).unwrap();
+        assert_eq!(still_features, DifficultyFeatures::default());
+        let busy_features = DifficultyFeatures::measure(&skeleton, &busy, frame_time).unwrap();
+        assert!(busy_features.speed_variance > 0.0);
+        // 0.2 radians a frame is 2 radians a second.
+        assert!((busy_features.rotation_energy - 4.0).abs() < 1e-2);
+
+        let scores = score_difficulty(vec![
+            ("busy.bvh".to_string(), busy_features),
+            ("still.bvh".to_string(), still_features),
+        ]);
+        assert_eq!(scores[0].clip, "busy.bvh");
+        assert!((scores[0].score - 2.0 / 3.0).abs() < 1e-6);
+        assert_eq!(scores[1].score, 0.0);
+    }
+}
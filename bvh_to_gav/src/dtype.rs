@@ -0,0 +1,227 @@
+//! Element type of the `.npy` tensors written by `convert`: f32 by default, f16 to halve the
+//! size of large datasets, f64 for precision experiments. Tensors are processed as f32
+//! throughout and only converted when written, and [`read_tensor`] reads any of the three back
+//! as f32, so every tool that reads tensors accepts them all.
+//!
+//! The integer types i16 and i8 hold quantization levels rather than values, see
+//! [`crate::quantization`], which scales them back with the sidecar written next to them.
+//!
+//! ndarray-npy doesn't know half precision floats, so f16 tensors are written and read here,
+//! in C order with a version 1.0 header.
+use std::{
+    fmt,
+    io::{Read, Write},
+    str::FromStr,
+};
+
+use anyhow::{Context, Result, bail};
+use half::f16;
+use ndarray::Array3;
+use ndarray_npy::{ReadNpyExt, WriteNpyExt};
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+/// The header, including magic, version and length, is padded to a multiple of this.
+const HEADER_ALIGNMENT: usize = 64;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dtype {
+    F16,
+    #[default]
+    F32,
+    F64,
+    I16,
+    I8,
+}
+
+const DTYPES: [Dtype; 5] = [Dtype::F16, Dtype::F32, Dtype::F64, Dtype::I16, Dtype::I8];
+
+impl Dtype {
+    /// Type descriptor of the `.npy` header, little endian.
+    fn descr(self) -> &'static str {
+        match self {
+            Dtype::F16 => "<f2",
+            Dtype::F32 => "<f4",
+            Dtype::F64 => "<f8",
+            Dtype::I16 => "<i2",
+            Dtype::I8 => "|i1",
+        }
+    }
+
+    /// Largest quantization level of the integer types, `None` for the float types.
+    pub fn max_level(self) -> Option<f32> {
+        match self {
+            Dtype::I16 => Some(i16::MAX as f32),
+            Dtype::I8 => Some(i8::MAX as f32),
+            Dtype::F16 | Dtype::F32 | Dtype::F64 => None,
+        }
+    }
+
+    fn from_descr(descr: &str) -> Result<Self> {
+        DTYPES
+            .into_iter()
+            .find(|dtype| dtype.descr() == descr)
+            .with_context(|| format!("Unsupported tensor dtype {}", descr))
+    }
+}
+
+impl FromStr for Dtype {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "f16" => Ok(Dtype::F16),
+            "f32" => Ok(Dtype::F32),
+            "f64" => Ok(Dtype::F64),
+            "i16" => Ok(Dtype::I16),
+            "i8" => Ok(Dtype::I8),
+            _ => bail!("Unknown dtype {}, expected f16, f32, f64, i16 or i8", name),
+        }
+    }
+}
+
+impl fmt::Display for Dtype {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Dtype::F16 => "f16",
+            Dtype::F32 => "f32",
+            Dtype::F64 => "f64",
+            Dtype::I16 => "i16",
+            Dtype::I8 => "i8",
+        })
+    }
+}
+
+/// Writes `data` as a `.npy` tensor of `dtype`. For the integer types `data` holds the
+/// quantization levels, which are rounded.
+pub fn write_tensor<W: Write>(mut writer: W, data: &Array3<f32>, dtype: Dtype) -> Result<()> {
+    match dtype {
+        Dtype::F32 => data.write_npy(writer)?,
+        Dtype::F64 => data.mapv(f64::from).write_npy(writer)?,
+        Dtype::I16 => data.mapv(|x| x.round() as i16).write_npy(writer)?,
+        Dtype::I8 => data.mapv(|x| x.round() as i8).write_npy(writer)?,
+        Dtype::F16 => {
+            let (a, b, c) = data.dim();
+            let mut header = format!(
+                "{{'descr': '{}', 'fortran_order': False, 'shape': ({}, {}, {}), }}",
+                dtype.descr(),
+                a,
+                b,
+                c
+            );
+            let unpadded = MAGIC.len() + 4 + header.len() + 1;
+            let padding = unpadded.next_multiple_of(HEADER_ALIGNMENT) - unpadded;
+            header.extend(std::iter::repeat_n(' ', padding));
+            header.push('\n');
+            writer.write_all(MAGIC)?;
+            writer.write_all(&[1, 0])?;
+            writer.write_all(&(header.len() as u16).to_le_bytes())?;
+            writer.write_all(header.as_bytes())?;
+            let bytes: Vec<u8> = data
+                .iter()
+                .flat_map(|&x| f16::from_f32(x).to_le_bytes())
+                .collect();
+            writer.write_all(&bytes)?;
+        }
+    }
+    Ok(())
+}
+
+/// The header text of the `.npy` file in `bytes`, and the offset of its data.
+fn split_header(bytes: &[u8]) -> Result<(&str, usize)> {
+    if !bytes.starts_with(MAGIC) || bytes.len() < MAGIC.len() + 4 {
+        bail!("Not a .npy file");
+    }
+    let (length, start) = match bytes[MAGIC.len()] {
+        1 => (
+            u16::from_le_bytes([bytes[8], bytes[9]]) as usize,
+            MAGIC.len() + 4,
+        ),
+        _ if bytes.len() >= MAGIC.len() + 6 => (
+            u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize,
+            MAGIC.len() + 6,
+        ),
+        _ => bail!("Truncated .npy header"),
+    };
+    let header = bytes
+        .get(start..start + length)
+        .context("Truncated .npy header")?;
+    Ok((std::str::from_utf8(header)?, start + length))
+}
+
+/// Value of `key` in the header dictionary, up to the character ending it.
+fn header_value<'a>(header: &'a str, key: &str, end: char) -> Result<&'a str> {
+    let start = header
+        .find(&format!("'{}': ", key))
+        .with_context(|| format!("The .npy header has no {}", key))?
+        + key.len()
+        + 4;
+    let length = header[start..]
+        .find(end)
+        .with_context(|| format!("Malformed {} in the .npy header", key))?;
+    Ok(&header[start..start + length])
+}
+
+/// Reads a `.npy` tensor of any [`Dtype`] as f32, and returns its dtype with it. Integer
+/// tensors are read as their quantization levels.
+pub fn read_typed_tensor<R: Read>(mut reader: R) -> Result<(Array3<f32>, Dtype)> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let (header, data_start) = split_header(&bytes)?;
+    let dtype = Dtype::from_descr(header_value(header, "descr", ',')?.trim_matches('\''))?;
+    let data = match dtype {
+        Dtype::F32 => Array3::<f32>::read_npy(&bytes[..])?,
+        Dtype::F64 => Array3::<f64>::read_npy(&bytes[..])?.mapv(|x| x as f32),
+        Dtype::I16 => Array3::<i16>::read_npy(&bytes[..])?.mapv(f32::from),
+        Dtype::I8 => Array3::<i8>::read_npy(&bytes[..])?.mapv(f32::from),
+        Dtype::F16 => {
+            if header_value(header, "fortran_order", ',')? != "False" {
+                bail!("Fortran ordered f16 tensors aren't supported");
+            }
+            let shape = header_value(header, "shape", ')')?
+                .trim_start_matches('(')
+                .split(',')
+                .map(str::trim)
+                .filter(|dim| !dim.is_empty())
+                .map(str::parse)
+                .collect::<Result<Vec<usize>, _>>()?;
+            let &[a, b, c] = shape.as_slice() else {
+                bail!("Expected a tensor of 3 dimensions, got {}", shape.len());
+            };
+            let values: Vec<f32> = bytes[data_start..]
+                .chunks_exact(2)
+                .map(|pair| f16::from_le_bytes([pair[0], pair[1]]).to_f32())
+                .collect();
+            Array3::from_shape_vec((a, b, c), values)
+                .context("The f16 tensor doesn't match its shape")?
+        }
+    };
+    Ok((data, dtype))
+}
+
+/// Reads a `.npy` tensor of any [`Dtype`] as f32, integer tensors as their levels.
+pub fn read_tensor<R: Read>(reader: R) -> Result<Array3<f32>> {
+    Ok(read_typed_tensor(reader)?.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_f16_tensors_round_trip() {
+        let data =
+            Array3::from_shape_fn((3, 4, 2), |(i, j, k)| i as f32 - 0.25 * j as f32 + k as f32);
+        let mut bytes = Vec::new();
+        write_tensor(&mut bytes, &data, Dtype::F16).unwrap();
+        let data_start = split_header(&bytes).unwrap().1;
+        assert_eq!(data_start % HEADER_ALIGNMENT, 0);
+        assert_eq!(bytes.len(), data_start + data.len() * 2);
+
+        let (read, dtype) = read_typed_tensor(&bytes[..]).unwrap();
+        assert_eq!(dtype, Dtype::F16);
+        assert_eq!(read, data);
+        assert!(read_tensor(&bytes[..bytes.len() - 1]).is_err());
+        assert_eq!("f64".parse::<Dtype>().unwrap(), Dtype::F64);
+        assert!("f8".parse::<Dtype>().is_err());
+    }
+}
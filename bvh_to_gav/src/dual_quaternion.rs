@@ -0,0 +1,174 @@
+//! Dual quaternion encoding, an alternative to the GAV layout for experiments with rigid motion
+//! representations. Every joint gets one curve holding its local transform as a unit dual
+//! quaternion: the rotation as the real part and half the translation times the rotation as the
+//! dual part, 8 floats per frame in the order `[real x, y, z, w, dual x, y, z, w]`.
+//!
+//! The root is translated by the root positions of the [`Animation`], the other joints by their
+//! skeleton offset. Decoding reads the rotation straight from the real part, so rotations
+//! round-trip exactly, and the root positions back from the dual part, up to float rounding.
+use anyhow::{Result, bail};
+use bevy_math::{Quat, Vec3};
+use ndarray::Array3;
+
+use crate::{Animation, skeleton::Skeleton};
+
+pub const DUAL_QUATERNION_CHANNELS: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DualQuat {
+    pub real: Quat,
+    pub dual: Quat,
+}
+
+impl DualQuat {
+    pub fn from_rotation_translation(rotation: Quat, translation: Vec3) -> Self {
+        let translation = Quat::from_xyzw(translation.x, translation.y, translation.z, 0.0);
+        DualQuat {
+            real: rotation,
+            dual: translation * rotation * 0.5,
+        }
+    }
+
+    pub fn rotation(&self) -> Quat {
+        self.real
+    }
+
+    pub fn translation(&self) -> Vec3 {
+        let translation = self.dual * self.real.conjugate() * 2.0;
+        Vec3::new(translation.x, translation.y, translation.z)
+    }
+
+    pub fn to_array(&self) -> [f32; DUAL_QUATERNION_CHANNELS] {
+        let (real, dual) = (self.real.to_array(), self.dual.to_array());
+        std::array::from_fn(|i| if i < 4 { real[i] } else { dual[i - 4] })
+    }
+
+    pub fn from_array(values: [f32; DUAL_QUATERNION_CHANNELS]) -> Self {
+        Self::from_slice(&values)
+    }
+
+    pub fn from_slice(values: &[f32]) -> Self {
+        DualQuat {
+            real: Quat::from_slice(&values[..4]),
+            dual: Quat::from_slice(&values[4..8]),
+        }
+    }
+}
+
+/// Encodes `animation` as a `(joints, frames, 8)` tensor of dual quaternions, joints in
+/// [`Skeleton::joint_order`].
+pub fn animation_to_dual_quaternions(
+    animation: &Animation,
+    skeleton: &Skeleton,
+) -> Result<Array3<f32>> {
+    if animation.joint_count() != skeleton.joint_count() {
+        bail!(
+            "The animation has {} joints but the skeleton {}",
+            animation.joint_count(),
+            skeleton.joint_count()
+        );
+    }
+    let shape = (
+        animation.joint_count(),
+        animation.frame_count(),
+        DUAL_QUATERNION_CHANNELS,
+    );
+    let mut data = Vec::with_capacity(shape.0 * shape.1 * shape.2);
+    for (joint, rotations) in skeleton.joints.iter().zip(&animation.joint_rotations) {
+        for (frame, rotation) in rotations.iter().enumerate() {
+            let translation = if joint.parent.is_none() {
+                animation.root_positions[frame]
+            } else {
+                joint.offset
+            };
+            data.extend(DualQuat::from_rotation_translation(*rotation, translation).to_array());
+        }
+    }
+    Ok(Array3::from_shape_vec(shape, data)?)
+}
+
+/// Decodes a tensor written by [`animation_to_dual_quaternions`]. The first curve is the root.
+pub fn dual_quaternions_to_animation(data: &Array3<f32>) -> Result<Animation> {
+    let (joint_count, frame_count, channel_count) = data.dim();
+    if channel_count != DUAL_QUATERNION_CHANNELS || joint_count == 0 {
+        bail!(
+            "Expected dual quaternion curves of shape (joints, frames, {}), got {:?}",
+            DUAL_QUATERNION_CHANNELS,
+            data.dim()
+        );
+    }
+    // Indexed rather than sliced, so tensors in any memory layout decode.
+    let decode = |joint: usize, frame: usize| {
+        DualQuat::from_array(std::array::from_fn(|channel| data[[joint, frame, channel]]))
+    };
+    Ok(Animation {
+        root_positions: (0..frame_count)
+            .map(|frame| decode(0, frame).translation())
+            .collect(),
+        joint_rotations: (0..joint_count)
+            .map(|joint| {
+                (0..frame_count)
+                    .map(|frame| decode(joint, frame).rotation())
+                    .collect()
+            })
+            .collect(),
+        events: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{ShapeBuilder, s};
+
+    use super::*;
+    use crate::skeleton::SkeletonJoint;
+
+    #[test]
+    fn test_dual_quaternion_round_trip() {
+        let joint = |name: &str, parent, offset| SkeletonJoint {
+            name: name.to_string(),
+            parent,
+            offset,
+            end_site: None,
+        };
+        let skeleton = Skeleton {
+            joints: vec![
+                joint("Hips", None, Vec3::ZERO),
+                joint("Spine", Some(0), Vec3::new(0.0, 12.0, 1.0)),
+            ],
+        };
+        let rotations: Vec<Quat> = [0.0f32, 1.0, 2.5, 4.0]
+            .iter()
+            .map(|angle| Quat::from_euler(bevy_math::EulerRot::YXZ, *angle, 0.3, -0.2))
+            .collect();
+        let animation = Animation {
+            root_positions: vec![
+                Vec3::new(0.0, 90.0, 0.0),
+                Vec3::new(5.0, 91.0, -2.0),
+                Vec3::new(10.0, 92.0, -4.0),
+                Vec3::new(15.0, 90.0, -6.0),
+            ],
+            joint_rotations: vec![rotations.clone(), rotations.iter().rev().copied().collect()],
+            events: vec![],
+        };
+
+        let data = animation_to_dual_quaternions(&animation, &skeleton).unwrap();
+        assert_eq!(data.dim(), (2, 4, 8));
+        let spine = DualQuat::from_slice(data.slice(s![1, 2, ..]).as_slice().unwrap());
+        assert!(spine.translation().distance(skeleton.joints[1].offset) < 1e-4);
+
+        let decoded = dual_quaternions_to_animation(&data).unwrap();
+        assert_eq!(decoded.joint_rotations, animation.joint_rotations);
+        let mut column_major = Array3::zeros(data.dim().f());
+        column_major.assign(&data);
+        assert_eq!(
+            dual_quaternions_to_animation(&column_major)
+                .unwrap()
+                .joint_rotations,
+            animation.joint_rotations
+        );
+        for (decoded, original) in decoded.root_positions.iter().zip(&animation.root_positions) {
+            assert!(decoded.distance(*original) < 1e-3);
+        }
+    }
+}
@@ -0,0 +1,263 @@
+//! Clip level embeddings for "find similar motions" queries. An embedding maps a whole clip to
+//! a fixed size vector; learned encoders can be plugged in by implementing [`ClipEmbedding`].
+use std::{fs::File, io::BufWriter, path::Path};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::{Animation, search::root_path};
+
+/// File name of the embedding index written next to the clips of a dataset.
+pub const EMBEDDING_INDEX_FILE: &str = "embeddings.json";
+
+pub trait ClipEmbedding {
+    /// Stored in the index, so vectors of different embeddings are never compared.
+    fn name(&self) -> &str;
+    fn dimensions(&self) -> usize;
+    fn embed(&self, animation: &Animation) -> Vec<f32>;
+}
+
+/// Default embedding built from kinematic statistics of the root and joints. It needs no model
+/// and works for any skeleton, since every statistic is aggregated over the joints.
+pub struct KinematicEmbedding {
+    pub fps: f32,
+}
+
+impl Default for KinematicEmbedding {
+    fn default() -> Self {
+        KinematicEmbedding { fps: 30.0 }
+    }
+}
+
+fn mean_and_deviation(values: &[f32]) -> (f32, f32) {
+    if values.is_empty() {
+        return (0.0, 0.0);
+    }
+    let mean = values.iter().sum::<f32>() / values.len() as f32;
+    let variance =
+        values.iter().map(|v| (v - mean) * (v - mean)).sum::<f32>() / values.len() as f32;
+    (mean, variance.sqrt())
+}
+
+impl KinematicEmbedding {
+    /// Summed angular speed of all joints per frame, in radians per second.
+    fn joint_speeds(&self, animation: &Animation) -> Vec<f32> {
+        (1..animation.frame_count())
+            .map(|frame| {
+                animation
+                    .joint_rotations
+                    .iter()
+                    .map(|joint| joint[frame - 1].angle_between(joint[frame]) * self.fps)
+                    .sum()
+            })
+            .collect()
+    }
+
+    /// Period in seconds of the strongest repetition in the joint speeds, e.g. the gait cycle.
+    fn dominant_period(&self, speeds: &[f32]) -> f32 {
+        let (mean, _) = mean_and_deviation(speeds);
+        let centered: Vec<f32> = speeds.iter().map(|s| s - mean).collect();
+        let correlation = |lag: usize| -> f32 {
+            centered
+                .iter()
+                .zip(&centered[lag..])
+                .map(|(a, b)| a * b)
+                .sum()
+        };
+        // Skip the first lags, which always correlate.
+        let min_lag = (self.fps / 5.0).max(1.0) as usize;
+        (min_lag..centered.len() / 2)
+            .map(|lag| (lag, correlation(lag)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .filter(|(_, correlation)| *correlation > 0.0)
+            .map_or(0.0, |(lag, _)| lag as f32 / self.fps)
+    }
+}
+
+impl ClipEmbedding for KinematicEmbedding {
+    fn name(&self) -> &str {
+        "kinematic"
+    }
+
+    fn dimensions(&self) -> usize {
+        10
+    }
+
+    fn embed(&self, animation: &Animation) -> Vec<f32> {
+        let path = root_path(animation);
+        let root_speeds: Vec<f32> = path
+            .windows(2)
+            .map(|step| step[0].distance(step[1]) * self.fps)
+            .collect();
+        let turn_rates: Vec<f32> = path
+            .windows(3)
+            .filter_map(|step| {
+                let (a, b) = (step[1] - step[0], step[2] - step[1]);
+                (a.length() > 1e-4 && b.length() > 1e-4).then(|| a.angle_to(b).abs() * self.fps)
+            })
+            .collect();
+        let heights: Vec<f32> = animation.root_positions.iter().map(|p| p.y).collect();
+        let joint_speeds = self.joint_speeds(animation);
+        // Range of motion: mean angle of every joint away from its first frame.
+        let joint_range: Vec<f32> = animation
+            .joint_rotations
+            .iter()
+            .map(|joint| {
+                joint.iter().map(|r| r.angle_between(joint[0])).sum::<f32>()
+                    / joint.len().max(1) as f32
+            })
+            .collect();
+
+        let (root_speed, root_speed_deviation) = mean_and_deviation(&root_speeds);
+        let (turn_rate, _) = mean_and_deviation(&turn_rates);
+        let (_, height_deviation) = mean_and_deviation(&heights);
+        let (joint_speed, joint_speed_deviation) = mean_and_deviation(&joint_speeds);
+        let (range, _) = mean_and_deviation(&joint_range);
+        vec![
+            root_speed,
+            root_speed_deviation,
+            turn_rate,
+            height_deviation,
+            joint_speed,
+            joint_speed_deviation,
+            joint_speeds.iter().copied().fold(0.0, f32::max),
+            range,
+            joint_range.iter().copied().fold(0.0, f32::max),
+            self.dominant_period(&joint_speeds),
+        ]
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EmbeddedClip {
+    /// Clip file name, relative to the dataset folder.
+    pub clip: String,
+    pub vector: Vec<f32>,
+}
+
+/// Embedding vectors of every clip in a dataset, stored as [`EMBEDDING_INDEX_FILE`].
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct EmbeddingIndex {
+    pub embedding: String,
+    pub dimensions: usize,
+    pub clips: Vec<EmbeddedClip>,
+}
+
+impl EmbeddingIndex {
+    pub fn new(embedding: &dyn ClipEmbedding) -> Self {
+        EmbeddingIndex {
+            embedding: embedding.name().to_string(),
+            dimensions: embedding.dimensions(),
+            clips: Vec::new(),
+        }
+    }
+
+    pub fn insert(&mut self, clip: String, vector: Vec<f32>) -> Result<()> {
+        if vector.len() != self.dimensions {
+            bail!(
+                "Embedding of {} has {} dimensions, expected {}",
+                clip,
+                vector.len(),
+                self.dimensions
+            );
+        }
+        self.clips.push(EmbeddedClip { clip, vector });
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("Could not open {}", path.display()))?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        serde_json::to_writer(BufWriter::new(File::create(path)?), self)?;
+        Ok(())
+    }
+
+    /// Standard deviation of every dimension over the indexed clips, so dimensions in different
+    /// units weigh in equally.
+    fn scales(&self) -> Vec<f32> {
+        (0..self.dimensions)
+            .map(|d| {
+                let values: Vec<f32> = self.clips.iter().map(|c| c.vector[d]).collect();
+                let (_, deviation) = mean_and_deviation(&values);
+                if deviation > 0.0 { deviation } else { 1.0 }
+            })
+            .collect()
+    }
+
+    /// The `k` clips closest to `vector` by standardized euclidean distance.
+    pub fn nearest(&self, vector: &[f32], k: usize) -> Vec<(String, f32)> {
+        let scales = self.scales();
+        let mut matches: Vec<(String, f32)> = self
+            .clips
+            .iter()
+            .map(|clip| {
+                let distance = clip
+                    .vector
+                    .iter()
+                    .zip(vector)
+                    .zip(&scales)
+                    .map(|((a, b), scale)| ((a - b) / scale).powi(2))
+                    .sum::<f32>()
+                    .sqrt();
+                (clip.clip.clone(), distance)
+            })
+            .collect();
+        matches.sort_by(|a, b| a.1.total_cmp(&b.1));
+        matches.truncate(k);
+        matches
+    }
+
+    /// The `k` clips most similar to an indexed clip, excluding the clip itself.
+    pub fn similar(&self, clip: &str, k: usize) -> Option<Vec<(String, f32)>> {
+        let query = self.clips.iter().find(|c| c.clip == clip)?;
+        let mut matches = self.nearest(&query.vector, k + 1);
+        matches.retain(|(name, _)| name != clip);
+        matches.truncate(k);
+        Some(matches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy_math::{Quat, Vec3};
+
+    fn walk(speed: f32, swing: f32) -> Animation {
+        let frames = 60;
+        Animation {
+            root_positions: (0..frames)
+                .map(|i| Vec3::new(0.0, 1.0, i as f32 * speed))
+                .collect(),
+            joint_rotations: vec![
+                (0..frames)
+                    .map(|i| Quat::from_rotation_x((i as f32 * 0.3).sin() * swing))
+                    .collect(),
+            ],
+            events: vec![],
+        }
+    }
+
+    #[test]
+    fn test_similar_clips_ranks_by_kinematics() {
+        let embedding = KinematicEmbedding::default();
+        let mut index = EmbeddingIndex::new(&embedding);
+        for (name, animation) in [
+            ("walk", walk(0.05, 0.4)),
+            ("walk_slow", walk(0.04, 0.35)),
+            ("run", walk(0.2, 1.2)),
+        ] {
+            let vector = embedding.embed(&animation);
+            assert_eq!(vector.len(), embedding.dimensions());
+            index.insert(name.to_string(), vector).unwrap();
+        }
+
+        let similar = index.similar("walk", 2).unwrap();
+        assert_eq!(similar[0].0, "walk_slow");
+        assert_eq!(similar[1].0, "run");
+        assert!(index.similar("missing", 2).is_none());
+    }
+}
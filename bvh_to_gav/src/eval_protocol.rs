@@ -0,0 +1,384 @@
+//! Evaluation protocols, so the numbers reported across experiments come from one code path. A
+//! protocol is a TOML file naming the clips to compare, how to window and align them, the
+//! metrics, see [`crate::metrics`], and the seeds the candidates were generated with:
+//!
+//! ```toml
+//! name = "holdout-v1"
+//! seeds = [0, 1, 2]
+//! metrics = ["mpjpe", "foot_skate"]
+//! alignment = "root"
+//! windows = "60:30"
+//!
+//! [[clips]]
+//! reference = "test/walk.bvh"
+//! candidate = "samples/seed_{seed}/walk.npy"
+//! ```
+//!
+//! Paths are relative to the protocol file, and `{seed}` in a candidate is replaced by every
+//! seed in turn. Without `metrics` every metric is computed, without `seeds` the seed is 0 and
+//! without `windows` whole clips are compared. `alignment` is `none`, `translation`, which
+//! moves every window to start at the origin, or `root`, which also turns it to face +Z, see
+//! [`crate::alignment::RootAlignment`].
+//!
+//! The evaluation has no randomness of its own, the seeds only name the candidates. A metric of
+//! a clip is its mean over the windows, and the summary of a metric is the mean and standard
+//! deviation over the seeds of its mean over the clips. Results are written as JSON with
+//! [`RESULTS_VERSION`], bumped whenever a metric or the way it is aggregated changes.
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Animation, alignment::RootAlignment, metrics::MotionMetrics, skeleton::Skeleton, storage,
+    windows::Windows,
+};
+
+/// Version of the results format and of the way the numbers in it are computed.
+pub const RESULTS_VERSION: u32 = 3;
+/// Replaced by the seed in the candidate paths of a protocol.
+pub const SEED_PLACEHOLDER: &str = "{seed}";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    Mpjpe,
+    BoneLengthViolation,
+    FootSkate,
+    AngularJerk,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 4] = [
+        Metric::Mpjpe,
+        Metric::BoneLengthViolation,
+        Metric::FootSkate,
+        Metric::AngularJerk,
+    ];
+
+    /// The value of this metric for the candidate of `metrics`.
+    pub fn value(&self, metrics: &MotionMetrics) -> f32 {
+        match self {
+            Metric::Mpjpe => metrics.mpjpe,
+            Metric::BoneLengthViolation => metrics.bone_length_violation,
+            Metric::FootSkate => metrics.candidate.foot_skate,
+            Metric::AngularJerk => metrics.candidate.angular_jerk,
+        }
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Metric::Mpjpe => "mpjpe",
+            Metric::BoneLengthViolation => "bone_length_violation",
+            Metric::FootSkate => "foot_skate",
+            Metric::AngularJerk => "angular_jerk",
+        })
+    }
+}
+
+/// How windows are aligned before they are compared.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Alignment {
+    #[default]
+    None,
+    Translation,
+    Root,
+}
+
+impl Alignment {
+    pub fn apply(&self, skeleton: &Skeleton, animation: &mut Animation) {
+        let fitted = RootAlignment::fit(skeleton, animation);
+        match self {
+            Alignment::None => {}
+            Alignment::Translation => RootAlignment { yaw: 0.0, ..fitted }.remove(animation),
+            Alignment::Root => fitted.remove(animation),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ProtocolClip {
+    pub reference: String,
+    pub candidate: String,
+}
+
+fn default_seeds() -> Vec<u64> {
+    vec![0]
+}
+
+fn default_metrics() -> Vec<Metric> {
+    Metric::ALL.to_vec()
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Protocol {
+    pub name: String,
+    #[serde(default = "default_seeds")]
+    pub seeds: Vec<u64>,
+    #[serde(default = "default_metrics")]
+    pub metrics: Vec<Metric>,
+    #[serde(default)]
+    pub alignment: Alignment,
+    /// Windows as `LENGTH[:STRIDE]`, see [`Windows`].
+    #[serde(default)]
+    pub windows: Option<String>,
+    pub clips: Vec<ProtocolClip>,
+}
+
+impl Protocol {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let protocol: Protocol =
+            toml::from_str(&text).with_context(|| format!("Invalid {}", path.display()))?;
+        if protocol.seeds.is_empty() || protocol.metrics.is_empty() || protocol.clips.is_empty() {
+            bail!("A protocol needs seeds, metrics and clips");
+        }
+        protocol.windows()?;
+        Ok(protocol)
+    }
+
+    pub fn windows(&self) -> Result<Option<Windows>> {
+        self.windows.as_deref().map(str::parse).transpose()
+    }
+
+    /// Path of the reference of `clip`, with `folder` the folder of the protocol file.
+    pub fn reference_path(&self, folder: &Path, clip: &ProtocolClip) -> PathBuf {
+        folder.join(&clip.reference)
+    }
+
+    /// Path of the candidate of `clip` generated with `seed`.
+    pub fn candidate_path(&self, folder: &Path, clip: &ProtocolClip, seed: u64) -> PathBuf {
+        folder.join(clip.candidate.replace(SEED_PLACEHOLDER, &seed.to_string()))
+    }
+
+    /// The metrics of `candidate` against `reference`, each the mean over the windows, and the
+    /// number of windows.
+    pub fn evaluate(
+        &self,
+        reference: (&Skeleton, &Animation),
+        candidate: (&Skeleton, &Animation),
+        frame_time: f32,
+    ) -> Result<(usize, BTreeMap<Metric, f32>)> {
+        let frame_count = reference.1.frame_count();
+        if candidate.1.frame_count() != frame_count {
+            bail!(
+                "The reference has {} frames but the candidate {}",
+                frame_count,
+                candidate.1.frame_count()
+            );
+        }
+        let ranges: Vec<(usize, usize)> = match self.windows()? {
+            Some(windows) => windows
+                .starts(frame_count)
+                .into_iter()
+                .map(|start| (start, start + windows.length))
+                .collect(),
+            None => vec![(0, frame_count)],
+        };
+        if ranges.is_empty() {
+            bail!("{} frames are fewer than a window", frame_count);
+        }
+        let window = |(skeleton, animation): (&Skeleton, &Animation), (start, end)| {
+            let mut window = animation.resample((start..end).map(|frame| frame as f32));
+            self.alignment.apply(skeleton, &mut window);
+            window
+        };
+        let mut totals: BTreeMap<Metric, f32> = BTreeMap::new();
+        for &range in &ranges {
+            let metrics = MotionMetrics::compare(
+                reference.0,
+                &window(reference, range),
+                candidate.0,
+                &window(candidate, range),
+                frame_time,
+            )?;
+            for metric in &self.metrics {
+                *totals.entry(*metric).or_default() += metric.value(&metrics);
+            }
+        }
+        for total in totals.values_mut() {
+            *total /= ranges.len() as f32;
+        }
+        Ok((ranges.len(), totals))
+    }
+}
+
+/// The metrics of the candidate of one seed of one clip.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ClipResult {
+    pub reference: String,
+    pub candidate: String,
+    pub seed: u64,
+    pub windows: usize,
+    pub metrics: BTreeMap<Metric, f32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct MetricSummary {
+    pub mean: f32,
+    /// Standard deviation over the seeds.
+    pub std: f32,
+}
+
+/// Summary of every metric of `clips` over `seeds`.
+pub fn summarize(
+    metrics: &[Metric],
+    seeds: &[u64],
+    clips: &[ClipResult],
+) -> BTreeMap<Metric, MetricSummary> {
+    metrics
+        .iter()
+        .map(|metric| {
+            let per_seed: Vec<f32> = seeds
+                .iter()
+                .map(|seed| {
+                    let values: Vec<f32> = clips
+                        .iter()
+                        .filter(|clip| clip.seed == *seed)
+                        .filter_map(|clip| clip.metrics.get(metric).copied())
+                        .collect();
+                    values.iter().sum::<f32>() / values.len().max(1) as f32
+                })
+                .collect();
+            let count = per_seed.len().max(1) as f32;
+            let mean = per_seed.iter().sum::<f32>() / count;
+            let variance = per_seed
+                .iter()
+                .map(|value| (value - mean).powi(2))
+                .sum::<f32>()
+                / count;
+            (
+                *metric,
+                MetricSummary {
+                    mean,
+                    std: variance.sqrt(),
+                },
+            )
+        })
+        .collect()
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct EvaluationResults {
+    pub version: u32,
+    /// Version of `bvh_to_gav` that computed the results.
+    pub tool_version: String,
+    pub protocol: String,
+    /// Hex encoded SHA-256 of the protocol file.
+    pub protocol_sha256: String,
+    pub seeds: Vec<u64>,
+    pub alignment: Alignment,
+    pub windows: Option<String>,
+    pub summary: BTreeMap<Metric, MetricSummary>,
+    pub clips: Vec<ClipResult>,
+}
+
+impl EvaluationResults {
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(serde_json::from_reader(storage::open(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        storage::write_with(path, |writer| {
+            Ok(serde_json::to_writer_pretty(writer, self)?)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_math::{Quat, Vec3};
+
+    use super::*;
+    use crate::skeleton::SkeletonJoint;
+
+    #[test]
+    fn test_protocol_windows_align_and_summarize() {
+        let protocol: Protocol = toml::from_str(
+            r#"
+            name = "holdout"
+            seeds = [1, 2]
+            metrics = ["mpjpe"]
+            alignment = "translation"
+            windows = "4:2"
+
+            [[clips]]
+            reference = "test/walk.bvh"
+            candidate = "samples/seed_{seed}/walk.npy"
+            "#,
+        )
+        .unwrap();
+        let folder = Path::new("eval");
+        assert_eq!(
+            protocol.reference_path(folder, &protocol.clips[0]),
+            Path::new("eval/test/walk.bvh")
+        );
+        assert_eq!(
+            protocol.candidate_path(folder, &protocol.clips[0], 2),
+            Path::new("eval/samples/seed_2/walk.npy")
+        );
+        let defaults: Protocol = toml::from_str("name = \"all\"\nclips = []").unwrap();
+        assert_eq!(defaults.seeds, [0]);
+        assert_eq!(defaults.metrics, Metric::ALL);
+        assert_eq!(defaults.alignment, Alignment::None);
+
+        let skeleton = Skeleton {
+            joints: vec![SkeletonJoint {
+                name: "Hips".to_string(),
+                parent: None,
+                offset: Vec3::ZERO,
+                end_site: None,
+            }],
+        };
+        let reference = Animation {
+            root_positions: (0..9).map(|frame| Vec3::X * frame as f32).collect(),
+            joint_rotations: vec![vec![Quat::IDENTITY; 9]],
+            events: Vec::new(),
+        };
+        // The same motion somewhere else, which the alignment removes.
+        let mut candidate = reference.clone();
+        for position in &mut candidate.root_positions {
+            *position += Vec3::new(0.0, 0.0, 50.0);
+        }
+        let (windows, metrics) = protocol
+            .evaluate((&skeleton, &reference), (&skeleton, &candidate), 0.1)
+            .unwrap();
+        assert_eq!(windows, 3);
+        assert!(metrics[&Metric::Mpjpe] < 1e-4);
+        let unaligned = Protocol {
+            alignment: Alignment::None,
+            ..protocol.clone()
+        };
+        let (_, metrics) = unaligned
+            .evaluate((&skeleton, &reference), (&skeleton, &candidate), 0.1)
+            .unwrap();
+        assert!((metrics[&Metric::Mpjpe] - 50.0).abs() < 1e-3);
+
+        let result = |seed, mpjpe| ClipResult {
+            reference: "walk.bvh".to_string(),
+            candidate: format!("walk_{}.npy", seed),
+            seed,
+            windows: 1,
+            metrics: BTreeMap::from([(Metric::Mpjpe, mpjpe)]),
+        };
+        let summary = summarize(
+            &[Metric::Mpjpe],
+            &[1, 2],
+            &[result(1, 1.0), result(1, 3.0), result(2, 4.0)],
+        );
+        assert_eq!(
+            summary[&Metric::Mpjpe],
+            MetricSummary {
+                mean: 3.0,
+                std: 1.0
+            }
+        );
+    }
+}
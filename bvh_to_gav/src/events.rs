@@ -0,0 +1,67 @@
+//! Per-frame event markers of a clip (footsteps, claps, beats). BVH has no place for them, so
+//! they are stored next to the clip as `<name>.events.json`. A GAV tensor converted into the same
+//! folder shares the sidecar of its clip.
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::storage;
+
+pub const EVENTS_EXTENSION: &str = "events.json";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AnimationEvent {
+    pub frame: usize,
+    pub name: String,
+    /// Free form data of the event, e.g. which foot hit the ground.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<String>,
+}
+
+/// Path of the events sidecar of a clip or tensor.
+pub fn events_path(clip: &Path) -> PathBuf {
+    clip.with_extension(EVENTS_EXTENSION)
+}
+
+/// Parses an events sidecar, sorted by frame.
+pub fn parse_events(bytes: &[u8]) -> Result<Vec<AnimationEvent>> {
+    let mut events: Vec<AnimationEvent> = serde_json::from_slice(bytes)?;
+    events.sort_by_key(|event| event.frame);
+    Ok(events)
+}
+
+/// Reads the events sidecar at `path`. A clip without a sidecar has no events.
+pub fn read_events(path: &Path) -> Result<Vec<AnimationEvent>> {
+    if !storage::exists(path)? {
+        return Ok(Vec::new());
+    }
+    let bytes = storage::read(path)?;
+    parse_events(&bytes).with_context(|| format!("Invalid events in {}", path.display()))
+}
+
+pub fn write_events(path: &Path, events: &[AnimationEvent]) -> Result<()> {
+    storage::write_with(path, |writer| {
+        Ok(serde_json::to_writer_pretty(writer, events)?)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_events_sorts_by_frame() {
+        let events = parse_events(
+            br#"[
+                {"frame": 12, "name": "footstep", "payload": "right"},
+                {"frame": 3, "name": "clap"}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].name, "clap");
+        assert_eq!(events[0].payload, None);
+        assert_eq!(events[1].payload.as_deref(), Some("right"));
+    }
+}
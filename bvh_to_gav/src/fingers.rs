@@ -0,0 +1,373 @@
+//! Compact finger encoding for hand heavy datasets (sign language, gestures). Every finger is
+//! reduced to a curl and a spread angle instead of one rotation per finger joint. The curl is
+//! spread evenly over the joints of the finger when decoding, twist is dropped.
+//!
+//! The axes are fitted once per skeleton, over all the clips of a dataset, so every clip of it
+//! encodes a curl with the same axis and sign.
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use bevy_math::{Quat, Vec2, Vec3};
+use ndarray::{Array3, Axis, s};
+use serde::{Deserialize, Serialize};
+
+use crate::{Animation, animation_to_gav, append_curve, gav_to_animation, skeleton::Skeleton};
+
+const FINGER_NAMES: [&str; 6] = ["thumb", "index", "middle", "ring", "pinky", "little"];
+const POWER_ITERATIONS: usize = 32;
+
+pub(crate) fn finger_of(name: &str) -> Option<&'static str> {
+    let name = name.to_lowercase();
+    FINGER_NAMES
+        .into_iter()
+        .find(|finger| name.contains(finger))
+}
+
+/// The joints of one finger, from the knuckle to the tip, with the local axes its curl and
+/// spread rotate about.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FingerChain {
+    /// Name of the knuckle joint.
+    pub name: String,
+    pub joints: Vec<usize>,
+    pub curl_axis: Vec3,
+    pub spread_axis: Vec3,
+}
+
+/// Finger chains of a skeleton, stored as a `<clip>.fingers.json` sidecar next to a tensor
+/// encoded with [`FingerEncoding::encode_gav`].
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct FingerEncoding {
+    /// Joint count of the full skeleton.
+    pub joint_count: usize,
+    pub chains: Vec<FingerChain>,
+}
+
+pub const FINGERS_EXTENSION: &str = "fingers.json";
+
+/// Path of the finger encoding sidecar of a clip.
+pub fn fingers_path(clip: &Path) -> PathBuf {
+    clip.with_extension(FINGERS_EXTENSION)
+}
+
+/// Finger chains of a skeleton, found by joint name. A chain starts at a finger joint whose
+/// parent belongs to another finger (or none) and follows the children of the same finger.
+pub fn finger_chains(skeleton: &Skeleton) -> Vec<Vec<usize>> {
+    let mut chains = Vec::new();
+    for (index, joint) in skeleton.joints.iter().enumerate() {
+        let Some(finger) = finger_of(&joint.name) else {
+            continue;
+        };
+        let parent_finger = joint
+            .parent
+            .and_then(|parent| finger_of(&skeleton.joints[parent].name));
+        if parent_finger == Some(finger) {
+            continue;
+        }
+        let mut chain = vec![index];
+        while let Some(child) = skeleton
+            .children(*chain.last().unwrap())
+            .find(|&child| finger_of(&skeleton.joints[child].name) == Some(finger))
+        {
+            chain.push(child);
+        }
+        chains.push(chain);
+    }
+    chains
+}
+
+/// Direction of the first bone of a chain, in the local space of its knuckle.
+fn bone_direction(skeleton: &Skeleton, chain: &[usize]) -> Vec3 {
+    let knuckle = &skeleton.joints[chain[0]];
+    chain
+        .get(1)
+        .map(|&next| skeleton.joints[next].offset)
+        .or(knuckle.end_site)
+        .and_then(|offset| offset.try_normalize())
+        .unwrap_or(Vec3::X)
+}
+
+/// Dominant rotation axis of the joints of a chain, found by power iteration on the covariance
+/// of their scaled axes, starting from the largest rotation. Falls back to `fallback` for
+/// fingers that don't move.
+fn dominant_axis(rotations: impl Iterator<Item = Quat>, fallback: Vec3) -> Vec3 {
+    let (mut xx, mut yy, mut zz) = (Vec3::ZERO, Vec3::ZERO, Vec3::ZERO);
+    let mut largest = Vec3::ZERO;
+    for rotation in rotations {
+        let v = rotation.to_scaled_axis();
+        xx += v * v.x;
+        yy += v * v.y;
+        zz += v * v.z;
+        if v.length_squared() > largest.length_squared() {
+            largest = v;
+        }
+    }
+    let Some(mut axis) = largest.try_normalize() else {
+        return fallback;
+    };
+    for _ in 0..POWER_ITERATIONS {
+        let next = xx * axis.x + yy * axis.y + zz * axis.z;
+        match next.try_normalize() {
+            Some(next) => axis = next,
+            None => return fallback,
+        }
+    }
+    axis
+}
+
+/// `axis` or its opposite, whichever has a positive largest component, as the dominant axis of
+/// a motion has no sign of its own.
+fn canonical_sign(axis: Vec3) -> Vec3 {
+    let largest = axis
+        .to_array()
+        .into_iter()
+        .max_by(|a, b| a.abs().total_cmp(&b.abs()))
+        .unwrap_or_default();
+    if largest < 0.0 { -axis } else { axis }
+}
+
+impl FingerEncoding {
+    /// Finds the finger chains of `skeleton` and fits their curl axes to the motion in all of
+    /// `animations`, clips of that skeleton. The spread axis is perpendicular to the curl axis
+    /// and the finger.
+    pub fn fit(skeleton: &Skeleton, animations: &[Animation]) -> Self {
+        let chains = finger_chains(skeleton)
+            .into_iter()
+            .map(|joints| {
+                let bone = bone_direction(skeleton, &joints);
+                let fallback = bone.any_orthonormal_vector();
+                let rotations = animations.iter().flat_map(|animation| {
+                    joints
+                        .iter()
+                        .flat_map(|&joint| animation.joint_rotations[joint].iter().copied())
+                });
+                let axis = dominant_axis(rotations, fallback);
+                let curl_axis = canonical_sign(
+                    (axis - bone * axis.dot(bone))
+                        .try_normalize()
+                        .unwrap_or(fallback),
+                );
+                FingerChain {
+                    name: skeleton.joints[joints[0]].name.clone(),
+                    joints,
+                    curl_axis,
+                    spread_axis: bone.cross(curl_axis).normalize(),
+                }
+            })
+            .collect();
+        FingerEncoding {
+            joint_count: skeleton.joint_count(),
+            chains,
+        }
+    }
+
+    /// Whether the encoding was fitted to `skeleton`, or a skeleton with the same fingers.
+    pub fn fits(&self, skeleton: &Skeleton) -> bool {
+        self.joint_count == skeleton.joint_count()
+            && self
+                .chains
+                .iter()
+                .all(|chain| skeleton.joints[chain.joints[0]].name == chain.name)
+    }
+
+    /// All joints covered by the encoding, which can be left out of the tensor.
+    pub fn finger_joints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.chains
+            .iter()
+            .flat_map(|chain| chain.joints.iter().copied())
+    }
+
+    /// Curl (x) and spread (y) in radians of every finger in every frame.
+    pub fn encode(&self, animation: &Animation) -> Vec<Vec<Vec2>> {
+        self.chains
+            .iter()
+            .map(|chain| {
+                (0..animation.frame_count())
+                    .map(|frame| {
+                        let angles = chain
+                            .joints
+                            .iter()
+                            .map(|&joint| animation.joint_rotations[joint][frame].to_scaled_axis());
+                        let curl = angles.clone().map(|v| v.dot(chain.curl_axis)).sum::<f32>()
+                            / chain.joints.len() as f32;
+                        let spread = angles
+                            .take(1)
+                            .map(|v| v.dot(chain.spread_axis))
+                            .sum::<f32>();
+                        Vec2::new(curl, spread)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Writes the finger joint rotations described by `parameters` (as returned by
+    /// [`FingerEncoding::encode`]) into `animation`.
+    pub fn decode(&self, parameters: &[Vec<Vec2>], animation: &mut Animation) {
+        for (chain, parameters) in self.chains.iter().zip(parameters) {
+            for (frame, parameter) in parameters.iter().enumerate() {
+                let curl = Quat::from_axis_angle(chain.curl_axis, parameter.x);
+                let spread = Quat::from_axis_angle(chain.spread_axis, parameter.y);
+                for (i, &joint) in chain.joints.iter().enumerate() {
+                    let rotation = if i == 0 { spread * curl } else { curl };
+                    if let Some(value) = animation.joint_rotations[joint].get_mut(frame) {
+                        *value = rotation;
+                    }
+                }
+            }
+        }
+    }
+
+    /// GAV tensor of `animation` with the finger joints left out and one `[curl, spread, 0]`
+    /// curve per finger appended after the remaining joints.
+    pub fn encode_gav(&self, animation: &Animation) -> Result<Array3<f32>> {
+        let mut kept = Animation {
+            root_positions: animation.root_positions.clone(),
+            joint_rotations: Vec::new(),
+            events: Vec::new(),
+        };
+        kept.joint_rotations = (0..animation.joint_count())
+            .filter(|joint| !self.finger_joints().any(|finger| finger == *joint))
+            .map(|joint| animation.joint_rotations[joint].clone())
+            .collect();
+        let mut gav_data = animation_to_gav(&kept)?;
+        for parameters in self.encode(animation) {
+            let curve: Vec<Vec3> = parameters.iter().map(|p| p.extend(0.0)).collect();
+            gav_data = append_curve(&gav_data, &curve)?;
+        }
+        Ok(gav_data)
+    }
+
+    /// Rebuilds the full animation from a tensor written by [`FingerEncoding::encode_gav`].
+    pub fn decode_gav(&self, gav_data: Array3<f32>) -> Result<Animation> {
+        let finger_count = self.chains.len();
+        let kept_count = self.joint_count - self.finger_joints().count();
+        if gav_data.dim().0 != 1 + kept_count + finger_count {
+            bail!(
+                "Expected {} curves for {} joints and {} fingers, found {}",
+                1 + kept_count + finger_count,
+                kept_count,
+                finger_count,
+                gav_data.dim().0
+            );
+        }
+        let parameters: Vec<Vec<Vec2>> = gav_data
+            .slice(s![1 + kept_count.., .., ..])
+            .axis_iter(Axis(0))
+            .map(|curve| {
+                curve
+                    .axis_iter(Axis(0))
+                    .map(|value| Vec2::new(value[0], value[1]))
+                    .collect()
+            })
+            .collect();
+        let kept = gav_to_animation(gav_data.slice(s![..1 + kept_count, .., ..]).to_owned())?;
+
+        let mut kept_rotations = kept.joint_rotations.into_iter();
+        let identity = vec![Quat::IDENTITY; kept.root_positions.len()];
+        let mut animation = Animation {
+            joint_rotations: (0..self.joint_count)
+                .map(|joint| {
+                    if self.finger_joints().any(|finger| finger == joint) {
+                        identity.clone()
+                    } else {
+                        kept_rotations.next().unwrap()
+                    }
+                })
+                .collect(),
+            root_positions: kept.root_positions,
+            events: Vec::new(),
+        };
+        self.decode(&parameters, &mut animation);
+        Ok(animation)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("Could not open {}", path.display()))?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), self)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::skeleton::SkeletonJoint;
+
+    fn hand() -> Skeleton {
+        let joint = |name: &str, parent| SkeletonJoint {
+            name: name.to_string(),
+            parent,
+            offset: Vec3::X * 3.0,
+            end_site: None,
+        };
+        Skeleton {
+            joints: vec![
+                joint("LeftHand", None),
+                joint("LeftHandIndex1", Some(0)),
+                joint("LeftHandIndex2", Some(1)),
+                joint("LeftHandIndex3", Some(2)),
+                joint("LeftHandThumb1", Some(0)),
+                joint("LeftHandThumb2", Some(4)),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_finger_chains_group_joints_by_finger() {
+        assert_eq!(finger_chains(&hand()), vec![vec![1, 2, 3], vec![4, 5]]);
+    }
+
+    #[test]
+    fn test_curl_round_trip() {
+        let skeleton = hand();
+        let curls = [0.0f32, 0.4, 0.8, 1.2];
+        let curl = |angle: f32| Quat::from_rotation_z(angle);
+        let mut animation = Animation {
+            root_positions: vec![Vec3::ZERO; curls.len()],
+            joint_rotations: vec![vec![Quat::IDENTITY; curls.len()]; 6],
+            events: vec![],
+        };
+        for joint in 1..4 {
+            animation.joint_rotations[joint] = curls.iter().map(|&a| curl(a)).collect();
+        }
+
+        let encoding = FingerEncoding::fit(&skeleton, std::slice::from_ref(&animation));
+        let index = &encoding.chains[0];
+        assert!(index.curl_axis.dot(Vec3::Z) > 0.999);
+        assert!(encoding.fits(&skeleton));
+
+        // A clip curling the other way fits the same axis.
+        let mut opposite = animation.clone();
+        for joint in 1..4 {
+            opposite.joint_rotations[joint] = curls.iter().map(|&a| curl(-a)).collect();
+        }
+        let fitted = FingerEncoding::fit(&skeleton, &[opposite]);
+        assert!(fitted.chains[0].curl_axis.dot(Vec3::Z) > 0.999);
+
+        let parameters = encoding.encode(&animation);
+        let mut decoded = Animation {
+            root_positions: animation.root_positions.clone(),
+            joint_rotations: vec![vec![Quat::IDENTITY; curls.len()]; 6],
+            events: vec![],
+        };
+        encoding.decode(&parameters, &mut decoded);
+        for joint in 1..4 {
+            for (original, decoded) in animation.joint_rotations[joint]
+                .iter()
+                .zip(&decoded.joint_rotations[joint])
+            {
+                assert!(original.angle_between(*decoded) < 1e-4);
+            }
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! K-fold cross-validation assignment of the clips of a [`crate::manifest::Manifest`]. Clips
+//! are grouped, each on its own or by the subject that performed them so no performer is in
+//! both the training and the validation folds, and the groups are spread over the folds largest
+//! first, each into the fold with the fewest clips so far. The assignment only depends on the
+//! clips and their subjects, so running it again gives the same folds.
+use std::{collections::BTreeMap, str::FromStr};
+
+use anyhow::{Error, Result, bail};
+use serde::{Deserialize, Serialize};
+
+/// What is kept together in one fold.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FoldGrouping {
+    #[default]
+    Clip,
+    /// Clips of the same `subject` metadata field. Clips without one are grouped on their own.
+    Subject,
+}
+
+impl FromStr for FoldGrouping {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "clip" => Ok(FoldGrouping::Clip),
+            "subject" => Ok(FoldGrouping::Subject),
+            _ => bail!("Unknown fold grouping {}, expected clip or subject", s),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FoldAssignment {
+    pub clip: String,
+    pub fold: usize,
+}
+
+/// Assigns `clips`, as `(clip, subject)`, to `k` folds.
+pub fn assign_folds(
+    clips: &[(String, Option<String>)],
+    k: usize,
+    grouping: FoldGrouping,
+) -> Result<Vec<FoldAssignment>> {
+    if k < 2 {
+        bail!("Cross-validation needs at least 2 folds, got {}", k);
+    }
+    // Keyed by whether the group is a subject too, so a clip named like a subject stays apart.
+    let mut groups: BTreeMap<(bool, &str), Vec<&str>> = BTreeMap::new();
+    for (clip, subject) in clips {
+        let key = match (grouping, subject) {
+            (FoldGrouping::Subject, Some(subject)) if !subject.is_empty() => {
+                (true, subject.as_str())
+            }
+            _ => (false, clip.as_str()),
+        };
+        groups.entry(key).or_default().push(clip);
+    }
+    if groups.len() < k {
+        bail!("{} groups can't be split into {} folds", groups.len(), k);
+    }
+
+    let mut groups: Vec<Vec<&str>> = groups.into_values().collect();
+    // Stable, so groups of the same size keep their name order.
+    groups.sort_by_key(|group| std::cmp::Reverse(group.len()));
+    let mut sizes = vec![0; k];
+    let mut folds: BTreeMap<&str, usize> = BTreeMap::new();
+    for group in groups {
+        let fold = (0..k).min_by_key(|&fold| sizes[fold]).unwrap_or_default();
+        sizes[fold] += group.len();
+        folds.extend(group.into_iter().map(|clip| (clip, fold)));
+    }
+    Ok(clips
+        .iter()
+        .map(|(clip, _)| FoldAssignment {
+            clip: clip.clone(),
+            fold: folds[clip.as_str()],
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subjects_stay_in_one_fold() {
+        let clip =
+            |name: &str, subject: Option<&str>| (name.to_string(), subject.map(str::to_string));
+        let clips = vec![
+            clip("a1", Some("anna")),
+            clip("a2", Some("anna")),
+            clip("a3", Some("anna")),
+            clip("b1", Some("ben")),
+            clip("b2", Some("ben")),
+            clip("c1", Some("cleo")),
+            clip("x", None),
+        ];
+        let folds = assign_folds(&clips, 3, FoldGrouping::Subject).unwrap();
+        let fold = |name: &str| folds.iter().find(|f| f.clip == name).unwrap().fold;
+        assert_eq!(fold("a1"), fold("a3"));
+        assert_eq!(fold("b1"), fold("b2"));
+        assert_ne!(fold("a1"), fold("b1"));
+        // The two single clips share the fold left over by the larger subjects.
+        assert_eq!(fold("c1"), fold("x"));
+        assert_eq!(
+            folds,
+            assign_folds(&clips, 3, FoldGrouping::Subject).unwrap()
+        );
+
+        let by_clip = assign_folds(&clips, 3, FoldGrouping::Clip).unwrap();
+        let mut sizes = [0; 3];
+        by_clip.iter().for_each(|f| sizes[f.fold] += 1);
+        assert_eq!(sizes, [3, 2, 2]);
+        assert!(assign_folds(&clips, 1, FoldGrouping::Clip).is_err());
+        assert!(assign_folds(&clips, 5, FoldGrouping::Subject).is_err());
+    }
+}
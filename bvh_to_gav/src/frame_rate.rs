@@ -0,0 +1,131 @@
+//! Frame rate consistency of a dataset. Clips captured at 30, 60 or 120 fps are resampled to
+//! the most common rate so that a training window of N frames always covers the same time.
+use std::{fs::File, io::BufWriter, path::Path};
+
+use anyhow::{Result, bail};
+use serde::Serialize;
+
+use crate::Animation;
+
+/// File name of the report written next to a unified dataset.
+pub const FRAME_RATE_REPORT_FILE: &str = "frame_rates.json";
+
+/// Frame rate of one clip, as listed in a [`FrameRateReport`].
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ClipFrameRate {
+    pub clip: String,
+    pub fps: f32,
+    pub frame_count: usize,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct FrameRateReport {
+    /// Most common frame rate of the dataset, rounded to whole frames per second.
+    pub modal_fps: f32,
+    pub clips: Vec<ClipFrameRate>,
+}
+
+/// Rounds a frame time to its frame rate, absorbing the precision of the BVH header so that
+/// `0.033333` and `0.0333333` count as the same rate.
+pub fn frame_rate(frame_time: f32) -> f32 {
+    (1.0 / frame_time).round()
+}
+
+impl FrameRateReport {
+    /// Groups `clips` as `(name, frame_time, frame_count)` by frame rate. Ties between rates
+    /// go to the higher one, which loses no motion when the others are resampled.
+    pub fn new(clips: impl IntoIterator<Item = (String, f32, usize)>) -> Result<Self> {
+        let clips: Vec<ClipFrameRate> = clips
+            .into_iter()
+            .map(|(clip, frame_time, frame_count)| ClipFrameRate {
+                clip,
+                fps: frame_rate(frame_time),
+                frame_count,
+            })
+            .collect();
+        if let Some(clip) = clips
+            .iter()
+            .find(|clip| !clip.fps.is_finite() || clip.fps <= 0.0)
+        {
+            bail!("{} has an invalid frame time", clip.clip);
+        }
+
+        let mut rates: Vec<f32> = clips.iter().map(|clip| clip.fps).collect();
+        rates.sort_by(f32::total_cmp);
+        let modal_fps = rates
+            .chunk_by(|a, b| a == b)
+            .max_by_key(|group| group.len())
+            .map(|group| group[0]);
+        let Some(modal_fps) = modal_fps else {
+            bail!("No clips to compare");
+        };
+        Ok(FrameRateReport { modal_fps, clips })
+    }
+
+    /// Clips whose frame rate differs from the modal one.
+    pub fn outliers(&self) -> impl Iterator<Item = &ClipFrameRate> {
+        self.clips.iter().filter(|clip| clip.fps != self.modal_fps)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), self)?;
+        Ok(())
+    }
+}
+
+/// Resamples `animation` from `frame_time` to `target_frame_time`, keeping its duration.
+/// Events move to the nearest frame at the new rate.
+pub fn resample_frame_time(
+    animation: &Animation,
+    frame_time: f32,
+    target_frame_time: f32,
+) -> Animation {
+    let duration = animation.frame_count().saturating_sub(1) as f32 * frame_time;
+    let frame_count = (duration / target_frame_time).round() as usize + 1;
+    let scale = target_frame_time / frame_time;
+    let mut resampled = animation.resample((0..frame_count).map(|frame| frame as f32 * scale));
+    resampled.events = animation
+        .events
+        .iter()
+        .map(|event| {
+            let mut event = event.clone();
+            event.frame = ((event.frame as f32 / scale).round() as usize).min(frame_count - 1);
+            event
+        })
+        .collect();
+    resampled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy_math::{Quat, Vec3};
+
+    #[test]
+    fn test_outliers_are_resampled_to_modal_rate() {
+        let report = FrameRateReport::new([
+            ("a.bvh".to_string(), 1.0 / 30.0, 31),
+            ("b.bvh".to_string(), 0.033333, 61),
+            ("c.bvh".to_string(), 1.0 / 120.0, 121),
+        ])
+        .unwrap();
+        assert_eq!(report.modal_fps, 30.0);
+        let outliers: Vec<&str> = report.outliers().map(|clip| clip.clip.as_str()).collect();
+        assert_eq!(outliers, vec!["c.bvh"]);
+
+        // One second at 120 fps, the root moving one unit per frame.
+        let animation = Animation {
+            root_positions: (0..121).map(|i| Vec3::new(i as f32, 0.0, 0.0)).collect(),
+            joint_rotations: vec![vec![Quat::IDENTITY; 121]],
+            events: vec![crate::events::AnimationEvent {
+                frame: 60,
+                name: "step".to_string(),
+                payload: None,
+            }],
+        };
+        let resampled = resample_frame_time(&animation, 1.0 / 120.0, 1.0 / 30.0);
+        assert_eq!(resampled.frame_count(), 31);
+        assert!((resampled.root_positions[30].x - 120.0).abs() < 1e-3);
+        assert_eq!(resampled.events[0].frame, 15);
+    }
+}
@@ -0,0 +1,157 @@
+//! Static HTML gallery of a dataset, browsable without running the preview app.
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::events::AnimationEvent;
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct GalleryClip {
+    pub name: String,
+    pub frame_count: usize,
+    /// Duration in seconds, when the frame time of the clip is known.
+    pub duration: Option<f32>,
+    /// Path of the thumbnail relative to the gallery folder.
+    pub thumbnail: Option<String>,
+    pub labels: Vec<String>,
+    pub metadata: BTreeMap<String, String>,
+    pub events: Vec<AnimationEvent>,
+}
+
+/// Reads the optional `<name>.meta.json` sidecar of a clip, the format used before
+/// [`crate::metadata`]. `labels` becomes the list of labels
+/// the gallery filters on, every other field is shown as metadata.
+pub fn read_clip_metadata(path: &Path) -> Result<(Vec<String>, BTreeMap<String, String>)> {
+    let mut labels = Vec::new();
+    let mut metadata = BTreeMap::new();
+    if !path.exists() {
+        return Ok((labels, metadata));
+    }
+
+    let value: serde_json::Value = serde_json::from_reader(fs::File::open(path)?)?;
+    if let serde_json::Value::Object(fields) = value {
+        for (key, value) in fields {
+            match (key.as_str(), value) {
+                ("labels", serde_json::Value::Array(values)) => {
+                    labels.extend(values.iter().filter_map(|v| v.as_str()).map(String::from));
+                }
+                (_, serde_json::Value::String(value)) => {
+                    metadata.insert(key, value);
+                }
+                (_, value) => {
+                    metadata.insert(key, value.to_string());
+                }
+            }
+        }
+    }
+    Ok((labels, metadata))
+}
+
+/// Writes `index.html` into `output_folder`. The clip list is embedded as JSON and filtered
+/// client side, so the gallery works straight from the file system.
+pub fn write_gallery(title: &str, clips: &[GalleryClip], output_folder: &Path) -> Result<()> {
+    fs::create_dir_all(output_folder)?;
+    // `</` would end the script element the data is embedded in.
+    let data = serde_json::to_string(clips)?.replace("</", "<\\/");
+    let html = TEMPLATE
+        .replace("{{title}}", &escape_html(title))
+        .replace("{{clips}}", &data);
+    fs::write(output_folder.join("index.html"), html)?;
+    Ok(())
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{{title}}</title>
+<style>
+body { font-family: sans-serif; margin: 2em; background: #1e1e1e; color: #ddd; }
+#filters button { margin: 0 0.3em 0.3em 0; background: #333; color: #ddd; border: 1px solid #555; border-radius: 1em; padding: 0.2em 0.8em; cursor: pointer; }
+#filters button.active { background: #4a7; color: #fff; }
+#search { margin-bottom: 1em; padding: 0.3em; width: 20em; }
+#clips { display: flex; flex-wrap: wrap; gap: 1em; }
+.clip { background: #2a2a2a; border-radius: 4px; padding: 0.5em; width: 320px; }
+.clip img { width: 100%; background: #000; }
+.clip h3 { font-size: 0.9em; margin: 0.3em 0; word-break: break-all; }
+.clip table { font-size: 0.75em; }
+.label { display: inline-block; background: #444; border-radius: 0.5em; padding: 0 0.4em; margin: 0 0.2em 0.2em 0; font-size: 0.75em; }
+</style>
+</head>
+<body>
+<h1>{{title}}</h1>
+<input id="search" placeholder="Search clips">
+<div id="filters"></div>
+<p id="count"></p>
+<div id="clips"></div>
+<script>
+const clips = {{clips}};
+const active = new Set();
+const text = (tag, content) => { const e = document.createElement(tag); e.textContent = content; return e; };
+
+function render() {
+  const query = document.getElementById("search").value.toLowerCase();
+  const container = document.getElementById("clips");
+  container.replaceChildren();
+  const visible = clips.filter(c =>
+    [...active].every(l => c.labels.includes(l)) && c.name.toLowerCase().includes(query));
+  for (const clip of visible) {
+    const card = document.createElement("div");
+    card.className = "clip";
+    if (clip.thumbnail) {
+      const img = document.createElement("img");
+      img.src = clip.thumbnail;
+      img.loading = "lazy";
+      card.appendChild(img);
+    }
+    card.appendChild(text("h3", clip.name));
+    for (const label of clip.labels) {
+      const chip = text("span", label);
+      chip.className = "label";
+      card.appendChild(chip);
+    }
+    const table = document.createElement("table");
+    const rows = [["frames", clip.frame_count]];
+    if (clip.duration !== null) rows.push(["duration", clip.duration.toFixed(2) + " s"]);
+    rows.push(...Object.entries(clip.metadata));
+    if (clip.events.length > 0) {
+      const counts = {};
+      for (const event of clip.events) counts[event.name] = (counts[event.name] || 0) + 1;
+      rows.push(["events", Object.entries(counts).map(([name, count]) => name + " \u00d7" + count).join(", ")]);
+    }
+    for (const [key, value] of rows) {
+      const row = document.createElement("tr");
+      row.appendChild(text("td", key));
+      row.appendChild(text("td", value));
+      table.appendChild(row);
+    }
+    card.appendChild(table);
+    container.appendChild(card);
+  }
+  document.getElementById("count").textContent = visible.length + " of " + clips.length + " clips";
+}
+
+const labels = [...new Set(clips.flatMap(c => c.labels))].sort();
+for (const label of labels) {
+  const button = text("button", label);
+  button.onclick = () => {
+    if (active.has(label)) active.delete(label); else active.add(label);
+    button.classList.toggle("active");
+    render();
+  };
+  document.getElementById("filters").appendChild(button);
+}
+document.getElementById("search").oninput = render;
+render();
+</script>
+</body>
+</html>
+"#;
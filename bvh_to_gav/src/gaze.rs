@@ -0,0 +1,104 @@
+//! Gaze direction and look-at target derived from the orientation of the head, used as
+//! conditioning for conversational agent animation.
+use bevy_math::Vec3;
+
+use crate::{Animation, kinematics::global_transforms, skeleton::Skeleton};
+
+/// Axis the head looks along in its rest pose, for skeletons facing +Z.
+pub const DEFAULT_HEAD_FORWARD: Vec3 = Vec3::Z;
+/// Distance of the look-at target in front of the head, in skeleton units (usually cm).
+pub const DEFAULT_GAZE_DISTANCE: f32 = 100.0;
+
+/// Whether a joint name looks like a head joint, excluding end joints such as `HeadTop_End`.
+pub fn is_head_name(name: &str) -> bool {
+    let name = name.to_lowercase();
+    name.contains("head") && !name.contains("end") && !name.contains("top")
+}
+
+/// Finds the head joint by name, preferring a joint called exactly `Head`.
+pub fn find_head(skeleton: &Skeleton) -> Option<usize> {
+    skeleton
+        .joint_order()
+        .position(|name| name.eq_ignore_ascii_case("head"))
+        .or_else(|| skeleton.joint_order().position(is_head_name))
+}
+
+pub struct Gaze {
+    /// World position of the head in every frame.
+    pub origins: Vec<Vec3>,
+    /// Unit gaze direction in every frame.
+    pub directions: Vec<Vec3>,
+}
+
+impl Gaze {
+    /// Look-at target `distance` in front of the head in every frame.
+    pub fn targets(&self, distance: f32) -> Vec<Vec3> {
+        self.origins
+            .iter()
+            .zip(&self.directions)
+            .map(|(origin, direction)| origin + direction * distance)
+            .collect()
+    }
+}
+
+/// Gaze of every frame, pointing along `forward` in the local space of the `head` joint.
+pub fn compute_gaze(
+    skeleton: &Skeleton,
+    animation: &Animation,
+    head: usize,
+    forward: Vec3,
+) -> Gaze {
+    let (origins, directions) = (0..animation.frame_count())
+        .map(|frame| {
+            let (position, rotation) = global_transforms(skeleton, animation, frame)[head];
+            (position, (rotation * forward).normalize_or_zero())
+        })
+        .unzip();
+    Gaze {
+        origins,
+        directions,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_math::Quat;
+
+    use super::*;
+    use crate::skeleton::SkeletonJoint;
+
+    #[test]
+    fn test_gaze_follows_neck_rotation() {
+        let joint = |name: &str, parent, offset| SkeletonJoint {
+            name: name.to_string(),
+            parent,
+            offset,
+            end_site: None,
+        };
+        let skeleton = Skeleton {
+            joints: vec![
+                joint("Hips", None, Vec3::ZERO),
+                joint("Neck", Some(0), Vec3::Y * 50.0),
+                joint("Head", Some(1), Vec3::Y * 10.0),
+                joint("HeadTop_End", Some(2), Vec3::Y * 10.0),
+            ],
+        };
+        assert_eq!(find_head(&skeleton), Some(2));
+
+        // The neck turns the head 90 degrees to the left.
+        let animation = Animation {
+            root_positions: vec![Vec3::new(0.0, 100.0, 0.0)],
+            joint_rotations: vec![
+                vec![Quat::IDENTITY],
+                vec![Quat::from_rotation_y(90f32.to_radians())],
+                vec![Quat::IDENTITY],
+                vec![Quat::IDENTITY],
+            ],
+            events: vec![],
+        };
+        let gaze = compute_gaze(&skeleton, &animation, 2, DEFAULT_HEAD_FORWARD);
+        assert!(gaze.origins[0].distance(Vec3::new(0.0, 160.0, 0.0)) < 1e-4);
+        assert!(gaze.directions[0].distance(Vec3::X) < 1e-4);
+        assert!(gaze.targets(100.0)[0].distance(Vec3::new(100.0, 160.0, 0.0)) < 1e-3);
+    }
+}
@@ -0,0 +1,259 @@
+//! A whole converted dataset packed into one HDF5 file, so training on tens of thousands of
+//! clips opens one file instead of three per clip. Needs bvh_to_gav built with the `hdf5`
+//! feature and the HDF5 library installed. The layout is
+//!
+//! - `/` with the attributes `format` (`animgen`) and `version`
+//! - `/clips/<clip>/gav`: the f32 GAV tensor of the clip, `(curves, frames, 3)`, denormalized
+//! - attributes of `/clips/<clip>`: `frame_time` and `fps` (f32), `joint_names` (strings),
+//!   `joint_parents` (i64, -1 for roots), `joint_offsets` and `end_sites` (f32, `(joints, 3)`,
+//!   NaN for joints without an end site)
+use std::path::Path;
+
+use anyhow::{Result, bail};
+use ndarray::{Array1, Array2, Array3};
+
+use crate::skeleton::Skeleton;
+
+pub const HDF5_FORMAT: &str = "animgen";
+pub const HDF5_VERSION: u32 = 1;
+
+/// The skeleton of a clip as the arrays stored in its attributes: parents, offsets and end
+/// sites.
+fn skeleton_arrays(skeleton: &Skeleton) -> Result<(Array1<i64>, Array2<f32>, Array2<f32>)> {
+    let parents = skeleton
+        .joints
+        .iter()
+        .map(|joint| joint.parent.map_or(-1, |parent| parent as i64))
+        .collect();
+    let offsets = Array2::from_shape_vec(
+        (skeleton.joint_count(), 3),
+        skeleton
+            .joints
+            .iter()
+            .flat_map(|joint| joint.offset.to_array())
+            .collect(),
+    )?;
+    let end_sites = Array2::from_shape_vec(
+        (skeleton.joint_count(), 3),
+        skeleton
+            .joints
+            .iter()
+            .flat_map(|joint| joint.end_site.map_or([f32::NAN; 3], |site| site.to_array()))
+            .collect(),
+    )?;
+    Ok((parents, offsets, end_sites))
+}
+
+/// Writes clips one at a time into a new HDF5 file.
+pub struct Hdf5Writer {
+    inner: imp::Writer,
+}
+
+impl Hdf5Writer {
+    pub fn create(path: &Path) -> Result<Self> {
+        Ok(Hdf5Writer {
+            inner: imp::Writer::create(path)?,
+        })
+    }
+
+    /// Adds the group of the clip `id`. Ids have to be unique and can't contain `/`.
+    pub fn add_clip(
+        &mut self,
+        id: &str,
+        data: &Array3<f32>,
+        skeleton: &Skeleton,
+        frame_time: f32,
+    ) -> Result<()> {
+        if id.is_empty() || id.contains('/') {
+            bail!("Invalid clip id {:?}", id);
+        }
+        let (parents, offsets, end_sites) = skeleton_arrays(skeleton)?;
+        self.inner.add_clip(
+            id,
+            data,
+            skeleton,
+            frame_time,
+            (&parents, &offsets, &end_sites),
+        )
+    }
+}
+
+#[cfg(feature = "hdf5")]
+mod imp {
+    use std::path::Path;
+
+    use anyhow::{Context, Result};
+    use hdf5::types::VarLenUnicode;
+    use ndarray::{Array1, Array2, Array3};
+
+    use super::{HDF5_FORMAT, HDF5_VERSION};
+    use crate::{frame_rate::frame_rate, skeleton::Skeleton};
+
+    pub struct Writer {
+        clips: hdf5::Group,
+    }
+
+    fn unicode(text: &str) -> Result<VarLenUnicode> {
+        text.parse()
+            .map_err(|e| anyhow::anyhow!("Could not store {:?}: {:?}", text, e))
+    }
+
+    impl Writer {
+        pub fn create(path: &Path) -> Result<Self> {
+            let file = hdf5::File::create(path)
+                .with_context(|| format!("Could not create {}", path.display()))?;
+            file.new_attr::<VarLenUnicode>()
+                .create("format")?
+                .write_scalar(&unicode(HDF5_FORMAT)?)?;
+            file.new_attr::<u32>()
+                .create("version")?
+                .write_scalar(&HDF5_VERSION)?;
+            Ok(Writer {
+                clips: file.create_group("clips")?,
+            })
+        }
+
+        pub fn add_clip(
+            &mut self,
+            id: &str,
+            data: &Array3<f32>,
+            skeleton: &Skeleton,
+            frame_time: f32,
+            (parents, offsets, end_sites): (&Array1<i64>, &Array2<f32>, &Array2<f32>),
+        ) -> Result<()> {
+            let group = self.clips.create_group(id)?;
+            group.new_dataset_builder().with_data(data).create("gav")?;
+            group
+                .new_attr::<f32>()
+                .create("frame_time")?
+                .write_scalar(&frame_time)?;
+            group
+                .new_attr::<f32>()
+                .create("fps")?
+                .write_scalar(&frame_rate(frame_time))?;
+            let names = skeleton
+                .joint_order()
+                .map(unicode)
+                .collect::<Result<Array1<VarLenUnicode>>>()?;
+            group
+                .new_attr_builder()
+                .with_data(&names)
+                .create("joint_names")?;
+            group
+                .new_attr_builder()
+                .with_data(parents)
+                .create("joint_parents")?;
+            group
+                .new_attr_builder()
+                .with_data(offsets)
+                .create("joint_offsets")?;
+            group
+                .new_attr_builder()
+                .with_data(end_sites)
+                .create("end_sites")?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(not(feature = "hdf5"))]
+mod imp {
+    use std::path::Path;
+
+    use anyhow::{Result, bail};
+    use ndarray::{Array1, Array2, Array3};
+
+    use crate::skeleton::Skeleton;
+
+    pub enum Writer {}
+
+    impl Writer {
+        pub fn create(path: &Path) -> Result<Self> {
+            bail!(
+                "Writing {} needs bvh_to_gav built with the hdf5 feature",
+                path.display()
+            )
+        }
+
+        pub fn add_clip(
+            &mut self,
+            _id: &str,
+            _data: &Array3<f32>,
+            _skeleton: &Skeleton,
+            _frame_time: f32,
+            _arrays: (&Array1<i64>, &Array2<f32>, &Array2<f32>),
+        ) -> Result<()> {
+            match *self {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_math::Vec3;
+
+    use super::*;
+    use crate::skeleton::SkeletonJoint;
+
+    #[test]
+    fn test_skeleton_is_stored_as_arrays() {
+        let skeleton = Skeleton {
+            joints: vec![
+                SkeletonJoint {
+                    name: "Hips".to_string(),
+                    parent: None,
+                    offset: Vec3::ZERO,
+                    end_site: None,
+                },
+                SkeletonJoint {
+                    name: "Head".to_string(),
+                    parent: Some(0),
+                    offset: Vec3::Y,
+                    end_site: Some(Vec3::new(0.0, 10.0, 0.0)),
+                },
+            ],
+        };
+        let (parents, offsets, end_sites) = skeleton_arrays(&skeleton).unwrap();
+        assert_eq!(parents.to_vec(), vec![-1, 0]);
+        assert_eq!(offsets.row(1).to_vec(), vec![0.0, 1.0, 0.0]);
+        assert!(end_sites.row(0).iter().all(|v| v.is_nan()));
+        assert_eq!(end_sites.row(1).to_vec(), vec![0.0, 10.0, 0.0]);
+    }
+
+    #[cfg(feature = "hdf5")]
+    #[test]
+    fn test_clips_are_written_to_their_groups() {
+        let skeleton = Skeleton {
+            joints: vec![SkeletonJoint {
+                name: "Hips".to_string(),
+                parent: None,
+                offset: Vec3::ZERO,
+                end_site: None,
+            }],
+        };
+        let data = Array3::from_shape_fn((2, 4, 3), |(c, f, v)| (c * 100 + f * 10 + v) as f32);
+        let path = std::env::temp_dir().join(format!("animgen_hdf5_{}.h5", std::process::id()));
+        let mut writer = Hdf5Writer::create(&path).unwrap();
+        writer
+            .add_clip("walk", &data, &skeleton, 1.0 / 30.0)
+            .unwrap();
+        assert!(
+            writer
+                .add_clip("run/fast", &data, &skeleton, 1.0 / 30.0)
+                .is_err()
+        );
+        drop(writer);
+
+        let file = hdf5::File::open(&path).unwrap();
+        let version: u32 = file.attr("version").unwrap().read_scalar().unwrap();
+        let written = file
+            .dataset("clips/walk/gav")
+            .unwrap()
+            .read::<f32, ndarray::Ix3>()
+            .unwrap();
+        drop(file);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(version, HDF5_VERSION);
+        assert_eq!(written, data);
+    }
+}
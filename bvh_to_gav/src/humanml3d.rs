@@ -0,0 +1,295 @@
+//! The 263 features per frame of HumanML3D, so text-to-motion models and metrics built on that
+//! dataset can read clips converted here. Clips are resampled to its 20 fps and measured in
+//! meters, and the 22 joints of its SMPL skeleton are found by name, see [`humanml3d_joints`].
+//! Every frame but the last has, in order:
+//!
+//! - the angular velocity of the root about Y, as half the angle per frame (1)
+//! - the velocity of the root on the ground, in the frame of the body facing (2)
+//! - the height of the root above the floor (1)
+//! - the positions of the other 21 joints relative to the root on the ground, facing +Z (63)
+//! - the rotations of the other 21 joints relative to their SMPL parent, as the first two
+//!   columns of their rotation matrix (126)
+//! - the velocities of all 22 joints in the frame of the body facing (66)
+//! - foot contacts of the left ankle and toe, then the right ones, 1 when they move less than
+//!   the HumanML3D threshold (4)
+//!
+//! The clip is first put on the floor, moved to the origin and turned to face +Z. Rotations are
+//! measured from the rest pose of the clip rather than from the SMPL one, and features aren't
+//! normalized with the HumanML3D mean and deviation.
+use anyhow::{Context, Result, bail};
+use bevy_math::{Mat3, Quat, Vec3};
+use ndarray::{Array1, Array2};
+
+use crate::{
+    Animation, frame_rate::resample_frame_time, kinematics::global_transforms,
+    reference_motion::normalize_name, skeleton::Skeleton,
+};
+
+pub const HUMANML3D_FPS: f32 = 20.0;
+pub const HUMANML3D_FEATURES: usize = 263;
+
+/// Joints of the SMPL skeleton of HumanML3D in its order, with their names in common
+/// skeletons, lower case without separators, in order of preference. The spine joints have no
+/// names, they're picked along the chain from the pelvis to the neck.
+const JOINTS: [(&str, &[&str]); 22] = [
+    ("pelvis", &["pelvis", "hips", "root"]),
+    ("left_hip", &["lefthip", "leftupleg", "leftthigh"]),
+    ("right_hip", &["righthip", "rightupleg", "rightthigh"]),
+    ("spine1", &[]),
+    (
+        "left_knee",
+        &["leftknee", "leftleg", "leftshin", "leftlowerleg"],
+    ),
+    (
+        "right_knee",
+        &["rightknee", "rightleg", "rightshin", "rightlowerleg"],
+    ),
+    ("spine2", &[]),
+    ("left_ankle", &["leftankle", "leftfoot"]),
+    ("right_ankle", &["rightankle", "rightfoot"]),
+    ("spine3", &[]),
+    ("left_foot", &["lefttoebase", "lefttoe", "leftfoot"]),
+    ("right_foot", &["righttoebase", "righttoe", "rightfoot"]),
+    ("neck", &["neck", "neck1"]),
+    (
+        "left_collar",
+        &["leftcollar", "leftclavicle", "leftshoulder"],
+    ),
+    (
+        "right_collar",
+        &["rightcollar", "rightclavicle", "rightshoulder"],
+    ),
+    ("head", &["head"]),
+    (
+        "left_shoulder",
+        &["leftarm", "leftupperarm", "leftshoulder"],
+    ),
+    (
+        "right_shoulder",
+        &["rightarm", "rightupperarm", "rightshoulder"],
+    ),
+    ("left_elbow", &["leftelbow", "leftforearm", "leftlowerarm"]),
+    (
+        "right_elbow",
+        &["rightelbow", "rightforearm", "rightlowerarm"],
+    ),
+    ("left_wrist", &["leftwrist", "lefthand"]),
+    ("right_wrist", &["rightwrist", "righthand"]),
+];
+const PARENTS: [usize; 22] = [
+    0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 9, 9, 12, 13, 14, 16, 17, 18, 19,
+];
+const SPINE: [usize; 3] = [3, 6, 9];
+const NECK: usize = 12;
+const HIPS: [usize; 2] = [1, 2];
+const SHOULDERS: [usize; 2] = [16, 17];
+const LEFT_FEET: [usize; 2] = [7, 10];
+const RIGHT_FEET: [usize; 2] = [8, 11];
+/// Squared distance in m² a foot moves per frame at most while in contact.
+const CONTACT_THRESHOLD: f32 = 0.002;
+
+/// Index in `skeleton` of every joint of the HumanML3D skeleton.
+pub fn humanml3d_joints(skeleton: &Skeleton) -> Result<Vec<usize>> {
+    let names: Vec<String> = skeleton.joint_order().map(normalize_name).collect();
+    let mut joints = vec![0; JOINTS.len()];
+    for (index, (name, aliases)) in JOINTS.iter().enumerate() {
+        if SPINE.contains(&index) {
+            continue;
+        }
+        joints[index] = aliases
+            .iter()
+            .find_map(|alias| names.iter().position(|name| name == alias))
+            .with_context(|| {
+                format!(
+                    "No joint for the HumanML3D {}, expected one named {}",
+                    name,
+                    aliases.join(", ")
+                )
+            })?;
+    }
+    // The joints between the pelvis and the neck, from the pelvis up.
+    let mut chain = Vec::new();
+    let mut joint = skeleton.joints[joints[NECK]].parent;
+    while let Some(index) = joint.filter(|&index| index != joints[0]) {
+        chain.push(index);
+        joint = skeleton.joints[index].parent;
+    }
+    if joint.is_none() || chain.len() < SPINE.len() {
+        bail!(
+            "HumanML3D needs three spine joints between the pelvis and the neck, found {}",
+            chain.len()
+        );
+    }
+    chain.reverse();
+    for (spine, position) in SPINE.iter().zip([0, chain.len() / 2, chain.len() - 1]) {
+        joints[*spine] = chain[position];
+    }
+    for (index, joint) in joints.iter().enumerate() {
+        if let Some(other) = joints[..index].iter().position(|other| other == joint) {
+            bail!(
+                "The HumanML3D {} and {} are the same joint {}",
+                JOINTS[other].0,
+                JOINTS[index].0,
+                skeleton.joints[*joint].name
+            );
+        }
+    }
+    Ok(joints)
+}
+
+/// Rotation about Y of the body facing, from the hips and shoulders, with +Z at 0.
+fn facing_angle(positions: &[Vec3]) -> f32 {
+    let across =
+        positions[HIPS[1]] - positions[HIPS[0]] + positions[SHOULDERS[1]] - positions[SHOULDERS[0]];
+    let forward = Vec3::Y.cross(across);
+    forward.x.atan2(forward.z)
+}
+
+/// `angle` wrapped to [-π, π].
+fn wrap(angle: f32) -> f32 {
+    (angle + std::f32::consts::PI).rem_euclid(std::f32::consts::TAU) - std::f32::consts::PI
+}
+
+/// HumanML3D features of `animation` on `skeleton`, whose lengths are in centimeters, as a
+/// `(frames - 1, 263)` array at 20 fps.
+pub fn humanml3d_features(
+    skeleton: &Skeleton,
+    animation: &Animation,
+    frame_time: f32,
+) -> Result<Array2<f32>> {
+    let joints = humanml3d_joints(skeleton)?;
+    let animation = resample_frame_time(animation, frame_time, 1.0 / HUMANML3D_FPS);
+    let frame_count = animation.frame_count();
+    if frame_count < 2 {
+        bail!("HumanML3D features need at least two frames at 20 fps");
+    }
+    let (mut positions, rotations): (Vec<Vec<Vec3>>, Vec<Vec<Quat>>) = (0..frame_count)
+        .map(|frame| {
+            let transforms = global_transforms(skeleton, &animation, frame);
+            joints
+                .iter()
+                .map(|&joint| (transforms[joint].0 * 0.01, transforms[joint].1))
+                .unzip()
+        })
+        .unzip();
+
+    // On the floor, at the origin and facing +Z in the first frame.
+    let floor = positions
+        .iter()
+        .flatten()
+        .map(|p| p.y)
+        .fold(f32::INFINITY, f32::min);
+    let start = positions[0][0];
+    let initial = Quat::from_rotation_y(-facing_angle(&positions[0]));
+    for position in positions.iter_mut().flatten() {
+        *position = initial * (*position - Vec3::new(start.x, floor, start.z));
+    }
+    let facings: Vec<f32> = positions.iter().map(|frame| facing_angle(frame)).collect();
+    let facing = |frame: usize| Quat::from_rotation_y(-facings[frame]);
+
+    let mut features = Array2::zeros((frame_count - 1, HUMANML3D_FEATURES));
+    for (frame, mut row) in features.rows_mut().into_iter().enumerate() {
+        let (current, next) = (&positions[frame], &positions[frame + 1]);
+        let root = current[0];
+        let root_velocity = facing(frame + 1) * (next[0] - root);
+        let mut values = vec![
+            -wrap(facings[frame + 1] - facings[frame]) / 2.0,
+            root_velocity.x,
+            root_velocity.z,
+            root.y,
+        ];
+        for position in &current[1..] {
+            let local = facing(frame) * (*position - Vec3::new(root.x, 0.0, root.z));
+            values.extend(local.to_array());
+        }
+        for (joint, rotation) in rotations[frame].iter().enumerate().skip(1) {
+            let local = Mat3::from_quat(rotations[frame][PARENTS[joint]].inverse() * *rotation);
+            values.extend(local.x_axis.to_array());
+            values.extend(local.y_axis.to_array());
+        }
+        for (position, next) in current.iter().zip(next) {
+            values.extend((facing(frame) * (*next - *position)).to_array());
+        }
+        for foot in LEFT_FEET.iter().chain(&RIGHT_FEET) {
+            let still = next[*foot].distance_squared(current[*foot]) < CONTACT_THRESHOLD;
+            values.push(if still { 1.0 } else { 0.0 });
+        }
+        row.assign(&Array1::from(values));
+    }
+    Ok(features)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::skeleton::SkeletonJoint;
+
+    #[test]
+    fn test_features_are_relative_to_the_body() {
+        let joints: [(&str, Option<usize>, Vec3); 22] = [
+            ("Hips", None, Vec3::ZERO),
+            ("Spine", Some(0), Vec3::Y * 10.0),
+            ("Spine1", Some(1), Vec3::Y * 10.0),
+            ("Spine2", Some(2), Vec3::Y * 10.0),
+            ("Neck", Some(3), Vec3::Y * 10.0),
+            ("Head", Some(4), Vec3::Y * 10.0),
+            ("LeftShoulder", Some(3), Vec3::X * 5.0),
+            ("LeftArm", Some(6), Vec3::X * 10.0),
+            ("LeftForeArm", Some(7), Vec3::X * 25.0),
+            ("LeftHand", Some(8), Vec3::X * 25.0),
+            ("RightShoulder", Some(3), Vec3::X * -5.0),
+            ("RightArm", Some(10), Vec3::X * -10.0),
+            ("RightForeArm", Some(11), Vec3::X * -25.0),
+            ("RightHand", Some(12), Vec3::X * -25.0),
+            ("LeftUpLeg", Some(0), Vec3::X * 10.0),
+            ("LeftLeg", Some(14), Vec3::Y * -40.0),
+            ("LeftFoot", Some(15), Vec3::Y * -40.0),
+            ("LeftToeBase", Some(16), Vec3::new(0.0, -10.0, 10.0)),
+            ("RightUpLeg", Some(0), Vec3::X * -10.0),
+            ("RightLeg", Some(18), Vec3::Y * -40.0),
+            ("RightFoot", Some(19), Vec3::Y * -40.0),
+            ("RightToeBase", Some(20), Vec3::new(0.0, -10.0, 10.0)),
+        ];
+        let skeleton = Skeleton {
+            joints: joints
+                .iter()
+                .map(|&(name, parent, offset)| SkeletonJoint {
+                    name: name.to_string(),
+                    parent,
+                    offset,
+                    end_site: None,
+                })
+                .collect(),
+        };
+        // Walking along +X, facing it, at 1 m/s.
+        let frames = 21;
+        let mut joint_rotations = vec![vec![Quat::IDENTITY; frames]; joints.len()];
+        joint_rotations[0] = vec![Quat::from_rotation_y(std::f32::consts::FRAC_PI_2); frames];
+        let animation = Animation {
+            root_positions: (0..frames)
+                .map(|f| Vec3::new(f as f32 * 5.0, 90.0, 0.0))
+                .collect(),
+            joint_rotations,
+            events: vec![],
+        };
+        let features = humanml3d_features(&skeleton, &animation, 1.0 / 20.0).unwrap();
+        assert_eq!(features.dim(), (frames - 1, HUMANML3D_FEATURES));
+        let row = features.row(3);
+        assert!(row[0].abs() < 1e-5);
+        assert!(row[1].abs() < 1e-5);
+        assert!((row[2] - 0.05).abs() < 1e-5);
+        assert!((row[3] - 0.9).abs() < 1e-5);
+        // The left hip on the +X side of the body, facing +Z.
+        assert!((row[4] - 0.1).abs() < 1e-5 && row[6].abs() < 1e-5);
+        assert!(
+            row.iter()
+                .skip(HUMANML3D_FEATURES - 4)
+                .all(|&contact| contact == 0.0)
+        );
+
+        let mut fused = skeleton.clone();
+        fused.joints[2].parent = Some(0);
+        fused.joints[3].parent = Some(0);
+        assert!(humanml3d_joints(&fused).is_err());
+    }
+}
@@ -0,0 +1,249 @@
+//! Content hashes of the files of a dataset, recorded in its [`crate::manifest::Manifest`] so
+//! the dataset can be checked after it was copied between training machines or to object
+//! storage. Every clip is hashed with SHA-256 together with its sidecars, the files next to it
+//! with one of the [`SIDECAR_EXTENSIONS`], such as its skeleton, events and metadata. Paths are
+//! recorded relative to the folder of the manifest, so it can be checked from anywhere. Files are
+//! read through [`crate::storage`], so datasets in object storage are checked in place.
+use std::{
+    io::Read,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    contacts::CONTACTS_EXTENSION,
+    events::EVENTS_EXTENSION,
+    fingers::FINGERS_EXTENSION,
+    keyframes::KEYFRAMES_EXTENSION,
+    metadata::{LEGACY_METADATA_EXTENSION, METADATA_EXTENSION},
+    pyramid::PYRAMID_EXTENSION,
+    quantization::QUANTIZATION_EXTENSION,
+    samples::{SAMPLES_EXTENSION, VARIANCE_EXTENSION},
+    skeleton::SKELETON_EXTENSION,
+    storage,
+    tracking::CONSTRAINTS_EXTENSION,
+    weights::WEIGHTS_EXTENSION,
+    windows::{HEMISPHERES_EXTENSION, POSITIONS_EXTENSION, WINDOWS_EXTENSION},
+};
+
+/// Extensions of the sidecars written next to a clip, replacing its own extension.
+pub const SIDECAR_EXTENSIONS: [&str; 16] = [
+    SKELETON_EXTENSION,
+    EVENTS_EXTENSION,
+    METADATA_EXTENSION,
+    LEGACY_METADATA_EXTENSION,
+    FINGERS_EXTENSION,
+    SAMPLES_EXTENSION,
+    VARIANCE_EXTENSION,
+    WEIGHTS_EXTENSION,
+    CONTACTS_EXTENSION,
+    PYRAMID_EXTENSION,
+    QUANTIZATION_EXTENSION,
+    CONSTRAINTS_EXTENSION,
+    WINDOWS_EXTENSION,
+    POSITIONS_EXTENSION,
+    HEMISPHERES_EXTENSION,
+    KEYFRAMES_EXTENSION,
+];
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FileHash {
+    pub path: String,
+    pub size: u64,
+    /// Hex encoded SHA-256 of the content.
+    pub sha256: String,
+}
+
+/// What is wrong with a file listed in a manifest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntegrityIssue {
+    Missing,
+    /// Same size but different content, as left by a damaged transfer.
+    Corrupted,
+    /// Different size, as left by rewriting the file.
+    Modified,
+}
+
+impl std::fmt::Display for IntegrityIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let issue = match self {
+            IntegrityIssue::Missing => "missing",
+            IntegrityIssue::Corrupted => "corrupted",
+            IntegrityIssue::Modified => "modified",
+        };
+        f.write_str(issue)
+    }
+}
+
+/// Size and hex encoded SHA-256 of everything `reader` reads.
+pub fn hash_reader<R: Read>(mut reader: R) -> Result<(u64, String)> {
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 64 * 1024];
+    let mut size = 0;
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        size += read as u64;
+    }
+    let sha256 = hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    Ok((size, sha256))
+}
+
+pub fn hash_file(path: &Path) -> Result<FileHash> {
+    let (size, sha256) = hash_reader(storage::open(path)?)?;
+    Ok(FileHash {
+        path: path.to_string_lossy().into_owned(),
+        size,
+        sha256,
+    })
+}
+
+/// `clip` and its sidecars, sorted. Other clips sharing its stem, such as `walk.v2.npy` next
+/// to `walk.npy`, aren't sidecars.
+pub fn clip_files(clip: &Path) -> Result<Vec<PathBuf>> {
+    let folder = match clip.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let sidecars: Vec<PathBuf> = SIDECAR_EXTENSIONS
+        .iter()
+        .map(|extension| clip.with_extension(extension))
+        .collect();
+    let mut files = vec![clip.to_path_buf()];
+    for path in storage::list_files(folder, false)? {
+        if let Some(sidecar) = sidecars
+            .iter()
+            .find(|sidecar| sidecar.file_name() == path.file_name())
+        {
+            files.push(sidecar.clone());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// `path` with `.` and `name/..` taken out, without looking at the file system.
+fn lexically_normal(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(normal.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                normal.pop();
+            }
+            component => normal.push(component),
+        }
+    }
+    normal
+}
+
+/// `path` relative to `folder` when it is inside it, as given otherwise. Both are compared as
+/// written, as object storage URIs can't be resolved on the file system.
+pub fn relative_path(folder: &Path, path: &Path) -> String {
+    match lexically_normal(path).strip_prefix(lexically_normal(folder)) {
+        Ok(relative) => relative.to_string_lossy().into_owned(),
+        Err(_) => path.to_string_lossy().into_owned(),
+    }
+}
+
+impl FileHash {
+    /// What is wrong with the file, given its size and hash on disk or `None` when it's gone.
+    pub fn check(&self, actual: Option<(u64, String)>) -> Option<IntegrityIssue> {
+        match actual {
+            None => Some(IntegrityIssue::Missing),
+            Some((size, _)) if size != self.size => Some(IntegrityIssue::Modified),
+            Some((_, sha256)) if sha256 != self.sha256 => Some(IntegrityIssue::Corrupted),
+            Some(_) => None,
+        }
+    }
+}
+
+/// Checks every file of `expected`, with paths relative to `folder`, against its content on
+/// disk.
+pub fn verify<'a>(
+    expected: &'a [FileHash],
+    folder: &Path,
+) -> Result<Vec<(&'a str, IntegrityIssue)>> {
+    let mut issues = Vec::new();
+    for file in expected {
+        let path = folder.join(&file.path);
+        let actual = if storage::exists(&path)? {
+            Some(hash_reader(storage::open(&path)?)?)
+        } else {
+            None
+        };
+        if let Some(issue) = file.check(actual) {
+            issues.push((file.path.as_str(), issue));
+        }
+    }
+    Ok(issues)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changed_files_are_reported() {
+        let (size, sha256) = hash_reader(&b"tensor"[..]).unwrap();
+        assert_eq!(size, 6);
+        assert_eq!(
+            sha256,
+            "cf4051ef5db4ce6e38aa9e5e2bc54993328ac077a19a8db4a8beff792afed5a9"
+        );
+        let expected = FileHash {
+            path: "walk.npy".to_string(),
+            size,
+            sha256,
+        };
+        assert_eq!(
+            expected.check(Some(hash_reader(&b"tensor"[..]).unwrap())),
+            None
+        );
+        assert_eq!(
+            expected.check(Some(hash_reader(&b"tenser"[..]).unwrap())),
+            Some(IntegrityIssue::Corrupted)
+        );
+        assert_eq!(
+            expected.check(Some(hash_reader(&b"tensors"[..]).unwrap())),
+            Some(IntegrityIssue::Modified)
+        );
+        assert_eq!(expected.check(None), Some(IntegrityIssue::Missing));
+    }
+
+    #[test]
+    fn test_clip_files_are_the_clip_and_its_sidecars() {
+        let folder = std::env::temp_dir().join(format!("animgen_integrity_{}", std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        for name in [
+            "walk.npy",
+            "walk.skeleton.json",
+            "walk.v2.npy",
+            "walk.notes.txt",
+        ] {
+            std::fs::write(folder.join(name), name).unwrap();
+        }
+        std::fs::create_dir_all(folder.join("takes")).unwrap();
+        // Neither the clip nor the manifest folder are written canonically.
+        let clip = folder.join("takes").join("..").join("walk.npy");
+        let files = clip_files(&clip).unwrap();
+        let relative: Vec<String> = files
+            .iter()
+            .map(|file| relative_path(&folder.join("."), file))
+            .collect();
+        std::fs::remove_dir_all(&folder).unwrap();
+        assert_eq!(relative, ["walk.npy", "walk.skeleton.json"]);
+    }
+}
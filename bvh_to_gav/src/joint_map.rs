@@ -0,0 +1,301 @@
+//! Joint selection and renaming, so clips from different rigs can be encoded with the same
+//! joints in the same order. A joint map is a TOML file:
+//!
+//! ```toml
+//! # Joints to encode, in this order, by their names after renaming. All joints when empty.
+//! joints = ["Hips", "Spine", "Head", "LeftFoot", "RightFoot"]
+//!
+//! [rename]
+//! "mixamorig:Hips" = "Hips"
+//! "mixamorig:Spine" = "Spine"
+//! ```
+//!
+//! Kept joints are listed after their kept parent, so the root comes first: a joint listed
+//! before its parent moves the parent up to just before it.
+//!
+//! A joint that is left out is folded into its children: its rotation is applied to each of
+//! them and its offset added to theirs. The offsets are measured in the rest pose, so this is
+//! exact for joints that don't move, such as twist or helper bones, and an approximation for
+//! joints that do.
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::{Context, Result, bail};
+use bevy_math::Quat;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Animation,
+    kinematics::rest_positions,
+    skeleton::{Skeleton, SkeletonJoint},
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct JointMap {
+    /// Joints to keep in output order, named after renaming. Parents listed after a child of
+    /// theirs move up to just before it.
+    pub joints: Vec<String>,
+    /// New name of each joint, by its name in the clip.
+    pub rename: BTreeMap<String, String>,
+}
+
+/// Result of [`JointMap::check`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct JointMapCheck {
+    /// Name after renaming and output index of every joint of the skeleton, in parser order.
+    /// Joints without an index are folded into their children.
+    pub joints: Vec<(String, Option<usize>)>,
+    /// Joints of the map that no joint, or more than one, is called after renaming.
+    pub unmatched: Vec<String>,
+    /// Renamed joints the skeleton doesn't have.
+    pub unused_renames: Vec<String>,
+}
+
+impl JointMapCheck {
+    pub fn is_valid(&self) -> bool {
+        self.unmatched.is_empty()
+    }
+}
+
+impl JointMap {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)?;
+        toml::from_str(&text).with_context(|| format!("Invalid {}", path.display()))
+    }
+
+    fn renamed<'a>(&'a self, name: &'a str) -> &'a str {
+        self.rename.get(name).map_or(name, String::as_str)
+    }
+
+    /// Indices of the kept joints of `skeleton`, in output order.
+    fn select(&self, skeleton: &Skeleton) -> Result<Vec<usize>> {
+        let names: Vec<&str> = skeleton.joint_order().map(|n| self.renamed(n)).collect();
+        if self.joints.is_empty() {
+            return Ok((0..names.len()).collect());
+        }
+        self.joints
+            .iter()
+            .map(|name| {
+                let mut matches = (0..names.len()).filter(|&index| names[index] == name);
+                match (matches.next(), matches.next()) {
+                    (Some(index), None) => Ok(index),
+                    (None, _) => bail!("No joint called {}", name),
+                    (Some(_), Some(_)) => bail!("More than one joint is called {}", name),
+                }
+            })
+            .collect()
+    }
+
+    /// The kept ancestor of `joint`, if any.
+    fn kept_ancestor(skeleton: &Skeleton, kept: &[bool], mut joint: usize) -> Option<usize> {
+        while let Some(parent) = skeleton.joints[joint].parent {
+            if kept[parent] {
+                return Some(parent);
+            }
+            joint = parent;
+        }
+        None
+    }
+
+    /// Indices of the kept joints of `skeleton` in output order: the order of the map, with
+    /// every joint after its kept ancestors, so the root is first.
+    fn order(&self, skeleton: &Skeleton) -> Result<Vec<usize>> {
+        let selected = self.select(skeleton)?;
+        let mut kept = vec![false; skeleton.joint_count()];
+        for &joint in &selected {
+            kept[joint] = true;
+        }
+        let mut placed = vec![false; skeleton.joint_count()];
+        let mut order = Vec::with_capacity(selected.len());
+        for &joint in &selected {
+            let mut chain = vec![joint];
+            while let Some(ancestor) = Self::kept_ancestor(skeleton, &kept, *chain.last().unwrap())
+            {
+                chain.push(ancestor);
+            }
+            for &joint in chain.iter().rev() {
+                if !placed[joint] {
+                    placed[joint] = true;
+                    order.push(joint);
+                }
+            }
+        }
+        Ok(order)
+    }
+
+    /// How the joints of `skeleton` map, for checking a joint map against a rig without
+    /// applying it.
+    pub fn check(&self, skeleton: &Skeleton) -> JointMapCheck {
+        let names: Vec<&str> = skeleton.joint_order().map(|n| self.renamed(n)).collect();
+        let order = self.order(skeleton).ok();
+        let mut check = JointMapCheck {
+            joints: names
+                .iter()
+                .enumerate()
+                .map(|(index, name)| {
+                    let output = match &order {
+                        Some(order) => order.iter().position(|&joint| joint == index),
+                        None => self.joints.iter().position(|joint| joint == name),
+                    };
+                    (name.to_string(), output)
+                })
+                .collect(),
+            ..Default::default()
+        };
+        check.unmatched = self
+            .joints
+            .iter()
+            .filter(|joint| names.iter().filter(|name| **name == joint.as_str()).count() != 1)
+            .cloned()
+            .collect();
+        check.unused_renames = self
+            .rename
+            .keys()
+            .filter(|name| skeleton.find(name).is_none())
+            .cloned()
+            .collect();
+        check
+    }
+
+    /// The skeleton and animation with only the selected joints, renamed and in the order of
+    /// [`JointMap::joints`], parents first.
+    pub fn apply(
+        &self,
+        skeleton: &Skeleton,
+        animation: &Animation,
+    ) -> Result<(Skeleton, Animation)> {
+        let selected = self.order(skeleton)?;
+        let mut new_index = vec![None; skeleton.joint_count()];
+        for (new, &old) in selected.iter().enumerate() {
+            new_index[old] = Some(new);
+        }
+        let kept: Vec<bool> = new_index.iter().map(Option::is_some).collect();
+        let kept_ancestor = |joint| Self::kept_ancestor(skeleton, &kept, joint);
+
+        let rest = rest_positions(skeleton);
+        let mut joints = Vec::with_capacity(selected.len());
+        let mut joint_rotations = Vec::with_capacity(selected.len());
+        for &old in &selected {
+            let joint = &skeleton.joints[old];
+            let ancestor = kept_ancestor(old);
+            if ancestor.is_none() && joint.parent.is_some() {
+                bail!(
+                    "{} has no kept ancestor, the root joint has to be kept",
+                    joint.name
+                );
+            }
+            joints.push(SkeletonJoint {
+                name: self.renamed(&joint.name).to_string(),
+                parent: ancestor.and_then(|ancestor| new_index[ancestor]),
+                offset: ancestor.map_or(joint.offset, |ancestor| rest[old] - rest[ancestor]),
+                end_site: joint.end_site,
+            });
+
+            // Rotations of the left out joints between the ancestor and this one, outermost
+            // first.
+            let mut chain = Vec::new();
+            let mut parent = joint.parent;
+            while let Some(index) = parent.filter(|&index| Some(index) != ancestor) {
+                chain.push(index);
+                parent = skeleton.joints[index].parent;
+            }
+            let rotations = (0..animation.frame_count())
+                .map(|frame| {
+                    chain
+                        .iter()
+                        .rev()
+                        .map(|&index| animation.joint_rotations[index][frame])
+                        .fold(Quat::IDENTITY, |a, b| a * b)
+                        * animation.joint_rotations[old][frame]
+                })
+                .collect();
+            joint_rotations.push(rotations);
+        }
+
+        Ok((
+            Skeleton { joints },
+            Animation {
+                root_positions: animation.root_positions.clone(),
+                joint_rotations,
+                events: animation.events.clone(),
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use bevy_math::Vec3;
+
+    use super::*;
+
+    #[test]
+    fn test_joints_are_selected_renamed_and_folded() {
+        let joint = |name: &str, parent, offset| SkeletonJoint {
+            name: name.to_string(),
+            parent,
+            offset,
+            end_site: None,
+        };
+        let skeleton = Skeleton {
+            joints: vec![
+                joint("rig:Hips", None, Vec3::ZERO),
+                joint("rig:Spine", Some(0), Vec3::Y * 10.0),
+                joint("rig:Chest", Some(1), Vec3::Y * 5.0),
+                joint("rig:LeftUpLeg", Some(0), Vec3::X * 8.0),
+            ],
+        };
+        let turn = Quat::from_rotation_z(FRAC_PI_2);
+        let animation = Animation {
+            root_positions: vec![Vec3::ZERO],
+            joint_rotations: vec![
+                vec![Quat::IDENTITY],
+                vec![turn],
+                vec![turn],
+                vec![Quat::IDENTITY],
+            ],
+            events: vec![],
+        };
+        let map: JointMap = toml::from_str(
+            r#"
+            joints = ["Chest", "Hips"]
+            [rename]
+            "rig:Hips" = "Hips"
+            "rig:Chest" = "Chest"
+            "#,
+        )
+        .unwrap();
+
+        // The hips move before the chest, their child.
+        let (mapped, mapped_animation) = map.apply(&skeleton, &animation).unwrap();
+        assert_eq!(
+            mapped.joint_order().collect::<Vec<_>>(),
+            vec!["Hips", "Chest"]
+        );
+        assert_eq!(mapped.joints[0].parent, None);
+        assert_eq!(mapped.joints[1].parent, Some(0));
+        assert_eq!(mapped.joints[1].offset, Vec3::Y * 15.0);
+        // The spine turn is folded into the chest, which is turned twice.
+        let chest = mapped_animation.joint_rotations[1][0];
+        assert!((chest * Vec3::X).distance(-Vec3::X) < 1e-5);
+
+        let check = map.check(&skeleton);
+        assert!(check.is_valid());
+        assert_eq!(check.joints[0], ("Hips".to_string(), Some(0)));
+        assert_eq!(check.joints[1], ("rig:Spine".to_string(), None));
+
+        let missing = JointMap {
+            joints: vec!["Head".to_string()],
+            ..map.clone()
+        };
+        assert!(missing.apply(&skeleton, &animation).is_err());
+        assert_eq!(missing.check(&skeleton).unmatched, ["Head"]);
+        let rootless = JointMap {
+            joints: vec!["Chest".to_string()],
+            ..map
+        };
+        assert!(rootless.apply(&skeleton, &animation).is_err());
+    }
+}
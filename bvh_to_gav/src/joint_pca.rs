@@ -0,0 +1,244 @@
+//! Principal components of the local rotations of every joint of a dataset, for showing where a
+//! pose lies in the distribution of each joint. Rotations are taken relative to the mean
+//! rotation of the joint, as scaled axis vectors like in [`crate::pose_prior`], and the
+//! principal axes of those vectors are the eigenvectors of their covariance. A rotation is out
+//! of distribution when its Mahalanobis distance from the mean is more than
+//! [`OUT_OF_DISTRIBUTION`] standard deviations.
+//!
+//! The report keeps the projection of a few hundred frames of the dataset on the first two axes
+//! of every joint, enough to plot the distribution without the clips.
+use std::{fs::File, io::BufWriter, path::Path};
+
+use anyhow::{Context, Result, bail};
+use bevy_math::{Quat, Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::{Animation, coverage::mean_rotation, pose_prior::tangent, skeleton::Skeleton};
+
+/// File name of the report written next to the clips of a dataset.
+pub const JOINT_PCA_FILE: &str = "joint_pca.json";
+/// Mahalanobis distance beyond which a rotation is out of distribution.
+pub const OUT_OF_DISTRIBUTION: f32 = 3.0;
+/// Frames of the dataset kept per joint for plotting.
+const SAMPLE_COUNT: usize = 256;
+/// Smallest variance of an axis, in square radians, so joints that never move don't put every
+/// other rotation infinitely far away.
+const MIN_VARIANCE: f32 = 1e-4;
+const JACOBI_SWEEPS: usize = 16;
+
+/// Eigenvalues and unit eigenvectors of the symmetric `matrix`, by Jacobi rotations, from the
+/// largest eigenvalue down.
+fn symmetric_eigen(mut matrix: [[f32; 3]; 3]) -> [(f32, Vec3); 3] {
+    let mut vectors = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+    for _ in 0..JACOBI_SWEEPS {
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if matrix[p][q].abs() <= f32::EPSILON * (matrix[p][p].abs() + matrix[q][q].abs()) {
+                continue;
+            }
+            let theta = (matrix[q][q] - matrix[p][p]) / (2.0 * matrix[p][q]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+            let c = 1.0 / (t * t + 1.0).sqrt();
+            let s = t * c;
+            for row in matrix.iter_mut().chain(vectors.iter_mut()) {
+                let (a, b) = (row[p], row[q]);
+                row[p] = c * a - s * b;
+                row[q] = s * a + c * b;
+            }
+            let (a, b) = (matrix[p], matrix[q]);
+            for k in 0..3 {
+                matrix[p][k] = c * a[k] - s * b[k];
+                matrix[q][k] = s * a[k] + c * b[k];
+            }
+        }
+    }
+    let mut pairs = [0, 1, 2].map(|i| {
+        let vector = Vec3::new(vectors[0][i], vectors[1][i], vectors[2][i]);
+        (matrix[i][i], vector.normalize_or_zero())
+    });
+    pairs.sort_by(|a, b| b.0.total_cmp(&a.0));
+    pairs
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct JointPca {
+    pub joint: String,
+    /// Rotation the tangent vectors are taken relative to.
+    pub mean_rotation: Quat,
+    /// Mean of the tangent vectors.
+    pub mean: Vec3,
+    /// Principal axes, from the most to the least variance.
+    pub axes: [Vec3; 3],
+    /// Variance along every axis, in square radians.
+    pub variances: [f32; 3],
+    /// Frames of the dataset, spread evenly, projected on the first two axes.
+    pub samples: Vec<Vec2>,
+}
+
+impl JointPca {
+    fn fit(joint: &str, rotations: &[Quat]) -> Self {
+        let mean_rotation = mean_rotation(rotations);
+        let tangents: Vec<Vec3> = rotations
+            .iter()
+            .map(|&rotation| tangent(mean_rotation, rotation))
+            .collect();
+        let count = tangents.len().max(1) as f32;
+        let mean = tangents.iter().sum::<Vec3>() / count;
+        let mut covariance = [[0.0; 3]; 3];
+        for d in tangents.iter().map(|&t| t - mean) {
+            for (i, row) in covariance.iter_mut().enumerate() {
+                for (j, value) in row.iter_mut().enumerate() {
+                    *value += d[i] * d[j] / count;
+                }
+            }
+        }
+        let components = symmetric_eigen(covariance);
+        let mut pca = JointPca {
+            joint: joint.to_string(),
+            mean_rotation,
+            mean,
+            axes: components.map(|(_, axis)| axis),
+            variances: components.map(|(variance, _)| variance.max(MIN_VARIANCE)),
+            samples: Vec::new(),
+        };
+        let step = tangents.len().div_ceil(SAMPLE_COUNT).max(1);
+        pca.samples = tangents
+            .iter()
+            .step_by(step)
+            .map(|&t| pca.project_tangent(t))
+            .collect();
+        pca
+    }
+
+    fn components(&self, tangent: Vec3) -> [f32; 3] {
+        self.axes.map(|axis| (tangent - self.mean).dot(axis))
+    }
+
+    fn project_tangent(&self, tangent: Vec3) -> Vec2 {
+        let [x, y, _] = self.components(tangent);
+        Vec2::new(x, y)
+    }
+
+    /// `rotation` on the first two principal axes.
+    pub fn project(&self, rotation: Quat) -> Vec2 {
+        self.project_tangent(tangent(self.mean_rotation, rotation))
+    }
+
+    /// Mahalanobis distance of `rotation` from the mean, in standard deviations.
+    pub fn distance(&self, rotation: Quat) -> f32 {
+        self.components(tangent(self.mean_rotation, rotation))
+            .iter()
+            .zip(self.variances)
+            .map(|(component, variance)| component * component / variance)
+            .sum::<f32>()
+            .sqrt()
+    }
+
+    /// Fraction of the variance the first two axes explain.
+    pub fn explained(&self) -> f32 {
+        let total: f32 = self.variances.iter().sum();
+        (self.variances[0] + self.variances[1]) / total
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct JointPcaReport {
+    pub clip_count: usize,
+    pub frame_count: usize,
+    /// Components of every joint of the dataset skeleton, in joint order.
+    pub joints: Vec<JointPca>,
+}
+
+impl JointPcaReport {
+    /// Fits the components of `animations`, which all have to be of `skeleton`.
+    pub fn new(skeleton: &Skeleton, animations: &[Animation]) -> Result<Self> {
+        if let Some(animation) = animations
+            .iter()
+            .find(|animation| animation.joint_count() != skeleton.joint_count())
+        {
+            bail!(
+                "An animation has {} joints, the skeleton {}",
+                animation.joint_count(),
+                skeleton.joint_count()
+            );
+        }
+        let frame_count: usize = animations.iter().map(Animation::frame_count).sum();
+        if frame_count == 0 {
+            bail!("No frames to fit");
+        }
+        let joints = skeleton
+            .joint_order()
+            .enumerate()
+            .map(|(joint, name)| {
+                let rotations: Vec<Quat> = animations
+                    .iter()
+                    .flat_map(|animation| animation.joint_rotations[joint].iter().copied())
+                    .collect();
+                JointPca::fit(name, &rotations)
+            })
+            .collect();
+        Ok(JointPcaReport {
+            clip_count: animations.len(),
+            frame_count,
+            joints,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("Could not open {}", path.display()))?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), self)?;
+        Ok(())
+    }
+
+    pub fn find(&self, joint: &str) -> Option<&JointPca> {
+        self.joints.iter().find(|pca| pca.joint == joint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::skeleton::SkeletonJoint;
+
+    #[test]
+    fn test_components_follow_the_motion() {
+        let skeleton = Skeleton {
+            joints: vec![SkeletonJoint {
+                name: "Knee".to_string(),
+                parent: None,
+                offset: Vec3::ZERO,
+                end_site: None,
+            }],
+        };
+        // Bends about X a lot and twists about Y a little, together.
+        let frames = 1000;
+        let rotations = (0..frames)
+            .map(|frame| {
+                let t = (frame as f32 * 0.37).sin();
+                Quat::from_scaled_axis(Vec3::new(0.8 * t, 0.1 * (frame as f32 * 1.3).cos(), 0.0))
+            })
+            .collect();
+        let animation = Animation {
+            root_positions: vec![Vec3::ZERO; frames],
+            joint_rotations: vec![rotations],
+            events: Vec::new(),
+        };
+        let report = JointPcaReport::new(&skeleton, &[animation]).unwrap();
+        let knee = report.find("Knee").unwrap();
+        assert!(knee.axes[0].x.abs() > 0.99, "{:?}", knee.axes);
+        assert!(knee.axes[1].y.abs() > 0.99, "{:?}", knee.axes);
+        assert!(knee.variances[0] > knee.variances[1]);
+        assert_eq!(knee.variances[2], MIN_VARIANCE);
+        assert!(knee.explained() > 0.99);
+        assert_eq!(knee.samples.len(), 250);
+
+        let bent = Quat::from_rotation_x(0.8);
+        assert!(knee.project(bent).x.abs() > 0.7);
+        assert!(knee.distance(bent) < OUT_OF_DISTRIBUTION);
+        assert!(knee.distance(Quat::from_rotation_z(0.5)) > OUT_OF_DISTRIBUTION);
+    }
+}
@@ -0,0 +1,376 @@
+//! Standard joint sets clips can be converted to, so tensors of clips from any rig have the
+//! same joints in the same order: the 22 body joints of SMPL, the 22 body joints of a Mixamo
+//! rig, without fingers, and the 31 joints of the CMU mocap database.
+//!
+//! The joints of a clip are found by common names, ignoring case, separators and namespaces
+//! such as `mixamorig:`, so `LeftUpLeg`, `l_thigh` and `left_hip` all find the left thigh. The
+//! root of the clip is always the root of the set. Names are tried in order, and a joint of
+//! the clip is only used once, so `Spine`, `Spine1` and `Spine2` fill the three spine joints
+//! of a set in order. Joints of the clip that aren't found are folded into their children,
+//! like those a [`JointMap`] leaves out. Joints of the set the clip doesn't have are added at
+//! their parent, with a zero offset and no rotation.
+//!
+//! Rigs whose names don't fit still need a joint map file.
+use std::{fmt, str::FromStr};
+
+use anyhow::{Result, bail};
+use bevy_math::{Quat, Vec3};
+
+use crate::{
+    Animation,
+    joint_map::JointMap,
+    skeleton::{Skeleton, SkeletonJoint},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Side {
+    Left,
+    Right,
+}
+
+/// Part of the body a joint of a set is, which gives the names it's found by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Part {
+    Root,
+    LowerSpine,
+    MiddleSpine,
+    UpperSpine,
+    Neck,
+    UpperNeck,
+    Head,
+    HipJoint(Side),
+    Thigh(Side),
+    Shin(Side),
+    Ankle(Side),
+    Toe(Side),
+    Collar(Side),
+    UpperArm(Side),
+    Forearm(Side),
+    Hand(Side),
+    FingerBase(Side),
+    IndexFinger(Side),
+    Thumb(Side),
+}
+
+impl Part {
+    /// Normalized names of the part, see [`normalize`], most specific first.
+    fn aliases(self) -> Vec<String> {
+        let (side, names): (Option<Side>, &[&str]) = match self {
+            Part::Root => (None, &[]),
+            Part::LowerSpine => (None, &["lowerback", "spine", "spine1", "abdomen"]),
+            Part::MiddleSpine => (None, &["spine", "spine1", "spine2"]),
+            Part::UpperSpine => (None, &["spine1", "spine2", "spine3", "chest", "upperchest"]),
+            Part::Neck => (None, &["neck", "lowerneck"]),
+            Part::UpperNeck => (None, &["neck1", "neck2", "upperneck"]),
+            Part::Head => (None, &["head"]),
+            Part::HipJoint(side) => (Some(side), &["hipjoint"]),
+            Part::Thigh(side) => (Some(side), &["hip", "upleg", "thigh", "upperleg"]),
+            Part::Shin(side) => (Some(side), &["knee", "leg", "shin", "lowerleg", "calf"]),
+            Part::Ankle(side) => (Some(side), &["ankle", "foot"]),
+            Part::Toe(side) => (Some(side), &["toebase", "toe", "toes", "foot"]),
+            Part::Collar(side) => (Some(side), &["collar", "clavicle", "shoulder"]),
+            Part::UpperArm(side) => (Some(side), &["arm", "upperarm", "shoulder"]),
+            Part::Forearm(side) => (Some(side), &["forearm", "elbow", "lowerarm"]),
+            Part::Hand(side) => (Some(side), &["wrist", "hand"]),
+            Part::FingerBase(side) => (Some(side), &["fingerbase"]),
+            Part::IndexFinger(side) => (Some(side), &["handindex1", "index1"]),
+            Part::Thumb(side) => (Some(side), &["thumb", "thumb1"]),
+        };
+        let Some(side) = side else {
+            return names.iter().map(|name| name.to_string()).collect();
+        };
+        let prefixes: &[&str] = match side {
+            Side::Left => &["left", "l"],
+            Side::Right => &["right", "r"],
+        };
+        names
+            .iter()
+            .flat_map(|name| {
+                let prefixed = prefixes.iter().map(move |side| format!("{}{}", side, name));
+                let suffixed = prefixes.iter().map(move |side| format!("{}{}", name, side));
+                prefixed.chain(suffixed)
+            })
+            .collect()
+    }
+}
+
+/// A joint of a set: its name, the name of its parent, empty for the root, and its part.
+type SetJoint = (&'static str, &'static str, Part);
+
+use Part::*;
+use Side::{Left as L, Right as R};
+
+const SMPL_22: &[SetJoint] = &[
+    ("pelvis", "", Root),
+    ("left_hip", "pelvis", Thigh(L)),
+    ("right_hip", "pelvis", Thigh(R)),
+    ("spine1", "pelvis", LowerSpine),
+    ("left_knee", "left_hip", Shin(L)),
+    ("right_knee", "right_hip", Shin(R)),
+    ("spine2", "spine1", MiddleSpine),
+    ("left_ankle", "left_knee", Ankle(L)),
+    ("right_ankle", "right_knee", Ankle(R)),
+    ("spine3", "spine2", UpperSpine),
+    ("left_foot", "left_ankle", Toe(L)),
+    ("right_foot", "right_ankle", Toe(R)),
+    ("neck", "spine3", Neck),
+    ("left_collar", "spine3", Collar(L)),
+    ("right_collar", "spine3", Collar(R)),
+    ("head", "neck", Head),
+    ("left_shoulder", "left_collar", UpperArm(L)),
+    ("right_shoulder", "right_collar", UpperArm(R)),
+    ("left_elbow", "left_shoulder", Forearm(L)),
+    ("right_elbow", "right_shoulder", Forearm(R)),
+    ("left_wrist", "left_elbow", Hand(L)),
+    ("right_wrist", "right_elbow", Hand(R)),
+];
+
+const MIXAMO: &[SetJoint] = &[
+    ("Hips", "", Root),
+    ("Spine", "Hips", LowerSpine),
+    ("Spine1", "Spine", MiddleSpine),
+    ("Spine2", "Spine1", UpperSpine),
+    ("Neck", "Spine2", Neck),
+    ("Head", "Neck", Head),
+    ("LeftShoulder", "Spine2", Collar(L)),
+    ("LeftArm", "LeftShoulder", UpperArm(L)),
+    ("LeftForeArm", "LeftArm", Forearm(L)),
+    ("LeftHand", "LeftForeArm", Hand(L)),
+    ("RightShoulder", "Spine2", Collar(R)),
+    ("RightArm", "RightShoulder", UpperArm(R)),
+    ("RightForeArm", "RightArm", Forearm(R)),
+    ("RightHand", "RightForeArm", Hand(R)),
+    ("LeftUpLeg", "Hips", Thigh(L)),
+    ("LeftLeg", "LeftUpLeg", Shin(L)),
+    ("LeftFoot", "LeftLeg", Ankle(L)),
+    ("LeftToeBase", "LeftFoot", Toe(L)),
+    ("RightUpLeg", "Hips", Thigh(R)),
+    ("RightLeg", "RightUpLeg", Shin(R)),
+    ("RightFoot", "RightLeg", Ankle(R)),
+    ("RightToeBase", "RightFoot", Toe(R)),
+];
+
+const CMU_31: &[SetJoint] = &[
+    ("Hips", "", Root),
+    ("LHipJoint", "Hips", HipJoint(L)),
+    ("LeftUpLeg", "LHipJoint", Thigh(L)),
+    ("LeftLeg", "LeftUpLeg", Shin(L)),
+    ("LeftFoot", "LeftLeg", Ankle(L)),
+    ("LeftToeBase", "LeftFoot", Toe(L)),
+    ("RHipJoint", "Hips", HipJoint(R)),
+    ("RightUpLeg", "RHipJoint", Thigh(R)),
+    ("RightLeg", "RightUpLeg", Shin(R)),
+    ("RightFoot", "RightLeg", Ankle(R)),
+    ("RightToeBase", "RightFoot", Toe(R)),
+    ("LowerBack", "Hips", LowerSpine),
+    ("Spine", "LowerBack", MiddleSpine),
+    ("Spine1", "Spine", UpperSpine),
+    ("Neck", "Spine1", Neck),
+    ("Neck1", "Neck", UpperNeck),
+    ("Head", "Neck1", Head),
+    ("LeftShoulder", "Spine1", Collar(L)),
+    ("LeftArm", "LeftShoulder", UpperArm(L)),
+    ("LeftForeArm", "LeftArm", Forearm(L)),
+    ("LeftHand", "LeftForeArm", Hand(L)),
+    ("LeftFingerBase", "LeftHand", FingerBase(L)),
+    ("LeftHandIndex1", "LeftFingerBase", IndexFinger(L)),
+    ("LThumb", "LeftHand", Thumb(L)),
+    ("RightShoulder", "Spine1", Collar(R)),
+    ("RightArm", "RightShoulder", UpperArm(R)),
+    ("RightForeArm", "RightArm", Forearm(R)),
+    ("RightHand", "RightForeArm", Hand(R)),
+    ("RightFingerBase", "RightHand", FingerBase(R)),
+    ("RightHandIndex1", "RightFingerBase", IndexFinger(R)),
+    ("RThumb", "RightHand", Thumb(R)),
+];
+
+/// `name` without namespace, lowercase and without separators.
+fn normalize(name: &str) -> String {
+    name.rsplit([':', '|'])
+        .next()
+        .unwrap_or(name)
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// A standard joint set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JointSet {
+    Smpl22,
+    Mixamo,
+    Cmu31,
+}
+
+impl JointSet {
+    fn joints(self) -> &'static [SetJoint] {
+        match self {
+            JointSet::Smpl22 => SMPL_22,
+            JointSet::Mixamo => MIXAMO,
+            JointSet::Cmu31 => CMU_31,
+        }
+    }
+
+    /// Names of the joints of the set, in tensor order.
+    pub fn joint_names(self) -> impl Iterator<Item = &'static str> {
+        self.joints().iter().map(|(name, _, _)| *name)
+    }
+
+    /// Joint map renaming the joints of `skeleton` found for the set, and keeping them in the
+    /// order of the set.
+    pub fn joint_map(self, skeleton: &Skeleton) -> JointMap {
+        let names: Vec<String> = skeleton.joint_order().map(normalize).collect();
+        let mut used = vec![false; names.len()];
+        let mut map = JointMap::default();
+        for &(name, _, part) in self.joints() {
+            let found = match part {
+                Part::Root => skeleton.roots().next(),
+                part => part.aliases().iter().find_map(|alias| {
+                    let mut matches = (0..names.len()).filter(|&i| !used[i] && names[i] == *alias);
+                    match (matches.next(), matches.next()) {
+                        (Some(index), None) => Some(index),
+                        _ => None,
+                    }
+                }),
+            };
+            if let Some(index) = found {
+                used[index] = true;
+                map.rename
+                    .insert(skeleton.joints[index].name.clone(), name.to_string());
+                map.joints.push(name.to_string());
+            }
+        }
+        map
+    }
+
+    /// The skeleton and animation with the joints of the set, in its order.
+    pub fn apply(
+        self,
+        skeleton: &Skeleton,
+        animation: &Animation,
+    ) -> Result<(Skeleton, Animation)> {
+        let (mapped, mapped_animation) = self.joint_map(skeleton).apply(skeleton, animation)?;
+        let position = |name: &str| self.joint_names().position(|joint| joint == name);
+        let mut joints = Vec::with_capacity(self.joints().len());
+        let mut joint_rotations = Vec::with_capacity(self.joints().len());
+        for &(name, parent, _) in self.joints() {
+            match mapped.find(name) {
+                Some(index) => {
+                    let joint = &mapped.joints[index];
+                    joints.push(SkeletonJoint {
+                        name: name.to_string(),
+                        parent: joint
+                            .parent
+                            .and_then(|parent| position(&mapped.joints[parent].name)),
+                        offset: joint.offset,
+                        end_site: joint.end_site,
+                    });
+                    joint_rotations.push(mapped_animation.joint_rotations[index].clone());
+                }
+                None => {
+                    joints.push(SkeletonJoint {
+                        name: name.to_string(),
+                        parent: position(parent),
+                        offset: Vec3::ZERO,
+                        end_site: None,
+                    });
+                    joint_rotations.push(vec![Quat::IDENTITY; animation.frame_count()]);
+                }
+            }
+        }
+        Ok((
+            Skeleton { joints },
+            Animation {
+                joint_rotations,
+                ..mapped_animation
+            },
+        ))
+    }
+}
+
+impl FromStr for JointSet {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "smpl22" => Ok(JointSet::Smpl22),
+            "mixamo" => Ok(JointSet::Mixamo),
+            "cmu31" => Ok(JointSet::Cmu31),
+            _ => bail!(
+                "Unknown joint set {}, expected smpl22, mixamo or cmu31",
+                name
+            ),
+        }
+    }
+}
+
+impl fmt::Display for JointSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            JointSet::Smpl22 => "smpl22",
+            JointSet::Mixamo => "mixamo",
+            JointSet::Cmu31 => "cmu31",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mixamo_rig_maps_to_smpl_joints() {
+        let joint = |name: &str, parent, offset| SkeletonJoint {
+            name: format!("mixamorig:{}", name),
+            parent,
+            offset,
+            end_site: None,
+        };
+        let skeleton = Skeleton {
+            joints: vec![
+                joint("Hips", None, Vec3::ZERO),
+                joint("Spine", Some(0), Vec3::Y * 10.0),
+                joint("Spine1", Some(1), Vec3::Y * 10.0),
+                joint("Head", Some(2), Vec3::Y * 20.0),
+                joint("LeftUpLeg", Some(0), Vec3::X * 8.0),
+                joint("LeftLeg", Some(4), Vec3::NEG_Y * 40.0),
+            ],
+        };
+        let turn = Quat::from_rotation_x(0.3);
+        let animation = Animation {
+            root_positions: vec![Vec3::ZERO; 2],
+            joint_rotations: vec![vec![turn; 2]; 6],
+            events: Vec::new(),
+        };
+
+        let (mapped, mapped_animation) = JointSet::Smpl22.apply(&skeleton, &animation).unwrap();
+        assert_eq!(
+            mapped.joint_order().collect::<Vec<_>>(),
+            JointSet::Smpl22.joint_names().collect::<Vec<_>>()
+        );
+        assert_eq!(mapped_animation.joint_count(), 22);
+        let find = |name| mapped.find(name).unwrap();
+        assert_eq!(
+            mapped.joints[find("left_knee")].parent,
+            Some(find("left_hip"))
+        );
+        assert_eq!(mapped.joints[find("spine2")].parent, Some(find("spine1")));
+        // The clip has no neck, so the head hangs from the second spine joint.
+        assert_eq!(mapped.joints[find("head")].parent, Some(find("spine2")));
+        assert_eq!(mapped.joints[find("neck")].parent, Some(find("spine3")));
+        assert_eq!(mapped.joints[find("neck")].offset, Vec3::ZERO);
+        assert_eq!(
+            mapped_animation.joint_rotations[find("neck")],
+            [Quat::IDENTITY; 2]
+        );
+        assert_eq!(
+            mapped_animation.joint_rotations[find("left_knee")],
+            [turn; 2]
+        );
+
+        assert_eq!(normalize("mixamorig:Left_Up-Leg"), "leftupleg");
+        assert_eq!("cmu31".parse::<JointSet>().unwrap(), JointSet::Cmu31);
+        assert_eq!(JointSet::Cmu31.joint_names().count(), 31);
+    }
+}
@@ -0,0 +1,342 @@
+//! Lossy keyframe compression of dense captures. Every curve, the root positions and the
+//! rotation of every joint, keeps only the frames it can't be interpolated from its other keys
+//! within a tolerance, positions linearly and rotations spherically, so a 120 fps capture
+//! holding still or moving steadily shrinks to a few keys. Keys are picked by splitting every
+//! span at its worst frame until all frames are within the tolerance, so the resampled clip is
+//! guaranteed to be.
+//!
+//! Compressed clips are `.keys.npz` archives of
+//!
+//! - `frame_count`: i64 scalar, frames of the resampled clip
+//! - `fps`: f32 scalar
+//! - `key_counts`: i64 of shape (joints + 1,), keys of the root positions, then of every joint
+//! - `key_frames`: i64 of shape (keys,), frame of every key, curve after curve
+//! - `key_values`: f32 of shape (keys, 4), root positions as x, y, z, 0 and rotations as x, y,
+//!   z, w
+//! - `joint_names` as in [`crate::npz`]
+//!
+//! with the skeleton sidecar next to them.
+use std::{
+    io::{Read, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, bail};
+use bevy_math::{Quat, Vec3, Vec4};
+use ndarray::{Array0, Array1, Array2};
+use ndarray_npy::{NpzReader, NpzWriter};
+
+use crate::{
+    Animation,
+    frame_rate::frame_rate,
+    npz::{FPS, JOINT_NAMES},
+    skeleton::Skeleton,
+};
+
+pub const KEYFRAMES_EXTENSION: &str = "keys.npz";
+const FRAME_COUNT: &str = "frame_count";
+const KEY_COUNTS: &str = "key_counts";
+const KEY_FRAMES: &str = "key_frames";
+const KEY_VALUES: &str = "key_values";
+
+/// Path of the keyframes `clip` is compressed to.
+pub fn keyframes_path(clip: &Path) -> PathBuf {
+    clip.with_extension(KEYFRAMES_EXTENSION)
+}
+
+/// How far a resampled frame may be from the original one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyframeTolerance {
+    /// Angle in radians between rotations.
+    pub angle: f32,
+    /// Distance between root positions, in the units of the clip.
+    pub distance: f32,
+}
+
+/// The keys of a curve, sorted by frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyCurve<T> {
+    pub frames: Vec<usize>,
+    pub values: Vec<T>,
+}
+
+impl<T: Copy> KeyCurve<T> {
+    /// Keys of `values` that interpolate all of them within `tolerance`.
+    fn reduce(
+        values: &[T],
+        interpolate: impl Fn(T, T, f32) -> T,
+        error: impl Fn(T, T) -> f32,
+        tolerance: f32,
+    ) -> Self {
+        let mut keys = vec![false; values.len()];
+        if let Some(last) = values.len().checked_sub(1) {
+            keys[0] = true;
+            keys[last] = true;
+            let mut spans = vec![(0, last)];
+            while let Some((start, end)) = spans.pop() {
+                let worst = (start + 1..end)
+                    .map(|frame| {
+                        let t = (frame - start) as f32 / (end - start) as f32;
+                        let interpolated = interpolate(values[start], values[end], t);
+                        (frame, error(interpolated, values[frame]))
+                    })
+                    .max_by(|(_, a), (_, b)| a.total_cmp(b));
+                if let Some((frame, worst_error)) = worst
+                    && worst_error > tolerance
+                {
+                    keys[frame] = true;
+                    spans.push((start, frame));
+                    spans.push((frame, end));
+                }
+            }
+        }
+        let frames: Vec<usize> = (0..values.len()).filter(|&frame| keys[frame]).collect();
+        KeyCurve {
+            values: frames.iter().map(|&frame| values[frame]).collect(),
+            frames,
+        }
+    }
+
+    /// Value of every frame up to `frame_count`, interpolated between the keys around it.
+    fn resample(&self, frame_count: usize, interpolate: impl Fn(T, T, f32) -> T) -> Vec<T> {
+        let mut next = 0;
+        (0..frame_count)
+            .map(|frame| {
+                while next < self.frames.len() && self.frames[next] < frame {
+                    next += 1;
+                }
+                match (next.checked_sub(1), self.frames.get(next)) {
+                    (_, Some(&key)) if key == frame => self.values[next],
+                    (Some(previous), Some(&key)) => {
+                        let start = self.frames[previous];
+                        let t = (frame - start) as f32 / (key - start) as f32;
+                        interpolate(self.values[previous], self.values[next], t)
+                    }
+                    (Some(previous), None) => self.values[previous],
+                    (None, _) => self.values[0],
+                }
+            })
+            .collect()
+    }
+}
+
+/// An animation stored as keyframes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyframeAnimation {
+    pub frame_count: usize,
+    pub root_positions: KeyCurve<Vec3>,
+    pub joint_rotations: Vec<KeyCurve<Quat>>,
+}
+
+impl KeyframeAnimation {
+    /// Keeps the keys of every curve of `animation` needed to stay within `tolerance`.
+    pub fn reduce(animation: &Animation, tolerance: KeyframeTolerance) -> Self {
+        KeyframeAnimation {
+            frame_count: animation.frame_count(),
+            root_positions: KeyCurve::reduce(
+                &animation.root_positions,
+                Vec3::lerp,
+                Vec3::distance,
+                tolerance.distance,
+            ),
+            joint_rotations: animation
+                .joint_rotations
+                .iter()
+                .map(|rotations| {
+                    KeyCurve::reduce(rotations, Quat::slerp, Quat::angle_between, tolerance.angle)
+                })
+                .collect(),
+        }
+    }
+
+    /// Keys of all curves.
+    pub fn key_count(&self) -> usize {
+        self.root_positions.frames.len()
+            + self
+                .joint_rotations
+                .iter()
+                .map(|curve| curve.frames.len())
+                .sum::<usize>()
+    }
+
+    /// The animation of every frame, without events.
+    pub fn resample(&self) -> Animation {
+        Animation {
+            root_positions: self.root_positions.resample(self.frame_count, Vec3::lerp),
+            joint_rotations: self
+                .joint_rotations
+                .iter()
+                .map(|curve| curve.resample(self.frame_count, Quat::slerp))
+                .collect(),
+            events: Vec::new(),
+        }
+    }
+}
+
+/// Writes the keyframes of an animation of `skeleton` as a compressed `.keys.npz` archive.
+pub fn write_keyframes_npz<W: Write + Seek>(
+    writer: W,
+    keyframes: &KeyframeAnimation,
+    skeleton: &Skeleton,
+    frame_time: f32,
+) -> Result<()> {
+    let key_counts: Array1<i64> = std::iter::once(keyframes.root_positions.frames.len())
+        .chain(keyframes.joint_rotations.iter().map(|c| c.frames.len()))
+        .map(|count| count as i64)
+        .collect();
+    let key_frames: Array1<i64> = keyframes
+        .root_positions
+        .frames
+        .iter()
+        .chain(keyframes.joint_rotations.iter().flat_map(|c| &c.frames))
+        .map(|&frame| frame as i64)
+        .collect();
+    let values: Vec<f32> = keyframes
+        .root_positions
+        .values
+        .iter()
+        .map(|p| p.extend(0.0))
+        .chain(
+            keyframes
+                .joint_rotations
+                .iter()
+                .flat_map(|c| c.values.iter().map(|&q| Vec4::from(q))),
+        )
+        .flat_map(|v| v.to_array())
+        .collect();
+    let key_values = Array2::from_shape_vec((key_frames.len(), 4), values)?;
+    let joint_names: Array1<u8> = skeleton
+        .joint_order()
+        .flat_map(|name| name.bytes().chain([b'\n']))
+        .collect();
+
+    let mut npz = NpzWriter::new_compressed(writer);
+    npz.add_array(
+        FRAME_COUNT,
+        &Array0::from_elem((), keyframes.frame_count as i64),
+    )?;
+    npz.add_array(FPS, &Array0::from_elem((), frame_rate(frame_time)))?;
+    npz.add_array(KEY_COUNTS, &key_counts)?;
+    npz.add_array(KEY_FRAMES, &key_frames)?;
+    npz.add_array(KEY_VALUES, &key_values)?;
+    npz.add_array(JOINT_NAMES, &joint_names)?;
+    npz.finish()?;
+    Ok(())
+}
+
+/// Reads keyframes written by [`write_keyframes_npz`] with their frame time, checking that
+/// their joints are those of `skeleton`.
+pub fn read_keyframes_npz<R: Read + Seek>(
+    reader: R,
+    skeleton: &Skeleton,
+) -> Result<(KeyframeAnimation, f32)> {
+    let mut npz = NpzReader::new(reader)?;
+    let names: Array1<u8> = npz.by_name(JOINT_NAMES)?;
+    let names = String::from_utf8(names.to_vec())?;
+    if !names.lines().eq(skeleton.joint_order()) {
+        bail!("The joints of the keyframes aren't those of their skeleton");
+    }
+    let frame_count: Array0<i64> = npz.by_name(FRAME_COUNT)?;
+    let fps: Array0<f32> = npz.by_name(FPS)?;
+    let key_counts: Array1<i64> = npz.by_name(KEY_COUNTS)?;
+    let key_frames: Array1<i64> = npz.by_name(KEY_FRAMES)?;
+    let key_values: Array2<f32> = npz.by_name(KEY_VALUES)?;
+    if key_counts.len() != skeleton.joint_count() + 1
+        || key_counts.sum() as usize != key_frames.len()
+        || key_values.dim() != (key_frames.len(), 4)
+    {
+        bail!("The key counts, frames and values of the keyframes don't match");
+    }
+
+    let frame_count = frame_count.into_scalar() as usize;
+    let mut start = 0;
+    let mut curves = key_counts.iter().map(|&count| {
+        let keys = start..start + count as usize;
+        start = keys.end;
+        let frames: Vec<usize> = keys.clone().map(|key| key_frames[key] as usize).collect();
+        if frames.first().is_some_and(|&first| first != 0)
+            || frames.windows(2).any(|pair| pair[0] >= pair[1])
+            || frames.last().is_some_and(|&last| last + 1 != frame_count)
+            || (frames.is_empty() && frame_count > 0)
+        {
+            bail!("The keys of a curve don't span the clip in order");
+        }
+        let values = keys
+            .map(|key| {
+                let v = key_values.row(key);
+                Vec4::new(v[0], v[1], v[2], v[3])
+            })
+            .collect::<Vec<_>>();
+        Ok((frames, values))
+    });
+    let (frames, values) = curves.next().transpose()?.unwrap_or_default();
+    let root_positions = KeyCurve {
+        frames,
+        values: values.into_iter().map(Vec4::truncate).collect(),
+    };
+    let joint_rotations = curves
+        .map(|curve| {
+            let (frames, values) = curve?;
+            Ok(KeyCurve {
+                frames,
+                values: values.into_iter().map(Quat::from_vec4).collect(),
+            })
+        })
+        .collect::<Result<_>>()?;
+    Ok((
+        KeyframeAnimation {
+            frame_count,
+            root_positions,
+            joint_rotations,
+        },
+        1.0 / fps.into_scalar(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyframes_resample_within_tolerance() {
+        let frame_count = 121;
+        // Walking steadily while the head nods once in the middle, and the arm holds still.
+        let nod = |frame: usize| {
+            let t = (frame as f32 - 60.0) / 10.0;
+            0.5 * (-t * t).exp()
+        };
+        let animation = Animation {
+            root_positions: (0..frame_count)
+                .map(|frame| Vec3::new(frame as f32, 90.0, 0.0))
+                .collect(),
+            joint_rotations: vec![
+                (0..frame_count)
+                    .map(|frame| Quat::from_rotation_x(nod(frame)))
+                    .collect(),
+                vec![Quat::from_rotation_z(0.3); frame_count],
+            ],
+            events: vec![],
+        };
+        let tolerance = KeyframeTolerance {
+            angle: 0.01,
+            distance: 0.01,
+        };
+        let keyframes = KeyframeAnimation::reduce(&animation, tolerance);
+        assert_eq!(keyframes.root_positions.frames, vec![0, 120]);
+        assert_eq!(keyframes.joint_rotations[1].frames, vec![0, 120]);
+        let nod_keys = keyframes.joint_rotations[0].frames.len();
+        assert!(nod_keys > 2 && nod_keys < 40);
+        assert!(keyframes.key_count() < frame_count);
+
+        let resampled = keyframes.resample();
+        assert_eq!(resampled.frame_count(), frame_count);
+        for frame in 0..frame_count {
+            let position = resampled.root_positions[frame];
+            assert!(position.distance(animation.root_positions[frame]) <= tolerance.distance);
+            for (joint, rotations) in resampled.joint_rotations.iter().enumerate() {
+                let original = animation.joint_rotations[joint][frame];
+                assert!(rotations[frame].angle_between(original) <= tolerance.angle + 1e-6);
+            }
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! Forward kinematics: global joint positions of an [`Animation`] on its skeleton, for
+//! positional losses and metrics. Roots are placed at the root positions of the animation, every
+//! other joint at its offset rotated by the global rotation of its parent.
+use anyhow::{Result, bail};
+use bevy_math::{Quat, Vec3};
+use ndarray::Array3;
+
+use crate::{Animation, skeleton::Skeleton};
+
+/// Rest position of every joint relative to the root.
+pub fn rest_positions(skeleton: &Skeleton) -> Vec<Vec3> {
+    let mut positions = vec![Vec3::ZERO; skeleton.joint_count()];
+    for joint in skeleton.depth_first_order() {
+        let parent = skeleton.joints[joint]
+            .parent
+            .map_or(Vec3::ZERO, |parent| positions[parent]);
+        positions[joint] = parent + skeleton.joints[joint].offset;
+    }
+    positions
+}
+
+/// Global position and rotation of every joint at `frame`, indexed like the skeleton.
+pub fn global_transforms(
+    skeleton: &Skeleton,
+    animation: &Animation,
+    frame: usize,
+) -> Vec<(Vec3, Quat)> {
+    let mut transforms = vec![(Vec3::ZERO, Quat::IDENTITY); skeleton.joint_count()];
+    for joint in skeleton.depth_first_order() {
+        let rotation = animation.joint_rotations[joint][frame];
+        transforms[joint] = match skeleton.joints[joint].parent {
+            Some(parent) => {
+                let (parent_position, parent_rotation) = transforms[parent];
+                (
+                    parent_position + parent_rotation * skeleton.joints[joint].offset,
+                    parent_rotation * rotation,
+                )
+            }
+            None => (animation.root_positions[frame], rotation),
+        };
+    }
+    transforms
+}
+
+/// Global position of every joint in every frame, as a `(joints, frames, 3)` tensor laid out
+/// like the curves of a GAV tensor.
+pub fn global_positions(skeleton: &Skeleton, animation: &Animation) -> Result<Array3<f32>> {
+    if animation.joint_count() != skeleton.joint_count() {
+        bail!(
+            "The animation has {} joints but the skeleton {}",
+            animation.joint_count(),
+            skeleton.joint_count()
+        );
+    }
+    let frame_count = animation.frame_count();
+    let mut positions = Array3::zeros((skeleton.joint_count(), frame_count, 3));
+    for frame in 0..frame_count {
+        for (joint, (position, _)) in global_transforms(skeleton, animation, frame)
+            .into_iter()
+            .enumerate()
+        {
+            for (channel, value) in position.to_array().into_iter().enumerate() {
+                positions[[joint, frame, channel]] = value;
+            }
+        }
+    }
+    Ok(positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+    use crate::skeleton::SkeletonJoint;
+
+    #[test]
+    fn test_global_positions_follow_parent_rotation() {
+        let joint = |name: &str, parent, offset| SkeletonJoint {
+            name: name.to_string(),
+            parent,
+            offset,
+            end_site: None,
+        };
+        let skeleton = Skeleton {
+            joints: vec![
+                joint("Hips", None, Vec3::ZERO),
+                joint("Spine", Some(0), Vec3::Y * 10.0),
+                joint("Neck", Some(1), Vec3::Y * 5.0),
+            ],
+        };
+        // The hips turn the spine onto +X in the second frame, the spine turns the neck further
+        // onto -Y.
+        let turn = Quat::from_rotation_z(-FRAC_PI_2);
+        let animation = Animation {
+            root_positions: vec![Vec3::ZERO, Vec3::new(0.0, 100.0, 0.0)],
+            joint_rotations: vec![
+                vec![Quat::IDENTITY, turn],
+                vec![Quat::IDENTITY, turn],
+                vec![Quat::IDENTITY; 2],
+            ],
+            events: vec![],
+        };
+
+        let positions = global_positions(&skeleton, &animation).unwrap();
+        assert_eq!(positions.dim(), (3, 2, 3));
+        let position = |joint: usize, frame: usize| {
+            Vec3::new(
+                positions[[joint, frame, 0]],
+                positions[[joint, frame, 1]],
+                positions[[joint, frame, 2]],
+            )
+        };
+        assert!(position(2, 0).distance(Vec3::Y * 15.0) < 1e-4);
+        assert!(position(1, 1).distance(Vec3::new(10.0, 100.0, 0.0)) < 1e-4);
+        assert!(position(2, 1).distance(Vec3::new(10.0, 95.0, 0.0)) < 1e-4);
+        assert_eq!(rest_positions(&skeleton)[2], Vec3::Y * 15.0);
+    }
+}
@@ -1,11 +1,94 @@
-use anyhow::Result;
+use std::{
+    io::{Cursor, Read},
+    path::Path,
+};
+
+use anyhow::{Context, Result, bail};
 use bevy_math::{Quat, Vec3};
-use bvh_anim_parser::types::BvhData;
-use ndarray::{Array3, ShapeError};
+use bvh_anim_parser::{
+    parse::{load_bvh_from_file, load_bvh_from_string},
+    types::{BvhData, BvhMetadata},
+};
+use ndarray::{Array3, Axis, ShapeError, concatenate};
+
+pub mod alignment;
+pub mod archive;
+pub mod augment;
+pub mod beats;
+pub mod blender_export;
+pub mod blending;
+pub mod bucketing;
+pub mod bvh_writer;
+pub mod constraints;
+pub mod contacts;
+pub mod container;
+pub mod convention;
+pub mod conversion;
+pub mod conversion_settings;
+pub mod coverage;
+pub mod csv_export;
+pub mod deflicker;
+pub mod delta;
+pub mod derivatives;
+pub mod difficulty;
+pub mod dtype;
+pub mod dual_quaternion;
+pub mod embedding;
+pub mod eval_protocol;
+pub mod events;
+pub mod fingers;
+pub mod folds;
+pub mod frame_rate;
+pub mod gallery;
+pub mod gaze;
+pub mod hdf5_export;
+pub mod humanml3d;
+pub mod integrity;
+pub mod joint_map;
+pub mod joint_pca;
+pub mod joint_sets;
+pub mod keyframes;
+pub mod kinematics;
+pub mod manifest;
+pub mod masking;
+pub mod merge;
+pub mod metadata;
+pub mod metrics;
+pub mod mirror;
+pub mod model_tag;
+pub mod noise_sweep;
+pub mod normalization;
+pub mod npz;
+pub mod parquet_export;
+pub mod plot;
+pub mod pose;
+pub mod pose_prior;
+pub mod pyramid;
+pub mod quantization;
+pub mod reference_motion;
+pub mod retarget;
+pub mod root_motion;
+pub mod round_trip;
+pub mod samples;
+pub mod sampling;
+pub mod search;
+pub mod segmentation;
+pub mod skeleton;
+pub mod smoothing;
+pub mod storage;
+pub mod thumbnail;
+pub mod tracking;
+pub mod validation;
+pub mod weights;
+pub mod windows;
 
+#[derive(Clone)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
 pub struct Animation {
     pub root_positions: Vec<Vec3>,
     pub joint_rotations: Vec<Vec<Quat>>,
+    /// Event markers, sorted by frame. See [`events`] for how they are stored next to a clip.
+    pub events: Vec<events::AnimationEvent>,
 }
 
 impl Animation {
@@ -17,8 +100,99 @@ impl Animation {
         self.root_positions.len()
     }
 }
+
+/// Parses the BVH clip at `path`, a local file or an object, see [`storage`].
+pub fn load_bvh(path: &Path) -> Result<(BvhMetadata, BvhData)> {
+    let parsed = if storage::is_remote(path) {
+        let text = String::from_utf8(storage::read(path)?)
+            .with_context(|| format!("{} is not a text file", path.display()))?;
+        conversion::catch_panic(|| load_bvh_from_string(&text))
+    } else {
+        conversion::catch_panic(|| load_bvh_from_file(&path.to_string_lossy()))
+    };
+    parsed.with_context(|| format!("Could not parse {}", path.display()))
+}
+
+/// Loads the BVH clip at `path` as its skeleton and an [`Animation`] of it, with the events of
+/// its sidecar, see [`events::events_path`]. Use [`load_bvh`] for the frame time and the rest
+/// of the header.
+pub fn load_animation(path: &Path) -> Result<(skeleton::Skeleton, Animation)> {
+    let (bvh_meta, bvh_data) = load_bvh(path)?;
+    check_frame_counts(&bvh_meta, &bvh_data)
+        .with_context(|| format!("Invalid clip {}", path.display()))?;
+    let mut animation = bvh_to_animation(&bvh_data, bvh_meta.num_frames);
+    animation.events = events::read_events(&events::events_path(path))?;
+    Ok((
+        skeleton::Skeleton::from_bvh(&bvh_meta, &bvh_data),
+        animation,
+    ))
+}
+
+/// Builds an [`Animation`] holding the full local rotation of every joint.
+pub fn bvh_to_animation(bvh_data: &BvhData, frame_count: usize) -> Animation {
+    let root_positions = bvh_data.pose_local_positions[0]
+        .iter()
+        .take(frame_count)
+        .map(|p| Vec3::new(p.x as f32, p.y as f32, p.z as f32))
+        .collect();
+    let joint_rotations = bvh_data
+        .pose_local_rotations
+        .iter()
+        .map(|joint| {
+            joint
+                .iter()
+                .take(frame_count)
+                .map(|q| Quat::from_xyzw(q.v.x as f32, q.v.y as f32, q.v.z as f32, q.s as f32))
+                .collect()
+        })
+        .collect();
+    Animation {
+        root_positions,
+        joint_rotations,
+        events: Vec::new(),
+    }
+}
+
+/// Checks that the root position track and the rotation track of every joint have the frame
+/// count of the header, naming the first track that doesn't.
+pub fn check_frame_counts(bvh_meta: &BvhMetadata, bvh_data: &BvhData) -> Result<()> {
+    let frame_count = bvh_meta.num_frames;
+    let Some(root_positions) = bvh_data.pose_local_positions.first() else {
+        bail!("The clip has no root position track");
+    };
+    if root_positions.len() != frame_count {
+        bail!(
+            "The root position track has {} frames but the header declares {}",
+            root_positions.len(),
+            frame_count
+        );
+    }
+    for (index, rotations) in bvh_data.pose_local_rotations.iter().enumerate() {
+        if rotations.len() != frame_count {
+            let joint = bvh_meta
+                .joints
+                .iter()
+                .find(|joint| joint.index == index)
+                .map_or_else(|| format!("joint {}", index), |joint| joint.name.clone());
+            bail!(
+                "The rotation track of {} has {} frames but the header declares {}",
+                joint,
+                rotations.len(),
+                frame_count
+            );
+        }
+    }
+    Ok(())
+}
+
 /// BVH to GAV (Geometric Algebra Animation Vector)
-pub fn bvh_to_gav(bvh_data: &BvhData, frame_count: usize) -> Result<Array3<f32>, ShapeError> {
+///
+/// Curve 0 holds the root positions, curve `i + 1` joint `i` in the order of
+/// [`skeleton::Skeleton::joint_order`]. Fails when a track doesn't have the frame count of the
+/// header, see [`check_frame_counts`].
+pub fn bvh_to_gav(bvh_meta: &BvhMetadata, bvh_data: &BvhData) -> Result<Array3<f32>> {
+    check_frame_counts(bvh_meta, bvh_data)?;
+    let frame_count = bvh_meta.num_frames;
     let joint_count = bvh_data.pose_local_rotations.len();
     let mut data = Vec::with_capacity(frame_count * (joint_count + 1));
     for frame in &bvh_data.pose_local_positions[0] {
@@ -33,16 +207,68 @@ pub fn bvh_to_gav(bvh_data: &BvhData, frame_count: usize) -> Result<Array3<f32>,
             // This is the equivalent of the bivector. When converting back to a quaternion,
             // The magnitude of the rotation can easily be recomputed since the original quaternion
             // was normalized and the bivector is not.
-            data.push(quat.v.x as f32);
-            data.push(quat.v.y as f32);
-            data.push(quat.v.z as f32);
+            // The scalar part is recomputed as non-negative, so quaternions in the other
+            // hemisphere are negated first. -q is the same rotation as q.
+            let sign = if quat.s < 0.0 { -1.0 } else { 1.0 };
+            data.push((sign * quat.v.x) as f32);
+            data.push((sign * quat.v.y) as f32);
+            data.push((sign * quat.v.z) as f32);
         }
     }
 
-    Array3::from_shape_vec((joint_count + 1, frame_count, 3), data)
+    Ok(Array3::from_shape_vec(
+        (joint_count + 1, frame_count, 3),
+        data,
+    )?)
 }
 
-/// BVH to GAV (Geometric Algebra Animation Vector)
+/// The quaternion of `rotation` with a non-negative scalar part, the hemisphere
+/// [`gav_to_animation`] reconstructs.
+pub fn canonical_rotation(rotation: Quat) -> Quat {
+    if rotation.w < 0.0 {
+        -rotation
+    } else {
+        rotation
+    }
+}
+
+/// The unit quaternion with vector part `v` and a non-negative scalar part, as stored in a GAV
+/// tensor.
+pub fn rotation_from_vector(v: Vec3) -> Quat {
+    let w = (1.0 - v.length_squared()).max(0.0).sqrt();
+    Quat::from_xyzw(v.x, v.y, v.z, w).normalize()
+}
+
+/// Encodes an [`Animation`] the same way [`bvh_to_gav`] encodes a BVH clip.
+pub fn animation_to_gav(animation: &Animation) -> Result<Array3<f32>, ShapeError> {
+    let frame_count = animation.frame_count();
+    let mut data = Vec::with_capacity(frame_count * (animation.joint_count() + 1) * 3);
+    data.extend(animation.root_positions.iter().flat_map(|p| p.to_array()));
+    for joint in &animation.joint_rotations {
+        data.extend(
+            joint
+                .iter()
+                .flat_map(|q| canonical_rotation(*q).xyz().to_array()),
+        );
+    }
+    Array3::from_shape_vec((animation.joint_count() + 1, frame_count, 3), data)
+}
+
+/// Appends an extra per-frame channel to a GAV tensor as one more curve, after the joints.
+pub fn append_curve(gav_data: &Array3<f32>, values: &[Vec3]) -> Result<Array3<f32>> {
+    let frame_count = gav_data.dim().1;
+    if values.len() != frame_count {
+        bail!("{} channel values for {} frames", values.len(), frame_count);
+    }
+    let data = values.iter().flat_map(|v| v.to_array()).collect();
+    let curve = Array3::from_shape_vec((1, frame_count, 3), data)?;
+    Ok(concatenate(Axis(0), &[gav_data.view(), curve.view()])?)
+}
+
+/// GAV (Geometric Algebra Animation Vector) to [`Animation`]
+///
+/// The scalar part of every rotation is recomputed from its vector part, assuming the unit
+/// quaternion has a non-negative scalar part.
 pub fn gav_to_animation(gav_data: Array3<f32>) -> Result<Animation> {
     let (curve_count, frame_count, _) = gav_data.dim();
     let mut root_positions = Vec::with_capacity(frame_count);
@@ -55,15 +281,115 @@ pub fn gav_to_animation(gav_data: Array3<f32>) -> Result<Animation> {
                 root_positions.push(Vec3::new(frame_value[0], frame_value[1], frame_value[2]));
             } else {
                 let joint_index = curve_index - 1;
-                joint_rotations[joint_index].push(
-                    Quat::from_xyzw(frame_value[0], frame_value[1], frame_value[2], 0.0)
-                        .normalize(),
-                );
+                let v = Vec3::new(frame_value[0], frame_value[1], frame_value[2]);
+                joint_rotations[joint_index].push(rotation_from_vector(v));
             }
         }
     }
     Ok(Animation {
         root_positions,
         joint_rotations,
+        events: Vec::new(),
     })
 }
+
+/// Loads a GAV tensor together with its skeleton and frame time, from a `.gav` container or
+/// from a `.npy` or `.npz` file and its sidecar, see [`skeleton::skeleton_path`] and [`npz`].
+/// Curves appended after
+/// the joints are dropped, tensors of a normalized dataset are denormalized, see
+/// [`normalization`], and delta tensors are decoded, see [`delta`].
+pub fn load_gav(path: &Path) -> Result<(Animation, skeleton::Skeleton, f32)> {
+    load_gav_from(path, storage::open(path)?)
+}
+
+/// Like [`load_gav`], but reads the `.gav`, `.npy` or `.npz` file at `path` from `reader`, for
+/// callers that want to track how much of a large file has been read. The skeleton sidecar of
+/// a `.npy` or `.npz` file is still opened next to `path`.
+pub fn load_gav_from<R: Read>(
+    path: &Path,
+    mut reader: R,
+) -> Result<(Animation, skeleton::Skeleton, f32)> {
+    let (mut animation, skeleton, frame_time) = if path.extension().is_some_and(|e| e == "gav") {
+        let gav = container::read_gav(&mut reader)?;
+        (gav_to_animation(gav.data)?, gav.skeleton, gav.frame_time)
+    } else {
+        let sidecar = skeleton::skeleton_path(path);
+        let file = storage::open(&sidecar).with_context(|| {
+            format!("Could not open the skeleton sidecar {}", sidecar.display())
+        })?;
+        let (skeleton, frame_time) = skeleton::read_skeleton_sidecar(file)?;
+        if path.extension().is_some_and(|e| e == "npz") {
+            // Zip archives are read from their end, so the archive is read whole first.
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes)?;
+            let animation = npz::read_npz(Cursor::new(bytes), &skeleton)?;
+            (animation, skeleton, frame_time)
+        } else {
+            let (mut data, dtype) = dtype::read_typed_tensor(reader)?;
+            quantization::dequantize_tensor(path, &mut data, dtype)?;
+            if let Some(stats) = normalization::NormalizationStats::applied_to(path)? {
+                stats.denormalize(&mut data)?;
+            }
+            let animation = if delta::is_delta_path(path) {
+                delta::delta_gav_to_animation(data)?
+            } else {
+                gav_to_animation(data)?
+            };
+            (animation, skeleton, frame_time)
+        }
+    };
+    if animation.joint_count() < skeleton.joint_count() {
+        bail!(
+            "{} has {} joint curves but its skeleton {} joints",
+            path.display(),
+            animation.joint_count(),
+            skeleton.joint_count()
+        );
+    }
+    animation.joint_rotations.truncate(skeleton.joint_count());
+    Ok((animation, skeleton, frame_time))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gav_round_trip_keeps_rotations_past_half_turn() {
+        // 270 degrees about Y has a negative scalar part.
+        let rotations = [0.0f32, 90.0, 180.0, 270.0]
+            .map(|angle| Quat::from_rotation_y(angle.to_radians()))
+            .to_vec();
+        assert!(rotations[3].w < 0.0);
+        let animation = Animation {
+            root_positions: vec![Vec3::ZERO; rotations.len()],
+            joint_rotations: vec![rotations.clone()],
+            events: vec![],
+        };
+
+        let decoded = gav_to_animation(animation_to_gav(&animation).unwrap()).unwrap();
+        for (original, decoded) in rotations.iter().zip(&decoded.joint_rotations[0]) {
+            assert!(original.angle_between(*decoded) < 1e-3);
+            assert!((*original * Vec3::X).distance(*decoded * Vec3::X) < 1e-3);
+        }
+    }
+
+    #[cfg(feature = "serialize")]
+    #[test]
+    fn test_animation_serde_round_trip() {
+        let animation = Animation {
+            root_positions: vec![Vec3::new(1.0, 90.0, -2.0); 2],
+            joint_rotations: vec![vec![Quat::from_rotation_x(0.5), Quat::IDENTITY]],
+            events: vec![events::AnimationEvent {
+                frame: 1,
+                name: "step".to_string(),
+                payload: None,
+            }],
+        };
+        let json = serde_json::to_string(&animation).unwrap();
+        let decoded: Animation = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.root_positions, animation.root_positions);
+        assert_eq!(decoded.joint_rotations, animation.joint_rotations);
+        assert_eq!(decoded.events, animation.events);
+    }
+}
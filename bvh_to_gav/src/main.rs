@@ -1,40 +1,3314 @@
-use std::env;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    env,
+    ffi::OsStr,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Cursor, Read, Write},
+    panic::AssertUnwindSafe,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
 
-use anyhow::Result;
-use bvh_anim_parser::parse::load_bvh_from_file;
-use bvh_to_gav::bvh_to_gav;
-use ndarray_npy::write_npy;
+use anyhow::{Context, Result, bail};
+use bevy_math::Vec2;
+use bvh_anim_parser::{
+    parse::load_bvh_from_string,
+    types::{BvhData, BvhMetadata},
+};
+use bvh_to_gav::{
+    Animation,
+    alignment::RootAlignment,
+    animation_to_gav, append_curve,
+    archive::{ArchiveFormat, extraction_folder, for_each_file},
+    augment::{Augmentation, augment},
+    beats::{append_beat_phase, beat_grid, beat_phase, read_beats, warp_to_beat_grid},
+    blender_export::BlenderBundle,
+    bucketing::{BucketAssignment, PaddingReport, bucket_lengths, bucket_of, clip_sequences},
+    bvh_to_animation, bvh_to_gav,
+    bvh_writer::write_bvh,
+    check_frame_counts,
+    constraints::project_rotations,
+    contacts::{ContactConfig, contacts_path, detect_contacts},
+    container::{GavFile, read_gav, write_gav},
+    convention::CoordinateConvention,
+    conversion::{CONVERSION_REPORT_FILE, ConversionReport, catch_panic, install_panic_hook},
+    coverage::{COVERAGE_REPORT_FILE, CoverageReport, LOW_COVERAGE},
+    csv_export::{curve_names, select_curves, write_channels_csv},
+    deflicker::{DeflickerSettings, deflicker},
+    delta::{DELTA_EXTENSION, animation_to_delta_gav, delta_gav_to_animation, is_delta_path},
+    derivatives::append_motion_channels,
+    difficulty::{ClipDifficulty, DifficultyFeatures, score_difficulty},
+    dtype::{Dtype, read_tensor, write_tensor},
+    dual_quaternion::{animation_to_dual_quaternions, dual_quaternions_to_animation},
+    embedding::{ClipEmbedding, EMBEDDING_INDEX_FILE, EmbeddingIndex, KinematicEmbedding},
+    eval_protocol::{ClipResult, EvaluationResults, Protocol, RESULTS_VERSION, summarize},
+    events::{
+        AnimationEvent, EVENTS_EXTENSION, events_path, parse_events, read_events, write_events,
+    },
+    fingers::{FingerEncoding, fingers_path},
+    folds::{FoldGrouping, assign_folds},
+    frame_rate::{FRAME_RATE_REPORT_FILE, FrameRateReport, frame_rate, resample_frame_time},
+    gallery::{GalleryClip, write_gallery},
+    gav_to_animation,
+    gaze::{DEFAULT_GAZE_DISTANCE, DEFAULT_HEAD_FORWARD, compute_gaze, find_head},
+    hdf5_export::Hdf5Writer,
+    humanml3d::humanml3d_features,
+    integrity::{FileHash, clip_files, hash_file, relative_path, verify},
+    joint_pca::{JOINT_PCA_FILE, JointPcaReport},
+    keyframes::{
+        KeyframeAnimation, KeyframeTolerance, keyframes_path, read_keyframes_npz,
+        write_keyframes_npz,
+    },
+    load_bvh, load_gav,
+    manifest::{Exclusion, Manifest, MetadataFilter},
+    masking::{
+        MASK_MANIFEST_FILE, MaskManifest, MaskStrategy, MaskedPair, apply_mask, mask_path,
+        masked_path,
+    },
+    merge::{MERGE_MANIFEST_FILE, merged_metadata, prefixed_id, reconcile},
+    metadata::{derive_metadata, metadata_path, read_metadata, write_metadata},
+    metrics::MotionMetrics,
+    mirror::{check_mirroring, lateral_axis, mirror_pairs},
+    noise_sweep::{
+        NOISE_MANIFEST_FILE, NoiseManifest, NoisePair, NoiseSchedule, noised, noised_path,
+    },
+    normalization::{
+        NORMALIZATION_FILE, NormalizationAccumulator, NormalizationMode, NormalizationStats,
+        normalization_path, read_raw_tensor,
+    },
+    npz::write_npz,
+    parquet_export::ParquetWriter,
+    pose::{Pose, read_pose_json, read_skeleton_json, write_pose_json},
+    pose_prior::{POSE_PRIOR_FILE, PosePrior},
+    pyramid::{PYRAMID_EXTENSION, write_pyramid},
+    quantization::{read_tensor_file, write_tensor_file},
+    reference_motion::{DeepMimicMotion, ReferenceFormat, write_lafan_npz},
+    retarget::Retargeting,
+    root_motion::{apply_root_motion, extract_root_motion, trajectory_curve},
+    round_trip::round_trip_errors,
+    sampling::{SamplingUnit, UNLABELED, balance, class_summary},
+    search::{PoseIndex, root_path, search_trajectories},
+    segmentation::{Segment, SegmentationConfig, segment},
+    skeleton::{Skeleton, read_skeleton_sidecar, skeleton_path, write_skeleton_json},
+    smoothing::smooth,
+    storage,
+    thumbnail::encode_gif,
+    tracking::{ClipConstraints, constraints_path},
+    validation::{channel_stats, validate_clip},
+    windows::{
+        ClipWindow, WINDOW_MANIFEST_FILE, WindowManifest, Windows, hemisphere_signs,
+        hemispheres_path, positions_path, window_path, window_rng, windows_path,
+    },
+};
+use clap::Parser;
+use ndarray::{Array3, s};
+use ndarray_npy::{WriteNpyExt, write_npy};
+use rand::{SeedableRng, rngs::StdRng};
+use serde::Serialize;
 
-fn convert_bvh_to_gav(source_folder: &str) -> Result<usize> {
-    let mut count = 0;
-    for file in std::fs::read_dir(source_folder).unwrap() {
-        let file = file.unwrap();
-        let path = file.path();
-        let output_path = path.with_extension("npy");
-        if path.extension().map(|s| s == "bvh").unwrap_or(false) {
-            // Call the conversion function here
-            if let Some(path) = file.path().to_str() {
-                let (bvh_meta, bvh_data) = load_bvh_from_file(path);
-                let gav_tensor = bvh_to_gav(&bvh_data, bvh_meta.num_frames)?;
-                write_npy(output_path, &gav_tensor)?;
-                count += 1;
+use crate::cli::{
+    Cli, CodecCommand, Command, ConvertArgs, EXIT_CHECK_FAILED, EXIT_FAILURE, FingersCommand,
+    MetaCommand, PriorCommand, VerifyCommand,
+};
+
+mod cli;
+
+/// Writes `array` as a `.npy` file to `path`, a local file or an object, see [`storage`].
+fn write_array<A: WriteNpyExt>(path: &Path, array: &A) -> Result<()> {
+    storage::write_with(path, |writer| Ok(array.write_npy(writer)?))
+}
+
+/// Converts every BVH clip in the source folder, see [`ConvertArgs`]. A clip that fails to
+/// convert doesn't stop the others, it's recorded in the report, which is saved next to the
+/// converted tensors as [`CONVERSION_REPORT_FILE`].
+fn convert_bvh_to_gav(options: &ConvertArgs) -> Result<ConversionReport> {
+    let archive = ArchiveFormat::of(&options.source_folder);
+    let output_folder = match (&options.output.out_dir, archive) {
+        (Some(out_dir), _) => out_dir.clone(),
+        (None, Some(_)) => extraction_folder(&options.source_folder),
+        (None, None) => options.source_folder.clone(),
+    };
+    if options.normalize.is_some() && storage::is_remote(&output_folder) {
+        bail!("--normalize needs a local output folder");
+    }
+    if options.canonicalize && storage::is_remote(&output_folder) {
+        bail!("--canonicalize needs a local output folder for the metadata");
+    }
+    storage::set_write_options(options.write.options())?;
+    let mut report = ConversionReport::default();
+    // The clips converted and their tensors, to split into windows once normalized.
+    let mut converted = Vec::new();
+    if let Some(format) = archive {
+        convert_archive(options, format, &output_folder, &mut report, &mut converted)?;
+    } else {
+        let inputs = storage::list_files(&options.source_folder, options.recursive)?;
+        for input in inputs
+            .iter()
+            .filter(|path| path.extension() == Some(OsStr::new("bvh")))
+        {
+            let output_path =
+                options
+                    .output
+                    .output_path(&options.source_folder, input, options.extension());
+            if storage::exists(&output_path)? && !options.output.overwrite {
+                report.skipped += 1;
+                continue;
+            }
+            let result = catch_panic(|| {
+                let events = read_events(&events_path(input))?;
+                let separate_events = options.output.out_dir.is_some();
+                convert_clip(
+                    options,
+                    load_bvh(input)?,
+                    events,
+                    separate_events,
+                    &output_path,
+                    None,
+                )?;
+                record_conversion_settings(options, input, &output_path)
+            });
+            let result = result.and_then(|converted| converted);
+            if result.is_ok() {
+                converted.push((input.clone(), output_path));
+            }
+            report.record(input, result);
+        }
+    }
+
+    if let Some(mode) = options.normalize
+        && report.converted > 0
+    {
+        normalize_dataset(&output_folder, mode, true)?;
+    }
+    if let Some(windows) = options.windows.filter(|_| options.split_windows) {
+        let mut manifest = WindowManifest::default();
+        for (source, output_path) in &converted {
+            manifest.windows.extend(split_windows(
+                source,
+                &clip_tensors(output_path, options),
+                &windows,
+            )?);
+        }
+        manifest.save(&output_folder.join(WINDOW_MANIFEST_FILE))?;
+    }
+    report.writes = storage::write_stats();
+    report.save(&output_folder.join(CONVERSION_REPORT_FILE))?;
+    Ok(report)
+}
+
+/// Calls `f` with every file of the archive at `path`, see [`for_each_file`].
+fn read_archive(
+    path: &Path,
+    format: ArchiveFormat,
+    f: impl FnMut(&Path, &mut dyn Read) -> Result<()>,
+) -> Result<()> {
+    if storage::is_remote(path) {
+        return for_each_file(format, Cursor::new(storage::read(path)?), f);
+    }
+    let file = File::open(path).with_context(|| format!("Could not open {}", path.display()))?;
+    for_each_file(format, BufReader::new(file), f)
+}
+
+/// Converts the BVH clips of an archive into `output_folder`, keeping the folders of the
+/// archive. The archive is read twice, first for the events sidecars, which can come after
+/// their clip.
+fn convert_archive(
+    options: &ConvertArgs,
+    format: ArchiveFormat,
+    output_folder: &Path,
+    report: &mut ConversionReport,
+    converted: &mut Vec<(PathBuf, PathBuf)>,
+) -> Result<()> {
+    let mut sidecars = HashMap::new();
+    read_archive(&options.source_folder, format, |path, content| {
+        if path.to_string_lossy().ends_with(EVENTS_EXTENSION) {
+            let mut bytes = Vec::new();
+            content.read_to_end(&mut bytes)?;
+            sidecars.insert(path.to_path_buf(), bytes);
+        }
+        Ok(())
+    })?;
+    read_archive(&options.source_folder, format, |path, content| {
+        if path.extension() != Some(OsStr::new("bvh")) {
+            return Ok(());
+        }
+        let output_path = output_folder.join(path).with_extension(options.extension());
+        if storage::exists(&output_path)? && !options.output.overwrite {
+            report.skipped += 1;
+            return Ok(());
+        }
+        let result = catch_panic(AssertUnwindSafe(|| {
+            let mut text = String::new();
+            content.read_to_string(&mut text)?;
+            let bvh = catch_panic(|| load_bvh_from_string(&text))
+                .with_context(|| format!("Could not parse {}", path.display()))?;
+            let events = match sidecars.get(&events_path(path)) {
+                Some(bytes) => parse_events(bytes)?,
+                None => Vec::new(),
+            };
+            convert_clip(options, bvh, events, true, &output_path, None)
+        }));
+        let source = options.source_folder.join(path);
+        let result = result.and_then(|converted| converted);
+        if result.is_ok() {
+            converted.push((source.clone(), output_path));
+        }
+        report.record(&source, result);
+        Ok(())
+    })
+}
+
+/// Path the mirrored variant of the clip converted to `output_path` is written to.
+fn mirrored_path(output_path: &Path, options: &ConvertArgs) -> PathBuf {
+    let stem = output_path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy();
+    output_path.with_file_name(format!("{}_mirrored.{}", stem, options.extension()))
+}
+
+/// Records the settings the clip `input` was converted to `output_path` with in the metadata
+/// of the tensor, for the preview to convert it again, see [`bvh_to_gav::conversion_settings`].
+fn record_conversion_settings(
+    options: &ConvertArgs,
+    input: &Path,
+    output_path: &Path,
+) -> Result<()> {
+    let Some(settings) = options
+        .settings(input)
+        .filter(|_| !storage::is_remote(output_path))
+    else {
+        return Ok(());
+    };
+    let mut metadata = read_metadata(output_path)?;
+    settings.apply_to(&mut metadata);
+    write_metadata(output_path, &metadata)
+}
+
+/// Records the transform `alignment` removed from the clip converted to `output_path` in its
+/// metadata, or clears the one of an earlier conversion.
+fn record_alignment(output_path: &Path, alignment: Option<RootAlignment>) -> Result<()> {
+    if storage::is_remote(output_path)
+        || (alignment.is_none() && !metadata_path(output_path).exists())
+    {
+        return Ok(());
+    }
+    let mut metadata = read_metadata(output_path)?;
+    match alignment {
+        Some(alignment) => alignment.apply_to(&mut metadata),
+        None => RootAlignment::clear(&mut metadata),
+    }
+    write_metadata(output_path, &metadata)
+}
+
+/// Tensors a clip is converted to, `output_path` and its variants.
+fn clip_tensors(output_path: &Path, options: &ConvertArgs) -> Vec<PathBuf> {
+    let mut tensors = vec![output_path.to_path_buf()];
+    if options.mirror || options.mirror_map.is_some() {
+        tensors.push(mirrored_path(output_path, options));
+    }
+    tensors
+}
+
+/// Writes every window of the `tensors` converted from the clip `source` as a tensor of its
+/// own, in the dtype of the tensor and normalized when it is, and returns them for the window
+/// manifest.
+fn split_windows(source: &Path, tensors: &[PathBuf], windows: &Windows) -> Result<Vec<ClipWindow>> {
+    let mut split = Vec::new();
+    for tensor in tensors {
+        let (data, dtype) = read_tensor_file(tensor)?;
+        let normalized = NormalizationStats::applied_to(tensor)?.is_some();
+        for (index, (start, window)) in windows.split(&data).into_iter().enumerate() {
+            let path = window_path(tensor, index);
+            write_tensor_file(&path, &window, dtype)?;
+            NormalizationStats::record(&path, normalized)?;
+            split.push(ClipWindow {
+                window: path.to_string_lossy().into_owned(),
+                tensor: tensor.to_string_lossy().into_owned(),
+                source: source.to_string_lossy().into_owned(),
+                start,
+                end: start + windows.length,
+            });
+        }
+    }
+    Ok(split)
+}
+
+/// Outcome of a request to [`ConversionService`], written back as a line of JSON.
+#[derive(Serialize)]
+struct ConversionResponse {
+    clip: String,
+    outputs: Vec<String>,
+    skipped: bool,
+    error: Option<String>,
+}
+
+/// Converts clips one request at a time in a long-running process. The options of a folder
+/// conversion, with the joint map, mirror map and joint set they name, are parsed once, and
+/// kept between requests are the normalization statistics of every output folder, which clips
+/// are normalized with before they are written when their folder is, and the buffer clips are
+/// read into.
+struct ConversionService<'a> {
+    options: &'a ConvertArgs,
+    normalization: HashMap<PathBuf, Option<NormalizationStats>>,
+    text: String,
+}
+
+impl<'a> ConversionService<'a> {
+    fn new(options: &'a ConvertArgs) -> Result<Self> {
+        if options.normalize.is_some() {
+            bail!(
+                "The service normalizes with the statistics of the output folders, convert with \
+                 --normalize first"
+            );
+        }
+        storage::set_write_options(options.write.options())?;
+        Ok(ConversionService {
+            options,
+            normalization: HashMap::new(),
+            text: String::new(),
+        })
+    }
+
+    /// Converts the BVH clip `input`, below the source folder, and returns the tensors written,
+    /// none when it was already converted.
+    fn convert(&mut self, input: &Path) -> Result<Vec<PathBuf>> {
+        let options = self.options;
+        let output_path =
+            options
+                .output
+                .output_path(&options.source_folder, input, options.extension());
+        if storage::exists(&output_path)? && !options.output.overwrite {
+            return Ok(Vec::new());
+        }
+        self.text.clear();
+        storage::open(input)?
+            .read_to_string(&mut self.text)
+            .with_context(|| format!("{} is not a text file", input.display()))?;
+        let text = &self.text;
+        let bvh = catch_panic(|| load_bvh_from_string(text))
+            .with_context(|| format!("Could not parse {}", input.display()))?;
+        let events = read_events(&events_path(input))?;
+        let folder = output_path.parent().unwrap_or(Path::new("")).to_path_buf();
+        let stats = match self.normalization.get(&folder) {
+            Some(stats) => stats,
+            None => {
+                let path = normalization_path(&output_path);
+                let stats = if storage::exists(&path)? {
+                    Some(NormalizationStats::load(&path)?).filter(NormalizationStats::is_applied)
+                } else {
+                    None
+                };
+                self.normalization.entry(folder).or_insert(stats)
+            }
+        };
+        convert_clip(
+            options,
+            bvh,
+            events,
+            options.output.out_dir.is_some(),
+            &output_path,
+            stats.as_ref(),
+        )?;
+        record_conversion_settings(options, input, &output_path)?;
+
+        let mut outputs = clip_tensors(&output_path, options);
+        if let Some(windows) = options.windows.filter(|_| options.split_windows) {
+            let split = split_windows(input, &outputs, &windows)?;
+            outputs.extend(split.into_iter().map(|window| PathBuf::from(window.window)));
+        }
+        Ok(outputs)
+    }
+}
+
+/// Serves conversion requests, the path of a BVH clip per line of `requests`, until they end,
+/// answering each with a line of JSON. Returns the number of failed requests.
+fn serve_conversions(
+    options: &ConvertArgs,
+    requests: impl BufRead,
+    mut responses: impl Write,
+) -> Result<usize> {
+    let mut service = ConversionService::new(options)?;
+    let mut failed = 0;
+    for line in requests.lines() {
+        let line = line?;
+        let clip = line.trim();
+        if clip.is_empty() {
+            continue;
+        }
+        let result = catch_panic(AssertUnwindSafe(|| service.convert(Path::new(clip))))
+            .and_then(|converted| converted);
+        let response = match result {
+            Ok(outputs) => ConversionResponse {
+                clip: clip.to_string(),
+                skipped: outputs.is_empty(),
+                outputs: outputs
+                    .iter()
+                    .map(|output| output.to_string_lossy().into_owned())
+                    .collect(),
+                error: None,
+            },
+            Err(e) => {
+                failed += 1;
+                ConversionResponse {
+                    clip: clip.to_string(),
+                    outputs: Vec::new(),
+                    skipped: false,
+                    error: Some(format!("{:#}", e)),
+                }
+            }
+        };
+        serde_json::to_writer(&mut responses, &response)?;
+        writeln!(responses)?;
+        responses.flush()?;
+    }
+    Ok(failed)
+}
+
+/// Converts a parsed BVH clip with its `events` to the tensor `output_path`, with its
+/// sidecars. `separate_events` is set when the tensor isn't written next to the clip, so it
+/// can't share the events sidecar of the clip. Tensors are normalized with `normalization`
+/// when it is given.
+fn convert_clip(
+    options: &ConvertArgs,
+    (mut bvh_meta, mut bvh_data): (BvhMetadata, BvhData),
+    events: Vec<AnimationEvent>,
+    separate_events: bool,
+    output_path: &Path,
+    normalization: Option<&NormalizationStats>,
+) -> Result<()> {
+    check_frame_counts(&bvh_meta, &bvh_data)?;
+    options
+        .convention
+        .to(&CoordinateConvention::default())
+        .apply_to_bvh(&mut bvh_meta, &mut bvh_data);
+    let mut frame_time = bvh_meta.frame_time as f32;
+    let mut skeleton = Skeleton::from_bvh(&bvh_meta, &bvh_data);
+    let mut animation = bvh_to_animation(&bvh_data, bvh_meta.num_frames);
+    animation.events = events;
+    // Events are written next to the tensor when they moved, or when the tensor can't share
+    // the sidecar of the clip.
+    let mut events_changed = separate_events;
+    let mut changed = false;
+    if let Some(joint_map) = &options.joint_map {
+        (skeleton, animation) = joint_map
+            .apply(&skeleton, &animation)
+            .context("Could not map the joints")?;
+        changed = true;
+    }
+    if let Some(joint_set) = options.joint_set {
+        (skeleton, animation) = joint_set
+            .apply(&skeleton, &animation)
+            .with_context(|| format!("Could not convert the joints to {}", joint_set))?;
+        changed = true;
+    }
+    match options.fps {
+        Some(fps) if frame_rate(frame_time) != fps => {
+            animation = resample_frame_time(&animation, frame_time, 1.0 / fps);
+            frame_time = 1.0 / fps;
+            events_changed = true;
+            changed = true;
+        }
+        _ => {}
+    }
+    if let Some(smoothing) = options.smooth {
+        smooth(
+            &mut animation,
+            &skeleton,
+            frame_time,
+            smoothing,
+            &options.smooth_joints,
+        )?;
+        changed = true;
+    }
+    let mut variants = vec![(
+        output_path.to_path_buf(),
+        animation,
+        changed,
+        events_changed,
+    )];
+    if options.mirror || options.mirror_map.is_some() {
+        let pairs = match &options.mirror_map {
+            Some(map) => map.pairs(&skeleton)?,
+            None => mirror_pairs(&skeleton),
+        };
+        if pairs.is_empty() {
+            bail!("The skeleton has no left/right joint pairs to mirror");
+        }
+        let mirrored = variants[0]
+            .1
+            .mirrored(&pairs, lateral_axis(&skeleton, &pairs).0);
+        variants.push((mirrored_path(output_path, options), mirrored, true, true));
+    }
+    for (output_path, mut animation, changed, events_changed) in variants {
+        if events_changed && !animation.events.is_empty() {
+            write_events(&events_path(&output_path), &animation.events)?;
+        }
+        let alignment = options
+            .canonicalize
+            .then(|| RootAlignment::fit(&skeleton, &animation));
+        if let Some(alignment) = &alignment {
+            alignment.remove(&mut animation);
+        }
+        record_alignment(&output_path, alignment)?;
+        let changed = changed || alignment.is_some();
+        let trajectory = if options.in_place {
+            let trajectory;
+            (animation, trajectory) = extract_root_motion(&animation);
+            Some(trajectory)
+        } else {
+            None
+        };
+        if let Some(windows) = &options.windows {
+            let frame_count = animation.frame_count();
+            write_array(
+                &windows_path(&output_path),
+                &windows.starts_array(frame_count),
+            )?;
+            write_array(
+                &hemispheres_path(&output_path),
+                &hemisphere_signs(&animation, windows),
+            )?;
+            if let Some(encoding) = &options.positional_encoding {
+                write_array(
+                    &positions_path(&output_path),
+                    &encoding.encode_windows(windows, frame_count),
+                )?;
+            }
+        }
+        storage::write_with(&skeleton_path(&output_path), |writer| {
+            write_skeleton_json(writer, &skeleton, frame_time)
+        })?;
+        if options.npz {
+            // Zip archives need a seekable writer, which remote objects aren't.
+            let mut archive = Cursor::new(Vec::new());
+            write_npz(&mut archive, &skeleton, &animation, frame_time)?;
+            storage::write_with(&output_path, |writer| {
+                Ok(writer.write_all(archive.get_ref())?)
+            })?;
+            continue;
+        }
+        let mut gav_tensor = if let Some(trajectory) = &trajectory {
+            append_curve(&animation_to_gav(&animation)?, trajectory)?
+        } else if changed {
+            animation_to_gav(&animation)?
+        } else {
+            bvh_to_gav(&bvh_meta, &bvh_data)?
+        };
+        if let Some(method) = options.derivatives {
+            gav_tensor = append_motion_channels(&gav_tensor, &animation, frame_time, method)?;
+        }
+        if let Some(stats) = normalization {
+            stats.normalize(&mut gav_tensor)?;
+        }
+        write_tensor_file(&output_path, &gav_tensor, options.dtype)?;
+        // A tensor converted again is raw unless it was normalized here, whatever the one it
+        // replaced was.
+        NormalizationStats::record(&output_path, normalization.is_some())?;
+    }
+    Ok(())
+}
+
+/// Computes the normalization statistics of the tensors in `dataset_folder` and saves them
+/// next to the tensors, normalizing the tensors with them when `apply` is set. Tensors that
+/// were already normalized are first restored, so the statistics always describe the raw data,
+/// and the dataset is normalized again with the new statistics.
+fn normalize_dataset(
+    dataset_folder: &Path,
+    mode: NormalizationMode,
+    apply: bool,
+) -> Result<NormalizationStats> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dataset_folder)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    paths.sort();
+    // Converted clips are the tensors with a skeleton sidecar.
+    paths
+        .retain(|path| path.extension() == Some(OsStr::new("npy")) && skeleton_path(path).exists());
+    let previous_path = dataset_folder.join(NORMALIZATION_FILE);
+    let was_applied =
+        previous_path.exists() && NormalizationStats::load(&previous_path)?.is_applied();
+
+    let mut accumulator = NormalizationAccumulator::default();
+    for path in &paths {
+        accumulator
+            .add(&read_raw_tensor(path)?.0)
+            .with_context(|| format!("Could not add {}", path.display()))?;
+    }
+    let mut stats = accumulator.finish(mode)?;
+    if apply || was_applied {
+        // Tensors are written back in the dtype they were converted to.
+        for path in &paths {
+            let (mut data, dtype) = read_raw_tensor(path)?;
+            stats.normalize(&mut data)?;
+            write_tensor_file(path, &data, dtype)?;
+            stats.normalized.insert(
+                path.file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned(),
+            );
+        }
+    }
+    stats.save(&dataset_folder.join(NORMALIZATION_FILE))?;
+    Ok(stats)
+}
+
+/// Converted clips in `dataset_folder`, the tensors with a skeleton sidecar, sorted.
+fn converted_clips(dataset_folder: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dataset_folder)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    paths.sort();
+    paths
+        .retain(|path| path.extension() == Some(OsStr::new("npy")) && skeleton_path(path).exists());
+    Ok(paths)
+}
+
+/// The denormalized tensor of a converted clip with its skeleton and frame time.
+fn read_converted_clip(path: &Path) -> Result<(Array3<f32>, Skeleton, f32)> {
+    let (data, _) = read_raw_tensor(path)?;
+    let (skeleton, frame_time) = read_skeleton_sidecar(File::open(skeleton_path(path))?)?;
+    Ok((data, skeleton, frame_time))
+}
+
+/// Packs the converted clips in `dataset_folder` into the HDF5 file `output`. Returns the
+/// number of clips.
+fn pack_hdf5(dataset_folder: &Path, output: &Path) -> Result<usize> {
+    let paths = converted_clips(dataset_folder)?;
+    let mut writer = Hdf5Writer::create(output)?;
+    for path in &paths {
+        let (data, skeleton, frame_time) = read_converted_clip(path)?;
+        let id = path.file_stem().unwrap_or_default().to_string_lossy();
+        writer
+            .add_clip(&id, &data, &skeleton, frame_time)
+            .with_context(|| format!("Could not add {}", path.display()))?;
+    }
+    Ok(paths.len())
+}
+
+/// Writes the frames of the converted clips in `dataset_folder` as rows of the Parquet file
+/// `output`. Returns the number of clips.
+fn export_parquet(dataset_folder: &Path, output: &Path) -> Result<usize> {
+    let paths = converted_clips(dataset_folder)?;
+    if paths.is_empty() {
+        bail!("No converted clips in {}", dataset_folder.display());
+    }
+    let mut writer = ParquetWriter::create(output)?;
+    for path in &paths {
+        let (data, skeleton, frame_time) = read_converted_clip(path)?;
+        let id = path.file_stem().unwrap_or_default().to_string_lossy();
+        writer
+            .add_clip(&id, &data, &skeleton, frame_time)
+            .with_context(|| format!("Could not add {}", path.display()))?;
+    }
+    writer.finish()?;
+    Ok(paths.len())
+}
+
+/// Writes a clip, or every clip of a dataset, as reference motion of `format` into
+/// `output_folder`, named after the clip. Returns the number of clips.
+fn export_reference(
+    path: &Path,
+    output_folder: &Path,
+    format: ReferenceFormat,
+    looping: bool,
+) -> Result<usize> {
+    let clips = if path.is_dir() {
+        dataset_clips(path, false)?
+    } else {
+        vec![path.to_path_buf()]
+    };
+    for clip in &clips {
+        let (animation, skeleton, frame_time) = load_clip(clip)?;
+        let output = output_folder
+            .join(clip.file_stem().unwrap_or_default())
+            .with_extension(format.extension());
+        match format {
+            ReferenceFormat::DeepMimic => {
+                DeepMimicMotion::new(&skeleton, &animation, frame_time, looping)
+                    .and_then(|motion| motion.save(&output))
+            }
+            ReferenceFormat::Lafan => {
+                // Zip archives need a seekable writer, which remote objects aren't.
+                let mut archive = Cursor::new(Vec::new());
+                write_lafan_npz(&mut archive, &skeleton, &animation, frame_time).and_then(|_| {
+                    storage::write_with(&output, |writer| Ok(writer.write_all(archive.get_ref())?))
+                })
             }
         }
+        .with_context(|| format!("Could not export {}", clip.display()))?;
     }
+    Ok(clips.len())
+}
 
+/// Writes a clip, or every clip of a dataset, as a temporal pyramid of `levels` levels into
+/// `output_folder`, first resampled to `fps` when given. Returns the number of clips.
+fn export_pyramid(
+    path: &Path,
+    output_folder: &Path,
+    levels: usize,
+    fps: Option<f32>,
+) -> Result<usize> {
+    let clips = if path.is_dir() {
+        dataset_clips(path, false)?
+    } else {
+        vec![path.to_path_buf()]
+    };
+    for clip in &clips {
+        let (mut animation, skeleton, mut frame_time) = load_clip(clip)?;
+        if let Some(fps) = fps {
+            animation = resample_frame_time(&animation, frame_time, 1.0 / fps);
+            frame_time = 1.0 / fps;
+        }
+        let output = output_folder
+            .join(clip.file_stem().unwrap_or_default())
+            .with_extension(PYRAMID_EXTENSION);
+        // Zip archives need a seekable writer, which remote objects aren't.
+        let mut archive = Cursor::new(Vec::new());
+        write_pyramid(&mut archive, &skeleton, &animation, frame_time, levels)
+            .and_then(|_| {
+                storage::write_with(&output, |writer| Ok(writer.write_all(archive.get_ref())?))
+            })
+            .and_then(|_| {
+                storage::write_with(&skeleton_path(&output), |writer| {
+                    write_skeleton_json(writer, &skeleton, frame_time)
+                })
+            })
+            .with_context(|| format!("Could not export {}", clip.display()))?;
+    }
+    Ok(clips.len())
+}
+
+/// Writes the HumanML3D features of a clip, or of every clip of a dataset, into
+/// `output_folder` as `.npy` arrays named after the clips, like its `new_joint_vecs`. Returns
+/// the number of clips.
+fn export_humanml3d(path: &Path, output_folder: &Path) -> Result<usize> {
+    let clips = if path.is_dir() {
+        dataset_clips(path, false)?
+    } else {
+        vec![path.to_path_buf()]
+    };
+    for clip in &clips {
+        let (animation, skeleton, frame_time) = load_clip(clip)?;
+        let output = output_folder
+            .join(clip.file_stem().unwrap_or_default())
+            .with_extension("npy");
+        humanml3d_features(&skeleton, &animation, frame_time)
+            .and_then(|features| write_array(&output, &features))
+            .with_context(|| format!("Could not export {}", clip.display()))?;
+    }
+    Ok(clips.len())
+}
+
+/// Compresses `clip` to keyframes in `output`, with its skeleton sidecar. Returns the number
+/// of keys and of frames of every curve.
+fn compress_clip(
+    clip: &Path,
+    output: &Path,
+    tolerance: KeyframeTolerance,
+) -> Result<(usize, usize)> {
+    let (animation, skeleton, frame_time) = load_clip(clip)?;
+    let keyframes = KeyframeAnimation::reduce(&animation, tolerance);
+    // Zip archives need a seekable writer, which remote objects aren't.
+    let mut archive = Cursor::new(Vec::new());
+    write_keyframes_npz(&mut archive, &keyframes, &skeleton, frame_time)?;
+    storage::write_with(output, |writer| Ok(writer.write_all(archive.get_ref())?))?;
+    storage::write_with(&skeleton_path(output), |writer| {
+        write_skeleton_json(writer, &skeleton, frame_time)
+    })?;
+    let values = animation.frame_count() * (animation.joint_count() + 1);
+    Ok((keyframes.key_count(), values))
+}
+
+/// Resamples the keyframes `input` to the GAV tensor `output`, with its skeleton sidecar.
+fn decompress_clip(input: &Path, output: &Path) -> Result<()> {
+    let (skeleton, _) = read_skeleton_sidecar(storage::open(&skeleton_path(input))?)?;
+    let (keyframes, frame_time) =
+        read_keyframes_npz(Cursor::new(storage::read(input)?), &skeleton)?;
+    let gav_tensor = animation_to_gav(&keyframes.resample())?;
+    storage::write_with(output, |writer| {
+        write_tensor(writer, &gav_tensor, Dtype::default())
+    })?;
+    storage::write_with(&skeleton_path(output), |writer| {
+        write_skeleton_json(writer, &skeleton, frame_time)
+    })
+}
+
+/// Extracts a single frame of `clip`. The output format is picked from the extension of
+/// `output`: `.bvh` and `.json` are written directly, `.png` is rendered by the preview app.
+fn extract_pose(clip: &str, frame: usize, output: &Path) -> Result<()> {
+    let extension = output
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    if extension == "png" {
+        return render_pose(clip, frame, output);
+    }
+
+    let (bvh_meta, bvh_data) = load_bvh(Path::new(clip))?;
+    let skeleton = Skeleton::from_bvh(&bvh_meta, &bvh_data);
+    let animation = bvh_to_animation(&bvh_data, bvh_meta.num_frames);
+    let pose = animation.pose(frame).with_context(|| {
+        format!(
+            "Frame {} is out of range, {} has {} frames",
+            frame,
+            clip,
+            animation.frame_count()
+        )
+    })?;
+
+    let mut writer = BufWriter::new(File::create(output)?);
+    match extension.as_str() {
+        "bvh" => write_bvh(
+            &mut writer,
+            &skeleton,
+            &pose.into(),
+            bvh_meta.frame_time as f32,
+        )?,
+        "json" => write_pose_json(
+            &mut writer,
+            &skeleton,
+            &pose,
+            frame,
+            bvh_meta.frame_time as f32,
+        )?,
+        _ => bail!("Unsupported pose format: {}", output.display()),
+    }
+    Ok(())
+}
+
+/// Runs the preview app in capture mode, which saves a screenshot of `frame` and exits.
+fn render_pose(clip: &str, frame: usize, output: &Path) -> Result<()> {
+    run_preview([
+        OsStr::new(clip),
+        OsStr::new("--frame"),
+        OsStr::new(&frame.to_string()),
+        OsStr::new("--screenshot"),
+        output.as_os_str(),
+    ])
+}
+
+fn run_preview<I: IntoIterator<Item = S>, S: AsRef<OsStr>>(args: I) -> Result<()> {
+    let preview = env::current_exe()?.with_file_name("preview");
+    let preview = if preview.exists() {
+        preview
+    } else {
+        "preview".into()
+    };
+    let status = std::process::Command::new(&preview)
+        .args(args)
+        .status()
+        .with_context(|| format!("Could not run {}", preview.display()))?;
+    if !status.success() {
+        bail!("Preview exited with {}", status);
+    }
+    Ok(())
+}
+
+const THUMBNAIL_FRAMES: usize = 30;
+const THUMBNAIL_FPS: u32 = 10;
+const THUMBNAIL_WIDTH: u32 = 320;
+
+/// Renders a looping GIF for every BVH clip in `source_folder` into `output_folder`.
+fn render_thumbnails(source_folder: &str, output_folder: &Path) -> Result<usize> {
+    std::fs::create_dir_all(output_folder)?;
+    let frames_folder = env::temp_dir().join(format!("animgen_thumbs_{}", std::process::id()));
+
+    let mut count = 0;
+    for file in std::fs::read_dir(source_folder)? {
+        let path = file?.path();
+        if !path.extension().map(|s| s == "bvh").unwrap_or(false) {
+            continue;
+        }
+        let Some(name) = path.file_stem() else {
+            continue;
+        };
+
+        std::fs::create_dir_all(&frames_folder)?;
+        run_preview([
+            path.as_os_str(),
+            OsStr::new("--sequence"),
+            frames_folder.as_os_str(),
+            OsStr::new("--sequence-frames"),
+            OsStr::new(&THUMBNAIL_FRAMES.to_string()),
+        ])?;
+        let frames: Vec<PathBuf> = (0..THUMBNAIL_FRAMES)
+            .map(|i| frames_folder.join(format!("frame_{:04}.png", i)))
+            .collect();
+        let output = output_folder.join(name).with_extension("gif");
+        encode_gif(&frames, &output, THUMBNAIL_FPS, THUMBNAIL_WIDTH)?;
+        std::fs::remove_dir_all(&frames_folder)?;
+        println!("{}", output.display());
+        count += 1;
+    }
     Ok(count)
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        eprintln!("Usage: {} <source_folder>", args[0]);
-        std::process::exit(1);
+/// Writes `clips`, which must share a skeleton and frame time, as a Blender pose library into
+/// `output_folder`, returning the path of its importer script.
+fn export_blender(output_folder: &Path, clips: &[PathBuf], name: &str) -> Result<PathBuf> {
+    let mut library: Option<(BlenderBundle, Skeleton, f32)> = None;
+    for path in clips {
+        let (animation, skeleton, frame_time) = load_clip(path)?;
+        let (bundle, first_skeleton, first_frame_time) = library.get_or_insert_with(|| {
+            (
+                BlenderBundle::new(name, &skeleton, frame_time),
+                skeleton.clone(),
+                frame_time,
+            )
+        });
+        if !skeleton.joint_order().eq(first_skeleton.joint_order()) {
+            bail!(
+                "{} has another skeleton than {}",
+                path.display(),
+                clips[0].display()
+            );
+        }
+        if (frame_time - *first_frame_time).abs() > 1e-6 {
+            bail!(
+                "{} has a frame time of {} but {} {}",
+                path.display(),
+                frame_time,
+                clips[0].display(),
+                first_frame_time
+            );
+        }
+        let clip_name = path.file_stem().unwrap_or_default().to_string_lossy();
+        bundle
+            .add_clip(&clip_name, &animation)
+            .with_context(|| format!("Could not export {}", path.display()))?;
+    }
+    let (bundle, _, _) = library.context("No clips to export")?;
+    bundle.save(output_folder)
+}
+
+/// Writes a static HTML gallery of the BVH clips and GAV tensors in `dataset_folder`.
+/// Thumbnails rendered by `thumbs` are copied over when `thumbnail_folder` is given.
+fn export_gallery(
+    dataset_folder: &Path,
+    output_folder: &Path,
+    thumbnail_folder: Option<&Path>,
+) -> Result<usize> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dataset_folder)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    paths.sort();
+
+    let mut clips = Vec::new();
+    for path in &paths {
+        let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        let (frame_count, duration) = match path.extension().and_then(|e| e.to_str()) {
+            Some("bvh") => {
+                let (bvh_meta, _) = load_bvh(path)?;
+                let duration = bvh_meta.num_frames as f32 * bvh_meta.frame_time as f32;
+                (bvh_meta.num_frames, Some(duration))
+            }
+            // Tensors converted from a BVH clip in the same folder are already listed.
+            Some("npy") if !path.with_extension("bvh").exists() => {
+                let (gav_data, _) = read_raw_tensor(path)?;
+                (gav_data.dim().1, None)
+            }
+            _ => continue,
+        };
+
+        let metadata = read_metadata(path)?;
+        let events = read_events(&events_path(path))?;
+        let mut thumbnail = None;
+        if let Some(folder) = thumbnail_folder {
+            let source = folder.join(name).with_extension("gif");
+            if source.exists() {
+                let relative = format!("thumbs/{}.gif", name);
+                std::fs::create_dir_all(output_folder.join("thumbs"))?;
+                std::fs::copy(&source, output_folder.join(&relative))?;
+                thumbnail = Some(relative);
+            }
+        }
+
+        clips.push(GalleryClip {
+            name: name.to_string(),
+            frame_count,
+            duration,
+            thumbnail,
+            metadata: metadata.fields(),
+            labels: metadata.labels,
+            events,
+        });
+    }
+
+    let title = dataset_folder
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "Dataset".to_string());
+    write_gallery(&title, &clips, output_folder)?;
+    Ok(clips.len())
+}
+
+const SEARCH_PROBES: usize = 8;
+
+/// Loads the joint names and rotations of a single frame query pose, from a pose JSON file or
+/// the first frame of a BVH clip.
+fn load_query_pose(path: &Path) -> Result<(Vec<String>, Pose)> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => read_pose_json(File::open(path)?),
+        Some("bvh") => {
+            let (bvh_meta, bvh_data) = load_bvh(path)?;
+            let skeleton = Skeleton::from_bvh(&bvh_meta, &bvh_data);
+            let animation = bvh_to_animation(&bvh_data, bvh_meta.num_frames);
+            let pose = animation
+                .pose(0)
+                .with_context(|| format!("{} has no frames", path.display()))?;
+            let names = skeleton.joint_order().map(String::from).collect();
+            Ok((names, pose))
+        }
+        _ => bail!("Unsupported query pose format: {}", path.display()),
+    }
+}
+
+/// Prints the `k` frames of the BVH clips in `dataset_folder` closest to the query pose.
+fn search_pose(dataset_folder: &Path, query: &Path, k: usize) -> Result<()> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dataset_folder)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    paths.sort();
+
+    let mut joint_names: Option<Vec<String>> = None;
+    let mut clips = Vec::new();
+    for path in paths
+        .iter()
+        .filter(|p| p.extension().is_some_and(|e| e == "bvh"))
+    {
+        let (bvh_meta, bvh_data) = load_bvh(path)?;
+        let skeleton = Skeleton::from_bvh(&bvh_meta, &bvh_data);
+        let mut animation = bvh_to_animation(&bvh_data, bvh_meta.num_frames);
+        // The first clip defines the joint order, the others are matched to it by name.
+        let names =
+            joint_names.get_or_insert_with(|| skeleton.joint_order().map(String::from).collect());
+        let Some(order) = names
+            .iter()
+            .map(|name| skeleton.find(name))
+            .collect::<Option<Vec<usize>>>()
+        else {
+            eprintln!("Skipping {}, its skeleton does not match", path.display());
+            continue;
+        };
+        animation.joint_rotations = order
+            .into_iter()
+            .map(|joint| animation.joint_rotations[joint].clone())
+            .collect();
+        clips.push((path.to_string_lossy().into_owned(), animation));
+    }
+    let Some(joint_names) = joint_names else {
+        bail!("No BVH clips found in {}", dataset_folder.display());
+    };
+
+    let index = PoseIndex::build(joint_names, clips, 1)?;
+    let (names, pose) = load_query_pose(query)?;
+    let query = index.reorder(&names, &pose.joint_rotations)?;
+    for found in index.search(&query, k, SEARCH_PROBES) {
+        println!(
+            "{}\t{}\t{:.2}",
+            found.clip,
+            found.frame,
+            found.distance.to_degrees()
+        );
+    }
+    Ok(())
+}
+
+/// Loads the ground plane root path of a BVH clip or GAV tensor. A `.json` file holds a sketched
+/// path as an array of `[x, z]` points.
+fn load_root_path(path: &Path) -> Result<Vec<Vec2>> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => {
+            let points: Vec<[f32; 2]> = serde_json::from_reader(File::open(path)?)
+                .with_context(|| format!("{} is not a list of points", path.display()))?;
+            Ok(points.into_iter().map(Vec2::from_array).collect())
+        }
+        Some("bvh") => {
+            let (bvh_meta, bvh_data) = load_bvh(path)?;
+            Ok(root_path(&bvh_to_animation(&bvh_data, bvh_meta.num_frames)))
+        }
+        Some("npy") => Ok(root_path(&gav_to_animation(read_raw_tensor(path)?.0)?)),
+        _ => bail!("Unsupported trajectory format: {}", path.display()),
+    }
+}
+
+/// Prints the `k` clips in `dataset_folder` whose root paths best match the query trajectory.
+fn search_trajectory(dataset_folder: &Path, query: &Path, k: usize) -> Result<()> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dataset_folder)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    paths.sort();
+
+    let mut clips = Vec::new();
+    for path in &paths {
+        let is_clip = match path.extension().and_then(|e| e.to_str()) {
+            Some("bvh") => true,
+            // Tensors converted from a BVH clip in the same folder are already listed.
+            Some("npy") => !path.with_extension("bvh").exists(),
+            _ => false,
+        };
+        if is_clip {
+            clips.push((path.to_string_lossy().into_owned(), load_root_path(path)?));
+        }
+    }
+
+    let query = load_root_path(query)?;
+    for found in search_trajectories(&query, &clips, k) {
+        println!("{}\t{:.2}", found.clip, found.distance);
+    }
+    Ok(())
+}
+
+/// Embeds every clip in `dataset_folder` and writes the index next to them.
+fn build_embedding_index(dataset_folder: &Path) -> Result<usize> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dataset_folder)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    paths.sort();
+
+    let mut index = EmbeddingIndex::new(&KinematicEmbedding::default());
+    for path in &paths {
+        let Some(name) = path.file_name().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        let vector = match path.extension().and_then(|e| e.to_str()) {
+            Some("bvh") => {
+                let (bvh_meta, bvh_data) = load_bvh(path)?;
+                let embedding = KinematicEmbedding {
+                    fps: 1.0 / bvh_meta.frame_time as f32,
+                };
+                embedding.embed(&bvh_to_animation(&bvh_data, bvh_meta.num_frames))
+            }
+            // Tensors converted from a BVH clip in the same folder are already listed.
+            Some("npy") if !path.with_extension("bvh").exists() => {
+                KinematicEmbedding::default().embed(&gav_to_animation(read_raw_tensor(path)?.0)?)
+            }
+            _ => continue,
+        };
+        index.insert(name.to_string(), vector)?;
+    }
+    index.save(&dataset_folder.join(EMBEDDING_INDEX_FILE))?;
+    Ok(index.clips.len())
+}
+
+/// Prints the `k` clips most similar to `clip`, using the index written by `embed`.
+fn find_similar_clips(dataset_folder: &Path, clip: &str, k: usize) -> Result<()> {
+    let index = EmbeddingIndex::load(&dataset_folder.join(EMBEDDING_INDEX_FILE))?;
+    let clip = Path::new(clip)
+        .file_name()
+        .map_or(clip.into(), |name| name.to_string_lossy());
+    let matches = index
+        .similar(&clip, k)
+        .with_context(|| format!("{} is not in the embedding index", clip))?;
+    for (name, distance) in matches {
+        println!("{}\t{:.3}", name, distance);
+    }
+    Ok(())
+}
+
+/// Warps `clip` so the beats in `beats_file` land on a `bpm` grid. A `.npy` output also gets
+/// the beat phase appended as an extra curve.
+fn align_to_beats(clip: &Path, beats_file: &Path, bpm: f32, output: &Path) -> Result<()> {
+    let (bvh_meta, bvh_data) = load_bvh(clip)?;
+    let skeleton = Skeleton::from_bvh(&bvh_meta, &bvh_data);
+    let frame_time = bvh_meta.frame_time as f32;
+    let mut animation = bvh_to_animation(&bvh_data, bvh_meta.num_frames);
+    animation.events = read_events(&events_path(clip))?;
+
+    let beats = read_beats(beats_file)?;
+    let warped = warp_to_beat_grid(&animation, frame_time, &beats, bpm)?;
+    match output.extension().and_then(|e| e.to_str()) {
+        Some("bvh") => {
+            let mut writer = BufWriter::new(File::create(output)?);
+            write_bvh(&mut writer, &skeleton, &warped, frame_time)?;
+        }
+        Some("npy") => {
+            let phase = beat_phase(warped.frame_count(), frame_time, &beat_grid(&beats, bpm));
+            let gav_data = append_beat_phase(&animation_to_gav(&warped)?, &phase)?;
+            write_npy(output, &gav_data)?;
+        }
+        _ => bail!("Unsupported output format: {}", output.display()),
+    }
+    if !warped.events.is_empty() {
+        write_events(&events_path(output), &warped.events)?;
+    }
+    derive_metadata(clip, output, &format!("warped to a {} bpm beat grid", bpm))?;
+    Ok(())
+}
+
+/// Converts `clip` to a GAV tensor with the gaze direction and look-at target of the head
+/// appended as two extra curves.
+fn export_gaze(clip: &Path, output: &Path, head: Option<&str>) -> Result<()> {
+    let (bvh_meta, bvh_data) = load_bvh(clip)?;
+    let skeleton = Skeleton::from_bvh(&bvh_meta, &bvh_data);
+    let head = match head {
+        Some(name) => skeleton.find(name),
+        None => find_head(&skeleton),
+    }
+    .with_context(|| format!("No head joint found in {}", clip.display()))?;
+
+    let animation = bvh_to_animation(&bvh_data, bvh_meta.num_frames);
+    let gaze = compute_gaze(&skeleton, &animation, head, DEFAULT_HEAD_FORWARD);
+    let gav_data = bvh_to_gav(&bvh_meta, &bvh_data)?;
+    let gav_data = append_curve(&gav_data, &gaze.directions)?;
+    let gav_data = append_curve(&gav_data, &gaze.targets(DEFAULT_GAZE_DISTANCE))?;
+    write_npy(output, &gav_data)?;
+    Ok(())
+}
+
+/// Fits the finger axes to `clips`, which share a skeleton, and writes them to `output`.
+/// Returns the number of fingers.
+fn fit_fingers(clips: &[PathBuf], output: &Path) -> Result<usize> {
+    let mut skeleton: Option<Skeleton> = None;
+    let mut animations = Vec::with_capacity(clips.len());
+    for path in clips {
+        let (bvh_meta, bvh_data) = load_bvh(path)?;
+        let clip_skeleton = Skeleton::from_bvh(&bvh_meta, &bvh_data);
+        let first = skeleton.get_or_insert_with(|| clip_skeleton.clone());
+        if !clip_skeleton.joint_order().eq(first.joint_order()) {
+            bail!(
+                "{} has another skeleton than {}",
+                path.display(),
+                clips[0].display()
+            );
+        }
+        animations.push(bvh_to_animation(&bvh_data, bvh_meta.num_frames));
+    }
+    let skeleton = skeleton.context("No clips to fit")?;
+    let encoding = FingerEncoding::fit(&skeleton, &animations);
+    if encoding.chains.is_empty() {
+        bail!("No finger joints found in {}", clips[0].display());
+    }
+    encoding.save(output)?;
+    Ok(encoding.chains.len())
+}
+
+/// Encodes `clip` with its fingers reduced to curl and spread curves, writing the finger chains
+/// to a sidecar next to `output`. The axes are read from `fingers` when given, so every clip
+/// of a dataset shares them, or fitted to the clip alone.
+fn encode_fingers(clip: &Path, output: &Path, fingers: Option<&Path>) -> Result<usize> {
+    let (bvh_meta, bvh_data) = load_bvh(clip)?;
+    let skeleton = Skeleton::from_bvh(&bvh_meta, &bvh_data);
+    let animation = bvh_to_animation(&bvh_data, bvh_meta.num_frames);
+    let encoding = match fingers {
+        Some(path) => {
+            let encoding = FingerEncoding::load(path)?;
+            if !encoding.fits(&skeleton) {
+                bail!(
+                    "{} was fitted to another skeleton than {}",
+                    path.display(),
+                    clip.display()
+                );
+            }
+            encoding
+        }
+        None => FingerEncoding::fit(&skeleton, std::slice::from_ref(&animation)),
+    };
+    if encoding.chains.is_empty() {
+        bail!("No finger joints found in {}", clip.display());
+    }
+    write_npy(output, &encoding.encode_gav(&animation)?)?;
+    encoding.save(&fingers_path(output))?;
+    Ok(encoding.chains.len())
+}
+
+/// Decodes a tensor written by [`encode_fingers`] back to a BVH clip, using the skeleton and
+/// frame time of `reference`.
+fn decode_fingers(input: &Path, reference: &Path, output: &Path) -> Result<()> {
+    let encoding = FingerEncoding::load(&fingers_path(input))?;
+    let (bvh_meta, bvh_data) = load_bvh(reference)?;
+    let skeleton = Skeleton::from_bvh(&bvh_meta, &bvh_data);
+    if skeleton.joint_count() != encoding.joint_count {
+        bail!(
+            "{} has {} joints but {} was encoded from {}",
+            reference.display(),
+            skeleton.joint_count(),
+            input.display(),
+            encoding.joint_count
+        );
+    }
+    let animation = encoding.decode_gav(read_raw_tensor(input)?.0)?;
+    let mut writer = BufWriter::new(File::create(output)?);
+    write_bvh(
+        &mut writer,
+        &skeleton,
+        &animation,
+        bvh_meta.frame_time as f32,
+    )?;
+    Ok(())
+}
+
+/// Encodes a BVH clip as dual quaternion curves, with a skeleton sidecar next to the tensor.
+fn encode_dual_quaternions(clip: &Path, output: &Path) -> Result<()> {
+    let (bvh_meta, bvh_data) = load_bvh(clip)?;
+    let skeleton = Skeleton::from_bvh(&bvh_meta, &bvh_data);
+    let animation = bvh_to_animation(&bvh_data, bvh_meta.num_frames);
+    let sidecar = BufWriter::new(File::create(skeleton_path(output))?);
+    write_skeleton_json(sidecar, &skeleton, bvh_meta.frame_time as f32)?;
+    write_npy(
+        output,
+        &animation_to_dual_quaternions(&animation, &skeleton)?,
+    )?;
+    Ok(())
+}
+
+/// Decodes a tensor written by [`encode_dual_quaternions`] back to a BVH clip.
+fn decode_dual_quaternions(input: &Path, output: &Path) -> Result<()> {
+    let sidecar = skeleton_path(input);
+    let (skeleton, frame_time) = read_skeleton_sidecar(
+        File::open(&sidecar).with_context(|| format!("Could not open {}", sidecar.display()))?,
+    )?;
+    let animation = dual_quaternions_to_animation(&read_raw_tensor(input)?.0)?;
+    let mut writer = BufWriter::new(File::create(output)?);
+    write_bvh(&mut writer, &skeleton, &animation, frame_time)?;
+    Ok(())
+}
+
+/// Encodes `clip` in place with its root trajectory as an extra curve, see
+/// [`extract_root_motion`].
+fn encode_root_motion(clip: &Path, output: &Path) -> Result<()> {
+    let (bvh_meta, bvh_data) = load_bvh(clip)?;
+    let skeleton = Skeleton::from_bvh(&bvh_meta, &bvh_data);
+    let (in_place, trajectory) =
+        extract_root_motion(&bvh_to_animation(&bvh_data, bvh_meta.num_frames));
+    let sidecar = BufWriter::new(File::create(skeleton_path(output))?);
+    write_skeleton_json(sidecar, &skeleton, bvh_meta.frame_time as f32)?;
+    write_npy(
+        output,
+        &append_curve(&animation_to_gav(&in_place)?, &trajectory)?,
+    )?;
+    Ok(())
+}
+
+/// Decodes an in-place tensor, as written by [`encode_root_motion`] or `--in-place`, back to a
+/// BVH clip moving along its trajectory.
+fn decode_root_motion(input: &Path, output: &Path) -> Result<()> {
+    let sidecar = skeleton_path(input);
+    let (skeleton, frame_time) = read_skeleton_sidecar(
+        File::open(&sidecar).with_context(|| format!("Could not open {}", sidecar.display()))?,
+    )?;
+    let (gav_tensor, _) = read_raw_tensor(input)?;
+    let trajectory = trajectory_curve(&gav_tensor, skeleton.joint_count())?;
+    let joints = gav_tensor
+        .slice(s![..=skeleton.joint_count(), .., ..])
+        .to_owned();
+    let animation = apply_root_motion(&gav_to_animation(joints)?, &trajectory)?;
+    let mut writer = BufWriter::new(File::create(output)?);
+    write_bvh(&mut writer, &skeleton, &animation, frame_time)?;
+    Ok(())
+}
+
+/// Encodes a BVH clip as per-frame deltas, see [`animation_to_delta_gav`], with a skeleton
+/// sidecar next to the tensor.
+fn encode_delta(clip: &Path, output: &Path) -> Result<()> {
+    if !is_delta_path(output) {
+        bail!(
+            "{} has to end in .{}, so it isn't read as poses",
+            output.display(),
+            DELTA_EXTENSION
+        );
+    }
+    let (bvh_meta, bvh_data) = load_bvh(clip)?;
+    let skeleton = Skeleton::from_bvh(&bvh_meta, &bvh_data);
+    let animation = bvh_to_animation(&bvh_data, bvh_meta.num_frames);
+    let sidecar = BufWriter::new(File::create(skeleton_path(output))?);
+    write_skeleton_json(sidecar, &skeleton, bvh_meta.frame_time as f32)?;
+    write_npy(output, &animation_to_delta_gav(&animation)?)?;
+    Ok(())
+}
+
+/// Decodes a tensor written by [`encode_delta`] back to a BVH clip.
+fn decode_delta(input: &Path, output: &Path) -> Result<()> {
+    let sidecar = skeleton_path(input);
+    let (skeleton, frame_time) = read_skeleton_sidecar(
+        File::open(&sidecar).with_context(|| format!("Could not open {}", sidecar.display()))?,
+    )?;
+    let animation = delta_gav_to_animation(read_raw_tensor(input)?.0)?;
+    let mut writer = BufWriter::new(File::create(output)?);
+    write_bvh(&mut writer, &skeleton, &animation, frame_time)?;
+    Ok(())
+}
+
+/// Labels the foot contacts of a BVH clip or GAV tensor and writes them next to it. Without
+/// `joints`, the toes and heels found by name are labelled.
+fn label_contacts(
+    input: &Path,
+    max_height: f32,
+    max_speed: f32,
+    joints: &[&str],
+) -> Result<PathBuf> {
+    let (animation, skeleton, frame_time) = match input.extension().and_then(|e| e.to_str()) {
+        Some("bvh") => {
+            let (bvh_meta, bvh_data) = load_bvh(input)?;
+            let skeleton = Skeleton::from_bvh(&bvh_meta, &bvh_data);
+            let animation = bvh_to_animation(&bvh_data, bvh_meta.num_frames);
+            (animation, skeleton, bvh_meta.frame_time as f32)
+        }
+        _ => load_gav(input)?,
+    };
+    let mut config = ContactConfig::new(&skeleton, joints)?;
+    config.max_height = max_height;
+    config.max_speed = max_speed;
+    let contacts = detect_contacts(&skeleton, &animation, frame_time, &config)?;
+    let output = contacts_path(input);
+    write_npy(&output, &contacts)?;
+    Ok(output)
+}
+
+/// Writes the tracking constraints of a clip next to it, see [`ClipConstraints`]. The contact
+/// options are those of [`label_contacts`].
+fn export_constraints(
+    input: &Path,
+    max_height: f32,
+    max_speed: f32,
+    joints: &[&str],
+) -> Result<PathBuf> {
+    let (animation, skeleton, frame_time) = load_clip(input)?;
+    let mut config = ContactConfig::new(&skeleton, joints)?;
+    config.max_height = max_height;
+    config.max_speed = max_speed;
+    let constraints = ClipConstraints::new(&skeleton, &animation, frame_time, &config)?;
+    let output = constraints_path(input);
+    constraints.save(&output)?;
+    Ok(output)
+}
+
+/// Writes a GAV tensor as a BVH clip. The skeleton and frame time come from a reference BVH
+/// clip, an exported pose or, without a reference, the skeleton sidecar of the tensor. A clip
+/// that was canonicalized is put back where it was captured. With `project`, rotations that
+/// aren't valid are projected first, see [`project_rotations`], and with `deflicker` curves
+/// oscillating faster than that many Hz are smoothed, see [`deflicker`].
+fn export_bvh(
+    input: &Path,
+    reference: Option<&Path>,
+    output: &Path,
+    project: bool,
+    deflicker_hz: Option<f32>,
+) -> Result<()> {
+    let alignment = RootAlignment::from_metadata(&read_metadata(input)?)?;
+    let (mut animation, skeleton, frame_time) = match reference {
+        None if !project => load_gav(input)?,
+        _ => {
+            let (mut data, stored) = if input.extension() == Some(OsStr::new("gav")) {
+                let gav = read_gav(&mut BufReader::new(File::open(input)?))?;
+                (gav.data, Some((gav.skeleton, gav.frame_time)))
+            } else {
+                (read_raw_tensor(input)?.0, None)
+            };
+            let (skeleton, frame_time) = match (reference, stored) {
+                (Some(reference), _) => match reference.extension().and_then(|e| e.to_str()) {
+                    Some("bvh") => {
+                        let (bvh_meta, bvh_data) = load_bvh(reference)?;
+                        let skeleton = Skeleton::from_bvh(&bvh_meta, &bvh_data);
+                        (skeleton, bvh_meta.frame_time as f32)
+                    }
+                    Some("json") => read_skeleton_json(File::open(reference)?)?,
+                    _ => bail!("Unsupported skeleton format: {}", reference.display()),
+                },
+                (None, Some(stored)) => stored,
+                (None, None) => read_skeleton_sidecar(storage::open(&skeleton_path(input))?)?,
+            };
+            if project {
+                println!("Projected rotations: {}", project_rotations(&mut data));
+            }
+            let mut animation = if is_delta_path(input) {
+                delta_gav_to_animation(data)?
+            } else {
+                gav_to_animation(data)?
+            };
+            if animation.joint_count() < skeleton.joint_count() {
+                bail!(
+                    "The skeleton has {} joints but the tensor only {} joint curves",
+                    skeleton.joint_count(),
+                    animation.joint_count()
+                );
+            }
+            animation.joint_rotations.truncate(skeleton.joint_count());
+            (animation, skeleton, frame_time)
+        }
+    };
+    if let Some(cutoff_hz) = deflicker_hz {
+        let settings = DeflickerSettings {
+            fps: 1.0 / frame_time,
+            cutoff_hz,
+            ..Default::default()
+        };
+        let report = deflicker(&mut animation, &settings);
+        println!(
+            "Deflickered {} curves over {} frames, jitter {:.6} to {:.6}",
+            report.curves_filtered,
+            report.flicker_frames,
+            report.jitter_before,
+            report.jitter_after
+        );
+    }
+    if let Some(alignment) = &alignment {
+        alignment.restore(&mut animation);
+    }
+    let mut writer = BufWriter::new(File::create(output)?);
+    write_bvh(&mut writer, &skeleton, &animation, frame_time)?;
+    Ok(())
+}
+
+/// Retargets a BVH clip to the skeleton of `target`, a BVH clip, pose or skeleton sidecar. Joints
+/// are mapped by name unless a mapping file is given.
+fn retarget_clip(clip: &Path, target: &Path, output: &Path, mapping: Option<&Path>) -> Result<()> {
+    let (bvh_meta, bvh_data) = load_bvh(clip)?;
+    let source = Skeleton::from_bvh(&bvh_meta, &bvh_data);
+    let frame_time = bvh_meta.frame_time as f32;
+    let mut animation = bvh_to_animation(&bvh_data, bvh_meta.num_frames);
+    animation.events = read_events(&events_path(clip))?;
+    let target_skeleton = match target.extension().and_then(|e| e.to_str()) {
+        Some("bvh") => {
+            let (target_meta, target_data) = load_bvh(target)?;
+            Skeleton::from_bvh(&target_meta, &target_data)
+        }
+        Some("json") => read_skeleton_json(File::open(target)?)?.0,
+        _ => bail!("Unsupported skeleton format: {}", target.display()),
+    };
+    let retargeting = match mapping {
+        Some(mapping) => Retargeting::load(mapping)?,
+        None => Retargeting::by_name(&source, &target_skeleton),
+    };
+    let retargeted = retargeting.retarget(&source, &animation, &target_skeleton)?;
+
+    match output.extension().and_then(|e| e.to_str()) {
+        Some("bvh") => {
+            let mut writer = BufWriter::new(File::create(output)?);
+            write_bvh(&mut writer, &target_skeleton, &retargeted, frame_time)?;
+        }
+        Some("npy") => {
+            let sidecar = BufWriter::new(File::create(skeleton_path(output))?);
+            write_skeleton_json(sidecar, &target_skeleton, frame_time)?;
+            write_npy(output, &animation_to_gav(&retargeted)?)?;
+        }
+        _ => bail!("Unsupported output format: {}", output.display()),
+    }
+    if !retargeted.events.is_empty() {
+        write_events(&events_path(output), &retargeted.events)?;
+    }
+    derive_metadata(clip, output, &format!("retargeted to {}", target.display()))?;
+    Ok(())
+}
+
+/// Checks the left/right joint pairs of the BVH clips in `dataset_folder`. Returns whether
+/// mirroring them for augmentation gives valid motion.
+fn check_mirror_pairs(dataset_folder: &Path) -> Result<bool> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dataset_folder)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    paths.sort();
+
+    let mut skeleton: Option<Skeleton> = None;
+    let mut animations = Vec::new();
+    for path in paths
+        .iter()
+        .filter(|p| p.extension() == Some(OsStr::new("bvh")))
+    {
+        let (bvh_meta, bvh_data) = load_bvh(path)?;
+        let clip_skeleton = Skeleton::from_bvh(&bvh_meta, &bvh_data);
+        match &skeleton {
+            Some(skeleton) if !skeleton.joint_order().eq(clip_skeleton.joint_order()) => {
+                eprintln!("Skipping {}: different skeleton", path.display());
+                continue;
+            }
+            Some(_) => {}
+            None => skeleton = Some(clip_skeleton),
+        }
+        animations.push(bvh_to_animation(&bvh_data, bvh_meta.num_frames));
+    }
+    let skeleton = skeleton.context("No BVH files found")?;
+
+    let report = check_mirroring(&skeleton, &animations)?;
+    println!(
+        "Lateral axis {}, {:.0}% aligned",
+        ["X", "Y", "Z"][report.lateral_axis],
+        report.axis_alignment * 100.0
+    );
+    for pair in &report.pairs {
+        println!(
+            "{}\t{}/{}\toffset {:.1}\tmean {:.1}\tspread {:.1}",
+            if pair.is_consistent() { "ok" } else { "WRONG" },
+            pair.left,
+            pair.right,
+            pair.offset_error,
+            pair.mean_error,
+            pair.spread_error
+        );
+    }
+    Ok(report.is_consistent())
+}
+
+/// The BVH clips of `dataset_folder` sharing the skeleton of the first one, with their paths.
+/// Clips of another skeleton are skipped.
+fn load_dataset_animations(
+    dataset_folder: &Path,
+) -> Result<(Skeleton, Vec<PathBuf>, Vec<Animation>)> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dataset_folder)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    paths.sort();
+
+    let mut skeleton: Option<Skeleton> = None;
+    let mut animations = Vec::new();
+    let mut clip_paths = Vec::new();
+    for path in paths
+        .iter()
+        .filter(|p| p.extension() == Some(OsStr::new("bvh")))
+    {
+        let (bvh_meta, bvh_data) = load_bvh(path)?;
+        let clip_skeleton = Skeleton::from_bvh(&bvh_meta, &bvh_data);
+        match &skeleton {
+            Some(skeleton) if !skeleton.joint_order().eq(clip_skeleton.joint_order()) => {
+                eprintln!("Skipping {}: different skeleton", path.display());
+                continue;
+            }
+            Some(_) => {}
+            None => skeleton = Some(clip_skeleton),
+        }
+        animations.push(bvh_to_animation(&bvh_data, bvh_meta.num_frames));
+        clip_paths.push(path.clone());
+    }
+    let skeleton = skeleton.context("No BVH files found")?;
+    Ok((skeleton, clip_paths, animations))
+}
+
+/// Measures the rotational range each joint covers over the BVH clips in `dataset_folder`,
+/// prints the joints from the least covered up and writes the report next to the clips.
+fn report_joint_coverage(dataset_folder: &Path) -> Result<()> {
+    let (skeleton, _, animations) = load_dataset_animations(dataset_folder)?;
+
+    let report = CoverageReport::new(&skeleton, &animations)?;
+    println!("{} clips, {} frames", report.clip_count, report.frame_count);
+    for joint in report.ranked() {
+        let [x, y, z] = joint.axis_ranges;
+        println!(
+            "{}	{}	{:.0}	x {:.0}	y {:.0}	z {:.0}	max {:.0}",
+            if report.normalized(joint) < LOW_COVERAGE {
+                "LOW"
+            } else {
+                "ok"
+            },
+            joint.joint,
+            joint.coverage(),
+            x,
+            y,
+            z,
+            joint.max_angle
+        );
+    }
+    report.save(&dataset_folder.join(COVERAGE_REPORT_FILE))
+}
+
+/// Fits the principal components of the joint rotations of the BVH clips in `dataset_folder`,
+/// prints how much variance the first two explain per joint and writes the report next to the
+/// clips.
+fn report_joint_pca(dataset_folder: &Path) -> Result<()> {
+    let (skeleton, _, animations) = load_dataset_animations(dataset_folder)?;
+
+    let report = JointPcaReport::new(&skeleton, &animations)?;
+    println!("{} clips, {} frames", report.clip_count, report.frame_count);
+    for joint in &report.joints {
+        let [a, b, c] = joint.variances.map(|variance| variance.sqrt().to_degrees());
+        println!(
+            "{}\t{:.0}%\tstd {:.1} {:.1} {:.1}",
+            joint.joint,
+            joint.explained() * 100.0,
+            a,
+            b,
+            c
+        );
+    }
+    report.save(&dataset_folder.join(JOINT_PCA_FILE))
+}
+
+/// Fits a pose prior with `components` Gaussians per joint to the BVH clips in
+/// `dataset_folder` and writes it next to the clips.
+fn fit_pose_prior(dataset_folder: &Path, components: usize) -> Result<()> {
+    let (skeleton, _, animations) = load_dataset_animations(dataset_folder)?;
+    let prior = PosePrior::fit(&skeleton, &animations, components)?;
+    println!(
+        "Fitted {} joints to {} clips, {} frames",
+        prior.joints.len(),
+        animations.len(),
+        prior.frame_count
+    );
+    prior.save(&dataset_folder.join(POSE_PRIOR_FILE))
+}
+
+/// Scores the BVH clip at `path`, or every BVH clip in the folder at `path`, against `prior`,
+/// printing the mean and worst frame log likelihood of each. Returns whether no clip has a
+/// frame below `threshold`.
+fn score_pose_prior(prior: &Path, path: &Path, threshold: Option<f32>) -> Result<bool> {
+    let prior = PosePrior::load(prior)?;
+    let (skeleton, paths, animations) = if path.is_dir() {
+        load_dataset_animations(path)?
+    } else {
+        let (bvh_meta, bvh_data) = load_bvh(path)?;
+        let skeleton = Skeleton::from_bvh(&bvh_meta, &bvh_data);
+        let animation = bvh_to_animation(&bvh_data, bvh_meta.num_frames);
+        (skeleton, vec![path.to_path_buf()], vec![animation])
+    };
+
+    let mut plausible = true;
+    for (path, animation) in paths.iter().zip(&animations) {
+        let scores = prior.score(&skeleton, animation)?;
+        let Some((worst_frame, worst)) = scores
+            .iter()
+            .copied()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        else {
+            continue;
+        };
+        let mean = scores.iter().sum::<f32>() / scores.len() as f32;
+        let flagged = threshold.is_some_and(|threshold| worst < threshold);
+        plausible &= !flagged;
+        println!(
+            "{}\t{}\tmean {:.1}\tworst {:.1} at frame {}",
+            if flagged { "LOW" } else { "ok" },
+            path.display(),
+            mean,
+            worst,
+            worst_frame
+        );
+    }
+    Ok(plausible)
+}
+
+/// Reports the frame rate of every BVH clip in `dataset_folder`. With an `output_folder`, all
+/// clips are written there at the most common frame rate, together with the report.
+fn unify_frame_rates(dataset_folder: &Path, output_folder: Option<&Path>) -> Result<()> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dataset_folder)?
+        .map(|entry| entry.map(|e| e.path()))
+        .filter(|path| {
+            path.as_ref()
+                .is_ok_and(|p| p.extension() == Some(OsStr::new("bvh")))
+        })
+        .collect::<Result<_, _>>()?;
+    paths.sort();
+
+    let mut clips = Vec::new();
+    for path in &paths {
+        let (bvh_meta, bvh_data) = load_bvh(path)?;
+        clips.push((path, bvh_meta, bvh_data));
+    }
+    let report = FrameRateReport::new(clips.iter().map(|(path, bvh_meta, _)| {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        (
+            name.into_owned(),
+            bvh_meta.frame_time as f32,
+            bvh_meta.num_frames,
+        )
+    }))?;
+    println!("Modal frame rate: {} fps", report.modal_fps);
+    for clip in report.outliers() {
+        println!(
+            "{}\t{} fps\t{} frames",
+            clip.clip, clip.fps, clip.frame_count
+        );
+    }
+
+    let Some(output_folder) = output_folder else {
+        return Ok(());
+    };
+    std::fs::create_dir_all(output_folder)?;
+    let target_frame_time = 1.0 / report.modal_fps;
+    for ((path, bvh_meta, bvh_data), clip) in clips.iter().zip(&report.clips) {
+        let output = output_folder.join(&clip.clip);
+        let mut animation = bvh_to_animation(bvh_data, bvh_meta.num_frames);
+        animation.events = read_events(&events_path(path))?;
+        if clip.fps == report.modal_fps {
+            std::fs::copy(path, &output)?;
+            write_metadata(&output, &read_metadata(path)?)?;
+        } else {
+            let skeleton = Skeleton::from_bvh(bvh_meta, bvh_data);
+            let frame_time = bvh_meta.frame_time as f32;
+            animation = resample_frame_time(&animation, frame_time, target_frame_time);
+            let mut writer = BufWriter::new(File::create(&output)?);
+            write_bvh(&mut writer, &skeleton, &animation, target_frame_time)?;
+            let correction = format!("resampled from {} to {} fps", clip.fps, report.modal_fps);
+            derive_metadata(path, &output, &correction)?;
+        }
+        if !animation.events.is_empty() {
+            write_events(&events_path(&output), &animation.events)?;
+        }
+    }
+    report.save(&output_folder.join(FRAME_RATE_REPORT_FILE))
+}
+
+/// Prints the metadata of `clip`, all fields or just `key`.
+fn print_metadata(clip: &Path, key: Option<&str>) -> Result<()> {
+    let metadata = read_metadata(clip)?;
+    match key {
+        Some(key) => println!("{}", metadata.get(key).unwrap_or_default()),
+        None => print!("{}", toml::to_string(&metadata)?),
+    }
+    Ok(())
+}
+
+fn set_metadata(clip: &Path, key: &str, value: &str) -> Result<()> {
+    let mut metadata = read_metadata(clip)?;
+    metadata.set(key, value);
+    write_metadata(clip, &metadata)
+}
+
+/// Writes a training manifest of the clips in `dataset_folder` whose metadata passes every
+/// filter. Returns the number of included and excluded clips.
+fn assemble_manifest(
+    dataset_folder: &Path,
+    output: &Path,
+    filters: &[MetadataFilter],
+) -> Result<(usize, usize)> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dataset_folder)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    paths.sort();
+
+    let mut clips = Vec::new();
+    for path in &paths {
+        let is_clip = match path.extension().and_then(|e| e.to_str()) {
+            Some("bvh") => true,
+            // Tensors converted from a BVH clip in the same folder are already listed.
+            Some("npy") => !path.with_extension("bvh").exists(),
+            _ => false,
+        };
+        if is_clip {
+            clips.push((path.to_string_lossy().into_owned(), read_metadata(path)?));
+        }
+    }
+
+    let manifest = Manifest::assemble(clips, filters);
+    for exclusion in &manifest.excluded {
+        eprintln!("Excluded {}: {}", exclusion.clip, exclusion.reason);
+    }
+    manifest.save(output)?;
+    Ok((manifest.clips.len(), manifest.excluded.len()))
+}
+
+/// Records the content hash of every clip in `manifest` and of its sidecars into it, with
+/// paths relative to the folder of the manifest.
+fn hash_manifest(manifest: &Path) -> Result<usize> {
+    let mut loaded = Manifest::load(manifest)?;
+    let folder = manifest_folder(manifest);
+    loaded.files.clear();
+    for clip in &loaded.clips {
+        for file in clip_files(Path::new(clip))? {
+            loaded.files.push(FileHash {
+                path: relative_path(folder, &file),
+                ..hash_file(&file)?
+            });
+        }
+    }
+    loaded.save(manifest)?;
+    Ok(loaded.files.len())
+}
+
+/// Folder of `manifest`, the current folder for a bare file name.
+fn manifest_folder(manifest: &Path) -> &Path {
+    match manifest.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+/// Checks the files of `manifest` against their recorded hashes, printing every missing,
+/// corrupted or modified file. Returns the number of files with issues.
+fn verify_manifest(manifest: &Path) -> Result<usize> {
+    let loaded = Manifest::load(manifest)?;
+    if loaded.files.is_empty() {
+        bail!(
+            "{} has no file hashes, record them with hash first",
+            manifest.display()
+        );
+    }
+    let issues = verify(&loaded.files, manifest_folder(manifest))?;
+    for (file, issue) in &issues {
+        println!("{}\t{}", issue, file);
+    }
+    println!(
+        "{} of {} files are intact",
+        loaded.files.len() - issues.len(),
+        loaded.files.len()
+    );
+    Ok(issues.len())
+}
+
+/// Merges the converted datasets in `sources` into `output_folder`, with clip ids prefixed by
+/// the name of their source folder. Clips are reconciled with the skeleton of the first clip and
+/// resampled to `fps`, by default the most common frame rate. Clips that can't be reconciled
+/// are listed as excluded in the manifest of the merged dataset.
+fn merge_datasets(sources: &[PathBuf], output_folder: &Path, fps: Option<f32>) -> Result<Manifest> {
+    let mut clips = Vec::new();
+    for (index, source) in sources.iter().enumerate() {
+        let name = source
+            .file_name()
+            .with_context(|| format!("{} has no folder name", source.display()))?
+            .to_string_lossy()
+            .into_owned();
+        if sources[..index]
+            .iter()
+            .any(|other| other.file_name() == source.file_name())
+        {
+            bail!(
+                "Two sources are called {}, their clip ids would collide",
+                name
+            );
+        }
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(source)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<_, _>>()?;
+        paths.sort();
+        // Converted clips are the tensors with a skeleton sidecar.
+        paths.retain(|path| {
+            path.extension() == Some(OsStr::new("npy")) && skeleton_path(path).exists()
+        });
+        clips.extend(paths.into_iter().map(|path| (name.clone(), path)));
+    }
+
+    let target_frame_time = match fps {
+        Some(fps) => 1.0 / fps,
+        None => {
+            let mut rates = Vec::with_capacity(clips.len());
+            for (_, path) in &clips {
+                let (_, frame_time) = read_skeleton_sidecar(File::open(skeleton_path(path))?)?;
+                rates.push((path.to_string_lossy().into_owned(), frame_time, 0));
+            }
+            1.0 / FrameRateReport::new(rates)?.modal_fps
+        }
+    };
+
+    std::fs::create_dir_all(output_folder)?;
+    let mut manifest = Manifest::default();
+    let mut reference = None;
+    for (source, path) in &clips {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let id = prefixed_id(source, &stem);
+        let (mut animation, skeleton, frame_time) = load_gav(path)?;
+        animation.events = read_events(&events_path(path))?;
+        let reference = reference.get_or_insert_with(|| skeleton.clone());
+        let reconciliation = match reconcile(reference, &skeleton) {
+            Ok(reconciliation) => reconciliation,
+            Err(e) => {
+                manifest.excluded.push(Exclusion {
+                    clip: id,
+                    reason: e.to_string(),
+                });
+                continue;
+            }
+        };
+        let (skeleton, mut animation) = reconciliation.apply(&skeleton, &animation);
+        if frame_rate(frame_time) != frame_rate(target_frame_time) {
+            animation = resample_frame_time(&animation, frame_time, target_frame_time);
+        }
+
+        let output = output_folder.join(format!("{}.npy", id));
+        let sidecar = BufWriter::new(File::create(skeleton_path(&output))?);
+        write_skeleton_json(sidecar, &skeleton, target_frame_time)?;
+        write_npy(&output, &animation_to_gav(&animation)?)?;
+        if !animation.events.is_empty() {
+            write_events(&events_path(&output), &animation.events)?;
+        }
+        let mut metadata = read_metadata(path)?;
+        merged_metadata(&mut metadata, source);
+        write_metadata(&output, &metadata)?;
+        manifest.clips.push(output.to_string_lossy().into_owned());
+    }
+    manifest.save(&output_folder.join(MERGE_MANIFEST_FILE))?;
+    Ok(manifest)
+}
+
+/// Number of frames of a BVH clip or GAV tensor.
+fn clip_frame_count(clip: &Path) -> Result<usize> {
+    match clip.extension().and_then(|e| e.to_str()) {
+        Some("bvh") => Ok(load_bvh(clip)?.0.num_frames),
+        Some("npy") => Ok(read_tensor(BufReader::new(File::open(clip)?))?.dim().1),
+        _ => bail!("Unsupported clip format: {}", clip.display()),
+    }
+}
+
+/// Writes class balanced sampling weights of the clips in `manifest`, or of their `windows`,
+/// into it. The class of a clip is the value of the metadata field `key`. With `segments`, the
+/// motion primitives of the manifest take the place of the clips, so windows never straddle
+/// two primitives.
+fn balance_manifest(
+    manifest: &Path,
+    key: &str,
+    windows: Option<Windows>,
+    segments: bool,
+) -> Result<()> {
+    let mut loaded = Manifest::load(manifest)?;
+    if segments && loaded.segments.is_empty() {
+        bail!("The manifest has no motion primitives, segment it first");
+    }
+    let mut units = Vec::new();
+    for clip in &loaded.clips {
+        let path = Path::new(clip);
+        let class = read_metadata(path)?
+            .get(key)
+            .filter(|value| !value.is_empty())
+            .unwrap_or_else(|| UNLABELED.to_string());
+        let ranges: Vec<(Option<usize>, usize)> = if segments {
+            loaded
+                .segments
+                .iter()
+                .filter(|segment| segment.clip == *clip)
+                .map(|segment| (Some(segment.start), segment.frame_count()))
+                .collect()
+        } else {
+            vec![(None, clip_frame_count(path)?)]
+        };
+        for (offset, frame_count) in ranges {
+            match &windows {
+                Some(windows) => {
+                    let offset = offset.unwrap_or_default();
+                    units.extend(windows.starts(frame_count).into_iter().map(|start| {
+                        SamplingUnit {
+                            clip: clip.clone(),
+                            start: Some(offset + start),
+                            class: class.clone(),
+                            frame_count: windows.length,
+                        }
+                    }))
+                }
+                None => units.push(SamplingUnit {
+                    clip: clip.clone(),
+                    start: offset,
+                    class: class.clone(),
+                    frame_count,
+                }),
+            }
+        }
+    }
+    loaded.sampling = balance(units);
+    for (class, (count, weight)) in class_summary(&loaded.sampling) {
+        let class = if class == UNLABELED {
+            "(no class)"
+        } else {
+            class
+        };
+        println!("{}\t{}\t{:.3}", class, count, weight);
+    }
+    loaded.save(manifest)
+}
+
+/// Cuts every clip of `manifest` into motion primitives and records them in it.
+fn segment_manifest(manifest: &Path, config: &SegmentationConfig) -> Result<()> {
+    let mut loaded = Manifest::load(manifest)?;
+    loaded.segments.clear();
+    for clip in &loaded.clips {
+        let (animation, skeleton, frame_time) = load_clip(Path::new(clip))?;
+        let ranges = segment(&skeleton, &animation, frame_time, config)
+            .with_context(|| format!("Could not segment {}", clip))?;
+        println!("{}\t{} primitives", clip, ranges.len());
+        loaded
+            .segments
+            .extend(ranges.into_iter().map(|range| Segment {
+                clip: clip.clone(),
+                start: range.start,
+                end: range.end,
+            }));
+    }
+    let frames: usize = loaded.segments.iter().map(Segment::frame_count).sum();
+    println!(
+        "{} primitives, {:.1} frames on average",
+        loaded.segments.len(),
+        frames as f32 / loaded.segments.len().max(1) as f32
+    );
+    loaded.save(manifest)
+}
+
+/// Writes the cross-validation fold of every clip in `manifest` into it.
+fn assign_manifest_folds(manifest: &Path, k: usize, grouping: FoldGrouping) -> Result<()> {
+    let mut loaded = Manifest::load(manifest)?;
+    let clips = loaded
+        .clips
+        .iter()
+        .map(|clip| Ok((clip.clone(), read_metadata(Path::new(clip))?.subject)))
+        .collect::<Result<Vec<_>>>()?;
+    loaded.folds = assign_folds(&clips, k, grouping)?;
+    for fold in 0..k {
+        let count = loaded.folds.iter().filter(|f| f.fold == fold).count();
+        println!("Fold {}\t{} clips", fold, count);
+    }
+    loaded.save(manifest)
+}
+
+/// Prints the padding of the sequences of `manifest` for every batch size and bucket count, and
+/// with `record` records the buckets of `buckets` buckets in the manifest.
+fn report_buckets(
+    manifest: &Path,
+    batch_sizes: &[usize],
+    buckets: usize,
+    max_length: Option<usize>,
+    record: bool,
+) -> Result<()> {
+    if buckets == 0 || batch_sizes.contains(&0) {
+        bail!("Bucket counts and batch sizes have to be positive");
     }
-    let source_folder = &args[1];
-    match convert_bvh_to_gav(source_folder) {
-        Ok(0) => println!("No BVH files found to convert"),
-        Ok(count) => println!("Successfully converted {} BVH files to GAV", count),
-        Err(e) => eprintln!("Error converting BVH to GAV: {}", e),
+    let mut loaded = Manifest::load(manifest)?;
+    let mut sequences = Vec::new();
+    for clip in &loaded.clips {
+        let (animation, _, _) = load_clip(Path::new(clip))?;
+        for (start, frames) in clip_sequences(animation.frame_count(), max_length) {
+            sequences.push((clip.clone(), start, frames));
+        }
+    }
+    let lengths: Vec<usize> = sequences.iter().map(|(_, _, frames)| *frames).collect();
+    println!("Batch size\tBuckets\tBatches\tPadding\tBucket lengths");
+    for &batch_size in batch_sizes {
+        for count in 1..=buckets {
+            let report = PaddingReport::new(&lengths, &bucket_lengths(&lengths, count), batch_size);
+            println!(
+                "{}\t{}\t{}\t{:.1}%\t{:?}",
+                batch_size,
+                report.buckets.len(),
+                report.batches,
+                report.waste() * 100.0,
+                report.buckets
+            );
+        }
+    }
+    if record {
+        let lengths_of_buckets = bucket_lengths(&lengths, buckets);
+        loaded.buckets = sequences
+            .into_iter()
+            .map(|(clip, start, frames)| {
+                let bucket = bucket_of(&lengths_of_buckets, frames);
+                BucketAssignment {
+                    clip,
+                    start,
+                    frames,
+                    bucket,
+                    bucket_length: lengths_of_buckets[bucket],
+                }
+            })
+            .collect();
+        loaded.save(manifest)?;
+    }
+    Ok(())
+}
+
+/// Scores the difficulty of the clips of `manifest` and records it in the manifest.
+fn score_manifest_difficulty(manifest: &Path) -> Result<()> {
+    let mut loaded = Manifest::load(manifest)?;
+    let clips = loaded
+        .clips
+        .iter()
+        .map(|clip| {
+            let (animation, skeleton, frame_time) = load_clip(Path::new(clip))?;
+            let features = DifficultyFeatures::measure(&skeleton, &animation, frame_time)
+                .with_context(|| format!("Could not score {}", clip))?;
+            Ok((clip.clone(), features))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    loaded.difficulty = score_difficulty(clips);
+    let mut sorted: Vec<&ClipDifficulty> = loaded.difficulty.iter().collect();
+    sorted.sort_by(|a, b| a.score.total_cmp(&b.score));
+    if let (Some(easiest), Some(hardest)) = (sorted.first(), sorted.last()) {
+        println!("Easiest\t{:.2}\t{}", easiest.score, easiest.clip);
+        println!("Hardest\t{:.2}\t{}", hardest.score, hardest.clip);
+    }
+    loaded.save(manifest)
+}
+
+/// Writes `count` randomly augmented variants of every clip in `dataset_folder` to
+/// `output_folder`, as `<clip>_aug<i>.npy` tensors with their sidecars.
+fn augment_dataset(
+    dataset_folder: &Path,
+    output_folder: &Path,
+    count: usize,
+    seed: u64,
+    augmentations: &[Augmentation],
+) -> Result<usize> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dataset_folder)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    paths.sort();
+
+    std::fs::create_dir_all(output_folder)?;
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut written = 0;
+    for path in &paths {
+        let (mut animation, skeleton, frame_time) = match path.extension().and_then(|e| e.to_str())
+        {
+            Some("bvh") => {
+                let (bvh_meta, bvh_data) = load_bvh(path)?;
+                (
+                    bvh_to_animation(&bvh_data, bvh_meta.num_frames),
+                    Skeleton::from_bvh(&bvh_meta, &bvh_data),
+                    bvh_meta.frame_time as f32,
+                )
+            }
+            // Tensors converted from a BVH clip in the same folder are already listed.
+            Some("npy") if !path.with_extension("bvh").exists() => load_gav(path)?,
+            _ => continue,
+        };
+        animation.events = read_events(&events_path(path))?;
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        for variant in 0..count {
+            let (augmented, frame_time) = augment(&animation, frame_time, augmentations, &mut rng);
+            let output = output_folder.join(format!("{}_aug{}.npy", stem, variant));
+            let sidecar = BufWriter::new(File::create(skeleton_path(&output))?);
+            write_skeleton_json(sidecar, &skeleton, frame_time)?;
+            write_npy(&output, &animation_to_gav(&augmented)?)?;
+            if !augmented.events.is_empty() {
+                write_events(&events_path(&output), &augmented.events)?;
+            }
+            written += 1;
+        }
+    }
+    Ok(written)
+}
+
+/// A clean window written by [`write_clean_windows`], which training copies are paired with.
+struct CleanWindow {
+    index: usize,
+    data: Array3<f32>,
+    path: PathBuf,
+    window: ClipWindow,
+}
+
+/// Writes the windows of the converted clips in `dataset_folder` into `output_folder`, handing
+/// the windows of every clip to `write_copies` with its tensor and name to write the copies
+/// paired with them.
+fn write_clean_windows(
+    dataset_folder: &Path,
+    output_folder: &Path,
+    windows: &Windows,
+    mut write_copies: impl FnMut(&Path, &str, &[CleanWindow]) -> Result<()>,
+) -> Result<()> {
+    std::fs::create_dir_all(output_folder)?;
+    for tensor in converted_clips(dataset_folder)? {
+        let (data, _) = read_raw_tensor(&tensor)?;
+        let clip = tensor.file_stem().unwrap_or_default().to_string_lossy();
+        let source = tensor.with_extension("bvh");
+        let source = if source.exists() {
+            source
+        } else {
+            tensor.clone()
+        };
+        let mut clean = Vec::new();
+        for (index, (start, window)) in windows.split(&data).into_iter().enumerate() {
+            let path = window_path(
+                &output_folder.join(tensor.file_name().unwrap_or_default()),
+                index,
+            );
+            write_array(&path, &window)?;
+            clean.push(CleanWindow {
+                index,
+                window: ClipWindow {
+                    window: path.to_string_lossy().into_owned(),
+                    tensor: tensor.to_string_lossy().into_owned(),
+                    source: source.to_string_lossy().into_owned(),
+                    start,
+                    end: start + windows.length,
+                },
+                data: window,
+                path,
+            });
+        }
+        write_copies(&tensor, &clip, &clean)?;
+    }
+    Ok(())
+}
+
+/// Writes the windows of the converted clips in `dataset_folder` into `output_folder`, each
+/// with its copies noised at every level of `schedule`, and lists the pairs in
+/// [`NOISE_MANIFEST_FILE`]. Returns the number of pairs.
+fn sweep_noise(
+    dataset_folder: &Path,
+    output_folder: &Path,
+    windows: &Windows,
+    schedule: &NoiseSchedule,
+    seed: u64,
+) -> Result<usize> {
+    let mut pairs = Vec::new();
+    write_clean_windows(dataset_folder, output_folder, windows, |_, clip, clean| {
+        for window in clean {
+            for (level, &sigma) in schedule.sigmas.iter().enumerate() {
+                let noised_window = noised(
+                    &window.data,
+                    sigma,
+                    &mut window_rng(seed, clip, window.index, level),
+                );
+                let noised = noised_path(&window.path, level);
+                write_array(&noised, &noised_window)?;
+                pairs.push(NoisePair {
+                    clean: window.window.clone(),
+                    noised: noised.to_string_lossy().into_owned(),
+                    level,
+                    sigma,
+                });
+            }
+        }
+        Ok(())
+    })?;
+    let manifest = NoiseManifest {
+        seed,
+        sigmas: schedule.sigmas.clone(),
+        pairs,
+    };
+    manifest.save(&output_folder.join(NOISE_MANIFEST_FILE))?;
+    Ok(manifest.pairs.len())
+}
+
+/// Writes the windows of the converted tensors of `dataset_folder` to `output_folder`, each with
+/// a masked copy and its mask for every one of `strategies`, and returns how many masked copies
+/// were written.
+fn mask_windows(
+    dataset_folder: &Path,
+    output_folder: &Path,
+    windows: &Windows,
+    strategies: &[MaskStrategy],
+    seed: u64,
+) -> Result<usize> {
+    let mut pairs = Vec::new();
+    write_clean_windows(
+        dataset_folder,
+        output_folder,
+        windows,
+        |tensor, clip, clean| {
+            let (skeleton, _) = read_skeleton_sidecar(storage::open(&skeleton_path(tensor))?)?;
+            for window in clean {
+                let (curve_count, frame_count, _) = window.data.dim();
+                for (number, strategy) in strategies.iter().enumerate() {
+                    let mask = strategy.mask(
+                        &skeleton,
+                        curve_count,
+                        frame_count,
+                        &mut window_rng(seed, clip, window.index, number),
+                    );
+                    let masked = masked_path(&window.path, number);
+                    write_array(&masked, &apply_mask(&window.data, &mask)?)?;
+                    write_array(&mask_path(&masked), &mask)?;
+                    pairs.push(MaskedPair {
+                        clean: window.window.clone(),
+                        masked: masked.to_string_lossy().into_owned(),
+                        mask: mask_path(&masked).to_string_lossy().into_owned(),
+                        strategy: strategy.to_string(),
+                    });
+                }
+            }
+            Ok(())
+        },
+    )?;
+    let manifest = MaskManifest {
+        seed,
+        strategies: strategies.iter().map(ToString::to_string).collect(),
+        pairs,
+    };
+    manifest.save(&output_folder.join(MASK_MANIFEST_FILE))?;
+    Ok(manifest.pairs.len())
+}
+
+/// Packs a BVH clip, or a `.npy` tensor with its skeleton sidecar, into a `.gav` container.
+fn pack_gav(input: &Path, output: &Path) -> Result<()> {
+    let gav = match input.extension().and_then(|e| e.to_str()) {
+        Some("bvh") => {
+            let (bvh_meta, bvh_data) = load_bvh(input)?;
+            GavFile {
+                frame_time: bvh_meta.frame_time as f32,
+                skeleton: Skeleton::from_bvh(&bvh_meta, &bvh_data),
+                data: bvh_to_gav(&bvh_meta, &bvh_data)?,
+            }
+        }
+        Some("npy") => {
+            let sidecar = skeleton_path(input);
+            let (skeleton, frame_time) = read_skeleton_sidecar(
+                File::open(&sidecar)
+                    .with_context(|| format!("Could not open {}", sidecar.display()))?,
+            )?;
+            GavFile {
+                frame_time,
+                skeleton,
+                data: read_raw_tensor(input)?.0,
+            }
+        }
+        _ => bail!("Unsupported input format: {}", input.display()),
+    };
+    write_gav(&mut BufWriter::new(File::create(output)?), &gav)
+}
+
+/// Writes the channels of the `joints` curves of a BVH clip, `.gav` container or converted
+/// tensor to the CSV file `output`.
+fn export_channels_csv(clip: &Path, output: &Path, joints: &[String]) -> Result<()> {
+    let (data, skeleton, frame_time) = match clip.extension().and_then(|e| e.to_str()) {
+        Some("bvh") => {
+            let (bvh_meta, bvh_data) = load_bvh(clip)?;
+            (
+                bvh_to_gav(&bvh_meta, &bvh_data)?,
+                Skeleton::from_bvh(&bvh_meta, &bvh_data),
+                bvh_meta.frame_time as f32,
+            )
+        }
+        Some("gav") => {
+            let gav = read_gav(&mut BufReader::new(File::open(clip)?))?;
+            (gav.data, gav.skeleton, gav.frame_time)
+        }
+        _ => read_converted_clip(clip)?,
+    };
+    let names = curve_names(&skeleton, data.dim().0);
+    let curves = select_curves(&names, joints)?;
+    let writer = BufWriter::new(File::create(output)?);
+    write_channels_csv(writer, &data, &names, &curves, frame_time)
+}
+
+/// Loads a BVH clip, or a GAV tensor with its skeleton, with its events.
+fn load_clip(path: &Path) -> Result<(Animation, Skeleton, f32)> {
+    let (mut animation, skeleton, frame_time) = match path.extension().and_then(|e| e.to_str()) {
+        Some("bvh") => {
+            let (bvh_meta, bvh_data) = load_bvh(path)?;
+            (
+                bvh_to_animation(&bvh_data, bvh_meta.num_frames),
+                Skeleton::from_bvh(&bvh_meta, &bvh_data),
+                bvh_meta.frame_time as f32,
+            )
+        }
+        Some("npy" | "npz" | "gav") => load_gav(path)?,
+        _ => bail!("Unsupported clip format: {}", path.display()),
+    };
+    animation.events = read_events(&events_path(path))?;
+    Ok((animation, skeleton, frame_time))
+}
+
+/// Clips of a dataset: BVH clips, and tensors with a skeleton sidecar that weren't converted
+/// from a BVH clip next to them.
+fn dataset_clips(folder: &Path, recursive: bool) -> Result<Vec<PathBuf>> {
+    let files = storage::list_files(folder, recursive)?;
+    let listed: HashSet<&Path> = files.iter().map(PathBuf::as_path).collect();
+    let clips = files
+        .iter()
+        .filter(|path| match path.extension().and_then(|e| e.to_str()) {
+            Some("bvh") | Some("gav") => true,
+            Some("npy") => {
+                !listed.contains(path.with_extension("bvh").as_path())
+                    && listed.contains(skeleton_path(path).as_path())
+            }
+            _ => false,
+        })
+        .cloned()
+        .collect();
+    Ok(clips)
+}
+
+/// Prints the length, tensor shape, range of every channel and joints of a clip. The channels
+/// of a `.npy` tensor are those stored, of any other clip those of its GAV encoding.
+fn inspect_clip(clip: &Path) -> Result<()> {
+    let (animation, skeleton, frame_time) = load_clip(clip)?;
+    println!("{}", clip.display());
+    println!(
+        "{} frames at {} fps, {:.2} s",
+        animation.frame_count(),
+        frame_rate(frame_time),
+        animation.frame_count().saturating_sub(1) as f32 * frame_time
+    );
+    let data = if clip.extension() == Some(OsStr::new("npy")) {
+        let (data, dtype) = read_raw_tensor(clip)?;
+        let (curves, _, channels) = data.dim();
+        println!(
+            "{:?} tensor, {} curves of {} channels, {} after the joints, {}{}",
+            data.dim(),
+            curves,
+            channels,
+            curves.saturating_sub(skeleton.joint_count() + 1),
+            dtype,
+            if NormalizationStats::applied_to(clip)?.is_some() {
+                ", normalized"
+            } else {
+                ""
+            }
+        );
+        data
+    } else {
+        let data = animation_to_gav(&animation)?;
+        println!("{:?} GAV tensor", data.dim());
+        data
+    };
+    if !animation.events.is_empty() {
+        println!("{} events", animation.events.len());
+    }
+    let stats = channel_stats(&data);
+    let nan_count: usize = stats.iter().flatten().map(|stats| stats.nan_count).sum();
+    println!("{} NaN values, channel ranges:", nan_count);
+    for (curve, channels) in stats.iter().enumerate() {
+        let name = match curve {
+            0 => "root".to_string(),
+            curve if curve <= skeleton.joint_count() => skeleton.joints[curve - 1].name.clone(),
+            curve => format!("curve {}", curve),
+        };
+        let ranges: Vec<String> = channels
+            .iter()
+            .map(|stats| {
+                let nans = if stats.nan_count > 0 {
+                    format!(" ({} NaN)", stats.nan_count)
+                } else {
+                    String::new()
+                };
+                format!("[{:.4}, {:.4}]{}", stats.min, stats.max, nans)
+            })
+            .collect();
+        println!("  {}: {}", name, ranges.join(" "));
+    }
+    println!("{} joints:", skeleton.joint_count());
+    for index in skeleton.depth_first_order() {
+        let mut depth = 0;
+        let mut joint = index;
+        while let Some(parent) = skeleton.joints[joint].parent {
+            depth += 1;
+            joint = parent;
+        }
+        println!("{}{}", "  ".repeat(depth + 1), skeleton.joints[index].name);
+    }
+    Ok(())
+}
+
+fn print_dataset_stats(dataset_folder: &Path, recursive: bool) -> Result<()> {
+    let clips = dataset_clips(dataset_folder, recursive)?;
+    let mut frame_count = 0;
+    let mut duration = 0.0;
+    let mut rates: BTreeMap<u32, usize> = BTreeMap::new();
+    let mut skeletons: BTreeMap<usize, usize> = BTreeMap::new();
+    let mut lengths = Vec::with_capacity(clips.len());
+    for clip in &clips {
+        let (animation, skeleton, frame_time) = load_clip(clip)?;
+        let length = animation.frame_count().saturating_sub(1) as f32 * frame_time;
+        frame_count += animation.frame_count();
+        duration += length;
+        lengths.push(length);
+        *rates.entry(frame_rate(frame_time) as u32).or_default() += 1;
+        *skeletons.entry(skeleton.joint_count()).or_default() += 1;
+    }
+    println!(
+        "{} clips, {} frames, {:.1} s",
+        clips.len(),
+        frame_count,
+        duration
+    );
+    if let (Some(shortest), Some(longest)) = (
+        lengths.iter().copied().reduce(f32::min),
+        lengths.iter().copied().reduce(f32::max),
+    ) {
+        println!(
+            "Clip length {:.2} s to {:.2} s, {:.2} s on average",
+            shortest,
+            longest,
+            duration / clips.len() as f32
+        );
+    }
+    for (fps, count) in rates {
+        println!("{} fps\t{} clips", fps, count);
+    }
+    for (joints, count) in skeletons {
+        println!("{} joints\t{} clips", joints, count);
+    }
+    Ok(())
+}
+
+/// Validates a clip, or every clip of a dataset, printing the problems of each. Returns the
+/// number of invalid clips.
+fn validate_clips(path: &Path, recursive: bool) -> Result<usize> {
+    let clips = if path.is_dir() {
+        dataset_clips(path, recursive)?
+    } else {
+        vec![path.to_path_buf()]
+    };
+    let mut invalid = 0;
+    for clip in &clips {
+        let problems = match load_clip(clip) {
+            Ok((animation, skeleton, frame_time)) => {
+                validate_clip(&skeleton, &animation, frame_time)
+            }
+            Err(e) => vec![format!("{:#}", e)],
+        };
+        if !problems.is_empty() {
+            invalid += 1;
+        }
+        for problem in problems {
+            println!("{}: {}", clip.display(), problem);
+        }
+    }
+    println!(
+        "{} of {} clips are valid",
+        clips.len() - invalid,
+        clips.len()
+    );
+    Ok(invalid)
+}
+
+fn check_frame_times(reference: f32, candidate: f32) -> Result<()> {
+    if (reference - candidate).abs() > 1e-6 {
+        bail!(
+            "The reference has a frame time of {} but the candidate {}",
+            reference,
+            candidate
+        );
+    }
+    Ok(())
+}
+
+/// Runs the evaluation protocol at `protocol_path`, see [`Protocol`], and writes its results to
+/// `output`.
+fn run_eval_protocol(protocol_path: &Path, output: &Path) -> Result<EvaluationResults> {
+    let protocol = Protocol::load(protocol_path)?;
+    let folder = protocol_path.parent().unwrap_or(Path::new(""));
+    let mut clips = Vec::new();
+    for clip in &protocol.clips {
+        let reference_path = protocol.reference_path(folder, clip);
+        let (reference, reference_skeleton, frame_time) = load_clip(&reference_path)?;
+        for &seed in &protocol.seeds {
+            let candidate_path = protocol.candidate_path(folder, clip, seed);
+            let (candidate, candidate_skeleton, candidate_frame_time) = load_clip(&candidate_path)?;
+            check_frame_times(frame_time, candidate_frame_time)?;
+            let (windows, metrics) = protocol
+                .evaluate(
+                    (&reference_skeleton, &reference),
+                    (&candidate_skeleton, &candidate),
+                    frame_time,
+                )
+                .with_context(|| format!("Could not evaluate {}", candidate_path.display()))?;
+            clips.push(ClipResult {
+                reference: clip.reference.clone(),
+                candidate: candidate_path.to_string_lossy().into_owned(),
+                seed,
+                windows,
+                metrics,
+            });
+        }
+    }
+    let results = EvaluationResults {
+        version: RESULTS_VERSION,
+        tool_version: env!("CARGO_PKG_VERSION").to_string(),
+        protocol: protocol.name.clone(),
+        protocol_sha256: hash_file(protocol_path)?.sha256,
+        summary: summarize(&protocol.metrics, &protocol.seeds, &clips),
+        seeds: protocol.seeds,
+        alignment: protocol.alignment,
+        windows: protocol.windows,
+        clips,
+    };
+    results.save(output)?;
+    Ok(results)
+}
+
+/// Prints the metrics of the clip `candidate` against the clip `reference`, see
+/// [`MotionMetrics`].
+fn print_motion_metrics(reference: &Path, candidate: &Path) -> Result<()> {
+    let (reference_animation, reference_skeleton, frame_time) = load_clip(reference)?;
+    let (candidate_animation, candidate_skeleton, candidate_frame_time) = load_clip(candidate)?;
+    check_frame_times(frame_time, candidate_frame_time)?;
+    let metrics = MotionMetrics::compare(
+        &reference_skeleton,
+        &reference_animation,
+        &candidate_skeleton,
+        &candidate_animation,
+        frame_time,
+    )?;
+    println!("MPJPE: {:.4}", metrics.mpjpe);
+    println!(
+        "Bone length violation: {:.4}",
+        metrics.bone_length_violation
+    );
+    println!(
+        "Foot skate: {:.4} (reference {:.4})",
+        metrics.candidate.foot_skate, metrics.reference.foot_skate
+    );
+    println!(
+        "Angular jerk: {:.4} (reference {:.4})",
+        metrics.candidate.angular_jerk, metrics.reference.angular_jerk
+    );
+    Ok(())
+}
+
+/// Checks that the BVH clips at `path`, a clip or a dataset folder, survive a round trip
+/// through the GAV encoding, printing the error of every joint of a single clip and of the
+/// joints past `tolerance` otherwise. Returns how many clips failed.
+fn check_round_trips(path: &Path, recursive: bool, tolerance: f32) -> Result<usize> {
+    let single = !path.is_dir();
+    let clips: Vec<PathBuf> = if single {
+        vec![path.to_path_buf()]
+    } else {
+        dataset_clips(path, recursive)?
+            .into_iter()
+            .filter(|clip| clip.extension() == Some(OsStr::new("bvh")))
+            .collect()
+    };
+    let mut failed = 0;
+    for clip in &clips {
+        let errors = load_bvh(clip).and_then(|(bvh_meta, bvh_data)| {
+            let gav_data = bvh_to_gav(&bvh_meta, &bvh_data)?;
+            round_trip_errors(
+                &Skeleton::from_bvh(&bvh_meta, &bvh_data),
+                &bvh_to_animation(&bvh_data, bvh_meta.num_frames),
+                gav_data,
+            )
+        });
+        let errors = match errors {
+            Ok(errors) => errors,
+            Err(e) => {
+                failed += 1;
+                println!("{}: {:#}", clip.display(), e);
+                continue;
+            }
+        };
+        let worst = errors.iter().map(|error| error.max).fold(0.0, f32::max);
+        if worst > tolerance {
+            failed += 1;
+        }
+        println!("{}: largest error {:.6}", clip.display(), worst);
+        for error in errors
+            .iter()
+            .filter(|error| single || error.max > tolerance)
+        {
+            println!(
+                "  {}: max {:.6}, mean {:.6}",
+                error.joint, error.max, error.mean
+            );
+        }
+    }
+    println!(
+        "{} of {} clips round trip within {}",
+        clips.len() - failed,
+        clips.len(),
+        tolerance
+    );
+    Ok(failed)
+}
+
+/// Runs `command`, returning the exit code for the checks that can fail without an error.
+fn run(command: Command) -> Result<ExitCode> {
+    match command {
+        Command::Convert(options) => {
+            let report = convert_bvh_to_gav(&options).context("Could not convert BVH to GAV")?;
+            for failure in &report.failed {
+                eprintln!("Could not convert {}: {}", failure.clip, failure.reason);
+            }
+            if report.converted + report.skipped + report.failed.len() == 0 {
+                println!("No BVH files found to convert");
+            } else {
+                println!(
+                    "Converted {} BVH files to GAV, skipped {} that were already converted, {} failed",
+                    report.converted,
+                    report.skipped,
+                    report.failed.len()
+                );
+                println!(
+                    "Wrote {:.1} MB in {} files at {:.1} MB/s",
+                    report.writes.bytes as f64 / 1e6,
+                    report.writes.files,
+                    report.writes.throughput()
+                );
+            }
+            if !report.failed.is_empty() {
+                return Ok(ExitCode::from(EXIT_FAILURE));
+            }
+        }
+        Command::Serve(options) => {
+            let failed = serve_conversions(&options, std::io::stdin().lock(), std::io::stdout())
+                .context("Could not serve conversions")?;
+            if failed > 0 {
+                return Ok(ExitCode::from(EXIT_FAILURE));
+            }
+        }
+        Command::Decode {
+            input,
+            output,
+            skeleton,
+            project,
+            deflicker,
+            options,
+            tag,
+        } => {
+            let mut output = output.unwrap_or_else(|| {
+                let root = input.parent().unwrap_or(Path::new(""));
+                options.output_path(root, &input, "bvh")
+            });
+            let tag = tag.tag();
+            if let Some(tag) = &tag {
+                output = tag.tagged_path(&output);
+            }
+            if output.exists() && !options.overwrite {
+                bail!(
+                    "{} exists, pass --overwrite to replace it",
+                    output.display()
+                );
+            }
+            export_bvh(&input, skeleton.as_deref(), &output, project, deflicker)
+                .context("Could not export BVH")?;
+            if let Some(tag) = tag {
+                let mut metadata = read_metadata(&input)?;
+                tag.apply_to(&mut metadata);
+                write_metadata(&output, &metadata).context("Could not tag the clip")?;
+            }
+        }
+        Command::Inspect { clip } => inspect_clip(&clip)?,
+        Command::Stats {
+            dataset_folder,
+            recursive,
+        } => print_dataset_stats(&dataset_folder, recursive)?,
+        Command::Validate { path, recursive } => {
+            if validate_clips(&path, recursive).context("Could not validate clips")? > 0 {
+                return Ok(ExitCode::from(EXIT_CHECK_FAILED));
+            }
+        }
+        Command::Verify(VerifyCommand::Roundtrip {
+            path,
+            recursive,
+            tolerance,
+        }) => {
+            if check_round_trips(&path, recursive, tolerance)
+                .context("Could not check round trips")?
+                > 0
+            {
+                return Ok(ExitCode::from(EXIT_CHECK_FAILED));
+            }
+        }
+        Command::Metrics {
+            reference,
+            candidate,
+        } => print_motion_metrics(&reference, &candidate).context("Could not compare the clips")?,
+        Command::EvalProtocol { protocol, output } => {
+            let results =
+                run_eval_protocol(&protocol, &output).context("Could not run the protocol")?;
+            for (metric, summary) in &results.summary {
+                println!("{}: {:.4} ± {:.4}", metric, summary.mean, summary.std);
+            }
+            println!(
+                "Evaluated {} candidates, results in {}",
+                results.clips.len(),
+                output.display()
+            );
+        }
+        Command::Pose {
+            clip,
+            frame,
+            output,
+        } => extract_pose(&clip.to_string_lossy(), frame, &output)
+            .context("Could not extract the pose")?,
+        Command::Thumbs {
+            source_folder,
+            output_folder,
+        } => {
+            let count = render_thumbnails(&source_folder.to_string_lossy(), &output_folder)
+                .context("Could not render thumbnails")?;
+            println!("Rendered {} thumbnails", count);
+        }
+        Command::Blender {
+            output_folder,
+            clips,
+            name,
+        } => {
+            let script = export_blender(&output_folder, &clips, &name)
+                .context("Could not export the pose library")?;
+            println!(
+                "Exported {} clips, run {} from Blender's Text Editor to import them",
+                clips.len(),
+                script.display()
+            );
+        }
+        Command::Gallery {
+            dataset_folder,
+            output_folder,
+            thumbnail_folder,
+        } => {
+            let count =
+                export_gallery(&dataset_folder, &output_folder, thumbnail_folder.as_deref())
+                    .context("Could not export the gallery")?;
+            println!("Exported a gallery of {} clips", count);
+        }
+        Command::Search {
+            dataset_folder,
+            query,
+            count,
+        } => search_pose(&dataset_folder, &query, count).context("Could not search poses")?,
+        Command::Trajectory {
+            dataset_folder,
+            query,
+            count,
+        } => search_trajectory(&dataset_folder, &query, count)
+            .context("Could not search trajectories")?,
+        Command::Beats {
+            clip,
+            beats,
+            bpm,
+            output,
+        } => align_to_beats(&clip, &beats, bpm, &output).context("Could not align to beats")?,
+        Command::Gaze {
+            clip,
+            output,
+            head_joint,
+        } => export_gaze(&clip, &output, head_joint.as_deref())
+            .context("Could not export the gaze")?,
+        Command::Contacts {
+            clip,
+            max_height,
+            max_speed,
+            joints,
+        } => {
+            let joints: Vec<&str> = joints.iter().map(String::as_str).collect();
+            let output = label_contacts(&clip, max_height, max_speed, &joints)
+                .context("Could not label contacts")?;
+            println!("Wrote contact labels to {}", output.display());
+        }
+        Command::Constraints {
+            clip,
+            max_height,
+            max_speed,
+            joints,
+        } => {
+            let joints: Vec<&str> = joints.iter().map(String::as_str).collect();
+            let output = export_constraints(&clip, max_height, max_speed, &joints)
+                .context("Could not export constraints")?;
+            println!("Wrote constraints to {}", output.display());
+        }
+        Command::Embed { dataset_folder } => {
+            let count = build_embedding_index(&dataset_folder).context("Could not embed clips")?;
+            println!("Embedded {} clips", count);
+        }
+        Command::Similar {
+            dataset_folder,
+            clip,
+            count,
+        } => find_similar_clips(&dataset_folder, &clip, count)
+            .context("Could not find similar clips")?,
+        Command::Meta(MetaCommand::Get { clip, key }) => {
+            print_metadata(&clip, key.as_deref()).context("Could not read metadata")?
+        }
+        Command::Meta(MetaCommand::Set { clip, key, value }) => {
+            set_metadata(&clip, &key, &value).context("Could not edit metadata")?
+        }
+        Command::Assemble {
+            dataset_folder,
+            manifest,
+            filters,
+        } => {
+            let (included, excluded) = assemble_manifest(&dataset_folder, &manifest, &filters)
+                .context("Could not assemble the manifest")?;
+            println!("Included {} clips, excluded {}", included, excluded);
+        }
+        Command::Balance {
+            manifest,
+            key,
+            windows,
+            segments,
+        } => balance_manifest(&manifest, &key, windows, segments)
+            .context("Could not balance the manifest")?,
+        Command::Segment {
+            manifest,
+            min_frames,
+            valley,
+            turn,
+        } => {
+            let config = SegmentationConfig {
+                min_frames,
+                valley_ratio: valley,
+                turn_angle: turn.to_radians(),
+                ..Default::default()
+            };
+            segment_manifest(&manifest, &config).context("Could not segment the clips")?
+        }
+        Command::Folds {
+            manifest,
+            k,
+            grouping,
+        } => assign_manifest_folds(&manifest, k, grouping).context("Could not assign folds")?,
+        Command::Difficulty { manifest } => {
+            score_manifest_difficulty(&manifest).context("Could not score the clip difficulty")?
+        }
+        Command::Buckets {
+            manifest,
+            batch_sizes,
+            buckets,
+            max_length,
+            record,
+        } => report_buckets(&manifest, &batch_sizes, buckets, max_length, record)
+            .context("Could not report the length buckets")?,
+        Command::Hash { manifest } => {
+            let count = hash_manifest(&manifest).context("Could not hash dataset files")?;
+            println!("Hashed {} files", count);
+        }
+        Command::Verify(VerifyCommand::Integrity { manifest }) => {
+            if verify_manifest(&manifest).context("Could not verify dataset files")? > 0 {
+                return Ok(ExitCode::from(EXIT_CHECK_FAILED));
+            }
+        }
+        Command::Merge {
+            output_folder,
+            dataset_folders,
+            fps,
+        } => {
+            let manifest = merge_datasets(&dataset_folders, &output_folder, fps)
+                .context("Could not merge datasets")?;
+            for exclusion in &manifest.excluded {
+                eprintln!("Conflict in {}: {}", exclusion.clip, exclusion.reason);
+            }
+            println!(
+                "Merged {} clips, {} conflicts",
+                manifest.clips.len(),
+                manifest.excluded.len()
+            );
+        }
+        Command::Reference {
+            path,
+            output_folder,
+            format,
+            looping,
+        } => {
+            let count = export_reference(&path, &output_folder, format, looping)
+                .context("Could not export reference motion")?;
+            println!(
+                "Wrote {} clips of {} reference motion to {}",
+                count,
+                format,
+                output_folder.display()
+            );
+        }
+        Command::Pyramid {
+            path,
+            output_folder,
+            levels,
+            fps,
+        } => {
+            let count = export_pyramid(&path, &output_folder, levels, fps)
+                .context("Could not export temporal pyramids")?;
+            println!(
+                "Wrote {} level pyramids of {} clips to {}",
+                levels,
+                count,
+                output_folder.display()
+            );
+        }
+        Command::Humanml3d {
+            path,
+            output_folder,
+        } => {
+            let count = export_humanml3d(&path, &output_folder)
+                .context("Could not export HumanML3D features")?;
+            println!(
+                "Wrote the HumanML3D features of {} clips to {}",
+                count,
+                output_folder.display()
+            );
+        }
+        Command::Csv {
+            clip,
+            output,
+            joints,
+        } => {
+            let output = output.unwrap_or_else(|| clip.with_extension("csv"));
+            export_channels_csv(&clip, &output, &joints)
+                .context("Could not export the channels")?;
+            println!("Wrote {}", output.display());
+        }
+        Command::Compress {
+            clip,
+            output,
+            angle_tolerance,
+            position_tolerance,
+        } => {
+            let output = output.unwrap_or_else(|| keyframes_path(&clip));
+            let tolerance = KeyframeTolerance {
+                angle: angle_tolerance.to_radians(),
+                distance: position_tolerance,
+            };
+            let (keys, values) =
+                compress_clip(&clip, &output, tolerance).context("Could not compress the clip")?;
+            println!(
+                "Kept {} of {} keys ({:.1}%) in {}",
+                keys,
+                values,
+                100.0 * keys as f32 / values.max(1) as f32,
+                output.display()
+            );
+        }
+        Command::Decompress { input, output } => {
+            // `<clip>.keys.npz` to `<clip>.npy`.
+            let output = output.unwrap_or_else(|| input.with_extension("").with_extension("npy"));
+            decompress_clip(&input, &output).context("Could not decompress the keyframes")?;
+            println!("Wrote {}", output.display());
+        }
+        Command::Hdf5 {
+            dataset_folder,
+            output,
+        } => {
+            let count =
+                pack_hdf5(&dataset_folder, &output).context("Could not write the HDF5 file")?;
+            println!("Packed {} clips into {}", count, output.display());
+        }
+        Command::Parquet {
+            dataset_folder,
+            output,
+        } => {
+            let count = export_parquet(&dataset_folder, &output)
+                .context("Could not write the Parquet file")?;
+            println!(
+                "Wrote the frames of {} clips to {}",
+                count,
+                output.display()
+            );
+        }
+        Command::Normalize {
+            dataset_folder,
+            mode,
+            apply,
+        } => {
+            let stats = normalize_dataset(&dataset_folder, mode, apply)
+                .context("Could not normalize the dataset")?;
+            println!(
+                "Computed {:?} statistics of {} curves over {} frames{}",
+                stats.mode,
+                stats.offset.len(),
+                stats.frame_count,
+                if stats.is_applied() {
+                    ", normalized the tensors"
+                } else {
+                    ""
+                }
+            );
+        }
+        Command::Augment {
+            dataset_folder,
+            output_folder,
+            count,
+            augmentations,
+            seed,
+        } => {
+            let written =
+                augment_dataset(&dataset_folder, &output_folder, count, seed, &augmentations)
+                    .context("Could not augment clips")?;
+            println!("Wrote {} augmented clips", written);
+        }
+        Command::Noise {
+            dataset_folder,
+            output_folder,
+            windows,
+            schedule,
+            seed,
+        } => {
+            let count = sweep_noise(&dataset_folder, &output_folder, &windows, &schedule, seed)
+                .context("Could not write the noised windows")?;
+            println!(
+                "Wrote {} noised windows at {} levels to {}",
+                count,
+                schedule.sigmas.len(),
+                output_folder.display()
+            );
+        }
+        Command::Mask {
+            dataset_folder,
+            output_folder,
+            windows,
+            strategies,
+            seed,
+        } => {
+            let count = mask_windows(&dataset_folder, &output_folder, &windows, &strategies, seed)
+                .context("Could not write the masked windows")?;
+            println!(
+                "Wrote {} masked windows for {} strategies to {}",
+                count,
+                strategies.len(),
+                output_folder.display()
+            );
+        }
+        Command::Mirror { dataset_folder } => {
+            if check_mirror_pairs(&dataset_folder).context("Could not check mirrored pairs")? {
+                println!("All pairs mirror consistently");
+            } else {
+                println!("Mirroring this dataset would produce invalid motion");
+                return Ok(ExitCode::from(EXIT_CHECK_FAILED));
+            }
+        }
+        Command::Coverage { dataset_folder } => {
+            report_joint_coverage(&dataset_folder).context("Could not measure joint coverage")?
+        }
+        Command::JointPca { dataset_folder } => {
+            report_joint_pca(&dataset_folder).context("Could not fit the joint components")?
+        }
+        Command::Prior(PriorCommand::Fit {
+            dataset_folder,
+            components,
+        }) => {
+            fit_pose_prior(&dataset_folder, components).context("Could not fit the pose prior")?
+        }
+        Command::Prior(PriorCommand::Score {
+            prior,
+            path,
+            threshold,
+        }) => {
+            if !score_pose_prior(&prior, &path, threshold).context("Could not score poses")? {
+                println!("Some clips have implausible poses");
+                return Ok(ExitCode::from(EXIT_CHECK_FAILED));
+            }
+        }
+        Command::Fps {
+            dataset_folder,
+            output_folder,
+        } => unify_frame_rates(&dataset_folder, output_folder.as_deref())
+            .context("Could not unify frame rates")?,
+        Command::Pack { input, output } => {
+            pack_gav(&input, &output).context("Could not pack the GAV file")?
+        }
+        Command::Fingers(FingersCommand::Fit { output, clips }) => {
+            let count = fit_fingers(&clips, &output).context("Could not fit the fingers")?;
+            println!("Fitted {} fingers to {} clips", count, clips.len());
+        }
+        Command::Fingers(FingersCommand::Encode {
+            clip,
+            output,
+            fingers,
+        }) => {
+            let count = encode_fingers(&clip, &output, fingers.as_deref())
+                .context("Could not encode fingers")?;
+            println!("Encoded {} fingers", count);
+        }
+        Command::Fingers(FingersCommand::Decode {
+            input,
+            reference,
+            output,
+        }) => decode_fingers(&input, &reference, &output).context("Could not decode fingers")?,
+        Command::Retarget {
+            clip,
+            target,
+            output,
+            mapping,
+        } => retarget_clip(&clip, &target, &output, mapping.as_deref())
+            .context("Could not retarget")?,
+        Command::Dualquat(CodecCommand::Encode { clip, output }) => {
+            encode_dual_quaternions(&clip, &output).context("Could not encode dual quaternions")?
+        }
+        Command::Dualquat(CodecCommand::Decode { input, output }) => {
+            decode_dual_quaternions(&input, &output).context("Could not decode dual quaternions")?
+        }
+        Command::Rootmotion(CodecCommand::Encode { clip, output }) => {
+            encode_root_motion(&clip, &output).context("Could not encode root motion")?
+        }
+        Command::Rootmotion(CodecCommand::Decode { input, output }) => {
+            decode_root_motion(&input, &output).context("Could not decode root motion")?
+        }
+        Command::Delta(CodecCommand::Encode { clip, output }) => {
+            encode_delta(&clip, &output).context("Could not encode deltas")?
+        }
+        Command::Delta(CodecCommand::Decode { input, output }) => {
+            decode_delta(&input, &output).context("Could not decode deltas")?
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    // Malformed clips are reported as failures, without the parser's panic on top.
+    install_panic_hook();
+    match run(cli.command) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            ExitCode::from(EXIT_FAILURE)
+        }
     }
 }
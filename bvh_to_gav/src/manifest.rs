@@ -0,0 +1,201 @@
+//! Training manifest assembly. Clips are filtered on their [`crate::metadata`] fields, for
+//! example to keep only licenses that allow commercial use, and every exclusion is recorded
+//! with its reason so the selection can be audited later.
+use std::{path::Path, str::FromStr};
+
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bucketing::BucketAssignment, difficulty::ClipDifficulty, folds::FoldAssignment,
+    integrity::FileHash, metadata::ClipMetadata, sampling::SamplingWeight, segmentation::Segment,
+    storage,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Condition {
+    Equals,
+    NotEquals,
+    Contains,
+    NotContains,
+}
+
+/// A condition on one metadata field, parsed from `key=value`, `key!=value`, `key~text` or
+/// `key!~text`. Comparisons ignore case. Clips missing the field never pass, so a filter such
+/// as `license!~NC` also excludes clips without a license.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MetadataFilter {
+    key: String,
+    condition: Condition,
+    value: String,
+}
+
+impl FromStr for MetadataFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(filter: &str) -> Result<Self> {
+        // Two character operators first, so `!=` isn't read as `=`.
+        let operators = [
+            ("!=", Condition::NotEquals),
+            ("!~", Condition::NotContains),
+            ("=", Condition::Equals),
+            ("~", Condition::Contains),
+        ];
+        for (operator, condition) in operators {
+            if let Some((key, value)) = filter.split_once(operator) {
+                if key.is_empty() {
+                    bail!("Filter {} has no field name", filter);
+                }
+                return Ok(MetadataFilter {
+                    key: key.trim().to_string(),
+                    condition,
+                    value: value.trim().to_lowercase(),
+                });
+            }
+        }
+        bail!("Filter {} has no =, !=, ~ or !~ operator", filter)
+    }
+}
+
+impl std::fmt::Display for MetadataFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let operator = match self.condition {
+            Condition::Equals => "=",
+            Condition::NotEquals => "!=",
+            Condition::Contains => "~",
+            Condition::NotContains => "!~",
+        };
+        write!(f, "{}{}{}", self.key, operator, self.value)
+    }
+}
+
+impl MetadataFilter {
+    /// Checks `metadata`, returning why the clip is excluded when it fails.
+    pub fn check(&self, metadata: &ClipMetadata) -> Result<(), String> {
+        let Some(value) = metadata.get(&self.key).filter(|value| !value.is_empty()) else {
+            return Err(format!("no {}", self.key));
+        };
+        let lowercase = value.to_lowercase();
+        let passes = match self.condition {
+            Condition::Equals => lowercase == self.value,
+            Condition::NotEquals => lowercase != self.value,
+            Condition::Contains => lowercase.contains(&self.value),
+            Condition::NotContains => !lowercase.contains(&self.value),
+        };
+        if passes {
+            Ok(())
+        } else {
+            Err(format!("{} is {:?}, needs {}", self.key, value, self))
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Exclusion {
+    pub clip: String,
+    pub reason: String,
+}
+
+/// Clips selected for training, with the filters that selected them and the audit log of
+/// everything left out.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Manifest {
+    pub filters: Vec<String>,
+    pub clips: Vec<String>,
+    pub excluded: Vec<Exclusion>,
+    /// Class balanced sampling weights of the clips or their windows, see [`crate::sampling`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sampling: Vec<SamplingWeight>,
+    /// Cross-validation fold of every clip, see [`crate::folds`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub folds: Vec<FoldAssignment>,
+    /// Content hash of every clip and its sidecars, see [`crate::integrity`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<FileHash>,
+    /// Motion primitives of the clips, see [`crate::segmentation`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub segments: Vec<Segment>,
+    /// Curriculum difficulty of every clip, see [`crate::difficulty`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub difficulty: Vec<ClipDifficulty>,
+    /// Length bucket of every sequence, see [`crate::bucketing`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub buckets: Vec<BucketAssignment>,
+}
+
+impl Manifest {
+    /// Keeps the clips whose metadata passes every filter. An excluded clip is listed with the
+    /// first filter it failed.
+    pub fn assemble(
+        clips: impl IntoIterator<Item = (String, ClipMetadata)>,
+        filters: &[MetadataFilter],
+    ) -> Self {
+        let mut manifest = Manifest {
+            filters: filters.iter().map(ToString::to_string).collect(),
+            ..Default::default()
+        };
+        for (clip, metadata) in clips {
+            match filters
+                .iter()
+                .try_for_each(|filter| filter.check(&metadata))
+            {
+                Ok(()) => manifest.clips.push(clip),
+                Err(reason) => manifest.excluded.push(Exclusion { clip, reason }),
+            }
+        }
+        manifest
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(serde_json::from_reader(storage::open(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        storage::write_with(path, |writer| {
+            Ok(serde_json::to_writer_pretty(writer, self)?)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assemble_excludes_with_reasons() {
+        let clip = |license: &str, consent: &str| {
+            let mut metadata = ClipMetadata::default();
+            metadata.set("license", license);
+            metadata.set("consent", consent);
+            metadata
+        };
+        let filters: Vec<MetadataFilter> = ["license!~NC", "consent=yes"]
+            .iter()
+            .map(|f| f.parse().unwrap())
+            .collect();
+        let manifest = Manifest::assemble(
+            [
+                ("a.bvh".to_string(), clip("CC-BY-4.0", "Yes")),
+                ("b.bvh".to_string(), clip("CC-BY-NC-4.0", "yes")),
+                ("c.bvh".to_string(), clip("", "yes")),
+                ("d.bvh".to_string(), clip("CC0", "no")),
+            ],
+            &filters,
+        );
+        assert_eq!(manifest.clips, vec!["a.bvh"]);
+        let reasons: Vec<&str> = manifest
+            .excluded
+            .iter()
+            .map(|e| e.reason.as_str())
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                "license is \"CC-BY-NC-4.0\", needs license!~nc",
+                "no license",
+                "consent is \"no\", needs consent=yes",
+            ]
+        );
+        assert!("license".parse::<MetadataFilter>().is_err());
+    }
+}
@@ -0,0 +1,284 @@
+//! Masked copies of training windows for in-betweening and completion models, which learn to
+//! fill in what was hidden. Every window is written with a masked copy per masking strategy:
+//!
+//! - `spans:COUNT:MAX_LENGTH`: `COUNT` spans of up to `MAX_LENGTH` frames at random, every
+//!   curve of them hidden
+//! - `keyframes:INTERVAL`: every frame hidden but every `INTERVAL`th and the last, so a model
+//!   sees keyframes only
+//! - `limbs:PROBABILITY`: every limb, see [`limbs`], hidden over the whole window with
+//!   `PROBABILITY`
+//!
+//! The mask of a window is a `u8` tensor of `(curves, frames)`, 1 where the curve is hidden,
+//! and the hidden values of the masked copy are 0. The randomness is seeded per window and
+//! strategy, see [`crate::windows::window_rng`], so a pair comes out the same whatever else
+//! is in the dataset.
+//!
+//! Clean windows are written as `<clip>_w<index>.npy`, like [`crate::windows::window_path`],
+//! their masked copies as `<clip>_w<index>_m<strategy>.npy` and the masks as
+//! `<clip>_w<index>_m<strategy>_mask.npy`, all listed in `mask_manifest.json`.
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{Error, Result, anyhow, bail};
+use ndarray::{Array2, Array3, Axis, s};
+use rand::{Rng, rngs::StdRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{skeleton::Skeleton, storage, windows::ClipWindow};
+
+/// File name of the manifest of pairs, in the output folder.
+pub const MASK_MANIFEST_FILE: &str = "mask_manifest.json";
+
+/// What a masked copy hides, see the module documentation for the syntax.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MaskStrategy {
+    Spans { count: usize, max_length: usize },
+    Keyframes { interval: usize },
+    Limbs { probability: f64 },
+}
+
+impl FromStr for MaskStrategy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        fn parse<T: FromStr>(value: &str, s: &str) -> Result<T> {
+            value
+                .parse()
+                .map_err(|_| anyhow!("Invalid value {} in {}", value, s))
+        }
+        let parts: Vec<&str> = s.split(':').collect();
+        let strategy = match parts.as_slice() {
+            ["spans", count, max_length] => MaskStrategy::Spans {
+                count: parse(count, s)?,
+                max_length: parse(max_length, s)?,
+            },
+            ["keyframes", interval] => MaskStrategy::Keyframes {
+                interval: parse(interval, s)?,
+            },
+            ["limbs", probability] => MaskStrategy::Limbs {
+                probability: parse(probability, s)?,
+            },
+            _ => bail!(
+                "Unknown masking strategy {}, expected spans:COUNT:MAX_LENGTH, \
+                 keyframes:INTERVAL or limbs:PROBABILITY",
+                s
+            ),
+        };
+        match strategy {
+            MaskStrategy::Spans { max_length: 0, .. } => bail!("Spans need a length"),
+            MaskStrategy::Keyframes { interval: 0 } => bail!("Keyframes need an interval"),
+            MaskStrategy::Limbs { probability } if !(0.0..=1.0).contains(&probability) => {
+                bail!("The probability {} is not between 0 and 1", probability)
+            }
+            _ => Ok(strategy),
+        }
+    }
+}
+
+impl fmt::Display for MaskStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MaskStrategy::Spans { count, max_length } => {
+                write!(f, "spans:{}:{}", count, max_length)
+            }
+            MaskStrategy::Keyframes { interval } => write!(f, "keyframes:{}", interval),
+            MaskStrategy::Limbs { probability } => write!(f, "limbs:{}", probability),
+        }
+    }
+}
+
+/// The chains of joints between the branches of `skeleton`, such as an arm from the shoulder to
+/// the hand or a finger, each in order from the joint nearest to the root. Roots are in none.
+pub fn limbs(skeleton: &Skeleton) -> Vec<Vec<usize>> {
+    let starts = skeleton.joints.iter().enumerate().filter(|(_, joint)| {
+        joint.parent.is_some_and(|parent| {
+            skeleton.joints[parent].parent.is_none() || skeleton.children(parent).count() > 1
+        })
+    });
+    starts
+        .map(|(start, _)| {
+            let mut chain = vec![start];
+            loop {
+                let mut children = skeleton.children(*chain.last().unwrap());
+                match (children.next(), children.next()) {
+                    (Some(child), None) => chain.push(child),
+                    _ => break chain,
+                }
+            }
+        })
+        .collect()
+}
+
+impl MaskStrategy {
+    /// Mask of a window of `curve_count` curves and `frame_count` frames of `skeleton`.
+    pub fn mask(
+        &self,
+        skeleton: &Skeleton,
+        curve_count: usize,
+        frame_count: usize,
+        rng: &mut StdRng,
+    ) -> Array2<u8> {
+        let mut mask = Array2::zeros((curve_count, frame_count));
+        if frame_count == 0 {
+            return mask;
+        }
+        match *self {
+            MaskStrategy::Spans { count, max_length } => {
+                for _ in 0..count {
+                    let start = rng.gen_range(0..frame_count);
+                    let end = (start + rng.gen_range(1..=max_length)).min(frame_count);
+                    mask.slice_mut(s![.., start..end]).fill(1);
+                }
+            }
+            MaskStrategy::Keyframes { interval } => {
+                for (frame, mut column) in mask.axis_iter_mut(Axis(1)).enumerate() {
+                    if frame % interval != 0 && frame + 1 != frame_count {
+                        column.fill(1);
+                    }
+                }
+            }
+            MaskStrategy::Limbs { probability } => {
+                for limb in limbs(skeleton) {
+                    if rng.gen_bool(probability) {
+                        // Curve 0 holds the root positions.
+                        for joint in limb.into_iter().filter(|joint| joint + 1 < curve_count) {
+                            mask.row_mut(joint + 1).fill(1);
+                        }
+                    }
+                }
+            }
+        }
+        mask
+    }
+}
+
+/// `window` with the values `mask` hides set to 0.
+pub fn apply_mask(window: &Array3<f32>, mask: &Array2<u8>) -> Result<Array3<f32>> {
+    let (curve_count, frame_count, _) = window.dim();
+    if mask.dim() != (curve_count, frame_count) {
+        bail!(
+            "A mask of {:?} for a window of {} curves and {} frames",
+            mask.dim(),
+            curve_count,
+            frame_count
+        );
+    }
+    let mut masked = window.clone();
+    for ((curve, frame), _) in mask.indexed_iter().filter(|(_, hidden)| **hidden != 0) {
+        masked.slice_mut(s![curve, frame, ..]).fill(0.0);
+    }
+    Ok(masked)
+}
+
+/// Path of the copy of the window tensor `window` masked by strategy `strategy`.
+pub fn masked_path(window: &Path, strategy: usize) -> PathBuf {
+    let stem = window.file_stem().unwrap_or_default().to_string_lossy();
+    window.with_file_name(format!("{}_m{:02}.npy", stem, strategy))
+}
+
+/// Path of the mask of the masked copy `masked`.
+pub fn mask_path(masked: &Path) -> PathBuf {
+    let stem = masked.file_stem().unwrap_or_default().to_string_lossy();
+    masked.with_file_name(format!("{}_mask.npy", stem))
+}
+
+/// A masked copy of a clean window.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MaskedPair {
+    /// The clean window, in `window`, and where it came from.
+    #[serde(flatten)]
+    pub clean: ClipWindow,
+    pub masked: String,
+    pub mask: String,
+    pub strategy: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct MaskManifest {
+    pub seed: u64,
+    pub strategies: Vec<String>,
+    pub pairs: Vec<MaskedPair>,
+}
+
+impl MaskManifest {
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(serde_json::from_reader(storage::open(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        storage::write_with(path, |writer| {
+            Ok(serde_json::to_writer_pretty(writer, self)?)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_math::Vec3;
+
+    use super::*;
+    use crate::{skeleton::SkeletonJoint, windows::window_rng};
+
+    fn skeleton() -> Skeleton {
+        let joint = |name: &str, parent| SkeletonJoint {
+            name: name.to_string(),
+            parent,
+            offset: Vec3::ZERO,
+            end_site: None,
+        };
+        Skeleton {
+            joints: vec![
+                joint("Hips", None),
+                joint("LeftUpLeg", Some(0)),
+                joint("LeftLeg", Some(1)),
+                joint("RightUpLeg", Some(0)),
+                joint("RightLeg", Some(3)),
+                joint("Spine", Some(0)),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_strategies_hide_frames_and_limbs() {
+        let skeleton = skeleton();
+        assert_eq!(limbs(&skeleton), [vec![1, 2], vec![3, 4], vec![5]]);
+        for text in ["spans:2:5", "keyframes:4", "limbs:0.5"] {
+            assert_eq!(text.parse::<MaskStrategy>().unwrap().to_string(), text);
+        }
+        assert!("keyframes:0".parse::<MaskStrategy>().is_err());
+        assert!("limbs:2".parse::<MaskStrategy>().is_err());
+        assert!("holes:3".parse::<MaskStrategy>().is_err());
+
+        let rng = || window_rng(3, "walk", 0, 0);
+        let keyframes = MaskStrategy::Keyframes { interval: 4 }.mask(&skeleton, 7, 10, &mut rng());
+        let visible: Vec<usize> = (0..10).filter(|f| keyframes[[3, *f]] == 0).collect();
+        assert_eq!(visible, [0, 4, 8, 9]);
+
+        let spans = MaskStrategy::Spans {
+            count: 3,
+            max_length: 4,
+        };
+        let mask = spans.mask(&skeleton, 7, 20, &mut rng());
+        assert_eq!(mask, spans.mask(&skeleton, 7, 20, &mut rng()));
+        let hidden = mask.row(0).sum();
+        assert!((1..=12).contains(&hidden));
+        // Spans hide every curve of their frames.
+        assert!(
+            mask.axis_iter(Axis(1))
+                .all(|c| c.iter().all(|v| *v == c[0]))
+        );
+
+        let all_limbs = MaskStrategy::Limbs { probability: 1.0 }.mask(&skeleton, 7, 5, &mut rng());
+        assert_eq!(all_limbs.row(0).sum(), 0);
+        assert_eq!(all_limbs.row(1).sum(), 0);
+        assert_eq!(all_limbs.slice(s![2.., ..]).sum(), 25);
+
+        let window = Array3::from_elem((7, 5, 3), 1.0);
+        let masked = apply_mask(&window, &all_limbs).unwrap();
+        assert_eq!(masked.sum(), 30.0);
+        assert!(apply_mask(&window, &keyframes).is_err());
+    }
+}
@@ -0,0 +1,212 @@
+//! Merging of converted datasets into one corpus. Every clip gets an id prefixed with the name
+//! of the dataset it came from, so clips with the same name in two sources stay apart. The
+//! skeletons are reconciled with the first clip of the merge: joints are reordered to its joint
+//! order, and bone lengths that differ by a unit change, such as meters against centimeters,
+//! are rescaled. Skeletons with other joints or another hierarchy can't be reconciled and are
+//! reported as conflicts instead.
+use anyhow::{Result, bail};
+
+use crate::{
+    Animation,
+    convention::LengthUnit,
+    conversion_settings::ConversionSettings,
+    metadata::ClipMetadata,
+    skeleton::{Skeleton, SkeletonJoint},
+};
+
+/// File name of the manifest written into a merged dataset.
+pub const MERGE_MANIFEST_FILE: &str = "manifest.json";
+/// Metadata field holding the dataset a merged clip came from.
+pub const SOURCE_KEY: &str = "source";
+/// Bone lengths within this factor of the reference after the unit change are a different
+/// performer, not a different unit.
+const PROPORTION_TOLERANCE: f32 = 2.0;
+
+/// Id of `clip` from the dataset `source` in the merged dataset.
+pub fn prefixed_id(source: &str, clip: &str) -> String {
+    format!("{}__{}", source, clip)
+}
+
+/// Metadata of a clip merged from the dataset `source`. The merged tensor is reconciled and
+/// written as f32, so the settings it was converted with no longer describe it.
+pub fn merged_metadata(metadata: &mut ClipMetadata, source: &str) {
+    metadata.set(SOURCE_KEY, source);
+    ConversionSettings::clear(metadata);
+}
+
+/// How a clip is brought in line with the reference skeleton.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reconciliation {
+    /// Index in the clip of every reference joint, in reference order.
+    pub order: Vec<usize>,
+    /// Factor converting the lengths of the clip to the unit of the reference.
+    pub scale: f32,
+}
+
+fn total_bone_length(skeleton: &Skeleton) -> f32 {
+    skeleton
+        .joints
+        .iter()
+        .map(|joint| joint.offset.length())
+        .sum()
+}
+
+/// Matches `skeleton` against `reference`, returning why it can't be when it doesn't fit.
+pub fn reconcile(reference: &Skeleton, skeleton: &Skeleton) -> Result<Reconciliation> {
+    let order = reference
+        .joint_order()
+        .map(|name| match skeleton.find(name) {
+            Some(index) => Ok(index),
+            None => bail!("no joint called {}", name),
+        })
+        .collect::<Result<Vec<usize>>>()?;
+    if let Some(extra) = skeleton
+        .joint_order()
+        .find(|name| reference.find(name).is_none())
+    {
+        bail!("joint {} isn't in the reference skeleton", extra);
+    }
+    for (joint, &index) in reference.joints.iter().zip(&order) {
+        let parent = |skeleton: &Skeleton, parent: Option<usize>| {
+            parent.map(|parent| skeleton.joints[parent].name.clone())
+        };
+        if parent(reference, joint.parent) != parent(skeleton, skeleton.joints[index].parent) {
+            bail!("{} has a different parent", joint.name);
+        }
+    }
+
+    let (reference_length, length) = (total_bone_length(reference), total_bone_length(skeleton));
+    if reference_length <= 0.0 || length <= 0.0 {
+        return Ok(Reconciliation { order, scale: 1.0 });
+    }
+    let ratio = reference_length / length;
+    let units = [LengthUnit::Meters, LengthUnit::Centimeters];
+    let scale = units
+        .iter()
+        .flat_map(|from| units.iter().map(move |to| from.meters() / to.meters()))
+        .min_by(|a, b| (ratio / a).ln().abs().total_cmp(&(ratio / b).ln().abs()))
+        .unwrap_or(1.0);
+    let proportion = ratio / scale;
+    if !(1.0 / PROPORTION_TOLERANCE..=PROPORTION_TOLERANCE).contains(&proportion) {
+        bail!(
+            "bones are {:.2}x as long as the reference, which isn't a unit change",
+            ratio
+        );
+    }
+    Ok(Reconciliation { order, scale })
+}
+
+impl Reconciliation {
+    /// The skeleton and animation in reference joint order and units.
+    pub fn apply(&self, skeleton: &Skeleton, animation: &Animation) -> (Skeleton, Animation) {
+        let mut new_index = vec![0; skeleton.joint_count()];
+        for (new, &old) in self.order.iter().enumerate() {
+            new_index[old] = new;
+        }
+        let joints = self
+            .order
+            .iter()
+            .map(|&old| {
+                let joint = &skeleton.joints[old];
+                SkeletonJoint {
+                    name: joint.name.clone(),
+                    parent: joint.parent.map(|parent| new_index[parent]),
+                    offset: joint.offset * self.scale,
+                    end_site: joint.end_site.map(|end_site| end_site * self.scale),
+                }
+            })
+            .collect();
+        let animation = Animation {
+            root_positions: animation
+                .root_positions
+                .iter()
+                .map(|position| *position * self.scale)
+                .collect(),
+            joint_rotations: self
+                .order
+                .iter()
+                .map(|&old| animation.joint_rotations[old].clone())
+                .collect(),
+            events: animation.events.clone(),
+        };
+        (Skeleton { joints }, animation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_math::{Quat, Vec3};
+
+    use super::*;
+
+    fn joint(name: &str, parent: Option<usize>, offset: Vec3) -> SkeletonJoint {
+        SkeletonJoint {
+            name: name.to_string(),
+            parent,
+            offset,
+            end_site: None,
+        }
+    }
+
+    #[test]
+    fn test_reconcile_reorders_and_rescales() {
+        let reference = Skeleton {
+            joints: vec![
+                joint("Hips", None, Vec3::ZERO),
+                joint("Spine", Some(0), Vec3::Y * 20.0),
+                joint("Leg", Some(0), Vec3::Y * -40.0),
+            ],
+        };
+        // The same rig in meters, a little taller and listing its joints in another order.
+        let skeleton = Skeleton {
+            joints: vec![
+                joint("Leg", Some(2), Vec3::Y * -0.5),
+                joint("Spine", Some(2), Vec3::Y * 0.25),
+                joint("Hips", None, Vec3::ZERO),
+            ],
+        };
+        let reconciliation = reconcile(&reference, &skeleton).unwrap();
+        assert_eq!(reconciliation.order, vec![2, 1, 0]);
+        assert_eq!(reconciliation.scale, 100.0);
+
+        let animation = Animation {
+            root_positions: vec![Vec3::new(1.0, 0.9, 0.0)],
+            joint_rotations: vec![
+                vec![Quat::from_rotation_x(0.1)],
+                vec![Quat::from_rotation_x(0.2)],
+                vec![Quat::from_rotation_x(0.3)],
+            ],
+            events: vec![],
+        };
+        let (merged_skeleton, merged) = reconciliation.apply(&skeleton, &animation);
+        assert_eq!(merged_skeleton.joints[2].parent, Some(0));
+        assert_eq!(merged_skeleton.joints[2].offset, Vec3::Y * -50.0);
+        assert_eq!(merged.root_positions[0], Vec3::new(100.0, 90.0, 0.0));
+        assert_eq!(merged.joint_rotations[0][0], Quat::from_rotation_x(0.3));
+
+        let mut giant = reference.clone();
+        giant.joints[1].offset *= 30.0;
+        giant.joints[2].offset *= 30.0;
+        assert!(reconcile(&reference, &giant).is_err());
+        let mut other = reference.clone();
+        other.joints[2].parent = Some(1);
+        assert!(reconcile(&reference, &other).is_err());
+        other.joints[2].name = "Arm".to_string();
+        assert!(reconcile(&reference, &other).is_err());
+    }
+
+    #[test]
+    fn test_merged_clips_have_no_conversion_settings() {
+        let mut metadata = ClipMetadata::default();
+        ConversionSettings {
+            source: "clips/walk.bvh".into(),
+            fps: Some(30.0),
+            ..Default::default()
+        }
+        .apply_to(&mut metadata);
+        merged_metadata(&mut metadata, "mocap");
+        assert_eq!(metadata.get(SOURCE_KEY).as_deref(), Some("mocap"));
+        assert_eq!(ConversionSettings::from_metadata(&metadata).unwrap(), None);
+        assert!(metadata.fields().keys().all(|key| key == SOURCE_KEY));
+    }
+}
@@ -0,0 +1,205 @@
+//! Curation metadata of a clip, stored in a `<clip>.anim.toml` sidecar so it travels with the
+//! file. Clips that only have the older `<clip>.meta.json` sidecar are read from that instead.
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::gallery::read_clip_metadata;
+
+pub const METADATA_EXTENSION: &str = "anim.toml";
+pub(crate) const LEGACY_METADATA_EXTENSION: &str = "meta.json";
+/// Quality flags the preview offers when reviewing a clip.
+pub const QUALITY_FLAGS: [&str; 6] = [
+    "foot_sliding",
+    "jitter",
+    "penetration",
+    "marker_swap",
+    "truncated",
+    "needs_review",
+];
+
+/// Path of the metadata sidecar of a clip.
+pub fn metadata_path(clip: &Path) -> PathBuf {
+    clip.with_extension(METADATA_EXTENSION)
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct ClipMetadata {
+    pub labels: Vec<String>,
+    /// Performer of the capture.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capture_notes: Option<String>,
+    /// Processing steps that changed the clip, oldest first.
+    pub corrections: Vec<String>,
+    /// Problems found while reviewing the clip, usually from [`QUALITY_FLAGS`].
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub quality: Vec<String>,
+    /// Fields without a meaning to the tools, kept as written.
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, toml::Value>,
+}
+
+/// `value` as text: strings as they are, other values as TOML.
+fn value_text(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// `text` as a TOML value that isn't a string.
+fn parse_value(text: &str) -> Option<toml::Value> {
+    let mut table: toml::Table = toml::from_str(&format!("value = {}", text)).ok()?;
+    table.remove("value").filter(|value| !value.is_str())
+}
+
+impl ClipMetadata {
+    /// Value of a field as text. List fields are joined with commas.
+    pub fn get(&self, key: &str) -> Option<String> {
+        match key {
+            "labels" => Some(self.labels.join(",")),
+            "subject" => self.subject.clone(),
+            "license" => self.license.clone(),
+            "capture_notes" => self.capture_notes.clone(),
+            "corrections" => Some(self.corrections.join(",")),
+            "quality" => Some(self.quality.join(",")),
+            _ => self.extra.get(key).map(value_text),
+        }
+    }
+
+    /// Sets a field from text. List fields are split on commas, an empty value clears a field.
+    /// Other fields stay numbers, booleans or arrays while the text parses as one.
+    pub fn set(&mut self, key: &str, value: &str) {
+        let list = || {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(String::from)
+                .collect()
+        };
+        let text = (!value.is_empty()).then(|| value.to_string());
+        match key {
+            "labels" => self.labels = list(),
+            "subject" => self.subject = text,
+            "license" => self.license = text,
+            "capture_notes" => self.capture_notes = text,
+            "corrections" => self.corrections = list(),
+            "quality" => self.quality = list(),
+            _ => match text {
+                Some(text) => {
+                    let typed = self
+                        .extra
+                        .get(key)
+                        .filter(|value| !value.is_str())
+                        .and_then(|_| parse_value(&text));
+                    let value = typed.unwrap_or(toml::Value::String(text));
+                    self.extra.insert(key.to_string(), value);
+                }
+                None => {
+                    self.extra.remove(key);
+                }
+            },
+        }
+    }
+
+    /// All set fields except the labels, as shown in the gallery.
+    pub fn fields(&self) -> BTreeMap<String, String> {
+        let mut fields: BTreeMap<String, String> = self
+            .extra
+            .iter()
+            .map(|(key, value)| (key.clone(), value_text(value)))
+            .collect();
+        for key in [
+            "subject",
+            "license",
+            "capture_notes",
+            "corrections",
+            "quality",
+        ] {
+            if let Some(value) = self.get(key).filter(|value| !value.is_empty()) {
+                fields.insert(key.to_string(), value);
+            }
+        }
+        fields
+    }
+}
+
+/// Reads the metadata sidecar of `clip`. Clips without one get empty metadata.
+pub fn read_metadata(clip: &Path) -> Result<ClipMetadata> {
+    let path = metadata_path(clip);
+    if path.exists() {
+        let text = fs::read_to_string(&path)?;
+        return toml::from_str(&text).with_context(|| format!("Invalid {}", path.display()));
+    }
+    let (labels, extra) = read_clip_metadata(&clip.with_extension(LEGACY_METADATA_EXTENSION))?;
+    let mut metadata = ClipMetadata {
+        labels,
+        ..Default::default()
+    };
+    for (key, value) in extra {
+        metadata.set(&key, &value);
+    }
+    Ok(metadata)
+}
+
+pub fn write_metadata(clip: &Path, metadata: &ClipMetadata) -> Result<()> {
+    fs::write(metadata_path(clip), toml::to_string(metadata)?)?;
+    Ok(())
+}
+
+/// Copies the metadata of `source` to the derived clip `output`, recording the `correction`
+/// that was applied to it.
+pub fn derive_metadata(source: &Path, output: &Path, correction: &str) -> Result<()> {
+    let mut metadata = read_metadata(source)?;
+    metadata.corrections.push(correction.to_string());
+    write_metadata(output, &metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metadata_toml_round_trip() {
+        let mut metadata = ClipMetadata::default();
+        metadata.set("labels", "walk, happy");
+        metadata.set("subject", "S03");
+        metadata.set("session", "2024-05-02");
+        metadata.set("quality", "jitter");
+        metadata
+            .corrections
+            .push("resampled from 120 to 30 fps".to_string());
+
+        let text = toml::to_string(&metadata).unwrap();
+        assert!(text.contains("subject = \"S03\""));
+        let read: ClipMetadata = toml::from_str(&text).unwrap();
+        assert_eq!(read, metadata);
+        assert_eq!(read.get("labels").as_deref(), Some("walk,happy"));
+        assert_eq!(read.get("session").as_deref(), Some("2024-05-02"));
+        assert_eq!(read.quality, ["jitter"]);
+
+        let mut edited: ClipMetadata =
+            toml::from_str(&format!("{}take = 3\nretargeted = true\n", text)).unwrap();
+        assert_eq!(edited.get("take").as_deref(), Some("3"));
+        assert_eq!(edited.fields()["retargeted"], "true");
+        edited.set("take", "4");
+        assert_eq!(edited.extra["take"], toml::Value::Integer(4));
+        edited.set("take", "fourth");
+        assert_eq!(edited.extra["take"].as_str(), Some("fourth"));
+
+        metadata.set("subject", "");
+        assert_eq!(metadata.get("subject"), None);
+        assert!(!metadata.fields().contains_key("subject"));
+    }
+}
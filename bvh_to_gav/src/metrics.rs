@@ -0,0 +1,295 @@
+//! Evaluation metrics of generated motion against a reference clip of the same skeleton:
+//!
+//! - MPJPE: the mean per joint position error, the distance between the global positions of a
+//!   joint in both clips averaged over joints and frames, in skeleton units
+//! - bone length violation: how far the bones of the candidate, measured between its joint
+//!   positions in every frame, are from the length the reference skeleton gives them, averaged
+//!   over bones and frames, in skeleton units. Zero when both clips share a skeleton, as
+//!   forward kinematics keeps bones rigid, so it shows candidates posing another skeleton.
+//! - foot skate: how far the contact joints, see [`crate::contacts::default_contact_joints`],
+//!   slide horizontally while they are near the ground, in skeleton units per second
+//! - angular jerk: the magnitude of the third derivative of the joint rotations, averaged over
+//!   joints and frames, in radians per second cubed
+//!
+//! Foot skate and jerk are measured on both clips, so the candidate can be read against the
+//! reference. Positions come from [`crate::kinematics::global_positions`], with Y up.
+use anyhow::{Result, bail};
+use bevy_math::Vec3;
+use ndarray::Array3;
+use serde::Serialize;
+
+use crate::{
+    Animation,
+    contacts::{DEFAULT_CONTACT_HEIGHT, default_contact_joints},
+    derivatives::{Differencing, angular_velocities, linear_velocities},
+    kinematics::global_positions,
+    skeleton::Skeleton,
+};
+
+/// Metrics of a single clip.
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct ClipMetrics {
+    pub foot_skate: f32,
+    pub angular_jerk: f32,
+}
+
+/// Metrics of a candidate clip against a reference.
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct MotionMetrics {
+    pub mpjpe: f32,
+    pub bone_length_violation: f32,
+    pub reference: ClipMetrics,
+    pub candidate: ClipMetrics,
+}
+
+fn position(positions: &Array3<f32>, joint: usize, frame: usize) -> Vec3 {
+    Vec3::new(
+        positions[[joint, frame, 0]],
+        positions[[joint, frame, 1]],
+        positions[[joint, frame, 2]],
+    )
+}
+
+/// Horizontal distance the contact joints slide per second while within
+/// [`DEFAULT_CONTACT_HEIGHT`] of the lowest height any of them reaches. Zero for skeletons
+/// without feet.
+fn foot_skate(skeleton: &Skeleton, positions: &Array3<f32>, frame_time: f32) -> f32 {
+    let joints = default_contact_joints(skeleton);
+    let frame_count = positions.dim().1;
+    if joints.is_empty() || frame_count < 2 {
+        return 0.0;
+    }
+    let ground = joints
+        .iter()
+        .flat_map(|&joint| (0..frame_count).map(move |frame| positions[[joint, frame, 1]]))
+        .fold(f32::INFINITY, f32::min);
+    let planted = |joint, frame| positions[[joint, frame, 1]] - ground <= DEFAULT_CONTACT_HEIGHT;
+    let slide: f32 = joints
+        .iter()
+        .flat_map(|&joint| (1..frame_count).map(move |frame| (joint, frame)))
+        .filter(|&(joint, frame)| planted(joint, frame - 1) && planted(joint, frame))
+        .map(|(joint, frame)| {
+            let step = position(positions, joint, frame) - position(positions, joint, frame - 1);
+            step.with_y(0.0).length()
+        })
+        .sum();
+    slide / ((frame_count - 1) as f32 * frame_time)
+}
+
+/// Mean magnitude of the third derivative of the rotations of every joint.
+fn angular_jerk(animation: &Animation, frame_time: f32) -> f32 {
+    let frame_count = animation.frame_count();
+    if frame_count == 0 || animation.joint_count() == 0 {
+        return 0.0;
+    }
+    let total: f32 = animation
+        .joint_rotations
+        .iter()
+        .flat_map(|rotations| {
+            let velocities = angular_velocities(rotations, frame_time, Differencing::Central);
+            let accelerations = linear_velocities(&velocities, frame_time, Differencing::Central);
+            linear_velocities(&accelerations, frame_time, Differencing::Central)
+        })
+        .map(|jerk| jerk.length())
+        .sum();
+    total / (frame_count * animation.joint_count()) as f32
+}
+
+impl ClipMetrics {
+    pub fn measure(skeleton: &Skeleton, animation: &Animation, frame_time: f32) -> Result<Self> {
+        let positions = global_positions(skeleton, animation)?;
+        Ok(ClipMetrics {
+            foot_skate: foot_skate(skeleton, &positions, frame_time),
+            angular_jerk: angular_jerk(animation, frame_time),
+        })
+    }
+}
+
+impl MotionMetrics {
+    /// Compares `candidate`, posing `candidate_skeleton`, to `reference`, posing
+    /// `reference_skeleton`. Both need the same joints, by name and order, and frame count.
+    pub fn compare(
+        reference_skeleton: &Skeleton,
+        reference: &Animation,
+        candidate_skeleton: &Skeleton,
+        candidate: &Animation,
+        frame_time: f32,
+    ) -> Result<Self> {
+        if reference_skeleton.joint_count() != candidate_skeleton.joint_count() {
+            bail!(
+                "The reference has {} joints but the candidate {}",
+                reference_skeleton.joint_count(),
+                candidate_skeleton.joint_count()
+            );
+        }
+        if let Some((expected, actual)) = reference_skeleton
+            .joints
+            .iter()
+            .zip(&candidate_skeleton.joints)
+            .find(|(expected, actual)| expected.name != actual.name)
+        {
+            bail!(
+                "The reference has joint {} where the candidate has {}",
+                expected.name,
+                actual.name
+            );
+        }
+        if reference.frame_count() != candidate.frame_count() {
+            bail!(
+                "The reference has {} frames but the candidate {}",
+                reference.frame_count(),
+                candidate.frame_count()
+            );
+        }
+        let expected = global_positions(reference_skeleton, reference)?;
+        let actual = global_positions(candidate_skeleton, candidate)?;
+        let (joint_count, frame_count, _) = expected.dim();
+        let frames = || 0..frame_count;
+
+        let samples = (joint_count * frame_count).max(1) as f32;
+        let mpjpe = (0..joint_count)
+            .flat_map(|joint| frames().map(move |frame| (joint, frame)))
+            .map(|(joint, frame)| {
+                position(&expected, joint, frame).distance(position(&actual, joint, frame))
+            })
+            .sum::<f32>()
+            / samples;
+
+        let bones: Vec<(usize, usize, f32)> = reference_skeleton
+            .joints
+            .iter()
+            .enumerate()
+            .filter_map(|(joint, bone)| Some((joint, bone.parent?, bone.offset.length())))
+            .collect();
+        let bone_length_violation = bones
+            .iter()
+            .flat_map(|&bone| frames().map(move |frame| (bone, frame)))
+            .map(|((joint, parent, length), frame)| {
+                let actual_length =
+                    position(&actual, joint, frame).distance(position(&actual, parent, frame));
+                (actual_length - length).abs()
+            })
+            .sum::<f32>()
+            / (bones.len() * frame_count).max(1) as f32;
+
+        Ok(MotionMetrics {
+            mpjpe,
+            bone_length_violation,
+            reference: ClipMetrics {
+                foot_skate: foot_skate(reference_skeleton, &expected, frame_time),
+                angular_jerk: angular_jerk(reference, frame_time),
+            },
+            candidate: ClipMetrics {
+                foot_skate: foot_skate(candidate_skeleton, &actual, frame_time),
+                angular_jerk: angular_jerk(candidate, frame_time),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_math::Quat;
+
+    use super::*;
+    use crate::skeleton::SkeletonJoint;
+
+    #[test]
+    fn test_metrics_of_a_sliding_clip() {
+        let skeleton = |leg: f32| Skeleton {
+            joints: vec![
+                SkeletonJoint {
+                    name: "Hips".to_string(),
+                    parent: None,
+                    offset: Vec3::ZERO,
+                    end_site: None,
+                },
+                SkeletonJoint {
+                    name: "LeftToe".to_string(),
+                    parent: Some(0),
+                    offset: Vec3::new(0.0, -leg, 0.0),
+                    end_site: None,
+                },
+            ],
+        };
+        let frame_time = 0.1;
+        let standing = Animation {
+            root_positions: vec![Vec3::new(0.0, 90.0, 0.0); 11],
+            joint_rotations: vec![vec![Quat::IDENTITY; 11]; 2],
+            events: Vec::new(),
+        };
+        // Slides 1 unit a frame with the toe on the ground.
+        let sliding = Animation {
+            root_positions: (0..11)
+                .map(|frame| Vec3::new(frame as f32, 90.0, 0.0))
+                .collect(),
+            ..standing.clone()
+        };
+
+        let same = MotionMetrics::compare(
+            &skeleton(90.0),
+            &standing,
+            &skeleton(90.0),
+            &standing,
+            frame_time,
+        )
+        .unwrap();
+        assert_eq!(same, MotionMetrics::default());
+
+        let metrics = MotionMetrics::compare(
+            &skeleton(90.0),
+            &standing,
+            &skeleton(90.0),
+            &sliding,
+            frame_time,
+        )
+        .unwrap();
+        // Hips and toe are 5 units off on average.
+        assert!((metrics.mpjpe - 5.0).abs() < 1e-4);
+        assert_eq!(metrics.bone_length_violation, 0.0);
+        assert_eq!(metrics.reference.foot_skate, 0.0);
+        assert!((metrics.candidate.foot_skate - 10.0).abs() < 1e-3);
+        assert_eq!(metrics.candidate.angular_jerk, 0.0);
+        let shorter = MotionMetrics::compare(
+            &skeleton(90.0),
+            &standing,
+            &skeleton(80.0),
+            &standing,
+            frame_time,
+        )
+        .unwrap();
+        assert!((shorter.bone_length_violation - 10.0).abs() < 1e-4);
+        let mut renamed = skeleton(90.0);
+        renamed.joints[1].name = "RightToe".to_string();
+        assert!(
+            MotionMetrics::compare(&skeleton(90.0), &standing, &renamed, &standing, frame_time)
+                .is_err()
+        );
+
+        let spinning = Animation {
+            joint_rotations: vec![
+                (0..11)
+                    .map(|frame| Quat::from_rotation_y((frame as f32 * 0.1).powi(3)))
+                    .collect(),
+                vec![Quat::IDENTITY; 11],
+            ],
+            ..standing.clone()
+        };
+        let jerk = ClipMetrics::measure(&skeleton(90.0), &spinning, frame_time).unwrap();
+        assert!(jerk.angular_jerk > 0.0);
+        assert!(
+            MotionMetrics::compare(
+                &skeleton(90.0),
+                &standing,
+                &skeleton(90.0),
+                &Animation {
+                    root_positions: vec![Vec3::ZERO; 3],
+                    joint_rotations: vec![vec![Quat::IDENTITY; 3]; 2],
+                    events: Vec::new(),
+                },
+                frame_time,
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,360 @@
+//! Left/right mirroring for augmentation, and checks that joint pairs follow mirrored axis
+//! conventions. Mirroring a clip swaps the pairs and reflects their rotations across the
+//! lateral axis, which only gives anatomically valid motion when the two sides are set up as
+//! mirror images.
+//!
+//! Pairs are found by name, or listed in a TOML file for rigs with other naming:
+//!
+//! ```toml
+//! [pairs]
+//! # Left joint = right joint
+//! arm_a = "arm_b"
+//! ```
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::{Context, Result, bail};
+use bevy_math::{Quat, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::{Animation, kinematics::rest_positions, skeleton::Skeleton};
+
+/// Largest angle between a mirrored left bone and its right counterpart in the rest pose.
+pub const MAX_OFFSET_ERROR_DEGREES: f32 = 10.0;
+/// Largest difference between the mirrored left and the right rotation statistics.
+pub const MAX_ROTATION_ERROR_DEGREES: f32 = 20.0;
+
+const SIDES: [(&str, &str); 4] = [
+    ("Left", "Right"),
+    ("left", "right"),
+    ("L_", "R_"),
+    ("_L", "_R"),
+];
+
+/// Name of the joint on the other side, if `name` belongs to one side.
+pub fn mirror_name(name: &str) -> Option<String> {
+    for (left, right) in SIDES {
+        if name.contains(left) {
+            return Some(name.replacen(left, right, 1));
+        }
+        if name.contains(right) {
+            return Some(name.replacen(right, left, 1));
+        }
+    }
+    // Short prefixes such as `LHand` and `RHand`.
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some('L'), Some(c)) if c.is_uppercase() => Some(format!("R{}", &name[1..])),
+        (Some('R'), Some(c)) if c.is_uppercase() => Some(format!("L{}", &name[1..])),
+        _ => None,
+    }
+}
+
+/// Left/right joint pairs of a skeleton, as `(left, right)` indices.
+pub fn mirror_pairs(skeleton: &Skeleton) -> Vec<(usize, usize)> {
+    let is_left = |name: &str| {
+        SIDES.iter().any(|(left, _)| name.contains(left))
+            || (name.starts_with('L') && name.chars().nth(1).is_some_and(char::is_uppercase))
+    };
+    skeleton
+        .joints
+        .iter()
+        .enumerate()
+        .filter(|(_, joint)| is_left(&joint.name))
+        .filter_map(|(left, joint)| {
+            let right = skeleton.find(&mirror_name(&joint.name)?)?;
+            Some((left, right))
+        })
+        .collect()
+}
+
+/// Explicit left/right joint pairs, for skeletons whose names don't follow [`SIDES`].
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct MirrorMap {
+    /// Right joint of each left joint, by name.
+    pub pairs: BTreeMap<String, String>,
+}
+
+impl MirrorMap {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)?;
+        toml::from_str(&text).with_context(|| format!("Invalid {}", path.display()))
+    }
+
+    /// The pairs as `(left, right)` indices of `skeleton`.
+    pub fn pairs(&self, skeleton: &Skeleton) -> Result<Vec<(usize, usize)>> {
+        let find = |name: &str| {
+            skeleton
+                .find(name)
+                .with_context(|| format!("The skeleton has no joint called {}", name))
+        };
+        self.pairs
+            .iter()
+            .map(|(left, right)| Ok((find(left)?, find(right)?)))
+            .collect()
+    }
+}
+
+/// Axis the `pairs` are spread along in the rest pose, 0 for X, and the fraction of the left
+/// to right distance that lies along it.
+pub fn lateral_axis(skeleton: &Skeleton, pairs: &[(usize, usize)]) -> (usize, f32) {
+    let positions = rest_positions(skeleton);
+    let spread = pairs
+        .iter()
+        .map(|&(left, right)| (positions[left] - positions[right]).abs())
+        .sum::<Vec3>();
+    let axis = (0..3)
+        .max_by(|&a, &b| spread[a].total_cmp(&spread[b]))
+        .unwrap();
+    (axis, spread[axis] / spread.length().max(f32::EPSILON))
+}
+
+/// Reflects a position across the plane perpendicular to `axis`.
+pub fn mirror_position(position: Vec3, axis: usize) -> Vec3 {
+    let mut position = position;
+    position[axis] = -position[axis];
+    position
+}
+
+/// Reflects a rotation across the plane perpendicular to `axis`. The rotation axis is a
+/// pseudovector, so the two components in the plane flip instead.
+pub fn mirror_rotation(rotation: Quat, axis: usize) -> Quat {
+    let mut v = -rotation.xyz();
+    v[axis] = -v[axis];
+    Quat::from_xyzw(v.x, v.y, v.z, rotation.w)
+}
+
+impl Animation {
+    /// The animation mirrored across the plane perpendicular to `axis`: the joints of each of
+    /// the `pairs` swap motion, and all positions and rotations are reflected. The skeleton
+    /// has to be symmetric, which [`check_mirroring`] verifies.
+    pub fn mirrored(&self, pairs: &[(usize, usize)], axis: usize) -> Animation {
+        let mut source: Vec<usize> = (0..self.joint_count()).collect();
+        for &(left, right) in pairs {
+            source[left] = right;
+            source[right] = left;
+        }
+        Animation {
+            root_positions: self
+                .root_positions
+                .iter()
+                .map(|&p| mirror_position(p, axis))
+                .collect(),
+            joint_rotations: source
+                .iter()
+                .map(|&joint| {
+                    self.joint_rotations[joint]
+                        .iter()
+                        .map(|&q| mirror_rotation(q, axis))
+                        .collect()
+                })
+                .collect(),
+            events: self.events.clone(),
+        }
+    }
+}
+
+/// Consistency of one joint pair.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PairCheck {
+    pub left: String,
+    pub right: String,
+    /// Angle between the mirrored left and the right rest offset, in degrees.
+    pub offset_error: f32,
+    /// Distance between the mirrored left and the right mean rotation, in degrees.
+    pub mean_error: f32,
+    /// Largest difference of the per axis rotation spread of both sides, in degrees.
+    pub spread_error: f32,
+}
+
+impl PairCheck {
+    pub fn is_consistent(&self) -> bool {
+        self.offset_error <= MAX_OFFSET_ERROR_DEGREES
+            && self.mean_error <= MAX_ROTATION_ERROR_DEGREES
+            && self.spread_error <= MAX_ROTATION_ERROR_DEGREES
+    }
+}
+
+pub struct MirrorReport {
+    /// Axis the pairs are spread along, 0 for X.
+    pub lateral_axis: usize,
+    /// Fraction of the left to right distance that lies along the lateral axis. Mirroring by
+    /// flipping one axis is only valid when this is close to one.
+    pub axis_alignment: f32,
+    pub pairs: Vec<PairCheck>,
+}
+
+impl MirrorReport {
+    pub fn is_consistent(&self) -> bool {
+        self.axis_alignment >= MAX_OFFSET_ERROR_DEGREES.to_radians().cos()
+            && self.pairs.iter().all(PairCheck::is_consistent)
+    }
+}
+
+/// Mean and per axis standard deviation of the scaled axis rotations of a joint, in radians.
+fn rotation_statistics<'a>(
+    animations: impl Iterator<Item = &'a Animation>,
+    joint: usize,
+    mirror_axis: Option<usize>,
+) -> (Vec3, Vec3) {
+    let mut count = 0;
+    let (mut sum, mut sum_squared) = (Vec3::ZERO, Vec3::ZERO);
+    for rotation in animations.flat_map(|animation| &animation.joint_rotations[joint]) {
+        let rotation = match mirror_axis {
+            Some(axis) => mirror_rotation(*rotation, axis),
+            None => *rotation,
+        };
+        let v = rotation.to_scaled_axis();
+        count += 1;
+        sum += v;
+        sum_squared += v * v;
+    }
+    if count == 0 {
+        return (Vec3::ZERO, Vec3::ZERO);
+    }
+    let mean = sum / count as f32;
+    let variance = (sum_squared / count as f32 - mean * mean).max(Vec3::ZERO);
+    (mean, Vec3::from_array(variance.to_array().map(f32::sqrt)))
+}
+
+/// Compares every left/right pair of `skeleton`, using the motion of all `animations`. The
+/// statistics of a single clip are rarely symmetric, a whole dataset gives better results.
+pub fn check_mirroring(skeleton: &Skeleton, animations: &[Animation]) -> Result<MirrorReport> {
+    let pairs = mirror_pairs(skeleton);
+    if pairs.is_empty() {
+        bail!("No left/right joint pairs found");
+    }
+    if let Some(animation) = animations
+        .iter()
+        .find(|animation| animation.joint_count() != skeleton.joint_count())
+    {
+        bail!(
+            "Skeleton has {} joints but an animation has {} rotation tracks",
+            skeleton.joint_count(),
+            animation.joint_count()
+        );
+    }
+
+    let (lateral_axis, axis_alignment) = lateral_axis(skeleton, &pairs);
+
+    let pairs = pairs
+        .into_iter()
+        .map(|(left, right)| {
+            let mirrored_offset = mirror_position(skeleton.joints[left].offset, lateral_axis);
+            let right_offset = skeleton.joints[right].offset;
+            let offset_error = if mirrored_offset.length() > f32::EPSILON
+                && right_offset.length() > f32::EPSILON
+            {
+                mirrored_offset.angle_between(right_offset).to_degrees()
+            } else {
+                0.0
+            };
+            let (left_mean, left_spread) =
+                rotation_statistics(animations.iter(), left, Some(lateral_axis));
+            let (right_mean, right_spread) = rotation_statistics(animations.iter(), right, None);
+            PairCheck {
+                left: skeleton.joints[left].name.clone(),
+                right: skeleton.joints[right].name.clone(),
+                offset_error,
+                mean_error: left_mean.distance(right_mean).to_degrees(),
+                spread_error: (left_spread - right_spread)
+                    .abs()
+                    .max_element()
+                    .to_degrees(),
+            }
+        })
+        .collect();
+    Ok(MirrorReport {
+        lateral_axis,
+        axis_alignment,
+        pairs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::skeleton::SkeletonJoint;
+
+    fn skeleton(right_arm: Vec3) -> Skeleton {
+        let joint = |name: &str, parent, offset| SkeletonJoint {
+            name: name.to_string(),
+            parent,
+            offset,
+            end_site: None,
+        };
+        Skeleton {
+            joints: vec![
+                joint("Hips", None, Vec3::ZERO),
+                joint("LeftArm", Some(0), Vec3::X * 20.0),
+                joint("RightArm", Some(0), right_arm),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_mirror_names() {
+        assert_eq!(mirror_name("LeftUpLeg").as_deref(), Some("RightUpLeg"));
+        assert_eq!(
+            mirror_name("mixamorig:RightHand").as_deref(),
+            Some("mixamorig:LeftHand")
+        );
+        assert_eq!(mirror_name("RHand").as_deref(), Some("LHand"));
+        assert_eq!(mirror_name("Hips"), None);
+    }
+
+    #[test]
+    fn test_flipped_twist_is_flagged() {
+        let raise = |angle: f32| Quat::from_rotation_x(angle);
+        let animation = |right_sign: f32| Animation {
+            root_positions: vec![Vec3::ZERO; 3],
+            joint_rotations: vec![
+                vec![Quat::IDENTITY; 3],
+                [0.2, 0.6, 1.0].map(raise).to_vec(),
+                [0.2, 0.6, 1.0].map(|a| raise(a * right_sign)).to_vec(),
+            ],
+            events: vec![],
+        };
+        let mirrored = skeleton(Vec3::X * -20.0);
+
+        // Raising both arms forward is the same rotation about X on both sides.
+        let report = check_mirroring(&mirrored, &[animation(1.0)]).unwrap();
+        assert_eq!(report.lateral_axis, 0);
+        assert!(report.is_consistent(), "{:?}", report.pairs);
+
+        // A right arm rotating the other way has an inverted axis convention.
+        let report = check_mirroring(&mirrored, &[animation(-1.0)]).unwrap();
+        assert!(!report.pairs[0].is_consistent());
+
+        // So does a right arm whose bone doesn't point away from the body.
+        let report = check_mirroring(&skeleton(Vec3::Y * 20.0), &[animation(1.0)]).unwrap();
+        assert!(report.pairs[0].offset_error > MAX_OFFSET_ERROR_DEGREES);
+    }
+
+    #[test]
+    fn test_mirrored_animation_swaps_sides() {
+        let skeleton = skeleton(Vec3::X * -20.0);
+        let animation = Animation {
+            root_positions: vec![Vec3::new(5.0, 90.0, 1.0)],
+            joint_rotations: vec![
+                vec![Quat::from_rotation_y(0.3)],
+                vec![Quat::from_rotation_z(0.5)],
+                vec![Quat::IDENTITY],
+            ],
+            events: vec![],
+        };
+        let pairs = mirror_pairs(&skeleton);
+        assert_eq!(lateral_axis(&skeleton, &pairs), (0, 1.0));
+        let mirrored = animation.mirrored(&pairs, 0);
+        assert_eq!(mirrored.root_positions[0], Vec3::new(-5.0, 90.0, 1.0));
+        // Turning left becomes turning right, and the left arm raise moves to the right arm.
+        assert!(mirrored.joint_rotations[0][0].abs_diff_eq(Quat::from_rotation_y(-0.3), 1e-6));
+        assert_eq!(mirrored.joint_rotations[1][0], Quat::IDENTITY);
+        assert!(mirrored.joint_rotations[2][0].abs_diff_eq(Quat::from_rotation_z(-0.5), 1e-6));
+
+        let map: MirrorMap = toml::from_str("[pairs]\nLeftArm = \"RightArm\"").unwrap();
+        assert_eq!(map.pairs(&skeleton).unwrap(), vec![(1, 2)]);
+        let unknown: MirrorMap = toml::from_str("[pairs]\nLeftLeg = \"RightLeg\"").unwrap();
+        assert!(unknown.pairs(&skeleton).is_err());
+    }
+}
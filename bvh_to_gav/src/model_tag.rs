@@ -0,0 +1,130 @@
+//! Model, checkpoint and training step a generated clip was decoded from, so the outputs of
+//! several checkpoints can sit side by side in one folder. The tag goes into the file name of
+//! the decoded clip and into its metadata sidecar, see [`crate::metadata`], where the preview
+//! reads it back to filter clips by model.
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::metadata::ClipMetadata;
+
+const MODEL_KEY: &str = "model";
+const CHECKPOINT_KEY: &str = "checkpoint";
+const STEP_KEY: &str = "step";
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ModelTag {
+    pub model: String,
+    pub checkpoint: Option<String>,
+    pub step: Option<u64>,
+}
+
+/// `text` with every character that doesn't belong in a file name replaced by `-`.
+fn file_name_part(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+impl ModelTag {
+    /// Suffix of the file names of tagged clips, e.g. `motiondiff-best-step12000`.
+    pub fn file_suffix(&self) -> String {
+        let mut suffix = file_name_part(&self.model);
+        if let Some(checkpoint) = &self.checkpoint {
+            suffix.push('-');
+            suffix.push_str(&file_name_part(checkpoint));
+        }
+        if let Some(step) = self.step {
+            suffix.push_str(&format!("-step{}", step));
+        }
+        suffix
+    }
+
+    /// `path` with the tag appended to its file stem, as `<stem>_<suffix>.<extension>`.
+    pub fn tagged_path(&self, path: &Path) -> PathBuf {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match path.extension() {
+            Some(extension) => format!(
+                "{}_{}.{}",
+                stem,
+                self.file_suffix(),
+                extension.to_string_lossy()
+            ),
+            None => format!("{}_{}", stem, self.file_suffix()),
+        };
+        path.with_file_name(name)
+    }
+
+    pub fn apply_to(&self, metadata: &mut ClipMetadata) {
+        metadata.set(MODEL_KEY, &self.model);
+        metadata.set(
+            CHECKPOINT_KEY,
+            self.checkpoint.as_deref().unwrap_or_default(),
+        );
+        metadata.set(
+            STEP_KEY,
+            &self.step.map(|step| step.to_string()).unwrap_or_default(),
+        );
+    }
+
+    /// The tag in `metadata`, `None` for clips that weren't decoded from a model.
+    pub fn from_metadata(metadata: &ClipMetadata) -> Option<Self> {
+        Some(ModelTag {
+            model: metadata.get(MODEL_KEY)?,
+            checkpoint: metadata.get(CHECKPOINT_KEY),
+            step: metadata.get(STEP_KEY).and_then(|step| step.parse().ok()),
+        })
+    }
+}
+
+impl fmt::Display for ModelTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.model)?;
+        if let Some(checkpoint) = &self.checkpoint {
+            write!(f, " {}", checkpoint)?;
+        }
+        if let Some(step) = self.step {
+            write!(f, " step {}", step)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tag_round_trips_through_metadata() {
+        let tag = ModelTag {
+            model: "motion diff".to_string(),
+            checkpoint: Some("best".to_string()),
+            step: Some(12000),
+        };
+        assert_eq!(
+            tag.tagged_path(Path::new("out/walk.bvh")),
+            Path::new("out/walk_motion-diff-best-step12000.bvh")
+        );
+        assert_eq!(tag.to_string(), "motion diff best step 12000");
+
+        let mut metadata = ClipMetadata::default();
+        assert_eq!(ModelTag::from_metadata(&metadata), None);
+        tag.apply_to(&mut metadata);
+        assert_eq!(ModelTag::from_metadata(&metadata), Some(tag));
+
+        let vae = ModelTag {
+            model: "vae".to_string(),
+            checkpoint: None,
+            step: None,
+        };
+        vae.apply_to(&mut metadata);
+        assert_eq!(ModelTag::from_metadata(&metadata), Some(vae));
+    }
+}
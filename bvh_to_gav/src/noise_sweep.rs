@@ -0,0 +1,153 @@
+//! Paired clean and noised training windows for denoising diffusion, at every noise level of a
+//! schedule, so training and debugging runs load precomputed pairs and the noising convention
+//! lives in one place. The convention is variance exploding:
+//!
+//! ```text
+//! noised = clean + sigma * epsilon,  epsilon ~ N(0, I)
+//! ```
+//!
+//! applied to every channel of the window as stored, so sigma is in normalized units for
+//! normalized datasets, see [`crate::normalization`]. Every window and noise level draws from a
+//! generator of its own, see [`crate::windows::window_rng`], so a pair comes out the same
+//! whatever else is in the dataset.
+//!
+//! The clean windows are written as `<clip>_w<index>.npy`, like [`crate::windows::window_path`],
+//! and their noised copies as `<clip>_w<index>_s<level>.npy`, all listed in
+//! `noise_manifest.json` with the noise level and the frames they came from.
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{Context, Error, Result, bail};
+use ndarray::Array3;
+use rand::rngs::StdRng;
+use rand_distr::{Distribution, StandardNormal};
+use serde::{Deserialize, Serialize};
+
+use crate::{storage, windows::ClipWindow};
+
+/// File name of the manifest of pairs, in the output folder.
+pub const NOISE_MANIFEST_FILE: &str = "noise_manifest.json";
+
+/// Noise levels, parsed from a comma separated list of sigmas, such as `0.1,0.5,1`, or from
+/// `geometric:MIN:MAX:COUNT` for `COUNT` levels spaced evenly in log sigma.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NoiseSchedule {
+    pub sigmas: Vec<f32>,
+}
+
+impl FromStr for NoiseSchedule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parse = |value: &str| -> Result<f32> {
+            value
+                .trim()
+                .parse()
+                .with_context(|| format!("Invalid noise level {} in {}", value, s))
+        };
+        let sigmas = match s.strip_prefix("geometric:") {
+            Some(range) => {
+                let parts: Vec<&str> = range.split(':').collect();
+                let &[min, max, count] = parts.as_slice() else {
+                    bail!("Invalid schedule {}, expected geometric:MIN:MAX:COUNT", s);
+                };
+                let (min, max) = (parse(min)?, parse(max)?);
+                let count: usize = count
+                    .parse()
+                    .with_context(|| format!("Invalid level count {}", count))?;
+                if count < 2 || min <= 0.0 || max <= min {
+                    bail!("A geometric schedule needs 0 < MIN < MAX and at least 2 levels");
+                }
+                let ratio = (max / min).powf(1.0 / (count - 1) as f32);
+                (0..count)
+                    .map(|level| min * ratio.powi(level as i32))
+                    .collect()
+            }
+            None => s.split(',').map(parse).collect::<Result<Vec<_>>>()?,
+        };
+        if sigmas.is_empty() || sigmas.iter().any(|sigma| *sigma < 0.0) {
+            bail!("Noise levels have to be zero or more");
+        }
+        Ok(NoiseSchedule { sigmas })
+    }
+}
+
+/// `clean` with Gaussian noise of standard deviation `sigma` added to every channel.
+pub fn noised(clean: &Array3<f32>, sigma: f32, rng: &mut StdRng) -> Array3<f32> {
+    clean.mapv(|value| {
+        let epsilon: f32 = StandardNormal.sample(rng);
+        value + sigma * epsilon
+    })
+}
+
+/// Path of the copy of the window tensor `window` noised at `level`.
+pub fn noised_path(window: &Path, level: usize) -> PathBuf {
+    let stem = window.file_stem().unwrap_or_default().to_string_lossy();
+    window.with_file_name(format!("{}_s{:02}.npy", stem, level))
+}
+
+/// A noised copy of a clean window.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NoisePair {
+    /// The clean window, in `window`, and where it came from.
+    #[serde(flatten)]
+    pub clean: ClipWindow,
+    pub noised: String,
+    pub level: usize,
+    pub sigma: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct NoiseManifest {
+    pub seed: u64,
+    pub sigmas: Vec<f32>,
+    pub pairs: Vec<NoisePair>,
+}
+
+impl NoiseManifest {
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(serde_json::from_reader(storage::open(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        storage::write_with(path, |writer| {
+            Ok(serde_json::to_writer_pretty(writer, self)?)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::windows::window_rng;
+
+    #[test]
+    fn test_noise_is_seeded_per_window_and_level() {
+        let schedule: NoiseSchedule = "geometric:0.01:1:3".parse().unwrap();
+        assert_eq!(schedule.sigmas.len(), 3);
+        assert!((schedule.sigmas[1] - 0.1).abs() < 1e-5);
+        assert_eq!(
+            "0, 0.5".parse::<NoiseSchedule>().unwrap().sigmas,
+            [0.0, 0.5]
+        );
+        assert!("geometric:1:0.1:3".parse::<NoiseSchedule>().is_err());
+        assert!("-1".parse::<NoiseSchedule>().is_err());
+
+        let clean = Array3::from_elem((10, 40, 3), 0.25);
+        let first = noised(&clean, 0.5, &mut window_rng(7, "walk", 3, 1));
+        assert_eq!(first, noised(&clean, 0.5, &mut window_rng(7, "walk", 3, 1)));
+        assert_ne!(first, noised(&clean, 0.5, &mut window_rng(7, "walk", 3, 2)));
+        assert_ne!(first, noised(&clean, 0.5, &mut window_rng(7, "walk", 4, 1)));
+        assert_ne!(first, noised(&clean, 0.5, &mut window_rng(7, "run", 3, 1)));
+        let deviation = (first.mapv(|value| (value - 0.25).powi(2)).mean().unwrap()).sqrt();
+        assert!((deviation - 0.5).abs() < 0.05);
+        assert_eq!(noised(&clean, 0.0, &mut window_rng(7, "walk", 3, 1)), clean);
+
+        assert_eq!(
+            noised_path(Path::new("out/walk_w0002.npy"), 3),
+            Path::new("out/walk_w0002_s03.npy")
+        );
+    }
+}
@@ -0,0 +1,286 @@
+//! Per-channel normalization statistics of a converted dataset. Every channel of the GAV
+//! tensors, one component of one curve, is normalized as `(value - offset) / scale`: with the
+//! mean and standard deviation over all frames of all clips for [`NormalizationMode::Standard`],
+//! or with the minimum and range into `[0, 1]` for [`NormalizationMode::MinMax`]. The
+//! statistics are saved next to the clips with the names of the tensors normalized with them,
+//! and [`read_raw_tensor`] and [`crate::load_gav`] undo it on load. Tensors converted again
+//! after that are raw until the dataset is normalized again, see [`NormalizationStats::record`].
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{Error, Result, bail};
+use ndarray::{Array3, Axis};
+use serde::{Deserialize, Serialize};
+
+use crate::{dtype::Dtype, quantization::read_tensor_file, storage};
+
+/// File name of the statistics written next to the clips of a dataset.
+pub const NORMALIZATION_FILE: &str = "normalization.json";
+/// Channels that vary less than this keep a scale of one, so constant channels stay finite.
+const MIN_SCALE: f32 = 1e-6;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NormalizationMode {
+    /// Zero mean and unit standard deviation.
+    #[default]
+    Standard,
+    /// Minimum at zero and maximum at one.
+    MinMax,
+}
+
+impl FromStr for NormalizationMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "standard" => Ok(NormalizationMode::Standard),
+            "minmax" => Ok(NormalizationMode::MinMax),
+            _ => bail!("Unknown normalization {}, expected standard or minmax", s),
+        }
+    }
+}
+
+/// Statistics of one pass over the tensors of a dataset.
+#[derive(Clone, Debug, Default)]
+pub struct NormalizationAccumulator {
+    frame_count: usize,
+    sum: Vec<[f64; 3]>,
+    sum_of_squares: Vec<[f64; 3]>,
+    min: Vec<[f32; 3]>,
+    max: Vec<[f32; 3]>,
+}
+
+impl NormalizationAccumulator {
+    /// Adds every frame of a `(curves, frames, 3)` tensor. All tensors need the same curves.
+    pub fn add(&mut self, gav_data: &Array3<f32>) -> Result<()> {
+        let curve_count = gav_data.dim().0;
+        if self.sum.is_empty() {
+            self.sum = vec![[0.0; 3]; curve_count];
+            self.sum_of_squares = vec![[0.0; 3]; curve_count];
+            self.min = vec![[f32::INFINITY; 3]; curve_count];
+            self.max = vec![[f32::NEG_INFINITY; 3]; curve_count];
+        } else if curve_count != self.sum.len() {
+            bail!(
+                "A tensor has {} curves where the others have {}",
+                curve_count,
+                self.sum.len()
+            );
+        }
+        for (curve, frames) in gav_data.axis_iter(Axis(0)).enumerate() {
+            for frame in frames.outer_iter() {
+                for (channel, &value) in frame.iter().enumerate() {
+                    self.sum[curve][channel] += value as f64;
+                    self.sum_of_squares[curve][channel] += (value as f64).powi(2);
+                    self.min[curve][channel] = self.min[curve][channel].min(value);
+                    self.max[curve][channel] = self.max[curve][channel].max(value);
+                }
+            }
+        }
+        self.frame_count += gav_data.dim().1;
+        Ok(())
+    }
+
+    pub fn finish(&self, mode: NormalizationMode) -> Result<NormalizationStats> {
+        if self.frame_count == 0 {
+            bail!("No frames to compute normalization statistics from");
+        }
+        let count = self.frame_count as f64;
+        let channels = |f: &dyn Fn(usize, usize) -> f32| -> Vec<[f32; 3]> {
+            (0..self.sum.len())
+                .map(|curve| std::array::from_fn(|channel| f(curve, channel)))
+                .collect()
+        };
+        let mean = |curve: usize, channel: usize| self.sum[curve][channel] / count;
+        let (offset, scale) = match mode {
+            NormalizationMode::Standard => (
+                channels(&|curve, channel| mean(curve, channel) as f32),
+                channels(&|curve, channel| {
+                    let mean = mean(curve, channel);
+                    let variance = self.sum_of_squares[curve][channel] / count - mean * mean;
+                    variance.max(0.0).sqrt() as f32
+                }),
+            ),
+            NormalizationMode::MinMax => (
+                self.min.clone(),
+                channels(&|curve, channel| self.max[curve][channel] - self.min[curve][channel]),
+            ),
+        };
+        let scale = scale
+            .into_iter()
+            .map(|channels| channels.map(|s| if s < MIN_SCALE { 1.0 } else { s }))
+            .collect();
+        Ok(NormalizationStats {
+            mode,
+            frame_count: self.frame_count,
+            offset,
+            scale,
+            normalized: BTreeSet::new(),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NormalizationStats {
+    pub mode: NormalizationMode,
+    /// Number of frames the statistics were computed from.
+    pub frame_count: usize,
+    /// Subtracted from every channel, per curve.
+    pub offset: Vec<[f32; 3]>,
+    /// Divides every channel after the offset, per curve.
+    pub scale: Vec<[f32; 3]>,
+    /// File names of the tensors of the dataset normalized with these statistics.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub normalized: BTreeSet<String>,
+}
+
+/// Path of the normalization statistics of the dataset `clip` belongs to.
+pub fn normalization_path(clip: &Path) -> PathBuf {
+    clip.with_file_name(NORMALIZATION_FILE)
+}
+
+fn tensor_name(clip: &Path) -> String {
+    clip.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Reads the tensor at `path` whatever its dtype, denormalized when it was normalized, so every
+/// reader of converted tensors sees the raw values.
+pub fn read_raw_tensor(path: &Path) -> Result<(Array3<f32>, Dtype)> {
+    let (mut data, dtype) = read_tensor_file(path)?;
+    if let Some(stats) = NormalizationStats::applied_to(path)? {
+        stats.denormalize(&mut data)?;
+    }
+    Ok((data, dtype))
+}
+
+impl NormalizationStats {
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(serde_json::from_reader(storage::open(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        storage::write_with(path, |writer| {
+            Ok(serde_json::to_writer_pretty(writer, self)?)
+        })
+    }
+
+    /// Whether the tensor `clip` of the dataset has been normalized with these statistics.
+    pub fn is_applied_to(&self, clip: &Path) -> bool {
+        self.normalized.contains(&tensor_name(clip))
+    }
+
+    /// Whether any tensor of the dataset has been normalized with these statistics.
+    pub fn is_applied(&self) -> bool {
+        !self.normalized.is_empty()
+    }
+
+    /// The statistics `clip` has been normalized with, if any.
+    pub fn applied_to(clip: &Path) -> Result<Option<Self>> {
+        let path = normalization_path(clip);
+        if !storage::exists(&path)? {
+            return Ok(None);
+        }
+        Ok(Some(Self::load(&path)?).filter(|stats| stats.is_applied_to(clip)))
+    }
+
+    /// Records whether `clip`, just written, is normalized with the statistics of its dataset,
+    /// when the dataset has any.
+    pub fn record(clip: &Path, normalized: bool) -> Result<()> {
+        let path = normalization_path(clip);
+        if !storage::exists(&path)? {
+            return Ok(());
+        }
+        let mut stats = Self::load(&path)?;
+        let changed = if normalized {
+            stats.normalized.insert(tensor_name(clip))
+        } else {
+            stats.normalized.remove(&tensor_name(clip))
+        };
+        if changed {
+            stats.save(&path)?;
+        }
+        Ok(())
+    }
+
+    fn check(&self, gav_data: &Array3<f32>) -> Result<()> {
+        if gav_data.dim().0 != self.offset.len() {
+            bail!(
+                "The tensor has {} curves but the statistics {}",
+                gav_data.dim().0,
+                self.offset.len()
+            );
+        }
+        Ok(())
+    }
+
+    pub fn normalize(&self, gav_data: &mut Array3<f32>) -> Result<()> {
+        self.check(gav_data)?;
+        for (curve, mut frames) in gav_data.axis_iter_mut(Axis(0)).enumerate() {
+            for mut frame in frames.outer_iter_mut() {
+                for (channel, value) in frame.iter_mut().enumerate() {
+                    *value = (*value - self.offset[curve][channel]) / self.scale[curve][channel];
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Undoes [`NormalizationStats::normalize`].
+    pub fn denormalize(&self, gav_data: &mut Array3<f32>) -> Result<()> {
+        self.check(gav_data)?;
+        for (curve, mut frames) in gav_data.axis_iter_mut(Axis(0)).enumerate() {
+            for mut frame in frames.outer_iter_mut() {
+                for (channel, value) in frame.iter_mut().enumerate() {
+                    *value = *value * self.scale[curve][channel] + self.offset[curve][channel];
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalization_round_trip() {
+        let a = Array3::from_shape_fn((2, 3, 3), |(curve, frame, channel)| {
+            (curve * 10 + frame) as f32 + channel as f32 * 0.5
+        });
+        // The second curve is constant in the second clip.
+        let b = Array3::from_shape_fn((2, 1, 3), |(curve, _, _)| curve as f32 * 4.0);
+        let mut accumulator = NormalizationAccumulator::default();
+        accumulator.add(&a).unwrap();
+        accumulator.add(&b).unwrap();
+        assert!(accumulator.add(&Array3::zeros((3, 1, 3))).is_err());
+
+        let mut stats = accumulator.finish(NormalizationMode::Standard).unwrap();
+        assert_eq!(stats.frame_count, 4);
+        assert!(!stats.is_applied());
+        stats.normalized.insert("walk.npy".to_string());
+        assert!(stats.is_applied_to(Path::new("data/walk.npy")));
+        assert!(!stats.is_applied_to(Path::new("data/walk_mirrored.npy")));
+        // Root x over frames 0, 1, 2 and 0.
+        assert_eq!(stats.offset[0][0], 0.75);
+        assert!((stats.scale[0][0] - 0.8291562).abs() < 1e-6);
+
+        let mut normalized = a.clone();
+        stats.normalize(&mut normalized).unwrap();
+        stats.denormalize(&mut normalized).unwrap();
+        assert!(normalized.iter().zip(&a).all(|(x, y)| (x - y).abs() < 1e-5));
+
+        let min_max = accumulator.finish(NormalizationMode::MinMax).unwrap();
+        let mut normalized = a.clone();
+        min_max.normalize(&mut normalized).unwrap();
+        assert_eq!(normalized[[1, 2, 2]], 1.0);
+        assert_eq!(normalized[[0, 0, 0]], 0.0);
+        assert!(min_max.normalize(&mut Array3::zeros((1, 1, 3))).is_err());
+    }
+}
@@ -0,0 +1,102 @@
+//! Self-describing `.npz` output, as an alternative to the single anonymous `.npy` tensor, so the
+//! training side can load a clip by array name without knowing the GAV layout:
+//!
+//! - `root_positions`: f32 of shape (frames, 3)
+//! - `joint_rotations`: f32 of shape (joints, frames, 4), unit quaternions as x, y, z, w with
+//!   a non-negative w
+//! - `fps`: f32 scalar
+//! - `joint_names`: u8 of the UTF-8 joint names, each ended by a newline, in joint order
+//!
+//! The skeleton sidecar is still written next to the archive, as the offsets and parents of the
+//! joints aren't in it.
+use std::io::{Read, Seek, Write};
+
+use anyhow::{Result, bail};
+use bevy_math::{Quat, Vec3};
+use ndarray::{Array0, Array1, Array2, Array3};
+use ndarray_npy::{NpzReader, NpzWriter};
+
+use crate::{Animation, canonical_rotation, frame_rate::frame_rate, skeleton::Skeleton};
+
+pub const ROOT_POSITIONS: &str = "root_positions";
+pub const JOINT_ROTATIONS: &str = "joint_rotations";
+pub const FPS: &str = "fps";
+pub const JOINT_NAMES: &str = "joint_names";
+
+/// Writes `animation` of `skeleton` as a compressed `.npz` archive.
+pub fn write_npz<W: Write + Seek>(
+    writer: W,
+    skeleton: &Skeleton,
+    animation: &Animation,
+    frame_time: f32,
+) -> Result<()> {
+    let frame_count = animation.frame_count();
+    let root_positions = Array2::from_shape_vec(
+        (frame_count, 3),
+        animation
+            .root_positions
+            .iter()
+            .flat_map(|p| p.to_array())
+            .collect(),
+    )?;
+    let joint_rotations = Array3::from_shape_vec(
+        (animation.joint_count(), frame_count, 4),
+        animation
+            .joint_rotations
+            .iter()
+            .flatten()
+            .flat_map(|&q| canonical_rotation(q).to_array())
+            .collect(),
+    )?;
+    let joint_names: Array1<u8> = skeleton
+        .joint_order()
+        .flat_map(|name| name.bytes().chain([b'\n']))
+        .collect();
+
+    let mut npz = NpzWriter::new_compressed(writer);
+    npz.add_array(ROOT_POSITIONS, &root_positions)?;
+    npz.add_array(JOINT_ROTATIONS, &joint_rotations)?;
+    npz.add_array(FPS, &Array0::from_elem((), frame_rate(frame_time)))?;
+    npz.add_array(JOINT_NAMES, &joint_names)?;
+    npz.finish()?;
+    Ok(())
+}
+
+/// Reads the animation of a `.npz` archive written by [`write_npz`], checking that its joints
+/// are those of `skeleton`.
+pub fn read_npz<R: Read + Seek>(reader: R, skeleton: &Skeleton) -> Result<Animation> {
+    let mut npz = NpzReader::new(reader)?;
+    let names: Array1<u8> = npz.by_name(JOINT_NAMES)?;
+    let names = String::from_utf8(names.to_vec())?;
+    if !names.lines().eq(skeleton.joint_order()) {
+        bail!("The joints of the archive aren't those of its skeleton");
+    }
+    let root_positions: Array2<f32> = npz.by_name(ROOT_POSITIONS)?;
+    let joint_rotations: Array3<f32> = npz.by_name(JOINT_ROTATIONS)?;
+    let (joint_count, frame_count, _) = joint_rotations.dim();
+    if root_positions.dim() != (frame_count, 3) || joint_rotations.dim().2 != 4 {
+        bail!(
+            "Root positions of shape {:?} don't fit joint rotations of shape {:?}",
+            root_positions.dim(),
+            joint_rotations.dim()
+        );
+    }
+    Ok(Animation {
+        root_positions: root_positions
+            .rows()
+            .into_iter()
+            .map(|p| Vec3::new(p[0], p[1], p[2]))
+            .collect(),
+        joint_rotations: (0..joint_count)
+            .map(|joint| {
+                (0..frame_count)
+                    .map(|frame| {
+                        let q = joint_rotations.slice(ndarray::s![joint, frame, ..]);
+                        Quat::from_xyzw(q[0], q[1], q[2], q[3])
+                    })
+                    .collect()
+            })
+            .collect(),
+        events: Vec::new(),
+    })
+}
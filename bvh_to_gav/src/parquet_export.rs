@@ -0,0 +1,210 @@
+//! The frames of a converted dataset flattened into one Parquet table, for dataset-wide
+//! filtering and statistics with DuckDB or Polars. Needs bvh_to_gav built with the `parquet`
+//! feature. Every row is a frame of a clip, with the columns
+//!
+//! - `clip` (string), `frame` (u32) and `time` (f32, seconds from the first frame)
+//! - one f32 column per channel of the GAV tensor, denormalized: `root_x`, `root_y` and
+//!   `root_z` for the root position, `<joint>_x`, `<joint>_y` and `<joint>_z` for the vector
+//!   part of the rotation of every joint, and `curve<n>_x` and so on for curves appended after
+//!   the joints.
+//!
+//! All clips have to share the channels of the first one.
+use std::path::Path;
+
+use anyhow::{Result, bail};
+use ndarray::Array3;
+
+use crate::skeleton::Skeleton;
+
+/// Names of the channel columns of a tensor of `curve_count` curves of `skeleton`.
+pub fn channel_names(skeleton: &Skeleton, curve_count: usize) -> Vec<String> {
+    let joint_count = skeleton.joint_count();
+    (0..curve_count)
+        .map(|curve| match curve {
+            0 => "root".to_string(),
+            curve if curve <= joint_count => skeleton.joints[curve - 1].name.clone(),
+            curve => format!("curve{}", curve - joint_count - 1),
+        })
+        .flat_map(|curve| ["x", "y", "z"].map(|axis| format!("{}_{}", curve, axis)))
+        .collect()
+}
+
+/// Writes clips one at a time into a new Parquet file, which is complete once finished.
+pub struct ParquetWriter {
+    inner: imp::Writer,
+    channels: Option<Vec<String>>,
+}
+
+impl ParquetWriter {
+    pub fn create(path: &Path) -> Result<Self> {
+        Ok(ParquetWriter {
+            inner: imp::Writer::create(path)?,
+            channels: None,
+        })
+    }
+
+    /// Appends the frames of the clip `id`.
+    pub fn add_clip(
+        &mut self,
+        id: &str,
+        data: &Array3<f32>,
+        skeleton: &Skeleton,
+        frame_time: f32,
+    ) -> Result<()> {
+        let channels = channel_names(skeleton, data.dim().0);
+        match &self.channels {
+            Some(first) if *first != channels => {
+                bail!("The channels of {} differ from those of the first clip", id)
+            }
+            Some(_) => {}
+            None => self.channels = Some(channels.clone()),
+        }
+        self.inner.add_clip(id, data, &channels, frame_time)
+    }
+
+    /// Writes the footer of the file.
+    pub fn finish(self) -> Result<()> {
+        self.inner.finish()
+    }
+}
+
+#[cfg(feature = "parquet")]
+mod imp {
+    use std::{fs::File, path::Path, sync::Arc};
+
+    use anyhow::{Context, Result};
+    use arrow_array::{ArrayRef, Float32Array, RecordBatch, StringArray, UInt32Array};
+    use arrow_schema::{DataType, Field, Schema};
+    use ndarray::Array3;
+    use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+
+    /// The file until the first clip gives the schema, then its writer.
+    pub struct Writer {
+        file: Option<File>,
+        writer: Option<ArrowWriter<File>>,
+    }
+
+    impl Writer {
+        pub fn create(path: &Path) -> Result<Self> {
+            let file = File::create(path)
+                .with_context(|| format!("Could not create {}", path.display()))?;
+            Ok(Writer {
+                file: Some(file),
+                writer: None,
+            })
+        }
+
+        pub fn add_clip(
+            &mut self,
+            id: &str,
+            data: &Array3<f32>,
+            channels: &[String],
+            frame_time: f32,
+        ) -> Result<()> {
+            let frame_count = data.dim().1;
+            let mut fields = vec![
+                Field::new("clip", DataType::Utf8, false),
+                Field::new("frame", DataType::UInt32, false),
+                Field::new("time", DataType::Float32, false),
+            ];
+            fields.extend(
+                channels
+                    .iter()
+                    .map(|name| Field::new(name, DataType::Float32, false)),
+            );
+            let schema = Arc::new(Schema::new(fields));
+            let mut columns: Vec<ArrayRef> = vec![
+                Arc::new(StringArray::from(vec![id; frame_count])),
+                Arc::new(UInt32Array::from_iter_values(0..frame_count as u32)),
+                Arc::new(Float32Array::from_iter_values(
+                    (0..frame_count).map(|frame| frame as f32 * frame_time),
+                )),
+            ];
+            for channel in 0..channels.len() {
+                let values = data.slice(ndarray::s![channel / 3, .., channel % 3]);
+                columns.push(Arc::new(Float32Array::from_iter_values(
+                    values.iter().copied(),
+                )));
+            }
+            let batch = RecordBatch::try_new(schema.clone(), columns)?;
+
+            if let Some(file) = self.file.take() {
+                let properties = WriterProperties::builder()
+                    .set_compression(Compression::SNAPPY)
+                    .build();
+                self.writer = Some(ArrowWriter::try_new(file, schema, Some(properties))?);
+            }
+            if let Some(writer) = &mut self.writer {
+                writer.write(&batch)?;
+            }
+            Ok(())
+        }
+
+        pub fn finish(self) -> Result<()> {
+            if let Some(writer) = self.writer {
+                writer.close()?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(not(feature = "parquet"))]
+mod imp {
+    use std::path::Path;
+
+    use anyhow::{Result, bail};
+    use ndarray::Array3;
+
+    pub enum Writer {}
+
+    impl Writer {
+        pub fn create(path: &Path) -> Result<Self> {
+            bail!(
+                "Writing {} needs bvh_to_gav built with the parquet feature",
+                path.display()
+            )
+        }
+
+        pub fn add_clip(
+            &mut self,
+            _id: &str,
+            _data: &Array3<f32>,
+            _channels: &[String],
+            _frame_time: f32,
+        ) -> Result<()> {
+            match *self {}
+        }
+
+        pub fn finish(self) -> Result<()> {
+            match self {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_math::Vec3;
+
+    use super::*;
+    use crate::skeleton::SkeletonJoint;
+
+    #[test]
+    fn test_channels_are_named_after_joints() {
+        let skeleton = Skeleton {
+            joints: vec![SkeletonJoint {
+                name: "Hips".to_string(),
+                parent: None,
+                offset: Vec3::ZERO,
+                end_site: None,
+            }],
+        };
+        assert_eq!(
+            channel_names(&skeleton, 3),
+            vec![
+                "root_x", "root_y", "root_z", "Hips_x", "Hips_y", "Hips_z", "curve0_x", "curve0_y",
+                "curve0_z"
+            ]
+        );
+    }
+}
@@ -0,0 +1,277 @@
+//! Line plots of curves and metrics, written as SVG at the size of a paper column or page so
+//! figures don't have to be re-plotted in matplotlib. The preview draws the same plots on screen.
+use std::{fmt::Write as _, fs, path::Path};
+
+use anyhow::Result;
+use bevy_math::Vec2;
+
+/// Text size of the tick labels, axis labels and legend, in points.
+const FONT_SIZE: f32 = 7.0;
+const TITLE_SIZE: f32 = 9.0;
+pub const TICK_COUNT: usize = 5;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Series {
+    pub name: String,
+    /// sRGB color of the line.
+    pub color: [u8; 3],
+    pub points: Vec<Vec2>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Plot {
+    pub title: String,
+    pub x_label: String,
+    pub y_label: String,
+    pub series: Vec<Series>,
+}
+
+/// Figure sizes of a two column paper layout.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FigureSize {
+    /// 3.5 by 2.5 inches, one column.
+    #[default]
+    Column,
+    /// 7 by 3 inches, the full text width.
+    Page,
+}
+
+impl FigureSize {
+    /// Width and height in points, 72 to the inch.
+    pub fn points(self) -> Vec2 {
+        match self {
+            FigureSize::Column => Vec2::new(252.0, 180.0),
+            FigureSize::Page => Vec2::new(504.0, 216.0),
+        }
+    }
+}
+
+/// Round step of about `range / count`: 1, 2 or 5 times a power of ten.
+fn nice_step(range: f32, count: usize) -> f32 {
+    let raw = range / count.max(1) as f32;
+    let magnitude = 10f32.powf(raw.log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .find(|factor| factor * magnitude >= raw)
+        .unwrap_or(10.0);
+    step * magnitude
+}
+
+/// Tick values at round numbers within `min..=max`, about `count` of them.
+pub fn ticks(min: f32, max: f32, count: usize) -> Vec<f32> {
+    if !(max - min).is_finite() || max <= min {
+        return vec![min];
+    }
+    let step = nice_step(max - min, count);
+    let first = (min / step).ceil() as i64;
+    let last = (max / step + 1e-4).floor() as i64;
+    (first..=last).map(|i| i as f32 * step).collect()
+}
+
+/// Tick label with as many decimals as the tick spacing needs.
+pub fn format_tick(value: f32, ticks: &[f32]) -> String {
+    let step = match ticks {
+        [a, b, ..] => (b - a).abs(),
+        _ => 1.0,
+    };
+    let decimals = (-step.log10().floor()).max(0.0) as usize;
+    format!("{:.*}", decimals, value)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl Plot {
+    /// Lower and upper corner of all points. An empty range is widened so the points can
+    /// still be placed.
+    pub fn bounds(&self) -> Option<(Vec2, Vec2)> {
+        let mut points = self.series.iter().flat_map(|s| &s.points);
+        let first = *points.next()?;
+        let (mut min, mut max) =
+            points.fold((first, first), |(min, max), p| (min.min(*p), max.max(*p)));
+        for axis in 0..2 {
+            if max[axis] <= min[axis] {
+                min[axis] -= 0.5;
+                max[axis] += 0.5;
+            }
+        }
+        Some((min, max))
+    }
+
+    pub fn to_svg(&self, size: FigureSize) -> String {
+        let figure = size.points();
+        // Room for the title above, tick and axis labels to the left and below.
+        let (left, right, top, bottom) = (42.0, 8.0, 18.0, 28.0);
+        let area_min = Vec2::new(left, top);
+        let area_size = figure - Vec2::new(left + right, top + bottom);
+        let (min, max) = self.bounds().unwrap_or((Vec2::ZERO, Vec2::ONE));
+        let place = |p: Vec2| {
+            let t = (p - min) / (max - min);
+            Vec2::new(
+                area_min.x + t.x * area_size.x,
+                area_min.y + (1.0 - t.y) * area_size.y,
+            )
+        };
+
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}pt" height="{h}pt" viewBox="0 0 {w} {h}" font-family="Helvetica, Arial, sans-serif" font-size="{FONT_SIZE}">"#,
+            w = figure.x,
+            h = figure.y,
+        );
+        let _ = writeln!(
+            svg,
+            r#"<rect width="{}" height="{}" fill="white"/>"#,
+            figure.x, figure.y
+        );
+        let _ = writeln!(
+            svg,
+            r#"<text x="{}" y="{}" text-anchor="middle" font-size="{TITLE_SIZE}">{}</text>"#,
+            figure.x / 2.0,
+            top - 6.0,
+            escape(&self.title)
+        );
+
+        let x_ticks = ticks(min.x, max.x, TICK_COUNT);
+        for x in &x_ticks {
+            let p = place(Vec2::new(*x, min.y));
+            let _ = writeln!(
+                svg,
+                r##"<line x1="{0:.2}" y1="{1:.2}" x2="{0:.2}" y2="{2:.2}" stroke="#ddd" stroke-width="0.5"/>"##,
+                p.x, area_min.y, p.y
+            );
+            let _ = writeln!(
+                svg,
+                r#"<text x="{:.2}" y="{:.2}" text-anchor="middle">{}</text>"#,
+                p.x,
+                p.y + FONT_SIZE + 2.0,
+                format_tick(*x, &x_ticks)
+            );
+        }
+        let y_ticks = ticks(min.y, max.y, TICK_COUNT);
+        for y in &y_ticks {
+            let p = place(Vec2::new(min.x, *y));
+            let _ = writeln!(
+                svg,
+                r##"<line x1="{0:.2}" y1="{1:.2}" x2="{2:.2}" y2="{1:.2}" stroke="#ddd" stroke-width="0.5"/>"##,
+                p.x,
+                p.y,
+                area_min.x + area_size.x
+            );
+            let _ = writeln!(
+                svg,
+                r#"<text x="{:.2}" y="{:.2}" text-anchor="end">{}</text>"#,
+                p.x - 3.0,
+                p.y + FONT_SIZE / 3.0,
+                format_tick(*y, &y_ticks)
+            );
+        }
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="black" stroke-width="0.75"/>"#,
+            area_min.x, area_min.y, area_size.x, area_size.y
+        );
+        let _ = writeln!(
+            svg,
+            r#"<text x="{}" y="{}" text-anchor="middle">{}</text>"#,
+            area_min.x + area_size.x / 2.0,
+            figure.y - 4.0,
+            escape(&self.x_label)
+        );
+        let _ = writeln!(
+            svg,
+            r#"<text transform="translate({},{}) rotate(-90)" text-anchor="middle">{}</text>"#,
+            FONT_SIZE + 2.0,
+            area_min.y + area_size.y / 2.0,
+            escape(&self.y_label)
+        );
+
+        for series in &self.series {
+            let points: Vec<String> = series
+                .points
+                .iter()
+                .map(|p| {
+                    let p = place(*p);
+                    format!("{:.2},{:.2}", p.x, p.y)
+                })
+                .collect();
+            let [r, g, b] = series.color;
+            let _ = writeln!(
+                svg,
+                r##"<polyline points="{}" fill="none" stroke="#{:02X}{:02X}{:02X}" stroke-width="1"/>"##,
+                points.join(" "),
+                r,
+                g,
+                b
+            );
+        }
+
+        // Legend in the top right corner of the plot area.
+        for (index, series) in self.series.iter().enumerate() {
+            let y = area_min.y + 8.0 + index as f32 * (FONT_SIZE + 3.0);
+            let x = area_min.x + area_size.x - 6.0;
+            let [r, g, b] = series.color;
+            let _ = writeln!(
+                svg,
+                r##"<line x1="{:.2}" y1="{y:.2}" x2="{:.2}" y2="{y:.2}" stroke="#{:02X}{:02X}{:02X}" stroke-width="1.5"/>"##,
+                x - 12.0,
+                x,
+                r,
+                g,
+                b
+            );
+            let _ = writeln!(
+                svg,
+                r#"<text x="{:.2}" y="{:.2}" text-anchor="end">{}</text>"#,
+                x - 15.0,
+                y + FONT_SIZE / 3.0,
+                escape(&series.name)
+            );
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    pub fn save_svg(&self, path: &Path, size: FigureSize) -> Result<()> {
+        fs::write(path, self.to_svg(size))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ticks_and_svg() {
+        assert_eq!(
+            ticks(0.0, 100.0, 5),
+            vec![0.0, 20.0, 40.0, 60.0, 80.0, 100.0]
+        );
+        assert_eq!(ticks(-0.3, 0.3, 5), vec![-0.2, 0.0, 0.2]);
+        assert_eq!(format_tick(0.2, &[0.1, 0.2]), "0.2");
+        assert_eq!(format_tick(40.0, &[20.0, 40.0]), "40");
+
+        let plot = Plot {
+            title: "Knee <left>".to_string(),
+            x_label: "frame".to_string(),
+            y_label: "angle".to_string(),
+            series: vec![Series {
+                name: "LeftLeg".to_string(),
+                color: [31, 119, 180],
+                points: vec![Vec2::new(0.0, 10.0), Vec2::new(10.0, 30.0)],
+            }],
+        };
+        let svg = plot.to_svg(FigureSize::Column);
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains(r#"width="252pt""#));
+        assert!(svg.contains("Knee &lt;left&gt;"));
+        assert!(svg.contains("#1F77B4"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+}
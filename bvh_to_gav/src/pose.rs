@@ -0,0 +1,226 @@
+use std::io::{Read, Write};
+
+use anyhow::{Result, bail};
+use bevy_math::{Quat, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Animation,
+    skeleton::{Skeleton, SkeletonJoint},
+};
+
+/// A single frame of an [`Animation`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct Pose {
+    pub root_position: Vec3,
+    pub joint_rotations: Vec<Quat>,
+}
+
+impl Animation {
+    pub fn pose(&self, frame: usize) -> Option<Pose> {
+        if frame >= self.frame_count() {
+            return None;
+        }
+        Some(Pose {
+            root_position: self.root_positions[frame],
+            joint_rotations: self
+                .joint_rotations
+                .iter()
+                .map(|rotations| rotations[frame])
+                .collect(),
+        })
+    }
+
+    /// Pose at a fractional frame, interpolated between the neighbouring frames. Frames outside
+    /// the clip are clamped to its first or last frame.
+    pub fn sample(&self, frame: f32) -> Option<Pose> {
+        let last = self.frame_count().checked_sub(1)?;
+        let frame = frame.clamp(0.0, last as f32);
+        let index = frame.floor() as usize;
+        let next = (index + 1).min(last);
+        let t = frame - index as f32;
+        Some(Pose {
+            root_position: self.root_positions[index].lerp(self.root_positions[next], t),
+            joint_rotations: self
+                .joint_rotations
+                .iter()
+                .map(|rotations| rotations[index].slerp(rotations[next], t))
+                .collect(),
+        })
+    }
+
+    /// A new animation holding the poses at the given fractional frames of this one. Events are
+    /// not carried over, only the caller knows how the frames map.
+    pub fn resample(&self, frames: impl IntoIterator<Item = f32>) -> Animation {
+        let mut animation = Animation {
+            root_positions: Vec::new(),
+            joint_rotations: vec![Vec::new(); self.joint_count()],
+            events: Vec::new(),
+        };
+        for pose in frames.into_iter().filter_map(|frame| self.sample(frame)) {
+            animation.root_positions.push(pose.root_position);
+            for (track, rotation) in animation
+                .joint_rotations
+                .iter_mut()
+                .zip(pose.joint_rotations)
+            {
+                track.push(rotation);
+            }
+        }
+        animation
+    }
+}
+
+impl From<Pose> for Animation {
+    /// A one frame animation holding `pose`.
+    fn from(pose: Pose) -> Self {
+        Animation {
+            root_positions: vec![pose.root_position],
+            joint_rotations: pose.joint_rotations.into_iter().map(|q| vec![q]).collect(),
+            events: Vec::new(),
+        }
+    }
+}
+
+/// On-disk JSON layout of a pose. Rotations are local quaternions stored as `[x, y, z, w]`.
+#[derive(Serialize, Deserialize)]
+struct PoseFile {
+    frame: usize,
+    frame_time: f32,
+    root_position: [f32; 3],
+    joints: Vec<JointPose>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JointPose {
+    name: String,
+    parent: Option<usize>,
+    offset: [f32; 3],
+    rotation: [f32; 4],
+}
+
+pub fn write_pose_json<W: Write>(
+    writer: W,
+    skeleton: &Skeleton,
+    pose: &Pose,
+    frame: usize,
+    frame_time: f32,
+) -> Result<()> {
+    if skeleton.joint_count() != pose.joint_rotations.len() {
+        bail!(
+            "Skeleton has {} joints but the pose has {} rotations",
+            skeleton.joint_count(),
+            pose.joint_rotations.len()
+        );
+    }
+    let file = PoseFile {
+        frame,
+        frame_time,
+        root_position: pose.root_position.to_array(),
+        joints: skeleton
+            .joints
+            .iter()
+            .zip(&pose.joint_rotations)
+            .map(|(joint, rotation)| JointPose {
+                name: joint.name.clone(),
+                parent: joint.parent,
+                offset: joint.offset.to_array(),
+                rotation: rotation.to_array(),
+            })
+            .collect(),
+    };
+    serde_json::to_writer_pretty(writer, &file)?;
+    Ok(())
+}
+
+/// Reads a pose written by [`write_pose_json`]. Joint rotations are returned in file order.
+pub fn read_pose_json<R: Read>(reader: R) -> Result<(Vec<String>, Pose)> {
+    let file: PoseFile = serde_json::from_reader(reader)?;
+    let names = file.joints.iter().map(|joint| joint.name.clone()).collect();
+    let pose = Pose {
+        root_position: Vec3::from_array(file.root_position),
+        joint_rotations: file
+            .joints
+            .iter()
+            .map(|joint| Quat::from_array(joint.rotation).normalize())
+            .collect(),
+    };
+    Ok((names, pose))
+}
+
+/// A skeleton file: a pose written by [`write_pose_json`], or a skeleton sidecar written by
+/// [`crate::skeleton::write_skeleton_json`]. Poses are tried first, as only they have rotations.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SkeletonJson {
+    Pose(PoseFile),
+    Sidecar {
+        frame_time: f32,
+        joints: Vec<SkeletonJoint>,
+    },
+}
+
+/// Reads the skeleton of a pose written by [`write_pose_json`] or of a skeleton sidecar,
+/// together with its frame time. End sites are not stored in pose files.
+pub fn read_skeleton_json<R: Read>(reader: R) -> Result<(Skeleton, f32)> {
+    let file = match serde_json::from_reader(reader)? {
+        SkeletonJson::Pose(file) => file,
+        SkeletonJson::Sidecar { frame_time, joints } => {
+            return Ok((Skeleton { joints }, frame_time));
+        }
+    };
+    let joints = file
+        .joints
+        .into_iter()
+        .map(|joint| SkeletonJoint {
+            name: joint.name,
+            parent: joint.parent,
+            offset: Vec3::from_array(joint.offset),
+            end_site: None,
+        })
+        .collect();
+    Ok((Skeleton { joints }, file.frame_time))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::skeleton::write_skeleton_json;
+
+    #[test]
+    fn test_skeleton_json_reads_poses_and_sidecars() {
+        let skeleton = Skeleton {
+            joints: vec![
+                SkeletonJoint {
+                    name: "Hips".to_string(),
+                    parent: None,
+                    offset: Vec3::new(0.0, 90.0, 0.0),
+                    end_site: None,
+                },
+                SkeletonJoint {
+                    name: "Head".to_string(),
+                    parent: Some(0),
+                    offset: Vec3::new(0.0, 60.0, 0.0),
+                    end_site: Some(Vec3::new(0.0, 10.0, 0.0)),
+                },
+            ],
+        };
+        let mut sidecar = Vec::new();
+        write_skeleton_json(&mut sidecar, &skeleton, 1.0 / 30.0).unwrap();
+        let (read, frame_time) = read_skeleton_json(sidecar.as_slice()).unwrap();
+        assert_eq!(read, skeleton);
+        assert_eq!(frame_time, 1.0 / 30.0);
+
+        let pose = Pose {
+            root_position: Vec3::new(0.0, 90.0, 0.0),
+            joint_rotations: vec![Quat::IDENTITY; 2],
+        };
+        let mut json = Vec::new();
+        write_pose_json(&mut json, &skeleton, &pose, 0, 1.0 / 60.0).unwrap();
+        let (read, frame_time) = read_skeleton_json(json.as_slice()).unwrap();
+        assert_eq!(read.joints[1].offset, skeleton.joints[1].offset);
+        assert_eq!(read.joints[1].end_site, None);
+        assert_eq!(frame_time, 1.0 / 60.0);
+    }
+}
@@ -0,0 +1,310 @@
+//! Pose prior of a dataset, a cheap plausibility score for generated frames and a filter for
+//! corrupted captures. The local rotation of every joint is taken relative to its mean rotation
+//! over the dataset, as a scaled axis vector in the tangent space of the mean, and modelled by
+//! a small mixture of axis-aligned Gaussians fitted with expectation maximization. A single
+//! component is a plain per-joint Gaussian. Joints are treated as independent, so the log
+//! likelihood of a pose is the sum over its joints.
+use std::{f32::consts::TAU, fs::File, io::BufWriter, path::Path};
+
+use anyhow::{Context, Result, bail};
+use bevy_math::{Quat, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::{Animation, coverage::mean_rotation, skeleton::Skeleton};
+
+/// File name of the prior written next to the clips of a dataset.
+pub const POSE_PRIOR_FILE: &str = "pose_prior.json";
+/// Smallest variance of a component, in square radians, so joints that never move don't give
+/// an infinite likelihood.
+const MIN_VARIANCE: f32 = 1e-4;
+const EM_ITERATIONS: usize = 50;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct GaussianComponent {
+    pub weight: f32,
+    pub mean: Vec3,
+    /// Variance of every axis, in square radians.
+    pub variance: Vec3,
+}
+
+impl GaussianComponent {
+    fn log_density(&self, x: Vec3) -> f32 {
+        let d = x - self.mean;
+        let mahalanobis = (d * d / self.variance).element_sum();
+        let log_determinant = self.variance.x.ln() + self.variance.y.ln() + self.variance.z.ln();
+        -0.5 * (mahalanobis + log_determinant + 3.0 * TAU.ln())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct JointPrior {
+    pub joint: String,
+    /// Rotation the tangent vectors are taken relative to.
+    pub mean_rotation: Quat,
+    pub components: Vec<GaussianComponent>,
+}
+
+/// `rotation` relative to `mean`, as a scaled axis vector.
+pub(crate) fn tangent(mean: Quat, rotation: Quat) -> Vec3 {
+    let relative = mean.inverse() * rotation;
+    let relative = if relative.w < 0.0 {
+        -relative
+    } else {
+        relative
+    };
+    relative.to_scaled_axis()
+}
+
+/// `ln(sum(exp(values)))` without overflow.
+fn log_sum_exp(values: impl Iterator<Item = f32> + Clone) -> f32 {
+    let max = values.clone().fold(f32::NEG_INFINITY, f32::max);
+    if max == f32::NEG_INFINITY {
+        return max;
+    }
+    max + values.map(|value| (value - max).exp()).sum::<f32>().ln()
+}
+
+/// Fits `component_count` components to `samples`, deterministically.
+fn fit_mixture(samples: &[Vec3], component_count: usize) -> Vec<GaussianComponent> {
+    let count = samples.len() as f32;
+    let mean = samples.iter().copied().sum::<Vec3>() / count;
+    let variance = (samples
+        .iter()
+        .map(|&x| (x - mean) * (x - mean))
+        .sum::<Vec3>()
+        / count)
+        .max(Vec3::splat(MIN_VARIANCE));
+    if component_count == 1 {
+        return vec![GaussianComponent {
+            weight: 1.0,
+            mean,
+            variance,
+        }];
+    }
+    // Every further component starts at the sample farthest from those placed so far, so
+    // components don't start out in the same mode, where they would stay.
+    let mut means = vec![samples[0]];
+    while means.len() < component_count {
+        let distance = |x: Vec3| {
+            means
+                .iter()
+                .map(|&m| x.distance_squared(m))
+                .fold(f32::INFINITY, f32::min)
+        };
+        let farthest = samples
+            .iter()
+            .copied()
+            .max_by(|&a, &b| distance(a).total_cmp(&distance(b)))
+            .unwrap_or(mean);
+        means.push(farthest);
+    }
+    let mut components: Vec<GaussianComponent> = means
+        .into_iter()
+        .map(|mean| GaussianComponent {
+            weight: 1.0 / component_count as f32,
+            mean,
+            variance,
+        })
+        .collect();
+
+    let mut responsibilities = vec![0.0; samples.len() * component_count];
+    for _ in 0..EM_ITERATIONS {
+        for (i, &x) in samples.iter().enumerate() {
+            let row = &mut responsibilities[i * component_count..(i + 1) * component_count];
+            for (r, component) in row.iter_mut().zip(&components) {
+                *r = component.weight.ln() + component.log_density(x);
+            }
+            let total = log_sum_exp(row.iter().copied());
+            row.iter_mut().for_each(|r| *r = (*r - total).exp());
+        }
+        for (k, component) in components.iter_mut().enumerate() {
+            let weights = || responsibilities.iter().skip(k).step_by(component_count);
+            let total: f32 = weights().sum();
+            if total < 1e-6 {
+                continue;
+            }
+            let mean = weights().zip(samples).map(|(&r, &x)| r * x).sum::<Vec3>() / total;
+            let variance = weights()
+                .zip(samples)
+                .map(|(&r, &x)| r * (x - mean) * (x - mean))
+                .sum::<Vec3>()
+                / total;
+            component.weight = total / count;
+            component.mean = mean;
+            component.variance = variance.max(Vec3::splat(MIN_VARIANCE));
+        }
+    }
+    components
+}
+
+impl JointPrior {
+    pub fn log_likelihood(&self, rotation: Quat) -> f32 {
+        let x = tangent(self.mean_rotation, rotation);
+        log_sum_exp(
+            self.components
+                .iter()
+                .map(|component| component.weight.ln() + component.log_density(x)),
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PosePrior {
+    pub frame_count: usize,
+    pub joints: Vec<JointPrior>,
+}
+
+impl PosePrior {
+    /// Fits the prior of `animations`, which all have to be of `skeleton`, with
+    /// `component_count` Gaussians per joint.
+    pub fn fit(
+        skeleton: &Skeleton,
+        animations: &[Animation],
+        component_count: usize,
+    ) -> Result<Self> {
+        if component_count == 0 {
+            bail!("The prior needs at least one component");
+        }
+        if let Some(animation) = animations
+            .iter()
+            .find(|animation| animation.joint_count() != skeleton.joint_count())
+        {
+            bail!(
+                "An animation has {} joints, the skeleton {}",
+                animation.joint_count(),
+                skeleton.joint_count()
+            );
+        }
+        let frame_count: usize = animations.iter().map(Animation::frame_count).sum();
+        if frame_count < component_count {
+            bail!(
+                "{} frames are too few to fit {} components",
+                frame_count,
+                component_count
+            );
+        }
+
+        let joints = skeleton
+            .joint_order()
+            .enumerate()
+            .map(|(joint, name)| {
+                let rotations: Vec<Quat> = animations
+                    .iter()
+                    .flat_map(|animation| animation.joint_rotations[joint].iter().copied())
+                    .collect();
+                let mean = mean_rotation(&rotations);
+                let samples: Vec<Vec3> = rotations.iter().map(|&q| tangent(mean, q)).collect();
+                JointPrior {
+                    joint: name.to_string(),
+                    mean_rotation: mean,
+                    components: fit_mixture(&samples, component_count),
+                }
+            })
+            .collect();
+        Ok(PosePrior {
+            frame_count,
+            joints,
+        })
+    }
+
+    /// Log likelihood of every frame of `animation`, whose joints are matched to the prior by
+    /// name through `skeleton`.
+    pub fn score(&self, skeleton: &Skeleton, animation: &Animation) -> Result<Vec<f32>> {
+        let joints = self
+            .joints
+            .iter()
+            .map(|prior| {
+                skeleton
+                    .joint_order()
+                    .position(|name| name == prior.joint)
+                    .filter(|&joint| joint < animation.joint_count())
+                    .with_context(|| format!("The clip has no joint {}", prior.joint))
+            })
+            .collect::<Result<Vec<usize>>>()?;
+        Ok((0..animation.frame_count())
+            .map(|frame| {
+                self.joints
+                    .iter()
+                    .zip(&joints)
+                    .map(|(prior, &joint)| {
+                        prior.log_likelihood(animation.joint_rotations[joint][frame])
+                    })
+                    .sum()
+            })
+            .collect())
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("Could not open {}", path.display()))?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), self)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::slice;
+
+    use super::*;
+    use crate::skeleton::SkeletonJoint;
+
+    #[test]
+    fn test_implausible_poses_score_lower() {
+        let joint = |name: &str, parent| SkeletonJoint {
+            name: name.to_string(),
+            parent,
+            offset: Vec3::Y,
+            end_site: None,
+        };
+        let skeleton = Skeleton {
+            joints: vec![joint("Hips", None), joint("Knee", Some(0))],
+        };
+        // The knee is either straight or bent at 90 degrees, never in between.
+        let frames = 100;
+        let knee = (0..frames)
+            .map(|f| {
+                let angle = if f % 2 == 0 { 0.0 } else { 90.0f32 };
+                Quat::from_rotation_x((angle + (f % 7) as f32).to_radians())
+            })
+            .collect();
+        let animation = Animation {
+            root_positions: vec![Vec3::ZERO; frames],
+            joint_rotations: vec![vec![Quat::IDENTITY; frames], knee],
+            events: vec![],
+        };
+        let gaussian = PosePrior::fit(&skeleton, slice::from_ref(&animation), 1).unwrap();
+        let mixture = PosePrior::fit(&skeleton, slice::from_ref(&animation), 2).unwrap();
+        let weights: Vec<f32> = mixture.joints[1]
+            .components
+            .iter()
+            .map(|c| c.weight)
+            .collect();
+        assert!(
+            weights.iter().all(|w| (w - 0.5).abs() < 0.05),
+            "{:?}",
+            weights
+        );
+
+        let mut test = animation;
+        test.joint_rotations[1] = [0.0f32, 45.0, 90.0, 180.0]
+            .map(|angle| Quat::from_rotation_x(angle.to_radians()))
+            .to_vec();
+        test.joint_rotations[0].truncate(4);
+        test.root_positions.truncate(4);
+        let scores = mixture.score(&skeleton, &test).unwrap();
+        // Halfway between the modes is plausible to a single Gaussian, not to the mixture.
+        assert!(scores[0] > scores[1] && scores[2] > scores[1]);
+        assert!(scores[3] < scores[1]);
+        assert!(gaussian.score(&skeleton, &test).unwrap()[1] > scores[1]);
+
+        let other = Skeleton {
+            joints: vec![joint("Hips", None)],
+        };
+        assert!(mixture.score(&other, &test).is_err());
+    }
+}
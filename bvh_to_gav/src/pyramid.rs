@@ -0,0 +1,133 @@
+//! Temporal pyramid of a clip, for coarse-to-fine generation models: the clip at its own frame
+//! rate and at half of it for every next level, say 30, 15 and 7.5 fps, in one `.pyramid.npz`
+//! archive:
+//!
+//! - `level_<n>`: the GAV tensor of level `n`, see [`crate::animation_to_gav`]
+//! - `fps`: f32 frame rate of every level, not rounded, as 7.5 fps is a level too
+//!
+//! Every level is derived from the clip itself the same way, low-pass filtered at half its
+//! frame rate, see [`crate::smoothing`], then resampled, so frame `i` of level `n` is at the
+//! time of frame `i * 2^n` of level 0. Frames past the last such frame are dropped, so every
+//! level ends on the same time. The skeleton sidecar is written next to the archive.
+use std::io::{Seek, Write};
+
+use anyhow::{Result, bail};
+use ndarray::Array1;
+use ndarray_npy::NpzWriter;
+
+use crate::{
+    Animation, animation_to_gav,
+    skeleton::Skeleton,
+    smoothing::{Smoothing, smooth},
+};
+
+pub const PYRAMID_EXTENSION: &str = "pyramid.npz";
+pub const FPS: &str = "fps";
+
+/// Name of the tensor of `level` in the archive.
+pub fn level_name(level: usize) -> String {
+    format!("level_{}", level)
+}
+
+/// The `levels` levels of `animation`, each with its frame time. Events are not carried over.
+pub fn pyramid_levels(
+    skeleton: &Skeleton,
+    animation: &Animation,
+    frame_time: f32,
+    levels: usize,
+) -> Result<Vec<(Animation, f32)>> {
+    if levels == 0 {
+        bail!("A pyramid needs at least one level");
+    }
+    let stride = 1 << (levels - 1);
+    if animation.frame_count() <= stride {
+        bail!(
+            "{} frames are too few for {} levels",
+            animation.frame_count(),
+            levels
+        );
+    }
+    // Every level ends on the last frame of the coarsest one.
+    let duration = (animation.frame_count() - 1) / stride * stride;
+    (0..levels)
+        .map(|level| {
+            let step = 1 << level;
+            let mut filtered = animation.clone();
+            if level > 0 {
+                let level_frame_time = frame_time * step as f32;
+                let cutoff_hz = 0.5 / level_frame_time;
+                smooth(
+                    &mut filtered,
+                    skeleton,
+                    frame_time,
+                    Smoothing::LowPass { cutoff_hz },
+                    &[],
+                )?;
+            }
+            let resampled =
+                filtered.resample((0..=duration / step).map(|frame| (frame * step) as f32));
+            Ok((resampled, frame_time * step as f32))
+        })
+        .collect()
+}
+
+/// Writes the `levels` level pyramid of `animation` as a compressed `.npz` archive.
+pub fn write_pyramid<W: Write + Seek>(
+    writer: W,
+    skeleton: &Skeleton,
+    animation: &Animation,
+    frame_time: f32,
+    levels: usize,
+) -> Result<()> {
+    let pyramid = pyramid_levels(skeleton, animation, frame_time, levels)?;
+    let mut npz = NpzWriter::new_compressed(writer);
+    for (level, (animation, _)) in pyramid.iter().enumerate() {
+        npz.add_array(level_name(level), &animation_to_gav(animation)?)?;
+    }
+    let rates: Array1<f32> = pyramid
+        .iter()
+        .map(|(_, frame_time)| 1.0 / frame_time)
+        .collect();
+    npz.add_array(FPS, &rates)?;
+    npz.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy_math::{Quat, Vec3};
+
+    use crate::skeleton::SkeletonJoint;
+
+    #[test]
+    fn test_pyramid_levels_are_aligned() {
+        let skeleton = Skeleton {
+            joints: vec![SkeletonJoint {
+                name: "Hips".to_string(),
+                parent: None,
+                offset: Vec3::ZERO,
+                end_site: None,
+            }],
+        };
+        let frame_count = 62;
+        let rotation = Quat::from_rotation_y(0.5);
+        let animation = Animation {
+            root_positions: vec![Vec3::new(0.0, 90.0, 0.0); frame_count],
+            joint_rotations: vec![vec![rotation; frame_count]],
+            events: Vec::new(),
+        };
+        let levels = pyramid_levels(&skeleton, &animation, 1.0 / 30.0, 3).unwrap();
+        let frame_counts: Vec<usize> = levels.iter().map(|(a, _)| a.frame_count()).collect();
+        // 61 frames is as far as the 4 frame stride of the coarsest level reaches.
+        assert_eq!(frame_counts, [61, 31, 16]);
+        for ((_, frame_time), fps) in levels.iter().zip([30.0, 15.0, 7.5]) {
+            assert!((1.0 / frame_time - fps).abs() < 1e-3);
+        }
+        for (level, _) in &levels {
+            assert!(level.root_positions[5].distance(Vec3::new(0.0, 90.0, 0.0)) < 1e-4);
+            assert!(level.joint_rotations[0][5].angle_between(rotation) < 1e-4);
+        }
+        assert!(pyramid_levels(&skeleton, &animation, 1.0 / 30.0, 7).is_err());
+    }
+}
@@ -0,0 +1,175 @@
+//! Quantized GAV tensors, to shrink datasets of many gigabytes. Every channel, one component of
+//! one curve, is stored as integer levels of [`Dtype::I16`] or [`Dtype::I8`] with
+//! `value = offset + scale * level`, the levels spanning the range of the channel in the clip.
+//! The offsets and scales are saved next to the tensor as `<clip>.quant.json`, and
+//! [`read_tensor_file`] and [`crate::load_gav`] scale the levels back on load.
+//!
+//! Quantizing rounds every value by at most half a scale, so an i16 channel keeps about five
+//! significant digits and an i8 channel about two.
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use ndarray::{Array3, Axis};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dtype::{Dtype, read_typed_tensor, write_tensor},
+    storage,
+};
+
+pub const QUANTIZATION_EXTENSION: &str = "quant.json";
+
+/// Path of the quantization sidecar of a tensor.
+pub fn quantization_path(tensor: &Path) -> PathBuf {
+    tensor.with_extension(QUANTIZATION_EXTENSION)
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Quantization {
+    /// Value of level zero, per curve.
+    pub offset: Vec<[f32; 3]>,
+    /// Value of one level, per curve.
+    pub scale: Vec<[f32; 3]>,
+}
+
+impl Quantization {
+    /// Offsets and scales that fit every channel of `gav_data` into the levels of `dtype`.
+    pub fn fit(gav_data: &Array3<f32>, dtype: Dtype) -> Result<Self> {
+        let Some(max_level) = dtype.max_level() else {
+            bail!("{} tensors aren't quantized", dtype);
+        };
+        let mut quantization = Quantization::default();
+        for frames in gav_data.axis_iter(Axis(0)) {
+            let mut offset = [0.0; 3];
+            let mut scale = [1.0; 3];
+            for (channel, values) in frames.axis_iter(Axis(1)).enumerate() {
+                let (min, max) = values
+                    .iter()
+                    .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &x| {
+                        (min.min(x), max.max(x))
+                    });
+                if min > max {
+                    continue;
+                }
+                offset[channel] = (min + max) / 2.0;
+                if max > min {
+                    scale[channel] = (max - min) / 2.0 / max_level;
+                }
+            }
+            quantization.offset.push(offset);
+            quantization.scale.push(scale);
+        }
+        Ok(quantization)
+    }
+
+    fn check(&self, gav_data: &Array3<f32>) -> Result<()> {
+        if gav_data.dim().0 != self.offset.len() {
+            bail!(
+                "The tensor has {} curves but the quantization {}",
+                gav_data.dim().0,
+                self.offset.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// Levels of the values in `gav_data`.
+    pub fn quantize(&self, gav_data: &Array3<f32>) -> Result<Array3<f32>> {
+        self.check(gav_data)?;
+        let mut levels = gav_data.clone();
+        for (curve, mut frames) in levels.axis_iter_mut(Axis(0)).enumerate() {
+            for mut frame in frames.outer_iter_mut() {
+                for (channel, value) in frame.iter_mut().enumerate() {
+                    *value = ((*value - self.offset[curve][channel]) / self.scale[curve][channel])
+                        .round();
+                }
+            }
+        }
+        Ok(levels)
+    }
+
+    /// Undoes [`Quantization::quantize`], up to the rounding.
+    pub fn dequantize(&self, levels: &mut Array3<f32>) -> Result<()> {
+        self.check(levels)?;
+        for (curve, mut frames) in levels.axis_iter_mut(Axis(0)).enumerate() {
+            for mut frame in frames.outer_iter_mut() {
+                for (channel, value) in frame.iter_mut().enumerate() {
+                    *value = *value * self.scale[curve][channel] + self.offset[curve][channel];
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(serde_json::from_reader(storage::open(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        storage::write_with(path, |writer| {
+            Ok(serde_json::to_writer_pretty(writer, self)?)
+        })
+    }
+}
+
+/// Writes `gav_data` as a tensor of `dtype` at `path`, quantized with a sidecar for the
+/// integer types.
+pub fn write_tensor_file(path: &Path, gav_data: &Array3<f32>, dtype: Dtype) -> Result<()> {
+    if dtype.max_level().is_none() {
+        return storage::write_with(path, |writer| write_tensor(writer, gav_data, dtype));
+    }
+    let quantization = Quantization::fit(gav_data, dtype)?;
+    let levels = quantization.quantize(gav_data)?;
+    storage::write_with(path, |writer| write_tensor(writer, &levels, dtype))?;
+    quantization.save(&quantization_path(path))
+}
+
+/// Scales the levels of a tensor read from `path` back to values with its sidecar, when `dtype`
+/// is quantized.
+pub fn dequantize_tensor(path: &Path, levels: &mut Array3<f32>, dtype: Dtype) -> Result<()> {
+    if dtype.max_level().is_none() {
+        return Ok(());
+    }
+    let sidecar = quantization_path(path);
+    Quantization::load(&sidecar)
+        .with_context(|| format!("Could not read the quantization {}", sidecar.display()))?
+        .dequantize(levels)
+}
+
+/// Reads the tensor at `path` as values, whatever its [`Dtype`], and returns its dtype with it.
+pub fn read_tensor_file(path: &Path) -> Result<(Array3<f32>, Dtype)> {
+    let (mut data, dtype) = read_typed_tensor(storage::open(path)?)?;
+    dequantize_tensor(path, &mut data, dtype)?;
+    Ok((data, dtype))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quantized_tensors_round_trip_within_half_a_level() {
+        let data = Array3::from_shape_fn((2, 50, 3), |(curve, frame, channel)| {
+            if curve == 1 {
+                // A constant channel keeps its value exactly.
+                7.5
+            } else {
+                (frame as f32 * 0.37 + channel as f32).sin() * 100.0
+            }
+        });
+        for dtype in [Dtype::I16, Dtype::I8] {
+            let quantization = Quantization::fit(&data, dtype).unwrap();
+            let mut levels = quantization.quantize(&data).unwrap();
+            let max_level = dtype.max_level().unwrap();
+            assert!(levels.iter().all(|level| level.abs() <= max_level));
+
+            quantization.dequantize(&mut levels).unwrap();
+            let tolerance = 100.0 / max_level * 0.51;
+            for (value, expected) in levels.iter().zip(&data) {
+                assert!((value - expected).abs() <= tolerance);
+            }
+            assert!(levels.index_axis(Axis(0), 1).iter().all(|&x| x == 7.5));
+        }
+        assert!(Quantization::fit(&data, Dtype::F16).is_err());
+    }
+}
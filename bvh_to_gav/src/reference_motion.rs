@@ -0,0 +1,359 @@
+//! Reference motion for physics-based character controllers, in the formats RL locomotion
+//! frameworks read directly:
+//!
+//! - DeepMimic motion JSON, `{"Loop": "wrap" | "none", "Frames": [...]}`, for its `humanoid3d`
+//!   character. Every frame is its duration, the root position in meters, then the rotation of
+//!   every joint relative to its parent as a w, x, y, z quaternion, except knees and elbows,
+//!   which are a single angle in radians about their Z axis. The joints of a clip are found by
+//!   name, see [`DEEPMIMIC_JOINTS`], and joints in between are folded into the rotation of the
+//!   next one found. The rest pose of the clip isn't retargeted, so it has to match the T-pose
+//!   of the character.
+//! - The npz layout of the LaFAN1 extraction scripts: `X`, the local joint positions (the root
+//!   position for roots, the offset for every other joint), of shape (frames, joints, 3), `Q`,
+//!   the local rotations as w, x, y, z of shape (frames, joints, 4), `parents` with -1 for
+//!   roots, `fps`, and `names` as in [`crate::npz`].
+use std::{
+    fmt,
+    io::{Seek, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use anyhow::{Context, Result, bail};
+use bevy_math::Quat;
+use ndarray::{Array0, Array1, Array3};
+use ndarray_npy::NpzWriter;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Animation, canonical_rotation, frame_rate::frame_rate, kinematics::global_transforms,
+    skeleton::Skeleton, storage,
+};
+
+/// A reference motion format.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReferenceFormat {
+    /// DeepMimic motion JSON.
+    #[default]
+    DeepMimic,
+    /// LaFAN1 npz arrays.
+    Lafan,
+}
+
+impl ReferenceFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ReferenceFormat::DeepMimic => "json",
+            ReferenceFormat::Lafan => "npz",
+        }
+    }
+}
+
+impl FromStr for ReferenceFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "deepmimic" => Ok(ReferenceFormat::DeepMimic),
+            "lafan" => Ok(ReferenceFormat::Lafan),
+            _ => bail!(
+                "Unknown reference format {}, expected deepmimic or lafan",
+                name
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ReferenceFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ReferenceFormat::DeepMimic => "deepmimic",
+            ReferenceFormat::Lafan => "lafan",
+        })
+    }
+}
+
+/// A joint of the DeepMimic humanoid, with its parent among them.
+pub struct DeepMimicJoint {
+    pub name: &'static str,
+    pub parent: Option<usize>,
+    /// Rotates about its Z axis only.
+    pub revolute: bool,
+    /// Names of the joint in common skeletons, lower case without separators, in order of
+    /// preference.
+    pub aliases: &'static [&'static str],
+}
+
+const fn joint(
+    name: &'static str,
+    parent: Option<usize>,
+    revolute: bool,
+    aliases: &'static [&'static str],
+) -> DeepMimicJoint {
+    DeepMimicJoint {
+        name,
+        parent,
+        revolute,
+        aliases,
+    }
+}
+
+/// Joints of the DeepMimic `humanoid3d` character, in the order of its motion frames.
+pub const DEEPMIMIC_JOINTS: [DeepMimicJoint; 13] = [
+    joint("root", None, false, &["root", "hips", "pelvis"]),
+    joint(
+        "chest",
+        Some(0),
+        false,
+        &["chest", "spine2", "spine1", "spine"],
+    ),
+    joint("neck", Some(1), false, &["neck", "neck1"]),
+    joint(
+        "right_hip",
+        Some(0),
+        false,
+        &["righthip", "rightupleg", "rightthigh", "rthigh"],
+    ),
+    joint(
+        "right_knee",
+        Some(3),
+        true,
+        &["rightknee", "rightleg", "rightshin", "rshin"],
+    ),
+    joint(
+        "right_ankle",
+        Some(4),
+        false,
+        &["rightankle", "rightfoot", "rfoot"],
+    ),
+    joint(
+        "right_shoulder",
+        Some(1),
+        false,
+        &["rightshoulder", "rightarm", "rightupperarm"],
+    ),
+    joint(
+        "right_elbow",
+        Some(6),
+        true,
+        &["rightelbow", "rightforearm", "rightlowerarm"],
+    ),
+    joint(
+        "left_hip",
+        Some(0),
+        false,
+        &["lefthip", "leftupleg", "leftthigh", "lthigh"],
+    ),
+    joint(
+        "left_knee",
+        Some(8),
+        true,
+        &["leftknee", "leftleg", "leftshin", "lshin"],
+    ),
+    joint(
+        "left_ankle",
+        Some(9),
+        false,
+        &["leftankle", "leftfoot", "lfoot"],
+    ),
+    joint(
+        "left_shoulder",
+        Some(1),
+        false,
+        &["leftshoulder", "leftarm", "leftupperarm"],
+    ),
+    joint(
+        "left_elbow",
+        Some(11),
+        true,
+        &["leftelbow", "leftforearm", "leftlowerarm"],
+    ),
+];
+
+/// `name` without a namespace such as `mixamorig:`, lower case and without separators.
+pub(crate) fn normalize_name(name: &str) -> String {
+    let name = name.rsplit(':').next().unwrap_or(name);
+    name.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Index in `skeleton` of every joint of [`DEEPMIMIC_JOINTS`].
+pub fn deepmimic_joints(skeleton: &Skeleton) -> Result<Vec<usize>> {
+    let names: Vec<String> = skeleton.joint_order().map(normalize_name).collect();
+    DEEPMIMIC_JOINTS
+        .iter()
+        .map(|joint| {
+            joint
+                .aliases
+                .iter()
+                .find_map(|alias| names.iter().position(|name| name == alias))
+                .with_context(|| {
+                    format!(
+                        "No joint for the DeepMimic {}, expected one named {}",
+                        joint.name,
+                        joint.aliases.join(", ")
+                    )
+                })
+        })
+        .collect()
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DeepMimicMotion {
+    #[serde(rename = "Loop")]
+    pub loop_mode: String,
+    #[serde(rename = "Frames")]
+    pub frames: Vec<Vec<f32>>,
+}
+
+impl DeepMimicMotion {
+    /// Motion of `animation` on `skeleton`, whose lengths are in centimeters.
+    pub fn new(
+        skeleton: &Skeleton,
+        animation: &Animation,
+        frame_time: f32,
+        looping: bool,
+    ) -> Result<Self> {
+        let joints = deepmimic_joints(skeleton)?;
+        let frames = (0..animation.frame_count())
+            .map(|frame| {
+                let transforms = global_transforms(skeleton, animation, frame);
+                let (root_position, _) = transforms[joints[0]];
+                let mut values = vec![frame_time];
+                values.extend((root_position * 0.01).to_array());
+                for (deepmimic, &joint) in DEEPMIMIC_JOINTS.iter().zip(&joints) {
+                    let parent_rotation = deepmimic
+                        .parent
+                        .map_or(Quat::IDENTITY, |p| transforms[joints[p]].1);
+                    let local = canonical_rotation(parent_rotation.inverse() * transforms[joint].1);
+                    if deepmimic.revolute {
+                        // The angle of the twist about Z.
+                        values.push(2.0 * local.z.atan2(local.w));
+                    } else {
+                        values.extend([local.w, local.x, local.y, local.z]);
+                    }
+                }
+                values
+            })
+            .collect();
+        Ok(DeepMimicMotion {
+            loop_mode: if looping { "wrap" } else { "none" }.to_string(),
+            frames,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        storage::write_with(path, |writer| Ok(serde_json::to_writer(writer, self)?))
+    }
+}
+
+/// Writes `animation` of `skeleton` in the LaFAN1 npz layout.
+pub fn write_lafan_npz<W: Write + Seek>(
+    writer: W,
+    skeleton: &Skeleton,
+    animation: &Animation,
+    frame_time: f32,
+) -> Result<()> {
+    if animation.joint_count() != skeleton.joint_count() {
+        bail!(
+            "The animation has {} joints but the skeleton {}",
+            animation.joint_count(),
+            skeleton.joint_count()
+        );
+    }
+    let (frame_count, joint_count) = (animation.frame_count(), skeleton.joint_count());
+    let positions =
+        Array3::from_shape_fn(
+            (frame_count, joint_count, 3),
+            |(frame, joint, axis)| match skeleton.joints[joint].parent {
+                Some(_) => skeleton.joints[joint].offset[axis],
+                None => animation.root_positions[frame][axis],
+            },
+        );
+    let rotations = Array3::from_shape_fn((frame_count, joint_count, 4), |(frame, joint, axis)| {
+        let q = animation.joint_rotations[joint][frame];
+        [q.w, q.x, q.y, q.z][axis]
+    });
+    let parents: Array1<i64> = skeleton
+        .joints
+        .iter()
+        .map(|joint| joint.parent.map_or(-1, |parent| parent as i64))
+        .collect();
+    let names: Array1<u8> = skeleton
+        .joint_order()
+        .flat_map(|name| name.bytes().chain([b'\n']))
+        .collect();
+
+    let mut npz = NpzWriter::new_compressed(writer);
+    npz.add_array("X", &positions)?;
+    npz.add_array("Q", &rotations)?;
+    npz.add_array("parents", &parents)?;
+    npz.add_array("fps", &Array0::from_elem((), frame_rate(frame_time)))?;
+    npz.add_array("names", &names)?;
+    npz.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_math::Vec3;
+
+    use super::*;
+    use crate::skeleton::SkeletonJoint;
+
+    #[test]
+    fn test_deepmimic_frames_match_the_humanoid() {
+        let names = [
+            ("mixamorig:Hips", None),
+            ("Spine", Some(0)),
+            ("Spine1", Some(1)),
+            ("Neck", Some(2)),
+            ("RightUpLeg", Some(0)),
+            ("RightLeg", Some(4)),
+            ("RightFoot", Some(5)),
+            ("RightArm", Some(2)),
+            ("RightForeArm", Some(7)),
+            ("LeftUpLeg", Some(0)),
+            ("LeftLeg", Some(9)),
+            ("LeftFoot", Some(10)),
+            ("LeftArm", Some(2)),
+            ("LeftForeArm", Some(12)),
+        ];
+        let skeleton = Skeleton {
+            joints: names
+                .iter()
+                .map(|&(name, parent)| SkeletonJoint {
+                    name: name.to_string(),
+                    parent,
+                    offset: Vec3::Y,
+                    end_site: None,
+                })
+                .collect(),
+        };
+        let mut joint_rotations = vec![vec![Quat::IDENTITY]; names.len()];
+        // Spine1 is the chest, so the rotation of Spine is folded into it.
+        joint_rotations[1][0] = Quat::from_rotation_y(0.25);
+        joint_rotations[2][0] = Quat::from_rotation_y(0.5);
+        joint_rotations[5][0] = Quat::from_rotation_z(-0.75);
+        let animation = Animation {
+            root_positions: vec![Vec3::new(0.0, 90.0, 0.0)],
+            joint_rotations,
+            events: vec![],
+        };
+        let motion = DeepMimicMotion::new(&skeleton, &animation, 1.0 / 30.0, true).unwrap();
+        assert_eq!(motion.loop_mode, "wrap");
+        let frame = &motion.frames[0];
+        assert_eq!(frame.len(), 44);
+        assert_eq!(frame[1..4], [0.0, 0.9, 0.0]);
+        let chest = Quat::from_xyzw(frame[9], frame[10], frame[11], frame[8]);
+        assert!(chest.angle_between(Quat::from_rotation_y(0.75)) < 1e-3);
+        // After root (7), chest, neck and right hip (4 each).
+        assert!((frame[1 + 7 + 12] + 0.75).abs() < 1e-5);
+
+        let mut missing = skeleton.clone();
+        missing.joints[3].name = "Head".to_string();
+        assert!(DeepMimicMotion::new(&missing, &animation, 1.0 / 30.0, false).is_err());
+    }
+}
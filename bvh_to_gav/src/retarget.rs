@@ -0,0 +1,248 @@
+//! Retargeting of an [`Animation`] to another skeleton, so one dataset can be built from captures
+//! of different rigs. Target joints are mapped to source joints by name, in a TOML file:
+//!
+//! ```toml
+//! [bones]
+//! # Target joint = source joint
+//! Hips = "mixamorig:Hips"
+//! LeftUpLeg = "mixamorig:LeftUpLeg"
+//! ```
+//!
+//! Every mapped bone of the target points in the same world direction as its source bone. The
+//! rest poses may differ, such as a T-pose and an A-pose, so each bone is first turned from its
+//! target rest direction onto the source one; twist about the bone is taken from the source as
+//! is. Target joints without a source keep their rest pose relative to their parent, and the
+//! root moves with the source root, scaled by the ratio of the hip heights.
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::{Context, Result, bail};
+use bevy_math::{Quat, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Animation,
+    kinematics::{global_transforms, rest_positions},
+    skeleton::Skeleton,
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Retargeting {
+    /// Source joint of each target joint, by name.
+    pub bones: BTreeMap<String, String>,
+}
+
+/// Height of the root above the lowest joint in the rest pose.
+fn hip_height(skeleton: &Skeleton) -> f32 {
+    let rest = rest_positions(skeleton);
+    let lowest = rest.iter().map(|p| p.y).fold(f32::INFINITY, f32::min);
+    skeleton
+        .roots()
+        .next()
+        .map_or(0.0, |root| rest[root].y - lowest)
+}
+
+impl Retargeting {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)?;
+        toml::from_str(&text).with_context(|| format!("Invalid {}", path.display()))
+    }
+
+    /// Maps every target joint to the source joint of the same name.
+    pub fn by_name(source: &Skeleton, target: &Skeleton) -> Self {
+        let bones = target
+            .joint_order()
+            .filter(|name| source.find(name).is_some())
+            .map(|name| (name.to_string(), name.to_string()))
+            .collect();
+        Retargeting { bones }
+    }
+
+    /// Source joint index of every target joint.
+    fn source_joints(&self, source: &Skeleton, target: &Skeleton) -> Result<Vec<Option<usize>>> {
+        for (target_name, source_name) in &self.bones {
+            if target.find(target_name).is_none() {
+                bail!("The target skeleton has no joint called {}", target_name);
+            }
+            if source.find(source_name).is_none() {
+                bail!("The source skeleton has no joint called {}", source_name);
+            }
+        }
+        Ok(target
+            .joint_order()
+            .map(|name| self.bones.get(name).and_then(|name| source.find(name)))
+            .collect())
+    }
+
+    /// Retargets `animation` of the `source` skeleton to the `target` skeleton.
+    pub fn retarget(
+        &self,
+        source: &Skeleton,
+        animation: &Animation,
+        target: &Skeleton,
+    ) -> Result<Animation> {
+        if animation.joint_count() != source.joint_count() {
+            bail!(
+                "The animation has {} joints but the source skeleton {}",
+                animation.joint_count(),
+                source.joint_count()
+            );
+        }
+        let source_joints = self.source_joints(source, target)?;
+        let target_root = target
+            .roots()
+            .next()
+            .context("The target skeleton is empty")?;
+        let source_root = source_joints[target_root].with_context(|| {
+            format!(
+                "The target root {} is not mapped",
+                target.joints[target_root].name
+            )
+        })?;
+
+        // Rotation turning each mapped target bone onto its source bone in the rest pose. A bone
+        // runs from a joint to its first mapped child.
+        let source_rest = rest_positions(source);
+        let target_rest = rest_positions(target);
+        let alignments: Vec<Quat> = (0..target.joint_count())
+            .map(|joint| {
+                let child = target
+                    .children(joint)
+                    .find(|&child| source_joints[child].is_some());
+                match (
+                    source_joints[joint],
+                    child.and_then(|c| Some((c, source_joints[c]?))),
+                ) {
+                    (Some(source_joint), Some((child, source_child))) => {
+                        let target_bone = target_rest[child] - target_rest[joint];
+                        let source_bone = source_rest[source_child] - source_rest[source_joint];
+                        if target_bone.length() > f32::EPSILON
+                            && source_bone.length() > f32::EPSILON
+                        {
+                            Quat::from_rotation_arc(
+                                target_bone.normalize(),
+                                source_bone.normalize(),
+                            )
+                        } else {
+                            Quat::IDENTITY
+                        }
+                    }
+                    _ => Quat::IDENTITY,
+                }
+            })
+            .collect();
+
+        let source_height = hip_height(source);
+        let scale = if source_height > f32::EPSILON {
+            hip_height(target) / source_height
+        } else {
+            1.0
+        };
+
+        let order = target.depth_first_order();
+        let mut retargeted = Animation {
+            root_positions: Vec::with_capacity(animation.frame_count()),
+            joint_rotations: vec![
+                Vec::with_capacity(animation.frame_count());
+                target.joint_count()
+            ],
+            events: animation.events.clone(),
+        };
+        let mut globals = vec![Quat::IDENTITY; target.joint_count()];
+        for frame in 0..animation.frame_count() {
+            let source_globals = global_transforms(source, animation, frame);
+            for &joint in &order {
+                let parent = target.joints[joint]
+                    .parent
+                    .map_or(Quat::IDENTITY, |parent| globals[parent]);
+                globals[joint] = match source_joints[joint] {
+                    Some(source_joint) => source_globals[source_joint].1 * alignments[joint],
+                    None => parent,
+                };
+                retargeted.joint_rotations[joint].push(parent.inverse() * globals[joint]);
+            }
+            let root_position: Vec3 = source_globals[source_root].0;
+            retargeted.root_positions.push(root_position * scale);
+        }
+        Ok(retargeted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+    use crate::{kinematics::global_positions, skeleton::SkeletonJoint};
+
+    #[test]
+    fn test_retarget_keeps_bone_directions() {
+        let joint = |name: &str, parent, offset| SkeletonJoint {
+            name: name.to_string(),
+            parent,
+            offset,
+            end_site: None,
+        };
+        // The source arm points along +X in the rest pose, the target arm down, and the target
+        // has an extra shoulder joint and is twice as tall.
+        let source = Skeleton {
+            joints: vec![
+                joint("Hips", None, Vec3::ZERO),
+                joint("LeftFoot", Some(0), Vec3::Y * -10.0),
+                joint("LeftArm", Some(0), Vec3::Y * 5.0),
+                joint("LeftHand", Some(2), Vec3::X * 4.0),
+            ],
+        };
+        let target = Skeleton {
+            joints: vec![
+                joint("root", None, Vec3::ZERO),
+                joint("foot_l", Some(0), Vec3::Y * -20.0),
+                joint("shoulder_l", Some(0), Vec3::Y * 10.0),
+                joint("arm_l", Some(2), Vec3::X * 2.0),
+                joint("hand_l", Some(3), Vec3::Y * -8.0),
+            ],
+        };
+        let map: Retargeting = toml::from_str(
+            r#"
+            [bones]
+            root = "Hips"
+            foot_l = "LeftFoot"
+            arm_l = "LeftArm"
+            hand_l = "LeftHand"
+            "#,
+        )
+        .unwrap();
+
+        // The source raises its arm straight up.
+        let animation = Animation {
+            root_positions: vec![Vec3::new(1.0, 10.0, 0.0)],
+            joint_rotations: vec![
+                vec![Quat::IDENTITY],
+                vec![Quat::IDENTITY],
+                vec![Quat::from_rotation_z(FRAC_PI_2)],
+                vec![Quat::IDENTITY],
+            ],
+            events: vec![],
+        };
+        let retargeted = map.retarget(&source, &animation, &target).unwrap();
+        assert_eq!(retargeted.joint_count(), 5);
+        assert!(retargeted.root_positions[0].distance(Vec3::new(2.0, 20.0, 0.0)) < 1e-4);
+
+        let positions = global_positions(&target, &retargeted).unwrap();
+        let position = |joint: usize| {
+            Vec3::new(
+                positions[[joint, 0, 0]],
+                positions[[joint, 0, 1]],
+                positions[[joint, 0, 2]],
+            )
+        };
+        let arm = (position(4) - position(3)).normalize();
+        assert!(arm.distance(Vec3::Y) < 1e-4);
+
+        let unknown = Retargeting {
+            bones: BTreeMap::from([("tail".to_string(), "Hips".to_string())]),
+        };
+        assert!(unknown.retarget(&source, &animation, &target).is_err());
+        assert_eq!(Retargeting::by_name(&source, &source).bones.len(), 4);
+    }
+}
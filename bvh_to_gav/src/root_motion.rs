@@ -0,0 +1,143 @@
+//! Root motion extraction for locomotion models. The ground plane translation and the heading
+//! of the root are split off into a trajectory, and the pose is kept in place: the root stays
+//! above the origin, facing forward, at its own height and with its own tilt. The trajectory
+//! is stored as one extra GAV curve after the joints, `(x, z, yaw)` per frame with the yaw in
+//! radians about +Y, unwrapped so it never jumps by a full turn.
+use std::f32::consts::{PI, TAU};
+
+use anyhow::{Result, bail};
+use bevy_math::{Quat, Vec3};
+use ndarray::{Array3, Axis};
+
+use crate::Animation;
+
+/// Rotation of `rotation` about +Y, from its swing twist decomposition.
+pub fn yaw(rotation: Quat) -> f32 {
+    2.0 * rotation.y.atan2(rotation.w)
+}
+
+/// Wraps an angle to `[-PI, PI)`.
+fn wrap_angle(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(TAU) - PI
+}
+
+/// Splits `animation` into an in-place animation and its trajectory.
+pub fn extract_root_motion(animation: &Animation) -> (Animation, Vec<Vec3>) {
+    let root_rotations = animation.joint_rotations.first();
+    let mut trajectory = Vec::with_capacity(animation.frame_count());
+    let mut heading = 0.0;
+    for (frame, position) in animation.root_positions.iter().enumerate() {
+        let frame_yaw = root_rotations.map_or(0.0, |rotations| yaw(rotations[frame]));
+        heading = if frame == 0 {
+            frame_yaw
+        } else {
+            heading + wrap_angle(frame_yaw - heading)
+        };
+        trajectory.push(Vec3::new(position.x, position.z, heading));
+    }
+
+    let mut in_place = Animation {
+        root_positions: animation
+            .root_positions
+            .iter()
+            .map(|p| Vec3::new(0.0, p.y, 0.0))
+            .collect(),
+        joint_rotations: animation.joint_rotations.clone(),
+        events: animation.events.clone(),
+    };
+    if let Some(root) = in_place.joint_rotations.first_mut() {
+        for (rotation, step) in root.iter_mut().zip(&trajectory) {
+            *rotation = Quat::from_rotation_y(-step.z) * *rotation;
+        }
+    }
+    (in_place, trajectory)
+}
+
+/// Moves an in-place animation along `trajectory`, undoing [`extract_root_motion`].
+pub fn apply_root_motion(animation: &Animation, trajectory: &[Vec3]) -> Result<Animation> {
+    if trajectory.len() != animation.frame_count() {
+        bail!(
+            "The trajectory has {} frames, the animation {}",
+            trajectory.len(),
+            animation.frame_count()
+        );
+    }
+    let mut moved = Animation {
+        root_positions: animation
+            .root_positions
+            .iter()
+            .zip(trajectory)
+            .map(|(p, step)| Quat::from_rotation_y(step.z) * *p + Vec3::new(step.x, 0.0, step.y))
+            .collect(),
+        joint_rotations: animation.joint_rotations.clone(),
+        events: animation.events.clone(),
+    };
+    if let Some(root) = moved.joint_rotations.first_mut() {
+        for (rotation, step) in root.iter_mut().zip(trajectory) {
+            *rotation = Quat::from_rotation_y(step.z) * *rotation;
+        }
+    }
+    Ok(moved)
+}
+
+/// The trajectory curve of a GAV tensor of `joint_count` joints, right after the joints.
+pub fn trajectory_curve(gav_data: &Array3<f32>, joint_count: usize) -> Result<Vec<Vec3>> {
+    let curve = joint_count + 1;
+    if gav_data.dim().0 <= curve {
+        bail!(
+            "Expected a trajectory curve after {} joints, the tensor has {} curves",
+            joint_count,
+            gav_data.dim().0
+        );
+    }
+    Ok(gav_data
+        .index_axis(Axis(0), curve)
+        .outer_iter()
+        .map(|v| Vec3::new(v[0], v[1], v[2]))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+    use crate::{animation_to_gav, append_curve};
+
+    #[test]
+    fn test_root_motion_round_trip() {
+        // Walking forward along a quarter circle while turning past half a turn, with a tilt.
+        let frames = 5;
+        let tilt = Quat::from_rotation_x(0.2);
+        let animation = Animation {
+            root_positions: (0..frames)
+                .map(|f| Vec3::new(f as f32 * 10.0, 90.0, f as f32 * -5.0))
+                .collect(),
+            joint_rotations: vec![
+                (0..frames)
+                    .map(|f| Quat::from_rotation_y(2.0 + f as f32 * FRAC_PI_2) * tilt)
+                    .collect(),
+                vec![Quat::from_rotation_z(0.3); frames],
+            ],
+            events: vec![],
+        };
+
+        let (in_place, trajectory) = extract_root_motion(&animation);
+        assert_eq!(in_place.root_positions[3], Vec3::new(0.0, 90.0, 0.0));
+        assert!(in_place.joint_rotations[0][3].angle_between(tilt) < 1e-3);
+        // The heading keeps increasing instead of wrapping around.
+        assert!((trajectory[4].z - (2.0 + 4.0 * FRAC_PI_2)).abs() < 1e-4);
+
+        let gav = append_curve(&animation_to_gav(&in_place).unwrap(), &trajectory).unwrap();
+        let decoded = apply_root_motion(&in_place, &trajectory_curve(&gav, 2).unwrap()).unwrap();
+        for frame in 0..frames {
+            assert!(decoded.root_positions[frame].distance(animation.root_positions[frame]) < 1e-3);
+            let (a, b) = (
+                decoded.joint_rotations[0][frame],
+                animation.joint_rotations[0][frame],
+            );
+            assert!((a * Vec3::X).distance(b * Vec3::X) < 1e-4);
+        }
+        assert!(trajectory_curve(&gav, 3).is_err());
+    }
+}
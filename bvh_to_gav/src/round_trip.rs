@@ -0,0 +1,118 @@
+//! Round trip check of the GAV encoding: a clip encoded as a GAV tensor and decoded again has
+//! to pose its skeleton like the clip itself. Comparing global joint positions, see
+//! [`crate::kinematics::global_positions`], rather than rotations catches encoding bugs such as
+//! a quaternion stored in the wrong hemisphere, whose vector part decodes to another rotation,
+//! while ignoring the harmless ones, such as `-q` decoding to `q`.
+//!
+//! Errors are distances in the units of the skeleton.
+use anyhow::{Result, bail};
+use ndarray::{Array3, Axis};
+
+use crate::{Animation, gav_to_animation, kinematics::global_positions, skeleton::Skeleton};
+
+/// Largest positional error a round trip may introduce by default, in the units of the
+/// skeleton, a tenth of a millimeter for clips in centimeters.
+pub const DEFAULT_TOLERANCE: f32 = 0.01;
+
+/// How far a joint ended up from where it should be, over all frames.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JointError {
+    pub joint: String,
+    pub max: f32,
+    pub mean: f32,
+}
+
+/// Positional error of every joint of `actual` against `expected`, in the order of the joints.
+pub fn positional_errors(
+    skeleton: &Skeleton,
+    expected: &Animation,
+    actual: &Animation,
+) -> Result<Vec<JointError>> {
+    if expected.frame_count() != actual.frame_count() {
+        bail!(
+            "{} frames came back from {}",
+            actual.frame_count(),
+            expected.frame_count()
+        );
+    }
+    let difference = global_positions(skeleton, expected)? - global_positions(skeleton, actual)?;
+    let distances: Array3<f32> = difference.mapv(|value| value * value);
+    Ok(skeleton
+        .joint_order()
+        .zip(distances.axis_iter(Axis(0)))
+        .map(|(joint, squared)| {
+            let per_frame: Vec<f32> = squared
+                .axis_iter(Axis(0))
+                .map(|channels| channels.sum().sqrt())
+                .collect();
+            JointError {
+                joint: joint.to_string(),
+                max: per_frame.iter().copied().fold(0.0, f32::max),
+                mean: per_frame.iter().sum::<f32>() / per_frame.len().max(1) as f32,
+            }
+        })
+        .collect())
+}
+
+/// Positional error of every joint of `animation` after decoding `gav`, its encoding.
+pub fn round_trip_errors(
+    skeleton: &Skeleton,
+    animation: &Animation,
+    gav: Array3<f32>,
+) -> Result<Vec<JointError>> {
+    positional_errors(skeleton, animation, &gav_to_animation(gav)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_math::{Quat, Vec3};
+
+    use super::*;
+    use crate::{animation_to_gav, skeleton::SkeletonJoint};
+
+    #[test]
+    fn test_lost_hemisphere_is_caught() {
+        let skeleton = Skeleton {
+            joints: vec![
+                SkeletonJoint {
+                    name: "Hips".to_string(),
+                    parent: None,
+                    offset: Vec3::ZERO,
+                    end_site: None,
+                },
+                SkeletonJoint {
+                    name: "Spine".to_string(),
+                    parent: Some(0),
+                    offset: Vec3::new(0.0, 10.0, 0.0),
+                    end_site: None,
+                },
+            ],
+        };
+        // A negative scalar part, the other hemisphere.
+        let rotation = -Quat::from_rotation_z(1.0);
+        assert!(rotation.w < 0.0);
+        let animation = Animation {
+            root_positions: vec![Vec3::new(0.0, 90.0, 0.0); 3],
+            joint_rotations: vec![vec![rotation; 3], vec![Quat::IDENTITY; 3]],
+            events: Vec::new(),
+        };
+        let errors =
+            round_trip_errors(&skeleton, &animation, animation_to_gav(&animation).unwrap())
+                .unwrap();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[1].joint, "Spine");
+        assert!(errors.iter().all(|error| error.max < 1e-4));
+
+        // Storing the vector part as is decodes to another rotation.
+        let mut lost = animation_to_gav(&animation).unwrap();
+        for frame in 0..3 {
+            for (channel, value) in rotation.xyz().to_array().into_iter().enumerate() {
+                lost[[1, frame, channel]] = value;
+            }
+        }
+        let errors = round_trip_errors(&skeleton, &animation, lost).unwrap();
+        assert_eq!(errors[0].max, 0.0);
+        assert!(errors[1].max > 1.0);
+        assert!((errors[1].mean - errors[1].max).abs() < 1e-4);
+    }
+}
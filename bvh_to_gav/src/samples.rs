@@ -0,0 +1,138 @@
+//! Several motions a probabilistic model generated for the same input, saved next to a clip as
+//! `<clip>.samples.npy`: a `(samples, curves, frames, 3)` array of GAV tensors. The spread of the
+//! joint positions over the samples shows how certain the model is.
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, bail};
+use bevy_math::Vec3;
+use ndarray::{Array2, Array3, Array4, Axis, s};
+use ndarray_npy::read_npy;
+
+use crate::{gav_to_animation, kinematics::global_positions, skeleton::Skeleton};
+
+pub const SAMPLES_EXTENSION: &str = "samples.npy";
+/// Per joint and frame variance of the joint positions, `(frames, joints)`, for models that
+/// predict it instead of drawing samples. Read with [`crate::weights::FrameWeights`].
+pub const VARIANCE_EXTENSION: &str = "variance.npy";
+
+pub fn samples_path(clip: &Path) -> PathBuf {
+    clip.with_extension(SAMPLES_EXTENSION)
+}
+
+pub fn variance_path(clip: &Path) -> PathBuf {
+    clip.with_extension(VARIANCE_EXTENSION)
+}
+
+pub fn read_samples(path: &Path) -> Result<Vec<Array3<f32>>> {
+    let samples: Array4<f32> = read_npy(path)?;
+    Ok(samples.axis_iter(Axis(0)).map(|s| s.to_owned()).collect())
+}
+
+/// Global joint positions of every sample and their spread.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SampleSpread {
+    /// `(joints, frames, 3)` positions of each sample.
+    pub positions: Vec<Array3<f32>>,
+    /// `(joints, frames, 3)` mean position over the samples.
+    pub mean: Array3<f32>,
+    /// `(joints, frames)` root mean square distance of the samples to the mean.
+    pub deviation: Array2<f32>,
+}
+
+impl SampleSpread {
+    /// Poses the GAV tensors of `samples` on `skeleton`. Curves after the joints, such as
+    /// appended velocities, are ignored.
+    pub fn new(skeleton: &Skeleton, samples: &[Array3<f32>]) -> Result<Self> {
+        let Some(first) = samples.first() else {
+            bail!("No samples");
+        };
+        let curves = skeleton.joint_count() + 1;
+        let frames = first.dim().1;
+        let positions = samples
+            .iter()
+            .map(|sample| {
+                if sample.dim().0 < curves || sample.dim().1 != frames {
+                    bail!(
+                        "Expected samples of {} curves and {} frames, got {:?}",
+                        curves,
+                        frames,
+                        sample.dim()
+                    );
+                }
+                let animation = gav_to_animation(sample.slice(s![..curves, .., ..]).to_owned())?;
+                global_positions(skeleton, &animation)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let count = positions.len() as f32;
+        let mean = positions.iter().fold(
+            Array3::zeros((skeleton.joint_count(), frames, 3)),
+            |sum, p| sum + p,
+        ) / count;
+        let deviation = positions
+            .iter()
+            .fold(Array2::zeros((skeleton.joint_count(), frames)), |sum, p| {
+                sum + (p - &mean).mapv(|d| d * d).sum_axis(Axis(2))
+            })
+            .mapv(|sum: f32| (sum / count).sqrt());
+        Ok(SampleSpread {
+            positions,
+            mean,
+            deviation,
+        })
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.deviation.ncols()
+    }
+
+    fn position(positions: &Array3<f32>, joint: usize, frame: usize) -> Vec3 {
+        Vec3::new(
+            positions[[joint, frame, 0]],
+            positions[[joint, frame, 1]],
+            positions[[joint, frame, 2]],
+        )
+    }
+
+    pub fn sample_position(&self, sample: usize, joint: usize, frame: usize) -> Vec3 {
+        Self::position(&self.positions[sample], joint, frame)
+    }
+
+    pub fn mean_position(&self, joint: usize, frame: usize) -> Vec3 {
+        Self::position(&self.mean, joint, frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_math::Quat;
+
+    use super::*;
+    use crate::{Animation, animation_to_gav, skeleton::SkeletonJoint};
+
+    #[test]
+    fn test_spread_of_samples() {
+        let skeleton = Skeleton {
+            joints: vec![SkeletonJoint {
+                name: "Hips".to_string(),
+                parent: None,
+                offset: Vec3::ZERO,
+                end_site: None,
+            }],
+        };
+        // Two samples on either side of the origin.
+        let sample = |x: f32| {
+            animation_to_gav(&Animation {
+                root_positions: vec![Vec3::X * x; 2],
+                joint_rotations: vec![vec![Quat::IDENTITY; 2]],
+                events: vec![],
+            })
+            .unwrap()
+        };
+        let spread = SampleSpread::new(&skeleton, &[sample(-2.0), sample(2.0)]).unwrap();
+        assert_eq!(spread.positions.len(), 2);
+        assert_eq!(spread.mean_position(0, 1), Vec3::ZERO);
+        assert_eq!(spread.deviation[[0, 1]], 2.0);
+        assert!(SampleSpread::new(&skeleton, &[]).is_err());
+    }
+}
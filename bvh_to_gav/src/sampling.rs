@@ -0,0 +1,104 @@
+//! Class balanced sampling weights for a training [`crate::manifest::Manifest`]. Every class
+//! gets the same share of the samples however many clips it has, and within a class clips are
+//! weighted by their length, so each frame of a class is equally likely. The classes are the
+//! values of one metadata field, `labels` by default, and clips without it form a class of
+//! their own. With training windows, every window is weighted instead of every clip.
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Class given to clips without the metadata field the classes are taken from.
+pub const UNLABELED: &str = "";
+
+/// Sampling weight of a clip, or of one window of it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SamplingWeight {
+    pub clip: String,
+    /// Start frame of the window or motion primitive, for per window or per primitive weights.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<usize>,
+    pub class: String,
+    pub weight: f32,
+}
+
+/// Something to sample: a clip, or a window of one, with its class and length in frames.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SamplingUnit {
+    pub clip: String,
+    pub start: Option<usize>,
+    pub class: String,
+    pub frame_count: usize,
+}
+
+/// Weights summing to one that give every class the same total weight, shared out within the
+/// class in proportion to the length of each unit. Units without frames get no weight.
+pub fn balance(units: Vec<SamplingUnit>) -> Vec<SamplingWeight> {
+    let mut class_frames: BTreeMap<&str, usize> = BTreeMap::new();
+    for unit in &units {
+        *class_frames.entry(&unit.class).or_default() += unit.frame_count;
+    }
+    class_frames.retain(|_, frames| *frames > 0);
+    let class_count = class_frames.len() as f32;
+    let weights: Vec<f32> = units
+        .iter()
+        .map(|unit| match class_frames.get(unit.class.as_str()) {
+            Some(&frames) => unit.frame_count as f32 / frames as f32 / class_count,
+            None => 0.0,
+        })
+        .collect();
+    units
+        .into_iter()
+        .zip(weights)
+        .map(|(unit, weight)| SamplingWeight {
+            clip: unit.clip,
+            start: unit.start,
+            class: unit.class,
+            weight,
+        })
+        .collect()
+}
+
+/// Number of units and total weight of every class.
+pub fn class_summary(weights: &[SamplingWeight]) -> BTreeMap<&str, (usize, f32)> {
+    let mut summary: BTreeMap<&str, (usize, f32)> = BTreeMap::new();
+    for weight in weights {
+        let (count, total) = summary.entry(&weight.class).or_default();
+        *count += 1;
+        *total += weight.weight;
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classes_get_equal_shares() {
+        let unit = |clip: &str, class: &str, frame_count| SamplingUnit {
+            clip: clip.to_string(),
+            start: None,
+            class: class.to_string(),
+            frame_count,
+        };
+        // Three walks against one jump, and a clip without a label.
+        let weights = balance(vec![
+            unit("walk_1", "walk", 100),
+            unit("walk_2", "walk", 100),
+            unit("walk_3", "walk", 200),
+            unit("jump", "jump", 50),
+            unit("idle", UNLABELED, 10),
+            unit("empty", "fall", 0),
+        ]);
+        let weight = |clip: &str| weights.iter().find(|w| w.clip == clip).unwrap().weight;
+        assert!((weight("jump") - 1.0 / 3.0).abs() < 1e-6);
+        assert!((weight("walk_3") - 2.0 * weight("walk_1")).abs() < 1e-6);
+        assert_eq!(weight("empty"), 0.0);
+        let total: f32 = weights.iter().map(|w| w.weight).sum();
+        assert!((total - 1.0).abs() < 1e-6);
+
+        let summary = class_summary(&weights);
+        assert_eq!(summary["walk"].0, 3);
+        assert!((summary["walk"].1 - summary[UNLABELED].1).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,361 @@
+//! Nearest-neighbour search over a dataset, to find training examples that resemble a given
+//! pose (e.g. a generated failure case) or follow a given root trajectory.
+use anyhow::{Result, bail};
+use bevy_math::{Mat3, Quat, Vec2};
+
+use crate::Animation;
+
+/// Mean geodesic angle in radians between the joint rotations of two poses.
+pub fn pose_distance(a: &[Quat], b: &[Quat]) -> f32 {
+    if a.is_empty() {
+        return 0.0;
+    }
+    let total: f32 = a
+        .iter()
+        .zip(b)
+        .map(|(a, b)| 2.0 * a.dot(*b).abs().min(1.0).acos())
+        .sum();
+    total / a.len() as f32
+}
+
+/// Continuous pose features: the first two columns of every joint's rotation matrix. Unlike
+/// quaternions these don't suffer from the double cover, so euclidean distance is meaningful.
+pub fn pose_features(rotations: &[Quat]) -> Vec<f32> {
+    let mut features = Vec::with_capacity(rotations.len() * 6);
+    for rotation in rotations {
+        let matrix = Mat3::from_quat(*rotation);
+        features.extend(matrix.x_axis.to_array());
+        features.extend(matrix.y_axis.to_array());
+    }
+    features
+}
+
+fn squared_distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PoseMatch {
+    pub clip: String,
+    pub frame: usize,
+    /// Mean joint angle to the query in radians, see [`pose_distance`].
+    pub distance: f32,
+}
+
+/// Approximate index over the frames of many clips. Frames are clustered with k-means on their
+/// [`pose_features`]; a query only visits the closest clusters and re-ranks their frames with
+/// the exact [`pose_distance`].
+pub struct PoseIndex {
+    joint_names: Vec<String>,
+    clips: Vec<String>,
+    /// Clip index and frame of every indexed pose.
+    entries: Vec<(usize, usize)>,
+    rotations: Vec<Vec<Quat>>,
+    centroids: Vec<Vec<f32>>,
+    lists: Vec<Vec<usize>>,
+}
+
+const KMEANS_ITERATIONS: usize = 10;
+const KMEANS_MAX_SAMPLES: usize = 20_000;
+
+impl PoseIndex {
+    /// Indexes every `stride`th frame of the clips. All clips must use the joints in
+    /// `joint_names`, in that order.
+    pub fn build(
+        joint_names: Vec<String>,
+        clips: Vec<(String, Animation)>,
+        stride: usize,
+    ) -> Result<Self> {
+        let mut names = Vec::with_capacity(clips.len());
+        let mut entries = Vec::new();
+        let mut rotations: Vec<Vec<Quat>> = Vec::new();
+        for (clip_index, (name, animation)) in clips.into_iter().enumerate() {
+            if animation.joint_count() != joint_names.len() {
+                bail!(
+                    "Clip {} has {} joints, expected {}",
+                    name,
+                    animation.joint_count(),
+                    joint_names.len()
+                );
+            }
+            for frame in (0..animation.frame_count()).step_by(stride.max(1)) {
+                entries.push((clip_index, frame));
+                rotations.push(
+                    animation
+                        .joint_rotations
+                        .iter()
+                        .map(|joint| joint[frame])
+                        .collect(),
+                );
+            }
+            names.push(name);
+        }
+
+        let features: Vec<Vec<f32>> = rotations.iter().map(|r| pose_features(r)).collect();
+        let cluster_count = (features.len() as f32).sqrt().ceil() as usize;
+        let centroids = kmeans(&features, cluster_count);
+        let mut lists = vec![Vec::new(); centroids.len()];
+        for (entry, feature) in features.iter().enumerate() {
+            lists[nearest(&centroids, feature)].push(entry);
+        }
+
+        Ok(PoseIndex {
+            joint_names,
+            clips: names,
+            entries,
+            rotations,
+            centroids,
+            lists,
+        })
+    }
+
+    pub fn joint_names(&self) -> &[String] {
+        &self.joint_names
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The `k` closest frames to `query`, searching the `probes` closest clusters.
+    pub fn search(&self, query: &[Quat], k: usize, probes: usize) -> Vec<PoseMatch> {
+        let features = pose_features(query);
+        let mut clusters: Vec<(usize, f32)> = self
+            .centroids
+            .iter()
+            .map(|c| squared_distance(c, &features))
+            .enumerate()
+            .collect();
+        clusters.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        let mut matches: Vec<PoseMatch> = clusters
+            .iter()
+            .take(probes.max(1))
+            .flat_map(|(cluster, _)| &self.lists[*cluster])
+            .map(|&entry| {
+                let (clip, frame) = self.entries[entry];
+                PoseMatch {
+                    clip: self.clips[clip].clone(),
+                    frame,
+                    distance: pose_distance(query, &self.rotations[entry]),
+                }
+            })
+            .collect();
+        matches.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        matches.truncate(k);
+        matches
+    }
+
+    /// Reorders the rotations of a pose with the given joint names into the index joint order.
+    pub fn reorder(&self, names: &[String], rotations: &[Quat]) -> Result<Vec<Quat>> {
+        self.joint_names
+            .iter()
+            .map(|name| match names.iter().position(|n| n == name) {
+                Some(index) => Ok(rotations[index]),
+                None => bail!("Query pose has no joint named {}", name),
+            })
+            .collect()
+    }
+}
+
+fn nearest(centroids: &[Vec<f32>], feature: &[f32]) -> usize {
+    centroids
+        .iter()
+        .map(|c| squared_distance(c, feature))
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(index, _)| index)
+        .unwrap_or(0)
+}
+
+/// Deterministic k-means: centroids start at evenly spaced samples so the index is reproducible.
+fn kmeans(features: &[Vec<f32>], k: usize) -> Vec<Vec<f32>> {
+    if features.is_empty() || k == 0 {
+        return Vec::new();
+    }
+    let sample_stride = features.len().div_ceil(KMEANS_MAX_SAMPLES);
+    let samples: Vec<&Vec<f32>> = features.iter().step_by(sample_stride).collect();
+    let k = k.min(samples.len());
+    let mut centroids: Vec<Vec<f32>> = (0..k)
+        .map(|i| samples[i * samples.len() / k].clone())
+        .collect();
+
+    let dimensions = centroids[0].len();
+    for _ in 0..KMEANS_ITERATIONS {
+        let mut sums = vec![vec![0.0; dimensions]; k];
+        let mut counts = vec![0usize; k];
+        for sample in &samples {
+            let cluster = nearest(&centroids, sample);
+            counts[cluster] += 1;
+            for (sum, value) in sums[cluster].iter_mut().zip(sample.iter()) {
+                *sum += value;
+            }
+        }
+        for ((centroid, sum), count) in centroids.iter_mut().zip(sums).zip(counts) {
+            // Empty clusters keep their previous centroid.
+            if count > 0 {
+                *centroid = sum.into_iter().map(|s| s / count as f32).collect();
+            }
+        }
+    }
+    centroids
+}
+
+/// Number of points trajectories are resampled to before they are compared.
+pub const TRAJECTORY_SAMPLES: usize = 64;
+
+/// Ground plane path of the root: the x and z position of every frame.
+pub fn root_path(animation: &Animation) -> Vec<Vec2> {
+    animation
+        .root_positions
+        .iter()
+        .map(|p| Vec2::new(p.x, p.z))
+        .collect()
+}
+
+/// Resamples a path to `count` points evenly spaced along its length, so paths drawn by hand
+/// can be compared with captured ones regardless of timing.
+pub fn resample_path(path: &[Vec2], count: usize) -> Vec<Vec2> {
+    let Some(&first) = path.first() else {
+        return Vec::new();
+    };
+    let mut lengths = Vec::with_capacity(path.len());
+    let mut total = 0.0;
+    lengths.push(0.0);
+    for segment in path.windows(2) {
+        total += segment[0].distance(segment[1]);
+        lengths.push(total);
+    }
+    if total == 0.0 || count < 2 {
+        return vec![first; count];
+    }
+
+    let mut segment = 0;
+    (0..count)
+        .map(|i| {
+            let target = total * i as f32 / (count - 1) as f32;
+            while segment + 2 < path.len() && lengths[segment + 1] < target {
+                segment += 1;
+            }
+            let length = lengths[segment + 1] - lengths[segment];
+            let t = if length > 0.0 {
+                ((target - lengths[segment]) / length).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            path[segment].lerp(path[segment + 1], t)
+        })
+        .collect()
+}
+
+/// Root mean square distance between two resampled paths after moving both to start at the
+/// origin and rotating `candidate` about the start to best fit `query`.
+pub fn trajectory_distance(query: &[Vec2], candidate: &[Vec2]) -> f32 {
+    let (Some(&query_start), Some(&candidate_start)) = (query.first(), candidate.first()) else {
+        return 0.0;
+    };
+    let pairs = || {
+        query
+            .iter()
+            .zip(candidate)
+            .map(move |(q, c)| (*q - query_start, *c - candidate_start))
+    };
+    // Closed form least squares rotation in the plane.
+    let (cross, dot) = pairs().fold((0.0, 0.0), |(cross, dot), (q, c)| {
+        (cross + c.perp_dot(q), dot + c.dot(q))
+    });
+    let rotation = Vec2::from_angle(cross.atan2(dot));
+    let squared: f32 = pairs()
+        .map(|(q, c)| rotation.rotate(c).distance_squared(q))
+        .sum();
+    (squared / query.len().min(candidate.len()) as f32).sqrt()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrajectoryMatch {
+    pub clip: String,
+    /// See [`trajectory_distance`], in the units of the root positions.
+    pub distance: f32,
+}
+
+/// The `k` clips whose root paths best match the `query` path.
+pub fn search_trajectories(
+    query: &[Vec2],
+    clips: &[(String, Vec<Vec2>)],
+    k: usize,
+) -> Vec<TrajectoryMatch> {
+    let query = resample_path(query, TRAJECTORY_SAMPLES);
+    let mut matches: Vec<TrajectoryMatch> = clips
+        .iter()
+        .map(|(clip, path)| TrajectoryMatch {
+            clip: clip.clone(),
+            distance: trajectory_distance(&query, &resample_path(path, TRAJECTORY_SAMPLES)),
+        })
+        .collect();
+    matches.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+    matches.truncate(k);
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy_math::Vec3;
+
+    fn swing(angles: impl Iterator<Item = f32>) -> Animation {
+        let joint: Vec<Quat> = angles.map(Quat::from_rotation_x).collect();
+        Animation {
+            root_positions: vec![Vec3::ZERO; joint.len()],
+            joint_rotations: vec![joint.clone(), joint],
+            events: vec![],
+        }
+    }
+
+    #[test]
+    fn test_pose_index_finds_closest_frame() {
+        let names = vec!["a".to_string(), "b".to_string()];
+        let index = PoseIndex::build(
+            names,
+            vec![
+                ("slow".to_string(), swing((0..50).map(|i| i as f32 * 0.01))),
+                ("fast".to_string(), swing((0..50).map(|i| i as f32 * 0.05))),
+            ],
+            1,
+        )
+        .unwrap();
+        assert_eq!(index.len(), 100);
+
+        let query = vec![Quat::from_rotation_x(2.0); 2];
+        let matches = index.search(&query, 3, 4);
+        assert_eq!(matches.len(), 3);
+        assert_eq!(matches[0].clip, "fast");
+        assert_eq!(matches[0].frame, 40);
+        assert!(matches[0].distance < 1e-3);
+        assert!(matches[0].distance <= matches[1].distance);
+    }
+
+    #[test]
+    fn test_trajectory_search_ignores_start_and_heading() {
+        let straight: Vec<Vec2> = (0..20).map(|i| Vec2::new(i as f32, 0.0)).collect();
+        let turn: Vec<Vec2> = (0..20)
+            .map(|i| Vec2::from_angle(i as f32 * 0.1) * 10.0)
+            .collect();
+        // The same straight line, moved and heading along another axis, with uneven spacing.
+        let query: Vec<Vec2> = (0..10)
+            .map(|i| Vec2::new(5.0, 3.0 + (i * i) as f32 * 19.0 / 81.0))
+            .collect();
+
+        let clips = vec![
+            ("turn".to_string(), turn),
+            ("straight".to_string(), straight),
+        ];
+        let matches = search_trajectories(&query, &clips, 2);
+        assert_eq!(matches[0].clip, "straight");
+        assert!(matches[0].distance < 1e-3);
+        assert!(matches[1].distance > 0.1);
+    }
+}
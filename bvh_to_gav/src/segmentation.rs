@@ -0,0 +1,204 @@
+//! Unsupervised segmentation of clips into motion primitives, for transition datasets and for
+//! analysis. Clips are cut where the body comes almost to rest, at valleys of the velocity
+//! profile (the summed speed of all joints, smoothed over a few frames), and where the root
+//! turns sharply on the ground. Cuts closer than a minimum length to each other or to the ends
+//! of the clip are dropped, keeping the most pronounced ones.
+use std::ops::Range;
+
+use anyhow::Result;
+use bevy_math::{Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Animation,
+    derivatives::{Differencing, linear_velocities},
+    kinematics::global_positions,
+    skeleton::Skeleton,
+};
+
+/// A motion primitive of a clip, recorded in the manifest.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Segment {
+    pub clip: String,
+    pub start: usize,
+    /// Frame after the last one of the segment.
+    pub end: usize,
+}
+
+impl Segment {
+    pub fn frame_count(&self) -> usize {
+        self.end - self.start
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SegmentationConfig {
+    /// Frames of the shortest segment.
+    pub min_frames: usize,
+    /// Valleys are cut when the speed drops below this fraction of the mean speed of the clip.
+    pub valley_ratio: f32,
+    /// Turns of the root, in radians over the smoothing window, cut from this angle up.
+    pub turn_angle: f32,
+    /// Frames on each side a frame is averaged with.
+    pub smoothing: usize,
+}
+
+impl Default for SegmentationConfig {
+    fn default() -> Self {
+        SegmentationConfig {
+            min_frames: 15,
+            valley_ratio: 0.5,
+            turn_angle: 60f32.to_radians(),
+            smoothing: 2,
+        }
+    }
+}
+
+/// Average of every value with the `radius` values on either side, as far as there are any.
+fn smooth(values: &[f32], radius: usize) -> Vec<f32> {
+    (0..values.len())
+        .map(|i| {
+            let window = &values[i.saturating_sub(radius)..(i + radius + 1).min(values.len())];
+            window.iter().sum::<f32>() / window.len() as f32
+        })
+        .collect()
+}
+
+/// Frames to cut at given the summed joint `speeds` and the root `velocities` of every frame,
+/// sorted.
+pub fn cut_frames(speeds: &[f32], velocities: &[Vec3], config: &SegmentationConfig) -> Vec<usize> {
+    let frame_count = speeds.len();
+    if frame_count < 2 * config.min_frames.max(1) {
+        return Vec::new();
+    }
+    let speeds = smooth(speeds, config.smoothing);
+    let mean_speed = speeds.iter().sum::<f32>() / frame_count as f32;
+    // Candidates with their priority, lower first.
+    let mut candidates: Vec<(f32, usize)> = (1..frame_count - 1)
+        .filter(|&i| speeds[i] <= speeds[i - 1] && speeds[i] < speeds[i + 1])
+        .filter(|&i| speeds[i] < config.valley_ratio * mean_speed)
+        .map(|i| (speeds[i] / mean_speed, i))
+        .collect();
+
+    let ground: Vec<Vec2> = velocities.iter().map(|v| Vec2::new(v.x, v.z)).collect();
+    let mean_ground_speed = ground.iter().map(|v| v.length()).sum::<f32>() / frame_count as f32;
+    let radius = config.smoothing.max(1);
+    let turn = |i: usize| {
+        let (before, after) = (ground[i - radius], ground[i + radius]);
+        // Directions are meaningless while the root barely moves.
+        let moving = 0.25 * mean_ground_speed;
+        if before.length() > moving && after.length() > moving {
+            before.angle_to(after).abs()
+        } else {
+            0.0
+        }
+    };
+    let turns: Vec<f32> = (0..frame_count)
+        .map(|i| {
+            if i >= radius && i + radius < frame_count {
+                turn(i)
+            } else {
+                0.0
+            }
+        })
+        .collect();
+    candidates.extend(
+        (1..frame_count - 1)
+            .filter(|&i| turns[i] >= turns[i - 1] && turns[i] > turns[i + 1])
+            .filter(|&i| turns[i] >= config.turn_angle)
+            .map(|i| (1.0 - turns[i] / std::f32::consts::PI, i)),
+    );
+
+    candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut cuts: Vec<usize> = Vec::new();
+    for (_, frame) in candidates {
+        let fits = frame >= config.min_frames
+            && frame_count - frame >= config.min_frames
+            && cuts
+                .iter()
+                .all(|&cut| cut.abs_diff(frame) >= config.min_frames);
+        if fits {
+            cuts.push(frame);
+        }
+    }
+    cuts.sort();
+    cuts
+}
+
+/// Frame ranges of the motion primitives of `animation`, covering the whole clip.
+pub fn segment(
+    skeleton: &Skeleton,
+    animation: &Animation,
+    frame_time: f32,
+    config: &SegmentationConfig,
+) -> Result<Vec<Range<usize>>> {
+    let positions = global_positions(skeleton, animation)?;
+    let (joint_count, frame_count, _) = positions.dim();
+    let trajectory = |joint: usize| -> Vec<Vec3> {
+        (0..frame_count)
+            .map(|frame| {
+                Vec3::new(
+                    positions[[joint, frame, 0]],
+                    positions[[joint, frame, 1]],
+                    positions[[joint, frame, 2]],
+                )
+            })
+            .collect()
+    };
+    let mut speeds = vec![0.0; frame_count];
+    for joint in 0..joint_count {
+        let velocities = linear_velocities(&trajectory(joint), frame_time, Differencing::Central);
+        for (speed, velocity) in speeds.iter_mut().zip(velocities) {
+            *speed += velocity.length();
+        }
+    }
+    let root_velocities =
+        linear_velocities(&animation.root_positions, frame_time, Differencing::Central);
+
+    let mut start = 0;
+    let mut segments = Vec::new();
+    for cut in cut_frames(&speeds, &root_velocities, config) {
+        segments.push(start..cut);
+        start = cut;
+    }
+    if start < frame_count {
+        segments.push(start..frame_count);
+    }
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clips_are_cut_at_rests_and_turns() {
+        let config = SegmentationConfig {
+            min_frames: 10,
+            ..Default::default()
+        };
+        // Two strides with a rest at frame 30 between them, walking along +X.
+        let speeds: Vec<f32> = (0..60)
+            .map(|f: i32| {
+                ((f - 30).abs() as f32 / 30.0 * std::f32::consts::PI)
+                    .sin()
+                    .abs()
+                    + 0.01
+            })
+            .collect();
+        let forward = vec![Vec3::X; 60];
+        assert_eq!(cut_frames(&speeds, &forward, &config), vec![30]);
+
+        // At a constant speed, turning from +X to +Z at frame 45.
+        let steady = vec![1.0; 90];
+        let turning: Vec<Vec3> = (0..90)
+            .map(|f| if f < 45 { Vec3::X } else { Vec3::Z })
+            .collect();
+        let cuts = cut_frames(&steady, &turning, &config);
+        assert_eq!(cuts.len(), 1);
+        assert!(cuts[0].abs_diff(45) <= 2, "{:?}", cuts);
+
+        // Rests too close to the ends of the clip aren't cut.
+        assert!(cut_frames(&speeds[..15], &forward[..15], &config).is_empty());
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use bevy_math::Vec3;
+use bvh_anim_parser::types::{BvhData, BvhMetadata};
+use serde::{Deserialize, Serialize};
+
+pub const SKELETON_EXTENSION: &str = "skeleton.json";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SkeletonJoint {
+    pub name: String,
+    pub parent: Option<usize>,
+    pub offset: Vec3,
+    pub end_site: Option<Vec3>,
+}
+
+/// Flattened joint hierarchy. Joints are stored in parser order, so the index of a joint
+/// matches the index of its rotation track in an [`crate::Animation`].
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Skeleton {
+    pub joints: Vec<SkeletonJoint>,
+}
+
+impl Skeleton {
+    pub fn from_bvh(bvh_meta: &BvhMetadata, bvh_data: &BvhData) -> Self {
+        let mut joints: Vec<SkeletonJoint> = bvh_meta
+            .joints
+            .iter()
+            .map(|joint| {
+                let offset = bvh_data.rest_local_positions[joint.index];
+                let end_site = joint.endsite.as_ref().map(|endsite| endsite.offset);
+                SkeletonJoint {
+                    name: joint.name.to_string(),
+                    parent: None,
+                    offset: Vec3::new(offset[0] as f32, offset[1] as f32, offset[2] as f32),
+                    end_site: end_site.map(|e| Vec3::new(e.x as f32, e.y as f32, e.z as f32)),
+                }
+            })
+            .collect();
+
+        for joint in &bvh_meta.joints {
+            for child in &joint.children {
+                joints[*child].parent = Some(joint.index);
+            }
+        }
+
+        Skeleton { joints }
+    }
+
+    pub fn joint_count(&self) -> usize {
+        self.joints.len()
+    }
+
+    /// Names of all joints in parser order, the order of the joint tracks in the GAV tensors,
+    /// [`crate::Animation`] and the exported poses.
+    pub fn joint_order(&self) -> impl Iterator<Item = &str> {
+        self.joints.iter().map(|joint| joint.name.as_str())
+    }
+
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.joints.iter().position(|joint| joint.name == name)
+    }
+
+    pub fn roots(&self) -> impl Iterator<Item = usize> + '_ {
+        self.joints
+            .iter()
+            .enumerate()
+            .filter(|(_, joint)| joint.parent.is_none())
+            .map(|(index, _)| index)
+    }
+
+    pub fn children(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        self.joints
+            .iter()
+            .enumerate()
+            .filter(move |(_, joint)| joint.parent == Some(index))
+            .map(|(index, _)| index)
+    }
+
+    /// Joint indices in the order a BVH hierarchy declares them (depth first from each root).
+    pub fn depth_first_order(&self) -> Vec<usize> {
+        fn visit(skeleton: &Skeleton, index: usize, order: &mut Vec<usize>) {
+            order.push(index);
+            for child in skeleton.children(index) {
+                visit(skeleton, child, order);
+            }
+        }
+
+        let mut order = Vec::with_capacity(self.joint_count());
+        for root in self.roots() {
+            visit(self, root, &mut order);
+        }
+        order
+    }
+}
+
+/// Path of the skeleton sidecar of a GAV tensor, which holds what the tensor itself loses.
+pub fn skeleton_path(tensor: &Path) -> PathBuf {
+    tensor.with_extension(SKELETON_EXTENSION)
+}
+
+/// On-disk JSON layout of a skeleton sidecar.
+#[derive(Serialize, Deserialize)]
+struct SkeletonFile {
+    frame_time: f32,
+    fps: f32,
+    joints: Vec<SkeletonJoint>,
+}
+
+pub fn write_skeleton_json<W: Write>(
+    writer: W,
+    skeleton: &Skeleton,
+    frame_time: f32,
+) -> Result<()> {
+    let file = SkeletonFile {
+        frame_time,
+        fps: 1.0 / frame_time,
+        joints: skeleton.joints.clone(),
+    };
+    serde_json::to_writer_pretty(writer, &file)?;
+    Ok(())
+}
+
+/// Reads a skeleton sidecar written by [`write_skeleton_json`], with its frame time.
+pub fn read_skeleton_sidecar<R: Read>(reader: R) -> Result<(Skeleton, f32)> {
+    let file: SkeletonFile = serde_json::from_reader(reader)?;
+    Ok((
+        Skeleton {
+            joints: file.joints,
+        },
+        file.frame_time,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skeleton_sidecar_round_trip() {
+        let skeleton = Skeleton {
+            joints: vec![
+                SkeletonJoint {
+                    name: "Hips".to_string(),
+                    parent: None,
+                    offset: Vec3::ZERO,
+                    end_site: None,
+                },
+                SkeletonJoint {
+                    name: "Spine".to_string(),
+                    parent: Some(0),
+                    offset: Vec3::new(0.0, 10.0, 0.5),
+                    end_site: Some(Vec3::Y * 5.0),
+                },
+            ],
+        };
+        let mut json = Vec::new();
+        write_skeleton_json(&mut json, &skeleton, 1.0 / 60.0).unwrap();
+        let (read, frame_time) = read_skeleton_sidecar(json.as_slice()).unwrap();
+        assert_eq!(read, skeleton);
+        assert_eq!(frame_time, 1.0 / 60.0);
+    }
+}
@@ -0,0 +1,347 @@
+//! Temporal smoothing of source clips before they are encoded, to keep mocap jitter out of the
+//! GAV channels. Unlike [`crate::deflicker`], which only filters around flicker it detects,
+//! every frame is filtered.
+//!
+//! Root positions are filtered component-wise. Joint rotations are filtered on the rotation
+//! group instead of component-wise, so they stay unit quaternions without renormalizing:
+//!
+//! - The low-pass filter is a first order filter of slerps towards every next frame, run
+//!   forward then backward so its phase shift cancels out.
+//! - The Savitzky–Golay filter fits a polynomial to the window around every frame, for
+//!   rotations in the tangent space of the frame, so it keeps peaks a low-pass filter rounds
+//!   off. Windows are padded past the ends of the clip with its first and last frame.
+//!
+//! Every joint can get a filter of its own, say a higher cutoff for the hands. The root
+//! positions are filtered like the root joint.
+use std::{f32::consts::TAU, fmt, str::FromStr};
+
+use anyhow::{Context, Error, Result, bail};
+use bevy_math::{Quat, Vec3};
+
+use crate::{Animation, canonical_rotation, skeleton::Skeleton};
+
+/// A temporal filter, parsed from `lowpass:CUTOFF_HZ` or `savgol:WINDOW[:ORDER]`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Smoothing {
+    LowPass {
+        cutoff_hz: f32,
+    },
+    /// Fits polynomials of `order` over `window` frames, an odd number.
+    SavitzkyGolay {
+        window: usize,
+        order: usize,
+    },
+}
+
+impl FromStr for Smoothing {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.split(':');
+        let smoothing = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some("lowpass"), Some(cutoff), None, None) => Smoothing::LowPass {
+                cutoff_hz: cutoff
+                    .parse()
+                    .with_context(|| format!("Invalid cutoff {}", cutoff))?,
+            },
+            (Some("savgol"), Some(window), order, None) => Smoothing::SavitzkyGolay {
+                window: window
+                    .parse()
+                    .with_context(|| format!("Invalid window {}", window))?,
+                order: order
+                    .map(str::parse)
+                    .transpose()
+                    .with_context(|| format!("Invalid order in {}", s))?
+                    .unwrap_or(2),
+            },
+            _ => bail!(
+                "Unknown smoothing {}, expected lowpass:CUTOFF_HZ or savgol:WINDOW[:ORDER]",
+                s
+            ),
+        };
+        match smoothing {
+            Smoothing::LowPass { cutoff_hz } if cutoff_hz.is_nan() || cutoff_hz <= 0.0 => {
+                bail!("The cutoff has to be positive")
+            }
+            Smoothing::SavitzkyGolay { window, order }
+                if window.is_multiple_of(2) || order >= window =>
+            {
+                bail!("The window has to be odd and longer than the order")
+            }
+            _ => Ok(smoothing),
+        }
+    }
+}
+
+impl fmt::Display for Smoothing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Smoothing::LowPass { cutoff_hz } => write!(f, "lowpass:{}", cutoff_hz),
+            Smoothing::SavitzkyGolay { window, order } => write!(f, "savgol:{}:{}", window, order),
+        }
+    }
+}
+
+/// The filter of one joint, parsed from `JOINT=FILTER`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JointSmoothing {
+    pub joint: String,
+    pub smoothing: Smoothing,
+}
+
+impl FromStr for JointSmoothing {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (joint, smoothing) = s
+            .split_once('=')
+            .with_context(|| format!("Expected JOINT=FILTER, got {}", s))?;
+        Ok(JointSmoothing {
+            joint: joint.trim().to_string(),
+            smoothing: smoothing.trim().parse()?,
+        })
+    }
+}
+
+/// Filters every curve of `animation` with `smoothing`, or the filter given for its joint in
+/// `joints`.
+pub fn smooth(
+    animation: &mut Animation,
+    skeleton: &Skeleton,
+    frame_time: f32,
+    smoothing: Smoothing,
+    joints: &[JointSmoothing],
+) -> Result<()> {
+    let mut filters = vec![smoothing; skeleton.joint_count()];
+    for joint in joints {
+        let index = skeleton
+            .find(&joint.joint)
+            .with_context(|| format!("No joint {} to smooth", joint.joint))?;
+        filters[index] = joint.smoothing;
+    }
+    let root_filter = skeleton
+        .roots()
+        .next()
+        .map_or(smoothing, |root| filters[root]);
+    animation.root_positions = match root_filter {
+        Smoothing::LowPass { cutoff_hz } => {
+            let alpha = low_pass_alpha(cutoff_hz, frame_time);
+            forward_backward(&animation.root_positions, |a, b| a.lerp(b, alpha))
+        }
+        Smoothing::SavitzkyGolay { window, order } => {
+            let coefficients = savitzky_golay_coefficients(window, order);
+            savitzky_golay(&animation.root_positions, &coefficients, |_, values| {
+                values.map(|(c, p)| c * p).sum()
+            })
+        }
+    };
+    for (rotations, filter) in animation.joint_rotations.iter_mut().zip(filters) {
+        *rotations = match filter {
+            Smoothing::LowPass { cutoff_hz } => {
+                let alpha = low_pass_alpha(cutoff_hz, frame_time);
+                forward_backward(rotations, |a, b| a.slerp(b, alpha))
+            }
+            Smoothing::SavitzkyGolay { window, order } => {
+                let coefficients = savitzky_golay_coefficients(window, order);
+                savitzky_golay(rotations, &coefficients, |center, values| {
+                    let tangent: Vec3 = values
+                        .map(|(c, q)| c * canonical_rotation(center.inverse() * q).to_scaled_axis())
+                        .sum();
+                    (center * Quat::from_scaled_axis(tangent)).normalize()
+                })
+            }
+        };
+    }
+    Ok(())
+}
+
+/// Weight of every new frame in a first order low-pass filter with the cutoff `cutoff_hz`.
+fn low_pass_alpha(cutoff_hz: f32, frame_time: f32) -> f32 {
+    1.0 - (-TAU * cutoff_hz * frame_time).exp()
+}
+
+/// `values` with `step` from the previous output to every next value run forward, then
+/// backward over the result.
+fn forward_backward<T: Copy>(values: &[T], step: impl Fn(T, T) -> T) -> Vec<T> {
+    let pass = |values: &mut dyn Iterator<Item = T>| -> Vec<T> {
+        let mut output: Vec<T> = Vec::with_capacity(values.size_hint().0);
+        for value in values {
+            output.push(match output.last() {
+                Some(&previous) => step(previous, value),
+                None => value,
+            });
+        }
+        output
+    };
+    let forward = pass(&mut values.iter().copied());
+    let mut backward = pass(&mut forward.into_iter().rev());
+    backward.reverse();
+    backward
+}
+
+/// Weights of the frames of a window, centered on the frame smoothed, of the least squares fit
+/// of a polynomial of `order` evaluated at the center.
+fn savitzky_golay_coefficients(window: usize, order: usize) -> Vec<f32> {
+    let half = (window / 2) as i64;
+    let powers = |k: i64| (0..=order).map(move |j| (k as f64).powi(j as i32));
+    // The normal equations of the fit, solved for the first row of their inverse.
+    let size = order + 1;
+    let mut system = vec![vec![0.0f64; size + 1]; size];
+    for k in -half..=half {
+        let row: Vec<f64> = powers(k).collect();
+        for i in 0..size {
+            for j in 0..size {
+                system[i][j] += row[i] * row[j];
+            }
+        }
+    }
+    system[0][size] = 1.0;
+    for column in 0..size {
+        let pivot = (column..size)
+            .max_by(|&a, &b| system[a][column].abs().total_cmp(&system[b][column].abs()))
+            .unwrap_or(column);
+        system.swap(column, pivot);
+        let pivot_row = system[column].clone();
+        for (row, values) in system.iter_mut().enumerate() {
+            if row != column {
+                let factor = values[column] / pivot_row[column];
+                for (value, pivot) in values.iter_mut().zip(&pivot_row).skip(column) {
+                    *value -= factor * pivot;
+                }
+            }
+        }
+    }
+    let solution: Vec<f64> = (0..size).map(|i| system[i][size] / system[i][i]).collect();
+    (-half..=half)
+        .map(|k| powers(k).zip(&solution).map(|(p, s)| p * s).sum::<f64>() as f32)
+        .collect()
+}
+
+/// `values` with every frame replaced by `combine` of it and the frames of its window, each
+/// with its coefficient.
+fn savitzky_golay<T: Copy>(
+    values: &[T],
+    coefficients: &[f32],
+    combine: impl Fn(T, &mut dyn Iterator<Item = (f32, T)>) -> T,
+) -> Vec<T> {
+    let half = coefficients.len() / 2;
+    let last = values.len().saturating_sub(1);
+    (0..values.len())
+        .map(|frame| {
+            let mut window = coefficients.iter().enumerate().map(|(i, &c)| {
+                let neighbor = (frame + i).saturating_sub(half).min(last);
+                (c, values[neighbor])
+            });
+            combine(values[frame], &mut window)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::skeleton::SkeletonJoint;
+
+    fn skeleton(names: &[&str]) -> Skeleton {
+        Skeleton {
+            joints: names
+                .iter()
+                .enumerate()
+                .map(|(i, name)| SkeletonJoint {
+                    name: name.to_string(),
+                    parent: i.checked_sub(1),
+                    offset: Vec3::Y,
+                    end_site: None,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_low_pass_removes_jitter() {
+        let frame_count = 60;
+        let jittered = |i: usize| if i.is_multiple_of(2) { 0.05 } else { -0.05 };
+        let mut animation = Animation {
+            root_positions: (0..frame_count)
+                .map(|i| Vec3::new(jittered(i), 90.0, 0.0))
+                .collect(),
+            joint_rotations: vec![
+                (0..frame_count)
+                    .map(|i| Quat::from_rotation_y(0.5 + jittered(i)))
+                    .collect(),
+                vec![Quat::from_rotation_x(0.25); frame_count],
+            ],
+            events: vec![],
+        };
+        let skeleton = skeleton(&["Hips", "Spine"]);
+        let lowpass = "lowpass:3".parse().unwrap();
+        smooth(&mut animation, &skeleton, 1.0 / 30.0, lowpass, &[]).unwrap();
+
+        let middle = frame_count / 2;
+        assert!(animation.root_positions[middle].x.abs() < 0.01);
+        assert!((animation.root_positions[middle].y - 90.0).abs() < 1e-3);
+        let rotation = animation.joint_rotations[0][middle];
+        assert!(rotation.angle_between(Quat::from_rotation_y(0.5)) < 0.02);
+        assert!((rotation.length() - 1.0).abs() < 1e-5);
+        // A constant rotation stays as it is.
+        assert!(
+            animation.joint_rotations[1][middle].angle_between(Quat::from_rotation_x(0.25)) < 1e-5
+        );
+    }
+
+    #[test]
+    fn test_savitzky_golay_keeps_polynomials() {
+        let coefficients = savitzky_golay_coefficients(5, 2);
+        let expected = [-3.0, 12.0, 17.0, 12.0, -3.0].map(|c| c / 35.0);
+        for (c, e) in coefficients.iter().zip(expected) {
+            assert!((c - e).abs() < 1e-6);
+        }
+
+        let frame_count = 20;
+        let mut animation = Animation {
+            root_positions: (0..frame_count)
+                .map(|i| Vec3::new(0.0, (i * i) as f32, 0.0))
+                .collect(),
+            joint_rotations: vec![
+                (0..frame_count)
+                    .map(|i| Quat::from_rotation_z(0.1 * i as f32))
+                    .collect(),
+                vec![Quat::IDENTITY; frame_count],
+            ],
+            events: vec![],
+        };
+        let expected = animation.clone();
+        let skeleton = skeleton(&["Hips", "Hand"]);
+        let joints = ["Hand=lowpass:12".parse().unwrap()];
+        smooth(
+            &mut animation,
+            &skeleton,
+            1.0 / 30.0,
+            "savgol:5".parse().unwrap(),
+            &joints,
+        )
+        .unwrap();
+        // Away from the padded ends, a parabola and a constant rate turn are fitted exactly.
+        for frame in 2..frame_count - 2 {
+            assert!(
+                (animation.root_positions[frame].y - expected.root_positions[frame].y).abs() < 1e-3
+            );
+            let rotation = animation.joint_rotations[0][frame];
+            assert!(rotation.angle_between(expected.joint_rotations[0][frame]) < 1e-3);
+        }
+
+        let unknown = ["Tail=lowpass:12".parse().unwrap()];
+        assert!(
+            smooth(
+                &mut animation,
+                &skeleton,
+                1.0 / 30.0,
+                "savgol:5".parse().unwrap(),
+                &unknown
+            )
+            .is_err()
+        );
+        assert!("savgol:4".parse::<Smoothing>().is_err());
+        assert!("lowpass:0".parse::<Smoothing>().is_err());
+    }
+}
@@ -0,0 +1,521 @@
+//! Dataset files on the local disk or in object storage, since datasets outgrow the disks of
+//! training machines. Paths starting with `s3://` or `gs://` are objects in a bucket, read and
+//! written through the `object_store` crate when built with the `object-store` feature, with
+//! credentials from the usual AWS or Google Cloud environment variables. Every other path is a
+//! local file.
+//!
+//! Writes are IO bound on network filesystems, so how local files are written can be tuned with
+//! [`set_write_options`]: files are streamed through a buffer by default, or rendered in memory
+//! and written in chunks by several threads at once, optionally bypassing the page cache. The
+//! bytes and time of every write are counted, see [`write_stats`].
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Cursor, Read, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, RwLock},
+    time::Instant,
+};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+
+const REMOTE_SCHEMES: [&str; 2] = ["s3://", "gs://"];
+
+/// Whether `path` is an object storage URI rather than a local path.
+pub fn is_remote(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|path| REMOTE_SCHEMES.iter().any(|scheme| path.starts_with(scheme)))
+}
+
+pub fn exists(path: &Path) -> Result<bool> {
+    if is_remote(path) {
+        remote::exists(path)
+    } else {
+        Ok(path.exists())
+    }
+}
+
+/// Opens the file at `path` for reading. Objects are downloaded completely first.
+pub fn open(path: &Path) -> Result<Box<dyn Read>> {
+    if is_remote(path) {
+        return Ok(Box::new(Cursor::new(remote::read(path)?)));
+    }
+    let file = File::open(path).with_context(|| format!("Could not open {}", path.display()))?;
+    Ok(Box::new(BufReader::new(file)))
+}
+
+pub fn read(path: &Path) -> Result<Vec<u8>> {
+    if is_remote(path) {
+        remote::read(path)
+    } else {
+        std::fs::read(path).with_context(|| format!("Could not read {}", path.display()))
+    }
+}
+
+/// Alignment of the offsets, lengths and buffers of direct IO, the largest common block size.
+pub const DIRECT_IO_ALIGNMENT: usize = 4096;
+
+/// How local files are written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WriteOptions {
+    /// Bytes buffered before they're written, or reserved up front for files rendered in memory.
+    pub buffer_size: usize,
+    /// Bytes per chunk of files written in chunks, a multiple of [`DIRECT_IO_ALIGNMENT`] with
+    /// direct IO.
+    pub chunk_size: usize,
+    /// Chunks written at once. With more than one, files are rendered in memory first.
+    pub threads: usize,
+    /// Bypasses the page cache, on Linux. Files are rendered in memory first.
+    pub direct_io: bool,
+}
+
+const DEFAULT_WRITE_OPTIONS: WriteOptions = WriteOptions {
+    buffer_size: 8 * 1024,
+    chunk_size: 8 * 1024 * 1024,
+    threads: 1,
+    direct_io: false,
+};
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        DEFAULT_WRITE_OPTIONS
+    }
+}
+
+impl WriteOptions {
+    fn chunked(&self) -> bool {
+        self.threads > 1 || self.direct_io
+    }
+}
+
+/// Bytes and time spent writing files.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct WriteStats {
+    pub files: usize,
+    pub bytes: u64,
+    pub seconds: f64,
+}
+
+impl WriteStats {
+    /// Megabytes written per second.
+    pub fn throughput(&self) -> f64 {
+        if self.seconds > 0.0 {
+            self.bytes as f64 / 1e6 / self.seconds
+        } else {
+            0.0
+        }
+    }
+}
+
+static WRITE_OPTIONS: RwLock<WriteOptions> = RwLock::new(DEFAULT_WRITE_OPTIONS);
+static WRITE_STATS: Mutex<WriteStats> = Mutex::new(WriteStats {
+    files: 0,
+    bytes: 0,
+    seconds: 0.0,
+});
+
+/// Sets how every later write of a local file is done.
+pub fn set_write_options(options: WriteOptions) -> Result<()> {
+    if options.buffer_size == 0 || options.chunk_size == 0 || options.threads == 0 {
+        bail!("Write buffer, chunk size and threads have to be positive");
+    }
+    if options.direct_io {
+        if !cfg!(target_os = "linux") {
+            bail!("Direct IO is only supported on Linux");
+        }
+        if !options.chunk_size.is_multiple_of(DIRECT_IO_ALIGNMENT) {
+            bail!(
+                "The chunk size of direct IO has to be a multiple of {} bytes",
+                DIRECT_IO_ALIGNMENT
+            );
+        }
+    }
+    *WRITE_OPTIONS.write().unwrap_or_else(|e| e.into_inner()) = options;
+    Ok(())
+}
+
+/// Files, bytes and time written so far by this process.
+pub fn write_stats() -> WriteStats {
+    *WRITE_STATS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn record_write(bytes: u64, start: Instant) {
+    let mut stats = WRITE_STATS.lock().unwrap_or_else(|e| e.into_inner());
+    stats.files += 1;
+    stats.bytes += bytes;
+    stats.seconds += start.elapsed().as_secs_f64();
+}
+
+/// Counts the bytes written through it.
+struct CountingWriter<W> {
+    inner: W,
+    bytes: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.bytes += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes the file at `path` with `write`, creating the folders of a local path. Objects are
+/// uploaded once `write` returns.
+pub fn write_with(path: &Path, write: impl FnOnce(&mut dyn Write) -> Result<()>) -> Result<()> {
+    let start = Instant::now();
+    let options = *WRITE_OPTIONS.read().unwrap_or_else(|e| e.into_inner());
+    if is_remote(path) || options.chunked() {
+        let mut bytes = Vec::with_capacity(options.buffer_size);
+        write(&mut bytes)?;
+        let length = bytes.len() as u64;
+        if is_remote(path) {
+            remote::write(path, bytes)?;
+        } else {
+            write_chunked(path, &bytes, &options)?;
+        }
+        record_write(length, start);
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let file =
+        File::create(path).with_context(|| format!("Could not create {}", path.display()))?;
+    let mut writer = CountingWriter {
+        inner: BufWriter::with_capacity(options.buffer_size, file),
+        bytes: 0,
+    };
+    write(&mut writer)?;
+    writer.flush()?;
+    record_write(writer.bytes, start);
+    Ok(())
+}
+
+/// `bytes` in chunks of `size`, with their offsets in a file where `bytes` start at `start`.
+fn offset_chunks(bytes: &[u8], start: usize, size: usize) -> Vec<(usize, &[u8])> {
+    bytes
+        .chunks(size)
+        .enumerate()
+        .map(|(index, chunk)| (start + index * size, chunk))
+        .collect()
+}
+
+/// Writes `bytes` to the local file at `path` in chunks, `options.threads` at a time.
+fn write_chunked(path: &Path, bytes: &[u8], options: &WriteOptions) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let file =
+        File::create(path).with_context(|| format!("Could not create {}", path.display()))?;
+    file.set_len(bytes.len() as u64)?;
+    // With direct IO, the tail that isn't a whole block goes through the page cache.
+    let direct_length = if options.direct_io {
+        bytes.len() / DIRECT_IO_ALIGNMENT * DIRECT_IO_ALIGNMENT
+    } else {
+        0
+    };
+    let direct = offset_chunks(&bytes[..direct_length], 0, options.chunk_size);
+    let buffered = offset_chunks(&bytes[direct_length..], direct_length, options.chunk_size);
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..options.threads)
+            .map(|worker| {
+                let (file, direct, buffered) = (&file, &direct, &buffered);
+                scope.spawn(move || -> Result<()> {
+                    let direct: Vec<_> = direct
+                        .iter()
+                        .skip(worker)
+                        .step_by(options.threads)
+                        .copied()
+                        .collect();
+                    if !direct.is_empty() {
+                        positioned::write_direct(path, &direct, options.chunk_size)?;
+                    }
+                    for &(offset, chunk) in buffered.iter().skip(worker).step_by(options.threads) {
+                        positioned::write_at(file, chunk, offset as u64)?;
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        workers.into_iter().try_for_each(|worker| {
+            worker
+                .join()
+                .unwrap_or_else(|_| bail!("A writer thread panicked"))
+        })
+    })
+    .with_context(|| format!("Could not write {}", path.display()))
+}
+
+#[cfg(unix)]
+mod positioned {
+    use std::{fs::File, os::unix::fs::FileExt, path::Path};
+
+    use anyhow::Result;
+
+    pub fn write_at(file: &File, bytes: &[u8], offset: u64) -> Result<()> {
+        Ok(file.write_all_at(bytes, offset)?)
+    }
+
+    /// Writes whole blocks with `O_DIRECT`, copied through a block-aligned buffer.
+    #[cfg(target_os = "linux")]
+    pub fn write_direct(path: &Path, chunks: &[(usize, &[u8])], chunk_size: usize) -> Result<()> {
+        use std::{fs::OpenOptions, os::unix::fs::OpenOptionsExt};
+
+        use anyhow::Context;
+
+        use super::DIRECT_IO_ALIGNMENT;
+
+        let file = OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_DIRECT)
+            .open(path)
+            .context("Could not open the file for direct IO")?;
+        let mut buffer = vec![0u8; chunk_size + DIRECT_IO_ALIGNMENT];
+        let start = buffer.as_ptr().align_offset(DIRECT_IO_ALIGNMENT);
+        for &(offset, chunk) in chunks {
+            let aligned = &mut buffer[start..start + chunk.len()];
+            aligned.copy_from_slice(chunk);
+            write_at(&file, aligned, offset as u64)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn write_direct(_path: &Path, _chunks: &[(usize, &[u8])], _size: usize) -> Result<()> {
+        anyhow::bail!("Direct IO is only supported on Linux")
+    }
+}
+
+#[cfg(windows)]
+mod positioned {
+    use std::{fs::File, os::windows::fs::FileExt, path::Path};
+
+    use anyhow::{Result, bail};
+
+    pub fn write_at(file: &File, mut bytes: &[u8], mut offset: u64) -> Result<()> {
+        while !bytes.is_empty() {
+            let written = file.seek_write(bytes, offset)?;
+            bytes = &bytes[written..];
+            offset += written as u64;
+        }
+        Ok(())
+    }
+
+    pub fn write_direct(_path: &Path, _chunks: &[(usize, &[u8])], _size: usize) -> Result<()> {
+        bail!("Direct IO is only supported on Linux")
+    }
+}
+
+/// Parses a size in bytes, with an optional `K`, `M` or `G` suffix for powers of 1024.
+pub fn parse_size(size: &str) -> Result<usize> {
+    let (number, scale) = match size.char_indices().last() {
+        Some((index, 'K' | 'k')) => (&size[..index], 1 << 10),
+        Some((index, 'M' | 'm')) => (&size[..index], 1 << 20),
+        Some((index, 'G' | 'g')) => (&size[..index], 1 << 30),
+        _ => (size, 1),
+    };
+    match number.parse::<usize>() {
+        Ok(number) if number > 0 => Ok(number * scale),
+        _ => bail!("Invalid size {}, expected bytes like 4096, 64K or 8M", size),
+    }
+}
+
+/// Files in `folder`, sorted, and in its subfolders too when `recursive` is set.
+pub fn list_files(folder: &Path, recursive: bool) -> Result<Vec<PathBuf>> {
+    if is_remote(folder) {
+        return remote::list_files(folder, recursive);
+    }
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(folder)
+        .with_context(|| format!("Could not read {}", folder.display()))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    paths.sort();
+    let mut files = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            files.push(path);
+        } else if recursive {
+            files.extend(list_files(&path, recursive)?);
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(feature = "object-store")]
+mod remote {
+    use std::{
+        collections::BTreeMap,
+        path::{Path, PathBuf},
+        sync::{Arc, Mutex, OnceLock},
+    };
+
+    use anyhow::{Context, Result};
+    use futures::TryStreamExt;
+    use object_store::{
+        ObjectStore, aws::AmazonS3Builder, gcp::GoogleCloudStorageBuilder, path::Path as ObjectPath,
+    };
+    use tokio::runtime::Runtime;
+
+    /// The runtime and the client of every bucket are kept for the whole process, a converted
+    /// clip makes several requests and setting up a client costs more than most of them.
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    static STORES: Mutex<BTreeMap<String, Arc<dyn ObjectStore>>> = Mutex::new(BTreeMap::new());
+
+    /// Store of the bucket of `path`, the key of the object within it and the URI of the
+    /// bucket.
+    fn open_store(path: &Path) -> Result<(Arc<dyn ObjectStore>, ObjectPath, String)> {
+        let uri = path.to_str().context("Object storage URIs must be UTF-8")?;
+        let (scheme, rest) = uri.split_once("://").context("Missing URI scheme")?;
+        let (bucket, key) = rest.split_once('/').unwrap_or((rest, ""));
+        let bucket_uri = format!("{}://{}", scheme, bucket);
+        let mut stores = STORES.lock().unwrap();
+        let store = match stores.get(&bucket_uri) {
+            Some(store) => store.clone(),
+            None => {
+                let store: Arc<dyn ObjectStore> = match scheme {
+                    "s3" => Arc::new(
+                        AmazonS3Builder::from_env()
+                            .with_bucket_name(bucket)
+                            .build()?,
+                    ),
+                    _ => Arc::new(
+                        GoogleCloudStorageBuilder::from_env()
+                            .with_bucket_name(bucket)
+                            .build()?,
+                    ),
+                };
+                stores.insert(bucket_uri.clone(), store.clone());
+                store
+            }
+        };
+        Ok((store, ObjectPath::from(key), bucket_uri))
+    }
+
+    fn block_on<F: Future>(future: F) -> Result<F::Output> {
+        let runtime = match RUNTIME.get() {
+            Some(runtime) => runtime,
+            None => {
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()?;
+                RUNTIME.get_or_init(|| runtime)
+            }
+        };
+        Ok(runtime.block_on(future))
+    }
+
+    pub fn exists(path: &Path) -> Result<bool> {
+        let (store, key, _) = open_store(path)?;
+        match block_on(store.head(&key))? {
+            Ok(_) => Ok(true),
+            Err(object_store::Error::NotFound { .. }) => Ok(false),
+            Err(e) => Err(e).with_context(|| format!("Could not find {}", path.display())),
+        }
+    }
+
+    pub fn read(path: &Path) -> Result<Vec<u8>> {
+        let (store, key, _) = open_store(path)?;
+        let bytes = block_on(async { store.get(&key).await?.bytes().await })?
+            .with_context(|| format!("Could not download {}", path.display()))?;
+        Ok(bytes.to_vec())
+    }
+
+    pub fn write(path: &Path, bytes: Vec<u8>) -> Result<()> {
+        let (store, key, _) = open_store(path)?;
+        block_on(store.put(&key, bytes.into()))?
+            .with_context(|| format!("Could not upload {}", path.display()))?;
+        Ok(())
+    }
+
+    pub fn list_files(folder: &Path, recursive: bool) -> Result<Vec<PathBuf>> {
+        let (store, prefix, bucket) = open_store(folder)?;
+        let objects = block_on(async {
+            if recursive {
+                store.list(Some(&prefix)).try_collect::<Vec<_>>().await
+            } else {
+                Ok(store.list_with_delimiter(Some(&prefix)).await?.objects)
+            }
+        })?
+        .with_context(|| format!("Could not list {}", folder.display()))?;
+        let mut files: Vec<PathBuf> = objects
+            .into_iter()
+            .map(|object| PathBuf::from(format!("{}/{}", bucket, object.location)))
+            .collect();
+        files.sort();
+        Ok(files)
+    }
+}
+
+#[cfg(not(feature = "object-store"))]
+mod remote {
+    use std::path::{Path, PathBuf};
+
+    use anyhow::{Result, bail};
+
+    fn unsupported<T>(path: &Path) -> Result<T> {
+        bail!(
+            "{} is in object storage, which needs bvh_to_gav built with the object-store feature",
+            path.display()
+        )
+    }
+
+    pub fn exists(path: &Path) -> Result<bool> {
+        unsupported(path)
+    }
+
+    pub fn read(path: &Path) -> Result<Vec<u8>> {
+        unsupported(path)
+    }
+
+    pub fn write(path: &Path, _bytes: Vec<u8>) -> Result<()> {
+        unsupported(path)
+    }
+
+    pub fn list_files(folder: &Path, _recursive: bool) -> Result<Vec<PathBuf>> {
+        unsupported(folder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remote_paths_keep_their_uri() {
+        let folder = Path::new("s3://mocap/cmu");
+        assert!(is_remote(folder));
+        assert!(is_remote(Path::new("gs://mocap")));
+        assert!(!is_remote(Path::new("data/s3/walk.bvh")));
+        let clip = folder.join("walk.bvh");
+        assert_eq!(
+            clip.with_extension("npy").to_str(),
+            Some("s3://mocap/cmu/walk.npy")
+        );
+        assert_eq!(clip.parent(), Some(folder));
+        assert_eq!(clip.strip_prefix(folder).unwrap(), Path::new("walk.bvh"));
+    }
+
+    #[test]
+    fn test_chunked_writes_match_streamed_ones() {
+        assert_eq!(parse_size("64K").unwrap(), 65536);
+        assert_eq!(parse_size("4096").unwrap(), 4096);
+        assert!(parse_size("8X").is_err());
+
+        let path = std::env::temp_dir().join(format!("animgen_chunks_{}.bin", std::process::id()));
+        let bytes: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let options = WriteOptions {
+            chunk_size: 1000,
+            threads: 3,
+            ..Default::default()
+        };
+        write_chunked(&path, &bytes, &options).unwrap();
+        let written = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written, bytes);
+    }
+}
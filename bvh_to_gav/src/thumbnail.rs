@@ -0,0 +1,30 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use anyhow::{Context, Result, bail};
+use image::{
+    Delay, Frame,
+    codecs::gif::{GifEncoder, Repeat},
+    imageops::{self, FilterType},
+};
+
+/// Encodes a sequence of rendered frames into a looping GIF, scaled down to `width` pixels.
+pub fn encode_gif<P: AsRef<Path>>(frames: &[P], output: &Path, fps: u32, width: u32) -> Result<()> {
+    if frames.is_empty() {
+        bail!("No frames to encode into {}", output.display());
+    }
+
+    let writer = BufWriter::new(File::create(output)?);
+    let mut encoder = GifEncoder::new_with_speed(writer, 10);
+    encoder.set_repeat(Repeat::Infinite)?;
+    let delay = Delay::from_numer_denom_ms(1000, fps.max(1));
+    for path in frames {
+        let path = path.as_ref();
+        let image = image::open(path)
+            .with_context(|| format!("Could not read frame {}", path.display()))?
+            .into_rgba8();
+        let height = (image.height() as u64 * width as u64 / image.width().max(1) as u64) as u32;
+        let image = imageops::resize(&image, width, height.max(1), FilterType::Triangle);
+        encoder.encode_frame(Frame::from_parts(image, 0, 0, delay))?;
+    }
+    Ok(())
+}
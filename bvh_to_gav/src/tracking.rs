@@ -0,0 +1,215 @@
+//! Per-frame constraint descriptors for physics-based retargeting and tracking controllers
+//! (DeepMimic-style trackers), computed from the foot contacts of [`crate::contacts`] and the
+//! global positions of [`crate::kinematics`]. They are written next to a clip as
+//! `<clip>.constraints.json`:
+//!
+//! ```json
+//! {
+//!   "format": "animgen-constraints",
+//!   "version": 1,
+//!   "fps": 30.0,
+//!   "up": "y",
+//!   "ground_height": 0.0,
+//!   "pelvis": "Hips",
+//!   "contact_joints": ["LeftToe", "RightToe"],
+//!   "hand_joints": ["LeftHand", "RightHand"],
+//!   "frames": [
+//!     {
+//!       "frame": 0,
+//!       "time": 0.0,
+//!       "pelvis_height": 92.5,
+//!       "contacts": [{ "joint": "LeftToe", "position": [10.2, 0.4, 3.1] }],
+//!       "hands": [{ "joint": "LeftHand", "position": [40.0, 100.2, 5.0] }, ...]
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! Positions are global, in skeleton units (usually cm) with Y up. `contacts` lists only the
+//! contact joints touching the ground in that frame, `hands` every hand joint in every frame.
+//! The pelvis height is measured from the ground, the lowest height any contact joint reaches.
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use bevy_math::Vec3;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Animation,
+    contacts::{ContactConfig, detect_contacts},
+    fingers::finger_of,
+    frame_rate::frame_rate,
+    kinematics::global_positions,
+    skeleton::Skeleton,
+    storage,
+};
+
+pub const CONSTRAINTS_EXTENSION: &str = "constraints.json";
+pub const CONSTRAINTS_FORMAT: &str = "animgen-constraints";
+pub const CONSTRAINTS_VERSION: u32 = 1;
+
+/// Path of the constraints written next to a clip.
+pub fn constraints_path(clip: &Path) -> PathBuf {
+    clip.with_extension(CONSTRAINTS_EXTENSION)
+}
+
+/// Hand joints found by name, skipping the fingers and end sites.
+pub fn hand_joints(skeleton: &Skeleton) -> Vec<usize> {
+    skeleton
+        .joint_order()
+        .enumerate()
+        .filter(|(_, name)| {
+            let name = name.to_lowercase();
+            (name.contains("hand") || name.contains("wrist"))
+                && !name.ends_with("end")
+                && finger_of(&name).is_none()
+        })
+        .map(|(index, _)| index)
+        .collect()
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct JointTarget {
+    pub joint: String,
+    pub position: Vec3,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FrameConstraints {
+    pub frame: usize,
+    /// Seconds from the first frame.
+    pub time: f32,
+    pub pelvis_height: f32,
+    pub contacts: Vec<JointTarget>,
+    pub hands: Vec<JointTarget>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ClipConstraints {
+    pub format: String,
+    pub version: u32,
+    pub fps: f32,
+    pub up: String,
+    pub ground_height: f32,
+    pub pelvis: String,
+    pub contact_joints: Vec<String>,
+    pub hand_joints: Vec<String>,
+    pub frames: Vec<FrameConstraints>,
+}
+
+impl ClipConstraints {
+    /// Constraints of `animation` with the foot contacts of `config`.
+    pub fn new(
+        skeleton: &Skeleton,
+        animation: &Animation,
+        frame_time: f32,
+        config: &ContactConfig,
+    ) -> Result<Self> {
+        let pelvis = skeleton
+            .roots()
+            .next()
+            .context("The skeleton has no root")?;
+        let hands = hand_joints(skeleton);
+        let contacts = detect_contacts(skeleton, animation, frame_time, config)?;
+        let positions = global_positions(skeleton, animation)?;
+        let position = |joint: usize, frame: usize| {
+            Vec3::new(
+                positions[[joint, frame, 0]],
+                positions[[joint, frame, 1]],
+                positions[[joint, frame, 2]],
+            )
+        };
+        let ground_height = config
+            .joints
+            .iter()
+            .flat_map(|&joint| (0..animation.frame_count()).map(move |frame| (joint, frame)))
+            .map(|(joint, frame)| positions[[joint, frame, 1]])
+            .fold(f32::INFINITY, f32::min);
+        let name = |joint: usize| skeleton.joints[joint].name.clone();
+        let target = |joint: usize, frame: usize| JointTarget {
+            joint: name(joint),
+            position: position(joint, frame),
+        };
+
+        let frames = (0..animation.frame_count())
+            .map(|frame| FrameConstraints {
+                frame,
+                time: frame as f32 * frame_time,
+                pelvis_height: position(pelvis, frame).y - ground_height,
+                contacts: config
+                    .joints
+                    .iter()
+                    .enumerate()
+                    .filter(|&(column, _)| contacts[[frame, column]] > 0.5)
+                    .map(|(_, &joint)| target(joint, frame))
+                    .collect(),
+                hands: hands.iter().map(|&joint| target(joint, frame)).collect(),
+            })
+            .collect();
+        Ok(ClipConstraints {
+            format: CONSTRAINTS_FORMAT.to_string(),
+            version: CONSTRAINTS_VERSION,
+            fps: frame_rate(frame_time),
+            up: "y".to_string(),
+            ground_height,
+            pelvis: name(pelvis),
+            contact_joints: config.joints.iter().map(|&joint| name(joint)).collect(),
+            hand_joints: hands.iter().map(|&joint| name(joint)).collect(),
+            frames,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        storage::write_with(path, |writer| {
+            Ok(serde_json::to_writer_pretty(writer, self)?)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_math::Quat;
+
+    use super::*;
+    use crate::skeleton::SkeletonJoint;
+
+    #[test]
+    fn test_constraints_follow_contacts_and_hands() {
+        let joint = |name: &str, parent, offset| SkeletonJoint {
+            name: name.to_string(),
+            parent,
+            offset,
+            end_site: None,
+        };
+        let skeleton = Skeleton {
+            joints: vec![
+                joint("Hips", None, Vec3::ZERO),
+                joint("LeftToe", Some(0), Vec3::new(0.0, -90.0, 10.0)),
+                joint("LeftHand", Some(0), Vec3::new(40.0, 10.0, 0.0)),
+                joint("LeftHandIndex1", Some(2), Vec3::new(5.0, 0.0, 0.0)),
+            ],
+        };
+        // Standing for 5 frames, then moving off at 3 m/s.
+        let frames: usize = 10;
+        let root_positions = (0..frames)
+            .map(|f| Vec3::new(f.saturating_sub(4) as f32 * 10.0, 90.0, 0.0))
+            .collect();
+        let animation = Animation {
+            root_positions,
+            joint_rotations: vec![vec![Quat::IDENTITY; frames]; 4],
+            events: vec![],
+        };
+        let config = ContactConfig::new(&skeleton, &[]).unwrap();
+        let constraints = ClipConstraints::new(&skeleton, &animation, 1.0 / 30.0, &config).unwrap();
+
+        assert_eq!(constraints.pelvis, "Hips");
+        assert_eq!(constraints.contact_joints, vec!["LeftToe"]);
+        assert_eq!(constraints.hand_joints, vec!["LeftHand"]);
+        assert_eq!(constraints.ground_height, 0.0);
+        let first = &constraints.frames[0];
+        assert_eq!(first.pelvis_height, 90.0);
+        assert_eq!(first.contacts[0].position, Vec3::new(0.0, 0.0, 10.0));
+        assert_eq!(first.hands[0].position, Vec3::new(40.0, 100.0, 0.0));
+        assert!(constraints.frames[8].contacts.is_empty());
+    }
+}
@@ -0,0 +1,159 @@
+//! Sanity checks of a loaded clip, to catch broken exports and damaged tensors before they end
+//! up in a training run. Every problem is reported, not only the first one.
+use ndarray::{Array3, Axis};
+
+use crate::{Animation, skeleton::Skeleton};
+
+/// How far a joint rotation may be from unit length before it is reported.
+const UNIT_TOLERANCE: f32 = 1e-3;
+
+/// Problems with `animation` and its `skeleton`, empty when the clip is valid.
+pub fn validate_clip(skeleton: &Skeleton, animation: &Animation, frame_time: f32) -> Vec<String> {
+    let mut problems = Vec::new();
+    if !frame_time.is_finite() || frame_time <= 0.0 {
+        problems.push(format!("invalid frame time {}", frame_time));
+    }
+    if animation.frame_count() == 0 {
+        problems.push("no frames".to_string());
+    }
+    if animation.joint_count() != skeleton.joint_count() {
+        problems.push(format!(
+            "{} rotation tracks for {} joints",
+            animation.joint_count(),
+            skeleton.joint_count()
+        ));
+    }
+
+    for (index, joint) in skeleton.joints.iter().enumerate() {
+        if joint
+            .parent
+            .is_some_and(|parent| parent == index || parent >= skeleton.joint_count())
+        {
+            problems.push(format!("{} has an invalid parent", joint.name));
+        }
+        if skeleton.joints[..index]
+            .iter()
+            .any(|j| j.name == joint.name)
+        {
+            problems.push(format!("more than one joint is called {}", joint.name));
+        }
+        if !joint.offset.is_finite() {
+            problems.push(format!("{} has an invalid offset", joint.name));
+        }
+    }
+
+    if let Some(frame) = animation.root_positions.iter().position(|p| !p.is_finite()) {
+        problems.push(format!("invalid root position at frame {}", frame));
+    }
+    for (joint, rotations) in animation.joint_rotations.iter().enumerate() {
+        let name = skeleton
+            .joints
+            .get(joint)
+            .map_or_else(|| format!("track {}", joint), |j| j.name.clone());
+        let invalid = rotations
+            .iter()
+            .position(|q| !q.is_finite() || (q.length() - 1.0).abs() > UNIT_TOLERANCE);
+        if let Some(frame) = invalid {
+            problems.push(format!("invalid rotation of {} at frame {}", name, frame));
+        }
+        if rotations.len() != animation.frame_count() {
+            problems.push(format!(
+                "{} has {} frames, the root {}",
+                name,
+                rotations.len(),
+                animation.frame_count()
+            ));
+        }
+    }
+    if let Some(event) = animation
+        .events
+        .iter()
+        .find(|event| event.frame >= animation.frame_count())
+    {
+        problems.push(format!(
+            "event {} at frame {} is past the end",
+            event.name, event.frame
+        ));
+    }
+    problems
+}
+
+/// Range of the values of a channel of a tensor, over its frames.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChannelStats {
+    /// Smallest value that isn't NaN, infinite when every value is NaN.
+    pub min: f32,
+    pub max: f32,
+    pub nan_count: usize,
+}
+
+/// Stats of every channel of every curve of a `(curves, frames, channels)` tensor.
+pub fn channel_stats(data: &Array3<f32>) -> Vec<Vec<ChannelStats>> {
+    data.axis_iter(Axis(0))
+        .map(|curve| {
+            curve
+                .axis_iter(Axis(1))
+                .map(|values| ChannelStats {
+                    min: values.iter().copied().fold(f32::INFINITY, f32::min),
+                    max: values.iter().copied().fold(f32::NEG_INFINITY, f32::max),
+                    nan_count: values.iter().filter(|v| v.is_nan()).count(),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_math::{Quat, Vec3};
+
+    use super::*;
+    use crate::skeleton::SkeletonJoint;
+
+    #[test]
+    fn test_every_problem_is_reported() {
+        let joint = |name: &str, parent| SkeletonJoint {
+            name: name.to_string(),
+            parent,
+            offset: Vec3::Y,
+            end_site: None,
+        };
+        let skeleton = Skeleton {
+            joints: vec![joint("Hips", None), joint("Spine", Some(0))],
+        };
+        let mut animation = Animation {
+            root_positions: vec![Vec3::ZERO; 3],
+            joint_rotations: vec![vec![Quat::IDENTITY; 3]; 2],
+            events: vec![],
+        };
+        assert!(validate_clip(&skeleton, &animation, 1.0 / 30.0).is_empty());
+
+        animation.root_positions[1].x = f32::NAN;
+        animation.joint_rotations[1][2] = Quat::from_xyzw(0.5, 0.0, 0.0, 0.5);
+        let mut broken = skeleton.clone();
+        broken.joints.push(joint("Spine", Some(3)));
+        assert_eq!(
+            validate_clip(&broken, &animation, 0.0),
+            vec![
+                "invalid frame time 0",
+                "2 rotation tracks for 3 joints",
+                "Spine has an invalid parent",
+                "more than one joint is called Spine",
+                "invalid root position at frame 1",
+                "invalid rotation of Spine at frame 2",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_channel_stats_skip_nans() {
+        let data = Array3::from_shape_vec((1, 3, 2), vec![1.0, f32::NAN, -2.0, f32::NAN, 4.0, 0.5])
+            .unwrap();
+        let stats = channel_stats(&data);
+        assert_eq!(stats[0][0].min, -2.0);
+        assert_eq!(stats[0][0].max, 4.0);
+        assert_eq!(stats[0][0].nan_count, 0);
+        assert_eq!((stats[0][1].min, stats[0][1].max), (0.5, 0.5));
+        assert_eq!(stats[0][1].nan_count, 2);
+    }
+}
@@ -0,0 +1,108 @@
+//! Scalars a model produced for every frame of a clip, such as attention weights or
+//! uncertainty, saved next to the clip as `<clip>.weights.npy`. The array is either one value
+//! per frame, `(frames,)`, or one per joint and frame, `(frames, joints)` with the joints in the
+//! order of the clip.
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, bail};
+use ndarray::{Array2, ArrayD, Axis, Ix1, Ix2};
+use ndarray_npy::read_npy;
+
+pub const WEIGHTS_EXTENSION: &str = "weights.npy";
+
+pub fn weights_path(clip: &Path) -> PathBuf {
+    clip.with_extension(WEIGHTS_EXTENSION)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameWeights {
+    /// `(frames, channels)`, with a single channel for per-frame weights.
+    values: Array2<f32>,
+    min: f32,
+    max: f32,
+}
+
+impl FrameWeights {
+    pub fn from_array(array: ArrayD<f32>) -> Result<Self> {
+        let values = match array.ndim() {
+            1 => array.into_dimensionality::<Ix1>()?.insert_axis(Axis(1)),
+            2 => array.into_dimensionality::<Ix2>()?,
+            _ => bail!(
+                "Expected weights of shape (frames,) or (frames, joints), got {:?}",
+                array.shape()
+            ),
+        };
+        let (min, max) = values
+            .iter()
+            .filter(|value| value.is_finite())
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), value| {
+                (min.min(*value), max.max(*value))
+            });
+        Ok(FrameWeights { values, min, max })
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        Self::from_array(read_npy(path)?)
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.values.nrows()
+    }
+
+    /// Whether there is a weight for every joint, rather than one for the whole frame.
+    pub fn per_joint(&self) -> bool {
+        self.values.ncols() > 1
+    }
+
+    pub fn joint_count(&self) -> usize {
+        self.values.ncols()
+    }
+
+    /// Lowest and highest finite weight of the clip.
+    pub fn range(&self) -> (f32, f32) {
+        (self.min, self.max)
+    }
+
+    pub fn value(&self, frame: usize, joint: usize) -> Option<f32> {
+        self.values.get([frame, joint]).copied()
+    }
+
+    /// Mean weight of `frame` over all joints.
+    pub fn frame_value(&self, frame: usize) -> Option<f32> {
+        (frame < self.frame_count()).then(|| self.values.row(frame).mean().unwrap_or(0.0))
+    }
+
+    /// `value` mapped to `0..=1` over the range of the clip, so colors are comparable between
+    /// frames.
+    pub fn normalize(&self, value: f32) -> f32 {
+        if self.max > self.min {
+            ((value - self.min) / (self.max - self.min)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{IxDyn, arr1, arr2};
+
+    use super::*;
+
+    #[test]
+    fn test_weights_per_frame_and_per_joint() {
+        let per_frame = FrameWeights::from_array(arr1(&[0.0, 2.0, 4.0]).into_dyn()).unwrap();
+        assert_eq!(per_frame.frame_count(), 3);
+        assert!(!per_frame.per_joint());
+        assert_eq!(per_frame.normalize(per_frame.value(1, 0).unwrap()), 0.5);
+        assert_eq!(per_frame.frame_value(3), None);
+
+        let per_joint =
+            FrameWeights::from_array(arr2(&[[1.0, 3.0], [f32::NAN, 5.0]]).into_dyn()).unwrap();
+        assert!(per_joint.per_joint());
+        assert_eq!(per_joint.range(), (1.0, 5.0));
+        assert_eq!(per_joint.frame_value(0), Some(2.0));
+
+        assert!(FrameWeights::from_array(ArrayD::zeros(IxDyn(&[2, 2, 2]))).is_err());
+    }
+}
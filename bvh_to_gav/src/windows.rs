@@ -0,0 +1,327 @@
+//! Fixed length training windows over a clip, and temporal positional encodings for each of
+//! them. The start frame of every window is saved next to the tensor of the clip as
+//! `<clip>.windows.npy`, and the encodings as `<clip>.positions.npy` with shape
+//! `(windows, length, dimensions)`, so a data loader only slices `tensor[:, start:start+length]`
+//! and never has to recompute either.
+//!
+//! Rotations are stored with a non-negative scalar part, so a joint turning through half a
+//! turn jumps to the opposite hemisphere, mid-window wherever the windows start. The sign of
+//! every rotation that makes each window continuous on its own is saved as
+//! `<clip>.hemispheres.npy`, see [`hemisphere_signs`], for loaders working with whole
+//! quaternions to multiply them by.
+//!
+//! For loaders that take whole tensors, every window can be written as a tensor of its own
+//! instead, `<clip>_w<index>.npy` with a skeleton sidecar, and recorded in
+//! `window_manifest.json` with the clip and frames it came from.
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{Context, Error, Result, bail};
+use bevy_math::Vec4;
+use ndarray::{Array1, Array2, Array3, Axis, s};
+use rand::{SeedableRng, rngs::StdRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{Animation, canonical_rotation, storage};
+
+pub const WINDOWS_EXTENSION: &str = "windows.npy";
+pub const POSITIONS_EXTENSION: &str = "positions.npy";
+pub const HEMISPHERES_EXTENSION: &str = "hemispheres.npy";
+/// File name of the manifest of split windows, in the output folder.
+pub const WINDOW_MANIFEST_FILE: &str = "window_manifest.json";
+pub const DEFAULT_ENCODING_DIMENSIONS: usize = 64;
+/// Wavelength scale of the sinusoidal encoding, as in "Attention Is All You Need".
+const SINUSOIDAL_BASE: f32 = 10000.0;
+
+pub fn windows_path(tensor: &Path) -> PathBuf {
+    tensor.with_extension(WINDOWS_EXTENSION)
+}
+
+pub fn positions_path(tensor: &Path) -> PathBuf {
+    tensor.with_extension(POSITIONS_EXTENSION)
+}
+
+pub fn hemispheres_path(tensor: &Path) -> PathBuf {
+    tensor.with_extension(HEMISPHERES_EXTENSION)
+}
+
+/// Path of the tensor of window `index` of the clip converted to `tensor`.
+pub fn window_path(tensor: &Path, index: usize) -> PathBuf {
+    let stem = tensor.file_stem().unwrap_or_default().to_string_lossy();
+    tensor.with_file_name(format!("{}_w{:04}.npy", stem, index))
+}
+
+/// Generator of the randomness of copy `copy` of window `window` of `clip`, such as a noise
+/// level or a masking strategy, seeded from all of them so a copy comes out the same whatever
+/// else is in the dataset.
+pub fn window_rng(seed: u64, clip: &str, window: usize, copy: usize) -> StdRng {
+    // FNV-1a, which unlike the std hashers is the same in every build.
+    let clip_hash = clip.bytes().fold(0xCBF2_9CE4_8422_2325, |hash: u64, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01B3)
+    });
+    // Spread so neighboring windows and copies don't get neighboring seeds.
+    let stream = (window as u64)
+        .wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        .wrapping_add((copy as u64).wrapping_mul(0xBF58_476D_1CE4_E5B9));
+    StdRng::seed_from_u64(seed ^ clip_hash ^ stream)
+}
+
+/// A window written as a tensor of its own.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ClipWindow {
+    pub window: String,
+    /// Tensor of the whole clip.
+    pub tensor: String,
+    /// Clip the tensor was converted from.
+    pub source: String,
+    pub start: usize,
+    /// Frame after the last one of the window.
+    pub end: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct WindowManifest {
+    pub windows: Vec<ClipWindow>,
+}
+
+impl WindowManifest {
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(serde_json::from_reader(storage::open(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        storage::write_with(path, |writer| {
+            Ok(serde_json::to_writer_pretty(writer, self)?)
+        })
+    }
+}
+
+/// `(windows, joints, length)` signs of the stored rotations of `animation` in each of
+/// `windows`. The first frame of a window keeps the stored hemisphere, and every later one is
+/// negated when it would otherwise flip from the frame before it. Windows are fixed
+/// independently, so a window starting right after a flip doesn't inherit its sign.
+pub fn hemisphere_signs(animation: &Animation, windows: &Windows) -> Array3<i8> {
+    let starts = windows.starts(animation.frame_count());
+    let mut signs = Array3::ones((starts.len(), animation.joint_count(), windows.length));
+    for (window, &start) in starts.iter().enumerate() {
+        for (joint, rotations) in animation.joint_rotations.iter().enumerate() {
+            let mut previous = Vec4::ZERO;
+            for (frame, rotation) in rotations[start..start + windows.length].iter().enumerate() {
+                let mut rotation = Vec4::from(canonical_rotation(*rotation));
+                if rotation.dot(previous) < 0.0 {
+                    rotation = -rotation;
+                    signs[[window, joint, frame]] = -1;
+                }
+                previous = rotation;
+            }
+        }
+    }
+    signs
+}
+
+/// Windows of `length` frames, `stride` frames apart, parsed from `LENGTH` or
+/// `LENGTH:STRIDE`. Without a stride the windows don't overlap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Windows {
+    pub length: usize,
+    pub stride: usize,
+}
+
+impl Windows {
+    /// Start frames of the windows that fit in `frame_count` frames. The frames after the
+    /// last whole window are left out.
+    pub fn starts(&self, frame_count: usize) -> Vec<usize> {
+        if frame_count < self.length {
+            return Vec::new();
+        }
+        (0..=frame_count - self.length)
+            .step_by(self.stride)
+            .collect()
+    }
+
+    pub fn starts_array(&self, frame_count: usize) -> Array1<i64> {
+        self.starts(frame_count)
+            .into_iter()
+            .map(|start| start as i64)
+            .collect()
+    }
+
+    /// The windows of the GAV tensor `data`, with their start frames.
+    pub fn split(&self, data: &Array3<f32>) -> Vec<(usize, Array3<f32>)> {
+        self.starts(data.dim().1)
+            .into_iter()
+            .map(|start| {
+                let window = data.slice(s![.., start..start + self.length, ..]);
+                (start, window.to_owned())
+            })
+            .collect()
+    }
+}
+
+impl FromStr for Windows {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (length, stride) = match s.split_once(':') {
+            Some((length, stride)) => (length, Some(stride)),
+            None => (s, None),
+        };
+        let length: usize = length
+            .parse()
+            .with_context(|| format!("Invalid window length: {}", length))?;
+        let stride: usize = match stride {
+            Some(stride) => stride
+                .parse()
+                .with_context(|| format!("Invalid window stride: {}", stride))?,
+            None => length,
+        };
+        if length == 0 || stride == 0 {
+            bail!("Window length and stride have to be positive");
+        }
+        Ok(Windows { length, stride })
+    }
+}
+
+/// Encoding of the position of each frame within its window, parsed from `index`,
+/// `sinusoidal` or `sinusoidal:DIMENSIONS`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PositionalEncoding {
+    /// Interleaved sines and cosines of geometrically increasing wavelengths.
+    Sinusoidal { dimensions: usize },
+    /// The frame index itself, as a placeholder for a learned embedding looked up by index.
+    Index,
+}
+
+impl PositionalEncoding {
+    /// `(length, dimensions)` encoding of one window.
+    pub fn encode(&self, length: usize) -> Array2<f32> {
+        match *self {
+            PositionalEncoding::Sinusoidal { dimensions } => {
+                Array2::from_shape_fn((length, dimensions), |(position, dimension)| {
+                    let pair = (dimension / 2 * 2) as f32 / dimensions as f32;
+                    let angle = position as f32 / SINUSOIDAL_BASE.powf(pair);
+                    if dimension % 2 == 0 {
+                        angle.sin()
+                    } else {
+                        angle.cos()
+                    }
+                })
+            }
+            PositionalEncoding::Index => {
+                Array2::from_shape_fn((length, 1), |(position, _)| position as f32)
+            }
+        }
+    }
+
+    /// `(windows, length, dimensions)` encodings of the windows of a clip of `frame_count`
+    /// frames.
+    pub fn encode_windows(&self, windows: &Windows, frame_count: usize) -> Array3<f32> {
+        let count = windows.starts(frame_count).len();
+        let window = self.encode(windows.length).insert_axis(Axis(0));
+        let (_, length, dimensions) = window.dim();
+        window
+            .broadcast((count, length, dimensions))
+            .map_or_else(|| Array3::zeros((0, length, dimensions)), |b| b.to_owned())
+    }
+}
+
+impl FromStr for PositionalEncoding {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            None if s == "index" => Ok(PositionalEncoding::Index),
+            None if s == "sinusoidal" => Ok(PositionalEncoding::Sinusoidal {
+                dimensions: DEFAULT_ENCODING_DIMENSIONS,
+            }),
+            Some(("sinusoidal", dimensions)) => match dimensions.parse() {
+                Ok(dimensions) if dimensions > 0 => {
+                    Ok(PositionalEncoding::Sinusoidal { dimensions })
+                }
+                _ => bail!("Invalid encoding dimensions: {}", dimensions),
+            },
+            _ => bail!(
+                "Unknown positional encoding {}, expected index or sinusoidal[:dimensions]",
+                s
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_math::{Quat, Vec3};
+
+    use super::*;
+
+    #[test]
+    fn test_windows_and_encodings_line_up() {
+        let windows: Windows = "4:2".parse().unwrap();
+        assert_eq!(windows.starts(9), vec![0, 2, 4]);
+        assert_eq!("4".parse::<Windows>().unwrap().starts(9), vec![0, 4]);
+        assert!(windows.starts(3).is_empty());
+        assert!("0".parse::<Windows>().is_err());
+
+        let data = Array3::from_shape_fn((2, 9, 3), |(_, frame, _)| frame as f32);
+        let split = windows.split(&data);
+        assert_eq!(split.len(), 3);
+        assert_eq!(split[1].0, 2);
+        assert_eq!(split[1].1.dim(), (2, 4, 3));
+        assert_eq!(split[1].1[[1, 3, 2]], 5.0);
+        assert_eq!(
+            window_path(Path::new("out/walk.npy"), 12),
+            Path::new("out/walk_w0012.npy")
+        );
+
+        let sinusoidal: PositionalEncoding = "sinusoidal:6".parse().unwrap();
+        let encodings = sinusoidal.encode_windows(&windows, 9);
+        assert_eq!(encodings.dim(), (3, 4, 6));
+        // Position 0 is sin(0) and cos(0) for every frequency.
+        assert_eq!(encodings[[2, 0, 0]], 0.0);
+        assert_eq!(encodings[[2, 0, 1]], 1.0);
+        assert!((encodings[[1, 1, 0]] - 1f32.sin()).abs() < 1e-6);
+        assert_eq!(
+            encodings.index_axis(Axis(0), 0),
+            encodings.index_axis(Axis(0), 2)
+        );
+
+        let index = PositionalEncoding::Index.encode_windows(&windows, 9);
+        assert_eq!(index.dim(), (3, 4, 1));
+        assert_eq!(index[[1, 3, 0]], 3.0);
+        assert_eq!(
+            PositionalEncoding::Index.encode_windows(&windows, 3).dim(),
+            (0, 4, 1)
+        );
+        assert!("learned".parse::<PositionalEncoding>().is_err());
+    }
+
+    #[test]
+    fn test_windows_are_continuous_on_their_own() {
+        // A full turn about Y in 40° steps, passing half a turn between frames 4 and 5.
+        let animation = Animation {
+            root_positions: vec![Vec3::ZERO; 9],
+            joint_rotations: vec![
+                (0..9)
+                    .map(|f| Quat::from_rotation_y(f as f32 * std::f32::consts::TAU / 9.0))
+                    .collect(),
+            ],
+            events: vec![],
+        };
+        let windows: Windows = "3:2".parse().unwrap();
+        let signs = hemisphere_signs(&animation, &windows);
+        assert_eq!(signs.dim(), (4, 1, 3));
+        assert!(signs.index_axis(Axis(0), 0).iter().all(|&sign| sign == 1));
+        assert_eq!(
+            signs
+                .index_axis(Axis(0), 2)
+                .iter()
+                .copied()
+                .collect::<Vec<_>>(),
+            vec![1, -1, -1]
+        );
+        // Starting after the flip, nothing to fix.
+        assert!(signs.index_axis(Axis(0), 3).iter().all(|&sign| sign == 1));
+    }
+}
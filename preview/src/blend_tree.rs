@@ -0,0 +1,469 @@
+//! Blend trees: a set of clips placed at points in a one or two dimensional parameter space.
+//! The preview blends the clips closest to the current parameter values, which makes it easy to
+//! judge whether a set of generated clips (e.g. walk and run cycles) blend well at runtime.
+//!
+//! Blend trees are RON files with the `.blendtree.ron` extension:
+//!
+//! ```ron
+//! (
+//!     parameters: [(name: "speed", min: 0.0, max: 4.0)],
+//!     clips: [
+//!         (path: "walk.bvh", position: [1.0]),
+//!         (path: "run.bvh", position: [3.0]),
+//!     ],
+//! )
+//! ```
+use bevy::{
+    asset::{AssetLoader, LoadContext, io::Reader},
+    prelude::*,
+};
+use bevy_egui::{EguiContexts, egui};
+use indexmap::IndexMap;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    Animation, asset_path,
+    bone_renderer::PoseRenderer,
+    bvh_asset_loader::{BvhAsset, JointHierarchy, KeyFrames},
+};
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct BlendParameter {
+    pub name: String,
+    pub min: f32,
+    pub max: f32,
+}
+
+#[derive(Deserialize)]
+struct BlendClipDefinition {
+    path: String,
+    position: Vec<f32>,
+}
+
+#[derive(Deserialize)]
+struct BlendTreeDefinition {
+    parameters: Vec<BlendParameter>,
+    clips: Vec<BlendClipDefinition>,
+}
+
+#[derive(Clone, Debug)]
+pub struct BlendClip {
+    pub bvh: Handle<BvhAsset>,
+    pub position: Vec<f32>,
+}
+
+#[derive(TypePath, Asset, Clone, Debug)]
+pub struct BlendTree {
+    pub parameters: Vec<BlendParameter>,
+    pub clips: Vec<BlendClip>,
+}
+
+impl BlendTree {
+    /// Blend weight of every clip for the given parameter values. One parameter interpolates
+    /// linearly between the two neighbouring clips, two parameters use inverse distance
+    /// weighting.
+    pub fn weights(&self, parameters: &[f32]) -> Vec<f32> {
+        blend_weights(
+            self.clips.iter().map(|clip| clip.position.as_slice()),
+            parameters,
+        )
+    }
+}
+
+fn blend_weights<'a>(positions: impl Iterator<Item = &'a [f32]>, parameters: &[f32]) -> Vec<f32> {
+    let positions: Vec<&[f32]> = positions.collect();
+    let mut weights = vec![0.0; positions.len()];
+    if positions.is_empty() {
+        return weights;
+    }
+
+    if parameters.len() == 1 {
+        let x = parameters[0];
+        let mut below: Option<usize> = None;
+        let mut above: Option<usize> = None;
+        for (i, position) in positions.iter().enumerate() {
+            let p = position[0];
+            if p <= x && below.is_none_or(|b| p > positions[b][0]) {
+                below = Some(i);
+            }
+            if p >= x && above.is_none_or(|a| p < positions[a][0]) {
+                above = Some(i);
+            }
+        }
+        match (below, above) {
+            (Some(b), Some(a)) if a != b => {
+                let t = (x - positions[b][0]) / (positions[a][0] - positions[b][0]);
+                weights[b] = 1.0 - t;
+                weights[a] = t;
+            }
+            (Some(i), _) | (_, Some(i)) => weights[i] = 1.0,
+            (None, None) => {}
+        }
+        return weights;
+    }
+
+    let distances: Vec<f32> = positions
+        .iter()
+        .map(|position| {
+            position
+                .iter()
+                .zip(parameters)
+                .map(|(p, x)| (p - x) * (p - x))
+                .sum::<f32>()
+                .sqrt()
+        })
+        .collect();
+    if let Some(exact) = distances.iter().position(|d| *d < 1e-6) {
+        weights[exact] = 1.0;
+        return weights;
+    }
+    for (weight, distance) in weights.iter_mut().zip(&distances) {
+        *weight = 1.0 / (distance * distance);
+    }
+    let total: f32 = weights.iter().sum();
+    weights.iter_mut().for_each(|w| *w /= total);
+    weights
+}
+
+#[derive(Default)]
+pub struct BlendTreeLoader;
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum BlendTreeLoaderError {
+    #[error("Could not load asset: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse blend tree: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    #[error("Invalid blend tree: {0}")]
+    Invalid(String),
+}
+
+impl AssetLoader for BlendTreeLoader {
+    type Asset = BlendTree;
+    type Settings = ();
+    type Error = BlendTreeLoaderError;
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let definition: BlendTreeDefinition = ron::de::from_bytes(&bytes)?;
+
+        let dimensions = definition.parameters.len();
+        if !(1..=2).contains(&dimensions) {
+            return Err(BlendTreeLoaderError::Invalid(format!(
+                "Expected 1 or 2 parameters, found {}",
+                dimensions
+            )));
+        }
+
+        let mut clips = Vec::with_capacity(definition.clips.len());
+        for clip in definition.clips {
+            if clip.position.len() != dimensions {
+                return Err(BlendTreeLoaderError::Invalid(format!(
+                    "Clip {} has {} coordinates but the tree has {} parameters",
+                    clip.path,
+                    clip.position.len(),
+                    dimensions
+                )));
+            }
+            // Clip paths are relative to the blend tree file.
+            let path = load_context
+                .asset_path()
+                .resolve(&clip.path)
+                .map_err(|e| BlendTreeLoaderError::Invalid(e.to_string()))?;
+            clips.push(BlendClip {
+                bvh: load_context.load(path),
+                position: clip.position,
+            });
+        }
+
+        Ok(BlendTree {
+            parameters: definition.parameters,
+            clips,
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["blendtree.ron"]
+    }
+}
+
+pub const BLEND_TREE_EXTENSION: &str = ".blendtree.ron";
+
+/// Playback state of a loaded blend tree.
+pub struct BlendTreePlayer {
+    pub(crate) tree: BlendTree,
+    pub(crate) clips: Vec<Animation>,
+    pub(crate) parameters: Vec<f32>,
+    /// Normalized playback time shared by all clips, so cycles of different length stay in sync.
+    phase: f32,
+    /// When set, the character is drawn in place at this transform instead of following the
+    /// root motion of the clips.
+    pub(crate) placement: Option<Transform>,
+}
+
+impl BlendTreePlayer {
+    /// Average horizontal root speed of the blended clips, in units per second.
+    pub(crate) fn root_speed(&self, weights: &[f32]) -> f32 {
+        self.clips
+            .iter()
+            .zip(weights)
+            .map(|(clip, weight)| {
+                let key_frames = &clip.key_frames;
+                let duration = (key_frames.count - 1) as f32 * key_frames.frame_time;
+                let Some(translations) = key_frames.joint_translations.get(&clip.skeleton.name)
+                else {
+                    return 0.0;
+                };
+                let (Some(first), Some(last)) = (translations.first(), translations.last()) else {
+                    return 0.0;
+                };
+                let distance = (*last - *first).with_y(0.0).length();
+                if duration > 0.0 {
+                    weight * distance / duration
+                } else {
+                    0.0
+                }
+            })
+            .sum()
+    }
+}
+
+#[derive(Resource)]
+pub enum BlendTreeState {
+    Loading(Handle<BlendTree>),
+    Loaded(BlendTreePlayer),
+}
+
+pub(crate) fn await_blend_tree_loaded(
+    state: Option<ResMut<BlendTreeState>>,
+    blend_trees: Res<Assets<BlendTree>>,
+    bvh_assets: Res<Assets<BvhAsset>>,
+    key_frames: Res<Assets<KeyFrames>>,
+    skeletons: Res<Assets<JointHierarchy>>,
+) {
+    let Some(mut state) = state else {
+        return;
+    };
+    let BlendTreeState::Loading(handle) = &*state else {
+        return;
+    };
+    let Some(tree) = blend_trees.get(handle) else {
+        return;
+    };
+
+    let mut clips = Vec::with_capacity(tree.clips.len());
+    for clip in &tree.clips {
+        let Some(bvh) = bvh_assets.get(&clip.bvh) else {
+            return;
+        };
+        match (
+            key_frames.get(&bvh.key_frames),
+            skeletons.get(&bvh.skeleton),
+        ) {
+            (Some(kf), Some(skeleton)) => clips.push(Animation {
+                key_frames: kf.clone(),
+                skeleton: skeleton.clone(),
+                path: asset_path(&clip.bvh),
+            }),
+            _ => return,
+        }
+    }
+
+    if clips.is_empty() {
+        error!("Blend tree has no clips.");
+        return;
+    }
+    info!("Loaded blend tree with {} clips.", clips.len());
+    let parameters = tree
+        .parameters
+        .iter()
+        .map(|p| (p.min + p.max) / 2.0)
+        .collect();
+    *state = BlendTreeState::Loaded(BlendTreePlayer {
+        tree: tree.clone(),
+        clips,
+        parameters,
+        phase: 0.0,
+        placement: None,
+    });
+}
+
+fn sample<T: Copy>(values: &[T], time: f32, interpolate: impl Fn(T, T, f32) -> T) -> T {
+    let last = values.len() - 1;
+    let time = time.clamp(0.0, last as f32);
+    let index = time.floor() as usize;
+    let next = (index + 1).min(last);
+    interpolate(values[index], values[next], time - index as f32)
+}
+
+/// Blends weighted samples of several clips into a single frame of key frames. Each sample is
+/// the clip, the (fractional) frame to sample it at and its weight.
+pub(crate) fn blend_pose(samples: &[(&KeyFrames, f32, f32)]) -> KeyFrames {
+    let mut joint_translations: IndexMap<String, Vec<Vec3>> = IndexMap::new();
+    let mut joint_rotations: IndexMap<String, Vec<Quat>> = IndexMap::new();
+
+    let reference = samples[0].0;
+    for name in reference.joint_translations.keys() {
+        let mut translation = Vec3::ZERO;
+        for (key_frames, frame, weight) in samples {
+            if let Some(values) = key_frames.joint_translations.get(name) {
+                translation += *weight * sample(values, *frame, Vec3::lerp);
+            }
+        }
+        joint_translations.insert(name.clone(), vec![translation]);
+    }
+
+    for name in reference.joint_order() {
+        let mut accumulated = Vec4::ZERO;
+        for (key_frames, frame, weight) in samples {
+            if let Some(values) = key_frames.joint_rotations.get(name) {
+                let mut rotation = Vec4::from(sample(values, *frame, Quat::slerp));
+                // Keep all contributions in the same hemisphere so they don't cancel out.
+                if rotation.dot(accumulated) < 0.0 {
+                    rotation = -rotation;
+                }
+                accumulated += *weight * rotation;
+            }
+        }
+        let rotation = if accumulated.length_squared() > 0.0 {
+            Quat::from_vec4(accumulated).normalize()
+        } else {
+            Quat::IDENTITY
+        };
+        joint_rotations.insert(name.to_string(), vec![rotation]);
+    }
+
+    KeyFrames {
+        frame_time: reference.frame_time,
+        count: 1,
+        joint_translations,
+        joint_rotations,
+        events: Vec::new(),
+    }
+}
+
+/// Draws a single frame produced by [`blend_pose`], in the palette color of `clip`.
+pub(crate) fn draw_blended_pose(
+    poses: &mut PoseRenderer,
+    clip: &str,
+    skeleton: &JointHierarchy,
+    pose: &KeyFrames,
+    placement: Option<&Transform>,
+) {
+    let root_translation = pose
+        .joint_translations
+        .get(&skeleton.name)
+        .map(|t| t[0])
+        .unwrap_or_default();
+    let root_transform = match placement {
+        // Only the root height is kept, the placement moves the character instead.
+        Some(placement) => {
+            placement.compute_matrix() * Mat4::from_translation(Vec3::Y * root_translation.y)
+        }
+        None => Mat4::from_translation(root_translation),
+    };
+    poses.draw_pose(clip, skeleton, pose, 0, root_transform, false);
+}
+
+pub(crate) fn update_blend_tree(
+    mut poses: PoseRenderer,
+    state: Option<ResMut<BlendTreeState>>,
+    time: Res<Time>,
+) {
+    let Some(mut state) = state else {
+        return;
+    };
+    let BlendTreeState::Loaded(player) = &mut *state else {
+        return;
+    };
+
+    let weights = player.tree.weights(&player.parameters);
+    let duration: f32 = player
+        .clips
+        .iter()
+        .zip(&weights)
+        .map(|(clip, weight)| weight * clip.key_frames.count as f32 * clip.key_frames.frame_time)
+        .sum();
+    if duration > 0.0 {
+        player.phase = (player.phase + time.delta_secs() / duration).fract();
+    }
+
+    let samples: Vec<_> = player
+        .clips
+        .iter()
+        .zip(weights)
+        .map(|(clip, weight)| {
+            let frame = player.phase * (clip.key_frames.count - 1) as f32;
+            (&clip.key_frames, frame, weight)
+        })
+        .collect();
+    let pose = blend_pose(&samples);
+    draw_blended_pose(
+        &mut poses,
+        &player.clips[0].path,
+        &player.clips[0].skeleton,
+        &pose,
+        player.placement.as_ref(),
+    );
+}
+
+pub(crate) fn blend_tree_ui(
+    mut contexts: EguiContexts,
+    state: Option<ResMut<BlendTreeState>>,
+) -> Result {
+    let Some(mut state) = state else {
+        return Ok(());
+    };
+    let BlendTreeState::Loaded(player) = &mut *state else {
+        return Ok(());
+    };
+
+    let ctx = contexts.ctx_mut()?;
+    egui::Window::new("Blend Tree").show(ctx, |ui| {
+        for (parameter, value) in player.tree.parameters.iter().zip(&mut player.parameters) {
+            ui.add(egui::Slider::new(value, parameter.min..=parameter.max).text(&parameter.name));
+        }
+        ui.separator();
+        let weights = player.tree.weights(&player.parameters);
+        for (clip, weight) in player.tree.clips.iter().zip(weights) {
+            let name = clip.bvh.path().map(|p| p.to_string()).unwrap_or_default();
+            ui.add(egui::ProgressBar::new(weight).text(name));
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blend_weights_1d() {
+        let positions = [[1.0], [3.0], [2.0]];
+        let positions = || positions.iter().map(|p| p.as_slice());
+
+        assert_eq!(blend_weights(positions(), &[1.5]), vec![0.5, 0.0, 0.5]);
+        assert_eq!(blend_weights(positions(), &[3.0]), vec![0.0, 1.0, 0.0]);
+        // Outside the range the closest clip plays on its own.
+        assert_eq!(blend_weights(positions(), &[0.0]), vec![1.0, 0.0, 0.0]);
+        assert_eq!(blend_weights(positions(), &[4.0]), vec![0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn test_blend_weights_2d() {
+        let positions = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]];
+        let positions = || positions.iter().map(|p| p.as_slice());
+
+        assert_eq!(blend_weights(positions(), &[1.0, 0.0]), vec![0.0, 1.0, 0.0]);
+        let weights = blend_weights(positions(), &[0.5, 0.5]);
+        assert!((weights.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+        assert!((weights[1] - weights[2]).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,261 @@
+//! Mesh based skeleton drawing. Every joint is an entity sharing one sphere mesh and material,
+//! every bone one sharing a cylinder, so Bevy batches each group into a single instanced draw
+//! call, one per bone color. Bones take the [`Palette`] color of the clip they belong to. Gizmos
+//! stay available as a debug view that also shows the joint axes, toggled with F3.
+use std::collections::HashMap;
+
+use bevy::{color::palettes::css::YELLOW, ecs::system::SystemParam, prelude::*};
+
+use crate::{
+    bvh_asset_loader::{JointHierarchy, KeyFrames},
+    draw_pose,
+    palette::Palette,
+};
+
+const JOINT_RADIUS: f32 = 2.0;
+const BONE_RADIUS: f32 = 1.0;
+
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum BoneRenderMode {
+    /// Instanced meshes, the default.
+    #[default]
+    Meshes,
+    /// Gizmo lines and joint axes, for debugging.
+    Gizmos,
+}
+
+/// Joint positions and bones of all poses drawn this frame, turned into instances in
+/// [`sync_bone_instances`].
+#[derive(Resource, Default)]
+pub(crate) struct PoseSegments {
+    joints: Vec<Vec3>,
+    bones: Vec<(Vec3, Vec3, Color)>,
+}
+
+impl PoseSegments {
+    fn add_pose(
+        &mut self,
+        skeleton: &JointHierarchy,
+        key_frames: &KeyFrames,
+        current_frame: usize,
+        parent_transform: Mat4,
+        rest: bool,
+        color: Color,
+    ) {
+        let joint_rotation = if rest {
+            Quat::IDENTITY
+        } else {
+            key_frames.joint_rotations[&skeleton.name][current_frame]
+        };
+        let joint_transform =
+            parent_transform * Mat4::from_rotation_translation(joint_rotation, skeleton.offset);
+        let world_position = joint_transform.col(3).xyz();
+        self.joints.push(world_position);
+        if let Some(end) = skeleton.end.filter(|end| end.length() > 0.0) {
+            self.bones
+                .push((world_position, joint_transform.transform_point3(end), color));
+        }
+        for child in &skeleton.children {
+            self.bones.push((
+                world_position,
+                joint_transform.transform_point3(child.offset),
+                color,
+            ));
+            self.add_pose(
+                child,
+                key_frames,
+                current_frame,
+                joint_transform,
+                rest,
+                color,
+            );
+        }
+    }
+}
+
+/// Draws poses with the current [`BoneRenderMode`]. The gizmos and palette are shared with the
+/// callers for overlays such as the gaze ray.
+#[derive(SystemParam)]
+pub(crate) struct PoseRenderer<'w, 's> {
+    pub gizmos: Gizmos<'w, 's>,
+    pub palette: ResMut<'w, Palette>,
+    mode: Res<'w, BoneRenderMode>,
+    segments: ResMut<'w, PoseSegments>,
+}
+
+impl PoseRenderer<'_, '_> {
+    /// Draws a pose of the clip at the asset path `clip`, in the color of its source.
+    pub fn draw_pose(
+        &mut self,
+        clip: &str,
+        skeleton: &JointHierarchy,
+        key_frames: &KeyFrames,
+        current_frame: usize,
+        parent_transform: Mat4,
+        rest: bool,
+    ) {
+        let color = self.palette.color(clip);
+        match *self.mode {
+            BoneRenderMode::Meshes => self.segments.add_pose(
+                skeleton,
+                key_frames,
+                current_frame,
+                parent_transform,
+                rest,
+                color,
+            ),
+            BoneRenderMode::Gizmos => draw_pose(
+                &mut self.gizmos,
+                skeleton,
+                key_frames,
+                current_frame,
+                parent_transform,
+                rest,
+                color,
+            ),
+        }
+    }
+}
+
+/// Shared meshes and materials of the instances, and the pooled entities using them.
+#[derive(Resource)]
+pub(crate) struct BoneInstances {
+    joint_mesh: Handle<Mesh>,
+    joint_material: Handle<StandardMaterial>,
+    bone_mesh: Handle<Mesh>,
+    /// Bone materials by sRGB color, created the first time a color is drawn.
+    bone_materials: HashMap<[u8; 4], Handle<StandardMaterial>>,
+    joints: Vec<Entity>,
+    bones: Vec<Entity>,
+}
+
+fn unlit(color: Color) -> StandardMaterial {
+    StandardMaterial {
+        base_color: color,
+        unlit: true,
+        ..default()
+    }
+}
+
+pub(crate) fn setup_bone_instances(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(BoneInstances {
+        joint_mesh: meshes.add(Sphere::new(1.0).mesh().ico(2).unwrap()),
+        joint_material: materials.add(unlit(YELLOW.into())),
+        bone_mesh: meshes.add(Cylinder::new(1.0, 1.0)),
+        bone_materials: HashMap::new(),
+        joints: Vec::new(),
+        bones: Vec::new(),
+    });
+}
+
+/// Transform of the unit cylinder stretched from `start` to `end`.
+fn bone_transform(start: Vec3, end: Vec3) -> Transform {
+    let direction = end - start;
+    let length = direction.length();
+    Transform {
+        translation: (start + end) / 2.0,
+        rotation: Quat::from_rotation_arc(Vec3::Y, direction.try_normalize().unwrap_or(Vec3::Y)),
+        scale: Vec3::new(BONE_RADIUS, length, BONE_RADIUS),
+    }
+}
+
+/// Spawns entities until `pool` has `count`, then places the first `count` and hides the rest.
+fn sync_pool(
+    commands: &mut Commands,
+    pool: &mut Vec<Entity>,
+    mesh: &Handle<Mesh>,
+    instances: impl ExactSizeIterator<Item = (Transform, Handle<StandardMaterial>)>,
+) {
+    let count = instances.len();
+    while pool.len() < count {
+        pool.push(
+            commands
+                .spawn((
+                    Mesh3d(mesh.clone()),
+                    Transform::default(),
+                    Visibility::Hidden,
+                ))
+                .id(),
+        );
+    }
+    for (entity, (transform, material)) in pool.iter().zip(instances) {
+        commands.entity(*entity).insert((
+            transform,
+            MeshMaterial3d(material),
+            Visibility::Inherited,
+        ));
+    }
+    for entity in &pool[count..] {
+        commands.entity(*entity).insert(Visibility::Hidden);
+    }
+}
+
+pub(crate) fn sync_bone_instances(
+    mut commands: Commands,
+    mut instances: ResMut<BoneInstances>,
+    mut segments: ResMut<PoseSegments>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let instances = &mut *instances;
+    sync_pool(
+        &mut commands,
+        &mut instances.joints,
+        &instances.joint_mesh,
+        segments.joints.iter().map(|position| {
+            (
+                Transform::from_translation(*position).with_scale(Vec3::splat(JOINT_RADIUS)),
+                instances.joint_material.clone(),
+            )
+        }),
+    );
+    let bone_materials = &mut instances.bone_materials;
+    let bones: Vec<_> = segments
+        .bones
+        .iter()
+        .map(|(start, end, color)| {
+            let material = bone_materials
+                .entry(color.to_srgba().to_u8_array())
+                .or_insert_with(|| materials.add(unlit(*color)));
+            (bone_transform(*start, *end), material.clone())
+        })
+        .collect();
+    sync_pool(
+        &mut commands,
+        &mut instances.bones,
+        &instances.bone_mesh,
+        bones.into_iter(),
+    );
+    segments.joints.clear();
+    segments.bones.clear();
+}
+
+pub(crate) fn toggle_bone_render_mode(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut mode: ResMut<BoneRenderMode>,
+) {
+    if keys.just_pressed(KeyCode::F3) {
+        *mode = match *mode {
+            BoneRenderMode::Meshes => BoneRenderMode::Gizmos,
+            BoneRenderMode::Gizmos => BoneRenderMode::Meshes,
+        };
+        info!("Drawing skeletons with {:?}", *mode);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bone_transform_spans_segment() {
+        let (start, end) = (Vec3::new(1.0, 2.0, 3.0), Vec3::new(1.0, 2.0, 13.0));
+        let matrix = bone_transform(start, end).compute_affine();
+        // The unit cylinder runs from -0.5 to 0.5 along Y.
+        assert!(matrix.transform_point3(Vec3::Y * -0.5).distance(start) < 1e-4);
+        assert!(matrix.transform_point3(Vec3::Y * 0.5).distance(end) < 1e-4);
+    }
+}
@@ -1,16 +1,26 @@
+use std::ops::Range;
+
 use bevy::{
     animation::{AnimationTargetId, animated_field, gltf_curves::SteppedKeyframeCurve},
     asset::{AssetLoader, AssetPath, LoadContext, io::Reader},
     math::curve::cores::UnevenCoreError,
-    platform::collections::HashMap,
     prelude::*,
 };
 use bvh_anim_parser::{
     parse::load_bvh_from_string,
     types::{BvhData, BvhMetadata, Joint},
 };
+use bvh_to_gav::{
+    convention::CoordinateConvention,
+    events::{AnimationEvent, EVENTS_EXTENSION, parse_events},
+};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
-#[derive(TypePath, Asset, Clone)]
+#[derive(Asset, Reflect, Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+// The type is recursive, so the derived field bounds would never resolve.
+#[reflect(no_field_bounds)]
 pub struct JointHierarchy {
     pub name: String,
     pub offset: Vec3,
@@ -23,21 +33,43 @@ pub struct BvhAsset {
     pub skeleton: Handle<JointHierarchy>,
     pub key_frames: Handle<KeyFrames>,
     pub clip: Handle<AnimationClip>,
+    /// Number of clips the capture is split into by [`BvhLoaderSettings::max_clip_frames`].
+    /// Loading the capture only builds the first one, `clip`, and its key frames; the others
+    /// are loaded on request by their [`BvhAssetLabel::ClipPart`] label.
+    pub part_count: usize,
     pub scene: Handle<Scene>,
 }
 
-#[derive(TypePath, Asset, Clone)]
+/// Tracks keyed by joint name. Joints are kept in the order they are declared in the BVH file,
+/// so iterating the tracks is the same on every run, see [`KeyFrames::joint_order`].
+// Opaque to reflection, so saving it in a review scene goes through serde and the derives stay
+// unconditional.
+#[derive(Asset, Reflect, Clone, Serialize, Deserialize)]
+#[reflect(opaque)]
+#[reflect(Clone, Serialize, Deserialize)]
 pub struct KeyFrames {
     pub frame_time: f32,
     pub count: usize,
-    pub joint_translations: HashMap<String, Vec<Vec3>>,
-    pub joint_rotations: HashMap<String, Vec<Quat>>,
+    pub joint_translations: IndexMap<String, Vec<Vec3>>,
+    pub joint_rotations: IndexMap<String, Vec<Quat>>,
+    /// Event markers read from the `<clip>.events.json` sidecar, sorted by frame.
+    pub events: Vec<AnimationEvent>,
+}
+
+impl KeyFrames {
+    /// Names of all joints in file order. Every joint has a rotation track, only some have a
+    /// translation track.
+    pub fn joint_order(&self) -> impl Iterator<Item = &str> {
+        self.joint_rotations.keys().map(String::as_str)
+    }
 }
 
 pub enum BvhAssetLabel {
     Skeleton,
     KeyFrames,
     Clip,
+    /// One part of a capture split into several clips.
+    ClipPart(usize),
     Scene,
 }
 
@@ -48,6 +80,34 @@ pub struct CharacterJoint;
 #[derive(Default)]
 pub struct BvhAssetLoader;
 
+/// Settings of [`BvhAssetLoader`], set in a `.bvh.meta` file or with `load_with_settings`.
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct BvhLoaderSettings {
+    /// Captures longer than this are split into several clips labeled `clip_0`, `clip_1` and so
+    /// on, so a long capture never has to be held in a single [`AnimationClip`]. The key frames
+    /// are those of the first clip.
+    pub max_clip_frames: Option<usize>,
+    /// Convention of the file, converted to the Y-up centimeter default on load.
+    #[serde(default)]
+    pub convention: CoordinateConvention,
+}
+
+impl BvhLoaderSettings {
+    /// Frame ranges of the clips a capture of `frame_count` frames is split into.
+    pub fn clip_ranges(&self, frame_count: usize) -> Vec<Range<usize>> {
+        match self.max_clip_frames {
+            Some(max) if max > 0 && frame_count > max => (0..frame_count)
+                .step_by(max)
+                .map(|start| start..(start + max).min(frame_count))
+                .collect(),
+            _ => vec![Range {
+                start: 0,
+                end: frame_count,
+            }],
+        }
+    }
+}
+
 /// Possible errors that can be produced by [`BvhAssetLoader`]
 #[non_exhaustive]
 #[derive(Debug, Error)]
@@ -63,33 +123,63 @@ pub enum BvhAssetLoaderError {
 }
 
 const CLIP: &str = "clip";
+const CLIP_PART_PREFIX: &str = "clip_";
 const SCENE: &str = "scene";
 const SKELETON: &str = "skeleton";
 const KEY_FRAMES: &str = "key_frames";
 
 impl AssetLoader for BvhAssetLoader {
     type Asset = BvhAsset;
-    type Settings = ();
+    type Settings = BvhLoaderSettings;
     type Error = BvhAssetLoaderError;
     async fn load(
         &self,
         reader: &mut dyn Reader,
-        _settings: &(),
+        settings: &BvhLoaderSettings,
         load_context: &mut LoadContext<'_>,
     ) -> Result<Self::Asset, Self::Error> {
         let mut bytes = Vec::new();
         reader.read_to_end(&mut bytes).await?;
         let content = String::from_utf8(bytes)?;
         // .map_err(|e| BvhAssetLoaderError::UnexpectedData(e.to_string()))?;
-        let (bvh_meta, bvh_data) = load_bvh_from_string(&content);
+        let (mut bvh_meta, mut bvh_data) = load_bvh_from_string(&content);
+        settings
+            .convention
+            .to(&CoordinateConvention::default())
+            .apply_to_bvh(&mut bvh_meta, &mut bvh_data);
+
+        let ranges = settings.clip_ranges(bvh_meta.num_frames);
+        let part = load_context
+            .asset_path()
+            .label()
+            .and_then(|label| label.strip_prefix(CLIP_PART_PREFIX))
+            .and_then(|part| part.parse::<usize>().ok());
 
         match load_context.asset_path().label() {
             Some(CLIP) => {
-                let key_frames = bvh_to_key_frames(&bvh_meta, &bvh_data)?;
-                let clip = bvh_to_clip(&bvh_meta, &bvh_data, key_frames)?;
+                let skeleton = JointHierarchy::from_bvh(&bvh_meta, &bvh_data)?;
+                let clip = bvh_to_clip(&bvh_meta, &bvh_data, &skeleton, ranges[0].clone())?;
                 let clip = load_context.add_labeled_asset(CLIP.to_string(), clip);
                 Ok(BvhAsset {
                     clip,
+                    part_count: ranges.len(),
+                    ..Default::default()
+                })
+            }
+            Some(label) if part.is_some() => {
+                let range = part.and_then(|part| ranges.get(part)).ok_or_else(|| {
+                    BvhAssetLoaderError::UnexpectedData(format!(
+                        "Clip {} does not exist, the capture has {} clips",
+                        label,
+                        ranges.len()
+                    ))
+                })?;
+                let skeleton = JointHierarchy::from_bvh(&bvh_meta, &bvh_data)?;
+                let clip = bvh_to_clip(&bvh_meta, &bvh_data, &skeleton, range.clone())?;
+                let clip = load_context.add_labeled_asset(label.to_string(), clip);
+                Ok(BvhAsset {
+                    clip,
+                    part_count: ranges.len(),
                     ..Default::default()
                 })
             }
@@ -103,7 +193,8 @@ impl AssetLoader for BvhAssetLoader {
                 })
             }
             Some(KEY_FRAMES) => {
-                let key_frames = bvh_to_key_frames(&bvh_meta, &bvh_data)?;
+                let mut key_frames = bvh_to_key_frames(&bvh_meta, &bvh_data, ranges[0].clone())?;
+                key_frames.events = load_events(load_context, ranges[0].clone()).await?;
 
                 let key_frames = load_context.add_labeled_asset(KEY_FRAMES.to_string(), key_frames);
                 Ok(BvhAsset {
@@ -121,12 +212,19 @@ impl AssetLoader for BvhAssetLoader {
                 })
             }
             _ => {
-                let key_frames = bvh_to_key_frames(&bvh_meta, &bvh_data)?;
+                // Only the first part of a split capture is built, the others are loaded by
+                // their label when they are needed.
+                let first = ranges[0].clone();
+                let mut key_frames = bvh_to_key_frames(&bvh_meta, &bvh_data, first.clone())?;
+                key_frames.events = load_events(load_context, first.clone()).await?;
                 let skeleton = JointHierarchy::from_bvh(&bvh_meta, &bvh_data)?;
-                let clip = bvh_to_clip(&bvh_meta, &bvh_data, key_frames.clone())?;
                 let scene = scene_from_bvh(&bvh_meta, &bvh_data)?;
-
-                let clip = load_context.add_labeled_asset(CLIP.to_string(), clip);
+                let clip = bvh_to_clip(&bvh_meta, &bvh_data, &skeleton, first)?;
+                let label = match ranges.len() {
+                    1 => BvhAssetLabel::Clip,
+                    _ => BvhAssetLabel::ClipPart(0),
+                };
+                let clip = load_context.add_labeled_asset(label.to_string(), clip);
                 let key_frames = load_context.add_labeled_asset(KEY_FRAMES.to_string(), key_frames);
                 let scene = load_context.add_labeled_asset(SCENE.to_string(), scene);
                 let skeleton = load_context.add_labeled_asset(SKELETON.to_string(), skeleton);
@@ -134,6 +232,7 @@ impl AssetLoader for BvhAssetLoader {
                     skeleton,
                     key_frames,
                     clip,
+                    part_count: ranges.len(),
                     scene,
                 })
             }
@@ -145,6 +244,34 @@ impl AssetLoader for BvhAssetLoader {
     }
 }
 
+/// Reads the events sidecar of the clip being loaded, keeping the events in `frames` relative
+/// to its start. Clips without one have no events.
+async fn load_events(
+    load_context: &mut LoadContext<'_>,
+    frames: Range<usize>,
+) -> Result<Vec<AnimationEvent>, BvhAssetLoaderError> {
+    let path = load_context
+        .path()
+        .with_extension(EVENTS_EXTENSION)
+        .to_string_lossy()
+        .into_owned();
+    match load_context.read_asset_bytes(path).await {
+        Ok(bytes) => {
+            let events = parse_events(&bytes)
+                .map_err(|e| BvhAssetLoaderError::UnexpectedData(e.to_string()))?;
+            Ok(events
+                .into_iter()
+                .filter(|event| frames.contains(&event.frame))
+                .map(|event| AnimationEvent {
+                    frame: event.frame - frames.start,
+                    ..event
+                })
+                .collect())
+        }
+        Err(_) => Ok(Vec::new()),
+    }
+}
+
 fn joint_offset(joint: &Joint, bvh_data: &BvhData) -> Vec3 {
     let offset = bvh_data.rest_local_positions[joint.index];
     Vec3::new(offset[0] as f32, offset[1] as f32, offset[2] as f32)
@@ -208,15 +335,19 @@ fn scene_from_bvh(
 }
 
 //-------------------------------------------------------------------------------------------------
+/// Key frames of the frames in `frames`.
 fn bvh_to_key_frames(
     bvh_meta: &BvhMetadata,
     bvh_data: &BvhData,
+    frames: Range<usize>,
 ) -> Result<KeyFrames, BvhAssetLoaderError> {
-    let mut joint_translations: HashMap<String, Vec<Vec3>> = HashMap::new();
-    let mut joint_rotations: HashMap<String, Vec<Quat>> = HashMap::new();
+    let mut joint_translations: IndexMap<String, Vec<Vec3>> = IndexMap::new();
+    let mut joint_rotations: IndexMap<String, Vec<Quat>> = IndexMap::new();
 
     for joint in &bvh_meta.joints {
         let joint_positions: Vec<Vec3> = bvh_data.pose_local_positions[joint.index]
+            .get(frames.clone())
+            .unwrap_or_default()
             .iter()
             .map(|v| Vec3::new(v.x as f32, v.y as f32, v.z as f32))
             .collect();
@@ -224,7 +355,7 @@ fn bvh_to_key_frames(
             joint_translations.insert(joint.name.to_string(), joint_positions);
         }
 
-        let rotation_frames: Vec<Quat> = bvh_data.pose_local_rotations[joint.index]
+        let rotation_frames: Vec<Quat> = bvh_data.pose_local_rotations[joint.index][frames.clone()]
             .iter()
             .map(|q| Quat::from_xyzw(q.v.x as f32, q.v.y as f32, q.v.z as f32, q.s as f32))
             .collect();
@@ -233,47 +364,53 @@ fn bvh_to_key_frames(
 
     Ok(KeyFrames {
         frame_time: bvh_meta.frame_time as f32,
-        count: bvh_meta.num_frames,
+        count: frames.len(),
         joint_translations,
         joint_rotations,
+        events: Vec::new(),
     })
 }
 
 //-------------------------------------------------------------------------------------------------
+/// Builds the curves of the frames in `frames` straight from the parsed data, so no copy of
+/// the key frames of the whole capture is needed.
 fn bvh_to_clip(
     bvh_meta: &BvhMetadata,
     bvh_data: &BvhData,
-    key_frames: KeyFrames,
+    skeleton: &JointHierarchy,
+    frames: Range<usize>,
 ) -> Result<AnimationClip, BvhAssetLoaderError> {
-    let skeleton = JointHierarchy::from_bvh(bvh_meta, bvh_data)?;
-
     let mut clip = AnimationClip::default();
     let frame_duration = bvh_meta.frame_time as f32;
 
-    for (joint_name, joint_positions) in key_frames.joint_translations {
-        let target_id = skeleton.target_id(joint_name.as_str()).ok_or_else(|| {
+    for joint in &bvh_meta.joints {
+        let target_id = skeleton.target_id(joint.name.as_str()).ok_or_else(|| {
             BvhAssetLoaderError::UnexpectedData(format!(
                 "Could not find target id for joint: {}",
-                joint_name
+                joint.name
             ))
         })?;
 
-        let joint_positions = create_curve(joint_positions.into_iter(), frame_duration)?;
-        let translation_property = animated_field!(Transform::translation);
-        let translation_curve =
-            VariableCurve::new(AnimatableCurve::new(translation_property, joint_positions));
-        clip.add_variable_curve_to_target(target_id, translation_curve);
-    }
-
-    for (joint_name, joint_rotations) in key_frames.joint_rotations {
-        let target_id = skeleton.target_id(joint_name.as_str()).ok_or_else(|| {
-            BvhAssetLoaderError::UnexpectedData(format!(
-                "Could not find target id for joint: {}",
-                joint_name
-            ))
-        })?;
+        let joint_positions = &bvh_data.pose_local_positions[joint.index];
+        if !joint_positions.is_empty() {
+            let joint_positions = create_curve(
+                joint_positions[frames.clone()]
+                    .iter()
+                    .map(|v| Vec3::new(v.x as f32, v.y as f32, v.z as f32)),
+                frame_duration,
+            )?;
+            let translation_property = animated_field!(Transform::translation);
+            let translation_curve =
+                VariableCurve::new(AnimatableCurve::new(translation_property, joint_positions));
+            clip.add_variable_curve_to_target(target_id, translation_curve);
+        }
 
-        let joint_rotations = create_curve(joint_rotations.into_iter(), frame_duration)?;
+        let joint_rotations = create_curve(
+            bvh_data.pose_local_rotations[joint.index][frames.clone()]
+                .iter()
+                .map(|q| Quat::from_xyzw(q.v.x as f32, q.v.y as f32, q.v.z as f32, q.s as f32)),
+            frame_duration,
+        )?;
         let rotation_property = animated_field!(Transform::rotation);
         let rotation_curve =
             VariableCurve::new(AnimatableCurve::new(rotation_property, joint_rotations));
@@ -304,6 +441,7 @@ impl core::fmt::Display for BvhAssetLabel {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             BvhAssetLabel::Clip => f.write_str(CLIP),
+            BvhAssetLabel::ClipPart(part) => write!(f, "{}{}", CLIP_PART_PREFIX, part),
             BvhAssetLabel::Scene => f.write_str(SCENE),
             BvhAssetLabel::Skeleton => f.write_str(SKELETON),
             BvhAssetLabel::KeyFrames => f.write_str(KEY_FRAMES),
@@ -374,6 +512,61 @@ mod tests {
     use super::*;
     use bevy::animation::AnimationTargetId;
 
+    #[test]
+    fn test_key_frames_round_trip_through_reflection() {
+        use bevy::reflect::{
+            TypeRegistry,
+            serde::{ReflectDeserializer, ReflectSerializer},
+        };
+        use serde::de::DeserializeSeed;
+
+        let mut registry = TypeRegistry::default();
+        registry.register::<KeyFrames>();
+        registry.register::<JointHierarchy>();
+
+        let key_frames = KeyFrames {
+            frame_time: 0.5,
+            count: 2,
+            joint_translations: IndexMap::from([("hips".to_string(), vec![Vec3::X, Vec3::Y])]),
+            joint_rotations: IndexMap::from([
+                ("hips".to_string(), vec![Quat::IDENTITY; 2]),
+                ("spine".to_string(), vec![Quat::from_rotation_x(1.0); 2]),
+            ]),
+            events: vec![AnimationEvent {
+                frame: 1,
+                name: "footstep".to_string(),
+                payload: None,
+            }],
+        };
+        let serialized = ron::to_string(&ReflectSerializer::new(&key_frames, &registry)).unwrap();
+        let mut deserializer = ron::Deserializer::from_str(&serialized).unwrap();
+        let value = ReflectDeserializer::new(&registry)
+            .deserialize(&mut deserializer)
+            .unwrap();
+        let restored = KeyFrames::from_reflect(value.as_partial_reflect()).unwrap();
+
+        assert_eq!(
+            restored.joint_order().collect::<Vec<_>>(),
+            ["hips", "spine"]
+        );
+        assert_eq!(
+            restored.joint_translations["hips"],
+            key_frames.joint_translations["hips"]
+        );
+    }
+
+    #[test]
+    fn test_clip_ranges_split_long_captures() {
+        let settings = BvhLoaderSettings {
+            max_clip_frames: Some(100),
+            ..Default::default()
+        };
+        assert_eq!(settings.clip_ranges(250), vec![0..100, 100..200, 200..250]);
+        assert_eq!(settings.clip_ranges(100), vec![0..100]);
+        assert_eq!(BvhLoaderSettings::default().clip_ranges(250), vec![0..250]);
+        assert_eq!(BvhAssetLabel::ClipPart(2).to_string(), "clip_2");
+    }
+
     #[test]
     fn test_target_id_simple_hierarchy() {
         // Build a simple hierarchy: root -> child1 -> child2
@@ -0,0 +1,216 @@
+use std::path::{Path, PathBuf};
+
+use bevy::{
+    asset::io::file::FileAssetReader,
+    prelude::*,
+    render::view::screenshot::{Screenshot, ScreenshotCaptured, save_to_disk},
+};
+use bvh_to_gav::convention::CoordinateConvention;
+
+use crate::{AnimationTimeline, LoadState};
+
+pub const USAGE: &str =
+    "Usage: preview [clip.bvh|clip.gav|clip.npy|tree.blendtree.ron|machine.statemachine.ron|review.scn.ron] [options]
+    --frame N               Start at frame N
+    --screenshot out.png    Save a screenshot of the frame and exit
+    --sequence folder       Save evenly spaced frames of the clip and exit
+    --sequence-frames N     Number of frames saved by --sequence (default 30)
+    --gamepad               Steer a blend tree with a gamepad
+    --script review.rhai    Run a Rhai script, see the Script window for the calls it can make
+    --convention z-up,cm    Up axis, unit and handedness of a BVH clip (default y-up,cm,right)
+Press F3 to switch between mesh and gizmo skeletons (with joint axes).
+Press F5 to save the loaded clip and timeline to review.scn.ron.
+Press F6 to save the frame from the front, side, top and three quarters as clip_fNNNN_views.png.
+Colors of clip folders are kept in palette.ron in the asset folder.
+Model outputs in clip.weights.npy, per frame or per joint and frame, are shown in the Layers window.
+Samples of a model in clip.samples.npy, or its variance in clip.variance.npy, show its uncertainty.
+The coverage.json report of bvh_to_gav coverage colors joints by the range the dataset covers.";
+
+/// Command line options of the preview app, see [`USAGE`].
+#[derive(Resource, Clone)]
+pub struct PreviewArgs {
+    pub clip: Option<PathBuf>,
+    pub frame: Option<usize>,
+    pub screenshot: Option<PathBuf>,
+    /// Folder to save evenly spaced frames of the clip to, as `frame_0000.png` and up.
+    pub sequence: Option<PathBuf>,
+    pub sequence_frames: usize,
+    /// Steer the loaded blend tree with a gamepad.
+    pub gamepad: bool,
+    /// Rhai script run once the viewer has started.
+    pub script: Option<PathBuf>,
+    /// Convention of the BVH clip, instead of the one in its `.bvh.meta` file.
+    pub convention: Option<CoordinateConvention>,
+}
+
+impl Default for PreviewArgs {
+    fn default() -> Self {
+        PreviewArgs {
+            clip: None,
+            frame: None,
+            screenshot: None,
+            sequence: None,
+            sequence_frames: 30,
+            gamepad: false,
+            script: None,
+            convention: None,
+        }
+    }
+}
+
+impl PreviewArgs {
+    pub fn from_env() -> Result<Self, String> {
+        let mut args = PreviewArgs::default();
+        let mut iter = std::env::args().skip(1);
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--frame" => {
+                    let value = iter.next().ok_or("--frame requires a value")?;
+                    let frame = value
+                        .parse()
+                        .map_err(|_| format!("Invalid frame index: {}", value))?;
+                    args.frame = Some(frame);
+                }
+                "--screenshot" => {
+                    let value = iter.next().ok_or("--screenshot requires a value")?;
+                    args.screenshot = Some(value.into());
+                }
+                "--sequence" => {
+                    let value = iter.next().ok_or("--sequence requires a value")?;
+                    args.sequence = Some(value.into());
+                }
+                "--sequence-frames" => {
+                    let value = iter.next().ok_or("--sequence-frames requires a value")?;
+                    args.sequence_frames = value
+                        .parse()
+                        .map_err(|_| format!("Invalid frame count: {}", value))?;
+                }
+                "--gamepad" => args.gamepad = true,
+                "--script" => {
+                    let value = iter.next().ok_or("--script requires a value")?;
+                    args.script = Some(value.into());
+                }
+                "--convention" => {
+                    let value = iter.next().ok_or("--convention requires a value")?;
+                    args.convention = Some(value.parse().map_err(|e| format!("{}", e))?);
+                }
+                _ if args.clip.is_none() => args.clip = Some(arg.into()),
+                _ => return Err(format!("Unexpected argument: {}", arg)),
+            }
+        }
+        Ok(args)
+    }
+
+    /// Folder the asset server should read from. A clip passed on the command line is loaded
+    /// relative to its own folder so it doesn't have to live in the assets directory.
+    pub fn asset_folder(&self) -> Option<String> {
+        let clip = std::path::absolute(self.clip.as_ref()?).ok()?;
+        Some(clip.parent()?.to_string_lossy().into_owned())
+    }
+
+    pub fn asset_path(&self) -> Option<String> {
+        let clip = self.clip.as_ref()?;
+        Some(clip.file_name()?.to_string_lossy().into_owned())
+    }
+
+    /// Location on disk of an asset, for files the preview writes next to its assets.
+    pub fn asset_file(&self, asset_path: impl AsRef<Path>) -> PathBuf {
+        let root = self.asset_folder().unwrap_or_else(|| "assets".to_string());
+        FileAssetReader::new(root).root_path().join(asset_path)
+    }
+}
+
+/// Number of rendered frames to wait after seeking before taking a screenshot, so the gizmos of
+/// the requested frame have made it to the swap chain.
+pub(crate) const SETTLE_FRAMES: u32 = 3;
+
+#[derive(Default)]
+pub(crate) enum CaptureState {
+    #[default]
+    Waiting,
+    /// Screenshots still to take, as timeline frame and output path, with the number of frames
+    /// left to wait before the first one is taken.
+    Capturing(Vec<(usize, PathBuf)>, u32),
+    Done,
+}
+
+/// Number of screenshots that still have to be written before the app exits.
+#[derive(Resource)]
+struct PendingScreenshots(usize);
+
+/// Seeks to the requested frame and, in screenshot or sequence mode, saves the window contents
+/// and exits once all screenshots are written.
+pub(crate) fn capture_requested_frame(
+    mut commands: Commands,
+    args: Res<PreviewArgs>,
+    load_state: Res<LoadState>,
+    mut timeline: ResMut<AnimationTimeline>,
+    mut state: Local<CaptureState>,
+) {
+    let LoadState::Loaded(animations) = &*load_state else {
+        return;
+    };
+    let last_frame = animations[timeline.anim_index].key_frames.count - 1;
+
+    match &mut *state {
+        CaptureState::Waiting => {
+            if let Some(frame) = args.frame {
+                timeline.current_frame = frame.min(last_frame);
+            }
+            let mut shots = Vec::new();
+            if let Some(path) = &args.screenshot {
+                shots.push((timeline.current_frame, path.clone()));
+            }
+            if let Some(folder) = &args.sequence {
+                let count = args.sequence_frames.max(1);
+                for i in 0..count {
+                    let frame = if count > 1 {
+                        i * last_frame / (count - 1)
+                    } else {
+                        0
+                    };
+                    shots.push((frame, folder.join(format!("frame_{:04}.png", i))));
+                }
+            }
+            if shots.is_empty() {
+                *state = CaptureState::Done;
+            } else {
+                commands.insert_resource(PendingScreenshots(shots.len()));
+                shots.reverse();
+                if let Some((frame, _)) = shots.last() {
+                    timeline.current_frame = *frame;
+                }
+                *state = CaptureState::Capturing(shots, SETTLE_FRAMES);
+            }
+        }
+        CaptureState::Capturing(shots, 0) => {
+            if let Some((_, path)) = shots.pop() {
+                info!("Saving screenshot to {}", path.display());
+                commands
+                    .spawn(Screenshot::primary_window())
+                    .observe(save_to_disk(path))
+                    .observe(exit_after_capture);
+            }
+            match shots.last() {
+                Some((frame, _)) => {
+                    timeline.current_frame = *frame;
+                    *state = CaptureState::Capturing(std::mem::take(shots), SETTLE_FRAMES);
+                }
+                None => *state = CaptureState::Done,
+            }
+        }
+        CaptureState::Capturing(_, remaining) => *remaining -= 1,
+        CaptureState::Done => {}
+    }
+}
+
+fn exit_after_capture(
+    _trigger: Trigger<ScreenshotCaptured>,
+    mut pending: ResMut<PendingScreenshots>,
+    mut exit: EventWriter<AppExit>,
+) {
+    pending.0 = pending.0.saturating_sub(1);
+    if pending.0 == 0 {
+        exit.write(AppExit::Success);
+    }
+}
@@ -0,0 +1,219 @@
+//! Contact sheets: the frame on the timeline rendered from the front, the side, the top and
+//! three quarters, in one image, so the screenshots in qualitative comparisons are all taken
+//! from the same angles.
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use bevy::{
+    prelude::*,
+    render::view::screenshot::{Screenshot, ScreenshotCaptured},
+};
+use image::{DynamicImage, RgbaImage, imageops};
+use preview::visualization::LayerContext;
+use smooth_bevy_cameras::{LookTransform, controllers::unreal::UnrealCameraController};
+
+use crate::{AnimationTimeline, capture::PreviewArgs, capture::SETTLE_FRAMES};
+
+/// Rendered frames to wait after moving the camera, for the camera smoothing to catch up.
+const VIEW_SETTLE_FRAMES: u32 = 60;
+/// Distance of the camera from the center of the skeleton, in radii of its bounding sphere.
+const VIEW_DISTANCE: f32 = 2.8;
+/// Radius of the framed sphere for skeletons without extent, in centimeters.
+const MIN_RADIUS: f32 = 50.0;
+
+/// Camera angles of a contact sheet, in the order they fill the 2x2 grid.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum View {
+    Front,
+    Side,
+    Top,
+    ThreeQuarter,
+}
+
+const VIEWS: [View; 4] = [View::Front, View::Side, View::Top, View::ThreeQuarter];
+
+impl View {
+    /// Camera placement looking at the sphere at `center` with `radius`.
+    fn look(self, center: Vec3, radius: f32) -> LookTransform {
+        let (direction, up) = match self {
+            View::Front => (Vec3::Z, Vec3::Y),
+            View::Side => (Vec3::X, Vec3::Y),
+            View::Top => (Vec3::Y, Vec3::NEG_Z),
+            View::ThreeQuarter => (Vec3::new(1.0, 0.5, 1.0).normalize(), Vec3::Y),
+        };
+        LookTransform {
+            eye: center + direction * radius * VIEW_DISTANCE,
+            target: center,
+            up,
+        }
+    }
+}
+
+/// Center and radius of the sphere around the joints of the posed clip.
+fn bounding_sphere(context: &LayerContext) -> Option<(Vec3, f32)> {
+    let positions = context.joints.iter().map(|joint| joint.position());
+    let (min, max) = positions.fold(None, |bounds: Option<(Vec3, Vec3)>, position| {
+        Some(bounds.map_or((position, position), |(min, max)| {
+            (min.min(position), max.max(position))
+        }))
+    })?;
+    Some((
+        (min + max) / 2.0,
+        ((max - min).length() / 2.0).max(MIN_RADIUS),
+    ))
+}
+
+/// `views` of equal size in a grid of two columns.
+fn compose(views: &[DynamicImage]) -> RgbaImage {
+    let (width, height) = views
+        .first()
+        .map_or((0, 0), |view| (view.width(), view.height()));
+    let rows = views.len().div_ceil(2) as u32;
+    let mut sheet = RgbaImage::new(width * 2, height * rows);
+    for (i, view) in views.iter().enumerate() {
+        let (x, y) = ((i % 2) as u32 * width, (i / 2) as u32 * height);
+        imageops::replace(&mut sheet, &view.to_rgba8(), x.into(), y.into());
+    }
+    sheet
+}
+
+/// Contact sheet of `clip` at `frame`, next to the clip.
+fn contact_sheet_path(args: &PreviewArgs, clip: &str, frame: usize) -> PathBuf {
+    let stem = Path::new(clip).with_extension("");
+    args.asset_file(format!("{}_f{:04}_views.png", stem.display(), frame))
+}
+
+#[derive(Default)]
+pub(crate) struct ContactSheetState {
+    /// Views still to take, last first.
+    views: Vec<View>,
+    /// Whether the camera looks from the next view.
+    aimed: bool,
+    /// Frames to wait before the next step.
+    wait: u32,
+    /// Camera to look from again once the sheet is done.
+    restore: Option<(LookTransform, f32)>,
+    center: Vec3,
+    radius: f32,
+    path: PathBuf,
+    /// Screenshots taken so far, by view index.
+    captured: Arc<Mutex<Vec<Option<DynamicImage>>>>,
+}
+
+/// Takes the frame on the timeline from each [`View`] when F6 is pressed and saves them as one
+/// image next to the clip.
+pub(crate) fn capture_contact_sheet(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    args: Res<PreviewArgs>,
+    context: Option<Res<LayerContext>>,
+    mut timeline: ResMut<AnimationTimeline>,
+    mut cameras: Query<(&mut LookTransform, &mut UnrealCameraController)>,
+    mut state: Local<ContactSheetState>,
+) {
+    let Ok((mut look, mut controller)) = cameras.single_mut() else {
+        return;
+    };
+
+    if state.restore.is_none() {
+        if !keys.just_pressed(KeyCode::F6) {
+            return;
+        }
+        let Some((context, (center, radius))) = context
+            .as_deref()
+            .and_then(|context| Some((context, bounding_sphere(context)?)))
+        else {
+            warn!("Nothing to capture, no animation is loaded.");
+            return;
+        };
+        timeline.playing = false;
+        *state = ContactSheetState {
+            views: VIEWS.iter().rev().copied().collect(),
+            aimed: false,
+            wait: 0,
+            restore: Some((*look, controller.smoothing_weight)),
+            center,
+            radius,
+            path: contact_sheet_path(&args, &context.clip, context.frame),
+            captured: Arc::new(Mutex::new(vec![None; VIEWS.len()])),
+        };
+        controller.smoothing_weight = 0.0;
+        return;
+    }
+
+    if state.wait > 0 {
+        state.wait -= 1;
+        return;
+    }
+    if !state.aimed {
+        if let Some(view) = state.views.last() {
+            *look = view.look(state.center, state.radius);
+            state.aimed = true;
+            state.wait = VIEW_SETTLE_FRAMES;
+            return;
+        }
+    } else if let Some(view) = state.views.pop() {
+        let index = VIEWS.len() - state.views.len() - 1;
+        let captured = state.captured.clone();
+        commands.spawn(Screenshot::primary_window()).observe(
+            move |trigger: Trigger<ScreenshotCaptured>| match trigger
+                .event()
+                .0
+                .clone()
+                .try_into_dynamic()
+            {
+                Ok(image) => captured.lock().unwrap()[index] = Some(image),
+                Err(e) => error!("Could not read the {:?} screenshot: {}", view, e),
+            },
+        );
+        // Keep the camera still until the screenshot has been rendered.
+        state.aimed = false;
+        state.wait = SETTLE_FRAMES;
+        return;
+    }
+
+    let views: Option<Vec<DynamicImage>> = state.captured.lock().unwrap().iter().cloned().collect();
+    let Some(views) = views else {
+        // The last screenshots are still on their way from the render world.
+        return;
+    };
+    let path = std::mem::take(&mut state.path);
+    match compose(&views).save(&path) {
+        Ok(()) => info!("Saved contact sheet to {}", path.display()),
+        Err(e) => error!("Could not save {}: {}", path.display(), e),
+    }
+    if let Some((restore, smoothing_weight)) = state.restore.take() {
+        *look = restore;
+        controller.smoothing_weight = smoothing_weight;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    #[test]
+    fn test_views_frame_the_skeleton() {
+        let center = Vec3::new(0.0, 100.0, 0.0);
+        for view in VIEWS {
+            let look = view.look(center, 100.0);
+            assert_eq!(look.target, center);
+            assert!((look.eye.distance(center) - 280.0).abs() < 1e-3);
+            assert!(look.up.cross(look.eye - center).length() > 1.0);
+        }
+        assert_eq!(View::Front.look(center, 100.0).eye.x, 0.0);
+
+        let sheet = compose(&[1u8, 2, 3, 4].map(|shade| {
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(3, 2, Rgba([shade, 0, 0, 255])))
+        }));
+        assert_eq!(sheet.dimensions(), (6, 4));
+        assert_eq!(sheet.get_pixel(0, 0)[0], 1);
+        assert_eq!(sheet.get_pixel(5, 1)[0], 2);
+        assert_eq!(sheet.get_pixel(0, 3)[0], 3);
+        assert_eq!(sheet.get_pixel(5, 3)[0], 4);
+    }
+}
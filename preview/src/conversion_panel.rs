@@ -0,0 +1,165 @@
+//! Shows the settings the loaded GAV tensor was converted with, which `bvh_to_gav convert`
+//! records in its metadata, and converts its source BVH clip again with edited settings. The
+//! tensor is overwritten and loaded again, so preprocessing choices can be compared without
+//! leaving the viewer.
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use bvh_to_gav::{
+    conversion_settings::ConversionSettings, derivatives::Differencing, dtype::Dtype,
+    metadata::read_metadata,
+};
+
+use crate::{
+    AnimationTimeline, LoadState, capture::PreviewArgs, gav_loading::is_gav_path,
+    gav_loading::start_gav_loading,
+};
+
+#[derive(Resource, Default)]
+pub(crate) struct ConversionPanel {
+    /// Asset path of the tensor the settings were read for.
+    path: String,
+    settings: Option<ConversionSettings>,
+    /// Convention being edited, parsed when converting.
+    convention: String,
+    error: Option<String>,
+}
+
+impl ConversionPanel {
+    /// Reads the settings of the tensor at the asset path `path`.
+    fn read(&mut self, args: &PreviewArgs, path: &str) {
+        *self = ConversionPanel {
+            path: path.to_string(),
+            ..Default::default()
+        };
+        let settings = read_metadata(&args.asset_file(path))
+            .and_then(|metadata| ConversionSettings::from_metadata(&metadata));
+        match settings {
+            Ok(settings) => {
+                self.convention = settings
+                    .as_ref()
+                    .map(|settings| settings.convention.to_string())
+                    .unwrap_or_default();
+                self.settings = settings;
+            }
+            Err(e) => self.error = Some(format!("{:#}", e)),
+        }
+    }
+
+    /// Converts the source clip into the tensor with the edited settings.
+    fn convert(&mut self, args: &PreviewArgs) -> Option<()> {
+        let settings = self.settings.as_mut()?;
+        let result = self.convention.parse().and_then(|convention| {
+            settings.convention = convention;
+            settings.convert(&args.asset_file(&self.path))
+        });
+        match result {
+            Ok(()) => {
+                self.error = None;
+                Some(())
+            }
+            Err(e) => {
+                self.error = Some(format!("{:#}", e));
+                None
+            }
+        }
+    }
+}
+
+pub(crate) fn conversion_panel_ui(
+    mut contexts: EguiContexts,
+    mut commands: Commands,
+    mut panel: ResMut<ConversionPanel>,
+    load_state: Res<LoadState>,
+    args: Res<PreviewArgs>,
+    mut timeline: ResMut<AnimationTimeline>,
+) -> Result {
+    let LoadState::Loaded(animations) = &*load_state else {
+        return Ok(());
+    };
+    let Some(animation) = animations.first().filter(|a| is_gav_path(&a.path)) else {
+        return Ok(());
+    };
+    if animation.path != panel.path {
+        panel.read(&args, &animation.path);
+    }
+
+    let mut convert = false;
+    let panel = &mut *panel;
+    egui::Window::new("Conversion").show(contexts.ctx_mut()?, |ui| {
+        let Some(settings) = &mut panel.settings else {
+            ui.label("The tensor has no recorded conversion settings.");
+            return;
+        };
+        ui.label(format!("Source: {}", settings.source.display()));
+        egui::Grid::new("conversion_settings").show(ui, |ui| {
+            ui.label("Convention");
+            ui.text_edit_singleline(&mut panel.convention)
+                .on_hover_text("y-up|z-up, m|cm and right|left, e.g. z-up,m");
+            ui.end_row();
+
+            ui.label("Dtype");
+            egui::ComboBox::from_id_salt("dtype")
+                .selected_text(settings.dtype.to_string())
+                .show_ui(ui, |ui| {
+                    for dtype in [Dtype::F16, Dtype::F32, Dtype::F64, Dtype::I16, Dtype::I8] {
+                        ui.selectable_value(&mut settings.dtype, dtype, dtype.to_string());
+                    }
+                });
+            ui.end_row();
+
+            ui.label("Root");
+            ui.vertical(|ui| {
+                ui.checkbox(&mut settings.in_place, "In place, trajectory as a curve");
+                ui.checkbox(&mut settings.canonicalize, "Start at the origin facing +Z");
+            });
+            ui.end_row();
+
+            ui.label("Derivatives");
+            let shown = settings
+                .derivatives
+                .map_or_else(|| "none".to_string(), |method| method.to_string());
+            egui::ComboBox::from_id_salt("derivatives")
+                .selected_text(shown)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut settings.derivatives, None, "none");
+                    for method in [
+                        Differencing::Forward,
+                        Differencing::Backward,
+                        Differencing::Central,
+                    ] {
+                        ui.selectable_value(
+                            &mut settings.derivatives,
+                            Some(method),
+                            method.to_string(),
+                        );
+                    }
+                });
+            ui.end_row();
+
+            ui.label("Frame rate");
+            ui.horizontal(|ui| {
+                let mut resample = settings.fps.is_some();
+                ui.checkbox(&mut resample, "Resample");
+                match (resample, &mut settings.fps) {
+                    (true, Some(fps)) => {
+                        ui.add(egui::DragValue::new(fps).range(1.0..=240.0).suffix(" fps"));
+                    }
+                    (true, fps @ None) => *fps = Some(30.0),
+                    (false, fps) => *fps = None,
+                }
+            });
+            ui.end_row();
+        });
+        convert = ui.button("Convert again").clicked();
+        if let Some(error) = &panel.error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+    });
+
+    if convert && panel.convert(&args).is_some() {
+        info!("Converted {} again.", panel.path);
+        start_gav_loading(&mut commands, &args, panel.path.clone());
+        *timeline = AnimationTimeline::default();
+    }
+    Ok(())
+}
@@ -0,0 +1,123 @@
+//! Dataset coverage of each joint, from the report `bvh_to_gav coverage` writes next to the
+//! clips. Joints of the skeleton are colored from barely exercised to the best covered joint,
+//! and the Layers window lists the joints from the least covered up.
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use bevy_egui::egui;
+use bvh_to_gav::coverage::{COVERAGE_REPORT_FILE, CoverageReport, LOW_COVERAGE};
+use preview::visualization::{LayerContext, VisualizationLayer};
+
+use crate::weight_overlay::heat_color;
+
+const JOINT_RADIUS: f32 = 3.0;
+const LIST_HEIGHT: f32 = 200.0;
+
+/// Layer showing the coverage report of the folder of the clip on the timeline.
+pub(crate) struct CoverageOverlay {
+    /// Folder the asset paths of clips are relative to.
+    root: PathBuf,
+    folder: Option<PathBuf>,
+    report: Result<CoverageReport, String>,
+}
+
+impl CoverageOverlay {
+    pub fn new(root: PathBuf) -> Self {
+        CoverageOverlay {
+            root,
+            folder: None,
+            report: Err(String::new()),
+        }
+    }
+
+    fn load(&mut self, context: &LayerContext) {
+        let folder = Path::new(&context.clip)
+            .parent()
+            .unwrap_or(Path::new(""))
+            .to_path_buf();
+        if self.folder.as_ref() == Some(&folder) {
+            return;
+        }
+        let path = self.root.join(&folder).join(COVERAGE_REPORT_FILE);
+        self.folder = Some(folder);
+        self.report = if path.exists() {
+            CoverageReport::load(&path)
+                .map_err(|e| format!("Could not read {}: {}", path.display(), e))
+        } else {
+            Err(format!(
+                "No {}, run bvh_to_gav coverage on the folder",
+                path.display()
+            ))
+        };
+        if let Err(e) = &self.report {
+            info!("{}", e);
+        }
+    }
+}
+
+impl VisualizationLayer for CoverageOverlay {
+    fn name(&self) -> &str {
+        "Joint coverage"
+    }
+
+    fn draw(&mut self, context: &LayerContext, gizmos: &mut Gizmos) {
+        self.load(context);
+        let Ok(report) = &self.report else {
+            return;
+        };
+        for joint in &context.joints {
+            if let Some(coverage) = report.find(&joint.name) {
+                gizmos.sphere(
+                    joint.position(),
+                    JOINT_RADIUS,
+                    heat_color(report.normalized(coverage)),
+                );
+            }
+        }
+    }
+
+    fn ui(&mut self, context: &LayerContext, ui: &mut egui::Ui) {
+        let report = match &self.report {
+            Ok(report) => report,
+            Err(e) => {
+                ui.label(e);
+                return;
+            }
+        };
+        ui.label(format!(
+            "{} clips, {} frames",
+            report.clip_count, report.frame_count
+        ));
+        let missing = context
+            .joints
+            .iter()
+            .filter(|joint| report.find(&joint.name).is_none())
+            .count();
+        if missing > 0 {
+            ui.label(format!(
+                "{} joints of this clip are not in the report",
+                missing
+            ));
+        }
+        egui::ScrollArea::vertical()
+            .max_height(LIST_HEIGHT)
+            .show(ui, |ui| {
+                egui::Grid::new("joint_coverage")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for joint in report.ranked() {
+                            let normalized = report.normalized(joint);
+                            let name = egui::RichText::new(&joint.joint);
+                            ui.label(if normalized < LOW_COVERAGE {
+                                name.color(egui::Color32::LIGHT_RED)
+                            } else {
+                                name
+                            });
+                            ui.label(format!("{:.0}°", joint.coverage()));
+                            ui.add(egui::ProgressBar::new(normalized).desired_width(100.0));
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+}
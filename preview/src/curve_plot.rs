@@ -0,0 +1,280 @@
+//! Curves panel: rotation angles of selected joints of the loaded clip over time. The plot is
+//! drawn with the layout of [`Plot::to_svg`] and can be exported next to the clip, as an SVG
+//! sized for papers or as a PNG of the panel as drawn on screen.
+use std::{
+    f32::consts::FRAC_PI_2,
+    path::{Path, PathBuf},
+};
+
+use bevy::{
+    prelude::*,
+    render::view::screenshot::{Screenshot, ScreenshotCaptured},
+};
+use bevy_egui::{EguiContexts, egui};
+use bvh_to_gav::plot::{FigureSize, Plot, Series, TICK_COUNT, format_tick, ticks};
+
+use crate::{Animation, AnimationTimeline, LoadState, capture::PreviewArgs, palette::Palette};
+
+/// Margins around the plot area in points, the same as the SVG.
+const MARGINS: (f32, f32, f32, f32) = (42.0, 8.0, 18.0, 28.0);
+const FONT_SIZE: f32 = 7.0;
+const TITLE_SIZE: f32 = 9.0;
+/// Width of the panel on screen. Text scales with it, so the proportions match the SVG.
+const PANEL_WIDTH: f32 = 504.0;
+
+#[derive(Default)]
+pub(crate) struct CurvePlotState {
+    joints: Vec<String>,
+    size: FigureSize,
+}
+
+fn curve_plot(animation: &Animation, joints: &[String], palette: &Palette) -> Plot {
+    let series = joints
+        .iter()
+        .filter_map(|joint| {
+            animation
+                .key_frames
+                .joint_rotations
+                .get(joint)
+                .map(|r| (joint, r))
+        })
+        .enumerate()
+        .map(|(index, (joint, rotations))| {
+            let [r, g, b, _] = palette.series_color(index).to_srgba().to_u8_array();
+            Series {
+                name: joint.clone(),
+                color: [r, g, b],
+                points: rotations
+                    .iter()
+                    .enumerate()
+                    .map(|(frame, rotation)| {
+                        let angle = rotation.angle_between(Quat::IDENTITY).to_degrees();
+                        Vec2::new(frame as f32, angle)
+                    })
+                    .collect(),
+            }
+        })
+        .collect();
+    Plot {
+        title: animation.path.clone(),
+        x_label: "Frame".to_string(),
+        y_label: "Rotation (degrees)".to_string(),
+        series,
+    }
+}
+
+/// Draws `plot` into `rect` with a line at the frame `marker`.
+fn draw_plot(painter: &egui::Painter, rect: egui::Rect, plot: &Plot, marker: Option<f32>) {
+    let scale = rect.width() / PANEL_WIDTH;
+    let (left, right, top, bottom) = MARGINS;
+    let area = egui::Rect::from_min_max(
+        rect.min + egui::vec2(left, top) * scale,
+        rect.max - egui::vec2(right, bottom) * scale,
+    );
+    let (min, max) = plot.bounds().unwrap_or((Vec2::ZERO, Vec2::ONE));
+    let place = |p: Vec2| {
+        let t = (p - min) / (max - min);
+        egui::pos2(
+            area.left() + t.x * area.width(),
+            area.bottom() - t.y * area.height(),
+        )
+    };
+    let font = |size: f32| egui::FontId::proportional(size * scale);
+    let grid = egui::Stroke::new(0.5 * scale, egui::Color32::from_gray(0xDD));
+
+    painter.rect_filled(rect, 0.0, egui::Color32::WHITE);
+    painter.text(
+        egui::pos2(rect.center().x, rect.top() + (top - 6.0) * scale),
+        egui::Align2::CENTER_BOTTOM,
+        &plot.title,
+        font(TITLE_SIZE),
+        egui::Color32::BLACK,
+    );
+    let x_ticks = ticks(min.x, max.x, TICK_COUNT);
+    for x in &x_ticks {
+        let p = place(Vec2::new(*x, min.y));
+        painter.vline(p.x, area.y_range(), grid);
+        painter.text(
+            p + egui::vec2(0.0, 2.0 * scale),
+            egui::Align2::CENTER_TOP,
+            format_tick(*x, &x_ticks),
+            font(FONT_SIZE),
+            egui::Color32::BLACK,
+        );
+    }
+    let y_ticks = ticks(min.y, max.y, TICK_COUNT);
+    for y in &y_ticks {
+        let p = place(Vec2::new(min.x, *y));
+        painter.hline(area.x_range(), p.y, grid);
+        painter.text(
+            p - egui::vec2(3.0 * scale, 0.0),
+            egui::Align2::RIGHT_CENTER,
+            format_tick(*y, &y_ticks),
+            font(FONT_SIZE),
+            egui::Color32::BLACK,
+        );
+    }
+    painter.rect_stroke(
+        area,
+        0.0,
+        egui::Stroke::new(0.75 * scale, egui::Color32::BLACK),
+        egui::StrokeKind::Middle,
+    );
+    painter.text(
+        egui::pos2(area.center().x, rect.bottom() - 4.0 * scale),
+        egui::Align2::CENTER_BOTTOM,
+        &plot.x_label,
+        font(FONT_SIZE),
+        egui::Color32::BLACK,
+    );
+    let galley =
+        painter.layout_no_wrap(plot.y_label.clone(), font(FONT_SIZE), egui::Color32::BLACK);
+    let y_label = egui::pos2(
+        rect.left() + 2.0 * scale,
+        area.center().y + galley.size().x / 2.0,
+    );
+    painter.add(
+        egui::epaint::TextShape::new(y_label, galley, egui::Color32::BLACK).with_angle(-FRAC_PI_2),
+    );
+
+    if let Some(frame) = marker {
+        let x = place(Vec2::new(frame, min.y)).x;
+        painter.vline(
+            x,
+            area.y_range(),
+            egui::Stroke::new(scale, egui::Color32::GRAY),
+        );
+    }
+    for (index, series) in plot.series.iter().enumerate() {
+        let [r, g, b] = series.color;
+        let color = egui::Color32::from_rgb(r, g, b);
+        let points = series.points.iter().map(|p| place(*p)).collect();
+        painter.add(egui::Shape::line(points, egui::Stroke::new(scale, color)));
+
+        let y = area.top() + (8.0 + index as f32 * (FONT_SIZE + 3.0)) * scale;
+        let x = area.right() - 6.0 * scale;
+        painter.hline(
+            (x - 12.0 * scale)..=x,
+            y,
+            egui::Stroke::new(1.5 * scale, color),
+        );
+        painter.text(
+            egui::pos2(x - 15.0 * scale, y),
+            egui::Align2::RIGHT_CENTER,
+            &series.name,
+            font(FONT_SIZE),
+            egui::Color32::BLACK,
+        );
+    }
+}
+
+/// Saves the part of the screenshot inside `rect`, in physical pixels.
+fn save_panel(path: PathBuf, rect: URect) -> impl FnMut(Trigger<ScreenshotCaptured>) {
+    move |trigger| {
+        let image = match trigger.event().0.clone().try_into_dynamic() {
+            Ok(image) => image,
+            Err(e) => {
+                error!("Could not read the screenshot: {}", e);
+                return;
+            }
+        };
+        let panel = image.crop_imm(rect.min.x, rect.min.y, rect.width(), rect.height());
+        match panel.to_rgb8().save(&path) {
+            Ok(()) => info!("Saved plot to {}", path.display()),
+            Err(e) => error!("Could not save {}: {}", path.display(), e),
+        }
+    }
+}
+
+pub(crate) fn curve_plot_ui(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    load_state: Res<LoadState>,
+    timeline: Res<AnimationTimeline>,
+    palette: Res<Palette>,
+    args: Res<PreviewArgs>,
+    mut state: Local<CurvePlotState>,
+) -> Result {
+    let LoadState::Loaded(animations) = &*load_state else {
+        return Ok(());
+    };
+    let Some(animation) = animations.get(timeline.anim_index) else {
+        return Ok(());
+    };
+    if state.joints.is_empty() {
+        state
+            .joints
+            .extend(animation.key_frames.joint_order().next().map(String::from));
+    }
+
+    let ctx = contexts.ctx_mut()?;
+    let pixels_per_point = ctx.pixels_per_point();
+    let plot = curve_plot(animation, &state.joints, &palette);
+    let mut export = None;
+    egui::Window::new("Curves")
+        .default_open(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let mut added = None;
+                egui::ComboBox::from_id_salt("curve_joint")
+                    .selected_text("Add joint")
+                    .show_ui(ui, |ui| {
+                        for joint in animation.key_frames.joint_order() {
+                            if ui.selectable_label(false, joint).clicked() {
+                                added = Some(joint.to_string());
+                            }
+                        }
+                    });
+                if let Some(joint) = added.filter(|joint| !state.joints.contains(joint)) {
+                    state.joints.push(joint);
+                }
+                if ui.button("Clear").clicked() {
+                    state.joints.clear();
+                }
+                ui.radio_value(&mut state.size, FigureSize::Column, "Column");
+                ui.radio_value(&mut state.size, FigureSize::Page, "Page");
+            });
+
+            let figure = state.size.points();
+            let size = egui::vec2(PANEL_WIDTH, PANEL_WIDTH * figure.y / figure.x);
+            let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+            draw_plot(
+                ui.painter(),
+                rect,
+                &plot,
+                Some(timeline.current_frame as f32),
+            );
+
+            ui.horizontal(|ui| {
+                if ui.button("Save SVG").clicked() {
+                    export = Some(("curves.svg", rect));
+                }
+                if ui.button("Save PNG").clicked() {
+                    export = Some(("curves.png", rect));
+                }
+            });
+        });
+
+    let Some((extension, rect)) = export else {
+        return Ok(());
+    };
+    let path = args.asset_file(Path::new(&animation.path).with_extension(extension));
+    if extension.ends_with("svg") {
+        match plot.save_svg(&path, state.size) {
+            Ok(()) => info!("Saved plot to {}", path.display()),
+            Err(e) => error!("Could not save {}: {}", path.display(), e),
+        }
+    } else {
+        let rect = URect::new(
+            (rect.left() * pixels_per_point) as u32,
+            (rect.top() * pixels_per_point) as u32,
+            (rect.right() * pixels_per_point) as u32,
+            (rect.bottom() * pixels_per_point) as u32,
+        );
+        commands
+            .spawn(Screenshot::primary_window())
+            .observe(save_panel(path, rect));
+    }
+    Ok(())
+}
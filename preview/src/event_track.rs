@@ -0,0 +1,103 @@
+//! Event track of the loaded clip, shown under the timeline slider. Events can be added at the
+//! current frame, removed and saved back to the `<clip>.events.json` sidecar.
+use bevy::prelude::*;
+use bevy_egui::egui;
+use bvh_to_gav::events::{AnimationEvent, events_path, write_events};
+
+use crate::{Animation, AnimationTimeline, capture::PreviewArgs};
+
+const MARKER_HEIGHT: f32 = 12.0;
+
+/// Event being typed in the timeline window.
+#[derive(Default)]
+pub(crate) struct EventDraft {
+    name: String,
+    payload: String,
+}
+
+pub(crate) fn event_track_ui(
+    ui: &mut egui::Ui,
+    animation: &mut Animation,
+    timeline: &mut AnimationTimeline,
+    draft: &mut EventDraft,
+    args: &PreviewArgs,
+    color: egui::Color32,
+) {
+    let last_frame = animation.key_frames.count.saturating_sub(1).max(1);
+    let (rect, _) = ui.allocate_exact_size(
+        egui::vec2(ui.available_width(), MARKER_HEIGHT),
+        egui::Sense::hover(),
+    );
+    let painter = ui.painter_at(rect);
+    let x = |frame: usize| rect.left() + rect.width() * frame as f32 / last_frame as f32;
+    painter.rect_filled(rect, 2.0, egui::Color32::from_gray(40));
+    for event in &animation.key_frames.events {
+        let color = if event.frame == timeline.current_frame {
+            egui::Color32::YELLOW
+        } else {
+            color
+        };
+        painter.vline(
+            x(event.frame),
+            rect.y_range(),
+            egui::Stroke::new(2.0, color),
+        );
+    }
+    painter.vline(
+        x(timeline.current_frame),
+        rect.y_range(),
+        egui::Stroke::new(1.0, egui::Color32::WHITE),
+    );
+
+    let mut removed = None;
+    let header = format!("Events ({})", animation.key_frames.events.len());
+    egui::CollapsingHeader::new(header).show(ui, |ui| {
+        for (index, event) in animation.key_frames.events.iter().enumerate() {
+            ui.horizontal(|ui| {
+                let label = match &event.payload {
+                    Some(payload) => format!("{}: {} ({})", event.frame, event.name, payload),
+                    None => format!("{}: {}", event.frame, event.name),
+                };
+                if ui.button(label).clicked() {
+                    timeline.current_frame = event.frame;
+                }
+                if ui.small_button("x").clicked() {
+                    removed = Some(index);
+                }
+            });
+        }
+
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut draft.name).hint_text("name"));
+            ui.add(egui::TextEdit::singleline(&mut draft.payload).hint_text("payload"));
+            let add = ui.add_enabled(
+                !draft.name.trim().is_empty(),
+                egui::Button::new(format!("Add at {}", timeline.current_frame)),
+            );
+            if add.clicked() {
+                let payload = draft.payload.trim();
+                let event = AnimationEvent {
+                    frame: timeline.current_frame,
+                    name: draft.name.trim().to_string(),
+                    payload: (!payload.is_empty()).then(|| payload.to_string()),
+                };
+                let events = &mut animation.key_frames.events;
+                let index = events.partition_point(|e| e.frame <= event.frame);
+                events.insert(index, event);
+                draft.payload.clear();
+            }
+        });
+
+        if ui.button("Save").clicked() {
+            let path = events_path(&args.asset_file(&animation.path));
+            match write_events(&path, &animation.key_frames.events) {
+                Ok(()) => info!("Saved events to {}", path.display()),
+                Err(e) => error!("Could not save events to {}: {}", path.display(), e),
+            }
+        }
+    });
+
+    if let Some(index) = removed {
+        animation.key_frames.events.remove(index);
+    }
+}
@@ -0,0 +1,171 @@
+//! Frame data panel: the values of every joint in the frame on the timeline, as a table that
+//! can be copied as tab separated text, to paste exact numbers into bug reports, spreadsheets
+//! and unit tests.
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+
+use crate::{Animation, AnimationTimeline, LoadState, layer_joints};
+
+/// Order of the Euler angles, the rotation channel order of most BVH files.
+const EULER_ORDER: EulerRot = EulerRot::ZXY;
+const COLUMNS: [&str; 11] = [
+    "joint", "euler_z", "euler_x", "euler_y", "quat_x", "quat_y", "quat_z", "quat_w", "world_x",
+    "world_y", "world_z",
+];
+
+/// Values of a joint in a frame.
+struct FrameRow {
+    joint: String,
+    /// Local rotation as Z, X and Y angles in degrees, see [`EULER_ORDER`].
+    euler: Vec3,
+    /// Local rotation.
+    rotation: Quat,
+    world_position: Vec3,
+}
+
+impl FrameRow {
+    fn values(&self) -> [f32; 10] {
+        let [z, x, y] = self.euler.to_array();
+        let [qx, qy, qz, qw] = self.rotation.to_array();
+        let [wx, wy, wz] = self.world_position.to_array();
+        [z, x, y, qx, qy, qz, qw, wx, wy, wz]
+    }
+}
+
+/// Rows of every joint of `animation` in `frame`, in depth first order.
+fn frame_rows(animation: &Animation, frame: usize) -> Vec<FrameRow> {
+    let key_frames = &animation.key_frames;
+    let root_translation = key_frames
+        .joint_translations
+        .get(&animation.skeleton.name)
+        .map_or(Vec3::ZERO, |translations| translations[frame]);
+    let mut joints = Vec::new();
+    layer_joints(
+        &mut joints,
+        &animation.skeleton,
+        key_frames,
+        frame,
+        None,
+        Mat4::from_translation(root_translation),
+        false,
+    );
+    joints
+        .into_iter()
+        .map(|joint| {
+            let rotation = key_frames.joint_rotations[&joint.name][frame];
+            let (z, x, y) = rotation.to_euler(EULER_ORDER);
+            FrameRow {
+                euler: Vec3::new(z.to_degrees(), x.to_degrees(), y.to_degrees()),
+                rotation,
+                world_position: joint.transform.col(3).xyz(),
+                joint: joint.name,
+            }
+        })
+        .collect()
+}
+
+/// `rows` as tab separated text with a header line.
+fn to_tsv(rows: &[FrameRow]) -> String {
+    let mut text = COLUMNS.join("\t");
+    for row in rows {
+        text.push('\n');
+        text.push_str(&row.joint);
+        for value in row.values() {
+            text.push_str(&format!("\t{}", value));
+        }
+    }
+    text.push('\n');
+    text
+}
+
+pub(crate) fn frame_table_ui(
+    mut contexts: EguiContexts,
+    load_state: Res<LoadState>,
+    timeline: Res<AnimationTimeline>,
+) -> Result {
+    let LoadState::Loaded(animations) = &*load_state else {
+        return Ok(());
+    };
+    let Some(animation) = animations.get(timeline.anim_index) else {
+        return Ok(());
+    };
+    let frame = timeline
+        .current_frame
+        .min(animation.key_frames.count.saturating_sub(1));
+    let rows = frame_rows(animation, frame);
+
+    egui::Window::new("Frame data")
+        .default_open(false)
+        .show(contexts.ctx_mut()?, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(format!("Frame {}", frame));
+                if ui.button("Copy").clicked() {
+                    ui.ctx().copy_text(to_tsv(&rows));
+                }
+            });
+            egui::ScrollArea::both().show(ui, |ui| {
+                egui::Grid::new("frame_data").striped(true).show(ui, |ui| {
+                    for column in COLUMNS {
+                        ui.strong(column);
+                    }
+                    ui.end_row();
+                    for row in &rows {
+                        ui.label(&row.joint);
+                        for value in row.values() {
+                            ui.monospace(format!("{:.4}", value));
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+        });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use indexmap::IndexMap;
+
+    use super::*;
+    use crate::bvh_asset_loader::{JointHierarchy, KeyFrames};
+
+    #[test]
+    fn test_frame_rows_pose_the_skeleton() {
+        let joint = |name: &str, offset, children| JointHierarchy {
+            name: name.to_string(),
+            offset,
+            end: None,
+            children,
+        };
+        let turn = Quat::from_rotation_z(std::f32::consts::FRAC_PI_2);
+        let animation = Animation {
+            skeleton: joint("Hips", Vec3::ZERO, vec![joint("Spine", Vec3::Y, vec![])]),
+            key_frames: KeyFrames {
+                frame_time: 1.0 / 30.0,
+                count: 2,
+                joint_translations: IndexMap::from([(
+                    "Hips".to_string(),
+                    vec![Vec3::ZERO, Vec3::X],
+                )]),
+                joint_rotations: IndexMap::from([
+                    ("Hips".to_string(), vec![Quat::IDENTITY, turn]),
+                    ("Spine".to_string(), vec![Quat::IDENTITY; 2]),
+                ]),
+                events: Vec::new(),
+            },
+            path: "walk.bvh".to_string(),
+        };
+
+        let rows = frame_rows(&animation, 1);
+        assert_eq!(rows[0].joint, "Hips");
+        assert!((rows[0].euler.x - 90.0).abs() < 1e-3);
+        // The hips turn the spine from above them to their side.
+        assert!(rows[1].world_position.length() < 1e-5);
+
+        let text = to_tsv(&rows);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("joint\teuler_z"));
+        assert_eq!(lines[2].split('\t').count(), COLUMNS.len());
+    }
+}
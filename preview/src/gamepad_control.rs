@@ -0,0 +1,109 @@
+//! Gamepad demo mode: the left stick steers a character driven by a blend tree. With a one
+//! parameter tree the stick length picks the speed parameter and the stick direction the facing,
+//! with a two parameter tree the stick axes map directly onto the parameters (strafe sets).
+//! The character travels at the root speed of the blended clips, so the demo shows how well a
+//! generated locomotion set follows the input.
+use std::collections::VecDeque;
+
+use bevy::{color::palettes::css::ORANGE, prelude::*};
+
+use crate::{blend_tree::BlendTreeState, palette::Palette};
+
+const DEAD_ZONE: f32 = 0.15;
+/// Turn rate towards the stick direction, in radians per second.
+const TURN_RATE: f32 = 4.0;
+const TRAIL_LENGTH: usize = 300;
+
+#[derive(Resource, Default)]
+pub struct GamepadControl {
+    position: Vec3,
+    yaw: f32,
+    trail: VecDeque<Vec3>,
+}
+
+fn apply_dead_zone(stick: Vec2) -> Vec2 {
+    let length = stick.length();
+    if length < DEAD_ZONE {
+        Vec2::ZERO
+    } else {
+        stick / length * ((length - DEAD_ZONE) / (1.0 - DEAD_ZONE)).min(1.0)
+    }
+}
+
+pub(crate) fn drive_blend_tree_with_gamepad(
+    mut gizmos: Gizmos,
+    mut control: ResMut<GamepadControl>,
+    state: Option<ResMut<BlendTreeState>>,
+    gamepads: Query<&Gamepad>,
+    time: Res<Time>,
+    mut palette: ResMut<Palette>,
+) {
+    let Some(mut state) = state else {
+        return;
+    };
+    let BlendTreeState::Loaded(player) = &mut *state else {
+        return;
+    };
+
+    let stick = gamepads
+        .iter()
+        .map(|gamepad| apply_dead_zone(gamepad.left_stick()))
+        .find(|stick| *stick != Vec2::ZERO)
+        .unwrap_or(Vec2::ZERO);
+    // Stick up moves away from the default camera, along -Z.
+    let input = Vec3::new(stick.x, 0.0, -stick.y);
+
+    let parameters = player.tree.parameters.clone();
+    if let [speed] = parameters.as_slice() {
+        player.parameters[0] = speed.min + input.length() * (speed.max - speed.min);
+        if input != Vec3::ZERO {
+            let target_yaw = input.x.atan2(input.z);
+            let mut difference = (target_yaw - control.yaw) % std::f32::consts::TAU;
+            if difference > std::f32::consts::PI {
+                difference -= std::f32::consts::TAU;
+            } else if difference < -std::f32::consts::PI {
+                difference += std::f32::consts::TAU;
+            }
+            let max_turn = TURN_RATE * time.delta_secs();
+            control.yaw += difference.clamp(-max_turn, max_turn);
+        }
+    } else {
+        for ((value, parameter), axis) in player
+            .parameters
+            .iter_mut()
+            .zip(&parameters)
+            .zip([stick.x, stick.y])
+        {
+            *value = parameter.min + (axis + 1.0) / 2.0 * (parameter.max - parameter.min);
+        }
+    }
+
+    let weights = player.tree.weights(&player.parameters);
+    let speed = player.root_speed(&weights);
+    // With one parameter the stick tilt already picked the speed, so it only gates the step.
+    let (direction, tilt) = match parameters.len() {
+        1 if input == Vec3::ZERO => (Vec3::ZERO, 0.0),
+        1 => (Quat::from_rotation_y(control.yaw) * Vec3::Z, 1.0),
+        _ => (input.normalize_or_zero(), input.length().min(1.0)),
+    };
+    let step = direction * speed * tilt * time.delta_secs();
+    control.position += step;
+
+    let position = control.position;
+    if control.trail.back() != Some(&position) {
+        control.trail.push_back(position);
+        if control.trail.len() > TRAIL_LENGTH {
+            control.trail.pop_front();
+        }
+    }
+    player.placement = Some(
+        Transform::from_translation(control.position)
+            .with_rotation(Quat::from_rotation_y(control.yaw)),
+    );
+
+    let trail_color = palette.color(&player.clips[0].path);
+    gizmos.linestrip(control.trail.iter().copied(), trail_color);
+    if input != Vec3::ZERO {
+        gizmos.arrow(position, position + input * 100.0, ORANGE);
+    }
+}
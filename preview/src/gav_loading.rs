@@ -0,0 +1,233 @@
+//! Opens `.gav` containers and `.npy` tensors written by `bvh_to_gav`. A tensor can be several
+//! gigabytes, so it is read and decoded on the async compute task pool while a progress bar
+//! shows how much of the file has been read, instead of freezing the window.
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use bevy::{
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, Task, futures::check_ready},
+};
+use bevy_egui::{EguiContexts, egui};
+use bvh_to_gav::{
+    events::{events_path, read_events},
+    load_gav_from,
+    skeleton::Skeleton,
+};
+use indexmap::IndexMap;
+
+use crate::{
+    Animation, LoadState,
+    bvh_asset_loader::{JointHierarchy, KeyFrames},
+    capture::PreviewArgs,
+};
+
+pub(crate) const GAV_EXTENSIONS: [&str; 2] = ["gav", "npy"];
+
+pub(crate) fn is_gav_path(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|e| GAV_EXTENSIONS.iter().any(|gav| e == *gav))
+}
+
+/// Counts the bytes read so far into a counter shared with the main thread.
+struct ProgressReader<R> {
+    inner: R,
+    read: Arc<AtomicU64>,
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.read.fetch_add(count as u64, Ordering::Relaxed);
+        Ok(count)
+    }
+}
+
+/// A tensor being decoded in the background.
+#[derive(Resource)]
+pub(crate) struct GavLoading {
+    path: String,
+    size: u64,
+    read: Arc<AtomicU64>,
+    task: Task<Result<Animation>>,
+}
+
+impl GavLoading {
+    /// Fraction of the file read so far. Decoding the last bytes still takes a moment after it
+    /// reaches one.
+    fn progress(&self) -> f32 {
+        if self.size == 0 {
+            return 0.0;
+        }
+        (self.read.load(Ordering::Relaxed) as f64 / self.size as f64).min(1.0) as f32
+    }
+}
+
+/// Starts decoding the tensor at the asset path `path` on the async compute task pool.
+pub(crate) fn start_gav_loading(commands: &mut Commands, args: &PreviewArgs, path: String) {
+    let file = args.asset_file(&path);
+    let size = std::fs::metadata(&file).map_or(0, |m| m.len());
+    let read = Arc::new(AtomicU64::new(0));
+    let task = {
+        let (path, read) = (path.clone(), read.clone());
+        AsyncComputeTaskPool::get().spawn(async move { decode_gav(&file, path, read) })
+    };
+    commands.insert_resource(GavLoading {
+        path,
+        size,
+        read,
+        task,
+    });
+}
+
+fn decode_gav(file: &Path, path: String, read: Arc<AtomicU64>) -> Result<Animation> {
+    let reader = ProgressReader {
+        inner: BufReader::new(File::open(file)?),
+        read,
+    };
+    let (animation, skeleton, frame_time) = load_gav_from(file, reader)?;
+    let mut key_frames = gav_key_frames(&animation, &skeleton, frame_time);
+    key_frames.events = read_events(&events_path(file))?;
+    Ok(Animation {
+        skeleton: joint_hierarchy(&skeleton)?,
+        key_frames,
+        path,
+    })
+}
+
+/// Tracks of a decoded tensor. Only the root is translated, the other joints keep their offset.
+fn gav_key_frames(
+    animation: &bvh_to_gav::Animation,
+    skeleton: &Skeleton,
+    frame_time: f32,
+) -> KeyFrames {
+    let mut joint_translations = IndexMap::new();
+    if let Some(root) = skeleton.joints.first() {
+        joint_translations.insert(root.name.clone(), animation.root_positions.clone());
+    }
+    let joint_rotations = skeleton
+        .joint_order()
+        .map(String::from)
+        .zip(animation.joint_rotations.iter().cloned())
+        .collect();
+    KeyFrames {
+        frame_time,
+        count: animation.frame_count(),
+        joint_translations,
+        joint_rotations,
+        events: Vec::new(),
+    }
+}
+
+fn joint_hierarchy(skeleton: &Skeleton) -> Result<JointHierarchy> {
+    fn build_hierarchy(skeleton: &Skeleton, index: usize) -> JointHierarchy {
+        let joint = &skeleton.joints[index];
+        JointHierarchy {
+            name: joint.name.clone(),
+            offset: joint.offset,
+            end: joint.end_site,
+            children: skeleton
+                .children(index)
+                .map(|child| build_hierarchy(skeleton, child))
+                .collect(),
+        }
+    }
+
+    let roots: Vec<usize> = skeleton.roots().collect();
+    match roots.as_slice() {
+        [root] => Ok(build_hierarchy(skeleton, *root)),
+        _ => Err(format!("Expected one root joint, found {}", roots.len()).into()),
+    }
+}
+
+pub(crate) fn await_gav_loaded(
+    mut commands: Commands,
+    loading: Option<ResMut<GavLoading>>,
+    mut load_state: ResMut<LoadState>,
+) {
+    let Some(mut loading) = loading else {
+        return;
+    };
+    let Some(result) = check_ready(&mut loading.task) else {
+        return;
+    };
+    commands.remove_resource::<GavLoading>();
+    match result {
+        Ok(animation) => {
+            info!("Loaded {}.", loading.path);
+            *load_state = LoadState::Loaded(vec![animation]);
+        }
+        Err(e) => error!("Could not load {}: {}", loading.path, e),
+    }
+}
+
+pub(crate) fn gav_loading_ui(
+    mut contexts: EguiContexts,
+    loading: Option<Res<GavLoading>>,
+) -> Result {
+    let Some(loading) = loading else {
+        return Ok(());
+    };
+    let progress = loading.progress();
+    egui::Window::new("Loading").show(contexts.ctx_mut()?, |ui| {
+        ui.label(&loading.path);
+        let text = if progress < 1.0 {
+            format!("Reading {:.0}%", progress * 100.0)
+        } else {
+            "Decoding".to_string()
+        };
+        ui.add(egui::ProgressBar::new(progress).text(text).animate(true));
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bvh_to_gav::skeleton::SkeletonJoint;
+
+    use super::*;
+
+    #[test]
+    fn test_gav_key_frames_follow_skeleton() {
+        let joint = |name: &str, parent, offset| SkeletonJoint {
+            name: name.to_string(),
+            parent,
+            offset,
+            end_site: None,
+        };
+        let skeleton = Skeleton {
+            joints: vec![
+                joint("Hips", None, Vec3::ZERO),
+                joint("Spine", Some(0), Vec3::Y),
+                joint("Neck", Some(1), Vec3::Y),
+            ],
+        };
+        let turn = Quat::from_rotation_y(1.0);
+        let animation = bvh_to_gav::Animation {
+            root_positions: vec![Vec3::ZERO, Vec3::X],
+            joint_rotations: vec![vec![Quat::IDENTITY, turn]; 3],
+            events: Vec::new(),
+        };
+
+        let key_frames = gav_key_frames(&animation, &skeleton, 0.5);
+        assert_eq!(key_frames.count, 2);
+        assert_eq!(
+            key_frames.joint_order().collect::<Vec<_>>(),
+            ["Hips", "Spine", "Neck"]
+        );
+        assert_eq!(key_frames.joint_translations["Hips"][1], Vec3::X);
+        assert_eq!(key_frames.joint_rotations["Neck"][1], turn);
+
+        let hierarchy = joint_hierarchy(&skeleton).unwrap();
+        assert_eq!(hierarchy.children[0].children[0].name, "Neck");
+        assert_eq!(hierarchy.children[0].offset, Vec3::Y);
+    }
+}
@@ -0,0 +1,267 @@
+//! Hierarchy panel: the joint tree of the loaded clip with the channels each joint has in its
+//! BVH file and their degrees of freedom, and how a joint map, see [`bvh_to_gav::joint_map`],
+//! maps every joint. Joints that don't fit are highlighted, to debug rigs and joint maps
+//! without a conversion run.
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use bvh_to_gav::joint_map::{JointMap, JointMapCheck};
+
+use crate::{
+    Animation, AnimationTimeline, LoadState, bvh_asset_loader::JointHierarchy,
+    capture::PreviewArgs, skeleton_editor::hierarchy_skeleton,
+};
+
+/// Joints folded by a joint map that turn further than this, in degrees, are highlighted, since
+/// folding is only exact for joints that don't move.
+const FOLDED_MOTION_DEGREES: f32 = 1.0;
+
+/// Channels declared by every joint in the `HIERARCHY` section of a BVH file, in file order.
+fn bvh_channels(text: &str) -> Vec<(String, Vec<String>)> {
+    let mut joints: Vec<(String, Vec<String>)> = Vec::new();
+    for line in text.lines() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("ROOT" | "JOINT") => {
+                joints.push((words.collect::<Vec<_>>().join(" "), Vec::new()));
+            }
+            Some("CHANNELS") => {
+                if let Some((_, channels)) = joints.last_mut() {
+                    channels.extend(words.skip(1).map(String::from));
+                }
+            }
+            Some("MOTION") => break,
+            _ => {}
+        }
+    }
+    joints
+}
+
+/// Why `channels` don't fit a joint, the root with three positions and three rotations, the
+/// other joints with three rotations.
+fn channel_mismatch(channels: &[String], root: bool) -> Option<&'static str> {
+    let rotations = channels.iter().filter(|c| c.ends_with("rotation")).count();
+    let positions = channels.iter().filter(|c| c.ends_with("position")).count();
+    if rotations != 3 {
+        Some("expected 3 rotation channels")
+    } else if root && positions != 3 {
+        Some("expected 3 position channels on the root")
+    } else if rotations + positions != channels.len() {
+        Some("unsupported channels")
+    } else {
+        None
+    }
+}
+
+/// Largest angle in degrees `joint` turns away from its first frame.
+fn motion_range(animation: &Animation, joint: &str) -> f32 {
+    let Some(rotations) = animation.key_frames.joint_rotations.get(joint) else {
+        return 0.0;
+    };
+    let first = rotations.first().copied().unwrap_or_default();
+    rotations
+        .iter()
+        .map(|rotation| rotation.angle_between(first).to_degrees())
+        .fold(0.0, f32::max)
+}
+
+#[derive(Resource, Default)]
+pub(crate) struct HierarchyView {
+    /// Asset path of the clip the channels were read for.
+    path: String,
+    /// Channels by joint name, empty for clips that aren't BVH files.
+    channels: Vec<(String, Vec<String>)>,
+    /// Joint map file, relative to the asset folder.
+    joint_map_path: String,
+    check: Option<JointMapCheck>,
+    error: Option<String>,
+}
+
+impl HierarchyView {
+    fn read(&mut self, args: &PreviewArgs, animation: &Animation) {
+        self.path = animation.path.clone();
+        self.channels.clear();
+        self.check = None;
+        self.error = None;
+        if animation.path.to_lowercase().ends_with(".bvh") {
+            match std::fs::read_to_string(args.asset_file(&animation.path)) {
+                Ok(text) => self.channels = bvh_channels(&text),
+                Err(e) => self.error = Some(format!("Could not read the channels: {}", e)),
+            }
+        }
+        if !self.joint_map_path.is_empty() {
+            self.check_joint_map(args, animation);
+        }
+    }
+
+    fn check_joint_map(&mut self, args: &PreviewArgs, animation: &Animation) {
+        let skeleton = hierarchy_skeleton(&animation.skeleton);
+        match JointMap::load(&args.asset_file(&self.joint_map_path)) {
+            Ok(map) => {
+                self.check = Some(map.check(&skeleton));
+                self.error = None;
+            }
+            Err(e) => {
+                self.check = None;
+                self.error = Some(format!("{:#}", e));
+            }
+        }
+    }
+
+    fn joint_ui(
+        &self,
+        ui: &mut egui::Ui,
+        animation: &Animation,
+        joint: &JointHierarchy,
+        root: bool,
+    ) {
+        let channels = self
+            .channels
+            .iter()
+            .find(|(name, _)| *name == joint.name)
+            .map(|(_, channels)| channels.as_slice());
+        let mut problems = Vec::new();
+        if let Some(problem) = channels.and_then(|channels| channel_mismatch(channels, root)) {
+            problems.push(problem.to_string());
+        }
+        let mapped = self.check.as_ref().and_then(|check| {
+            let index = hierarchy_position(&animation.skeleton, &joint.name)?;
+            check.joints.get(index).cloned()
+        });
+        let status = match &mapped {
+            Some((name, Some(output))) => format!("→ {} #{}", name, output),
+            Some((_, None)) => {
+                let range = motion_range(animation, &joint.name);
+                if range > FOLDED_MOTION_DEGREES {
+                    problems.push(format!("folded but turns {:.0}°", range));
+                }
+                "folded".to_string()
+            }
+            None => String::new(),
+        };
+
+        let mut text = joint.name.clone();
+        if let Some(channels) = channels {
+            text.push_str(&format!("  {} DOF: {}", channels.len(), channels.join(" ")));
+        }
+        if !status.is_empty() {
+            text.push_str(&format!("  {}", status));
+        }
+        let color = if problems.is_empty() {
+            ui.visuals().text_color()
+        } else {
+            egui::Color32::RED
+        };
+        let header = egui::RichText::new(text).color(color);
+        let response = egui::CollapsingHeader::new(header)
+            .id_salt(&joint.name)
+            .default_open(true)
+            .show(ui, |ui| {
+                for child in &joint.children {
+                    self.joint_ui(ui, animation, child, false);
+                }
+            });
+        if !problems.is_empty() {
+            response.header_response.on_hover_text(problems.join("\n"));
+        }
+    }
+}
+
+/// Index of `name` in the depth first order of `skeleton`, the order of the parsed BVH file.
+fn hierarchy_position(skeleton: &JointHierarchy, name: &str) -> Option<usize> {
+    fn visit(joint: &JointHierarchy, name: &str, index: &mut usize) -> bool {
+        if joint.name == name {
+            return true;
+        }
+        *index += 1;
+        joint.children.iter().any(|child| visit(child, name, index))
+    }
+    let mut index = 0;
+    visit(skeleton, name, &mut index).then_some(index)
+}
+
+pub(crate) fn hierarchy_view_ui(
+    mut contexts: EguiContexts,
+    mut view: ResMut<HierarchyView>,
+    load_state: Res<LoadState>,
+    timeline: Res<AnimationTimeline>,
+    args: Res<PreviewArgs>,
+) -> Result {
+    let LoadState::Loaded(animations) = &*load_state else {
+        return Ok(());
+    };
+    let Some(animation) = animations.get(timeline.anim_index) else {
+        return Ok(());
+    };
+    if animation.path != view.path {
+        view.read(&args, animation);
+    }
+
+    let view = &mut *view;
+    egui::Window::new("Hierarchy")
+        .default_open(false)
+        .show(contexts.ctx_mut()?, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Joint map");
+                ui.text_edit_singleline(&mut view.joint_map_path)
+                    .on_hover_text("TOML joint map, relative to the asset folder");
+                if ui.button("Check").clicked() {
+                    view.check_joint_map(&args, animation);
+                }
+            });
+            if let Some(check) = &view.check {
+                for joint in &check.unmatched {
+                    ui.colored_label(
+                        egui::Color32::RED,
+                        format!("{} matches no joint, or more than one", joint),
+                    );
+                }
+                for joint in &check.unused_renames {
+                    ui.colored_label(
+                        egui::Color32::YELLOW,
+                        format!("{} is renamed but not in the skeleton", joint),
+                    );
+                }
+            }
+            if let Some(error) = &view.error {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+            ui.separator();
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                view.joint_ui(ui, animation, &animation.skeleton, true);
+            });
+        });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bvh_channels_are_read_per_joint() {
+        let text = "HIERARCHY
+ROOT Hips
+{
+  OFFSET 0 0 0
+  CHANNELS 6 Xposition Yposition Zposition Zrotation Xrotation Yrotation
+  JOINT Spine
+  {
+    OFFSET 0 10 0
+    CHANNELS 2 Zrotation Xrotation
+    End Site
+    {
+      OFFSET 0 5 0
+    }
+  }
+}
+MOTION
+Frames: 1
+";
+        let channels = bvh_channels(text);
+        assert_eq!(channels.len(), 2);
+        assert_eq!(channels[0].0, "Hips");
+        assert_eq!(channel_mismatch(&channels[0].1, true), None);
+        assert_eq!(channels[1].1, ["Zrotation", "Xrotation"]);
+        assert!(channel_mismatch(&channels[1].1, false).is_some());
+    }
+}
@@ -0,0 +1,203 @@
+//! Where the current pose lies in the rotation distribution of each joint, from the report
+//! `bvh_to_gav joint-pca` writes next to the clips. Joints whose local rotation is out of
+//! distribution are marked on the skeleton, and the Layers window plots the dataset frames of a
+//! joint on its first two principal axes with the current frame on top.
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use bevy_egui::egui;
+use bvh_to_gav::joint_pca::{JOINT_PCA_FILE, JointPcaReport, OUT_OF_DISTRIBUTION};
+use preview::visualization::{LayerContext, VisualizationLayer};
+
+const JOINT_RADIUS: f32 = 3.0;
+const PLOT_SIZE: f32 = 200.0;
+const SAMPLE_RADIUS: f32 = 1.5;
+const POSE_RADIUS: f32 = 4.0;
+
+/// Rotation of every joint relative to its parent, the world rotation for roots.
+fn local_rotations(context: &LayerContext) -> Vec<Quat> {
+    context
+        .joints
+        .iter()
+        .map(|joint| {
+            let local = match joint.parent {
+                Some(parent) => context.joints[parent].transform.inverse() * joint.transform,
+                None => joint.transform,
+            };
+            local.to_scale_rotation_translation().1
+        })
+        .collect()
+}
+
+/// Layer showing the current pose against the joint PCA report of the folder of the clip on
+/// the timeline.
+pub(crate) struct JointPcaOverlay {
+    /// Folder the asset paths of clips are relative to.
+    root: PathBuf,
+    folder: Option<PathBuf>,
+    report: Result<JointPcaReport, String>,
+    /// Joint plotted in the Layers window.
+    selected: Option<String>,
+}
+
+impl JointPcaOverlay {
+    pub fn new(root: PathBuf) -> Self {
+        JointPcaOverlay {
+            root,
+            folder: None,
+            report: Err(String::new()),
+            selected: None,
+        }
+    }
+
+    fn load(&mut self, context: &LayerContext) {
+        let folder = Path::new(&context.clip)
+            .parent()
+            .unwrap_or(Path::new(""))
+            .to_path_buf();
+        if self.folder.as_ref() == Some(&folder) {
+            return;
+        }
+        let path = self.root.join(&folder).join(JOINT_PCA_FILE);
+        self.folder = Some(folder);
+        self.report = if path.exists() {
+            JointPcaReport::load(&path)
+                .map_err(|e| format!("Could not read {}: {}", path.display(), e))
+        } else {
+            Err(format!(
+                "No {}, run bvh_to_gav joint-pca on the folder",
+                path.display()
+            ))
+        };
+        if let Err(e) = &self.report {
+            info!("{}", e);
+        }
+    }
+}
+
+/// Scatter plot of the dataset samples of a joint, with `pose` drawn over them.
+fn scatter_plot(ui: &mut egui::Ui, samples: &[Vec2], pose: Vec2, outlier: bool) {
+    let (rect, _) = ui.allocate_exact_size(egui::Vec2::splat(PLOT_SIZE), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0.0, egui::Color32::WHITE);
+    let extent = samples
+        .iter()
+        .chain([&pose])
+        .map(|sample| sample.abs().max_element())
+        .fold(f32::EPSILON, f32::max)
+        * 1.1;
+    let to_screen =
+        |point: Vec2| rect.center() + egui::vec2(point.x, -point.y) * (rect.width() / 2.0 / extent);
+    let axes = egui::Stroke::new(1.0, egui::Color32::LIGHT_GRAY);
+    painter.hline(rect.x_range(), rect.center().y, axes);
+    painter.vline(rect.center().x, rect.y_range(), axes);
+    for &sample in samples {
+        painter.circle_filled(to_screen(sample), SAMPLE_RADIUS, egui::Color32::GRAY);
+    }
+    painter.circle_filled(
+        to_screen(pose),
+        POSE_RADIUS,
+        if outlier {
+            egui::Color32::RED
+        } else {
+            egui::Color32::DARK_GREEN
+        },
+    );
+}
+
+impl VisualizationLayer for JointPcaOverlay {
+    fn name(&self) -> &str {
+        "Joint PCA"
+    }
+
+    fn draw(&mut self, context: &LayerContext, gizmos: &mut Gizmos) {
+        self.load(context);
+        let Ok(report) = &self.report else {
+            return;
+        };
+        for (joint, rotation) in context.joints.iter().zip(local_rotations(context)) {
+            let Some(pca) = report.find(&joint.name) else {
+                continue;
+            };
+            if pca.distance(rotation) > OUT_OF_DISTRIBUTION {
+                gizmos.sphere(joint.position(), JOINT_RADIUS, Color::srgb(1.0, 0.0, 0.0));
+            }
+        }
+    }
+
+    fn ui(&mut self, context: &LayerContext, ui: &mut egui::Ui) {
+        let report = match &self.report {
+            Ok(report) => report,
+            Err(e) => {
+                ui.label(e);
+                return;
+            }
+        };
+        ui.label(format!(
+            "{} clips, {} frames",
+            report.clip_count, report.frame_count
+        ));
+        let rotations = local_rotations(context);
+        let distances: Vec<(&str, f32)> = context
+            .joints
+            .iter()
+            .zip(&rotations)
+            .filter_map(|(joint, rotation)| {
+                Some((
+                    joint.name.as_str(),
+                    report.find(&joint.name)?.distance(*rotation),
+                ))
+            })
+            .collect();
+        if distances.is_empty() {
+            ui.label("No joint of this clip is in the report");
+            return;
+        }
+
+        let selected = self
+            .selected
+            .get_or_insert_with(|| distances[0].0.to_string());
+        egui::ComboBox::from_label("Joint")
+            .selected_text(selected.as_str())
+            .show_ui(ui, |ui| {
+                for (name, _) in &distances {
+                    ui.selectable_value(selected, name.to_string(), *name);
+                }
+            });
+        let joint = context
+            .joints
+            .iter()
+            .position(|joint| &joint.name == selected);
+        if let (Some(joint), Some(pca)) = (joint, report.find(selected)) {
+            let distance = pca.distance(rotations[joint]);
+            ui.label(format!(
+                "{:.1} std from the mean, the plot explains {:.0}% of the variance",
+                distance,
+                pca.explained() * 100.0
+            ));
+            scatter_plot(
+                ui,
+                &pca.samples,
+                pca.project(rotations[joint]),
+                distance > OUT_OF_DISTRIBUTION,
+            );
+        } else {
+            ui.label(format!("{} is not in this clip", selected));
+        }
+
+        let mut outliers: Vec<_> = distances
+            .into_iter()
+            .filter(|(_, distance)| *distance > OUT_OF_DISTRIBUTION)
+            .collect();
+        outliers.sort_by(|a, b| b.1.total_cmp(&a.1));
+        if outliers.is_empty() {
+            ui.label("Every joint is in distribution");
+        }
+        for (name, distance) in outliers {
+            ui.colored_label(
+                egui::Color32::LIGHT_RED,
+                format!("{} {:.1} std", name, distance),
+            );
+        }
+    }
+}
@@ -0,0 +1,2 @@
+//! Parts of the preview that other crates can build on.
+pub mod visualization;
@@ -1,5 +1,29 @@
 //! Plays an animation on a skinned glTF model of a fox.
+mod blend_tree;
+mod bone_renderer;
 mod bvh_asset_loader;
+mod capture;
+mod contact_sheet;
+mod conversion_panel;
+mod coverage_overlay;
+mod curve_plot;
+mod event_track;
+mod frame_table;
+mod gamepad_control;
+mod gav_loading;
+mod hierarchy_view;
+mod joint_pca_overlay;
+mod metadata_panel;
+mod model_outputs;
+mod palette;
+mod playback;
+mod review_scene;
+mod scripting;
+mod similar_clips;
+mod skeleton_editor;
+mod state_machine;
+mod uncertainty;
+mod weight_overlay;
 use bevy::{
     color::palettes::css::{BLUE, GREEN, RED, YELLOW},
     ecs::{error, world},
@@ -17,20 +41,82 @@ use std::f32::consts::PI;
 
 use bevy_egui::{EguiContexts, EguiPlugin, EguiPrimaryContextPass, egui};
 use bvh_asset_loader::BvhAssetLoader;
+use preview::visualization::{
+    GazeLayer, LayerContext, LayerJoint, VisualizationLayerApp, VisualizationPlugin,
+    draw_visualization_layers,
+};
 
-use crate::bvh_asset_loader::{BvhAsset, BvhAssetLabel, CharacterJoint, JointHierarchy, KeyFrames};
+use crate::blend_tree::{
+    BLEND_TREE_EXTENSION, BlendTree, BlendTreeLoader, BlendTreeState, await_blend_tree_loaded,
+    blend_tree_ui, update_blend_tree,
+};
+use crate::bone_renderer::{
+    BoneRenderMode, PoseRenderer, PoseSegments, setup_bone_instances, sync_bone_instances,
+    toggle_bone_render_mode,
+};
+use crate::bvh_asset_loader::{
+    BvhAsset, BvhAssetLabel, BvhLoaderSettings, CharacterJoint, JointHierarchy, KeyFrames,
+};
+use crate::capture::{PreviewArgs, USAGE, capture_requested_frame};
+use crate::contact_sheet::capture_contact_sheet;
+use crate::conversion_panel::{ConversionPanel, conversion_panel_ui};
+use crate::coverage_overlay::CoverageOverlay;
+use crate::curve_plot::curve_plot_ui;
+use crate::event_track::{EventDraft, event_track_ui};
+use crate::frame_table::frame_table_ui;
+use crate::gamepad_control::{GamepadControl, drive_blend_tree_with_gamepad};
+use crate::gav_loading::{await_gav_loaded, gav_loading_ui, is_gav_path, start_gav_loading};
+use crate::hierarchy_view::{HierarchyView, hierarchy_view_ui};
+use crate::joint_pca_overlay::JointPcaOverlay;
+use crate::metadata_panel::{MetadataPanel, metadata_panel_ui};
+use crate::model_outputs::{load_model_outputs, model_outputs_ui};
+use crate::palette::{Palette, egui_color, load_palette, palette_ui};
+use crate::playback::{
+    PlaybackMode, advance_timeline_real_time, draw_root_trajectory, playback_ui, root_translation,
+    step_timeline_fixed, sync_fixed_timestep,
+};
+use crate::review_scene::{REVIEW_SCENE_EXTENSION, save_review_scene};
+use crate::scripting::{ScriptRunner, run_script_commands, run_startup_script, script_console_ui};
+use crate::similar_clips::{load_similar_clips, similar_clips_ui};
+use crate::skeleton_editor::{SkeletonEditor, skeleton_editor_ui};
+use crate::state_machine::{
+    STATE_MACHINE_EXTENSION, StateMachine, StateMachineLoader, StateMachineState,
+    await_state_machine_loaded, state_machine_ui, update_state_machine,
+};
+use crate::uncertainty::UncertaintyOverlay;
+use crate::weight_overlay::WeightOverlay;
 
 // An example asset that contains a mesh and animation.
 const ANIMATION_FILE: &str = "corrected_animations/dataset-1_bow_active_001.bvh";
 
-#[derive(Resource, Default)]
+#[derive(Resource, Reflect, Default)]
+#[reflect(Resource, Default)]
 struct AnimationTimeline {
-    next_frame_time: f32,
     current_frame: usize,
     anim_index: usize,
+    playing: bool,
+    mode: PlaybackMode,
+    /// Fraction of a frame played in real time mode but not shown yet.
+    frame_progress: f32,
+    /// Plays the clip in place instead of in world space, see [`playback`].
+    in_place: bool,
 }
 
 fn main() {
+    let args = PreviewArgs::from_env().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        eprintln!("{}", USAGE);
+        std::process::exit(1);
+    });
+    let mut asset_plugin = AssetPlugin::default();
+    if let Some(folder) = args.asset_folder() {
+        asset_plugin.file_path = folder;
+    }
+    let weight_overlay = WeightOverlay::new(args.asset_file(""));
+    let uncertainty_overlay = UncertaintyOverlay::new(args.asset_file(""));
+    let coverage_overlay = CoverageOverlay::new(args.asset_file(""));
+    let joint_pca_overlay = JointPcaOverlay::new(args.asset_file(""));
+
     App::new()
         .insert_resource(AmbientLight {
             color: Color::WHITE,
@@ -38,23 +124,100 @@ fn main() {
             ..default()
         })
         .register_type::<CharacterJoint>()
-        .add_plugins(DefaultPlugins)
+        .register_type::<AnimationTimeline>()
+        .register_type::<LoadState>()
+        .add_plugins(DefaultPlugins.set(asset_plugin))
         .add_plugins(LookTransformPlugin)
         .add_plugins(UnrealCameraPlugin::default())
         .add_plugins(EguiPlugin::default())
+        .add_plugins(VisualizationPlugin)
+        .add_visualization_layer(GazeLayer)
+        .add_visualization_layer(weight_overlay)
+        .add_visualization_layer(uncertainty_overlay)
+        .add_visualization_layer(coverage_overlay)
+        .add_visualization_layer(joint_pca_overlay)
         .insert_resource(AnimationTimeline::default())
         .insert_resource(LoadState::default())
+        .insert_resource(args)
+        .init_resource::<BoneRenderMode>()
+        .init_resource::<ConversionPanel>()
+        .init_resource::<HierarchyView>()
+        .init_resource::<MetadataPanel>()
+        .init_resource::<PoseSegments>()
+        .init_resource::<ScriptRunner>()
+        .init_resource::<SkeletonEditor>()
         .init_asset::<BvhAsset>()
         .init_asset::<KeyFrames>()
         .init_asset::<JointHierarchy>()
         .init_asset_loader::<BvhAssetLoader>()
+        .init_asset::<BlendTree>()
+        .init_asset_loader::<BlendTreeLoader>()
+        .init_asset::<StateMachine>()
+        .init_asset_loader::<StateMachineLoader>()
         // .add_systems(Startup, setup_mesh_and_animation)
         .add_systems(Startup, setup_camera_and_environment)
         .add_systems(Startup, load_animation)
+        .add_systems(Startup, load_similar_clips)
+        .add_systems(Startup, load_model_outputs)
+        .add_systems(Startup, setup_bone_instances)
+        .add_systems(Startup, load_palette)
+        .add_systems(Startup, run_startup_script)
         .add_systems(Update, await_animation_loaded)
-        .add_systems(Update, update_animation)
+        .add_systems(Update, await_gav_loaded)
+        .add_systems(
+            Update,
+            (
+                sync_fixed_timestep,
+                advance_timeline_real_time,
+                update_animation,
+            )
+                .chain(),
+        )
+        .add_systems(FixedUpdate, step_timeline_fixed)
+        .add_systems(Update, capture_requested_frame)
+        .add_systems(Update, save_review_scene)
+        .add_systems(Update, capture_contact_sheet)
+        .add_systems(Update, run_script_commands)
+        .add_systems(
+            Update,
+            update_layer_context
+                .after(update_animation)
+                .before(draw_visualization_layers),
+        )
+        .add_systems(Update, toggle_bone_render_mode)
+        .add_systems(Update, draw_root_trajectory)
+        .add_systems(
+            PostUpdate,
+            sync_bone_instances.before(TransformSystem::TransformPropagate),
+        )
+        .add_systems(
+            Update,
+            (
+                await_blend_tree_loaded,
+                drive_blend_tree_with_gamepad.run_if(resource_exists::<GamepadControl>),
+                update_blend_tree,
+            )
+                .chain(),
+        )
+        .add_systems(
+            Update,
+            (await_state_machine_loaded, update_state_machine).chain(),
+        )
         // .add_systems(Update, draw_characters)
         .add_systems(EguiPrimaryContextPass, timeline_slider_ui)
+        .add_systems(EguiPrimaryContextPass, blend_tree_ui)
+        .add_systems(EguiPrimaryContextPass, state_machine_ui)
+        .add_systems(EguiPrimaryContextPass, similar_clips_ui)
+        .add_systems(EguiPrimaryContextPass, model_outputs_ui)
+        .add_systems(EguiPrimaryContextPass, gav_loading_ui)
+        .add_systems(EguiPrimaryContextPass, conversion_panel_ui)
+        .add_systems(EguiPrimaryContextPass, metadata_panel_ui)
+        .add_systems(EguiPrimaryContextPass, skeleton_editor_ui)
+        .add_systems(EguiPrimaryContextPass, hierarchy_view_ui)
+        .add_systems(EguiPrimaryContextPass, palette_ui)
+        .add_systems(EguiPrimaryContextPass, curve_plot_ui)
+        .add_systems(EguiPrimaryContextPass, frame_table_ui)
+        .add_systems(EguiPrimaryContextPass, script_console_ui)
         .run();
 }
 
@@ -67,12 +230,16 @@ struct AnimationToPlay {
     index: AnimationNodeIndex,
 }
 
+#[derive(Reflect)]
 pub struct Animation {
     key_frames: KeyFrames,
     skeleton: JointHierarchy,
+    /// Asset path of the clip, files edited in the preview are saved next to it.
+    path: String,
 }
 
-#[derive(Default, Resource)]
+#[derive(Default, Resource, Reflect)]
+#[reflect(Resource, Default)]
 pub enum LoadState {
     #[default]
     Waiting,
@@ -80,8 +247,53 @@ pub enum LoadState {
     Loaded(Vec<Animation>),
 }
 
-fn load_animation(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let handle = asset_server.load::<BvhAsset>(ANIMATION_FILE);
+fn load_animation(mut commands: Commands, asset_server: Res<AssetServer>, args: Res<PreviewArgs>) {
+    let path = args
+        .asset_path()
+        .unwrap_or_else(|| ANIMATION_FILE.to_string());
+    if path.ends_with(BLEND_TREE_EXTENSION) {
+        let handle = asset_server.load::<BlendTree>(path);
+        commands.insert_resource(BlendTreeState::Loading(handle));
+        if args.gamepad {
+            commands.insert_resource(GamepadControl::default());
+        }
+        return;
+    }
+    if args.gamepad {
+        warn!("--gamepad requires a blend tree, ignoring it.");
+    }
+    if path.ends_with(STATE_MACHINE_EXTENSION) {
+        let handle = asset_server.load::<StateMachine>(path);
+        commands.insert_resource(StateMachineState::Loading(handle));
+        return;
+    }
+    if path.ends_with(REVIEW_SCENE_EXTENSION) {
+        // Spawning the scene restores the saved resources, including the loaded animation.
+        commands.spawn(DynamicSceneRoot(asset_server.load(path)));
+        return;
+    }
+    load_clip(&mut commands, &asset_server, &args, path);
+}
+
+/// Starts loading a BVH clip or GAV tensor at the asset path `path`.
+fn load_clip(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    args: &PreviewArgs,
+    path: String,
+) {
+    if is_gav_path(&path) {
+        start_gav_loading(commands, args, path);
+        return;
+    }
+    let handle = match args.convention {
+        Some(convention) => {
+            asset_server.load_with_settings::<BvhAsset, BvhLoaderSettings>(path, move |settings| {
+                settings.convention = convention;
+            })
+        }
+        None => asset_server.load::<BvhAsset>(path),
+    };
     commands.insert_resource(LoadState::Loading(handle));
 }
 
@@ -105,6 +317,7 @@ fn await_animation_loaded(
                         *load_state = LoadState::Loaded(vec![Animation {
                             key_frames: kf.clone(),
                             skeleton: skeleton.clone(),
+                            path: asset_path(handle),
                         }]);
                     }
                     _ => {
@@ -118,6 +331,13 @@ fn await_animation_loaded(
     }
 }
 
+fn asset_path<A: Asset>(handle: &Handle<A>) -> String {
+    handle
+        .path()
+        .map(|path| path.path().to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
 fn draw_children_rest_position(
     gizmos: &mut Gizmos,
     joint: &JointHierarchy,
@@ -170,6 +390,7 @@ fn draw_pose(
     current_frame: usize,
     parent_transform: Mat4,
     rest: bool,
+    color: Color,
 ) {
     let joint_rotation = if rest {
         Quat::IDENTITY
@@ -191,7 +412,7 @@ fn draw_pose(
         let child_world_position = (joint_transform * Mat4::from_translation(child.offset))
             .col(3)
             .xyz();
-        gizmos.line(world_position, child_world_position, Color::WHITE);
+        gizmos.line(world_position, child_world_position, color);
         draw_pose(
             gizmos,
             child,
@@ -199,37 +420,99 @@ fn draw_pose(
             current_frame,
             joint_transform,
             rest,
+            color,
         );
     }
 }
 
-fn update_animation(
-    mut gizmos: Gizmos,
-    mut timeline: ResMut<AnimationTimeline>,
+/// Joints of `skeleton` posed at `current_frame` with the transforms of [`draw_pose`], appended
+/// to `joints` in depth first order.
+fn layer_joints(
+    joints: &mut Vec<LayerJoint>,
+    skeleton: &JointHierarchy,
+    key_frames: &KeyFrames,
+    current_frame: usize,
+    parent: Option<usize>,
+    parent_transform: Mat4,
+    rest: bool,
+) {
+    let joint_rotation = if rest {
+        Quat::IDENTITY
+    } else {
+        key_frames.joint_rotations[&skeleton.name][current_frame]
+    };
+    let transform =
+        parent_transform * Mat4::from_rotation_translation(joint_rotation, skeleton.offset);
+    let index = joints.len();
+    joints.push(LayerJoint {
+        name: skeleton.name.clone(),
+        parent,
+        transform,
+    });
+    for child in &skeleton.children {
+        layer_joints(
+            joints,
+            child,
+            key_frames,
+            current_frame,
+            Some(index),
+            transform,
+            rest,
+        );
+    }
+}
+
+/// Poses the clip on the timeline for the visualization layers.
+fn update_layer_context(
+    mut commands: Commands,
+    timeline: Res<AnimationTimeline>,
     animation: Res<LoadState>,
-    time: Res<Time>,
 ) {
-    // if timeline.next_frame_time >= time.elapsed_secs() {
-    //     return;
-    // }
+    let LoadState::Loaded(animations) = &*animation else {
+        return;
+    };
+    let Some(animation) = animations.get(timeline.anim_index) else {
+        return;
+    };
+    let root_translation = root_translation(animation, timeline.current_frame, timeline.in_place);
+    let mut joints = Vec::new();
+    layer_joints(
+        &mut joints,
+        &animation.skeleton,
+        &animation.key_frames,
+        timeline.current_frame,
+        None,
+        Mat4::from_translation(root_translation),
+        timeline.current_frame == 0,
+    );
+    commands.insert_resource(LayerContext {
+        clip: animation.path.clone(),
+        frame: timeline.current_frame,
+        frame_count: animation.key_frames.count,
+        frame_time: animation.key_frames.frame_time,
+        playing: timeline.playing,
+        joints,
+    });
+}
 
+fn update_animation(
+    mut poses: PoseRenderer,
+    timeline: Res<AnimationTimeline>,
+    animation: Res<LoadState>,
+) {
     if let LoadState::Loaded(animations) = &*animation {
         let animation = &animations[timeline.anim_index];
-        let root_translation = animation.key_frames.joint_translations[&animation.skeleton.name]
-            [timeline.current_frame];
+        let root_translation =
+            root_translation(animation, timeline.current_frame, timeline.in_place);
 
-        draw_pose(
-            &mut gizmos,
+        poses.draw_pose(
+            &animation.path,
             &animation.skeleton,
             &animation.key_frames,
             timeline.current_frame,
             Mat4::from_translation(root_translation),
             timeline.current_frame == 0,
         );
-
-        // timeline.current_frame += 1;
-        // timeline.current_frame %= animation.key_frames.count;
-        timeline.next_frame_time = time.elapsed_secs() + animation.key_frames.frame_time;
     }
 }
 
@@ -401,20 +684,28 @@ fn draw_characters(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn timeline_slider_ui(
     mut contexts: EguiContexts,
     mut timeline: ResMut<AnimationTimeline>,
     mut controllers: Query<&mut UnrealCameraController>,
-    animations: Res<LoadState>,
+    mut animations: ResMut<LoadState>,
+    mut draft: Local<EventDraft>,
+    args: Res<PreviewArgs>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+    mut palette: ResMut<Palette>,
 ) -> Result {
-    if let LoadState::Loaded(animations) = &*animations {
-        let animation = &animations[timeline.anim_index];
+    if let LoadState::Loaded(animations) = &mut *animations {
+        let animation = &mut animations[timeline.anim_index];
         let last_frame = animation.key_frames.count - 1;
+        let color = egui_color(palette.color(&animation.path));
         let ctx = contexts.ctx_mut()?;
         egui::Window::new("Timeline").show(ctx, |ui| {
             let slider =
                 egui::Slider::new(&mut timeline.current_frame, 0..=last_frame).text("Animation");
             ui.add(slider);
+            playback_ui(ui, &mut timeline, &mut virtual_time);
+            event_track_ui(ui, animation, &mut timeline, &mut draft, &args, color);
         });
 
         let pointer_over_ui = ctx.is_pointer_over_area();
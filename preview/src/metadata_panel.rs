@@ -0,0 +1,195 @@
+//! Edits the curation metadata of the clip on the timeline, its `.anim.toml` sidecar, see
+//! [`bvh_to_gav::metadata`], so labels, notes and quality flags are written down while the
+//! motion is being watched.
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use bvh_to_gav::metadata::{
+    ClipMetadata, QUALITY_FLAGS, metadata_path, read_metadata, write_metadata,
+};
+
+use crate::{AnimationTimeline, LoadState, capture::PreviewArgs};
+
+#[derive(Resource, Default)]
+pub(crate) struct MetadataPanel {
+    /// Asset path of the clip the metadata was read for.
+    path: String,
+    metadata: ClipMetadata,
+    /// Labels being edited, split on commas when they change.
+    labels: String,
+    /// Field being added, as key and value.
+    new_field: (String, String),
+    /// Whether there are edits that haven't been saved.
+    modified: bool,
+    error: Option<String>,
+}
+
+impl MetadataPanel {
+    /// Reads the metadata of the clip at the asset path `path`.
+    fn read(&mut self, args: &PreviewArgs, path: &str) {
+        *self = MetadataPanel {
+            path: path.to_string(),
+            ..Default::default()
+        };
+        match read_metadata(&args.asset_file(path)) {
+            Ok(metadata) => {
+                self.labels = metadata.labels.join(", ");
+                self.metadata = metadata;
+            }
+            Err(e) => self.error = Some(format!("{:#}", e)),
+        }
+    }
+
+    fn save(&mut self, args: &PreviewArgs) {
+        let clip = args.asset_file(&self.path);
+        match write_metadata(&clip, &self.metadata) {
+            Ok(()) => {
+                info!("Saved {}", metadata_path(&clip).display());
+                self.modified = false;
+                self.error = None;
+            }
+            Err(e) => self.error = Some(format!("{:#}", e)),
+        }
+    }
+
+    /// Editor of a text field of the metadata, see [`ClipMetadata::set`].
+    fn text_field(&mut self, ui: &mut egui::Ui, key: &str, multiline: bool) {
+        let mut text = self.metadata.get(key).unwrap_or_default();
+        let response = if multiline {
+            ui.text_edit_multiline(&mut text)
+        } else {
+            ui.text_edit_singleline(&mut text)
+        };
+        if response.changed() {
+            self.metadata.set(key, &text);
+            self.modified = true;
+        }
+    }
+
+    fn quality_flags(&mut self, ui: &mut egui::Ui) {
+        let mut flags: Vec<String> = QUALITY_FLAGS.iter().map(|flag| flag.to_string()).collect();
+        // Flags set by other tools are listed too, so they can be cleared.
+        flags.extend(
+            self.metadata
+                .quality
+                .iter()
+                .filter(|flag| !QUALITY_FLAGS.contains(&flag.as_str()))
+                .cloned(),
+        );
+        ui.horizontal_wrapped(|ui| {
+            for flag in flags {
+                let mut set = self.metadata.quality.contains(&flag);
+                if ui.checkbox(&mut set, &flag).changed() {
+                    if set {
+                        self.metadata.quality.push(flag);
+                    } else {
+                        self.metadata.quality.retain(|other| *other != flag);
+                    }
+                    self.modified = true;
+                }
+            }
+        });
+    }
+}
+
+pub(crate) fn metadata_panel_ui(
+    mut contexts: EguiContexts,
+    mut panel: ResMut<MetadataPanel>,
+    load_state: Res<LoadState>,
+    timeline: Res<AnimationTimeline>,
+    args: Res<PreviewArgs>,
+) -> Result {
+    let LoadState::Loaded(animations) = &*load_state else {
+        return Ok(());
+    };
+    let Some(animation) = animations.get(timeline.anim_index) else {
+        return Ok(());
+    };
+    if animation.path != panel.path {
+        panel.read(&args, &animation.path);
+    }
+
+    let panel = &mut *panel;
+    egui::Window::new("Metadata")
+        .default_open(false)
+        .show(contexts.ctx_mut()?, |ui| {
+            egui::Grid::new("clip_metadata")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Labels");
+                    if ui
+                        .text_edit_singleline(&mut panel.labels)
+                        .on_hover_text("Comma separated, e.g. walk, happy")
+                        .changed()
+                    {
+                        panel.metadata.set("labels", &panel.labels);
+                        panel.modified = true;
+                    }
+                    ui.end_row();
+
+                    ui.label("Subject");
+                    panel.text_field(ui, "subject", false);
+                    ui.end_row();
+
+                    ui.label("License");
+                    panel.text_field(ui, "license", false);
+                    ui.end_row();
+
+                    ui.label("Notes");
+                    panel.text_field(ui, "capture_notes", true);
+                    ui.end_row();
+
+                    ui.label("Quality");
+                    panel.quality_flags(ui);
+                    ui.end_row();
+
+                    let keys: Vec<String> = panel.metadata.extra.keys().cloned().collect();
+                    for key in keys {
+                        ui.label(&key);
+                        panel.text_field(ui, &key, false);
+                        ui.end_row();
+                    }
+
+                    ui.add(egui::TextEdit::singleline(&mut panel.new_field.0).hint_text("field"));
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(&mut panel.new_field.1);
+                        let (key, value) = &panel.new_field;
+                        if ui
+                            .add_enabled(!key.trim().is_empty(), egui::Button::new("Add"))
+                            .clicked()
+                        {
+                            panel.metadata.set(key.trim(), value);
+                            panel.new_field = Default::default();
+                            panel.modified = true;
+                        }
+                    });
+                    ui.end_row();
+                });
+
+            if !panel.metadata.corrections.is_empty() {
+                ui.collapsing("Corrections", |ui| {
+                    for correction in &panel.metadata.corrections {
+                        ui.label(correction);
+                    }
+                });
+            }
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(panel.modified, egui::Button::new("Save"))
+                    .clicked()
+                {
+                    panel.save(&args);
+                }
+                if ui
+                    .add_enabled(panel.modified, egui::Button::new("Revert"))
+                    .clicked()
+                {
+                    let path = std::mem::take(&mut panel.path);
+                    panel.read(&args, &path);
+                }
+            });
+            if let Some(error) = &panel.error {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+        });
+    Ok(())
+}
@@ -0,0 +1,110 @@
+//! Lists the clips decoded from model outputs in the folder of the loaded clip, grouped by the
+//! model tag `bvh_to_gav decode --model` records in their metadata, so the outputs of several
+//! checkpoints can be compared one model at a time. Clicking a clip loads it in place of the
+//! current one.
+use std::path::Path;
+
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use bvh_to_gav::{metadata::read_metadata, model_tag::ModelTag};
+
+use crate::{ANIMATION_FILE, AnimationTimeline, LoadState, capture::PreviewArgs};
+
+#[derive(Resource)]
+pub struct ModelOutputs {
+    /// Folder of the clips, relative to the asset root.
+    folder: String,
+    /// File name and tag of every tagged clip, sorted by tag.
+    clips: Vec<(String, ModelTag)>,
+    /// Tags shown, every tag when `None`.
+    filter: Option<ModelTag>,
+}
+
+impl ModelOutputs {
+    fn tags(&self) -> Vec<&ModelTag> {
+        let mut tags: Vec<&ModelTag> = self.clips.iter().map(|(_, tag)| tag).collect();
+        tags.dedup();
+        tags
+    }
+}
+
+pub(crate) fn load_model_outputs(mut commands: Commands, args: Res<PreviewArgs>) {
+    let asset_path = args
+        .asset_path()
+        .unwrap_or_else(|| ANIMATION_FILE.to_string());
+    let folder = Path::new(&asset_path).parent().unwrap_or(Path::new(""));
+    let Ok(entries) = std::fs::read_dir(args.asset_file(folder)) else {
+        return;
+    };
+
+    let mut clips = Vec::new();
+    for path in entries.filter_map(|entry| entry.ok().map(|e| e.path())) {
+        if path.extension().is_none_or(|e| e != "bvh") {
+            continue;
+        }
+        let tag = match read_metadata(&path) {
+            Ok(metadata) => ModelTag::from_metadata(&metadata),
+            Err(e) => {
+                warn!("Could not read the metadata of {}: {}", path.display(), e);
+                None
+            }
+        };
+        if let (Some(tag), Some(name)) = (tag, path.file_name()) {
+            clips.push((name.to_string_lossy().into_owned(), tag));
+        }
+    }
+    if clips.is_empty() {
+        return;
+    }
+    clips.sort_by(|(a_name, a_tag), (b_name, b_tag)| (a_tag, a_name).cmp(&(b_tag, b_name)));
+    commands.insert_resource(ModelOutputs {
+        folder: folder.to_string_lossy().into_owned(),
+        clips,
+        filter: None,
+    });
+}
+
+pub(crate) fn model_outputs_ui(
+    mut contexts: EguiContexts,
+    outputs: Option<ResMut<ModelOutputs>>,
+    asset_server: Res<AssetServer>,
+    mut load_state: ResMut<LoadState>,
+    mut timeline: ResMut<AnimationTimeline>,
+) -> Result {
+    let Some(mut outputs) = outputs else {
+        return Ok(());
+    };
+
+    let mut selected = None;
+    let mut filter = outputs.filter.clone();
+    egui::Window::new("Model outputs").show(contexts.ctx_mut()?, |ui| {
+        let shown = filter
+            .as_ref()
+            .map_or_else(|| "All models".to_string(), ModelTag::to_string);
+        egui::ComboBox::from_id_salt("model_tag")
+            .selected_text(shown)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut filter, None, "All models");
+                for tag in outputs.tags() {
+                    ui.selectable_value(&mut filter, Some(tag.clone()), tag.to_string());
+                }
+            });
+        ui.separator();
+        for (clip, tag) in &outputs.clips {
+            if filter.as_ref().is_some_and(|filter| filter != tag) {
+                continue;
+            }
+            if ui.button(clip).on_hover_text(tag.to_string()).clicked() {
+                selected = Some(clip.clone());
+            }
+        }
+    });
+    outputs.filter = filter;
+
+    if let Some(clip) = selected {
+        let asset_path = Path::new(&outputs.folder).join(clip);
+        *load_state = LoadState::Loading(asset_server.load(asset_path));
+        *timeline = AnimationTimeline::default();
+    }
+    Ok(())
+}
@@ -0,0 +1,191 @@
+//! Colors of clip sources, so a dataset or model variant is drawn in the same color in every
+//! view and every session. The source of a clip is the folder it lives in. A source gets the
+//! next free color of the palette the first time it is shown, and the assignment is saved to
+//! `palette.ron` in the asset folder, where colors can also be edited by hand:
+//!
+//! ```ron
+//! (
+//!     colors: ["#1F77B4", "#FF7F0E", "#2CA02C"],
+//!     sources: {
+//!         "corrected_animations": "#1F77B4",
+//!     },
+//! )
+//! ```
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use crate::capture::PreviewArgs;
+
+pub const PALETTE_FILE: &str = "palette.ron";
+
+/// Colors handed out to new sources, in order. The categorical palette of matplotlib, so plots
+/// made from the same data outside the preview can match.
+const DEFAULT_COLORS: [&str; 10] = [
+    "#1F77B4", "#FF7F0E", "#2CA02C", "#D62728", "#9467BD", "#8C564B", "#E377C2", "#7F7F7F",
+    "#BCBD22", "#17BECF",
+];
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct PaletteConfig {
+    pub colors: Vec<String>,
+    /// Color of every source seen so far, as `#RRGGBB`.
+    pub sources: IndexMap<String, String>,
+}
+
+impl Default for PaletteConfig {
+    fn default() -> Self {
+        PaletteConfig {
+            colors: DEFAULT_COLORS.iter().map(|c| c.to_string()).collect(),
+            sources: IndexMap::new(),
+        }
+    }
+}
+
+impl PaletteConfig {
+    /// Assigns the first palette color not used by another source, cycling through the palette
+    /// once every color is taken.
+    fn assign(&mut self, source: &str) -> String {
+        let color = self
+            .colors
+            .iter()
+            .find(|color| !self.sources.values().any(|used| used == *color))
+            .or_else(|| {
+                self.colors
+                    .get(self.sources.len() % self.colors.len().max(1))
+            })
+            .cloned()
+            .unwrap_or_else(|| DEFAULT_COLORS[0].to_string());
+        self.sources.insert(source.to_string(), color.clone());
+        color
+    }
+}
+
+#[derive(Resource)]
+pub(crate) struct Palette {
+    config: PaletteConfig,
+    /// Folder the asset paths are relative to.
+    root: PathBuf,
+    path: PathBuf,
+}
+
+impl Palette {
+    /// Source of the clip at `asset_path`, the name of its folder on disk.
+    pub fn source(&self, asset_path: &str) -> String {
+        let file = self.root.join(asset_path);
+        let folder = file.parent().and_then(Path::file_name);
+        folder.unwrap_or_default().to_string_lossy().into_owned()
+    }
+
+    /// Color of the source of the clip at `asset_path`, assigning one if it has none yet.
+    pub fn color(&mut self, asset_path: &str) -> Color {
+        let source = self.source(asset_path);
+        let hex = match self.config.sources.get(&source) {
+            Some(hex) => hex.clone(),
+            None => {
+                let hex = self.config.assign(&source);
+                self.save();
+                hex
+            }
+        };
+        Srgba::hex(&hex).map_or(Color::WHITE, Color::from)
+    }
+
+    /// Color of the `index`th series of a plot, cycling through the palette colors.
+    pub fn series_color(&self, index: usize) -> Color {
+        let colors = &self.config.colors;
+        colors
+            .get(index % colors.len().max(1))
+            .and_then(|hex| Srgba::hex(hex).ok())
+            .map_or(Color::WHITE, Color::from)
+    }
+
+    fn save(&self) {
+        let pretty = ron::ser::PrettyConfig::default();
+        let result = ron::ser::to_string_pretty(&self.config, pretty)
+            .map_err(|e| e.to_string())
+            .and_then(|text| std::fs::write(&self.path, text).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            warn!("Could not save {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+pub(crate) fn load_palette(mut commands: Commands, args: Res<PreviewArgs>) {
+    let path = args.asset_file(PALETTE_FILE);
+    let config = match std::fs::read_to_string(&path) {
+        Ok(text) => ron::from_str(&text).unwrap_or_else(|e| {
+            warn!(
+                "Invalid {}, using the default palette: {}",
+                path.display(),
+                e
+            );
+            PaletteConfig::default()
+        }),
+        Err(_) => PaletteConfig::default(),
+    };
+    commands.insert_resource(Palette {
+        config,
+        root: args.asset_file(""),
+        path,
+    });
+}
+
+/// Color swatches of the sources seen so far. Editing one saves the palette.
+pub(crate) fn palette_ui(mut contexts: EguiContexts, mut palette: ResMut<Palette>) -> Result {
+    if palette.config.sources.is_empty() {
+        return Ok(());
+    }
+    let mut changed = false;
+    egui::Window::new("Palette")
+        .default_open(false)
+        .show(contexts.ctx_mut()?, |ui| {
+            for (source, hex) in palette.config.sources.iter_mut() {
+                let Ok(color) = Srgba::hex(&*hex) else {
+                    continue;
+                };
+                let mut rgb = [color.red, color.green, color.blue];
+                ui.horizontal(|ui| {
+                    if ui.color_edit_button_rgb(&mut rgb).changed() {
+                        *hex = Srgba::rgb(rgb[0], rgb[1], rgb[2]).to_hex();
+                        changed = true;
+                    }
+                    ui.label(source.as_str());
+                });
+            }
+        });
+    if changed {
+        palette.save();
+    }
+    Ok(())
+}
+
+/// Converts a palette color for egui plots.
+pub(crate) fn egui_color(color: Color) -> egui::Color32 {
+    let [r, g, b, a] = color.to_srgba().to_u8_array();
+    egui::Color32::from_rgba_unmultiplied(r, g, b, a)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sources_keep_their_colors() {
+        let mut config = PaletteConfig {
+            colors: vec!["#111111".to_string(), "#222222".to_string()],
+            sources: IndexMap::new(),
+        };
+        config
+            .sources
+            .insert("captures".to_string(), "#111111".to_string());
+        assert_eq!(config.assign("model_a"), "#222222");
+        // Every color is taken, so the palette cycles.
+        assert_eq!(config.assign("model_b"), "#111111");
+        assert_eq!(config.sources["captures"], "#111111");
+    }
+}
@@ -0,0 +1,144 @@
+//! Timeline playback, independent of the render frame rate. At capture fps the timeline steps
+//! one frame per `FixedUpdate` tick, with the fixed timestep set to the frame time of the clip,
+//! so every captured frame is played and the virtual clock (pause, speed) is honoured. In real
+//! time mode it follows the wall clock instead and skips frames to catch up after a hitch.
+//!
+//! Clips play in world space, or in place with the horizontal root translation removed and
+//! the root trajectory drawn on the ground around the character instead, which makes limb
+//! motion easier to judge in locomotion clips that travel far.
+use bevy::{color::palettes::css::ORANGE, prelude::*};
+use bevy_egui::egui;
+
+use crate::{Animation, AnimationTimeline, LoadState};
+
+#[derive(Reflect, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PlaybackMode {
+    #[default]
+    CaptureFps,
+    RealTime,
+}
+
+/// Frame count and frame time of the clip on the timeline.
+fn clip_timing(load_state: &LoadState, timeline: &AnimationTimeline) -> Option<(usize, f32)> {
+    let LoadState::Loaded(animations) = load_state else {
+        return None;
+    };
+    let key_frames = &animations.get(timeline.anim_index)?.key_frames;
+    (key_frames.count > 0).then_some((key_frames.count, key_frames.frame_time))
+}
+
+/// Keeps the fixed timestep at the frame time of the loaded clip.
+pub(crate) fn sync_fixed_timestep(
+    load_state: Res<LoadState>,
+    timeline: Res<AnimationTimeline>,
+    mut fixed_time: ResMut<Time<Fixed>>,
+) {
+    let Some((_, frame_time)) = clip_timing(&load_state, &timeline) else {
+        return;
+    };
+    if frame_time > 0.0 && fixed_time.timestep().as_secs_f32() != frame_time {
+        fixed_time.set_timestep_seconds(frame_time as f64);
+    }
+}
+
+pub(crate) fn step_timeline_fixed(
+    load_state: Res<LoadState>,
+    mut timeline: ResMut<AnimationTimeline>,
+) {
+    if !timeline.playing || timeline.mode != PlaybackMode::CaptureFps {
+        return;
+    }
+    if let Some((count, _)) = clip_timing(&load_state, &timeline) {
+        timeline.current_frame = (timeline.current_frame + 1) % count;
+    }
+}
+
+pub(crate) fn advance_timeline_real_time(
+    load_state: Res<LoadState>,
+    mut timeline: ResMut<AnimationTimeline>,
+    time: Res<Time<Real>>,
+) {
+    if !timeline.playing || timeline.mode != PlaybackMode::RealTime {
+        if timeline.frame_progress != 0.0 {
+            timeline.frame_progress = 0.0;
+        }
+        return;
+    }
+    let Some((count, frame_time)) = clip_timing(&load_state, &timeline) else {
+        return;
+    };
+    let progress = timeline.frame_progress + time.delta_secs() / frame_time.max(f32::EPSILON);
+    let frames = progress.floor();
+    timeline.frame_progress = progress - frames;
+    timeline.current_frame = (timeline.current_frame + frames as usize) % count;
+}
+
+/// Translation of the root of `animation` at `frame`, keeping only its height in place.
+pub(crate) fn root_translation(animation: &Animation, frame: usize, in_place: bool) -> Vec3 {
+    let translation = animation
+        .key_frames
+        .joint_translations
+        .get(&animation.skeleton.name)
+        .map_or(Vec3::ZERO, |translations| translations[frame]);
+    if in_place {
+        Vec3::new(0.0, translation.y, 0.0)
+    } else {
+        translation
+    }
+}
+
+/// Draws the trajectory of the root on the ground when playing in place, moved along with the
+/// character so it passes under it.
+pub(crate) fn draw_root_trajectory(
+    mut gizmos: Gizmos,
+    load_state: Res<LoadState>,
+    timeline: Res<AnimationTimeline>,
+) {
+    let LoadState::Loaded(animations) = &*load_state else {
+        return;
+    };
+    let Some(animation) = animations.get(timeline.anim_index) else {
+        return;
+    };
+    let Some(translations) = animation
+        .key_frames
+        .joint_translations
+        .get(&animation.skeleton.name)
+        .filter(|_| timeline.in_place)
+    else {
+        return;
+    };
+    let Some(current) = translations.get(timeline.current_frame) else {
+        return;
+    };
+    let ground =
+        |translation: &Vec3| Vec3::new(translation.x - current.x, 0.0, translation.z - current.z);
+    gizmos.linestrip(translations.iter().map(ground), ORANGE);
+    gizmos.sphere(Isometry3d::IDENTITY, 2.0, ORANGE);
+}
+
+/// Play button, playback mode and speed of the virtual clock, shown in the timeline window.
+pub(crate) fn playback_ui(
+    ui: &mut egui::Ui,
+    timeline: &mut AnimationTimeline,
+    virtual_time: &mut Time<Virtual>,
+) {
+    ui.horizontal(|ui| {
+        let label = if timeline.playing { "Pause" } else { "Play" };
+        if ui.button(label).clicked() {
+            timeline.playing = !timeline.playing;
+        }
+        ui.radio_value(&mut timeline.mode, PlaybackMode::CaptureFps, "Capture fps");
+        ui.radio_value(&mut timeline.mode, PlaybackMode::RealTime, "Real time");
+        let mut speed = virtual_time.relative_speed();
+        let slider = egui::Slider::new(&mut speed, 0.1..=2.0).text("Speed");
+        if ui
+            .add_enabled(timeline.mode == PlaybackMode::CaptureFps, slider)
+            .changed()
+        {
+            virtual_time.set_relative_speed(speed);
+        }
+        ui.checkbox(&mut timeline.in_place, "In place")
+            .on_hover_text("Removes the horizontal root translation and draws the trajectory");
+    });
+}
@@ -0,0 +1,37 @@
+//! Saves the state of the viewer as a Bevy scene, so a review setup can be shared and reopened
+//! later with `preview review.scn.ron`.
+use bevy::{prelude::*, scene::DynamicSceneBuilder};
+
+use crate::{AnimationTimeline, LoadState};
+
+pub const REVIEW_SCENE_EXTENSION: &str = ".scn.ron";
+const REVIEW_SCENE_FILE: &str = "review.scn.ron";
+
+/// Writes the loaded animation and timeline position to [`REVIEW_SCENE_FILE`] when F5 is pressed.
+pub(crate) fn save_review_scene(world: &mut World) {
+    if !world
+        .resource::<ButtonInput<KeyCode>>()
+        .just_pressed(KeyCode::F5)
+    {
+        return;
+    }
+    if !matches!(world.resource::<LoadState>(), LoadState::Loaded(_)) {
+        warn!("Nothing to save, no animation is loaded.");
+        return;
+    }
+
+    let scene = DynamicSceneBuilder::from_world(world)
+        .deny_all_resources()
+        .allow_resource::<LoadState>()
+        .allow_resource::<AnimationTimeline>()
+        .extract_resources()
+        .build();
+    let type_registry = world.resource::<AppTypeRegistry>().read();
+    match scene.serialize(&type_registry) {
+        Ok(serialized) => match std::fs::write(REVIEW_SCENE_FILE, serialized) {
+            Ok(()) => info!("Saved review setup to {}", REVIEW_SCENE_FILE),
+            Err(e) => error!("Could not write {}: {}", REVIEW_SCENE_FILE, e),
+        },
+        Err(e) => error!("Could not serialize the review setup: {}", e),
+    }
+}
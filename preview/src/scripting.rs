@@ -0,0 +1,446 @@
+//! Rhai scripts that drive the viewer, for review sequences that would otherwise be clicked
+//! through by hand. A script runs to the end at once and queues its viewer calls, which are
+//! then played back one after the other: loading a clip waits until it is loaded, and a
+//! screenshot waits a few frames after seeking so the pose is on screen.
+//!
+//! ```rhai
+//! load("walks/walk_01.bvh");
+//! toggle("gizmos", true);
+//! for frame in [0, 30, 60] {
+//!     seek(frame);
+//!     screenshot(`walk_01_${frame}.png`);
+//! }
+//! metric("root_distance");
+//! exit();
+//! ```
+use std::{cell::RefCell, collections::VecDeque, path::PathBuf, rc::Rc};
+
+use bevy::{
+    prelude::*,
+    render::view::screenshot::{Screenshot, save_to_disk},
+};
+use bevy_egui::{EguiContexts, egui};
+use rhai::{Engine, EvalAltResult};
+
+use crate::{
+    Animation, AnimationTimeline, LoadState,
+    bone_renderer::BoneRenderMode,
+    bvh_asset_loader::KeyFrames,
+    capture::{PreviewArgs, SETTLE_FRAMES},
+    gav_loading::GavLoading,
+    load_clip,
+    playback::PlaybackMode,
+};
+
+/// Scripts stop after this many operations, so an endless loop can't hang the viewer.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Toggle {
+    Playing,
+    RealTime,
+    Gizmos,
+}
+
+impl Toggle {
+    const NAMES: [(&str, Toggle); 3] = [
+        ("playing", Toggle::Playing),
+        ("real_time", Toggle::RealTime),
+        ("gizmos", Toggle::Gizmos),
+    ];
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::NAMES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, t)| *t)
+    }
+}
+
+/// Measurements of the loaded clip a script can print.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Metric {
+    Frames,
+    /// Length in seconds.
+    Duration,
+    /// Length of the path of the root joint.
+    RootDistance,
+    /// Fastest speed of the root joint, in units per second.
+    MaxRootSpeed,
+}
+
+impl Metric {
+    const NAMES: [(&str, Metric); 4] = [
+        ("frames", Metric::Frames),
+        ("duration", Metric::Duration),
+        ("root_distance", Metric::RootDistance),
+        ("max_root_speed", Metric::MaxRootSpeed),
+    ];
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::NAMES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, m)| *m)
+    }
+
+    fn name(self) -> &'static str {
+        Self::NAMES
+            .iter()
+            .find(|(_, m)| *m == self)
+            .map_or("", |(n, _)| n)
+    }
+
+    fn measure(self, key_frames: &KeyFrames) -> f32 {
+        let root = key_frames
+            .joint_order()
+            .next()
+            .and_then(|root| key_frames.joint_translations.get(root));
+        let steps = || {
+            root.into_iter()
+                .flat_map(|positions| positions.windows(2))
+                .map(|pair| pair[0].distance(pair[1]))
+        };
+        match self {
+            Metric::Frames => key_frames.count as f32,
+            Metric::Duration => key_frames.count as f32 * key_frames.frame_time,
+            Metric::RootDistance => steps().sum(),
+            Metric::MaxRootSpeed => {
+                steps().fold(0.0, f32::max) / key_frames.frame_time.max(f32::EPSILON)
+            }
+        }
+    }
+}
+
+/// A viewer call made by a script.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum ScriptCommand {
+    /// Loads a BVH clip or GAV tensor by asset path.
+    Load(String),
+    Seek(usize),
+    Toggle(Toggle, bool),
+    Metric(Metric),
+    Screenshot(PathBuf),
+    /// Waits this many frames before the next command.
+    Wait(u32),
+    Print(String),
+    Exit,
+}
+
+/// Runs `source` and returns the viewer calls it made, in order.
+pub(crate) fn evaluate_script(source: &str) -> Result<Vec<ScriptCommand>, String> {
+    let commands = Rc::new(RefCell::new(Vec::new()));
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+
+    let queue = commands.clone();
+    engine.on_print(move |text| {
+        queue
+            .borrow_mut()
+            .push(ScriptCommand::Print(text.to_string()))
+    });
+    let queue = commands.clone();
+    engine.register_fn("load", move |path: &str| {
+        queue
+            .borrow_mut()
+            .push(ScriptCommand::Load(path.to_string()));
+    });
+    let queue = commands.clone();
+    engine.register_fn("seek", move |frame: i64| {
+        queue
+            .borrow_mut()
+            .push(ScriptCommand::Seek(frame.max(0) as usize));
+    });
+    let queue = commands.clone();
+    engine.register_fn(
+        "toggle",
+        move |name: &str, on: bool| -> Result<(), Box<EvalAltResult>> {
+            let toggle = Toggle::from_name(name).ok_or(format!("Unknown toggle {}", name))?;
+            queue.borrow_mut().push(ScriptCommand::Toggle(toggle, on));
+            Ok(())
+        },
+    );
+    let queue = commands.clone();
+    engine.register_fn(
+        "metric",
+        move |name: &str| -> Result<(), Box<EvalAltResult>> {
+            let metric = Metric::from_name(name).ok_or(format!("Unknown metric {}", name))?;
+            queue.borrow_mut().push(ScriptCommand::Metric(metric));
+            Ok(())
+        },
+    );
+    let queue = commands.clone();
+    engine.register_fn("screenshot", move |path: &str| {
+        let mut queue = queue.borrow_mut();
+        queue.push(ScriptCommand::Wait(SETTLE_FRAMES));
+        queue.push(ScriptCommand::Screenshot(path.into()));
+    });
+    let queue = commands.clone();
+    engine.register_fn("wait", move |frames: i64| {
+        queue
+            .borrow_mut()
+            .push(ScriptCommand::Wait(frames.max(0) as u32));
+    });
+    let queue = commands.clone();
+    engine.register_fn("exit", move || {
+        // Gives the last screenshot time to be written.
+        let mut queue = queue.borrow_mut();
+        queue.push(ScriptCommand::Wait(SETTLE_FRAMES));
+        queue.push(ScriptCommand::Exit);
+    });
+
+    engine.run(source).map_err(|e| e.to_string())?;
+    drop(engine);
+    Ok(commands.take())
+}
+
+/// Queued script commands and the output of the console.
+#[derive(Resource, Default)]
+pub(crate) struct ScriptRunner {
+    commands: VecDeque<ScriptCommand>,
+    wait: u32,
+    log: Vec<String>,
+}
+
+impl ScriptRunner {
+    fn log(&mut self, line: String) {
+        info!("{}", line);
+        self.log.push(line);
+    }
+
+    /// Replaces the queued commands with those of `source`.
+    pub fn run(&mut self, source: &str) {
+        self.wait = 0;
+        match evaluate_script(source) {
+            Ok(commands) => self.commands = commands.into(),
+            Err(e) => {
+                self.commands.clear();
+                self.log(format!("Script error: {}", e));
+            }
+        }
+    }
+}
+
+/// Queues the script passed with `--script`.
+pub(crate) fn run_startup_script(args: Res<PreviewArgs>, mut runner: ResMut<ScriptRunner>) {
+    let Some(path) = &args.script else {
+        return;
+    };
+    match std::fs::read_to_string(path) {
+        Ok(source) => runner.run(&source),
+        Err(e) => runner.log(format!("Could not read {}: {}", path.display(), e)),
+    }
+}
+
+/// Whether a clip is still loading, or an error if it failed to load.
+fn loading(world: &World) -> Result<bool, String> {
+    if world.contains_resource::<GavLoading>() {
+        return Ok(true);
+    }
+    match world.resource::<LoadState>() {
+        LoadState::Loading(handle) => {
+            let state = world.resource::<AssetServer>().load_state(handle);
+            if state.is_failed() {
+                Err(format!("Could not load {}", crate::asset_path(handle)))
+            } else {
+                Ok(true)
+            }
+        }
+        _ => Ok(false),
+    }
+}
+
+fn current_animation(world: &World) -> Result<&Animation, String> {
+    let LoadState::Loaded(animations) = world.resource::<LoadState>() else {
+        return Err("No clip is loaded".to_string());
+    };
+    animations
+        .get(world.resource::<AnimationTimeline>().anim_index)
+        .ok_or_else(|| "No clip is loaded".to_string())
+}
+
+fn execute(world: &mut World, command: ScriptCommand) -> Result<(), String> {
+    match command {
+        ScriptCommand::Load(path) => {
+            let args = world.resource::<PreviewArgs>().clone();
+            let asset_server = world.resource::<AssetServer>().clone();
+            *world.resource_mut::<AnimationTimeline>() = AnimationTimeline::default();
+            load_clip(&mut world.commands(), &asset_server, &args, path);
+            world.flush();
+        }
+        ScriptCommand::Seek(frame) => {
+            let last_frame = current_animation(world)?.key_frames.count.saturating_sub(1);
+            world.resource_mut::<AnimationTimeline>().current_frame = frame.min(last_frame);
+        }
+        ScriptCommand::Toggle(toggle, on) => match toggle {
+            Toggle::Playing => world.resource_mut::<AnimationTimeline>().playing = on,
+            Toggle::RealTime => {
+                world.resource_mut::<AnimationTimeline>().mode = if on {
+                    PlaybackMode::RealTime
+                } else {
+                    PlaybackMode::CaptureFps
+                }
+            }
+            Toggle::Gizmos => {
+                *world.resource_mut::<BoneRenderMode>() = if on {
+                    BoneRenderMode::Gizmos
+                } else {
+                    BoneRenderMode::Meshes
+                }
+            }
+        },
+        ScriptCommand::Metric(metric) => {
+            let animation = current_animation(world)?;
+            let line = format!(
+                "{} {}: {}",
+                animation.path,
+                metric.name(),
+                metric.measure(&animation.key_frames)
+            );
+            world.resource_mut::<ScriptRunner>().log(line);
+        }
+        ScriptCommand::Screenshot(path) => {
+            world
+                .spawn(Screenshot::primary_window())
+                .observe(save_to_disk(path));
+        }
+        ScriptCommand::Wait(frames) => world.resource_mut::<ScriptRunner>().wait = frames,
+        ScriptCommand::Print(text) => world.resource_mut::<ScriptRunner>().log(text),
+        ScriptCommand::Exit => {
+            world.send_event(AppExit::Success);
+        }
+    }
+    Ok(())
+}
+
+/// Runs queued commands until one has to wait for a clip to load or for frames to pass. A
+/// command that fails stops the script.
+pub(crate) fn run_script_commands(world: &mut World) {
+    loop {
+        if world.resource::<ScriptRunner>().commands.is_empty() {
+            return;
+        }
+        let loading = loading(world);
+        let mut runner = world.resource_mut::<ScriptRunner>();
+        match loading {
+            Ok(true) => return,
+            Ok(false) if runner.wait > 0 => {
+                runner.wait -= 1;
+                return;
+            }
+            Ok(false) => {}
+            Err(e) => {
+                runner.commands.clear();
+                runner.log(format!("Script stopped: {}", e));
+                return;
+            }
+        }
+        let Some(command) = runner.commands.pop_front() else {
+            return;
+        };
+        if let Err(e) = execute(world, command) {
+            let mut runner = world.resource_mut::<ScriptRunner>();
+            runner.commands.clear();
+            runner.log(format!("Script stopped: {}", e));
+        }
+    }
+}
+
+pub(crate) fn script_console_ui(
+    mut contexts: EguiContexts,
+    mut runner: ResMut<ScriptRunner>,
+    mut source: Local<String>,
+) -> Result {
+    egui::Window::new("Script")
+        .default_open(false)
+        .show(contexts.ctx_mut()?, |ui| {
+            ui.add(
+                egui::TextEdit::multiline(&mut *source)
+                    .code_editor()
+                    .desired_rows(8)
+                    .desired_width(f32::INFINITY),
+            );
+            ui.horizontal(|ui| {
+                if ui.button("Run").clicked() {
+                    runner.run(&source);
+                }
+                if ui
+                    .add_enabled(!runner.commands.is_empty(), egui::Button::new("Stop"))
+                    .clicked()
+                {
+                    runner.commands.clear();
+                    runner.wait = 0;
+                }
+                if ui.button("Clear log").clicked() {
+                    runner.log.clear();
+                }
+                if !runner.commands.is_empty() {
+                    ui.label(format!("{} calls left", runner.commands.len()));
+                }
+            });
+            egui::ScrollArea::vertical()
+                .max_height(120.0)
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    for line in &runner.log {
+                        ui.monospace(line);
+                    }
+                });
+        });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use indexmap::IndexMap;
+
+    use super::*;
+
+    #[test]
+    fn test_script_queues_viewer_calls() {
+        let commands = evaluate_script(
+            r#"
+            load("walk.bvh");
+            for frame in [0, 10] {
+                seek(frame);
+                screenshot(`walk_${frame}.png`);
+            }
+            toggle("gizmos", true);
+            metric("root_distance");
+            print("done");
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            commands,
+            vec![
+                ScriptCommand::Load("walk.bvh".to_string()),
+                ScriptCommand::Seek(0),
+                ScriptCommand::Wait(SETTLE_FRAMES),
+                ScriptCommand::Screenshot("walk_0.png".into()),
+                ScriptCommand::Seek(10),
+                ScriptCommand::Wait(SETTLE_FRAMES),
+                ScriptCommand::Screenshot("walk_10.png".into()),
+                ScriptCommand::Toggle(Toggle::Gizmos, true),
+                ScriptCommand::Metric(Metric::RootDistance),
+                ScriptCommand::Print("done".to_string()),
+            ]
+        );
+        assert!(evaluate_script(r#"metric("beauty");"#).is_err());
+        assert!(evaluate_script("loop {}").is_err());
+
+        let key_frames = KeyFrames {
+            frame_time: 0.5,
+            count: 3,
+            joint_translations: IndexMap::from([(
+                "Hips".to_string(),
+                vec![Vec3::ZERO, Vec3::X, Vec3::X * 4.0],
+            )]),
+            joint_rotations: IndexMap::from([("Hips".to_string(), vec![Quat::IDENTITY; 3])]),
+            events: vec![],
+        };
+        assert_eq!(Metric::RootDistance.measure(&key_frames), 4.0);
+        assert_eq!(Metric::MaxRootSpeed.measure(&key_frames), 6.0);
+        assert_eq!(Metric::Duration.measure(&key_frames), 1.5);
+    }
+}
@@ -0,0 +1,101 @@
+//! Lists the clips most similar to the loaded one, using the embedding index written by
+//! `bvh_to_gav embed` next to the clips. Clicking a clip loads it in place of the current one.
+use std::path::Path;
+
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use bvh_to_gav::embedding::{EMBEDDING_INDEX_FILE, EmbeddingIndex};
+
+use crate::{ANIMATION_FILE, AnimationTimeline, LoadState, capture::PreviewArgs};
+
+const SIMILAR_CLIP_COUNT: usize = 10;
+
+#[derive(Resource)]
+pub struct SimilarClips {
+    index: EmbeddingIndex,
+    /// Folder of the clips in the index, relative to the asset root.
+    folder: String,
+    current: String,
+    matches: Vec<(String, f32)>,
+}
+
+impl SimilarClips {
+    fn select(&mut self, clip: String) {
+        self.matches = self
+            .index
+            .similar(&clip, SIMILAR_CLIP_COUNT)
+            .unwrap_or_default();
+        self.current = clip;
+    }
+
+    fn asset_path(&self) -> String {
+        Path::new(&self.folder)
+            .join(&self.current)
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+pub(crate) fn load_similar_clips(mut commands: Commands, args: Res<PreviewArgs>) {
+    let asset_path = args
+        .asset_path()
+        .unwrap_or_else(|| ANIMATION_FILE.to_string());
+    let asset_path = Path::new(&asset_path);
+    if asset_path.extension().is_none_or(|e| e != "bvh") {
+        return;
+    }
+    let (Some(folder), Some(clip)) = (asset_path.parent(), asset_path.file_name()) else {
+        return;
+    };
+
+    let index_path = args.asset_file(folder.join(EMBEDDING_INDEX_FILE));
+    if !index_path.exists() {
+        return;
+    }
+    match EmbeddingIndex::load(&index_path) {
+        Ok(index) => {
+            let mut similar = SimilarClips {
+                index,
+                folder: folder.to_string_lossy().into_owned(),
+                current: String::new(),
+                matches: Vec::new(),
+            };
+            similar.select(clip.to_string_lossy().into_owned());
+            commands.insert_resource(similar);
+        }
+        Err(e) => warn!("Could not load {}: {}", index_path.display(), e),
+    }
+}
+
+pub(crate) fn similar_clips_ui(
+    mut contexts: EguiContexts,
+    similar: Option<ResMut<SimilarClips>>,
+    asset_server: Res<AssetServer>,
+    mut load_state: ResMut<LoadState>,
+    mut timeline: ResMut<AnimationTimeline>,
+) -> Result {
+    let Some(mut similar) = similar else {
+        return Ok(());
+    };
+
+    let mut selected = None;
+    egui::Window::new("Similar clips").show(contexts.ctx_mut()?, |ui| {
+        ui.label(&similar.current);
+        ui.separator();
+        if similar.matches.is_empty() {
+            ui.label("Not in the embedding index");
+        }
+        for (clip, distance) in &similar.matches {
+            if ui.button(format!("{} ({:.2})", clip, distance)).clicked() {
+                selected = Some(clip.clone());
+            }
+        }
+    });
+
+    if let Some(clip) = selected {
+        similar.select(clip);
+        *load_state = LoadState::Loading(asset_server.load(similar.asset_path()));
+        *timeline = AnimationTimeline::default();
+    }
+    Ok(())
+}
@@ -0,0 +1,266 @@
+//! Rest pose editor: adjusts the joint offsets and rest rotations of the loaded skeleton, for
+//! rigs whose exported rest pose doesn't match their motion data. The clip is posed with the
+//! edited skeleton as it is changed, and the result is saved as a skeleton sidecar,
+//! `<clip>.skeleton.json`, the one a GAV tensor is loaded with and `bvh_to_gav to-bvh` accepts
+//! as reference.
+//!
+//! A rest rotation turns the offsets of the children of a joint, and its end site, so it bends
+//! the bones below the joint in the rest pose without changing the rotation tracks.
+use std::{collections::HashMap, fs::File, io::BufWriter};
+
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use bvh_to_gav::skeleton::{Skeleton, SkeletonJoint, skeleton_path, write_skeleton_json};
+
+use crate::{AnimationTimeline, LoadState, bvh_asset_loader::JointHierarchy, capture::PreviewArgs};
+
+/// Order of the Euler angles of the rest rotations, the rotation channel order of most BVH
+/// files.
+const EULER_ORDER: EulerRot = EulerRot::ZXY;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct JointEdit {
+    /// Offset replacing the one in the rest pose, in the space of the parent.
+    offset: Option<Vec3>,
+    /// Rest rotation as Z, X and Y angles in degrees, see [`EULER_ORDER`].
+    rest_rotation: Vec3,
+}
+
+impl JointEdit {
+    fn rest_rotation(&self) -> Quat {
+        let [z, x, y] = self.rest_rotation.to_array().map(f32::to_radians);
+        Quat::from_euler(EULER_ORDER, z, x, y)
+    }
+}
+
+#[derive(Resource, Default)]
+pub(crate) struct SkeletonEditor {
+    editing: bool,
+    /// Asset path of the clip, with its skeleton as loaded.
+    original: Option<(String, JointHierarchy)>,
+    edits: HashMap<String, JointEdit>,
+    selected: String,
+    error: Option<String>,
+}
+
+/// `original` with the `edits` applied. The children of a joint get its rest rotation before
+/// their own offset edits, so an edited offset is the one the joint ends up with.
+fn edited_hierarchy(
+    original: &JointHierarchy,
+    edits: &HashMap<String, JointEdit>,
+) -> JointHierarchy {
+    fn edit(
+        joint: &JointHierarchy,
+        offset: Vec3,
+        edits: &HashMap<String, JointEdit>,
+    ) -> JointHierarchy {
+        let joint_edit = edits.get(&joint.name).copied().unwrap_or_default();
+        let rotation = joint_edit.rest_rotation();
+        JointHierarchy {
+            name: joint.name.clone(),
+            offset: joint_edit.offset.unwrap_or(offset),
+            end: joint.end.map(|end| rotation * end),
+            children: joint
+                .children
+                .iter()
+                .map(|child| edit(child, rotation * child.offset, edits))
+                .collect(),
+        }
+    }
+    edit(original, original.offset, edits)
+}
+
+/// The joints of `hierarchy` in depth first order, the order of the parsed BVH file.
+pub(crate) fn hierarchy_skeleton(hierarchy: &JointHierarchy) -> Skeleton {
+    fn flatten(joints: &mut Vec<SkeletonJoint>, joint: &JointHierarchy, parent: Option<usize>) {
+        let index = joints.len();
+        joints.push(SkeletonJoint {
+            name: joint.name.clone(),
+            parent,
+            offset: joint.offset,
+            end_site: joint.end,
+        });
+        for child in &joint.children {
+            flatten(joints, child, Some(index));
+        }
+    }
+    let mut joints = Vec::new();
+    flatten(&mut joints, hierarchy, None);
+    Skeleton { joints }
+}
+
+fn find<'a>(joint: &'a JointHierarchy, name: &str) -> Option<&'a JointHierarchy> {
+    if joint.name == name {
+        return Some(joint);
+    }
+    joint.children.iter().find_map(|child| find(child, name))
+}
+
+fn joint_names(joint: &JointHierarchy, names: &mut Vec<String>) {
+    names.push(joint.name.clone());
+    for child in &joint.children {
+        joint_names(child, names);
+    }
+}
+
+pub(crate) fn skeleton_editor_ui(
+    mut contexts: EguiContexts,
+    mut editor: ResMut<SkeletonEditor>,
+    mut load_state: ResMut<LoadState>,
+    timeline: Res<AnimationTimeline>,
+    args: Res<PreviewArgs>,
+) -> Result {
+    let LoadState::Loaded(animations) = &mut *load_state else {
+        return Ok(());
+    };
+    let Some(animation) = animations.get_mut(timeline.anim_index) else {
+        return Ok(());
+    };
+    let editor = &mut *editor;
+    if editor
+        .original
+        .as_ref()
+        .is_none_or(|(path, _)| *path != animation.path)
+    {
+        editor.original = Some((animation.path.clone(), animation.skeleton.clone()));
+        editor.edits.clear();
+        editor.selected = animation.skeleton.name.clone();
+        editor.error = None;
+    }
+
+    let mut changed = false;
+    egui::Window::new("Skeleton")
+        .default_open(false)
+        .show(contexts.ctx_mut()?, |ui| {
+            ui.checkbox(&mut editor.editing, "Edit rest pose");
+            if !editor.editing {
+                return;
+            }
+            let mut names = Vec::new();
+            joint_names(&animation.skeleton, &mut names);
+            egui::ComboBox::from_label("Joint")
+                .selected_text(&editor.selected)
+                .show_ui(ui, |ui| {
+                    for name in names {
+                        let label = ui.selectable_label(editor.selected == name, &name);
+                        if label.clicked() {
+                            editor.selected = name;
+                        }
+                    }
+                });
+            let Some(joint) = find(&animation.skeleton, &editor.selected) else {
+                return;
+            };
+            let edit = editor.edits.entry(editor.selected.clone()).or_default();
+            egui::Grid::new("joint_rest_pose").show(ui, |ui| {
+                ui.label("Offset");
+                let mut offset = joint.offset.to_array();
+                for value in &mut offset {
+                    changed |= ui.add(egui::DragValue::new(value).speed(0.1)).changed();
+                }
+                if Vec3::from_array(offset) != joint.offset {
+                    edit.offset = Some(Vec3::from_array(offset));
+                }
+                ui.end_row();
+
+                ui.label("Rest rotation (ZXY)");
+                let mut angles = edit.rest_rotation.to_array();
+                for value in &mut angles {
+                    let angle = egui::DragValue::new(value)
+                        .range(-180.0..=180.0)
+                        .suffix("°");
+                    changed |= ui.add(angle).changed();
+                }
+                edit.rest_rotation = Vec3::from_array(angles);
+                ui.end_row();
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Reset joint").clicked() {
+                    editor.edits.remove(&editor.selected);
+                    changed = true;
+                }
+                if ui.button("Reset all").clicked() {
+                    editor.edits.clear();
+                    changed = true;
+                }
+                if ui.button("Save skeleton").clicked() {
+                    let path = skeleton_path(&args.asset_file(&animation.path));
+                    let skeleton = hierarchy_skeleton(&animation.skeleton);
+                    let frame_time = animation.key_frames.frame_time;
+                    let result = match File::create(&path) {
+                        Ok(file) => {
+                            write_skeleton_json(BufWriter::new(file), &skeleton, frame_time)
+                                .map_err(|e| format!("{:#}", e))
+                        }
+                        Err(e) => Err(e.to_string()),
+                    };
+                    editor.error = match result {
+                        Ok(()) => {
+                            info!("Saved {}", path.display());
+                            None
+                        }
+                        Err(e) => Some(format!("Could not save {}: {}", path.display(), e)),
+                    };
+                }
+            });
+            if let Some(error) = &editor.error {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+        });
+
+    if changed && let Some((_, original)) = &editor.original {
+        animation.skeleton = edited_hierarchy(original, &editor.edits);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rest_rotation_turns_the_bones_below() {
+        let joint = |name: &str, offset, children| JointHierarchy {
+            name: name.to_string(),
+            offset,
+            end: None,
+            children,
+        };
+        let original = joint(
+            "Hips",
+            Vec3::ZERO,
+            vec![joint(
+                "Spine",
+                Vec3::Y,
+                vec![joint("Head", Vec3::Y, vec![])],
+            )],
+        );
+        let edits = HashMap::from([
+            (
+                "Hips".to_string(),
+                JointEdit {
+                    offset: None,
+                    rest_rotation: Vec3::new(90.0, 0.0, 0.0),
+                },
+            ),
+            (
+                "Head".to_string(),
+                JointEdit {
+                    offset: Some(Vec3::Y * 2.0),
+                    rest_rotation: Vec3::ZERO,
+                },
+            ),
+        ]);
+        let edited = edited_hierarchy(&original, &edits);
+        // 90 degrees about Z turns the spine from up to the side.
+        assert!(edited.children[0].offset.distance(Vec3::NEG_X) < 1e-5);
+        assert_eq!(edited.children[0].children[0].offset, Vec3::Y * 2.0);
+
+        let skeleton = hierarchy_skeleton(&edited);
+        assert_eq!(
+            skeleton.joint_order().collect::<Vec<_>>(),
+            ["Hips", "Spine", "Head"]
+        );
+        assert_eq!(skeleton.joints[2].parent, Some(1));
+    }
+}
@@ -0,0 +1,432 @@
+//! Animation state machines: every state plays a clip, transitions crossfade to another state
+//! when a key is pressed or when a non-looping clip finishes. This lets a set of generated clips
+//! be tried out in a game-like control context instead of linear playback only.
+//!
+//! State machines are RON files with the `.statemachine.ron` extension:
+//!
+//! ```ron
+//! (
+//!     initial: "idle",
+//!     states: [
+//!         (name: "idle", clip: "idle.bvh"),
+//!         (name: "wave", clip: "wave.bvh", looping: false),
+//!     ],
+//!     transitions: [
+//!         (from: "idle", to: "wave", trigger: Key("w"), crossfade: 0.3),
+//!         (from: "wave", to: "idle", trigger: Finished, crossfade: 0.3),
+//!     ],
+//! )
+//! ```
+use bevy::{
+    asset::{AssetLoader, LoadContext, io::Reader},
+    prelude::*,
+};
+use bevy_egui::{EguiContexts, egui};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    Animation, asset_path,
+    blend_tree::{blend_pose, draw_blended_pose},
+    bone_renderer::PoseRenderer,
+    bvh_asset_loader::{BvhAsset, JointHierarchy, KeyFrames},
+};
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub enum TransitionTrigger {
+    /// A key, either a letter or digit or a named key such as `Space` or `ArrowUp`.
+    Key(String),
+    /// The clip of the source state played to its end.
+    Finished,
+}
+
+#[derive(Deserialize)]
+struct StateDefinition {
+    name: String,
+    clip: String,
+    #[serde(default = "default_looping")]
+    looping: bool,
+}
+
+fn default_looping() -> bool {
+    true
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Transition {
+    pub from: String,
+    pub to: String,
+    pub trigger: TransitionTrigger,
+    /// Crossfade duration in seconds.
+    #[serde(default)]
+    pub crossfade: f32,
+}
+
+#[derive(Deserialize)]
+struct StateMachineDefinition {
+    initial: String,
+    states: Vec<StateDefinition>,
+    transitions: Vec<Transition>,
+}
+
+#[derive(Clone, Debug)]
+pub struct State {
+    pub name: String,
+    pub bvh: Handle<BvhAsset>,
+    pub looping: bool,
+}
+
+#[derive(TypePath, Asset, Clone, Debug)]
+pub struct StateMachine {
+    pub initial: usize,
+    pub states: Vec<State>,
+    pub transitions: Vec<Transition>,
+}
+
+impl StateMachine {
+    fn state_index(&self, name: &str) -> Option<usize> {
+        self.states.iter().position(|state| state.name == name)
+    }
+}
+
+#[derive(Default)]
+pub struct StateMachineLoader;
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum StateMachineLoaderError {
+    #[error("Could not load asset: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse state machine: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    #[error("Invalid state machine: {0}")]
+    Invalid(String),
+}
+
+impl AssetLoader for StateMachineLoader {
+    type Asset = StateMachine;
+    type Settings = ();
+    type Error = StateMachineLoaderError;
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let definition: StateMachineDefinition = ron::de::from_bytes(&bytes)?;
+
+        let mut states = Vec::with_capacity(definition.states.len());
+        for state in definition.states {
+            // Clip paths are relative to the state machine file.
+            let path = load_context
+                .asset_path()
+                .resolve(&state.clip)
+                .map_err(|e| StateMachineLoaderError::Invalid(e.to_string()))?;
+            states.push(State {
+                name: state.name,
+                bvh: load_context.load(path),
+                looping: state.looping,
+            });
+        }
+
+        let machine = StateMachine {
+            initial: 0,
+            states,
+            transitions: definition.transitions,
+        };
+        let unknown_state =
+            |name: &str| StateMachineLoaderError::Invalid(format!("Unknown state: {}", name));
+        for transition in &machine.transitions {
+            if let TransitionTrigger::Key(key) = &transition.trigger {
+                key_code(key).ok_or_else(|| {
+                    StateMachineLoaderError::Invalid(format!("Unknown key: {}", key))
+                })?;
+            }
+            machine
+                .state_index(&transition.from)
+                .ok_or_else(|| unknown_state(&transition.from))?;
+            machine
+                .state_index(&transition.to)
+                .ok_or_else(|| unknown_state(&transition.to))?;
+        }
+        let initial = machine
+            .state_index(&definition.initial)
+            .ok_or_else(|| unknown_state(&definition.initial))?;
+
+        Ok(StateMachine { initial, ..machine })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["statemachine.ron"]
+    }
+}
+
+pub const STATE_MACHINE_EXTENSION: &str = ".statemachine.ron";
+
+/// Physical key for a key name, either a letter or digit or a named key such as `Space`.
+fn key_code(name: &str) -> Option<KeyCode> {
+    let key = match name.to_uppercase().as_str() {
+        "A" => KeyCode::KeyA,
+        "B" => KeyCode::KeyB,
+        "C" => KeyCode::KeyC,
+        "D" => KeyCode::KeyD,
+        "E" => KeyCode::KeyE,
+        "F" => KeyCode::KeyF,
+        "G" => KeyCode::KeyG,
+        "H" => KeyCode::KeyH,
+        "I" => KeyCode::KeyI,
+        "J" => KeyCode::KeyJ,
+        "K" => KeyCode::KeyK,
+        "L" => KeyCode::KeyL,
+        "M" => KeyCode::KeyM,
+        "N" => KeyCode::KeyN,
+        "O" => KeyCode::KeyO,
+        "P" => KeyCode::KeyP,
+        "Q" => KeyCode::KeyQ,
+        "R" => KeyCode::KeyR,
+        "S" => KeyCode::KeyS,
+        "T" => KeyCode::KeyT,
+        "U" => KeyCode::KeyU,
+        "V" => KeyCode::KeyV,
+        "W" => KeyCode::KeyW,
+        "X" => KeyCode::KeyX,
+        "Y" => KeyCode::KeyY,
+        "Z" => KeyCode::KeyZ,
+        "0" => KeyCode::Digit0,
+        "1" => KeyCode::Digit1,
+        "2" => KeyCode::Digit2,
+        "3" => KeyCode::Digit3,
+        "4" => KeyCode::Digit4,
+        "5" => KeyCode::Digit5,
+        "6" => KeyCode::Digit6,
+        "7" => KeyCode::Digit7,
+        "8" => KeyCode::Digit8,
+        "9" => KeyCode::Digit9,
+        "SPACE" => KeyCode::Space,
+        "ENTER" => KeyCode::Enter,
+        "TAB" => KeyCode::Tab,
+        "BACKSPACE" => KeyCode::Backspace,
+        "ARROWUP" => KeyCode::ArrowUp,
+        "ARROWDOWN" => KeyCode::ArrowDown,
+        "ARROWLEFT" => KeyCode::ArrowLeft,
+        "ARROWRIGHT" => KeyCode::ArrowRight,
+        _ => return None,
+    };
+    Some(key)
+}
+
+struct Crossfade {
+    from: usize,
+    from_time: f32,
+    elapsed: f32,
+    duration: f32,
+}
+
+/// Playback state of a loaded state machine.
+pub struct StateMachinePlayer {
+    machine: StateMachine,
+    clips: Vec<Animation>,
+    current: usize,
+    /// Playback time of the current state in seconds.
+    time: f32,
+    crossfade: Option<Crossfade>,
+}
+
+impl StateMachinePlayer {
+    fn duration(&self, state: usize) -> f32 {
+        let key_frames = &self.clips[state].key_frames;
+        (key_frames.count - 1) as f32 * key_frames.frame_time
+    }
+
+    /// Fractional frame of `state` at `time`, wrapped or clamped depending on looping.
+    fn frame(&self, state: usize, time: f32) -> f32 {
+        let key_frames = &self.clips[state].key_frames;
+        let duration = self.duration(state);
+        let time = if self.machine.states[state].looping && duration > 0.0 {
+            time % duration
+        } else {
+            time.min(duration)
+        };
+        time / key_frames.frame_time
+    }
+
+    fn transition_to(&mut self, transition: &Transition) {
+        let Some(to) = self.machine.state_index(&transition.to) else {
+            return;
+        };
+        info!("{} -> {}", transition.from, transition.to);
+        self.crossfade = (transition.crossfade > 0.0).then_some(Crossfade {
+            from: self.current,
+            from_time: self.time,
+            elapsed: 0.0,
+            duration: transition.crossfade,
+        });
+        self.current = to;
+        self.time = 0.0;
+    }
+
+    fn advance(&mut self, delta: f32, keys: &ButtonInput<KeyCode>) {
+        self.time += delta;
+        if let Some(crossfade) = &mut self.crossfade {
+            crossfade.elapsed += delta;
+            crossfade.from_time += delta;
+            if crossfade.elapsed >= crossfade.duration {
+                self.crossfade = None;
+            }
+        }
+
+        let current = &self.machine.states[self.current].name;
+        let finished =
+            !self.machine.states[self.current].looping && self.time >= self.duration(self.current);
+        let triggered = self
+            .machine
+            .transitions
+            .iter()
+            .filter(|transition| &transition.from == current)
+            .find(|transition| match &transition.trigger {
+                TransitionTrigger::Key(key) => {
+                    key_code(key).is_some_and(|key| keys.just_pressed(key))
+                }
+                TransitionTrigger::Finished => finished,
+            })
+            .cloned();
+        if let Some(transition) = triggered {
+            self.transition_to(&transition);
+        }
+    }
+
+    fn pose(&self) -> KeyFrames {
+        let current = &self.clips[self.current].key_frames;
+        let current_frame = self.frame(self.current, self.time);
+        match &self.crossfade {
+            Some(crossfade) => {
+                let weight = (crossfade.elapsed / crossfade.duration).clamp(0.0, 1.0);
+                let from_frame = self.frame(crossfade.from, crossfade.from_time);
+                blend_pose(&[
+                    (
+                        &self.clips[crossfade.from].key_frames,
+                        from_frame,
+                        1.0 - weight,
+                    ),
+                    (current, current_frame, weight),
+                ])
+            }
+            None => blend_pose(&[(current, current_frame, 1.0)]),
+        }
+    }
+}
+
+#[derive(Resource)]
+pub enum StateMachineState {
+    Loading(Handle<StateMachine>),
+    Loaded(StateMachinePlayer),
+}
+
+pub(crate) fn await_state_machine_loaded(
+    state: Option<ResMut<StateMachineState>>,
+    state_machines: Res<Assets<StateMachine>>,
+    bvh_assets: Res<Assets<BvhAsset>>,
+    key_frames: Res<Assets<KeyFrames>>,
+    skeletons: Res<Assets<JointHierarchy>>,
+) {
+    let Some(mut state) = state else {
+        return;
+    };
+    let StateMachineState::Loading(handle) = &*state else {
+        return;
+    };
+    let Some(machine) = state_machines.get(handle) else {
+        return;
+    };
+
+    let mut clips = Vec::with_capacity(machine.states.len());
+    for machine_state in &machine.states {
+        let Some(bvh) = bvh_assets.get(&machine_state.bvh) else {
+            return;
+        };
+        match (
+            key_frames.get(&bvh.key_frames),
+            skeletons.get(&bvh.skeleton),
+        ) {
+            (Some(kf), Some(skeleton)) => clips.push(Animation {
+                key_frames: kf.clone(),
+                skeleton: skeleton.clone(),
+                path: asset_path(&machine_state.bvh),
+            }),
+            _ => return,
+        }
+    }
+
+    if clips.is_empty() {
+        error!("State machine has no states.");
+        return;
+    }
+    info!("Loaded state machine with {} states.", clips.len());
+    *state = StateMachineState::Loaded(StateMachinePlayer {
+        current: machine.initial,
+        machine: machine.clone(),
+        clips,
+        time: 0.0,
+        crossfade: None,
+    });
+}
+
+pub(crate) fn update_state_machine(
+    mut poses: PoseRenderer,
+    state: Option<ResMut<StateMachineState>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    time: Res<Time>,
+) {
+    let Some(mut state) = state else {
+        return;
+    };
+    let StateMachineState::Loaded(player) = &mut *state else {
+        return;
+    };
+
+    player.advance(time.delta_secs(), &keys);
+    let pose = player.pose();
+    let clip = &player.clips[player.current];
+    draw_blended_pose(&mut poses, &clip.path, &clip.skeleton, &pose, None);
+}
+
+pub(crate) fn state_machine_ui(
+    mut contexts: EguiContexts,
+    state: Option<Res<StateMachineState>>,
+) -> Result {
+    let Some(state) = state else {
+        return Ok(());
+    };
+    let StateMachineState::Loaded(player) = &*state else {
+        return Ok(());
+    };
+
+    let ctx = contexts.ctx_mut()?;
+    egui::Window::new("State Machine").show(ctx, |ui| {
+        let current = &player.machine.states[player.current].name;
+        ui.label(format!("State: {}", current));
+        if let Some(crossfade) = &player.crossfade {
+            let from = &player.machine.states[crossfade.from].name;
+            ui.add(
+                egui::ProgressBar::new(crossfade.elapsed / crossfade.duration)
+                    .text(format!("Crossfade from {}", from)),
+            );
+        }
+        ui.separator();
+        for transition in player
+            .machine
+            .transitions
+            .iter()
+            .filter(|transition| &transition.from == current)
+        {
+            let trigger = match &transition.trigger {
+                TransitionTrigger::Key(key) => format!("[{}]", key),
+                TransitionTrigger::Finished => "when finished".to_string(),
+            };
+            ui.label(format!("{} {}", trigger, transition.to));
+        }
+    });
+    Ok(())
+}
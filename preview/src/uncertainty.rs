@@ -0,0 +1,169 @@
+//! Uncertainty of a probabilistic generator. Samples in `<clip>.samples.npy` are drawn as faint
+//! ghosts, or as their mean pose inside translucent tubes as wide as the spread of each joint.
+//! A per-joint `<clip>.variance.npy` draws the same tubes around the clip itself.
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use bevy_egui::egui;
+use bvh_to_gav::{
+    samples::{SampleSpread, read_samples, samples_path, variance_path},
+    weights::FrameWeights,
+};
+use preview::visualization::{LayerContext, VisualizationLayer};
+
+const GHOST_COLOR: Color = Color::srgba(0.6, 0.8, 1.0, 0.35);
+const MEAN_COLOR: Color = Color::WHITE;
+const TUBE_COLOR: Color = Color::srgba(1.0, 0.6, 0.2, 0.25);
+
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+enum Display {
+    #[default]
+    Envelope,
+    Ghosts,
+}
+
+enum Uncertainty {
+    Samples(SampleSpread),
+    /// Per joint variance of the positions of the clip.
+    Variance(FrameWeights),
+}
+
+/// A translucent capsule around the bone from `a` to `b`.
+fn draw_tube(gizmos: &mut Gizmos, a: Vec3, b: Vec3, radius: f32) {
+    let bone = b - a;
+    if radius <= f32::EPSILON || bone.length() <= f32::EPSILON {
+        return;
+    }
+    let rotation = Quat::from_rotation_arc(Vec3::Y, bone.normalize());
+    gizmos.primitive_3d(
+        &Capsule3d::new(radius, bone.length()),
+        Isometry3d::new((a + b) / 2.0, rotation),
+        TUBE_COLOR,
+    );
+}
+
+pub(crate) struct UncertaintyOverlay {
+    /// Folder the asset paths of clips are relative to.
+    root: PathBuf,
+    clip: Option<String>,
+    uncertainty: Result<Uncertainty, String>,
+    display: Display,
+}
+
+impl UncertaintyOverlay {
+    pub fn new(root: PathBuf) -> Self {
+        UncertaintyOverlay {
+            root,
+            clip: None,
+            uncertainty: Err(String::new()),
+            display: Display::default(),
+        }
+    }
+
+    fn read(&self, context: &LayerContext) -> Result<Uncertainty, String> {
+        let clip = Path::new(&context.clip);
+        let samples = self.root.join(samples_path(clip));
+        if samples.exists() {
+            let spread = read_samples(&samples)
+                .and_then(|samples| SampleSpread::new(&context.skeleton(), &samples))
+                .map_err(|e| format!("Could not read {}: {}", samples.display(), e))?;
+            return Ok(Uncertainty::Samples(spread));
+        }
+        let variance = self.root.join(variance_path(clip));
+        if variance.exists() {
+            let weights = FrameWeights::read(&variance)
+                .map_err(|e| format!("Could not read {}: {}", variance.display(), e))?;
+            if weights.joint_count() != context.joints.len() {
+                return Err(format!(
+                    "{} has variances for {} joints, the clip has {}",
+                    variance.display(),
+                    weights.joint_count(),
+                    context.joints.len()
+                ));
+            }
+            return Ok(Uncertainty::Variance(weights));
+        }
+        Err(format!(
+            "No {} or {}",
+            samples.display(),
+            variance.display()
+        ))
+    }
+}
+
+impl VisualizationLayer for UncertaintyOverlay {
+    fn name(&self) -> &str {
+        "Uncertainty"
+    }
+
+    fn draw(&mut self, context: &LayerContext, gizmos: &mut Gizmos) {
+        if self.clip.as_deref() != Some(context.clip.as_str()) {
+            self.clip = Some(context.clip.clone());
+            self.uncertainty = self.read(context);
+            if let Err(e) = &self.uncertainty {
+                info!("{}", e);
+            }
+        }
+        let frame = context.frame;
+        match &self.uncertainty {
+            Ok(Uncertainty::Samples(spread)) if frame < spread.frame_count() => {
+                match self.display {
+                    Display::Ghosts => {
+                        for sample in 0..spread.positions.len() {
+                            for (parent, child) in context.bones() {
+                                gizmos.line(
+                                    spread.sample_position(sample, parent, frame),
+                                    spread.sample_position(sample, child, frame),
+                                    GHOST_COLOR,
+                                );
+                            }
+                        }
+                    }
+                    Display::Envelope => {
+                        for (parent, child) in context.bones() {
+                            let (a, b) = (
+                                spread.mean_position(parent, frame),
+                                spread.mean_position(child, frame),
+                            );
+                            gizmos.line(a, b, MEAN_COLOR);
+                            let radius = (spread.deviation[[parent, frame]]
+                                + spread.deviation[[child, frame]])
+                                / 2.0;
+                            draw_tube(gizmos, a, b, radius);
+                        }
+                    }
+                }
+            }
+            Ok(Uncertainty::Variance(variance)) => {
+                for (parent, child) in context.bones() {
+                    let deviation = |joint| variance.value(frame, joint).unwrap_or(0.0).sqrt();
+                    draw_tube(
+                        gizmos,
+                        context.joints[parent].position(),
+                        context.joints[child].position(),
+                        (deviation(parent) + deviation(child)) / 2.0,
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ui(&mut self, _context: &LayerContext, ui: &mut egui::Ui) {
+        match &self.uncertainty {
+            Ok(Uncertainty::Samples(spread)) => {
+                ui.label(format!("{} samples", spread.positions.len()));
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut self.display, Display::Envelope, "Mean and spread");
+                    ui.radio_value(&mut self.display, Display::Ghosts, "Samples");
+                });
+            }
+            Ok(Uncertainty::Variance(_)) => {
+                ui.label("Per joint variance");
+            }
+            Err(e) => {
+                ui.label(e);
+            }
+        }
+    }
+}
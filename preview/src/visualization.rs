@@ -0,0 +1,265 @@
+//! Overlays drawn on top of the animated skeleton, such as the gaze ray, muscle activations or
+//! attention weights of a model. A layer only sees the posed skeleton and the timeline through
+//! [`LayerContext`], so crates depending on `preview` can add their own without touching the
+//! systems that play and draw clips:
+//!
+//! ```ignore
+//! App::new()
+//!     .add_plugins(VisualizationPlugin)
+//!     .add_visualization_layer(MyHeatmapLayer::default());
+//! ```
+//!
+//! Every layer can be switched on and off in the Layers window, which also shows the settings of
+//! the layer.
+use bevy::{color::palettes::css::ORANGE, prelude::*};
+use bevy_egui::{EguiContexts, EguiPrimaryContextPass, egui};
+use bvh_to_gav::{
+    gaze::{DEFAULT_GAZE_DISTANCE, DEFAULT_HEAD_FORWARD, is_head_name},
+    skeleton::{Skeleton, SkeletonJoint},
+};
+
+/// A joint of the posed skeleton.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LayerJoint {
+    pub name: String,
+    /// Index of the parent joint in [`LayerContext::joints`].
+    pub parent: Option<usize>,
+    /// World transform of the joint in the current frame.
+    pub transform: Mat4,
+}
+
+impl LayerJoint {
+    pub fn position(&self) -> Vec3 {
+        self.transform.col(3).xyz()
+    }
+}
+
+/// The clip on the timeline, posed at the current frame. Updated by the preview before the
+/// layers are drawn, and absent until a clip is loaded.
+#[derive(Resource, Clone, Debug, Default, PartialEq)]
+pub struct LayerContext {
+    /// Asset path of the clip.
+    pub clip: String,
+    pub frame: usize,
+    pub frame_count: usize,
+    pub frame_time: f32,
+    pub playing: bool,
+    /// Joints in depth first order, parents before their children.
+    pub joints: Vec<LayerJoint>,
+}
+
+impl LayerContext {
+    pub fn find(&self, name: &str) -> Option<&LayerJoint> {
+        self.joints.iter().find(|joint| joint.name == name)
+    }
+
+    /// The skeleton of the clip in joint order, for posing other motions of it. Offsets are
+    /// recovered from the posed transforms, the root is at the origin.
+    pub fn skeleton(&self) -> Skeleton {
+        let joints = self
+            .joints
+            .iter()
+            .map(|joint| SkeletonJoint {
+                name: joint.name.clone(),
+                parent: joint.parent,
+                offset: joint.parent.map_or(Vec3::ZERO, |parent| {
+                    self.joints[parent]
+                        .transform
+                        .inverse()
+                        .transform_point3(joint.position())
+                }),
+                end_site: None,
+            })
+            .collect();
+        Skeleton { joints }
+    }
+
+    /// Bones as the index of the parent and child joint.
+    pub fn bones(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.joints
+            .iter()
+            .enumerate()
+            .filter_map(|(child, joint)| Some((joint.parent?, child)))
+    }
+}
+
+pub trait VisualizationLayer: Send + Sync + 'static {
+    /// Name shown in the Layers window.
+    fn name(&self) -> &str;
+
+    /// Draws the layer for the current frame.
+    fn draw(&mut self, context: &LayerContext, gizmos: &mut Gizmos);
+
+    /// Settings of the layer, shown in the Layers window while it is enabled.
+    fn ui(&mut self, _context: &LayerContext, _ui: &mut egui::Ui) {}
+}
+
+struct RegisteredLayer {
+    enabled: bool,
+    layer: Box<dyn VisualizationLayer>,
+}
+
+/// Layers in the order they were added, which is the order they are drawn in.
+#[derive(Resource, Default)]
+pub struct VisualizationLayers {
+    layers: Vec<RegisteredLayer>,
+}
+
+impl VisualizationLayers {
+    pub fn add(&mut self, layer: impl VisualizationLayer) {
+        self.layers.push(RegisteredLayer {
+            enabled: true,
+            layer: Box::new(layer),
+        });
+    }
+
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) {
+        for registered in &mut self.layers {
+            if registered.layer.name() == name {
+                registered.enabled = enabled;
+            }
+        }
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.layers.iter().map(|registered| registered.layer.name())
+    }
+
+    fn enabled_mut(&mut self) -> impl Iterator<Item = &mut Box<dyn VisualizationLayer>> {
+        self.layers
+            .iter_mut()
+            .filter(|registered| registered.enabled)
+            .map(|registered| &mut registered.layer)
+    }
+}
+
+pub trait VisualizationLayerApp {
+    fn add_visualization_layer(&mut self, layer: impl VisualizationLayer) -> &mut Self;
+}
+
+impl VisualizationLayerApp for App {
+    fn add_visualization_layer(&mut self, layer: impl VisualizationLayer) -> &mut Self {
+        self.init_resource::<VisualizationLayers>();
+        self.world_mut()
+            .resource_mut::<VisualizationLayers>()
+            .add(layer);
+        self
+    }
+}
+
+/// Draws the registered layers and shows the Layers window.
+pub struct VisualizationPlugin;
+
+impl Plugin for VisualizationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<VisualizationLayers>()
+            .add_systems(Update, draw_visualization_layers)
+            .add_systems(EguiPrimaryContextPass, visualization_layers_ui);
+    }
+}
+
+pub fn draw_visualization_layers(
+    context: Option<Res<LayerContext>>,
+    mut layers: ResMut<VisualizationLayers>,
+    mut gizmos: Gizmos,
+) {
+    let Some(context) = context else {
+        return;
+    };
+    for layer in layers.enabled_mut() {
+        layer.draw(&context, &mut gizmos);
+    }
+}
+
+fn visualization_layers_ui(
+    mut contexts: EguiContexts,
+    context: Option<Res<LayerContext>>,
+    mut layers: ResMut<VisualizationLayers>,
+) -> Result {
+    let (Some(context), false) = (context, layers.layers.is_empty()) else {
+        return Ok(());
+    };
+    egui::Window::new("Layers").show(contexts.ctx_mut()?, |ui| {
+        for registered in &mut layers.layers {
+            ui.checkbox(&mut registered.enabled, registered.layer.name());
+            if registered.enabled {
+                ui.indent(registered.layer.name().to_string(), |ui| {
+                    registered.layer.ui(&context, ui);
+                });
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Ray along the facing direction of the head joint.
+pub struct GazeLayer;
+
+impl VisualizationLayer for GazeLayer {
+    fn name(&self) -> &str {
+        "Gaze"
+    }
+
+    fn draw(&mut self, context: &LayerContext, gizmos: &mut Gizmos) {
+        let Some(head) = context
+            .joints
+            .iter()
+            .find(|joint| is_head_name(&joint.name))
+        else {
+            return;
+        };
+        let position = head.position();
+        let direction = head
+            .transform
+            .transform_vector3(DEFAULT_HEAD_FORWARD)
+            .normalize_or_zero();
+        gizmos.arrow(
+            position,
+            position + direction * DEFAULT_GAZE_DISTANCE,
+            ORANGE,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layers_are_registered_and_toggled() {
+        struct Counter(usize);
+        impl VisualizationLayer for Counter {
+            fn name(&self) -> &str {
+                "Counter"
+            }
+            fn draw(&mut self, _context: &LayerContext, _gizmos: &mut Gizmos) {
+                self.0 += 1;
+            }
+        }
+
+        let mut app = App::new();
+        app.add_visualization_layer(GazeLayer)
+            .add_visualization_layer(Counter(0));
+        let mut layers = app.world_mut().resource_mut::<VisualizationLayers>();
+        assert_eq!(layers.names().collect::<Vec<_>>(), vec!["Gaze", "Counter"]);
+        layers.set_enabled("Gaze", false);
+        assert_eq!(layers.enabled_mut().count(), 1);
+
+        let joint = |name: &str, parent, position: Vec3| LayerJoint {
+            name: name.to_string(),
+            parent,
+            transform: Mat4::from_translation(position),
+        };
+        let context = LayerContext {
+            joints: vec![
+                joint("Hips", None, Vec3::ZERO),
+                joint("Spine", Some(0), Vec3::Y),
+                joint("Head", Some(1), Vec3::Y * 2.0),
+            ],
+            ..default()
+        };
+        assert_eq!(context.find("Head").unwrap().position(), Vec3::Y * 2.0);
+        assert_eq!(context.bones().collect::<Vec<_>>(), vec![(0, 1), (1, 2)]);
+        assert_eq!(context.skeleton().joints[2].offset, Vec3::Y);
+    }
+}
@@ -0,0 +1,141 @@
+//! Model outputs next to the motion they were computed for. The `<clip>.weights.npy` array of
+//! the clip on the timeline is shown as a heat strip over all frames in the Layers window, and
+//! weights per joint also color the bones of the skeleton.
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use bevy_egui::egui;
+use bvh_to_gav::weights::{FrameWeights, weights_path};
+use preview::visualization::{LayerContext, VisualizationLayer};
+
+use crate::palette::egui_color;
+
+const STRIP_SIZE: egui::Vec2 = egui::vec2(300.0, 16.0);
+
+/// Color of a normalized weight, from dark purple through red to yellow.
+pub(crate) fn heat_color(t: f32) -> Color {
+    let stops = [
+        Srgba::rgb(0.05, 0.03, 0.2),
+        Srgba::rgb(0.73, 0.21, 0.33),
+        Srgba::rgb(0.99, 0.91, 0.15),
+    ];
+    let t = t.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+    let index = (t.floor() as usize).min(stops.len() - 2);
+    stops[index].mix(&stops[index + 1], t - index as f32).into()
+}
+
+/// Layer showing the weights of the clip on the timeline, read again when the clip changes.
+pub(crate) struct WeightOverlay {
+    /// Folder the asset paths of clips are relative to.
+    root: PathBuf,
+    clip: Option<String>,
+    weights: Result<FrameWeights, String>,
+}
+
+impl WeightOverlay {
+    pub fn new(root: PathBuf) -> Self {
+        WeightOverlay {
+            root,
+            clip: None,
+            weights: Err(String::new()),
+        }
+    }
+
+    fn load(&mut self, context: &LayerContext) {
+        if self.clip.as_deref() == Some(context.clip.as_str()) {
+            return;
+        }
+        self.clip = Some(context.clip.clone());
+        let path = self.root.join(weights_path(Path::new(&context.clip)));
+        self.weights = if !path.exists() {
+            Err(format!("No weights at {}", path.display()))
+        } else {
+            FrameWeights::read(&path)
+                .map_err(|e| format!("Could not read {}: {}", path.display(), e))
+                .and_then(|weights| {
+                    if weights.frame_count() != context.frame_count {
+                        Err(format!(
+                            "{} has {} frames of weights for {} frames of motion",
+                            path.display(),
+                            weights.frame_count(),
+                            context.frame_count
+                        ))
+                    } else if weights.per_joint() && weights.joint_count() != context.joints.len() {
+                        Err(format!(
+                            "{} has weights for {} joints, the clip has {}",
+                            path.display(),
+                            weights.joint_count(),
+                            context.joints.len()
+                        ))
+                    } else {
+                        Ok(weights)
+                    }
+                })
+        };
+        if let Err(e) = &self.weights {
+            info!("{}", e);
+        }
+    }
+}
+
+impl VisualizationLayer for WeightOverlay {
+    fn name(&self) -> &str {
+        "Model weights"
+    }
+
+    fn draw(&mut self, context: &LayerContext, gizmos: &mut Gizmos) {
+        self.load(context);
+        let Ok(weights) = &self.weights else {
+            return;
+        };
+        if !weights.per_joint() {
+            return;
+        }
+        // Each bone takes the weight of the joint it ends in.
+        for (parent, child) in context.bones() {
+            let Some(value) = weights.value(context.frame, child) else {
+                continue;
+            };
+            gizmos.line(
+                context.joints[parent].position(),
+                context.joints[child].position(),
+                heat_color(weights.normalize(value)),
+            );
+        }
+    }
+
+    fn ui(&mut self, context: &LayerContext, ui: &mut egui::Ui) {
+        let weights = match &self.weights {
+            Ok(weights) => weights,
+            Err(e) => {
+                ui.label(e);
+                return;
+            }
+        };
+        let (rect, _) = ui.allocate_exact_size(STRIP_SIZE, egui::Sense::hover());
+        let painter = ui.painter_at(rect);
+        let frame_width = rect.width() / weights.frame_count().max(1) as f32;
+        for frame in 0..weights.frame_count() {
+            let value = weights.frame_value(frame).unwrap_or(0.0);
+            let left = rect.left() + frame as f32 * frame_width;
+            painter.rect_filled(
+                egui::Rect::from_x_y_ranges(left..=left + frame_width.max(1.0), rect.y_range()),
+                0.0,
+                egui_color(heat_color(weights.normalize(value))),
+            );
+        }
+        let x = rect.left() + (context.frame as f32 + 0.5) * frame_width;
+        painter.vline(
+            x,
+            rect.y_range(),
+            egui::Stroke::new(2.0, egui::Color32::WHITE),
+        );
+
+        let (min, max) = weights.range();
+        let current = weights.frame_value(context.frame).unwrap_or(f32::NAN);
+        ui.label(format!(
+            "Frame {}: {:.3} (range {:.3} to {:.3})",
+            context.frame, current, min, max
+        ));
+    }
+}